@@ -0,0 +1,911 @@
+//! Stable-memory-backed Agent Memory
+//! Splits each agent's memory into separately keyed stable maps (memories, knowledge
+//! nodes, edges, vectors) plus secondary indexes, so a write only touches the entries
+//! it changes instead of re-encoding the agent's whole history.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::consolidation::{self, ConsolidationConfig};
+use crate::memory::{
+    AgentMemory, KnowledgeEdge, KnowledgeGraph, KnowledgeNode, Memory, MemoryStore, MemoryType,
+    NodeType, VectorEntry, VectorStore,
+};
+use crate::{AGENT_EDGES, AGENT_INDEX, AGENT_META, AGENT_NODES, AGENT_RECORDS, AGENT_VECTORS};
+
+/// Defaults used when an agent's memory is first created
+pub const DEFAULT_BUFFER_SIZE: usize = 100;
+pub const DEFAULT_VECTOR_DIM: usize = 768;
+pub const DEFAULT_CONTEXT_SIZE: usize = 20;
+
+/// Key of an agent: owning principal + agent id
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AgentKey {
+    pub owner: String,
+    pub agent_id: String,
+}
+
+/// Key of a single memory, node, edge or vector belonging to an agent
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntryKey {
+    pub owner: String,
+    pub agent_id: String,
+    pub id: String,
+}
+
+impl EntryKey {
+    fn new(owner: &str, agent_id: &str, id: &str) -> Self {
+        Self {
+            owner: owner.to_string(),
+            agent_id: agent_id.to_string(),
+            id: id.to_string(),
+        }
+    }
+}
+
+/// Secondary index families
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IndexKind {
+    MemoryTag,   // tag -> memory ids
+    MemoryType,  // memory type -> memory ids
+    NodeType,    // node type -> node ids
+    NodeLabel,   // lowercase label -> node ids
+    EdgeSource,  // source node id -> edge ids
+    EdgeTarget,  // target node id -> edge ids
+    GrantOut,    // share grants issued by this agent
+    GrantIn,     // share grants received by this agent
+    Archived,    // summary memory id -> archived source memory ids
+}
+
+/// Secondary index entry: (agent, index, value) -> id
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IndexKey {
+    pub owner: String,
+    pub agent_id: String,
+    pub kind: IndexKind,
+    pub value: String,
+    pub id: String,
+}
+
+/// Small per-agent record: buffers, context window and counters.
+/// Bounded by `buffer_size` and `context_size`, so rewriting it is constant cost.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AgentMeta {
+    pub agent_id: String,
+    pub short_term_buffer: Vec<String>,
+    pub buffer_size: usize,
+    pub context_window: Vec<String>,
+    pub context_size: usize,
+    pub vector_dim: usize,
+    pub memory_count: u64,
+    pub node_count: u64,
+    pub edge_count: u64,
+    pub vector_count: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub maintenance_interval_ns: Option<u64>,
+    pub next_maintenance_at: Option<u64>,
+    pub consolidation: Option<ConsolidationConfig>,
+    pub consolidation_cursor: Option<String>,  // Last episodic id of an unfinished consolidation pass
+}
+
+impl AgentMeta {
+    pub fn new(agent_id: &str, now: u64) -> Self {
+        Self {
+            agent_id: agent_id.to_string(),
+            short_term_buffer: Vec::new(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            context_window: Vec::new(),
+            context_size: DEFAULT_CONTEXT_SIZE,
+            vector_dim: DEFAULT_VECTOR_DIM,
+            memory_count: 0,
+            node_count: 0,
+            edge_count: 0,
+            vector_count: 0,
+            created_at: now,
+            updated_at: now,
+            maintenance_interval_ns: None,
+            next_maintenance_at: None,
+            consolidation: None,
+            consolidation_cursor: None,
+        }
+    }
+}
+
+macro_rules! impl_candid_storable {
+    ($($t:ty),*) => {
+        $(
+            impl Storable for $t {
+                fn to_bytes(&self) -> Cow<[u8]> {
+                    Cow::Owned(Encode!(self).unwrap())
+                }
+
+                fn from_bytes(bytes: Cow<[u8]>) -> Self {
+                    Decode!(bytes.as_ref(), Self).unwrap()
+                }
+
+                const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+            }
+        )*
+    };
+}
+
+impl_candid_storable!(AgentKey, EntryKey, IndexKey, AgentMeta, Memory, KnowledgeNode, KnowledgeEdge, VectorEntry);
+
+fn agent_key(owner: &str, agent_id: &str) -> AgentKey {
+    AgentKey {
+        owner: owner.to_string(),
+        agent_id: agent_id.to_string(),
+    }
+}
+
+fn index_key(owner: &str, agent_id: &str, kind: IndexKind, value: &str, id: &str) -> IndexKey {
+    IndexKey {
+        owner: owner.to_string(),
+        agent_id: agent_id.to_string(),
+        kind,
+        value: value.to_string(),
+        id: id.to_string(),
+    }
+}
+
+pub fn memory_type_key(memory_type: &MemoryType) -> String {
+    format!("{:?}", memory_type)
+}
+
+pub fn node_type_key(node_type: &NodeType) -> String {
+    format!("{:?}", node_type)
+}
+
+// === Index helpers ===
+
+pub(crate) fn index_insert(owner: &str, agent_id: &str, kind: IndexKind, value: &str, id: &str) {
+    AGENT_INDEX.with(|i| {
+        i.borrow_mut().insert(index_key(owner, agent_id, kind, value, id), ());
+    });
+}
+
+pub(crate) fn index_remove(owner: &str, agent_id: &str, kind: IndexKind, value: &str, id: &str) {
+    AGENT_INDEX.with(|i| {
+        i.borrow_mut().remove(&index_key(owner, agent_id, kind, value, id));
+    });
+}
+
+/// All ids stored under one index value, in key order
+pub fn index_lookup(owner: &str, agent_id: &str, kind: IndexKind, value: &str) -> Vec<String> {
+    index_page(owner, agent_id, kind, value, None, usize::MAX)
+}
+
+/// Up to `limit` ids of an index entry, starting after the id `after`
+pub fn index_page(owner: &str, agent_id: &str, kind: IndexKind, value: &str, after: Option<&str>, limit: usize) -> Vec<String> {
+    let start = index_key(owner, agent_id, kind.clone(), value, after.unwrap_or(""));
+    AGENT_INDEX.with(|i| {
+        i.borrow()
+            .range(start..)
+            .take_while(|(k, _)| {
+                k.owner == owner && k.agent_id == agent_id && k.kind == kind && k.value == value
+            })
+            .filter(|(k, _)| Some(k.id.as_str()) != after)
+            .take(limit)
+            .map(|(k, _)| k.id)
+            .collect()
+    })
+}
+
+/// Archived memories leave the type index so they are not consolidated or decayed again
+fn index_memory(owner: &str, agent_id: &str, memory: &Memory) {
+    match memory.metadata.get(consolidation::ARCHIVED_INTO) {
+        Some(summary_id) => index_insert(owner, agent_id, IndexKind::Archived, summary_id, &memory.id),
+        None => index_insert(owner, agent_id, IndexKind::MemoryType, &memory_type_key(&memory.memory_type), &memory.id),
+    }
+    for tag in &memory.tags {
+        index_insert(owner, agent_id, IndexKind::MemoryTag, tag, &memory.id);
+    }
+}
+
+fn unindex_memory(owner: &str, agent_id: &str, memory: &Memory) {
+    match memory.metadata.get(consolidation::ARCHIVED_INTO) {
+        Some(summary_id) => index_remove(owner, agent_id, IndexKind::Archived, summary_id, &memory.id),
+        None => index_remove(owner, agent_id, IndexKind::MemoryType, &memory_type_key(&memory.memory_type), &memory.id),
+    }
+    for tag in &memory.tags {
+        index_remove(owner, agent_id, IndexKind::MemoryTag, tag, &memory.id);
+    }
+}
+
+fn index_node(owner: &str, agent_id: &str, node: &KnowledgeNode) {
+    index_insert(owner, agent_id, IndexKind::NodeType, &node_type_key(&node.node_type), &node.id);
+    index_insert(owner, agent_id, IndexKind::NodeLabel, &node.label.to_lowercase(), &node.id);
+}
+
+fn unindex_node(owner: &str, agent_id: &str, node: &KnowledgeNode) {
+    index_remove(owner, agent_id, IndexKind::NodeType, &node_type_key(&node.node_type), &node.id);
+    index_remove(owner, agent_id, IndexKind::NodeLabel, &node.label.to_lowercase(), &node.id);
+}
+
+fn index_edge(owner: &str, agent_id: &str, edge: &KnowledgeEdge) {
+    index_insert(owner, agent_id, IndexKind::EdgeSource, &edge.source_id, &edge.id);
+    index_insert(owner, agent_id, IndexKind::EdgeTarget, &edge.target_id, &edge.id);
+}
+
+fn unindex_edge(owner: &str, agent_id: &str, edge: &KnowledgeEdge) {
+    index_remove(owner, agent_id, IndexKind::EdgeSource, &edge.source_id, &edge.id);
+    index_remove(owner, agent_id, IndexKind::EdgeTarget, &edge.target_id, &edge.id);
+}
+
+// === Agent metadata ===
+
+pub fn get_meta(owner: &str, agent_id: &str) -> Option<AgentMeta> {
+    AGENT_META.with(|m| m.borrow().get(&agent_key(owner, agent_id)))
+}
+
+pub(crate) fn put_meta(owner: &str, meta: AgentMeta) {
+    AGENT_META.with(|m| {
+        m.borrow_mut().insert(agent_key(owner, &meta.agent_id), meta);
+    });
+}
+
+/// Get or create the metadata record for an agent
+pub fn ensure_agent(owner: &str, agent_id: &str, now: u64) -> AgentMeta {
+    if let Some(meta) = get_meta(owner, agent_id) {
+        return meta;
+    }
+    let meta = AgentMeta::new(agent_id, now);
+    put_meta(owner, meta.clone());
+    meta
+}
+
+/// List agent ids that have stored memory for an owner
+pub fn list_agents(owner: &str) -> Vec<String> {
+    let start = agent_key(owner, "");
+    AGENT_META.with(|m| {
+        m.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.owner == owner)
+            .map(|(k, _)| k.agent_id)
+            .collect()
+    })
+}
+
+// === Memories ===
+
+pub fn get_memory(owner: &str, agent_id: &str, id: &str) -> Option<Memory> {
+    AGENT_RECORDS.with(|r| r.borrow().get(&EntryKey::new(owner, agent_id, id)))
+}
+
+/// Insert or replace a memory, keeping indexes and counters in sync.
+/// Returns true if the memory is new.
+pub fn put_memory(owner: &str, agent_id: &str, memory: Memory) -> bool {
+    let key = EntryKey::new(owner, agent_id, &memory.id);
+    let previous = AGENT_RECORDS.with(|r| r.borrow_mut().insert(key, memory.clone()));
+    if let Some(prev) = &previous {
+        unindex_memory(owner, agent_id, prev);
+    }
+    index_memory(owner, agent_id, &memory);
+
+    if previous.is_none() {
+        if let Some(mut meta) = get_meta(owner, agent_id) {
+            meta.memory_count += 1;
+            put_meta(owner, meta);
+        }
+    }
+    previous.is_none()
+}
+
+/// Remove a memory and its index entries
+pub fn delete_memory(owner: &str, agent_id: &str, id: &str) -> Option<Memory> {
+    let removed = AGENT_RECORDS.with(|r| r.borrow_mut().remove(&EntryKey::new(owner, agent_id, id)));
+    if let Some(memory) = &removed {
+        unindex_memory(owner, agent_id, memory);
+        if let Some(mut meta) = get_meta(owner, agent_id) {
+            meta.memory_count = meta.memory_count.saturating_sub(1);
+            meta.short_term_buffer.retain(|b| b != id);
+            put_meta(owner, meta);
+        }
+    }
+    removed
+}
+
+/// Store a new memory. Mirrors `MemoryStore::add_memory`: the id enters the
+/// short-term buffer and low-importance short-term memories that fall out of
+/// the buffer are forgotten.
+pub fn remember(
+    owner: &str,
+    agent_id: &str,
+    content: String,
+    memory_type: MemoryType,
+    importance: f32,
+    tags: Vec<String>,
+    now: u64,
+) -> String {
+    let mut meta = ensure_agent(owner, agent_id, now);
+
+    let memory = Memory {
+        id: format!("{}-mem-{}", agent_id, now),
+        memory_type,
+        content,
+        summary: None,
+        embedding: None,
+        importance,
+        access_count: 0,
+        last_accessed: now,
+        created_at: now,
+        metadata: HashMap::new(),
+        related_memories: Vec::new(),
+        tags,
+        provenance: None,
+    };
+    let id = memory.id.clone();
+
+    meta.short_term_buffer.push(id.clone());
+    let mut evicted = Vec::new();
+    while meta.short_term_buffer.len() > meta.buffer_size {
+        evicted.push(meta.short_term_buffer.remove(0));
+    }
+    meta.updated_at = now;
+    put_meta(owner, meta);
+
+    for old_id in evicted {
+        if let Some(old) = get_memory(owner, agent_id, &old_id) {
+            if old.importance < 0.5 && old.memory_type == MemoryType::ShortTerm {
+                delete_memory(owner, agent_id, &old_id);
+            }
+        }
+    }
+
+    put_memory(owner, agent_id, memory);
+    id
+}
+
+/// Reject an embedding whose length differs from the agent's vector dimension
+pub fn check_embedding(owner: &str, agent_id: &str, embedding: &[f32], now: u64) -> Result<(), String> {
+    let meta = ensure_agent(owner, agent_id, now);
+    if embedding.len() != meta.vector_dim {
+        return Err(format!(
+            "Embedding dimension mismatch: expected {}, got {}",
+            meta.vector_dim,
+            embedding.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Attach an embedding to a stored memory so consolidation can cluster it by vector
+pub fn set_embedding(owner: &str, agent_id: &str, id: &str, embedding: Vec<f32>, now: u64) -> Result<(), String> {
+    check_embedding(owner, agent_id, &embedding, now)?;
+    let mut memory = get_memory(owner, agent_id, id).ok_or("Memory not found")?;
+    memory.embedding = Some(embedding);
+    put_memory(owner, agent_id, memory);
+    Ok(())
+}
+
+/// Iterate all memories of an agent in id order
+pub fn for_each_memory<F: FnMut(Memory)>(owner: &str, agent_id: &str, mut f: F) {
+    let start = EntryKey::new(owner, agent_id, "");
+    AGENT_RECORDS.with(|r| {
+        for (_, memory) in r
+            .borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.owner == owner && k.agent_id == agent_id)
+        {
+            f(memory);
+        }
+    });
+}
+
+pub fn memories_by_tag(owner: &str, agent_id: &str, tag: &str) -> Vec<Memory> {
+    index_lookup(owner, agent_id, IndexKind::MemoryTag, tag)
+        .iter()
+        .filter_map(|id| get_memory(owner, agent_id, id))
+        .collect()
+}
+
+pub fn memories_by_type(owner: &str, agent_id: &str, memory_type: &MemoryType) -> Vec<Memory> {
+    index_lookup(owner, agent_id, IndexKind::MemoryType, &memory_type_key(memory_type))
+        .iter()
+        .filter_map(|id| get_memory(owner, agent_id, id))
+        .collect()
+}
+
+/// Originals that were archived into a consolidated summary
+pub fn archived_memories(owner: &str, agent_id: &str, summary_id: &str) -> Vec<Memory> {
+    index_lookup(owner, agent_id, IndexKind::Archived, summary_id)
+        .iter()
+        .filter_map(|id| get_memory(owner, agent_id, id))
+        .collect()
+}
+
+/// Recall memories whose content matches words of the query (archived originals excluded)
+pub fn recall(owner: &str, agent_id: &str, query: &str, max_results: usize) -> Vec<Memory> {
+    let query_words: Vec<String> = query.to_lowercase()
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect();
+
+    let mut results: Vec<(Memory, usize)> = Vec::new();
+    for_each_memory(owner, agent_id, |m| {
+        if consolidation::is_archived(&m) {
+            return;
+        }
+        let content_lower = m.content.to_lowercase();
+        let matches = query_words.iter()
+            .filter(|w| content_lower.contains(w.as_str()))
+            .count();
+        if matches > 0 {
+            results.push((m, matches));
+        }
+    });
+
+    results.sort_by_key(|r| std::cmp::Reverse(r.1));
+    results.into_iter()
+        .take(max_results)
+        .map(|(m, _)| m)
+        .collect()
+}
+
+// === Context window ===
+
+pub fn add_context(owner: &str, agent_id: &str, message: String, now: u64) {
+    let mut meta = ensure_agent(owner, agent_id, now);
+    meta.context_window.push(message);
+    while meta.context_window.len() > meta.context_size {
+        meta.context_window.remove(0);
+    }
+    meta.updated_at = now;
+    put_meta(owner, meta);
+}
+
+pub fn get_context(owner: &str, agent_id: &str) -> String {
+    get_meta(owner, agent_id)
+        .map(|m| m.context_window.join("\n"))
+        .unwrap_or_default()
+}
+
+// === Knowledge graph ===
+
+pub fn get_node(owner: &str, agent_id: &str, id: &str) -> Option<KnowledgeNode> {
+    AGENT_NODES.with(|n| n.borrow().get(&EntryKey::new(owner, agent_id, id)))
+}
+
+/// Insert or replace a knowledge node
+pub fn add_node(owner: &str, agent_id: &str, node: KnowledgeNode, now: u64) -> String {
+    let mut meta = ensure_agent(owner, agent_id, now);
+    let id = node.id.clone();
+    let key = EntryKey::new(owner, agent_id, &id);
+
+    let previous = AGENT_NODES.with(|n| n.borrow_mut().insert(key, node.clone()));
+    if let Some(prev) = &previous {
+        unindex_node(owner, agent_id, prev);
+    } else {
+        meta.node_count += 1;
+    }
+    index_node(owner, agent_id, &node);
+
+    meta.updated_at = now;
+    put_meta(owner, meta);
+    id
+}
+
+/// All nodes with a label (case-insensitive)
+pub fn nodes_by_label(owner: &str, agent_id: &str, label: &str) -> Vec<KnowledgeNode> {
+    index_lookup(owner, agent_id, IndexKind::NodeLabel, &label.to_lowercase())
+        .iter()
+        .filter_map(|id| get_node(owner, agent_id, id))
+        .collect()
+}
+
+/// Find the most recently added node with a label (case-insensitive)
+pub fn find_node_by_label(owner: &str, agent_id: &str, label: &str) -> Option<KnowledgeNode> {
+    nodes_by_label(owner, agent_id, label)
+        .into_iter()
+        .max_by_key(|n| n.created_at)
+}
+
+/// Iterate all knowledge nodes of an agent in id order
+pub fn for_each_node<F: FnMut(KnowledgeNode) -> bool>(owner: &str, agent_id: &str, mut f: F) {
+    let start = EntryKey::new(owner, agent_id, "");
+    AGENT_NODES.with(|n| {
+        for (_, node) in n
+            .borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.owner == owner && k.agent_id == agent_id)
+        {
+            if !f(node) {
+                break;
+            }
+        }
+    });
+}
+
+pub fn nodes_by_type(owner: &str, agent_id: &str, node_type: &NodeType) -> Vec<KnowledgeNode> {
+    index_lookup(owner, agent_id, IndexKind::NodeType, &node_type_key(node_type))
+        .iter()
+        .filter_map(|id| get_node(owner, agent_id, id))
+        .collect()
+}
+
+pub fn get_edge(owner: &str, agent_id: &str, id: &str) -> Option<KnowledgeEdge> {
+    AGENT_EDGES.with(|e| e.borrow().get(&EntryKey::new(owner, agent_id, id)))
+}
+
+/// Add an edge between two existing nodes
+pub fn add_edge(owner: &str, agent_id: &str, edge: KnowledgeEdge, now: u64) -> Result<String, String> {
+    let has_node = |id: &str| {
+        AGENT_NODES.with(|n| n.borrow().contains_key(&EntryKey::new(owner, agent_id, id)))
+    };
+    if !has_node(&edge.source_id) {
+        return Err(format!("Source node {} not found", edge.source_id));
+    }
+    if !has_node(&edge.target_id) {
+        return Err(format!("Target node {} not found", edge.target_id));
+    }
+
+    let mut meta = ensure_agent(owner, agent_id, now);
+    let id = edge.id.clone();
+    let previous = AGENT_EDGES.with(|e| e.borrow_mut().insert(EntryKey::new(owner, agent_id, &id), edge.clone()));
+    match &previous {
+        Some(prev) => unindex_edge(owner, agent_id, prev),
+        None => meta.edge_count += 1,
+    }
+    index_edge(owner, agent_id, &edge);
+    meta.updated_at = now;
+    put_meta(owner, meta);
+    Ok(id)
+}
+
+/// Remove an edge and its adjacency entries
+pub fn delete_edge(owner: &str, agent_id: &str, id: &str) -> Option<KnowledgeEdge> {
+    let removed = AGENT_EDGES.with(|e| e.borrow_mut().remove(&EntryKey::new(owner, agent_id, id)));
+    if let Some(edge) = &removed {
+        unindex_edge(owner, agent_id, edge);
+        if let Some(mut meta) = get_meta(owner, agent_id) {
+            meta.edge_count = meta.edge_count.saturating_sub(1);
+            put_meta(owner, meta);
+        }
+    }
+    removed
+}
+
+/// Remove a node together with every edge touching it
+pub fn delete_node(owner: &str, agent_id: &str, id: &str) -> Option<KnowledgeNode> {
+    let removed = AGENT_NODES.with(|n| n.borrow_mut().remove(&EntryKey::new(owner, agent_id, id)));
+    if let Some(node) = &removed {
+        unindex_node(owner, agent_id, node);
+        let incident: Vec<String> = edges_from(owner, agent_id, id)
+            .into_iter()
+            .chain(edges_to(owner, agent_id, id))
+            .map(|e| e.id)
+            .collect();
+        for edge_id in incident {
+            delete_edge(owner, agent_id, &edge_id);
+        }
+        if let Some(mut meta) = get_meta(owner, agent_id) {
+            meta.node_count = meta.node_count.saturating_sub(1);
+            put_meta(owner, meta);
+        }
+    }
+    removed
+}
+
+/// Edges leaving a node
+pub fn edges_from(owner: &str, agent_id: &str, node_id: &str) -> Vec<KnowledgeEdge> {
+    index_lookup(owner, agent_id, IndexKind::EdgeSource, node_id)
+        .iter()
+        .filter_map(|id| get_edge(owner, agent_id, id))
+        .collect()
+}
+
+/// Edges entering a node
+pub fn edges_to(owner: &str, agent_id: &str, node_id: &str) -> Vec<KnowledgeEdge> {
+    index_lookup(owner, agent_id, IndexKind::EdgeTarget, node_id)
+        .iter()
+        .filter_map(|id| get_edge(owner, agent_id, id))
+        .collect()
+}
+
+// === Vectors ===
+
+pub fn add_vector(owner: &str, agent_id: &str, entry: VectorEntry, now: u64) -> Result<String, String> {
+    let mut meta = ensure_agent(owner, agent_id, now);
+    if entry.vector.len() != meta.vector_dim {
+        return Err(format!(
+            "Vector dimension mismatch: expected {}, got {}",
+            meta.vector_dim,
+            entry.vector.len()
+        ));
+    }
+
+    let id = entry.id.clone();
+    let previous = AGENT_VECTORS.with(|v| v.borrow_mut().insert(EntryKey::new(owner, agent_id, &id), entry));
+    if previous.is_none() {
+        meta.vector_count += 1;
+    }
+    meta.updated_at = now;
+    put_meta(owner, meta);
+    Ok(id)
+}
+
+pub fn search_vectors(owner: &str, agent_id: &str, query: &[f32], top_k: usize) -> Vec<(VectorEntry, f32)> {
+    let start = EntryKey::new(owner, agent_id, "");
+    let mut results: Vec<(VectorEntry, f32)> = AGENT_VECTORS.with(|v| {
+        v.borrow()
+            .range(start..)
+            .take_while(|(k, _)| k.owner == owner && k.agent_id == agent_id)
+            .map(|(_, e)| {
+                let score = VectorStore::cosine_similarity(query, &e.vector);
+                (e, score)
+            })
+            .collect()
+    });
+
+    results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    results.truncate(top_k);
+    results
+}
+
+// === Maintenance ===
+
+/// Consolidate, decay and archive. Only the short-term buffer and short-term
+/// memories are visited (only they change importance), not the full history.
+/// Memories that decay to zero are archived as `DECAYED` rather than deleted.
+pub fn maintain(owner: &str, agent_id: &str, now: u64) -> Result<(), String> {
+    let meta = get_meta(owner, agent_id).ok_or("No memory found for agent")?;
+
+    // Consolidate important short-term memories into long-term
+    for id in &meta.short_term_buffer {
+        if let Some(mut memory) = get_memory(owner, agent_id, id) {
+            if memory.importance >= 0.7 && memory.memory_type == MemoryType::ShortTerm {
+                memory.memory_type = MemoryType::LongTerm;
+                put_memory(owner, agent_id, memory);
+            }
+        }
+    }
+
+    // Decay the remaining short-term memories and archive those that reach zero
+    let day_ns = 24 * 60 * 60 * 1_000_000_000u64;
+    let decay_rate = 0.01f32;
+    for mut memory in memories_by_type(owner, agent_id, &MemoryType::ShortTerm) {
+        let age_days = now.saturating_sub(memory.last_accessed) / day_ns;
+        let decay = decay_rate * age_days as f32;
+        memory.importance = (memory.importance - decay).max(0.0);
+
+        if memory.importance > 0.0 {
+            if decay > 0.0 {
+                put_memory(owner, agent_id, memory);
+            }
+        } else {
+            consolidation::archive(&mut memory, consolidation::DECAYED, now);
+            let id = memory.id.clone();
+            put_memory(owner, agent_id, memory);
+            if let Some(mut meta) = get_meta(owner, agent_id) {
+                meta.short_term_buffer.retain(|b| *b != id);
+                put_meta(owner, meta);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// (memories, nodes, edges) counts from the agent's counters
+pub fn stats(owner: &str, agent_id: &str) -> (u64, u64, u64) {
+    get_meta(owner, agent_id)
+        .map(|m| (m.memory_count, m.node_count, m.edge_count))
+        .unwrap_or((0, 0, 0))
+}
+
+// === Whole-agent views ===
+
+/// Assemble the full in-memory `AgentMemory` view of an agent (read-only snapshot)
+pub fn load_agent_memory(owner: &str, agent_id: &str, now: u64) -> AgentMemory {
+    let meta = get_meta(owner, agent_id).unwrap_or_else(|| AgentMeta::new(agent_id, now));
+
+    let mut memory_store = MemoryStore::new(meta.buffer_size);
+    memory_store.short_term_buffer = meta.short_term_buffer.clone();
+    for_each_memory(owner, agent_id, |m| {
+        memory_store.memories.insert(m.id.clone(), m);
+    });
+
+    let start = EntryKey::new(owner, agent_id, "");
+    let in_agent = |k: &EntryKey| k.owner == owner && k.agent_id == agent_id;
+
+    let mut knowledge_graph = KnowledgeGraph::new();
+    AGENT_NODES.with(|n| {
+        for (_, node) in n.borrow().range(start.clone()..).take_while(|(k, _)| in_agent(k)) {
+            knowledge_graph.add_node(node);
+        }
+    });
+    AGENT_EDGES.with(|e| {
+        for (_, edge) in e.borrow().range(start.clone()..).take_while(|(k, _)| in_agent(k)) {
+            knowledge_graph.edges.insert(edge.id.clone(), edge);
+        }
+    });
+
+    let mut vector_store = VectorStore::new(meta.vector_dim);
+    AGENT_VECTORS.with(|v| {
+        for (_, entry) in v.borrow().range(start.clone()..).take_while(|(k, _)| in_agent(k)) {
+            vector_store.entries.insert(entry.id.clone(), entry);
+        }
+    });
+
+    AgentMemory {
+        agent_id: agent_id.to_string(),
+        memory_store,
+        knowledge_graph,
+        vector_store,
+        context_window: meta.context_window,
+        context_size: meta.context_size,
+    }
+}
+
+/// Append `newer` to `older`, keeping the last `limit` distinct ids
+fn merge_ids(older: &[String], newer: &[String], limit: usize) -> Vec<String> {
+    let mut merged: Vec<String> = older.iter().filter(|id| !newer.contains(id)).cloned().collect();
+    merged.extend(newer.iter().cloned());
+    let excess = merged.len().saturating_sub(limit);
+    merged.split_off(excess)
+}
+
+/// Split a monolithic `AgentMemory` into the keyed maps (used for migration).
+/// An agent already written to through this store keeps what it has: the legacy
+/// buffer and context go before its own, and its entries win over legacy ones
+/// with the same id.
+pub fn import_agent_memory(owner: &str, agent: &AgentMemory, now: u64) {
+    let existing = get_meta(owner, &agent.agent_id);
+    let mut meta = ensure_agent(owner, &agent.agent_id, now);
+    if existing.is_none() {
+        meta.buffer_size = agent.memory_store.buffer_size;
+        meta.context_size = agent.context_size;
+    }
+    if meta.vector_count == 0 {
+        meta.vector_dim = agent.vector_store.dimension;
+    }
+    meta.short_term_buffer = merge_ids(&agent.memory_store.short_term_buffer, &meta.short_term_buffer, meta.buffer_size);
+    meta.context_window = merge_ids(&agent.context_window, &meta.context_window, meta.context_size);
+    put_meta(owner, meta);
+
+    let agent_id = &agent.agent_id;
+    for memory in agent.memory_store.memories.values() {
+        if get_memory(owner, agent_id, &memory.id).is_none() {
+            put_memory(owner, agent_id, memory.clone());
+        }
+    }
+    for node in agent.knowledge_graph.nodes.values() {
+        if get_node(owner, agent_id, &node.id).is_none() {
+            add_node(owner, agent_id, node.clone(), now);
+        }
+    }
+    for edge in agent.knowledge_graph.edges.values() {
+        if get_edge(owner, agent_id, &edge.id).is_none() {
+            let _ = add_edge(owner, agent_id, edge.clone(), now);
+        }
+    }
+    for entry in agent.vector_store.entries.values() {
+        let exists = AGENT_VECTORS.with(|v| v.borrow().contains_key(&EntryKey::new(owner, agent_id, &entry.id)));
+        if !exists {
+            let _ = add_vector(owner, agent_id, entry.clone(), now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::node;
+
+    #[test]
+    fn test_remember_indexes_tags_and_type() {
+        let owner = "owner-remember";
+        let id = remember(owner, "raven", "Pepper harvest".to_string(), MemoryType::Episodic, 0.8, vec!["farm".to_string()], 1);
+
+        assert_eq!(get_memory(owner, "raven", &id).unwrap().content, "Pepper harvest");
+        assert_eq!(memories_by_tag(owner, "raven", "farm").len(), 1);
+        assert_eq!(memories_by_type(owner, "raven", &MemoryType::Episodic).len(), 1);
+        assert_eq!(stats(owner, "raven"), (1, 0, 0));
+
+        // Other agents of the same owner are isolated
+        assert!(memories_by_tag(owner, "other", "farm").is_empty());
+    }
+
+    #[test]
+    fn test_buffer_eviction_forgets_unimportant_short_term() {
+        let owner = "owner-evict";
+        ensure_agent(owner, "raven", 0);
+        let mut meta = get_meta(owner, "raven").unwrap();
+        meta.buffer_size = 2;
+        put_meta(owner, meta);
+
+        let first = remember(owner, "raven", "a".to_string(), MemoryType::ShortTerm, 0.1, vec![], 1);
+        remember(owner, "raven", "b".to_string(), MemoryType::ShortTerm, 0.9, vec![], 2);
+        remember(owner, "raven", "c".to_string(), MemoryType::ShortTerm, 0.9, vec![], 3);
+
+        assert!(get_memory(owner, "raven", &first).is_none());
+        assert_eq!(stats(owner, "raven").0, 2);
+        assert!(index_lookup(owner, "raven", IndexKind::MemoryType, "ShortTerm")
+            .iter()
+            .all(|id| id != &first));
+    }
+
+    #[test]
+    fn test_graph_edges_and_labels() {
+        let owner = "owner-graph";
+        add_node(owner, "raven", node("n1", NodeType::Entity, "Raven", &[]), 1);
+        add_node(owner, "raven", node("n2", NodeType::Entity, "AI", &[]), 1);
+
+        let edge = KnowledgeEdge {
+            id: "e1".to_string(),
+            source_id: "n1".to_string(),
+            target_id: "n2".to_string(),
+            relationship: "uses".to_string(),
+            weight: 1.0,
+            properties: HashMap::new(),
+            created_at: 1,
+        };
+        assert!(add_edge(owner, "raven", edge.clone(), 1).is_ok());
+
+        let mut dangling = edge;
+        dangling.target_id = "missing".to_string();
+        assert!(add_edge(owner, "raven", dangling, 1).is_err());
+
+        assert_eq!(find_node_by_label(owner, "raven", "raven").unwrap().id, "n1");
+        assert_eq!(nodes_by_type(owner, "raven", &NodeType::Entity).len(), 2);
+        assert_eq!(stats(owner, "raven"), (0, 2, 1));
+
+        let agent = load_agent_memory(owner, "raven", 2);
+        assert_eq!(agent.knowledge_graph.get_neighbors("n1").len(), 1);
+    }
+
+    #[test]
+    fn test_maintain_consolidates_and_archives_decayed() {
+        let owner = "owner-maintain";
+        let day_ns = 24 * 60 * 60 * 1_000_000_000u64;
+        let keep = remember(owner, "raven", "keep".to_string(), MemoryType::ShortTerm, 0.9, vec![], 1);
+        let fade = remember(owner, "raven", "fade".to_string(), MemoryType::ShortTerm, 0.3, vec![], 2);
+
+        maintain(owner, "raven", 2 + 100 * day_ns).unwrap();
+
+        assert_eq!(get_memory(owner, "raven", &keep).unwrap().memory_type, MemoryType::LongTerm);
+        let faded = get_memory(owner, "raven", &fade).unwrap();
+        assert_eq!(faded.importance, 0.0);
+        assert!(consolidation::is_archived(&faded));
+        assert_eq!(archived_memories(owner, "raven", consolidation::DECAYED).len(), 1);
+        assert!(memories_by_type(owner, "raven", &MemoryType::ShortTerm).is_empty());
+        assert!(recall(owner, "raven", "fade", 10).is_empty());
+        assert_eq!(get_meta(owner, "raven").unwrap().short_term_buffer, vec![keep.clone()]);
+        assert_eq!(stats(owner, "raven").0, 2);
+    }
+
+    #[test]
+    fn test_import_merges_into_existing_agent() {
+        let owner = "owner-import";
+        let fresh = remember(owner, "raven", "fresh".to_string(), MemoryType::Episodic, 0.5, vec![], 5);
+
+        let mut legacy = AgentMemory::new("raven".to_string(), 10, DEFAULT_VECTOR_DIM, 10);
+        let old = get_memory(owner, "raven", &fresh).map(|mut m| {
+            m.id = "raven-mem-1".to_string();
+            m.content = "legacy".to_string();
+            m
+        }).unwrap();
+        legacy.memory_store.add_memory(old);
+
+        import_agent_memory(owner, &legacy, 6);
+
+        assert_eq!(get_memory(owner, "raven", &fresh).unwrap().content, "fresh");
+        assert_eq!(get_memory(owner, "raven", "raven-mem-1").unwrap().content, "legacy");
+        assert_eq!(get_meta(owner, "raven").unwrap().short_term_buffer, vec!["raven-mem-1".to_string(), fresh]);
+    }
+
+    #[test]
+    fn test_embedding_must_match_vector_dim() {
+        let owner = "owner-embedding";
+        let id = remember(owner, "raven", "embedded".to_string(), MemoryType::Episodic, 0.5, vec![], 1);
+        assert!(check_embedding(owner, "raven", &[0.1; 3], 2).is_err());
+        assert!(set_embedding(owner, "raven", &id, vec![0.1; 3], 2).is_err());
+
+        set_embedding(owner, "raven", &id, vec![0.1; DEFAULT_VECTOR_DIM], 2).unwrap();
+        assert_eq!(get_memory(owner, "raven", &id).unwrap().embedding.map(|e| e.len()), Some(DEFAULT_VECTOR_DIM));
+    }
+}
@@ -0,0 +1,629 @@
+//! Memory Consolidation - turn clusters of episodic memories into semantic facts
+//! Episodic memories are clustered by shared tag, then by embedding proximity. Each
+//! cluster is summarised into a `MemoryType::Semantic` memory (rule-based extractive
+//! summary, optionally rewritten by the LLM Council), linked to its sources in the
+//! knowledge graph, and the originals are archived instead of deleted.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use crate::agent_store::{self, AgentKey, IndexKind};
+use crate::memory::{KnowledgeEdge, KnowledgeNode, Memory, MemoryType, NodeType, VectorStore};
+use crate::MAINTENANCE_DUE;
+
+pub const ARCHIVED_INTO: &str = "archived_into";
+/// `ARCHIVED_INTO` value of short-term memories whose importance decayed to zero
+pub const DECAYED: &str = "decayed";
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+/// Episodic memories clustered per run; larger agents resume from a cursor next run
+pub const EPISODES_PER_RUN: usize = 500;
+
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "was", "were", "are", "with", "that", "this", "from", "have", "has",
+    "had", "but", "not", "you", "your", "our", "their", "they", "them", "its", "into", "about",
+    "then", "than", "there", "when", "what", "which", "who", "will", "would", "can", "could",
+];
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Summarizer {
+    RuleBased,
+    LlmCouncil,  // Rule-based summary first, replaced when the council session is finalized
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ConsolidationConfig {
+    pub min_cluster_size: u32,
+    pub max_cluster_size: u32,
+    pub similarity_threshold: f32,  // Cosine similarity for embedding clusters
+    pub min_age_ns: u64,            // Only episodes older than this are consolidated
+    pub max_summary_sentences: u32,
+    pub summarizer: Summarizer,
+}
+
+impl Default for ConsolidationConfig {
+    fn default() -> Self {
+        Self {
+            min_cluster_size: 3,
+            max_cluster_size: 20,
+            similarity_threshold: 0.8,
+            min_age_ns: DAY_NS,
+            max_summary_sentences: 3,
+            summarizer: Summarizer::RuleBased,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ConsolidationReport {
+    pub clusters: u32,
+    pub summary_ids: Vec<String>,
+    pub archived: u32,
+    pub council_sessions: Vec<String>,
+}
+
+/// Group of episodic memory ids to summarise together
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cluster {
+    pub key: String,
+    pub member_ids: Vec<String>,
+}
+
+/// Due-queue entry for scheduled maintenance, ordered by time
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MaintenanceDue {
+    pub due_at: u64,
+    pub agent: AgentKey,
+}
+
+impl Storable for MaintenanceDue {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// Summary waiting for a council session to rewrite it
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PendingSummary {
+    pub owner: String,
+    pub agent_id: String,
+    pub summary_id: String,
+}
+
+impl Storable for PendingSummary {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+pub fn is_archived(memory: &Memory) -> bool {
+    memory.metadata.contains_key(ARCHIVED_INTO)
+}
+
+/// Mark a memory as archived into a summary (or `DECAYED`); it stays stored but leaves recall
+pub fn archive(memory: &mut Memory, into: &str, now: u64) {
+    memory.metadata.insert(ARCHIVED_INTO.to_string(), into.to_string());
+    memory.metadata.insert("archived_at".to_string(), now.to_string());
+}
+
+// === Clustering ===
+
+fn centroid(vectors: &[&Vec<f32>]) -> Vec<f32> {
+    let dim = vectors.first().map(|v| v.len()).unwrap_or(0);
+    let mut sum = vec![0.0f32; dim];
+    for v in vectors {
+        for (s, x) in sum.iter_mut().zip(v.iter()) {
+            *s += x;
+        }
+    }
+    let n = vectors.len().max(1) as f32;
+    sum.iter().map(|s| s / n).collect()
+}
+
+/// Cluster memories: first by shared tag (most common tags first), then the rest by
+/// embedding proximity to a running centroid. Clusters below `min_cluster_size` are dropped.
+pub fn cluster_memories(memories: &[Memory], config: &ConsolidationConfig) -> Vec<Cluster> {
+    let min = config.min_cluster_size.max(2) as usize;
+    let max = config.max_cluster_size.max(min as u32) as usize;
+
+    let mut ordered: Vec<&Memory> = memories.iter().collect();
+    ordered.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+
+    let mut tag_counts: HashMap<&str, usize> = HashMap::new();
+    for m in &ordered {
+        for tag in &m.tags {
+            *tag_counts.entry(tag.as_str()).or_insert(0) += 1;
+        }
+    }
+    let mut tags: Vec<(&str, usize)> = tag_counts.into_iter().filter(|(_, c)| *c >= min).collect();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut assigned: HashSet<&str> = HashSet::new();
+    let mut clusters = Vec::new();
+
+    for (tag, _) in tags {
+        let members: Vec<&str> = ordered.iter()
+            .filter(|m| !assigned.contains(m.id.as_str()) && m.tags.iter().any(|t| t == tag))
+            .map(|m| m.id.as_str())
+            .take(max)
+            .collect();
+        if members.len() >= min {
+            assigned.extend(members.iter().copied());
+            clusters.push(Cluster {
+                key: tag.to_string(),
+                member_ids: members.into_iter().map(String::from).collect(),
+            });
+        }
+    }
+
+    // Leader clustering over embeddings for whatever the tags did not cover
+    let mut groups: Vec<Vec<&Memory>> = Vec::new();
+    for m in ordered.iter().filter(|m| !assigned.contains(m.id.as_str())) {
+        let embedding = match &m.embedding {
+            Some(e) if !e.is_empty() => e,
+            _ => continue,
+        };
+        let best = groups.iter_mut()
+            .filter(|g| g.len() < max)
+            .map(|g| {
+                let vectors: Vec<&Vec<f32>> = g.iter().filter_map(|x| x.embedding.as_ref()).collect();
+                let score = VectorStore::cosine_similarity(embedding, &centroid(&vectors));
+                (g, score)
+            })
+            .filter(|(_, score)| *score >= config.similarity_threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((group, _)) => group.push(m),
+            None => groups.push(vec![m]),
+        }
+    }
+
+    for group in groups.into_iter().filter(|g| g.len() >= min) {
+        clusters.push(Cluster {
+            key: format!("similar:{}", group[0].id),
+            member_ids: group.iter().map(|m| m.id.clone()).collect(),
+        });
+    }
+
+    clusters
+}
+
+// === Summarisation ===
+
+fn words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2 && !STOPWORDS.contains(w))
+        .map(String::from)
+        .collect()
+}
+
+fn sentences(text: &str) -> Vec<String> {
+    text.split(['.', '!', '?', '\n'])
+        .map(|s| s.trim().to_string())
+        .filter(|s| s.len() > 3)
+        .collect()
+}
+
+/// Extractive summary: keep the sentences whose words recur most across the cluster
+pub fn summarize(memories: &[&Memory], topic: &str, max_sentences: usize) -> String {
+    let mut frequency: HashMap<String, f32> = HashMap::new();
+    for m in memories {
+        // Count each word once per memory so one long memory does not dominate
+        let unique: HashSet<String> = words(&m.content).into_iter().collect();
+        for w in unique {
+            *frequency.entry(w).or_insert(0.0) += 1.0;
+        }
+    }
+
+    let mut candidates: Vec<(usize, String, f32)> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    for m in memories {
+        for sentence in sentences(&m.content) {
+            if !seen.insert(sentence.to_lowercase()) {
+                continue;
+            }
+            let tokens = words(&sentence);
+            if tokens.is_empty() {
+                continue;
+            }
+            let score = tokens.iter().map(|w| frequency.get(w).copied().unwrap_or(0.0)).sum::<f32>()
+                / (tokens.len() as f32).sqrt();
+            candidates.push((candidates.len(), sentence, score));
+        }
+    }
+
+    let mut best = candidates.clone();
+    best.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    best.truncate(max_sentences.max(1));
+    best.sort_by_key(|c| c.0);
+
+    let body: Vec<String> = best.into_iter().map(|(_, s, _)| s).collect();
+    format!("{}: {}.", topic, body.join(". "))
+}
+
+fn topic_for(cluster: &Cluster, members: &[&Memory]) -> String {
+    if cluster.key.starts_with("similar:") {
+        // Use the most frequent word as the topic of an embedding cluster
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for m in members {
+            for w in words(&m.content) {
+                *counts.entry(w).or_insert(0) += 1;
+            }
+        }
+        counts.into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .map(|(w, _)| w)
+            .unwrap_or_else(|| "memories".to_string())
+    } else {
+        cluster.key.clone()
+    }
+}
+
+// === Knowledge graph links ===
+
+fn memory_node_id(memory_id: &str) -> String {
+    format!("mem:{}", memory_id)
+}
+
+fn ensure_memory_node(owner: &str, agent_id: &str, memory: &Memory, node_type: NodeType, label: &str, now: u64) -> String {
+    let id = memory_node_id(&memory.id);
+    if agent_store::get_node(owner, agent_id, &id).is_none() {
+        let mut properties = HashMap::new();
+        properties.insert("memory_id".to_string(), memory.id.clone());
+        agent_store::add_node(owner, agent_id, KnowledgeNode {
+            id: id.clone(),
+            node_type,
+            label: label.to_string(),
+            properties,
+            embedding: memory.embedding.clone(),
+            created_at: now,
+            updated_at: now,
+            provenance: None,
+        }, now);
+    }
+    id
+}
+
+// === Consolidation ===
+
+/// Consolidate the next `limit` of an agent's episodic memories, continuing from where
+/// the previous run stopped and wrapping around once the end is reached
+pub fn consolidate(owner: &str, agent_id: &str, config: &ConsolidationConfig, now: u64, limit: usize) -> ConsolidationReport {
+    let cursor = agent_store::get_meta(owner, agent_id).and_then(|m| m.consolidation_cursor);
+    let page = agent_store::index_page(
+        owner,
+        agent_id,
+        IndexKind::MemoryType,
+        &agent_store::memory_type_key(&MemoryType::Episodic),
+        cursor.as_deref(),
+        limit,
+    );
+    let next_cursor = if page.len() == limit { page.last().cloned() } else { None };
+    let episodes: Vec<Memory> = page.iter()
+        .filter_map(|id| agent_store::get_memory(owner, agent_id, id))
+        .filter(|m| !is_archived(m) && m.created_at.saturating_add(config.min_age_ns) <= now)
+        .collect();
+
+    let by_id: HashMap<&str, &Memory> = episodes.iter().map(|m| (m.id.as_str(), m)).collect();
+    let mut report = ConsolidationReport::default();
+
+    for (i, cluster) in cluster_memories(&episodes, config).iter().enumerate() {
+        let members: Vec<&Memory> = cluster.member_ids.iter()
+            .filter_map(|id| by_id.get(id.as_str()).copied())
+            .collect();
+        let topic = topic_for(cluster, &members);
+
+        // Tags shared by at least half of the sources
+        let mut tag_counts: HashMap<&str, usize> = HashMap::new();
+        for m in &members {
+            for t in &m.tags {
+                *tag_counts.entry(t.as_str()).or_insert(0) += 1;
+            }
+        }
+        let mut tags: Vec<String> = tag_counts.into_iter()
+            .filter(|(_, c)| c * 2 >= members.len())
+            .map(|(t, _)| t.to_string())
+            .collect();
+        tags.sort();
+
+        let embeddings: Vec<&Vec<f32>> = members.iter().filter_map(|m| m.embedding.as_ref()).collect();
+        let mut metadata = HashMap::new();
+        metadata.insert("consolidated_from".to_string(), members.len().to_string());
+        metadata.insert("cluster".to_string(), cluster.key.clone());
+        metadata.insert("summarizer".to_string(), "rule_based".to_string());
+
+        let summary = Memory {
+            id: format!("{}-sem-{}-{}", agent_id, now, i),
+            memory_type: MemoryType::Semantic,
+            content: summarize(&members, &topic, config.max_summary_sentences as usize),
+            summary: Some(format!("Consolidated from {} episodic memories about {}", members.len(), topic)),
+            embedding: if embeddings.len() == members.len() { Some(centroid(&embeddings)) } else { None },
+            importance: members.iter().map(|m| m.importance).fold(0.0, f32::max),
+            access_count: 0,
+            last_accessed: now,
+            created_at: now,
+            metadata,
+            related_memories: cluster.member_ids.clone(),
+            tags,
+            provenance: None,
+        };
+        let summary_id = summary.id.clone();
+        agent_store::put_memory(owner, agent_id, summary.clone());
+
+        // Link summary -> sources in the knowledge graph
+        let summary_node = ensure_memory_node(owner, agent_id, &summary, NodeType::Concept, &topic, now);
+        for source in &members {
+            let label: String = source.content.chars().take(60).collect();
+            let source_node = ensure_memory_node(owner, agent_id, source, NodeType::Event, &label, now);
+            let _ = agent_store::add_edge(owner, agent_id, KnowledgeEdge {
+                id: format!("summarizes:{}:{}", summary_id, source.id),
+                source_id: summary_node.clone(),
+                target_id: source_node,
+                relationship: "summarizes".to_string(),
+                weight: 1.0,
+                properties: HashMap::new(),
+                created_at: now,
+            }, now);
+
+            let mut archived = (*source).clone();
+            archive(&mut archived, &summary_id, now);
+            agent_store::put_memory(owner, agent_id, archived);
+            report.archived += 1;
+        }
+
+        report.clusters += 1;
+        report.summary_ids.push(summary_id);
+    }
+
+    if let Some(mut meta) = agent_store::get_meta(owner, agent_id) {
+        meta.consolidation_cursor = next_cursor;
+        agent_store::put_meta(owner, meta);
+    }
+    report
+}
+
+/// Build the chairman-style prompt used when the council rewrites a summary
+pub fn council_prompt(summary: &Memory, sources: &[Memory]) -> String {
+    let mut prompt = String::from(
+        "Summarise the following related episodic memories into a few concise, factual statements \
+        that remain true independent of when they happened.\n\n",
+    );
+    for (i, m) in sources.iter().enumerate() {
+        prompt.push_str(&format!("{}. {}\n", i + 1, m.content));
+    }
+    prompt.push_str(&format!("\nDraft summary: {}\n", summary.content));
+    prompt
+}
+
+/// Replace a rule-based summary with the council's final response
+pub fn apply_council_summary(owner: &str, agent_id: &str, summary_id: &str, content: String) -> Result<(), String> {
+    let mut summary = agent_store::get_memory(owner, agent_id, summary_id).ok_or("Summary memory not found")?;
+    summary.content = content;
+    summary.metadata.insert("summarizer".to_string(), "llm_council".to_string());
+    agent_store::put_memory(owner, agent_id, summary);
+    Ok(())
+}
+
+// === Scheduled maintenance ===
+
+fn due_entry(owner: &str, agent_id: &str, due_at: u64) -> MaintenanceDue {
+    MaintenanceDue {
+        due_at,
+        agent: AgentKey {
+            owner: owner.to_string(),
+            agent_id: agent_id.to_string(),
+        },
+    }
+}
+
+/// Run maintenance + consolidation for an agent every `interval_ns`
+pub fn schedule_maintenance(
+    owner: &str,
+    agent_id: &str,
+    interval_ns: u64,
+    config: ConsolidationConfig,
+    now: u64,
+) -> Result<u64, String> {
+    if interval_ns == 0 {
+        return Err("Interval must be greater than zero".to_string());
+    }
+    let mut meta = agent_store::get_meta(owner, agent_id).ok_or("No memory found for agent")?;
+    if let Some(previous) = meta.next_maintenance_at {
+        MAINTENANCE_DUE.with(|d| d.borrow_mut().remove(&due_entry(owner, agent_id, previous)));
+    }
+
+    let next = now.saturating_add(interval_ns);
+    meta.maintenance_interval_ns = Some(interval_ns);
+    meta.next_maintenance_at = Some(next);
+    meta.consolidation = Some(config);
+    agent_store::put_meta(owner, meta);
+    MAINTENANCE_DUE.with(|d| d.borrow_mut().insert(due_entry(owner, agent_id, next), ()));
+    Ok(next)
+}
+
+pub fn cancel_maintenance(owner: &str, agent_id: &str) -> Result<(), String> {
+    let mut meta = agent_store::get_meta(owner, agent_id).ok_or("No memory found for agent")?;
+    if let Some(previous) = meta.next_maintenance_at.take() {
+        MAINTENANCE_DUE.with(|d| d.borrow_mut().remove(&due_entry(owner, agent_id, previous)));
+    }
+    meta.maintenance_interval_ns = None;
+    agent_store::put_meta(owner, meta);
+    Ok(())
+}
+
+/// Maintain and consolidate one agent with its configured (or default) settings
+pub fn maintain_agent(owner: &str, agent_id: &str, now: u64) -> Result<ConsolidationReport, String> {
+    agent_store::maintain(owner, agent_id, now)?;
+    let config = agent_store::get_meta(owner, agent_id)
+        .and_then(|m| m.consolidation)
+        .unwrap_or_default();
+    Ok(consolidate(owner, agent_id, &config, now, EPISODES_PER_RUN))
+}
+
+/// Process up to `limit` agents whose maintenance is due and reschedule them
+pub fn run_due_maintenance(now: u64, limit: usize) -> Vec<(AgentKey, ConsolidationReport)> {
+    let due: Vec<MaintenanceDue> = MAINTENANCE_DUE.with(|d| {
+        d.borrow()
+            .iter()
+            .take_while(|(k, _)| k.due_at <= now)
+            .take(limit)
+            .map(|(k, _)| k)
+            .collect()
+    });
+
+    let mut results = Vec::new();
+    for entry in due {
+        MAINTENANCE_DUE.with(|d| d.borrow_mut().remove(&entry));
+        let AgentKey { owner, agent_id } = entry.agent.clone();
+        let mut meta = match agent_store::get_meta(&owner, &agent_id) {
+            Some(meta) => meta,
+            None => continue,
+        };
+
+        if let Ok(report) = maintain_agent(&owner, &agent_id, now) {
+            results.push((entry.agent.clone(), report));
+        }
+
+        // maintain_agent may have touched the meta (buffer), so reload before rescheduling
+        meta = agent_store::get_meta(&owner, &agent_id).unwrap_or(meta);
+        if let Some(interval) = meta.maintenance_interval_ns {
+            let next = now.saturating_add(interval);
+            meta.next_maintenance_at = Some(next);
+            agent_store::put_meta(&owner, meta);
+            MAINTENANCE_DUE.with(|d| d.borrow_mut().insert(due_entry(&owner, &agent_id, next), ()));
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn episode(id: &str, content: &str, tags: &[&str], embedding: Option<Vec<f32>>) -> Memory {
+        Memory {
+            id: id.to_string(),
+            memory_type: MemoryType::Episodic,
+            content: content.to_string(),
+            summary: None,
+            embedding,
+            importance: 0.6,
+            access_count: 0,
+            last_accessed: 0,
+            created_at: 0,
+            metadata: HashMap::new(),
+            related_memories: Vec::new(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            provenance: None,
+        }
+    }
+
+    #[test]
+    fn test_cluster_by_tag_then_vector() {
+        let memories = vec![
+            episode("a", "Shipped peppers to Austin", &["shipping"], None),
+            episode("b", "Shipped seeds to Dallas", &["shipping"], None),
+            episode("c", "Shipped peppers to Denver", &["shipping"], None),
+            episode("d", "Wallet swap", &[], Some(vec![1.0, 0.0])),
+            episode("e", "Wallet swap again", &[], Some(vec![0.95, 0.05])),
+            episode("f", "Another wallet swap", &[], Some(vec![0.9, 0.1])),
+            episode("g", "Unrelated", &[], Some(vec![0.0, 1.0])),
+        ];
+
+        let clusters = cluster_memories(&memories, &ConsolidationConfig::default());
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].key, "shipping");
+        assert_eq!(clusters[0].member_ids, vec!["a", "b", "c"]);
+        assert_eq!(clusters[1].member_ids, vec!["d", "e", "f"]);
+    }
+
+    #[test]
+    fn test_rule_based_summary_prefers_recurring_content() {
+        let a = episode("a", "Peppers sold out at the farmers market. It rained.", &[], None);
+        let b = episode("b", "Farmers market demand for peppers was high.", &[], None);
+        let c = episode("c", "Ghost peppers sold fast at the market.", &[], None);
+
+        let summary = summarize(&[&a, &b, &c], "market", 1);
+        assert!(summary.starts_with("market: "));
+        assert!(summary.to_lowercase().contains("peppers"));
+        assert!(!summary.contains("rained"));
+    }
+
+    #[test]
+    fn test_consolidate_archives_and_links_sources() {
+        let owner = "owner-consolidate";
+        for (i, text) in ["Delivered order to Austin", "Delivered order to Dallas", "Delivered order late"].iter().enumerate() {
+            agent_store::remember(owner, "raven", text.to_string(), MemoryType::Episodic, 0.5, vec!["delivery".to_string()], i as u64 + 1);
+        }
+
+        let report = consolidate(owner, "raven", &ConsolidationConfig::default(), 10 * DAY_NS, EPISODES_PER_RUN);
+        assert_eq!(report.clusters, 1);
+        assert_eq!(report.archived, 3);
+
+        let summary = agent_store::get_memory(owner, "raven", &report.summary_ids[0]).unwrap();
+        assert_eq!(summary.memory_type, MemoryType::Semantic);
+        assert_eq!(summary.related_memories.len(), 3);
+        assert_eq!(summary.tags, vec!["delivery"]);
+
+        // Originals are kept but no longer episodic candidates
+        assert!(agent_store::memories_by_type(owner, "raven", &MemoryType::Episodic).is_empty());
+        assert_eq!(agent_store::stats(owner, "raven").0, 4);
+        assert_eq!(agent_store::edges_from(owner, "raven", &memory_node_id(&summary.id)).len(), 3);
+
+        // Running again finds nothing new
+        assert_eq!(consolidate(owner, "raven", &ConsolidationConfig::default(), 11 * DAY_NS, EPISODES_PER_RUN).clusters, 0);
+        assert_eq!(agent_store::archived_memories(owner, "raven", &summary.id).len(), 3);
+    }
+
+    #[test]
+    fn test_consolidate_resumes_from_cursor() {
+        let owner = "owner-cursor";
+        for i in 0..5 {
+            agent_store::remember(owner, "raven", format!("Fed hens {}", i), MemoryType::Episodic, 0.5, vec!["hens".to_string()], i + 1);
+        }
+
+        let first = consolidate(owner, "raven", &ConsolidationConfig::default(), 10 * DAY_NS, 3);
+        assert_eq!(first.archived, 3);
+        assert_eq!(agent_store::get_meta(owner, "raven").unwrap().consolidation_cursor, Some("raven-mem-3".to_string()));
+
+        // The remaining two are below the cluster size, and the pass wraps around
+        assert_eq!(consolidate(owner, "raven", &ConsolidationConfig::default(), 10 * DAY_NS, 3).clusters, 0);
+        assert_eq!(agent_store::get_meta(owner, "raven").unwrap().consolidation_cursor, None);
+        assert_eq!(agent_store::memories_by_type(owner, "raven", &MemoryType::Episodic).len(), 2);
+    }
+
+    #[test]
+    fn test_scheduled_maintenance_runs_when_due() {
+        let owner = "owner-schedule";
+        for i in 0..3 {
+            agent_store::remember(owner, "crow", format!("Watered field {}", i), MemoryType::Episodic, 0.5, vec!["irrigation".to_string()], i + 1);
+        }
+        let next = schedule_maintenance(owner, "crow", DAY_NS, ConsolidationConfig::default(), DAY_NS).unwrap();
+        assert_eq!(next, 2 * DAY_NS);
+
+        assert!(run_due_maintenance(next - 1, 10).is_empty());
+        let ran = run_due_maintenance(next, 10);
+        assert_eq!(ran.len(), 1);
+        assert_eq!(ran[0].1.clusters, 1);
+        assert_eq!(agent_store::get_meta(owner, "crow").unwrap().next_maintenance_at, Some(3 * DAY_NS));
+
+        cancel_maintenance(owner, "crow").unwrap();
+        assert!(run_due_maintenance(10 * DAY_NS, 10).is_empty());
+    }
+}
@@ -0,0 +1,987 @@
+//! Knowledge Graph Query - pattern matching and traversal over an agent's knowledge graph
+//! Supports node type / label / property patterns, relation-filtered variable-length
+//! paths, weighted shortest paths and simple aggregates. Queries can be given as a
+//! `GraphQuery` record or in a small text syntax:
+//!
+//! ```text
+//! MATCH (w {label: "wallet-1"})-[owns*1..3]->(t:Entity {kind: "token"}) RETURN count BY kind LIMIT 50
+//! ```
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::agent_store;
+use crate::memory::{KnowledgeEdge, KnowledgeGraph, KnowledgeNode, NodeType};
+
+/// Upper bound on variable-length path hops
+pub const MAX_HOPS: u32 = 6;
+/// Upper bound on rows materialised by a single query
+pub const MAX_ROWS: usize = 1_000;
+/// Upper bound on edge expansions per query (keeps queries inside the instruction limit)
+pub const MAX_EXPANSIONS: usize = 20_000;
+const DEFAULT_LIMIT: u32 = 100;
+
+// === Query types ===
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Contains,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Exists,
+}
+
+/// Condition on a node property; `Gt`/`Lt` compare numerically when both sides parse
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PropertyFilter {
+    pub key: String,
+    pub op: CompareOp,
+    pub value: String,
+}
+
+/// Node constraints; empty pattern matches any node
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NodePattern {
+    pub id: Option<String>,
+    pub node_type: Option<NodeType>,
+    pub label: Option<String>,
+    pub properties: Vec<PropertyFilter>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum EdgeDirection {
+    Outgoing,
+    Incoming,
+    Both,
+}
+
+/// Edge constraints for one path step; empty `relationships` matches any relation
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EdgePattern {
+    pub relationships: Vec<String>,
+    pub direction: EdgeDirection,
+    pub min_hops: u32,
+    pub max_hops: u32,
+    pub min_weight: Option<f32>,
+    pub max_weight: Option<f32>,
+}
+
+impl Default for EdgePattern {
+    fn default() -> Self {
+        Self {
+            relationships: Vec::new(),
+            direction: EdgeDirection::Outgoing,
+            min_hops: 1,
+            max_hops: 1,
+            min_weight: None,
+            max_weight: None,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PathStep {
+    pub edge: EdgePattern,
+    pub node: NodePattern,
+}
+
+/// Aggregation over the last bound node of each row
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Aggregate {
+    Count,
+    CountByType,
+    CountByProperty(String),
+}
+
+/// Path pattern: start node followed by zero or more (edge, node) steps
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GraphQuery {
+    pub start: NodePattern,
+    pub steps: Vec<PathStep>,
+    pub aggregate: Option<Aggregate>,
+    pub limit: Option<u32>,
+}
+
+/// One match: the bound nodes (start + one per step) and the full path walked
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct QueryRow {
+    pub nodes: Vec<KnowledgeNode>,
+    pub path: Vec<String>,
+    pub edges: Vec<String>,
+    pub hops: u32,
+    pub total_weight: f32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AggregateRow {
+    pub key: String,
+    pub count: u64,
+    pub min_hops: u32,
+    pub total_weight: f32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct QueryResult {
+    pub rows: Vec<QueryRow>,
+    pub aggregates: Vec<AggregateRow>,
+    pub total_matches: u64,
+    pub truncated: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WeightedPath {
+    pub node_ids: Vec<String>,
+    pub edge_ids: Vec<String>,
+    pub hops: u32,
+    pub total_weight: f32,
+}
+
+// === Graph access ===
+
+/// Read access needed by the query engine
+pub trait GraphSource {
+    fn node(&self, id: &str) -> Option<KnowledgeNode>;
+    fn edges_from(&self, id: &str) -> Vec<KnowledgeEdge>;
+    fn edges_to(&self, id: &str) -> Vec<KnowledgeEdge>;
+    fn nodes_with_label(&self, label: &str) -> Vec<KnowledgeNode>;
+    fn nodes_of_type(&self, node_type: &NodeType) -> Vec<KnowledgeNode>;
+    fn all_nodes(&self, limit: usize) -> Vec<KnowledgeNode>;
+}
+
+impl GraphSource for KnowledgeGraph {
+    fn node(&self, id: &str) -> Option<KnowledgeNode> {
+        self.nodes.get(id).cloned()
+    }
+
+    fn edges_from(&self, id: &str) -> Vec<KnowledgeEdge> {
+        self.edges.values().filter(|e| e.source_id == id).cloned().collect()
+    }
+
+    fn edges_to(&self, id: &str) -> Vec<KnowledgeEdge> {
+        self.edges.values().filter(|e| e.target_id == id).cloned().collect()
+    }
+
+    fn nodes_with_label(&self, label: &str) -> Vec<KnowledgeNode> {
+        let label = label.to_lowercase();
+        self.nodes.values().filter(|n| n.label.to_lowercase() == label).cloned().collect()
+    }
+
+    fn nodes_of_type(&self, node_type: &NodeType) -> Vec<KnowledgeNode> {
+        self.nodes.values().filter(|n| &n.node_type == node_type).cloned().collect()
+    }
+
+    fn all_nodes(&self, limit: usize) -> Vec<KnowledgeNode> {
+        self.nodes.values().take(limit).cloned().collect()
+    }
+}
+
+/// An agent's knowledge graph in stable memory (see agent_store)
+pub struct AgentGraph<'a> {
+    pub owner: &'a str,
+    pub agent_id: &'a str,
+}
+
+impl GraphSource for AgentGraph<'_> {
+    fn node(&self, id: &str) -> Option<KnowledgeNode> {
+        agent_store::get_node(self.owner, self.agent_id, id)
+    }
+
+    fn edges_from(&self, id: &str) -> Vec<KnowledgeEdge> {
+        agent_store::edges_from(self.owner, self.agent_id, id)
+    }
+
+    fn edges_to(&self, id: &str) -> Vec<KnowledgeEdge> {
+        agent_store::edges_to(self.owner, self.agent_id, id)
+    }
+
+    fn nodes_with_label(&self, label: &str) -> Vec<KnowledgeNode> {
+        agent_store::nodes_by_label(self.owner, self.agent_id, label)
+    }
+
+    fn nodes_of_type(&self, node_type: &NodeType) -> Vec<KnowledgeNode> {
+        agent_store::nodes_by_type(self.owner, self.agent_id, node_type)
+    }
+
+    fn all_nodes(&self, limit: usize) -> Vec<KnowledgeNode> {
+        let mut nodes = Vec::new();
+        agent_store::for_each_node(self.owner, self.agent_id, |n| {
+            nodes.push(n);
+            nodes.len() < limit
+        });
+        nodes
+    }
+}
+
+// === Matching ===
+
+fn compare_values(actual: &str, expected: &str) -> Ordering {
+    match (actual.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => actual.cmp(expected),
+    }
+}
+
+fn matches_property(node: &KnowledgeNode, filter: &PropertyFilter) -> bool {
+    let actual = match node.properties.get(&filter.key) {
+        Some(v) => v,
+        None => return filter.op == CompareOp::NotEq,
+    };
+
+    match filter.op {
+        CompareOp::Eq => actual == &filter.value,
+        CompareOp::NotEq => actual != &filter.value,
+        CompareOp::Contains => actual.to_lowercase().contains(&filter.value.to_lowercase()),
+        CompareOp::Gt => compare_values(actual, &filter.value) == Ordering::Greater,
+        CompareOp::Gte => compare_values(actual, &filter.value) != Ordering::Less,
+        CompareOp::Lt => compare_values(actual, &filter.value) == Ordering::Less,
+        CompareOp::Lte => compare_values(actual, &filter.value) != Ordering::Greater,
+        CompareOp::Exists => true,
+    }
+}
+
+pub fn matches_node(node: &KnowledgeNode, pattern: &NodePattern) -> bool {
+    if let Some(id) = &pattern.id {
+        if &node.id != id {
+            return false;
+        }
+    }
+    if let Some(node_type) = &pattern.node_type {
+        if &node.node_type != node_type {
+            return false;
+        }
+    }
+    if let Some(label) = &pattern.label {
+        if node.label.to_lowercase() != label.to_lowercase() {
+            return false;
+        }
+    }
+    pattern.properties.iter().all(|f| matches_property(node, f))
+}
+
+fn matches_edge(edge: &KnowledgeEdge, pattern: &EdgePattern) -> bool {
+    if !pattern.relationships.is_empty()
+        && !pattern.relationships.iter().any(|r| r.eq_ignore_ascii_case(&edge.relationship))
+    {
+        return false;
+    }
+    if let Some(min) = pattern.min_weight {
+        if edge.weight < min {
+            return false;
+        }
+    }
+    if let Some(max) = pattern.max_weight {
+        if edge.weight > max {
+            return false;
+        }
+    }
+    true
+}
+
+/// Edges usable from `node_id` under a pattern, paired with the node they lead to
+fn step_edges<G: GraphSource>(graph: &G, node_id: &str, pattern: &EdgePattern) -> Vec<(KnowledgeEdge, String)> {
+    let mut out = Vec::new();
+    if pattern.direction != EdgeDirection::Incoming {
+        for e in graph.edges_from(node_id) {
+            if matches_edge(&e, pattern) {
+                let next = e.target_id.clone();
+                out.push((e, next));
+            }
+        }
+    }
+    if pattern.direction != EdgeDirection::Outgoing {
+        for e in graph.edges_to(node_id) {
+            if matches_edge(&e, pattern) {
+                let next = e.source_id.clone();
+                out.push((e, next));
+            }
+        }
+    }
+    out
+}
+
+fn candidate_starts<G: GraphSource>(graph: &G, pattern: &NodePattern) -> Vec<KnowledgeNode> {
+    let candidates = if let Some(id) = &pattern.id {
+        graph.node(id).into_iter().collect()
+    } else if let Some(label) = &pattern.label {
+        graph.nodes_with_label(label)
+    } else if let Some(node_type) = &pattern.node_type {
+        graph.nodes_of_type(node_type)
+    } else {
+        graph.all_nodes(MAX_ROWS)
+    };
+
+    candidates.into_iter().filter(|n| matches_node(n, pattern)).collect()
+}
+
+/// Hop from `start_id` along matching edges, over paths that do not revisit a node.
+/// A node is reported once for every hop count within [min_hops, max_hops] at which
+/// such a path reaches it, with the first path found for that hop count.
+fn traverse<G: GraphSource>(
+    graph: &G,
+    start_id: &str,
+    pattern: &EdgePattern,
+    budget: &mut usize,
+) -> Vec<(String, Vec<String>, Vec<String>, f32)> {
+    let mut results = Vec::new();
+    let mut visited: HashSet<(String, u32)> = HashSet::new(); // (node, hops)
+    let mut queue: VecDeque<(String, Vec<String>, Vec<String>, f32)> = VecDeque::new();
+
+    queue.push_back((start_id.to_string(), Vec::new(), Vec::new(), 0.0));
+
+    while let Some((current, path, edges, weight)) = queue.pop_front() {
+        let depth = edges.len() as u32;
+        if depth >= pattern.min_hops && depth > 0 {
+            results.push((current.clone(), path.clone(), edges.clone(), weight));
+        }
+        if depth >= pattern.max_hops {
+            continue;
+        }
+
+        for (edge, next) in step_edges(graph, &current, pattern) {
+            if *budget == 0 {
+                return results;
+            }
+            *budget -= 1;
+
+            if next == start_id || path.contains(&next) {
+                continue;
+            }
+            if visited.insert((next.clone(), depth + 1)) {
+                let mut new_path = path.clone();
+                new_path.push(next.clone());
+                let mut new_edges = edges.clone();
+                new_edges.push(edge.id.clone());
+                queue.push_back((next, new_path, new_edges, weight + edge.weight));
+            }
+        }
+    }
+
+    results
+}
+
+fn aggregate_key(node: &KnowledgeNode, aggregate: &Aggregate) -> String {
+    match aggregate {
+        Aggregate::Count => "count".to_string(),
+        Aggregate::CountByType => agent_store::node_type_key(&node.node_type),
+        Aggregate::CountByProperty(key) => node.properties.get(key).cloned().unwrap_or_default(),
+    }
+}
+
+fn validate(query: &GraphQuery) -> Result<(), String> {
+    for step in &query.steps {
+        if step.edge.max_hops == 0 || step.edge.min_hops > step.edge.max_hops {
+            return Err(format!(
+                "Invalid hop range {}..{}",
+                step.edge.min_hops, step.edge.max_hops
+            ));
+        }
+        if step.edge.max_hops > MAX_HOPS {
+            return Err(format!("Max hops is {}", MAX_HOPS));
+        }
+    }
+    Ok(())
+}
+
+/// Run a path-pattern query against a graph
+pub fn execute<G: GraphSource>(graph: &G, query: &GraphQuery) -> Result<QueryResult, String> {
+    validate(query)?;
+
+    let mut truncated = false;
+    let mut budget = MAX_EXPANSIONS;
+
+    let mut rows: Vec<QueryRow> = candidate_starts(graph, &query.start)
+        .into_iter()
+        .map(|n| QueryRow {
+            path: vec![n.id.clone()],
+            nodes: vec![n],
+            edges: Vec::new(),
+            hops: 0,
+            total_weight: 0.0,
+        })
+        .collect();
+
+    for step in &query.steps {
+        let mut next_rows = Vec::new();
+        'rows: for row in &rows {
+            let last = row.path.last().cloned().unwrap_or_default();
+            for (node_id, path, edges, weight) in traverse(graph, &last, &step.edge, &mut budget) {
+                let node = match graph.node(&node_id) {
+                    Some(n) if matches_node(&n, &step.node) => n,
+                    _ => continue,
+                };
+
+                let mut new_row = row.clone();
+                new_row.nodes.push(node);
+                new_row.path.extend(path);
+                new_row.hops += edges.len() as u32;
+                new_row.edges.extend(edges);
+                new_row.total_weight += weight;
+                next_rows.push(new_row);
+
+                if next_rows.len() >= MAX_ROWS {
+                    truncated = true;
+                    break 'rows;
+                }
+            }
+        }
+        rows = next_rows;
+    }
+
+    if budget == 0 {
+        truncated = true;
+    }
+
+    let mut aggregates = Vec::new();
+    if let Some(aggregate) = &query.aggregate {
+        let mut groups: HashMap<String, AggregateRow> = HashMap::new();
+        for row in &rows {
+            let node = match row.nodes.last() {
+                Some(n) => n,
+                None => continue,
+            };
+            let key = aggregate_key(node, aggregate);
+            let entry = groups.entry(key.clone()).or_insert(AggregateRow {
+                key,
+                count: 0,
+                min_hops: u32::MAX,
+                total_weight: 0.0,
+            });
+            entry.count += 1;
+            entry.min_hops = entry.min_hops.min(row.hops);
+            entry.total_weight += row.total_weight;
+        }
+        aggregates = groups.into_values().collect();
+        aggregates.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+    }
+
+    let total_matches = rows.len() as u64;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT) as usize;
+    if rows.len() > limit {
+        rows.truncate(limit);
+        truncated = true;
+    }
+
+    Ok(QueryResult {
+        rows,
+        aggregates,
+        total_matches,
+        truncated,
+    })
+}
+
+#[derive(PartialEq)]
+struct HeapEntry {
+    cost: f32,
+    node_id: String,
+    hops: u32,
+}
+
+impl Eq for HeapEntry {}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so BinaryHeap pops the cheapest entry first
+        other.cost.total_cmp(&self.cost)
+            .then_with(|| self.node_id.cmp(&other.node_id))
+            .then_with(|| other.hops.cmp(&self.hops))
+    }
+}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Dijkstra shortest path using edge `weight` as cost (negative weights count as 0),
+/// over paths of at most `pattern.max_hops` edges. Search states are (node, hops)
+/// labels; a label is dropped when another reaches the node in no more hops for no
+/// more cost. `Ok(None)` means no such path exists; exhausting the expansion budget
+/// is an error, since a path may still exist.
+pub fn shortest_path<G: GraphSource>(
+    graph: &G,
+    start_id: &str,
+    end_id: &str,
+    pattern: &EdgePattern,
+) -> Result<Option<WeightedPath>, String> {
+    if pattern.max_hops == 0 || pattern.max_hops > MAX_HOPS {
+        return Err(format!("Max hops must be between 1 and {}", MAX_HOPS));
+    }
+    if graph.node(start_id).is_none() || graph.node(end_id).is_none() {
+        return Ok(None);
+    }
+
+    let mut labels: HashMap<String, Vec<(u32, f32)>> = HashMap::new(); // node -> live (hops, cost)
+    let mut previous: HashMap<(String, u32), (String, String)> = HashMap::new(); // (node, hops) -> (prev node, edge)
+    let mut heap = BinaryHeap::new();
+    let mut budget = MAX_EXPANSIONS;
+
+    labels.insert(start_id.to_string(), vec![(0, 0.0)]);
+    heap.push(HeapEntry { cost: 0.0, node_id: start_id.to_string(), hops: 0 });
+
+    while let Some(HeapEntry { cost, node_id, hops }) = heap.pop() {
+        if node_id == end_id {
+            let mut node_ids = vec![node_id.clone()];
+            let mut edge_ids = Vec::new();
+            let mut current = (node_id, hops);
+            while let Some((prev, edge)) = previous.get(&current) {
+                edge_ids.push(edge.clone());
+                node_ids.push(prev.clone());
+                current = (prev.clone(), current.1 - 1);
+            }
+            node_ids.reverse();
+            edge_ids.reverse();
+            return Ok(Some(WeightedPath {
+                hops,
+                node_ids,
+                edge_ids,
+                total_weight: cost,
+            }));
+        }
+        let live = labels.get(&node_id).is_some_and(|l| l.contains(&(hops, cost)));
+        if !live || hops >= pattern.max_hops {
+            continue;
+        }
+
+        for (edge, next) in step_edges(graph, &node_id, pattern) {
+            if budget == 0 {
+                return Err(format!("Path search exceeded its budget of {} edge expansions", MAX_EXPANSIONS));
+            }
+            budget -= 1;
+
+            let next_cost = cost + edge.weight.max(0.0);
+            let next_hops = hops + 1;
+            let next_labels = labels.entry(next.clone()).or_default();
+            if next_labels.iter().any(|&(h, c)| h <= next_hops && c <= next_cost) {
+                continue;
+            }
+            next_labels.retain(|&(h, c)| !(next_hops <= h && next_cost <= c));
+            next_labels.push((next_hops, next_cost));
+            previous.insert((next.clone(), next_hops), (node_id.clone(), edge.id.clone()));
+            heap.push(HeapEntry { cost: next_cost, node_id: next, hops: next_hops });
+        }
+    }
+
+    Ok(None)
+}
+
+// === Text syntax ===
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(String),
+    Sym(char),
+    Range, // ".."
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let quote = c;
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && chars[i] != quote {
+                i += 1;
+            }
+            if i >= chars.len() {
+                return Err("Unterminated string".to_string());
+            }
+            tokens.push(Token::Str(chars[start..i].iter().collect()));
+            i += 1;
+        } else if c == '.' && chars.get(i + 1) == Some(&'.') {
+            tokens.push(Token::Range);
+            i += 2;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_digit() || (chars[i] == '.' && chars.get(i + 1) != Some(&'.')))
+            {
+                i += 1;
+            }
+            tokens.push(Token::Num(chars[start..i].iter().collect()));
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '-') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "()[]{}:,*|-<>=!~".contains(c) {
+            tokens.push(Token::Sym(c));
+            i += 1;
+        } else {
+            return Err(format!("Unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn eat_sym(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Sym(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_sym(&mut self, c: char) -> Result<(), String> {
+        if self.eat_sym(c) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}' at token {}", c, self.pos))
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(s)) if s.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(s)) => Ok(s),
+            other => Err(format!("Expected identifier, found {:?}", other)),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        match self.next() {
+            Some(Token::Num(n)) => n.parse().map_err(|_| format!("Invalid number {}", n)),
+            other => Err(format!("Expected number, found {:?}", other)),
+        }
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Str(s)) | Some(Token::Num(s)) | Some(Token::Ident(s)) => Ok(s),
+            other => Err(format!("Expected value, found {:?}", other)),
+        }
+    }
+
+    fn compare_op(&mut self) -> Result<CompareOp, String> {
+        let op = match self.next() {
+            Some(Token::Sym(':')) => CompareOp::Eq,
+            Some(Token::Sym('=')) => CompareOp::Eq,
+            Some(Token::Sym('~')) => CompareOp::Contains,
+            Some(Token::Sym('!')) => {
+                self.expect_sym('=')?;
+                CompareOp::NotEq
+            }
+            Some(Token::Sym('>')) => {
+                if self.eat_sym('=') { CompareOp::Gte } else { CompareOp::Gt }
+            }
+            Some(Token::Sym('<')) => {
+                if self.eat_sym('=') { CompareOp::Lte } else { CompareOp::Lt }
+            }
+            other => return Err(format!("Expected comparison, found {:?}", other)),
+        };
+        Ok(op)
+    }
+
+    // (var:Type {key: value, key > value})
+    fn node(&mut self) -> Result<NodePattern, String> {
+        self.expect_sym('(')?;
+        let mut pattern = NodePattern::default();
+
+        if let Some(Token::Ident(_)) = self.peek() {
+            self.next();
+        }
+        if self.eat_sym(':') {
+            let type_name = self.ident()?;
+            pattern.node_type = Some(parse_node_type(&type_name)?);
+        }
+        if self.eat_sym('{') {
+            loop {
+                let key = self.ident()?;
+                let op = self.compare_op()?;
+                let value = self.value()?;
+                match key.as_str() {
+                    "label" if op == CompareOp::Eq => pattern.label = Some(value),
+                    "id" if op == CompareOp::Eq => pattern.id = Some(value),
+                    _ => pattern.properties.push(PropertyFilter { key, op, value }),
+                }
+                if !self.eat_sym(',') {
+                    break;
+                }
+            }
+            self.expect_sym('}')?;
+        }
+
+        self.expect_sym(')')?;
+        Ok(pattern)
+    }
+
+    // -[rel|rel2*1..3]->  <-[rel]-  -[rel]-
+    fn edge(&mut self) -> Result<EdgePattern, String> {
+        let incoming = self.eat_sym('<');
+        self.expect_sym('-')?;
+        let mut pattern = EdgePattern::default();
+
+        if self.eat_sym('[') {
+            self.eat_sym(':');
+            if let Some(Token::Ident(_)) = self.peek() {
+                pattern.relationships.push(self.ident()?);
+                while self.eat_sym('|') {
+                    self.eat_sym(':');
+                    pattern.relationships.push(self.ident()?);
+                }
+            }
+            if self.eat_sym('*') {
+                pattern.min_hops = 1;
+                pattern.max_hops = MAX_HOPS;
+                if let Some(Token::Num(_)) = self.peek() {
+                    pattern.min_hops = self.number()?;
+                    pattern.max_hops = pattern.min_hops;
+                }
+                if self.peek() == Some(&Token::Range) {
+                    self.next();
+                    pattern.max_hops = match self.peek() {
+                        Some(Token::Num(_)) => self.number()?,
+                        _ => MAX_HOPS,
+                    };
+                }
+            }
+            self.expect_sym(']')?;
+        }
+
+        self.expect_sym('-')?;
+        let outgoing = self.eat_sym('>');
+        pattern.direction = match (incoming, outgoing) {
+            (true, false) => EdgeDirection::Incoming,
+            (false, true) => EdgeDirection::Outgoing,
+            (false, false) => EdgeDirection::Both,
+            (true, true) => return Err("Edge cannot point both ways".to_string()),
+        };
+        Ok(pattern)
+    }
+}
+
+fn parse_node_type(name: &str) -> Result<NodeType, String> {
+    match name.to_lowercase().as_str() {
+        "entity" => Ok(NodeType::Entity),
+        "concept" => Ok(NodeType::Concept),
+        "event" => Ok(NodeType::Event),
+        "action" => Ok(NodeType::Action),
+        "attribute" => Ok(NodeType::Attribute),
+        _ => Err(format!("Unknown node type {}", name)),
+    }
+}
+
+/// Parse the text form: `MATCH <node> (<edge> <node>)* [RETURN count [BY type|<prop>]] [LIMIT n]`
+pub fn parse_query(input: &str) -> Result<GraphQuery, String> {
+    let mut parser = Parser { tokens: tokenize(input)?, pos: 0 };
+
+    if !parser.eat_keyword("match") {
+        return Err("Query must start with MATCH".to_string());
+    }
+
+    let mut query = GraphQuery {
+        start: parser.node()?,
+        ..Default::default()
+    };
+
+    while matches!(parser.peek(), Some(Token::Sym('-')) | Some(Token::Sym('<'))) {
+        let edge = parser.edge()?;
+        let node = parser.node()?;
+        query.steps.push(PathStep { edge, node });
+    }
+
+    if parser.eat_keyword("return") {
+        if parser.eat_keyword("count") {
+            query.aggregate = Some(if parser.eat_keyword("by") {
+                let key = parser.ident()?;
+                if key.eq_ignore_ascii_case("type") {
+                    Aggregate::CountByType
+                } else {
+                    Aggregate::CountByProperty(key)
+                }
+            } else {
+                Aggregate::Count
+            });
+        } else {
+            // Rows are always returned; projected variables are accepted and ignored
+            while let Some(Token::Ident(s)) = parser.peek() {
+                if s.eq_ignore_ascii_case("limit") {
+                    break;
+                }
+                parser.next();
+                parser.eat_sym(',');
+            }
+        }
+    }
+
+    if parser.eat_keyword("limit") {
+        query.limit = Some(parser.number()?);
+    }
+
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("Unexpected trailing input at token {}", parser.pos));
+    }
+
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::node;
+
+    fn edge(id: &str, source: &str, target: &str, relationship: &str, weight: f32) -> KnowledgeEdge {
+        KnowledgeEdge {
+            id: id.to_string(),
+            source_id: source.to_string(),
+            target_id: target.to_string(),
+            relationship: relationship.to_string(),
+            weight,
+            properties: HashMap::new(),
+            created_at: 0,
+        }
+    }
+
+    // wallet -owns-> vault -owns-> token-a, wallet -owns-> token-b, wallet -likes-> token-c
+    fn wallet_graph() -> KnowledgeGraph {
+        let mut graph = KnowledgeGraph::new();
+        graph.add_node(node("w", NodeType::Entity, "wallet-1", &[("kind", "wallet")]));
+        graph.add_node(node("v", NodeType::Entity, "vault", &[("kind", "vault")]));
+        graph.add_node(node("a", NodeType::Entity, "token-a", &[("kind", "token"), ("value", "50")]));
+        graph.add_node(node("b", NodeType::Entity, "token-b", &[("kind", "token"), ("value", "5")]));
+        graph.add_node(node("c", NodeType::Entity, "token-c", &[("kind", "token")]));
+        graph.add_edge(edge("e1", "w", "v", "owns", 1.0)).unwrap();
+        graph.add_edge(edge("e2", "v", "a", "owns", 1.0)).unwrap();
+        graph.add_edge(edge("e3", "w", "b", "owns", 5.0)).unwrap();
+        graph.add_edge(edge("e4", "w", "c", "likes", 1.0)).unwrap();
+        graph.add_edge(edge("e5", "a", "b", "swaps", 1.0)).unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_tokens_owned_within_hops() {
+        let graph = wallet_graph();
+        let query = parse_query(
+            r#"MATCH (w {label: "wallet-1"})-[owns*1..3]->(t:Entity {kind: "token"}) RETURN t"#,
+        )
+        .unwrap();
+
+        let result = execute(&graph, &query).unwrap();
+        let mut found: Vec<String> = result.rows.iter().map(|r| r.nodes[1].id.clone()).collect();
+        found.sort();
+        assert_eq!(found, vec!["a", "b"]);
+
+        let via_vault = result.rows.iter().find(|r| r.nodes[1].id == "a").unwrap();
+        assert_eq!(via_vault.path, vec!["w", "v", "a"]);
+        assert_eq!(via_vault.hops, 2);
+    }
+
+    #[test]
+    fn test_property_comparison_and_aggregate() {
+        let graph = wallet_graph();
+        let query = parse_query("MATCH (w {label: 'wallet-1'})-[*]->(t {value > 10}) RETURN count BY kind").unwrap();
+        let result = execute(&graph, &query).unwrap();
+
+        assert_eq!(result.total_matches, 1);
+        assert_eq!(result.aggregates.len(), 1);
+        assert_eq!(result.aggregates[0].key, "token");
+        assert_eq!(result.aggregates[0].count, 1);
+    }
+
+    #[test]
+    fn test_incoming_direction() {
+        let graph = wallet_graph();
+        let query = parse_query("MATCH (t {label: 'token-b'})<-[owns]-(owner) LIMIT 5").unwrap();
+        let result = execute(&graph, &query).unwrap();
+
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0].nodes[1].id, "w");
+    }
+
+    #[test]
+    fn test_weighted_shortest_path() {
+        let graph = wallet_graph();
+        let pattern = EdgePattern {
+            direction: EdgeDirection::Outgoing,
+            max_hops: MAX_HOPS,
+            ..Default::default()
+        };
+
+        // Direct w->b costs 5, w->v->a->b costs 3
+        let path = shortest_path(&graph, "w", "b", &pattern).unwrap().unwrap();
+        assert_eq!(path.node_ids, vec!["w", "v", "a", "b"]);
+        assert_eq!(path.total_weight, 3.0);
+
+        assert!(shortest_path(&graph, "b", "w", &pattern).unwrap().is_none());
+
+        // Within two hops only the direct edge qualifies
+        let short = EdgePattern { max_hops: 2, ..pattern.clone() };
+        let path = shortest_path(&graph, "w", "b", &short).unwrap().unwrap();
+        assert_eq!((path.node_ids, path.hops, path.total_weight), (vec!["w".to_string(), "b".to_string()], 1, 5.0));
+
+        assert!(shortest_path(&graph, "w", "b", &EdgePattern { max_hops: MAX_HOPS + 1, ..pattern }).is_err());
+    }
+
+    #[test]
+    fn test_traverse_reports_every_hop_count() {
+        let graph = wallet_graph();
+        // token-b is one hop from the wallet, and three hops via vault and token-a
+        let query = parse_query("MATCH (w {label: 'wallet-1'})-[*2..3]->(t {kind: 'token'})").unwrap();
+        let result = execute(&graph, &query).unwrap();
+        let mut found: Vec<(String, u32)> = result.rows.iter().map(|r| (r.nodes[1].id.clone(), r.hops)).collect();
+        found.sort();
+        assert_eq!(found, vec![("a".to_string(), 2), ("b".to_string(), 3)]);
+
+        // Paths do not return to a node they already passed
+        let query = parse_query("MATCH (w {label: 'wallet-1'})-[*1..4]-(x {label: 'wallet-1'})").unwrap();
+        assert_eq!(execute(&graph, &query).unwrap().total_matches, 0);
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        assert!(parse_query("(a)-->(b)").is_err());
+        assert!(parse_query("MATCH (a:Planet)").is_err());
+
+        let too_deep = GraphQuery {
+            steps: vec![PathStep {
+                edge: EdgePattern { max_hops: MAX_HOPS + 1, ..Default::default() },
+                node: NodePattern::default(),
+            }],
+            ..Default::default()
+        };
+        assert!(execute(&KnowledgeGraph::new(), &too_deep).is_err());
+    }
+}
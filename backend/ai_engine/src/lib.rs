@@ -1,8 +1,14 @@
 //! AI Engine Canister - Route optimization, LLM Council, and AI Memory features
 //! Handles HTTPS outcalls for AI services, multi-LLM consensus, and persistent memory
 
+pub mod agent_store;
+pub mod consolidation;
+pub mod graph_query;
 pub mod llm_council;
 pub mod memory;
+pub mod sharing;
+#[cfg(test)]
+mod test_support;
 
 use candid::{CandidType, Decode, Encode, Principal};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
@@ -23,6 +29,28 @@ const CACHE_MEM_ID: MemoryId = MemoryId::new(0);
 const CONFIG_MEM_ID: MemoryId = MemoryId::new(1);
 const LLM_SESSIONS_MEM_ID: MemoryId = MemoryId::new(2);
 const AGENT_MEMORY_MEM_ID: MemoryId = MemoryId::new(3);
+const AGENT_META_MEM_ID: MemoryId = MemoryId::new(4);
+const AGENT_RECORDS_MEM_ID: MemoryId = MemoryId::new(5);
+const AGENT_NODES_MEM_ID: MemoryId = MemoryId::new(6);
+const AGENT_EDGES_MEM_ID: MemoryId = MemoryId::new(7);
+const AGENT_VECTORS_MEM_ID: MemoryId = MemoryId::new(8);
+const AGENT_INDEX_MEM_ID: MemoryId = MemoryId::new(9);
+const SHARE_GRANTS_MEM_ID: MemoryId = MemoryId::new(10);
+const SHARE_EXPIRY_MEM_ID: MemoryId = MemoryId::new(11);
+const MAINTENANCE_DUE_MEM_ID: MemoryId = MemoryId::new(12);
+const PENDING_SUMMARIES_MEM_ID: MemoryId = MemoryId::new(13);
+
+// Scheduled memory maintenance: timer period, agents processed per tick and
+// expired shares ended per sweep
+const MAINTENANCE_TICK_SECS: u64 = 300;
+const MAINTENANCE_AGENTS_PER_TICK: usize = 10;
+const EXPIRED_SHARES_PER_SWEEP: usize = 20;
+
+// Legacy memory migration: principals moved per timer callback
+const MIGRATION_PRINCIPALS_PER_BATCH: usize = 10;
+
+// Council sessions are heap-only; pending summaries reopened per timer callback after upgrade
+const REOPEN_SUMMARIES_PER_BATCH: usize = 50;
 
 // Route optimization result
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -78,7 +106,7 @@ impl Storable for StorableCouncilSession {
     const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
 }
 
-// Agent Memory wrapper for stable storage
+// Legacy agent memory wrapper (whole KIPMemoryLink per principal), migrated after upgrade
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct StorableAgentMemory {
     pub memory: memory::KIPMemoryLink,
@@ -176,12 +204,67 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(LLM_SESSIONS_MEM_ID))
         ));
 
-    // Agent memory storage (principal -> memory)
+    // Legacy agent memory storage (principal -> memory), drained in batches after upgrade
     static AGENT_MEMORIES: RefCell<StableBTreeMap<StorableString, StorableAgentMemory, MemoryType>> =
         RefCell::new(StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(AGENT_MEMORY_MEM_ID))
         ));
 
+    // Agent memory, keyed per entry (see agent_store)
+    static AGENT_META: RefCell<StableBTreeMap<agent_store::AgentKey, agent_store::AgentMeta, MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AGENT_META_MEM_ID))
+        ));
+
+    static AGENT_RECORDS: RefCell<StableBTreeMap<agent_store::EntryKey, memory::Memory, MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AGENT_RECORDS_MEM_ID))
+        ));
+
+    static AGENT_NODES: RefCell<StableBTreeMap<agent_store::EntryKey, memory::KnowledgeNode, MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AGENT_NODES_MEM_ID))
+        ));
+
+    static AGENT_EDGES: RefCell<StableBTreeMap<agent_store::EntryKey, memory::KnowledgeEdge, MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AGENT_EDGES_MEM_ID))
+        ));
+
+    static AGENT_VECTORS: RefCell<StableBTreeMap<agent_store::EntryKey, memory::VectorEntry, MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AGENT_VECTORS_MEM_ID))
+        ));
+
+    // Secondary indexes (memory tags/types, node types/labels)
+    static AGENT_INDEX: RefCell<StableBTreeMap<agent_store::IndexKey, (), MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(AGENT_INDEX_MEM_ID))
+        ));
+
+    // Cross-agent share grants (grant_id -> grant) and their expiry queue
+    static SHARE_GRANTS: RefCell<StableBTreeMap<StorableString, sharing::ShareGrant, MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SHARE_GRANTS_MEM_ID))
+        ));
+
+    static SHARE_EXPIRY: RefCell<StableBTreeMap<sharing::ExpiryKey, (), MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SHARE_EXPIRY_MEM_ID))
+        ));
+
+    // Scheduled memory maintenance queue (due time + agent)
+    static MAINTENANCE_DUE: RefCell<StableBTreeMap<consolidation::MaintenanceDue, (), MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MAINTENANCE_DUE_MEM_ID))
+        ));
+
+    // Council sessions rewriting consolidated summaries (session_id -> owner, agent, summary id)
+    static PENDING_SUMMARIES: RefCell<StableBTreeMap<StorableString, consolidation::PendingSummary, MemoryType>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PENDING_SUMMARIES_MEM_ID))
+        ));
+
     // In-memory LLM Council for active operations
     static LLM_COUNCIL: RefCell<llm_council::LLMCouncil> =
         RefCell::new(llm_council::LLMCouncil::new(llm_council::CouncilConfig::default()));
@@ -205,13 +288,114 @@ fn init() {
         config.admin = caller;
         c.borrow_mut().set(config).unwrap();
     });
+
+    start_maintenance_timer();
 }
 
 #[pre_upgrade]
 fn pre_upgrade() {}
 
 #[post_upgrade]
-fn post_upgrade() {}
+fn post_upgrade() {
+    ic_cdk_timers::set_timer(std::time::Duration::ZERO, migrate_legacy_agent_memories);
+    ic_cdk_timers::set_timer(std::time::Duration::ZERO, || reopen_pending_summaries(None));
+    start_maintenance_timer();
+}
+
+/// Timers do not survive upgrades, so this is started from both init and post_upgrade
+fn start_maintenance_timer() {
+    ic_cdk_timers::set_timer_interval(std::time::Duration::from_secs(MAINTENANCE_TICK_SECS), || {
+        let now = ic_cdk::api::time();
+        for (agent, report) in consolidation::run_due_maintenance(now, MAINTENANCE_AGENTS_PER_TICK) {
+            request_council_summaries(&agent.owner, &agent.agent_id, &report);
+        }
+        sharing::sweep_expired(now, EXPIRED_SHARES_PER_SWEEP);
+    });
+}
+
+/// Open council sessions for summaries of agents configured to use the LLM Council
+fn request_council_summaries(owner: &str, agent_id: &str, report: &consolidation::ConsolidationReport) -> Vec<String> {
+    let uses_council = agent_store::get_meta(owner, agent_id)
+        .and_then(|m| m.consolidation)
+        .map(|c| c.summarizer == consolidation::Summarizer::LlmCouncil)
+        .unwrap_or(false);
+    if !uses_council {
+        return Vec::new();
+    }
+
+    report.summary_ids
+        .iter()
+        .filter_map(|summary_id| open_council_session(owner, agent_id, summary_id))
+        .collect()
+}
+
+/// Open a council session rewriting one consolidated summary and record it as pending.
+/// Session ids are derived from the summary id, so reopening one reuses its key.
+fn open_council_session(owner: &str, agent_id: &str, summary_id: &str) -> Option<String> {
+    let summary = agent_store::get_memory(owner, agent_id, summary_id)?;
+    let sources = agent_store::archived_memories(owner, agent_id, summary_id);
+    let now = ic_cdk::api::time();
+    let council_query = llm_council::CouncilQuery {
+        query_id: format!("consolidate-{}", summary_id),
+        user_query: consolidation::council_prompt(&summary, &sources),
+        context: None,
+        requested_at: now,
+        priority: llm_council::QueryPriority::Low,
+    };
+    let session_id = LLM_COUNCIL.with(|c| c.borrow_mut().create_session(council_query));
+    PENDING_SUMMARIES.with(|p| {
+        p.borrow_mut().insert(StorableString(session_id.clone()), consolidation::PendingSummary {
+            owner: owner.to_string(),
+            agent_id: agent_id.to_string(),
+            summary_id: summary_id.to_string(),
+        })
+    });
+    Some(session_id)
+}
+
+/// Council sessions do not survive upgrades, so reopen the ones still pending in bounded
+/// batches. Pending summaries whose summary memory is gone are dropped.
+fn reopen_pending_summaries(after: Option<String>) {
+    let start = match &after {
+        Some(key) => std::ops::Bound::Excluded(StorableString(key.clone())),
+        None => std::ops::Bound::Unbounded,
+    };
+    let batch: Vec<(StorableString, consolidation::PendingSummary)> = PENDING_SUMMARIES.with(|p| {
+        p.borrow().range((start, std::ops::Bound::Unbounded)).take(REOPEN_SUMMARIES_PER_BATCH).collect()
+    });
+
+    for (session_id, pending) in &batch {
+        let live = LLM_COUNCIL.with(|c| c.borrow().get_session(&session_id.0).is_some());
+        if !live && open_council_session(&pending.owner, &pending.agent_id, &pending.summary_id).is_none() {
+            PENDING_SUMMARIES.with(|p| p.borrow_mut().remove(session_id));
+        }
+    }
+
+    if batch.len() == REOPEN_SUMMARIES_PER_BATCH {
+        let last = batch.last().map(|(k, _)| k.0.clone());
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, move || reopen_pending_summaries(last));
+    }
+}
+
+/// Split pre-existing per-principal memory blobs into the keyed agent maps. Runs a
+/// bounded batch per timer callback, rescheduling itself until the legacy map is empty,
+/// so a large legacy store cannot exhaust the upgrade's instruction limit.
+fn migrate_legacy_agent_memories() {
+    let now = ic_cdk::api::time();
+    let legacy: Vec<(StorableString, StorableAgentMemory)> =
+        AGENT_MEMORIES.with(|m| m.borrow().iter().take(MIGRATION_PRINCIPALS_PER_BATCH).collect());
+
+    for (principal, stored) in legacy {
+        for agent in stored.memory.agent_memories.values() {
+            agent_store::import_agent_memory(&principal.0, agent, now);
+        }
+        AGENT_MEMORIES.with(|m| m.borrow_mut().remove(&principal));
+    }
+
+    if AGENT_MEMORIES.with(|m| !m.borrow().is_empty()) {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, migrate_legacy_agent_memories);
+    }
+}
 
 // === Route Optimization ===
 
@@ -278,7 +462,7 @@ async fn optimize_route(origin: String, destination: String) -> Result<RouteOpti
     let (res,) = http_request(request, 20_000_000_000).await
         .map_err(|(code, msg)| format!("HTTP request failed: {:?} - {}", code, msg))?;
 
-    if res.status != 200 {
+    if res.status != 200u64 {
         return Err(format!("API returned error status: {}", res.status));
     }
 
@@ -566,9 +750,27 @@ fn finalize_council_response(
     final_response: String,
     summary: String,
 ) -> Result<llm_council::CouncilResult, String> {
-    LLM_COUNCIL.with(|c| {
+    // Sessions opened by memory consolidation write their answer back into the summary,
+    // so only the agent's owner or an admin may finalize them
+    let key = StorableString(session_id.clone());
+    let pending = PENDING_SUMMARIES.with(|p| p.borrow().get(&key));
+    if let Some(pending) = &pending {
+        let caller = ic_cdk::caller();
+        if caller.to_text() != pending.owner && !is_admin(caller) {
+            return Err("Only the agent owner or admin can finalize a consolidation session".to_string());
+        }
+    }
+
+    let result = LLM_COUNCIL.with(|c| {
         c.borrow_mut().set_final_response(&session_id, final_response, summary)
-    })
+    })?;
+
+    if let Some(pending) = pending {
+        PENDING_SUMMARIES.with(|p| p.borrow_mut().remove(&key));
+        consolidation::apply_council_summary(&pending.owner, &pending.agent_id, &pending.summary_id, result.final_response.clone())?;
+    }
+
+    Ok(result)
 }
 
 /// Get council session status
@@ -595,31 +797,39 @@ fn get_council_config() -> llm_council::CouncilConfig {
 
 // === Agent Memory API ===
 
+fn parse_memory_type(memory_type: &str) -> memory::MemoryType {
+    match memory_type {
+        "short_term" => memory::MemoryType::ShortTerm,
+        "long_term" => memory::MemoryType::LongTerm,
+        "episodic" => memory::MemoryType::Episodic,
+        "semantic" => memory::MemoryType::Semantic,
+        "procedural" => memory::MemoryType::Procedural,
+        _ => memory::MemoryType::ShortTerm,
+    }
+}
+
+fn parse_node_type(node_type: &str) -> memory::NodeType {
+    match node_type {
+        "entity" => memory::NodeType::Entity,
+        "concept" => memory::NodeType::Concept,
+        "event" => memory::NodeType::Event,
+        "action" => memory::NodeType::Action,
+        "attribute" => memory::NodeType::Attribute,
+        _ => memory::NodeType::Entity,
+    }
+}
+
 /// Get or create agent memory for a user
 #[update]
 fn get_agent_memory(agent_id: String) -> memory::AgentMemory {
-    let caller = ic_cdk::caller();
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        let mut memories = m.borrow_mut();
-        
-        if let Some(stored) = memories.get(&StorableString(principal_str.clone())) {
-            let mut link = stored.memory.clone();
-            link.get_agent_memory(&agent_id).clone()
-        } else {
-            let mut link = memory::KIPMemoryLink::new(principal_str.clone());
-            let agent_mem = link.get_agent_memory(&agent_id).clone();
-            memories.insert(
-                StorableString(principal_str),
-                StorableAgentMemory { memory: link }
-            );
-            agent_mem
-        }
-    })
+    let principal_str = ic_cdk::caller().to_text();
+    let now = ic_cdk::api::time();
+
+    agent_store::ensure_agent(&principal_str, &agent_id, now);
+    agent_store::load_agent_memory(&principal_str, &agent_id, now)
 }
 
-/// Add a memory for an agent
+/// Add a memory for an agent, optionally with an embedding used for consolidation clustering
 #[update]
 fn remember(
     agent_id: String,
@@ -627,62 +837,54 @@ fn remember(
     memory_type: String,
     importance: f32,
     tags: Vec<String>,
+    embedding: Option<Vec<f32>>,
 ) -> Result<String, String> {
     let caller = ic_cdk::caller();
     if caller == Principal::anonymous() {
         return Err("Authentication required".to_string());
     }
 
-    let mem_type = match memory_type.as_str() {
-        "short_term" => memory::MemoryType::ShortTerm,
-        "long_term" => memory::MemoryType::LongTerm,
-        "episodic" => memory::MemoryType::Episodic,
-        "semantic" => memory::MemoryType::Semantic,
-        "procedural" => memory::MemoryType::Procedural,
-        _ => memory::MemoryType::ShortTerm,
-    };
-
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        let mut memories = m.borrow_mut();
-        
-        let mut link = if let Some(stored) = memories.get(&StorableString(principal_str.clone())) {
-            stored.memory.clone()
-        } else {
-            memory::KIPMemoryLink::new(principal_str.clone())
-        };
-
-        let agent_mem = link.get_agent_memory(&agent_id);
-        let memory_id = agent_mem.remember(content, mem_type, importance, tags);
+    let owner = caller.to_text();
+    let now = ic_cdk::api::time();
+    if let Some(embedding) = &embedding {
+        agent_store::check_embedding(&owner, &agent_id, embedding, now)?;
+    }
 
-        memories.insert(
-            StorableString(principal_str),
-            StorableAgentMemory { memory: link }
-        );
+    let memory_id = agent_store::remember(
+        &owner,
+        &agent_id,
+        content,
+        parse_memory_type(&memory_type),
+        importance,
+        tags,
+        now,
+    );
+    if let Some(embedding) = embedding {
+        agent_store::set_embedding(&owner, &agent_id, &memory_id, embedding, now)?;
+    }
 
-        Ok(memory_id)
-    })
+    Ok(memory_id)
 }
 
 /// Recall memories relevant to a query
 #[query]
 fn recall(agent_id: String, query: String, max_results: u32) -> Vec<memory::Memory> {
-    let caller = ic_cdk::caller();
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        if let Some(stored) = m.borrow().get(&StorableString(principal_str)) {
-            let mut link = stored.memory.clone();
-            let agent_mem = link.get_agent_memory(&agent_id);
-            agent_mem.recall(&query, max_results as usize)
-                .into_iter()
-                .cloned()
-                .collect()
-        } else {
-            Vec::new()
-        }
-    })
+    let principal_str = ic_cdk::caller().to_text();
+    agent_store::recall(&principal_str, &agent_id, &query, max_results as usize)
+}
+
+/// Get memories carrying a tag
+#[query]
+fn recall_by_tag(agent_id: String, tag: String) -> Vec<memory::Memory> {
+    let principal_str = ic_cdk::caller().to_text();
+    agent_store::memories_by_tag(&principal_str, &agent_id, &tag)
+}
+
+/// Get memories of a type
+#[query]
+fn recall_by_type(agent_id: String, memory_type: String) -> Vec<memory::Memory> {
+    let principal_str = ic_cdk::caller().to_text();
+    agent_store::memories_by_type(&principal_str, &agent_id, &parse_memory_type(&memory_type))
 }
 
 /// Add context to agent conversation
@@ -693,43 +895,16 @@ fn add_context(agent_id: String, message: String) -> Result<String, String> {
         return Err("Authentication required".to_string());
     }
 
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        let mut memories = m.borrow_mut();
-        
-        let mut link = if let Some(stored) = memories.get(&StorableString(principal_str.clone())) {
-            stored.memory.clone()
-        } else {
-            memory::KIPMemoryLink::new(principal_str.clone())
-        };
+    agent_store::add_context(&caller.to_text(), &agent_id, message, ic_cdk::api::time());
 
-        let agent_mem = link.get_agent_memory(&agent_id);
-        agent_mem.add_context(message);
-
-        memories.insert(
-            StorableString(principal_str),
-            StorableAgentMemory { memory: link }
-        );
-
-        Ok("Context added".to_string())
-    })
+    Ok("Context added".to_string())
 }
 
 /// Get current conversation context
 #[query]
 fn get_context(agent_id: String) -> String {
-    let caller = ic_cdk::caller();
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        if let Some(stored) = m.borrow().get(&StorableString(principal_str)) {
-            let mut link = stored.memory.clone();
-            link.get_agent_memory(&agent_id).get_context()
-        } else {
-            String::new()
-        }
-    })
+    let principal_str = ic_cdk::caller().to_text();
+    agent_store::get_context(&principal_str, &agent_id)
 }
 
 /// Add a knowledge node to agent's knowledge graph
@@ -745,47 +920,19 @@ fn add_knowledge_node(
         return Err("Authentication required".to_string());
     }
 
-    let n_type = match node_type.as_str() {
-        "entity" => memory::NodeType::Entity,
-        "concept" => memory::NodeType::Concept,
-        "event" => memory::NodeType::Event,
-        "action" => memory::NodeType::Action,
-        "attribute" => memory::NodeType::Attribute,
-        _ => memory::NodeType::Entity,
+    let now = ic_cdk::api::time();
+    let node = memory::KnowledgeNode {
+        id: format!("node-{}", now),
+        node_type: parse_node_type(&node_type),
+        label,
+        properties: properties.into_iter().collect(),
+        embedding: None,
+        created_at: now,
+        updated_at: now,
+        provenance: None,
     };
 
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        let mut memories = m.borrow_mut();
-        
-        let mut link = if let Some(stored) = memories.get(&StorableString(principal_str.clone())) {
-            stored.memory.clone()
-        } else {
-            memory::KIPMemoryLink::new(principal_str.clone())
-        };
-
-        let agent_mem = link.get_agent_memory(&agent_id);
-        
-        let node = memory::KnowledgeNode {
-            id: format!("node-{}", ic_cdk::api::time()),
-            node_type: n_type,
-            label,
-            properties: properties.into_iter().collect(),
-            embedding: None,
-            created_at: ic_cdk::api::time(),
-            updated_at: ic_cdk::api::time(),
-        };
-
-        let node_id = agent_mem.knowledge_graph.add_node(node);
-
-        memories.insert(
-            StorableString(principal_str),
-            StorableAgentMemory { memory: link }
-        );
-
-        Ok(node_id)
-    })
+    Ok(agent_store::add_node(&caller.to_text(), &agent_id, node, now))
 }
 
 /// Add a knowledge edge between nodes
@@ -802,107 +949,202 @@ fn add_knowledge_edge(
         return Err("Authentication required".to_string());
     }
 
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        let mut memories = m.borrow_mut();
-        
-        let mut link = if let Some(stored) = memories.get(&StorableString(principal_str.clone())) {
-            stored.memory.clone()
-        } else {
-            memory::KIPMemoryLink::new(principal_str.clone())
-        };
-
-        let agent_mem = link.get_agent_memory(&agent_id);
-        
-        let edge = memory::KnowledgeEdge {
-            id: format!("edge-{}", ic_cdk::api::time()),
-            source_id,
-            target_id,
-            relationship,
-            weight,
-            properties: HashMap::new(),
-            created_at: ic_cdk::api::time(),
-        };
-
-        let result = agent_mem.knowledge_graph.add_edge(edge)?;
-
-        memories.insert(
-            StorableString(principal_str),
-            StorableAgentMemory { memory: link }
-        );
-
-        Ok(result)
-    })
+    let now = ic_cdk::api::time();
+    let edge = memory::KnowledgeEdge {
+        id: format!("edge-{}", now),
+        source_id,
+        target_id,
+        relationship,
+        weight,
+        properties: HashMap::new(),
+        created_at: now,
+    };
+
+    agent_store::add_edge(&caller.to_text(), &agent_id, edge, now)
 }
 
 /// Find knowledge node by label
 #[query]
 fn find_knowledge_node(agent_id: String, label: String) -> Option<memory::KnowledgeNode> {
+    let principal_str = ic_cdk::caller().to_text();
+    agent_store::find_node_by_label(&principal_str, &agent_id, &label)
+}
+
+/// Run a structured pattern query over the agent's knowledge graph
+#[query]
+fn query_knowledge_graph(agent_id: String, query: graph_query::GraphQuery) -> Result<graph_query::QueryResult, String> {
+    let principal_str = ic_cdk::caller().to_text();
+    let graph = graph_query::AgentGraph { owner: &principal_str, agent_id: &agent_id };
+    graph_query::execute(&graph, &query)
+}
+
+/// Run a text query (e.g. `MATCH (w {label: "wallet"})-[owns*1..3]->(t) RETURN t`)
+#[query]
+fn query_knowledge_graph_text(agent_id: String, query: String) -> Result<graph_query::QueryResult, String> {
+    let parsed = graph_query::parse_query(&query)?;
+    let principal_str = ic_cdk::caller().to_text();
+    let graph = graph_query::AgentGraph { owner: &principal_str, agent_id: &agent_id };
+    graph_query::execute(&graph, &parsed)
+}
+
+/// Weighted shortest path between two nodes (edge weight as cost), of at most
+/// `max_hops` edges (default and upper bound `MAX_HOPS`)
+#[query]
+fn find_knowledge_path(
+    agent_id: String,
+    start_id: String,
+    end_id: String,
+    relationships: Vec<String>,
+    direction: graph_query::EdgeDirection,
+    max_hops: Option<u32>,
+) -> Result<Option<graph_query::WeightedPath>, String> {
+    let principal_str = ic_cdk::caller().to_text();
+    let graph = graph_query::AgentGraph { owner: &principal_str, agent_id: &agent_id };
+    let pattern = graph_query::EdgePattern {
+        relationships,
+        direction,
+        max_hops: max_hops.unwrap_or(graph_query::MAX_HOPS),
+        ..Default::default()
+    };
+    graph_query::shortest_path(&graph, &start_id, &end_id, &pattern)
+}
+
+// === Knowledge Sharing API ===
+
+/// Share knowledge from one of the caller's agents with another agent
+#[update]
+fn share_knowledge(request: sharing::ShareRequest) -> Result<sharing::ShareGrant, String> {
     let caller = ic_cdk::caller();
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        if let Some(stored) = m.borrow().get(&StorableString(principal_str)) {
-            let mut link = stored.memory.clone();
-            let agent_mem = link.get_agent_memory(&agent_id);
-            agent_mem.knowledge_graph.find_by_label(&label).cloned()
-        } else {
-            None
-        }
-    })
+    if caller == Principal::anonymous() {
+        return Err("Authentication required".to_string());
+    }
+    if Principal::from_text(&request.to_owner).is_err() {
+        return Err("Invalid recipient principal".to_string());
+    }
+
+    sharing::create_grant(&caller.to_text(), request, ic_cdk::api::time())
+}
+
+/// Accept a Copy share addressed to the caller, copying its knowledge into their agent
+#[update]
+fn accept_share(grant_id: String) -> Result<sharing::ShareGrant, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Authentication required".to_string());
+    }
+
+    sharing::accept_grant(&caller.to_text(), &grant_id, ic_cdk::api::time())
+}
+
+/// Decline a Copy share addressed to the caller
+#[update]
+fn decline_share(grant_id: String) -> Result<sharing::ShareGrant, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Authentication required".to_string());
+    }
+
+    sharing::decline_grant(&caller.to_text(), &grant_id, ic_cdk::api::time())
+}
+
+/// Read live knowledge shared with the caller
+#[query]
+fn read_shared_knowledge(grant_id: String) -> Result<sharing::SharedKnowledge, String> {
+    let principal_str = ic_cdk::caller().to_text();
+    sharing::read_shared(&principal_str, &grant_id, ic_cdk::api::time())
+}
+
+/// Revoke a share, removing or flagging copied knowledge
+#[update]
+fn revoke_share(grant_id: String, action: sharing::RevokeAction) -> Result<sharing::ShareGrant, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Authentication required".to_string());
+    }
+
+    sharing::revoke_grant(&caller.to_text(), &grant_id, action, ic_cdk::api::time())
+}
+
+/// List what an agent has shared and received
+#[query]
+fn list_shares(agent_id: String) -> sharing::ShareListing {
+    let principal_str = ic_cdk::caller().to_text();
+    sharing::list_grants(&principal_str, &agent_id)
+}
+
+/// End a batch of expired shares ahead of the maintenance timer (returns number ended)
+#[update]
+fn sweep_expired_shares() -> Result<u64, String> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err("Only admin can sweep expired shares".to_string());
+    }
+    Ok(sharing::sweep_expired(ic_cdk::api::time(), EXPIRED_SHARES_PER_SWEEP))
 }
 
 /// Perform memory maintenance (consolidation, decay, forgetting)
 #[update]
-fn maintain_memory(agent_id: String) -> Result<String, String> {
+fn maintain_memory(agent_id: String) -> Result<consolidation::ConsolidationReport, String> {
     let caller = ic_cdk::caller();
     if caller == Principal::anonymous() {
         return Err("Authentication required".to_string());
     }
 
-    let principal_str = caller.to_text();
+    let owner = caller.to_text();
+    let mut report = consolidation::maintain_agent(&owner, &agent_id, ic_cdk::api::time())?;
+    report.council_sessions = request_council_summaries(&owner, &agent_id, &report);
 
-    AGENT_MEMORIES.with(|m| {
-        let mut memories = m.borrow_mut();
-        
-        if let Some(stored) = memories.get(&StorableString(principal_str.clone())) {
-            let mut link = stored.memory.clone();
-            let agent_mem = link.get_agent_memory(&agent_id);
-            agent_mem.maintain();
+    Ok(report)
+}
+
+/// Run maintenance + consolidation for an agent on a timer
+#[update]
+fn schedule_memory_maintenance(
+    agent_id: String,
+    interval_secs: u64,
+    config: Option<consolidation::ConsolidationConfig>,
+) -> Result<u64, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Authentication required".to_string());
+    }
+    if interval_secs < MAINTENANCE_TICK_SECS {
+        return Err(format!("Interval must be at least {} seconds", MAINTENANCE_TICK_SECS));
+    }
 
-            memories.insert(
-                StorableString(principal_str),
-                StorableAgentMemory { memory: link }
-            );
+    let interval_ns = interval_secs.checked_mul(1_000_000_000)
+        .ok_or("Interval is too large")?;
 
-            Ok("Memory maintenance completed".to_string())
-        } else {
-            Err("No memory found for user".to_string())
-        }
-    })
+    consolidation::schedule_maintenance(
+        &caller.to_text(),
+        &agent_id,
+        interval_ns,
+        config.unwrap_or_default(),
+        ic_cdk::api::time(),
+    )
+}
+
+/// Stop scheduled maintenance for an agent
+#[update]
+fn cancel_memory_maintenance(agent_id: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Authentication required".to_string());
+    }
+    consolidation::cancel_maintenance(&caller.to_text(), &agent_id)
+}
+
+/// Original memories archived into a consolidated summary
+#[query]
+fn get_archived_memories(agent_id: String, summary_id: String) -> Vec<Memory> {
+    let principal_str = ic_cdk::caller().to_text();
+    agent_store::archived_memories(&principal_str, &agent_id, &summary_id)
 }
 
 /// Get memory statistics for an agent
 #[query]
 fn get_memory_stats(agent_id: String) -> (u64, u64, u64) {
-    let caller = ic_cdk::caller();
-    let principal_str = caller.to_text();
-
-    AGENT_MEMORIES.with(|m| {
-        if let Some(stored) = m.borrow().get(&StorableString(principal_str)) {
-            let mut link = stored.memory.clone();
-            let agent_mem = link.get_agent_memory(&agent_id);
-            
-            let memory_count = agent_mem.memory_store.memories.len() as u64;
-            let node_count = agent_mem.knowledge_graph.nodes.len() as u64;
-            let edge_count = agent_mem.knowledge_graph.edges.len() as u64;
-            
-            (memory_count, node_count, edge_count)
-        } else {
-            (0, 0, 0)
-        }
-    })
+    let principal_str = ic_cdk::caller().to_text();
+    agent_store::stats(&principal_str, &agent_id)
 }
 
 // Generate Candid
@@ -31,6 +31,7 @@ pub struct Memory {
     pub metadata: HashMap<String, String>,
     pub related_memories: Vec<String>, // IDs of related memories
     pub tags: Vec<String>,
+    pub provenance: Option<Provenance>, // Set on copies shared from another agent
 }
 
 /// Knowledge Graph Node
@@ -43,6 +44,19 @@ pub struct KnowledgeNode {
     pub embedding: Option<Vec<f32>>,
     pub created_at: u64,
     pub updated_at: u64,
+    pub provenance: Option<Provenance>, // Set on copies shared from another agent
+}
+
+/// Origin of knowledge copied from another agent
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Provenance {
+    pub source_owner: String,
+    pub source_agent: String,
+    pub source_id: String,
+    pub source_version: u64,  // updated_at (nodes) / created_at (memories) of the source
+    pub grant_id: String,
+    pub copied_at: u64,
+    pub revoked: bool,        // Source owner revoked the share; kept but flagged
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -345,7 +359,7 @@ impl VectorStore {
     }
 
     /// Cosine similarity between two vectors
-    fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
         if a.len() != b.len() {
             return 0.0;
         }
@@ -428,6 +442,7 @@ impl AgentMemory {
             metadata: HashMap::new(),
             related_memories: Vec::new(),
             tags,
+            provenance: None,
         };
 
         self.memory_store.add_memory(memory)
@@ -503,15 +518,28 @@ impl KIPMemoryLink {
             ))
     }
 
-    /// Share knowledge between agents
+    /// Share knowledge between agents (copies are stamped with their provenance)
     pub fn share_knowledge(&mut self, from_agent: &str, to_agent: &str, node_ids: Vec<String>) {
         let from_memory = match self.agent_memories.get(from_agent) {
             Some(m) => m,
             None => return,
         };
 
+        let now = ic_cdk::api::time();
         let nodes_to_share: Vec<KnowledgeNode> = node_ids.iter()
             .filter_map(|id| from_memory.knowledge_graph.nodes.get(id).cloned())
+            .map(|mut node| {
+                node.provenance = Some(Provenance {
+                    source_owner: self.principal_id.clone(),
+                    source_agent: from_agent.to_string(),
+                    source_id: node.id.clone(),
+                    source_version: node.updated_at,
+                    grant_id: String::new(),
+                    copied_at: now,
+                    revoked: false,
+                });
+                node
+            })
             .collect();
 
         let edges_to_share: Vec<KnowledgeEdge> = from_memory.knowledge_graph.edges.values()
@@ -553,6 +581,7 @@ mod tests {
             metadata: HashMap::new(),
             related_memories: Vec::new(),
             tags: vec!["test".to_string()],
+            provenance: None,
         };
 
         let id = store.add_memory(memory);
@@ -572,6 +601,7 @@ mod tests {
             embedding: None,
             created_at: 0,
             updated_at: 0,
+            provenance: None,
         };
 
         let node2 = KnowledgeNode {
@@ -582,6 +612,7 @@ mod tests {
             embedding: None,
             created_at: 0,
             updated_at: 0,
+            provenance: None,
         };
 
         graph.add_node(node1);
@@ -0,0 +1,535 @@
+//! Cross-Agent Knowledge Sharing
+//! The owner of an agent issues grants that let another agent read (live) or copy
+//! selected knowledge nodes and memories. Copy grants stay pending until the recipient
+//! accepts them, so nothing is written into an agent its owner did not agree to.
+//! Copies carry a `Provenance` pointing back to the source, and revoking or expiring a
+//! grant removes or flags them.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::agent_store::{self, IndexKind};
+use crate::memory::{KnowledgeEdge, KnowledgeNode, Memory, Provenance};
+use crate::{StorableString, SHARE_EXPIRY, SHARE_GRANTS};
+
+/// Max nodes + memories in one grant
+pub const MAX_SHARE_ITEMS: usize = 500;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ShareMode {
+    Read,  // Recipient reads the source entries live while the grant is active
+    Copy,  // Entries are copied into the recipient agent with provenance
+}
+
+/// What happens to copies when a grant is revoked or expires
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RevokeAction {
+    Remove,
+    Flag,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum GrantStatus {
+    Pending, // Copy grant awaiting the recipient's acceptance
+    Active,
+    Declined,
+    Revoked,
+    Expired,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ShareRequest {
+    pub from_agent: String,
+    pub to_owner: String,
+    pub to_agent: String,
+    pub mode: ShareMode,
+    pub node_ids: Vec<String>,
+    pub memory_ids: Vec<String>,
+    pub include_edges: bool,  // Also share edges whose both ends are shared
+    pub expires_at: Option<u64>,
+    pub on_expiry: RevokeAction,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ShareGrant {
+    pub grant_id: String,
+    pub owner: String,
+    pub from_agent: String,
+    pub to_owner: String,
+    pub to_agent: String,
+    pub mode: ShareMode,
+    pub node_ids: Vec<String>,
+    pub memory_ids: Vec<String>,
+    pub include_edges: bool,
+    pub expires_at: Option<u64>,
+    pub on_expiry: RevokeAction,
+    pub status: GrantStatus,
+    // Ids of the copies in the recipient agent (Copy mode)
+    pub copied_node_ids: Vec<String>,
+    pub copied_edge_ids: Vec<String>,
+    pub copied_memory_ids: Vec<String>,
+    pub created_at: u64,
+    pub ended_at: Option<u64>,
+}
+
+impl ShareGrant {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.status == GrantStatus::Active && self.expires_at.is_none_or(|t| now < t)
+    }
+
+    /// Pending or active: not yet declined, revoked or expired
+    fn is_open(&self) -> bool {
+        matches!(self.status, GrantStatus::Pending | GrantStatus::Active)
+    }
+}
+
+/// Live view of a grant's source entries (Read mode)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SharedKnowledge {
+    pub nodes: Vec<KnowledgeNode>,
+    pub edges: Vec<KnowledgeEdge>,
+    pub memories: Vec<Memory>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ShareListing {
+    pub shared: Vec<ShareGrant>,
+    pub received: Vec<ShareGrant>,
+}
+
+/// Expiry queue entry, ordered by time
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExpiryKey {
+    pub expires_at: u64,
+    pub grant_id: String,
+}
+
+impl Storable for ShareGrant {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for ExpiryKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+pub fn get_grant(grant_id: &str) -> Option<ShareGrant> {
+    SHARE_GRANTS.with(|g| g.borrow().get(&StorableString(grant_id.to_string())))
+}
+
+fn put_grant(grant: &ShareGrant) {
+    SHARE_GRANTS.with(|g| {
+        g.borrow_mut().insert(StorableString(grant.grant_id.clone()), grant.clone());
+    });
+}
+
+/// Id of a copy inside the recipient agent
+fn copy_id(grant_id: &str, source_id: &str) -> String {
+    format!("{}:{}", grant_id, source_id)
+}
+
+fn provenance(grant: &ShareGrant, source_id: &str, source_version: u64, now: u64) -> Provenance {
+    Provenance {
+        source_owner: grant.owner.clone(),
+        source_agent: grant.from_agent.clone(),
+        source_id: source_id.to_string(),
+        source_version,
+        grant_id: grant.grant_id.clone(),
+        copied_at: now,
+        revoked: false,
+    }
+}
+
+/// Edges of the source agent whose two ends are both in `node_ids`
+fn edges_within(owner: &str, agent_id: &str, node_ids: &[String]) -> Vec<KnowledgeEdge> {
+    node_ids
+        .iter()
+        .flat_map(|id| agent_store::edges_from(owner, agent_id, id))
+        .filter(|e| node_ids.contains(&e.target_id))
+        .collect()
+}
+
+/// Issue a grant from one of `owner`'s agents. Read grants are active at once; Copy
+/// grants are pending until the recipient calls `accept_grant`.
+pub fn create_grant(owner: &str, request: ShareRequest, now: u64) -> Result<ShareGrant, String> {
+    if request.to_owner == owner && request.to_agent == request.from_agent {
+        return Err("Cannot share an agent's knowledge with itself".to_string());
+    }
+    if request.node_ids.is_empty() && request.memory_ids.is_empty() {
+        return Err("Nothing to share".to_string());
+    }
+    if request.node_ids.len() + request.memory_ids.len() > MAX_SHARE_ITEMS {
+        return Err(format!("At most {} items per grant", MAX_SHARE_ITEMS));
+    }
+    if let Some(expires_at) = request.expires_at {
+        if expires_at <= now {
+            return Err("Expiry must be in the future".to_string());
+        }
+    }
+
+    // Only the agent's own knowledge may be shared; received copies stay with the recipient
+    for id in &request.node_ids {
+        let node = agent_store::get_node(owner, &request.from_agent, id)
+            .ok_or(format!("Node {} not found", id))?;
+        if node.provenance.is_some() {
+            return Err(format!("Node {} was shared from another agent and cannot be re-shared", id));
+        }
+    }
+    for id in &request.memory_ids {
+        let memory = agent_store::get_memory(owner, &request.from_agent, id)
+            .ok_or(format!("Memory {} not found", id))?;
+        if memory.provenance.is_some() {
+            return Err(format!("Memory {} was shared from another agent and cannot be re-shared", id));
+        }
+    }
+
+    let grant_count = SHARE_GRANTS.with(|g| g.borrow().len());
+    let status = match request.mode {
+        ShareMode::Read => GrantStatus::Active,
+        ShareMode::Copy => GrantStatus::Pending,
+    };
+    let grant = ShareGrant {
+        grant_id: format!("grant-{}-{}", now, grant_count),
+        owner: owner.to_string(),
+        from_agent: request.from_agent,
+        to_owner: request.to_owner,
+        to_agent: request.to_agent,
+        mode: request.mode,
+        node_ids: request.node_ids,
+        memory_ids: request.memory_ids,
+        include_edges: request.include_edges,
+        expires_at: request.expires_at,
+        on_expiry: request.on_expiry,
+        status,
+        copied_node_ids: Vec::new(),
+        copied_edge_ids: Vec::new(),
+        copied_memory_ids: Vec::new(),
+        created_at: now,
+        ended_at: None,
+    };
+
+    put_grant(&grant);
+    agent_store::index_insert(&grant.owner, &grant.from_agent, IndexKind::GrantOut, "", &grant.grant_id);
+    agent_store::index_insert(&grant.to_owner, &grant.to_agent, IndexKind::GrantIn, "", &grant.grant_id);
+    if let Some(expires_at) = grant.expires_at {
+        SHARE_EXPIRY.with(|e| {
+            e.borrow_mut().insert(ExpiryKey { expires_at, grant_id: grant.grant_id.clone() }, ());
+        });
+    }
+
+    Ok(grant)
+}
+
+/// Accept a pending Copy grant (recipient only): the source entries that still exist
+/// are copied into the recipient agent and the grant becomes active
+pub fn accept_grant(caller: &str, grant_id: &str, now: u64) -> Result<ShareGrant, String> {
+    let mut grant = get_grant(grant_id).ok_or("Grant not found")?;
+    if grant.to_owner != caller {
+        return Err("Grant was not issued to caller".to_string());
+    }
+    if grant.status != GrantStatus::Pending {
+        return Err("Grant is not awaiting acceptance".to_string());
+    }
+    if grant.expires_at.is_some_and(|t| now >= t) {
+        return Err("Grant has expired".to_string());
+    }
+
+    agent_store::ensure_agent(&grant.to_owner, &grant.to_agent, now);
+
+    let mut copied_nodes = Vec::new();
+    for id in &grant.node_ids {
+        if let Some(mut node) = agent_store::get_node(&grant.owner, &grant.from_agent, id) {
+            node.provenance = Some(provenance(&grant, id, node.updated_at, now));
+            node.id = copy_id(&grant.grant_id, id);
+            node.updated_at = now;
+            copied_nodes.push(id.clone());
+            grant.copied_node_ids.push(agent_store::add_node(&grant.to_owner, &grant.to_agent, node, now));
+        }
+    }
+
+    if grant.include_edges {
+        for mut edge in edges_within(&grant.owner, &grant.from_agent, &copied_nodes) {
+            edge.id = copy_id(&grant.grant_id, &edge.id);
+            edge.source_id = copy_id(&grant.grant_id, &edge.source_id);
+            edge.target_id = copy_id(&grant.grant_id, &edge.target_id);
+            edge.properties.insert("grant_id".to_string(), grant.grant_id.clone());
+            grant.copied_edge_ids.push(agent_store::add_edge(&grant.to_owner, &grant.to_agent, edge, now)?);
+        }
+    }
+
+    for id in &grant.memory_ids {
+        if let Some(mut memory) = agent_store::get_memory(&grant.owner, &grant.from_agent, id) {
+            memory.provenance = Some(provenance(&grant, id, memory.created_at, now));
+            memory.id = copy_id(&grant.grant_id, id);
+            memory.related_memories.clear();
+            agent_store::put_memory(&grant.to_owner, &grant.to_agent, memory.clone());
+            grant.copied_memory_ids.push(memory.id);
+        }
+    }
+
+    grant.status = GrantStatus::Active;
+    put_grant(&grant);
+    Ok(grant)
+}
+
+/// Decline a pending Copy grant (recipient only); nothing is copied
+pub fn decline_grant(caller: &str, grant_id: &str, now: u64) -> Result<ShareGrant, String> {
+    let grant = get_grant(grant_id).ok_or("Grant not found")?;
+    if grant.to_owner != caller {
+        return Err("Grant was not issued to caller".to_string());
+    }
+    if grant.status != GrantStatus::Pending {
+        return Err("Grant is not awaiting acceptance".to_string());
+    }
+    Ok(end_grant(grant, GrantStatus::Declined, &RevokeAction::Remove, now))
+}
+
+/// Read the live source entries of a grant (recipient only, while active)
+pub fn read_shared(caller: &str, grant_id: &str, now: u64) -> Result<SharedKnowledge, String> {
+    let grant = get_grant(grant_id).ok_or("Grant not found")?;
+    if grant.to_owner != caller {
+        return Err("Grant was not issued to caller".to_string());
+    }
+    if !grant.is_active(now) {
+        return Err("Grant is no longer active".to_string());
+    }
+
+    let nodes: Vec<KnowledgeNode> = grant.node_ids.iter()
+        .filter_map(|id| agent_store::get_node(&grant.owner, &grant.from_agent, id))
+        .collect();
+    let edges = if grant.include_edges {
+        edges_within(&grant.owner, &grant.from_agent, &grant.node_ids)
+    } else {
+        Vec::new()
+    };
+    let memories: Vec<Memory> = grant.memory_ids.iter()
+        .filter_map(|id| agent_store::get_memory(&grant.owner, &grant.from_agent, id))
+        .collect();
+
+    Ok(SharedKnowledge { nodes, edges, memories })
+}
+
+/// Remove or flag a grant's copies in the recipient agent
+fn retract_copies(grant: &ShareGrant, action: &RevokeAction, now: u64) {
+    let (owner, agent) = (&grant.to_owner, &grant.to_agent);
+    match action {
+        RevokeAction::Remove => {
+            for id in &grant.copied_edge_ids {
+                agent_store::delete_edge(owner, agent, id);
+            }
+            for id in &grant.copied_node_ids {
+                agent_store::delete_node(owner, agent, id);
+            }
+            for id in &grant.copied_memory_ids {
+                agent_store::delete_memory(owner, agent, id);
+            }
+        }
+        RevokeAction::Flag => {
+            for id in &grant.copied_node_ids {
+                if let Some(mut node) = agent_store::get_node(owner, agent, id) {
+                    if let Some(p) = node.provenance.as_mut() {
+                        p.revoked = true;
+                    }
+                    agent_store::add_node(owner, agent, node, now);
+                }
+            }
+            for id in &grant.copied_memory_ids {
+                if let Some(mut memory) = agent_store::get_memory(owner, agent, id) {
+                    if let Some(p) = memory.provenance.as_mut() {
+                        p.revoked = true;
+                    }
+                    agent_store::put_memory(owner, agent, memory);
+                }
+            }
+        }
+    }
+}
+
+fn end_grant(mut grant: ShareGrant, status: GrantStatus, action: &RevokeAction, now: u64) -> ShareGrant {
+    if grant.mode == ShareMode::Copy {
+        retract_copies(&grant, action, now);
+    }
+    if let Some(expires_at) = grant.expires_at {
+        SHARE_EXPIRY.with(|e| {
+            e.borrow_mut().remove(&ExpiryKey { expires_at, grant_id: grant.grant_id.clone() });
+        });
+    }
+    grant.status = status;
+    grant.ended_at = Some(now);
+    put_grant(&grant);
+    grant
+}
+
+/// Revoke a grant (issuing owner only)
+pub fn revoke_grant(caller: &str, grant_id: &str, action: RevokeAction, now: u64) -> Result<ShareGrant, String> {
+    let grant = get_grant(grant_id).ok_or("Grant not found")?;
+    if grant.owner != caller {
+        return Err("Only the owner of the sharing agent can revoke".to_string());
+    }
+    if !grant.is_open() {
+        return Err("Grant already ended".to_string());
+    }
+    Ok(end_grant(grant, GrantStatus::Revoked, &action, now))
+}
+
+/// End up to `limit` grants whose expiry has passed, applying their `on_expiry`
+/// action; the rest are left for the next sweep
+pub fn sweep_expired(now: u64, limit: usize) -> u64 {
+    let due: Vec<ExpiryKey> = SHARE_EXPIRY.with(|e| {
+        e.borrow()
+            .iter()
+            .take_while(|(k, _)| k.expires_at <= now)
+            .take(limit)
+            .map(|(k, _)| k)
+            .collect()
+    });
+
+    let mut ended = 0;
+    for key in due {
+        match get_grant(&key.grant_id) {
+            Some(grant) if grant.is_open() => {
+                let action = grant.on_expiry.clone();
+                end_grant(grant, GrantStatus::Expired, &action, now);
+                ended += 1;
+            }
+            _ => {
+                SHARE_EXPIRY.with(|e| e.borrow_mut().remove(&key));
+            }
+        }
+    }
+    ended
+}
+
+/// Grants an agent has issued and received
+pub fn list_grants(owner: &str, agent_id: &str) -> ShareListing {
+    let load = |kind: IndexKind| -> Vec<ShareGrant> {
+        agent_store::index_lookup(owner, agent_id, kind, "")
+            .iter()
+            .filter_map(|id| get_grant(id))
+            .collect()
+    };
+
+    ShareListing {
+        shared: load(IndexKind::GrantOut),
+        received: load(IndexKind::GrantIn),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MemoryType, NodeType};
+    use crate::test_support::node;
+    use std::collections::HashMap;
+
+    fn setup(owner: &str) -> String {
+        agent_store::add_node(owner, "axiom", node("n1", NodeType::Concept, "pepper", &[]), 1);
+        agent_store::add_node(owner, "axiom", node("n2", NodeType::Concept, "seed", &[]), 1);
+        agent_store::add_edge(owner, "axiom", KnowledgeEdge {
+            id: "e1".to_string(),
+            source_id: "n1".to_string(),
+            target_id: "n2".to_string(),
+            relationship: "grows_from".to_string(),
+            weight: 1.0,
+            properties: HashMap::new(),
+            created_at: 1,
+        }, 1).unwrap();
+        agent_store::remember(owner, "axiom", "Peppers like heat".to_string(), MemoryType::Semantic, 0.9, vec![], 2)
+    }
+
+    fn request(to_owner: &str, mode: ShareMode, memory_id: &str) -> ShareRequest {
+        ShareRequest {
+            from_agent: "axiom".to_string(),
+            to_owner: to_owner.to_string(),
+            to_agent: "raven".to_string(),
+            mode,
+            node_ids: vec!["n1".to_string(), "n2".to_string()],
+            memory_ids: vec![memory_id.to_string()],
+            include_edges: true,
+            expires_at: Some(100),
+            on_expiry: RevokeAction::Flag,
+        }
+    }
+
+    #[test]
+    fn test_copy_grant_sets_provenance_and_revoke_removes() {
+        let memory_id = setup("alice-copy");
+        let pending = create_grant("alice-copy", request("bob-copy", ShareMode::Copy, &memory_id), 10).unwrap();
+
+        // Nothing reaches the recipient until they accept
+        assert_eq!(pending.status, GrantStatus::Pending);
+        assert_eq!(agent_store::stats("bob-copy", "raven"), (0, 0, 0));
+        assert!(accept_grant("mallory", &pending.grant_id, 11).is_err());
+        let grant = accept_grant("bob-copy", &pending.grant_id, 11).unwrap();
+        assert_eq!(grant.status, GrantStatus::Active);
+        assert!(accept_grant("bob-copy", &pending.grant_id, 11).is_err());
+
+        let copy = agent_store::get_node("bob-copy", "raven", &grant.copied_node_ids[0]).unwrap();
+        let prov = copy.provenance.unwrap();
+        assert_eq!(prov.source_agent, "axiom");
+        assert_eq!(prov.source_owner, "alice-copy");
+        assert_eq!(prov.grant_id, grant.grant_id);
+        assert_eq!(agent_store::stats("bob-copy", "raven"), (1, 2, 1));
+
+        // Copies cannot be passed on
+        let mut reshare = request("carol-copy", ShareMode::Copy, &grant.copied_memory_ids[0]);
+        reshare.from_agent = "raven".to_string();
+        reshare.node_ids = grant.copied_node_ids.clone();
+        assert!(create_grant("bob-copy", reshare, 11).is_err());
+
+        // Only the issuing owner can revoke
+        assert!(revoke_grant("bob-copy", &grant.grant_id, RevokeAction::Remove, 12).is_err());
+        revoke_grant("alice-copy", &grant.grant_id, RevokeAction::Remove, 12).unwrap();
+        assert_eq!(agent_store::stats("bob-copy", "raven"), (0, 0, 0));
+
+        let listing = list_grants("alice-copy", "axiom");
+        assert_eq!(listing.shared.len(), 1);
+        assert_eq!(listing.shared[0].status, GrantStatus::Revoked);
+        assert_eq!(list_grants("bob-copy", "raven").received.len(), 1);
+    }
+
+    #[test]
+    fn test_read_grant_and_expiry_flags_copies() {
+        let memory_id = setup("alice-read");
+        let read = create_grant("alice-read", request("bob-read", ShareMode::Read, &memory_id), 10).unwrap();
+
+        assert!(read_shared("mallory", &read.grant_id, 20).is_err());
+        let shared = read_shared("bob-read", &read.grant_id, 20).unwrap();
+        assert_eq!(shared.nodes.len(), 2);
+        assert_eq!(shared.edges.len(), 1);
+        assert_eq!(shared.memories.len(), 1);
+        assert_eq!(agent_store::stats("bob-read", "raven"), (0, 0, 0));
+
+        let copy = create_grant("alice-read", request("bob-read", ShareMode::Copy, &memory_id), 10).unwrap();
+        let copy = accept_grant("bob-read", &copy.grant_id, 10).unwrap();
+        let declined = create_grant("alice-read", request("bob-read", ShareMode::Copy, &memory_id), 10).unwrap();
+        assert_eq!(decline_grant("bob-read", &declined.grant_id, 12).unwrap().status, GrantStatus::Declined);
+        let unanswered = create_grant("alice-read", request("bob-read", ShareMode::Copy, &memory_id), 10).unwrap();
+        assert_eq!(sweep_expired(150, 2), 2);
+        assert_eq!(sweep_expired(150, 2), 1);
+        assert_eq!(get_grant(&unanswered.grant_id).unwrap().status, GrantStatus::Expired);
+        assert!(accept_grant("bob-read", &unanswered.grant_id, 150).is_err());
+        assert!(read_shared("bob-read", &read.grant_id, 150).is_err());
+
+        let flagged = agent_store::get_memory("bob-read", "raven", &copy.copied_memory_ids[0]).unwrap();
+        assert!(flagged.provenance.unwrap().revoked);
+        assert_eq!(get_grant(&copy.grant_id).unwrap().status, GrantStatus::Expired);
+    }
+}
@@ -0,0 +1,17 @@
+//! Fixtures shared by the unit tests of the memory modules
+
+use crate::memory::{KnowledgeNode, NodeType};
+
+/// Knowledge node with the given properties and no embedding or provenance
+pub fn node(id: &str, node_type: NodeType, label: &str, props: &[(&str, &str)]) -> KnowledgeNode {
+    KnowledgeNode {
+        id: id.to_string(),
+        node_type,
+        label: label.to_string(),
+        properties: props.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        embedding: None,
+        created_at: 0,
+        updated_at: 0,
+        provenance: None,
+    }
+}
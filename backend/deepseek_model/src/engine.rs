@@ -0,0 +1,905 @@
+//! On-Canister Inference Engine
+//!
+//! CPU forward pass for llama-family GGUF models (Llama, Qwen2 / DeepSeek-R1-Distill):
+//! token embedding -> per layer [RMSNorm, Q/K/V projections, RoPE, grouped-query
+//! attention over the KV cache, output projection, RMSNorm, SwiGLU feed-forward]
+//! -> final RMSNorm -> logits -> temperature / top-p sampling.
+//!
+//! Weights stay quantized in stable memory; each matmul reads one tensor and
+//! dequantizes it row by row. A 7B forward pass does not fit one message, so the
+//! work is a resumable state machine: `run` advances one layer at a time while the
+//! caller's budget allows, and `InferenceState` (token history, KV cache, the
+//! half-finished hidden state and the sampler RNG) is persisted between calls.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use ic_stable_structures::Storable;
+
+use crate::gguf::{self, ByteSource, GgufValue, TensorInfo};
+use crate::quant;
+use crate::tokenizer::byte_to_char;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelConfig {
+    pub arch: String,
+    pub dim: usize,
+    pub n_layers: usize,
+    pub n_heads: usize,
+    pub n_kv_heads: usize,
+    pub head_dim: usize,
+    pub ffn_dim: usize,
+    pub vocab_size: usize,
+    pub context_length: usize,
+    pub rms_eps: f32,
+    pub rope_base: f32,
+    pub rope_neox: bool, // Qwen2 rotates (i, i + d/2) pairs; Llama rotates (2i, 2i + 1)
+}
+
+impl ModelConfig {
+    pub fn kv_dim(&self) -> usize {
+        self.n_kv_heads * self.head_dim
+    }
+}
+
+/// Token strings from the GGUF metadata, with greedy longest-match encoding
+#[derive(Clone, Debug)]
+pub struct Vocab {
+    pub tokens: Vec<String>,
+    lookup: HashMap<String, u32>,
+    max_token_chars: usize,
+    pub bos: Option<u32>,
+    pub eos: Option<u32>,
+    pub add_bos: bool,
+    byte_level: bool, // GPT-2 style byte-to-unicode tokens ("gpt2"); otherwise SentencePiece ("llama")
+}
+
+fn char_to_byte(c: char) -> Option<u8> {
+    (0..=255u8).find(|b| byte_to_char(*b) == c)
+}
+
+impl Vocab {
+    pub fn from_metadata(metadata: &BTreeMap<String, GgufValue>) -> Result<Self, String> {
+        let tokens: Vec<String> = metadata
+            .get("tokenizer.ggml.tokens")
+            .and_then(|v| v.as_array())
+            .ok_or("GGUF has no tokenizer.ggml.tokens")?
+            .iter()
+            .map(|t| t.as_str().unwrap_or_default().to_string())
+            .collect();
+        let lookup = tokens.iter().enumerate().map(|(i, t)| (t.clone(), i as u32)).collect();
+        let max_token_chars = tokens.iter().map(|t| t.chars().count()).max().unwrap_or(1);
+        let id = |key: &str| metadata.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+        Ok(Self {
+            max_token_chars,
+            lookup,
+            bos: id("tokenizer.ggml.bos_token_id"),
+            eos: id("tokenizer.ggml.eos_token_id"),
+            add_bos: matches!(metadata.get("tokenizer.ggml.add_bos_token"), Some(GgufValue::Bool(true))),
+            byte_level: metadata.get("tokenizer.ggml.model").and_then(|v| v.as_str()) != Some("llama"),
+            tokens,
+        })
+    }
+
+    /// Greedy longest-match encoding (characters missing from the vocabulary are skipped)
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        let mapped: Vec<char> = if self.byte_level {
+            text.bytes().map(byte_to_char).collect()
+        } else {
+            format!(" {}", text).replace(' ', "\u{2581}").chars().collect()
+        };
+        let mut ids = Vec::new();
+        // Chat templates spell BOS out themselves
+        let bos_in_text = self.bos.and_then(|id| self.tokens.get(id as usize)).is_some_and(|t| text.starts_with(t.as_str()));
+        if self.add_bos && !bos_in_text {
+            ids.extend(self.bos);
+        }
+        let mut i = 0;
+        while i < mapped.len() {
+            let longest = (1..=self.max_token_chars.min(mapped.len() - i)).rev().find_map(|n| {
+                let piece: String = mapped[i..i + n].iter().collect();
+                self.lookup.get(&piece).map(|id| (*id, n))
+            });
+            match longest {
+                Some((id, n)) => {
+                    ids.push(id);
+                    i += n;
+                }
+                None => i += 1,
+            }
+        }
+        ids
+    }
+
+    pub fn token_id(&self, token: &str) -> Option<u32> {
+        self.lookup.get(token).copied()
+    }
+
+    pub fn decode(&self, ids: &[u32]) -> String {
+        let pieces: String = ids
+            .iter()
+            .filter(|id| Some(**id) != self.bos && Some(**id) != self.eos)
+            .filter_map(|id| self.tokens.get(*id as usize))
+            .map(|s| s.as_str())
+            .collect();
+        if self.byte_level {
+            let bytes: Vec<u8> = pieces.chars().filter_map(char_to_byte).collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        } else {
+            pieces.replace('\u{2581}', " ").trim_start().to_string()
+        }
+    }
+}
+
+pub struct Model {
+    pub config: ModelConfig,
+    pub vocab: Vocab,
+    pub layers: Range<usize>, // Transformer blocks this file holds (all of them unless a pipeline stage)
+    tensors: BTreeMap<String, TensorInfo>,
+}
+
+impl Model {
+    pub fn load<S: ByteSource + ?Sized>(source: &S) -> Result<Self, String> {
+        Self::load_layers(source, 0, None)
+    }
+
+    /// Load only layers `start..end` (a pipeline stage; `end` defaults to the last
+    /// layer): the embedding is needed by the first stage and the output head by the last
+    pub fn load_layers<S: ByteSource + ?Sized>(source: &S, start: usize, end: Option<usize>) -> Result<Self, String> {
+        let file = gguf::parse(source)?;
+        let meta = &file.metadata;
+        let arch = meta.get("general.architecture").and_then(|v| v.as_str()).ok_or("GGUF has no general.architecture")?.to_string();
+        if !matches!(arch.as_str(), "llama" | "qwen2") {
+            return Err(format!("Unsupported architecture {}", arch));
+        }
+        let num = |key: &str| -> Result<usize, String> {
+            meta.get(&format!("{}.{}", arch, key)).and_then(|v| v.as_u64()).map(|v| v as usize)
+                .ok_or_else(|| format!("GGUF missing {}.{}", arch, key))
+        };
+        let float = |key: &str, default: f32| meta.get(&format!("{}.{}", arch, key)).and_then(|v| v.as_f32()).unwrap_or(default);
+
+        let dim = num("embedding_length")?;
+        let n_heads = num("attention.head_count")?;
+        let n_kv_heads = num("attention.head_count_kv").unwrap_or(n_heads);
+        let vocab = Vocab::from_metadata(meta)?;
+        let config = ModelConfig {
+            dim,
+            n_layers: num("block_count")?,
+            n_heads,
+            n_kv_heads,
+            head_dim: dim / n_heads,
+            ffn_dim: num("feed_forward_length")?,
+            vocab_size: file.tensors.get("token_embd.weight").map(|t| t.rows()).unwrap_or(vocab.tokens.len()),
+            context_length: num("context_length").unwrap_or(2048),
+            rms_eps: float("attention.layer_norm_rms_epsilon", 1e-6),
+            rope_base: float("rope.freq_base", 10_000.0),
+            rope_neox: arch == "qwen2",
+            arch,
+        };
+        if n_heads == 0 || n_kv_heads == 0 || dim % n_heads != 0 || n_heads % n_kv_heads != 0 {
+            return Err("Invalid attention head configuration".to_string());
+        }
+
+        let layers = start..end.unwrap_or(config.n_layers);
+        if layers.is_empty() || layers.end > config.n_layers {
+            return Err(format!("Layer range {:?} outside the model's {} layers", layers, config.n_layers));
+        }
+        let model = Self { config, vocab, layers, tensors: file.tensors };
+        for layer in model.layers.clone() {
+            for name in ["attn_norm", "attn_q", "attn_k", "attn_v", "attn_output", "ffn_norm", "ffn_gate", "ffn_up", "ffn_down"] {
+                model.tensor(&format!("blk.{}.{}.weight", layer, name))?;
+            }
+        }
+        if model.is_first_stage() {
+            model.tensor("token_embd.weight")?;
+        }
+        if model.is_last_stage() {
+            model.tensor("output_norm.weight")?;
+            if !model.tensors.contains_key("output.weight") {
+                model.tensor("token_embd.weight")?;
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn is_first_stage(&self) -> bool {
+        self.layers.start == 0
+    }
+
+    pub fn is_last_stage(&self) -> bool {
+        self.layers.end == self.config.n_layers
+    }
+
+    pub fn holds_all_layers(&self) -> bool {
+        self.is_first_stage() && self.is_last_stage()
+    }
+
+    fn tensor(&self, name: &str) -> Result<&TensorInfo, String> {
+        self.tensors.get(name).ok_or_else(|| format!("Tensor {} not found", name))
+    }
+
+    fn read_tensor<S: ByteSource + ?Sized>(&self, source: &S, info: &TensorInfo) -> Result<Vec<u8>, String> {
+        source.read(info.offset, info.byte_len()?)
+    }
+
+    /// y = W x (+ bias when the model has one)
+    fn matvec<S: ByteSource + ?Sized>(&self, source: &S, name: &str, x: &[f32]) -> Result<Vec<f32>, String> {
+        let info = self.tensor(&format!("{}.weight", name))?;
+        if info.row_len() != x.len() {
+            return Err(format!("{}: expected input of {}, got {}", name, info.row_len(), x.len()));
+        }
+        let mut y = quant::matvec(info.ty, &self.read_tensor(source, info)?, info.rows(), x)?;
+        if let Some(bias) = self.tensors.get(&format!("{}.bias", name)) {
+            for (v, b) in y.iter_mut().zip(self.vector(source, bias)?) {
+                *v += b;
+            }
+        }
+        Ok(y)
+    }
+
+    /// A 1-D tensor (norm weights, biases) as f32
+    fn vector<S: ByteSource + ?Sized>(&self, source: &S, info: &TensorInfo) -> Result<Vec<f32>, String> {
+        let mut out = vec![0.0; info.row_len() * info.rows()];
+        quant::dequantize_row(info.ty, &self.read_tensor(source, info)?, &mut out);
+        Ok(out)
+    }
+
+    fn embedding<S: ByteSource + ?Sized>(&self, source: &S, token: u32) -> Result<Vec<f32>, String> {
+        let info = self.tensor("token_embd.weight")?;
+        if token as usize >= info.rows() {
+            return Err(format!("Token {} outside vocabulary", token));
+        }
+        let row_bytes = info.ty.row_bytes(info.row_len())?;
+        let bytes = source.read(info.offset + token as u64 * row_bytes as u64, row_bytes)?;
+        let mut out = vec![0.0; info.row_len()];
+        quant::dequantize_row(info.ty, &bytes, &mut out);
+        Ok(out)
+    }
+
+    fn rms_norm<S: ByteSource + ?Sized>(&self, source: &S, name: &str, x: &[f32]) -> Result<Vec<f32>, String> {
+        let weight = self.vector(source, self.tensor(name)?)?;
+        let mean_sq = x.iter().map(|v| v * v).sum::<f32>() / x.len() as f32;
+        let scale = 1.0 / (mean_sq + self.config.rms_eps).sqrt();
+        Ok(x.iter().zip(weight).map(|(v, w)| v * scale * w).collect())
+    }
+
+    fn rope(&self, v: &mut [f32], heads: usize, pos: usize) {
+        let hd = self.config.head_dim;
+        for head in v.chunks_mut(hd).take(heads) {
+            for i in 0..hd / 2 {
+                let theta = pos as f32 * self.config.rope_base.powf(-((2 * i) as f32) / hd as f32);
+                let (sin, cos) = theta.sin_cos();
+                let (a, b) = if self.config.rope_neox { (i, i + hd / 2) } else { (2 * i, 2 * i + 1) };
+                let (x0, x1) = (head[a], head[b]);
+                head[a] = x0 * cos - x1 * sin;
+                head[b] = x0 * sin + x1 * cos;
+            }
+        }
+    }
+
+    /// One transformer block for the token at `pos`; appends its K/V to the cache
+    fn layer<S: ByteSource + ?Sized>(&self, source: &S, layer: usize, x: &mut [f32], pos: usize, cache: &mut LayerCache) -> Result<(), String> {
+        let c = &self.config;
+        let p = format!("blk.{}", layer);
+
+        let h = self.rms_norm(source, &format!("{}.attn_norm.weight", p), x)?;
+        let mut q = self.matvec(source, &format!("{}.attn_q", p), &h)?;
+        let mut k = self.matvec(source, &format!("{}.attn_k", p), &h)?;
+        let v = self.matvec(source, &format!("{}.attn_v", p), &h)?;
+        self.rope(&mut q, c.n_heads, pos);
+        self.rope(&mut k, c.n_kv_heads, pos);
+
+        let kv_dim = c.kv_dim();
+        if cache.keys.len() != pos * kv_dim {
+            return Err(format!("KV cache out of sync at layer {} position {}", layer, pos));
+        }
+        cache.keys.extend_from_slice(&k);
+        cache.values.extend_from_slice(&v);
+
+        let hd = c.head_dim;
+        let group = c.n_heads / c.n_kv_heads;
+        let scale = 1.0 / (hd as f32).sqrt();
+        let mut attn = vec![0.0f32; c.n_heads * hd];
+        for head in 0..c.n_heads {
+            let kv_off = (head / group) * hd;
+            let qh = &q[head * hd..(head + 1) * hd];
+            let mut scores: Vec<f32> = (0..=pos)
+                .map(|t| {
+                    let kt = &cache.keys[t * kv_dim + kv_off..t * kv_dim + kv_off + hd];
+                    qh.iter().zip(kt).map(|(a, b)| a * b).sum::<f32>() * scale
+                })
+                .collect();
+            softmax(&mut scores);
+            let out = &mut attn[head * hd..(head + 1) * hd];
+            for (t, w) in scores.iter().enumerate() {
+                let vt = &cache.values[t * kv_dim + kv_off..t * kv_dim + kv_off + hd];
+                for (o, v) in out.iter_mut().zip(vt) {
+                    *o += w * v;
+                }
+            }
+        }
+        for (xi, o) in x.iter_mut().zip(self.matvec(source, &format!("{}.attn_output", p), &attn)?) {
+            *xi += o;
+        }
+
+        let h = self.rms_norm(source, &format!("{}.ffn_norm.weight", p), x)?;
+        let gate = self.matvec(source, &format!("{}.ffn_gate", p), &h)?;
+        let up = self.matvec(source, &format!("{}.ffn_up", p), &h)?;
+        let act: Vec<f32> = gate.iter().zip(up).map(|(g, u)| g / (1.0 + (-g).exp()) * u).collect();
+        for (xi, d) in x.iter_mut().zip(self.matvec(source, &format!("{}.ffn_down", p), &act)?) {
+            *xi += d;
+        }
+        Ok(())
+    }
+
+    fn logits<S: ByteSource + ?Sized>(&self, source: &S, x: &[f32]) -> Result<Vec<f32>, String> {
+        let h = self.rms_norm(source, "output_norm.weight", x)?;
+        // Models with tied embeddings have no separate output matrix
+        let head = if self.tensors.contains_key("output.weight") { "output" } else { "token_embd" };
+        self.matvec(source, head, &h)
+    }
+}
+
+fn softmax(v: &mut [f32]) {
+    let max = v.iter().fold(f32::MIN, |m, x| m.max(*x));
+    let mut sum = 0.0;
+    for x in v.iter_mut() {
+        *x = (*x - max).exp();
+        sum += *x;
+    }
+    for x in v.iter_mut() {
+        *x /= sum;
+    }
+}
+
+fn next_random(state: &mut u64) -> f32 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    ((z ^ (z >> 31)) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Pick the next token: greedy at temperature 0, otherwise nucleus (top-p) sampling
+pub fn sample(logits: &[f32], temperature: f32, top_p: f32, rng: &mut u64) -> u32 {
+    if temperature <= 0.0 {
+        return logits.iter().enumerate().fold((0, f32::MIN), |best, (i, v)| if *v > best.1 { (i, *v) } else { best }).0 as u32;
+    }
+    let mut probs: Vec<f32> = logits.iter().map(|l| l / temperature).collect();
+    softmax(&mut probs);
+    let mut order: Vec<usize> = (0..probs.len()).collect();
+    order.sort_by(|a, b| probs[*b].total_cmp(&probs[*a]).then_with(|| a.cmp(b)));
+
+    let mut kept = 0;
+    let mut mass = 0.0;
+    for i in &order {
+        mass += probs[*i];
+        kept += 1;
+        if mass >= top_p.clamp(0.0, 1.0) {
+            break;
+        }
+    }
+    let mut r = next_random(rng) * mass;
+    for i in &order[..kept] {
+        r -= probs[*i];
+        if r <= 0.0 {
+            return *i as u32;
+        }
+    }
+    order[kept - 1] as u32
+}
+
+// ============================================================================
+// Resumable Generation State
+// ============================================================================
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LayerCache {
+    pub keys: Vec<f32>,   // position-major, kv_dim per position
+    pub values: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    EndOfSequence,
+    MaxTokens,
+    ContextFull,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InferenceState {
+    pub tokens: Vec<u32>, // Prompt followed by generated tokens
+    pub prompt_len: u32,
+    pub pos: u32,                 // Tokens fully processed into the KV cache
+    pub layer: Option<u32>,       // Next layer for the token at `pos`, if mid-token
+    pub hidden: Vec<f32>,         // Hidden state of the token at `pos` while mid-token
+    pub cache: Vec<LayerCache>,
+    pub temperature: f32,
+    pub top_p: f32,
+    pub max_new_tokens: u32,
+    pub rng: u64,
+    pub stop: Option<StopReason>,
+    pub stop_tokens: Vec<u32>, // End-of-turn tokens of the chat template, besides EOS
+}
+
+impl InferenceState {
+    pub fn new(config: &ModelConfig, mut prompt: Vec<u32>, max_new_tokens: u32, temperature: f32, top_p: f32, seed: u64) -> Result<Self, String> {
+        if prompt.is_empty() {
+            return Err("Empty prompt".to_string());
+        }
+        // Keep the most recent tokens when the prompt alone would fill the context
+        let room = config.context_length.saturating_sub(max_new_tokens.max(1) as usize).max(1);
+        if prompt.len() > room {
+            prompt.drain(..prompt.len() - room);
+        }
+        Ok(Self {
+            prompt_len: prompt.len() as u32,
+            tokens: prompt,
+            pos: 0,
+            layer: None,
+            hidden: Vec::new(),
+            cache: vec![LayerCache::default(); config.n_layers],
+            temperature,
+            top_p,
+            max_new_tokens,
+            rng: seed,
+            stop: None,
+            stop_tokens: Vec::new(),
+        })
+    }
+
+    pub fn generated(&self) -> &[u32] {
+        &self.tokens[self.prompt_len as usize..]
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.stop.is_some()
+    }
+}
+
+/// Advance generation one layer at a time while `budget()` allows (checked before
+/// every unit of work). Returns the tokens generated during this call.
+pub fn run<S: ByteSource + ?Sized>(model: &Model, source: &S, state: &mut InferenceState, budget: &mut dyn FnMut() -> bool) -> Result<Vec<u32>, String> {
+    let c = &model.config;
+    let mut new_tokens = Vec::new();
+    if !model.holds_all_layers() {
+        return Err("This canister holds a pipeline stage, not the whole model".to_string());
+    }
+
+    while state.stop.is_none() && budget() {
+        let pos = state.pos as usize;
+        match state.layer {
+            None => {
+                state.hidden = model.embedding(source, state.tokens[pos])?;
+                state.layer = Some(0);
+            }
+            Some(layer) if (layer as usize) < c.n_layers => {
+                let mut hidden = std::mem::take(&mut state.hidden);
+                model.layer(source, layer as usize, &mut hidden, pos, &mut state.cache[layer as usize])?;
+                state.hidden = hidden;
+                state.layer = Some(layer + 1);
+            }
+            Some(_) => {
+                state.layer = None;
+                state.pos += 1;
+                if pos + 1 < state.tokens.len() {
+                    // Still reading the prompt: the next token is already known
+                    state.hidden.clear();
+                    continue;
+                }
+                let logits = model.logits(source, &state.hidden)?;
+                state.hidden.clear();
+                let token = sample(&logits, state.temperature, state.top_p, &mut state.rng);
+                state.tokens.push(token);
+                new_tokens.push(token);
+
+                if Some(token) == model.vocab.eos || state.stop_tokens.contains(&token) {
+                    state.stop = Some(StopReason::EndOfSequence);
+                } else if state.generated().len() as u32 >= state.max_new_tokens {
+                    state.stop = Some(StopReason::MaxTokens);
+                } else if state.tokens.len() >= c.context_length {
+                    state.stop = Some(StopReason::ContextFull);
+                }
+            }
+        }
+    }
+    Ok(new_tokens)
+}
+
+/// Logits after reading `tokens` (a fresh forward pass; used for evaluation)
+pub fn prompt_logits<S: ByteSource + ?Sized>(model: &Model, source: &S, tokens: &[u32]) -> Result<Vec<f32>, String> {
+    let mut cache = vec![LayerCache::default(); model.config.n_layers];
+    let mut x = Vec::new();
+    for (pos, token) in tokens.iter().enumerate() {
+        x = model.embedding(source, *token)?;
+        for (layer, cache) in cache.iter_mut().enumerate() {
+            model.layer(source, layer, &mut x, pos, cache)?;
+        }
+    }
+    model.logits(source, &x)
+}
+
+// ============================================================================
+// Pipeline Stages
+// ============================================================================
+
+/// First stage: the token's embedding
+pub fn embed<S: ByteSource + ?Sized>(model: &Model, source: &S, token: u32) -> Result<Vec<f32>, String> {
+    if !model.is_first_stage() {
+        return Err("Only the first pipeline stage embeds tokens".to_string());
+    }
+    model.embedding(source, token)
+}
+
+/// Run the stage's layers over the hidden state of the token at `pos`. `caches[i]`
+/// belongs to layer `model.layers.start + i`; entries at or past `pos`, left by an
+/// earlier attempt of the same step, are discarded so retries are idempotent.
+pub fn forward_stage<S: ByteSource + ?Sized>(model: &Model, source: &S, hidden: &mut [f32], pos: usize, caches: &mut [LayerCache]) -> Result<(), String> {
+    if hidden.len() != model.config.dim || caches.len() != model.layers.len() {
+        return Err(format!("Expected a hidden state of {} and {} layer caches", model.config.dim, model.layers.len()));
+    }
+    let keep = pos * model.config.kv_dim();
+    for (layer, cache) in model.layers.clone().zip(caches.iter_mut()) {
+        cache.keys.truncate(keep);
+        cache.values.truncate(keep);
+        model.layer(source, layer, hidden, pos, cache)?;
+    }
+    Ok(())
+}
+
+/// Last stage: sample the next token from the final hidden state
+pub fn next_token<S: ByteSource + ?Sized>(model: &Model, source: &S, hidden: &[f32], temperature: f32, top_p: f32, rng: &mut u64) -> Result<u32, String> {
+    if !model.is_last_stage() {
+        return Err("Only the last pipeline stage produces tokens".to_string());
+    }
+    Ok(sample(&model.logits(source, hidden)?, temperature, top_p, rng))
+}
+
+// Compact binary encoding: the KV cache dominates and candid is slow on large float vectors
+pub(crate) fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn put_f32s(out: &mut Vec<u8>, v: &[f32]) {
+    put_u32(out, v.len() as u32);
+    for x in v {
+        out.extend_from_slice(&x.to_le_bytes());
+    }
+}
+
+pub(crate) struct Input<'a>(pub &'a [u8], pub usize);
+
+impl Input<'_> {
+    pub fn take(&mut self, n: usize) -> &[u8] {
+        let s = &self.0[self.1..self.1 + n];
+        self.1 += n;
+        s
+    }
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take(4).try_into().unwrap())
+    }
+    fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.take(4).try_into().unwrap())
+    }
+    pub fn f32s(&mut self) -> Vec<f32> {
+        let n = self.u32() as usize;
+        (0..n).map(|_| self.f32()).collect()
+    }
+}
+
+impl Storable for InferenceState {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut out = Vec::new();
+        put_u32(&mut out, self.tokens.len() as u32);
+        for t in &self.tokens {
+            put_u32(&mut out, *t);
+        }
+        put_u32(&mut out, self.prompt_len);
+        put_u32(&mut out, self.pos);
+        put_u32(&mut out, self.layer.map(|l| l + 1).unwrap_or(0));
+        put_f32s(&mut out, &self.hidden);
+        put_u32(&mut out, self.cache.len() as u32);
+        for layer in &self.cache {
+            put_f32s(&mut out, &layer.keys);
+            put_f32s(&mut out, &layer.values);
+        }
+        out.extend_from_slice(&self.temperature.to_le_bytes());
+        out.extend_from_slice(&self.top_p.to_le_bytes());
+        put_u32(&mut out, self.max_new_tokens);
+        out.extend_from_slice(&self.rng.to_le_bytes());
+        out.push(match self.stop {
+            None => 0,
+            Some(StopReason::EndOfSequence) => 1,
+            Some(StopReason::MaxTokens) => 2,
+            Some(StopReason::ContextFull) => 3,
+        });
+        put_u32(&mut out, self.stop_tokens.len() as u32);
+        for t in &self.stop_tokens {
+            put_u32(&mut out, *t);
+        }
+        Cow::Owned(out)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let mut i = Input(&bytes, 0);
+        let n = i.u32() as usize;
+        let tokens = (0..n).map(|_| i.u32()).collect();
+        let prompt_len = i.u32();
+        let pos = i.u32();
+        let layer = match i.u32() {
+            0 => None,
+            l => Some(l - 1),
+        };
+        let hidden = i.f32s();
+        let layers = i.u32() as usize;
+        let cache = (0..layers).map(|_| LayerCache { keys: i.f32s(), values: i.f32s() }).collect();
+        let temperature = i.f32();
+        let top_p = i.f32();
+        let max_new_tokens = i.u32();
+        let rng = u64::from_le_bytes(i.take(8).try_into().unwrap());
+        let stop = match i.take(1)[0] {
+            1 => Some(StopReason::EndOfSequence),
+            2 => Some(StopReason::MaxTokens),
+            3 => Some(StopReason::ContextFull),
+            _ => None,
+        };
+        // States persisted before stop tokens existed end here
+        let stop_tokens = if i.1 < i.0.len() {
+            let n = i.u32() as usize;
+            (0..n).map(|_| i.u32()).collect()
+        } else {
+            Vec::new()
+        };
+        Self { tokens, prompt_len, pos, layer, hidden, cache, temperature, top_p, max_new_tokens, rng, stop, stop_tokens }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[cfg(test)]
+pub(crate) mod test_model {
+    //! A tiny two-layer Qwen2-style model (dim 32, 4 query / 2 KV heads, FFN 256)
+    use super::*;
+    use crate::gguf::writer::{write, TensorData};
+    use crate::quant::{quantize_q4_k, quantize_q8_0, GgmlType};
+
+    pub const TOKENS: [&str; 16] = [
+        "<s>", "</s>", "h", "e", "l", "o", "Ġ", "w", "r", "d", "he", "ll", "Ġw", "or", "hello", "Ġworld",
+    ];
+
+    fn weights(n: usize, seed: u64, scale: f32) -> Vec<f32> {
+        let mut s = seed;
+        (0..n).map(|_| (next_random(&mut s) * 2.0 - 1.0) * scale).collect()
+    }
+
+    fn f32_bytes(v: &[f32]) -> Vec<u8> {
+        v.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    fn matrix(name: &str, cols: usize, rows: usize, ty: GgmlType, seed: u64) -> TensorData {
+        let w = weights(cols * rows, seed, 0.4);
+        let data = match ty {
+            GgmlType::Q8_0 => w.chunks(cols).flat_map(quantize_q8_0).collect(),
+            GgmlType::Q4K => w.chunks(cols).flat_map(quantize_q4_k).collect(),
+            _ => f32_bytes(&w),
+        };
+        TensorData { name: name.into(), dims: vec![cols as u64, rows as u64], ty, data }
+    }
+
+    fn vector(name: &str, n: usize, seed: u64, base: f32) -> TensorData {
+        let v: Vec<f32> = weights(n, seed, 0.1).iter().map(|x| x + base).collect();
+        TensorData { name: name.into(), dims: vec![n as u64], ty: GgmlType::F32, data: f32_bytes(&v) }
+    }
+
+    pub fn build() -> Vec<u8> {
+        let (dim, kv, ffn, vocab) = (32, 16, 256, TOKENS.len());
+        let mut tensors = vec![matrix("token_embd.weight", dim, vocab, GgmlType::F32, 1)];
+        for l in 0..2u64 {
+            let p = |n: &str| format!("blk.{}.{}", l, n);
+            let s = 100 * (l + 1);
+            tensors.push(vector(&p("attn_norm.weight"), dim, s, 1.0));
+            tensors.push(matrix(&p("attn_q.weight"), dim, dim, GgmlType::Q8_0, s + 1));
+            tensors.push(vector(&p("attn_q.bias"), dim, s + 2, 0.0));
+            tensors.push(matrix(&p("attn_k.weight"), dim, kv, GgmlType::Q8_0, s + 3));
+            tensors.push(vector(&p("attn_k.bias"), kv, s + 4, 0.0));
+            tensors.push(matrix(&p("attn_v.weight"), dim, kv, GgmlType::Q8_0, s + 5));
+            tensors.push(vector(&p("attn_v.bias"), kv, s + 6, 0.0));
+            tensors.push(matrix(&p("attn_output.weight"), dim, dim, GgmlType::Q8_0, s + 7));
+            tensors.push(vector(&p("ffn_norm.weight"), dim, s + 8, 1.0));
+            tensors.push(matrix(&p("ffn_gate.weight"), dim, ffn, GgmlType::Q8_0, s + 9));
+            tensors.push(matrix(&p("ffn_up.weight"), dim, ffn, GgmlType::Q8_0, s + 10));
+            tensors.push(matrix(&p("ffn_down.weight"), ffn, dim, GgmlType::Q4K, s + 11));
+        }
+        tensors.push(vector("output_norm.weight", dim, 900, 1.0));
+        tensors.push(matrix("output.weight", dim, vocab, GgmlType::Q8_0, 901));
+
+        write(
+            &[
+                ("general.architecture", GgufValue::Str("qwen2".into())),
+                ("qwen2.embedding_length", GgufValue::UInt(dim as u64)),
+                ("qwen2.block_count", GgufValue::UInt(2)),
+                ("qwen2.feed_forward_length", GgufValue::UInt(ffn as u64)),
+                ("qwen2.attention.head_count", GgufValue::UInt(4)),
+                ("qwen2.attention.head_count_kv", GgufValue::UInt(2)),
+                ("qwen2.context_length", GgufValue::UInt(64)),
+                ("qwen2.attention.layer_norm_rms_epsilon", GgufValue::Float(1e-6)),
+                ("qwen2.rope.freq_base", GgufValue::Float(10000.0)),
+                ("tokenizer.ggml.model", GgufValue::Str("gpt2".into())),
+                ("tokenizer.ggml.tokens", GgufValue::Array(TOKENS.iter().map(|t| GgufValue::Str(t.to_string())).collect())),
+                ("tokenizer.ggml.bos_token_id", GgufValue::UInt(0)),
+                ("tokenizer.ggml.eos_token_id", GgufValue::UInt(1)),
+            ],
+            &tensors,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dense(model: &Model, file: &Vec<u8>, name: &str) -> (Vec<f32>, usize) {
+        let info = model.tensor(name).unwrap();
+        let mut out = vec![0.0; info.row_len() * info.rows()];
+        let row_bytes = info.ty.row_bytes(info.row_len()).unwrap();
+        let bytes = file.read(info.offset, info.byte_len().unwrap()).unwrap();
+        for (r, row) in out.chunks_mut(info.row_len()).enumerate() {
+            quant::dequantize_row(info.ty, &bytes[r * row_bytes..(r + 1) * row_bytes], row);
+        }
+        (out, info.row_len())
+    }
+
+    fn mul(model: &Model, file: &Vec<u8>, name: &str, x: &[f32]) -> Vec<f32> {
+        let (w, cols) = dense(model, file, &format!("{}.weight", name));
+        let mut y: Vec<f32> = w.chunks(cols).map(|r| r.iter().zip(x).map(|(a, b)| a * b).sum()).collect();
+        if model.tensors.contains_key(&format!("{}.bias", name)) {
+            let (b, _) = dense(model, file, &format!("{}.bias", name));
+            y.iter_mut().zip(b).for_each(|(v, b)| *v += b);
+        }
+        y
+    }
+
+    fn norm(model: &Model, file: &Vec<u8>, name: &str, x: &[f32]) -> Vec<f32> {
+        let (w, _) = dense(model, file, name);
+        let rms = (x.iter().map(|v| v * v).sum::<f32>() / x.len() as f32 + 1e-6).sqrt();
+        x.iter().zip(w).map(|(v, w)| v / rms * w).collect()
+    }
+
+    fn rope_neox(v: &mut [f32], hd: usize, pos: usize) {
+        for head in v.chunks_mut(hd) {
+            for i in 0..hd / 2 {
+                let theta = pos as f32 / 10000f32.powf(2.0 * i as f32 / hd as f32);
+                let (x0, x1) = (head[i], head[i + hd / 2]);
+                head[i] = x0 * theta.cos() - x1 * theta.sin();
+                head[i + hd / 2] = x0 * theta.sin() + x1 * theta.cos();
+            }
+        }
+    }
+
+    /// Straightforward full-sequence forward pass (no KV cache) over dequantized weights
+    fn reference_logits(model: &Model, file: &Vec<u8>, tokens: &[u32]) -> Vec<f32> {
+        let (embd, dim) = dense(model, file, "token_embd.weight");
+        let mut xs: Vec<Vec<f32>> = tokens.iter().map(|t| embd[*t as usize * dim..(*t as usize + 1) * dim].to_vec()).collect();
+        let hd = 8;
+        for l in 0..2 {
+            let p = |n: &str| format!("blk.{}.{}", l, n);
+            let mut qs = Vec::new();
+            let mut ks = Vec::new();
+            let mut vs = Vec::new();
+            for (pos, x) in xs.iter().enumerate() {
+                let h = norm(model, file, &p("attn_norm.weight"), x);
+                let mut q = mul(model, file, &p("attn_q"), &h);
+                let mut k = mul(model, file, &p("attn_k"), &h);
+                rope_neox(&mut q, hd, pos);
+                rope_neox(&mut k, hd, pos);
+                qs.push(q);
+                ks.push(k);
+                vs.push(mul(model, file, &p("attn_v"), &h));
+            }
+            for (pos, x) in xs.iter_mut().enumerate() {
+                let mut attn = vec![0.0; 32];
+                for head in 0..4 {
+                    let kvh = head / 2;
+                    let mut w: Vec<f32> = (0..=pos)
+                        .map(|t| (0..hd).map(|i| qs[pos][head * hd + i] * ks[t][kvh * hd + i]).sum::<f32>() / (hd as f32).sqrt())
+                        .collect();
+                    softmax(&mut w);
+                    for (t, wt) in w.iter().enumerate() {
+                        for i in 0..hd {
+                            attn[head * hd + i] += wt * vs[t][kvh * hd + i];
+                        }
+                    }
+                }
+                let o = mul(model, file, &p("attn_output"), &attn);
+                x.iter_mut().zip(o).for_each(|(a, b)| *a += b);
+                let h = norm(model, file, &p("ffn_norm.weight"), x);
+                let g = mul(model, file, &p("ffn_gate"), &h);
+                let u = mul(model, file, &p("ffn_up"), &h);
+                let a: Vec<f32> = g.iter().zip(u).map(|(g, u)| g / (1.0 + (-g).exp()) * u).collect();
+                let d = mul(model, file, &p("ffn_down"), &a);
+                x.iter_mut().zip(d).for_each(|(a, b)| *a += b);
+            }
+        }
+        let h = norm(model, file, "output_norm.weight", xs.last().unwrap());
+        mul(model, file, "output", &h)
+    }
+
+    fn argmax(v: &[f32]) -> u32 {
+        sample(v, 0.0, 1.0, &mut 0)
+    }
+
+    #[test]
+    fn test_tiny_model_matches_reference_forward() {
+        let file = test_model::build();
+        let model = Model::load(&file).unwrap();
+        assert_eq!((model.config.n_layers, model.config.head_dim, model.config.vocab_size), (2, 8, 16));
+        assert!(model.config.rope_neox);
+
+        let prompt = model.vocab.encode("hello world");
+        assert_eq!(prompt, vec![14, 15]);
+        assert_eq!(model.vocab.decode(&prompt), "hello world");
+
+        let got = prompt_logits(&model, &file, &prompt).unwrap();
+        let want = reference_logits(&model, &file, &prompt);
+        for (a, b) in got.iter().zip(&want) {
+            assert!((a - b).abs() < 1e-3, "{} vs {}", a, b);
+        }
+
+        // Greedy generation with the KV cache equals re-running the full sequence each step
+        let mut state = InferenceState::new(&model.config, prompt.clone(), 5, 0.0, 1.0, 7).unwrap();
+        let generated = run(&model, &file, &mut state, &mut || true).unwrap();
+        assert!(state.is_done() && !generated.is_empty());
+        let mut seq = prompt.clone();
+        for token in &generated {
+            assert_eq!(*token, argmax(&reference_logits(&model, &file, &seq)));
+            seq.push(*token);
+        }
+
+        // A chat template's end-of-turn token ends generation like EOS
+        let mut state = InferenceState::new(&model.config, prompt, 5, 0.0, 1.0, 7).unwrap();
+        state.stop_tokens = vec![generated[0]];
+        state = InferenceState::from_bytes(state.to_bytes());
+        assert_eq!(run(&model, &file, &mut state, &mut || true).unwrap(), vec![generated[0]]);
+        assert_eq!(state.stop, Some(StopReason::EndOfSequence));
+    }
+
+    #[test]
+    fn test_generation_resumes_across_calls_with_persisted_state() {
+        let file = test_model::build();
+        let model = Model::load(&file).unwrap();
+        let prompt = model.vocab.encode("hello world");
+
+        let mut whole = InferenceState::new(&model.config, prompt.clone(), 6, 0.8, 0.9, 42).unwrap();
+        let expected = run(&model, &file, &mut whole, &mut || true).unwrap();
+
+        // One layer per "message", state round-tripped through stable storage in between
+        let mut state = InferenceState::new(&model.config, prompt, 6, 0.8, 0.9, 42).unwrap();
+        let mut generated = Vec::new();
+        let mut calls = 0;
+        while !state.is_done() {
+            let mut units = 1;
+            generated.extend(run(&model, &file, &mut state, &mut || { units -= 1; units >= 0 }).unwrap());
+            state = InferenceState::from_bytes(state.to_bytes());
+            calls += 1;
+        }
+        assert_eq!(generated, expected);
+        assert_eq!(state, whole);
+        assert!(calls > 20);
+        assert_eq!(state.cache[0].keys.len(), (state.pos as usize) * model.config.kv_dim());
+    }
+
+    #[test]
+    fn test_sampling() {
+        let logits = [1.0, 3.0, 2.0, -1.0];
+        assert_eq!(sample(&logits, 0.0, 0.9, &mut 1), 1);
+        // A tiny nucleus keeps only the most likely token
+        for seed in 0..20 {
+            assert_eq!(sample(&logits, 1.0, 0.01, &mut { seed }), 1);
+        }
+        let mut rng = 5;
+        let picks: Vec<u32> = (0..200).map(|_| sample(&logits, 1.0, 1.0, &mut rng)).collect();
+        assert!(picks.contains(&2) && picks.iter().filter(|p| **p == 1).count() > 100);
+    }
+}
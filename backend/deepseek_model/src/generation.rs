@@ -0,0 +1,219 @@
+//! Chunked Generation Sessions
+//!
+//! Long completions are produced in rounds instead of one blocking call:
+//! - `start_generation` stores a session and schedules the first round on a timer
+//! - Each round generates up to `ROUND_TOKENS` tokens continuing from the prompt plus
+//!   the text generated so far, appends it to the session, and schedules the next
+//! - Clients poll `next_chunk(session_id)`, which returns the text produced since
+//!   their previous poll
+//!
+//! When a GGUF model is active, rounds run the on-canister engine instead of the
+//! HTTP outcall: each round spends one message's instruction budget advancing the
+//! persisted `InferenceState` (KV cache included) and appends whatever it produced.
+//!
+//! Session state lives in stable memory, so an upgrade between rounds only pauses
+//! generation; `post_upgrade` (or the next poll) resumes it.
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Tokens generated per round (fits the per-message instruction budget)
+pub const ROUND_TOKENS: u32 = 256;
+/// Upper bound on a whole session's completion
+pub const MAX_SESSION_TOKENS: u32 = 4096;
+/// Unfinished sessions one caller may hold at once (the configured orchestrator is exempt)
+pub const MAX_ACTIVE_SESSIONS_PER_CALLER: usize = 4;
+/// Sessions are dropped this long after their last update
+pub const SESSION_TTL_NS: u64 = 60 * 60 * 1_000_000_000;
+/// Consecutive failed rounds before a session is abandoned
+pub const MAX_ROUND_FAILURES: u32 = 3;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum GenerationStatus {
+    Generating,
+    Completed,
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GenerationSession {
+    pub id: String,
+    pub owner: Principal,
+    pub prompt: String, // Fully rendered prompt (system + context + query)
+    pub generated: String,
+    pub tokens_generated: u32,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub top_p: f32,
+    pub rounds: u32,
+    pub failures: u32,
+    pub read_offset: u64, // Bytes of `generated` already returned by next_chunk
+    pub chunks_returned: u32,
+    pub status: GenerationStatus,
+    pub created_at: u64,
+    pub updated_at: u64,
+    #[serde(default)]
+    pub local_model: Option<String>, // Model id when generating on-canister; None uses the HTTP outcall
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GenerationChunk {
+    pub session_id: String,
+    pub index: u32,            // Sequence number of this chunk
+    pub text: String,          // Text generated since the previous poll
+    pub tokens_generated: u32, // Running total for the session
+    pub done: bool,
+    pub status: GenerationStatus,
+}
+
+impl Storable for GenerationSession {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl GenerationSession {
+    pub fn new(id: String, owner: Principal, prompt: String, max_tokens: u32, temperature: f32, top_p: f32, now: u64) -> Self {
+        Self {
+            id,
+            owner,
+            prompt,
+            generated: String::new(),
+            tokens_generated: 0,
+            max_tokens: max_tokens.clamp(1, MAX_SESSION_TOKENS),
+            temperature,
+            top_p,
+            rounds: 0,
+            failures: 0,
+            read_offset: 0,
+            chunks_returned: 0,
+            status: GenerationStatus::Generating,
+            created_at: now,
+            updated_at: now,
+            local_model: None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status == GenerationStatus::Generating
+    }
+
+    /// Tokens to request in the next round
+    pub fn round_budget(&self) -> u32 {
+        self.max_tokens.saturating_sub(self.tokens_generated).min(ROUND_TOKENS)
+    }
+
+    /// Prompt for the next round: the original prompt continued by everything so far
+    pub fn continuation_prompt(&self) -> String {
+        format!("{}{}", self.prompt, self.generated)
+    }
+
+    /// Record a finished round; `stopped` means the model ended the answer itself
+    /// (end-of-sequence or stop string) rather than running out of budget. An empty
+    /// round is not an ending: a local round may be spent entirely on the prompt.
+    pub fn apply_round(&mut self, text: &str, tokens: u32, stopped: bool, now: u64) {
+        self.generated.push_str(text);
+        self.tokens_generated = self.tokens_generated.saturating_add(tokens);
+        self.rounds += 1;
+        self.failures = 0;
+        self.updated_at = now;
+        if stopped || self.tokens_generated >= self.max_tokens {
+            self.status = GenerationStatus::Completed;
+        }
+    }
+
+    /// Record a failed round; the session fails after repeated errors
+    pub fn apply_failure(&mut self, error: String, now: u64) {
+        self.failures += 1;
+        self.updated_at = now;
+        if self.failures >= MAX_ROUND_FAILURES {
+            self.status = GenerationStatus::Failed(error);
+        }
+    }
+
+    /// Text produced since the previous poll, advancing the read cursor
+    pub fn take_chunk(&mut self) -> GenerationChunk {
+        let start = (self.read_offset as usize).min(self.generated.len());
+        let text = self.generated[start..].to_string();
+        self.read_offset = self.generated.len() as u64;
+        let chunk = GenerationChunk {
+            session_id: self.id.clone(),
+            index: self.chunks_returned,
+            text,
+            tokens_generated: self.tokens_generated,
+            done: !self.is_active(),
+            status: self.status.clone(),
+        };
+        self.chunks_returned += 1;
+        chunk
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now.saturating_sub(self.updated_at) > SESSION_TTL_NS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(max_tokens: u32) -> GenerationSession {
+        GenerationSession::new("gen-1".into(), Principal::anonymous(), "User: hi\n\nAssistant:".into(), max_tokens, 0.7, 0.95, 0)
+    }
+
+    #[test]
+    fn test_long_completion_spans_rounds() {
+        let mut s = session(1000);
+        assert_eq!(s.round_budget(), ROUND_TOKENS);
+
+        s.apply_round(" Hello", ROUND_TOKENS, false, 1);
+        s.apply_round(" there", ROUND_TOKENS, false, 2);
+        s.apply_round(" again", ROUND_TOKENS, false, 3);
+        assert!(s.is_active());
+        assert_eq!(s.round_budget(), 1000 - 3 * ROUND_TOKENS);
+        assert_eq!(s.continuation_prompt(), "User: hi\n\nAssistant: Hello there again");
+
+        s.apply_round(" end", 1000 - 3 * ROUND_TOKENS, false, 4);
+        assert_eq!(s.status, GenerationStatus::Completed);
+        assert_eq!(s.tokens_generated, 1000);
+    }
+
+    #[test]
+    fn test_chunks_return_only_new_text() {
+        let mut s = session(2000);
+        s.apply_round("first ", ROUND_TOKENS, false, 1);
+        let c0 = s.take_chunk();
+        assert_eq!((c0.index, c0.text.as_str(), c0.done), (0, "first ", false));
+        assert_eq!(s.take_chunk().text, "");
+
+        // The model hit a stop token before using its budget
+        s.apply_round("second", 12, true, 2);
+        let c2 = s.take_chunk();
+        assert_eq!((c2.index, c2.text.as_str(), c2.done), (2, "second", true));
+        assert_eq!(c2.tokens_generated, ROUND_TOKENS + 12);
+    }
+
+    #[test]
+    fn test_failures_and_limits() {
+        let mut s = session(100_000);
+        assert_eq!(s.max_tokens, MAX_SESSION_TOKENS);
+        for _ in 0..MAX_ROUND_FAILURES - 1 {
+            s.apply_failure("timeout".into(), 1);
+        }
+        assert!(s.is_active());
+        s.apply_failure("timeout".into(), 2);
+        assert_eq!(s.status, GenerationStatus::Failed("timeout".into()));
+        assert!(!s.is_expired(SESSION_TTL_NS));
+        assert!(s.is_expired(SESSION_TTL_NS + 3));
+    }
+}
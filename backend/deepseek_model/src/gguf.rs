@@ -0,0 +1,347 @@
+//! GGUF Reader
+//!
+//! Parses the header of a GGUF (v2/v3) model file: metadata key/values and the
+//! tensor directory (name, shape, type, offset). Tensor data is not loaded here;
+//! the engine reads each tensor's bytes from the `ByteSource` when it needs them.
+//!
+//! The uploaded shards of a model are the GGUF file split in order, so the canister
+//! exposes them as one contiguous byte source.
+
+use std::collections::BTreeMap;
+
+use crate::quant::GgmlType;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+const DEFAULT_ALIGNMENT: u64 = 32;
+/// Header bytes fetched per read while parsing
+const READ_WINDOW: usize = 1024 * 1024;
+
+/// Random access to the bytes of a model file
+pub trait ByteSource {
+    fn size(&self) -> u64;
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, String>;
+}
+
+impl ByteSource for Vec<u8> {
+    fn size(&self) -> u64 {
+        self.as_slice().len() as u64
+    }
+
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, String> {
+        let start = offset as usize;
+        self.get(start..start + len)
+            .map(|b| b.to_vec())
+            .ok_or_else(|| format!("Read past end of file at {}", offset))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GgufValue {
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Array(Vec<GgufValue>),
+}
+
+impl GgufValue {
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            GgufValue::UInt(v) => Some(*v),
+            GgufValue::Int(v) if *v >= 0 => Some(*v as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            GgufValue::Float(v) => Some(*v as f32),
+            GgufValue::UInt(v) => Some(*v as f32),
+            GgufValue::Int(v) => Some(*v as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[GgufValue]> {
+        match self {
+            GgufValue::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TensorInfo {
+    pub name: String,
+    pub dims: Vec<u64>, // dims[0] is the row length (contiguous)
+    pub ty: GgmlType,
+    pub offset: u64, // Absolute offset in the file
+}
+
+impl TensorInfo {
+    pub fn row_len(&self) -> usize {
+        self.dims.first().copied().unwrap_or(1) as usize
+    }
+
+    pub fn rows(&self) -> usize {
+        self.dims.iter().skip(1).product::<u64>() as usize
+    }
+
+    pub fn byte_len(&self) -> Result<usize, String> {
+        Ok(self.ty.row_bytes(self.row_len())? * self.rows())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GgufFile {
+    pub version: u32,
+    pub metadata: BTreeMap<String, GgufValue>,
+    pub tensors: BTreeMap<String, TensorInfo>,
+}
+
+/// Sequential reader over a byte source with a read-ahead window
+struct Reader<'a, S: ByteSource + ?Sized> {
+    source: &'a S,
+    pos: u64,
+    window: Vec<u8>,
+    window_start: u64,
+}
+
+impl<'a, S: ByteSource + ?Sized> Reader<'a, S> {
+    fn bytes(&mut self, n: usize) -> Result<Vec<u8>, String> {
+        let end = self.pos + n as u64;
+        if self.pos < self.window_start || end > self.window_start + self.window.len() as u64 {
+            let available = self.source.size().saturating_sub(self.pos);
+            if (n as u64) > available {
+                return Err(format!("Unexpected end of GGUF data at {}", self.pos));
+            }
+            let len = (n.max(READ_WINDOW) as u64).min(available) as usize;
+            self.window = self.source.read(self.pos, len)?;
+            self.window_start = self.pos;
+        }
+        let start = (self.pos - self.window_start) as usize;
+        self.pos = end;
+        Ok(self.window[start..start + n].to_vec())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u64()?;
+        if len > 1 << 24 {
+            return Err(format!("GGUF string too long ({} bytes)", len));
+        }
+        String::from_utf8(self.bytes(len as usize)?).map_err(|e| format!("Invalid UTF-8 in GGUF string: {}", e))
+    }
+
+    fn value(&mut self, ty: u32) -> Result<GgufValue, String> {
+        Ok(match ty {
+            0 => GgufValue::UInt(self.u8()? as u64),
+            1 => GgufValue::Int(self.u8()? as i8 as i64),
+            2 => GgufValue::UInt(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()) as u64),
+            3 => GgufValue::Int(i16::from_le_bytes(self.bytes(2)?.try_into().unwrap()) as i64),
+            4 => GgufValue::UInt(self.u32()? as u64),
+            5 => GgufValue::Int(self.u32()? as i32 as i64),
+            6 => GgufValue::Float(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()) as f64),
+            7 => GgufValue::Bool(self.u8()? != 0),
+            8 => GgufValue::Str(self.string()?),
+            9 => {
+                let item_ty = self.u32()?;
+                let count = self.u64()?;
+                if count > 1 << 24 {
+                    return Err(format!("GGUF array too long ({} items)", count));
+                }
+                let mut items = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    items.push(self.value(item_ty)?);
+                }
+                GgufValue::Array(items)
+            }
+            10 => GgufValue::UInt(self.u64()?),
+            11 => GgufValue::Int(self.u64()? as i64),
+            12 => GgufValue::Float(f64::from_le_bytes(self.bytes(8)?.try_into().unwrap())),
+            other => return Err(format!("Unknown GGUF value type {}", other)),
+        })
+    }
+}
+
+/// Parse the GGUF header (metadata and tensor directory)
+pub fn parse<S: ByteSource + ?Sized>(source: &S) -> Result<GgufFile, String> {
+    let mut r = Reader { source, pos: 0, window: Vec::new(), window_start: 0 };
+    if r.bytes(4)?.as_slice() != GGUF_MAGIC {
+        return Err("Not a GGUF file (bad magic)".to_string());
+    }
+    let version = r.u32()?;
+    if !(2..=3).contains(&version) {
+        return Err(format!("Unsupported GGUF version {}", version));
+    }
+    let tensor_count = r.u64()?;
+    let kv_count = r.u64()?;
+
+    let mut metadata = BTreeMap::new();
+    for _ in 0..kv_count {
+        let key = r.string()?;
+        let ty = r.u32()?;
+        metadata.insert(key, r.value(ty)?);
+    }
+
+    let mut infos = Vec::with_capacity(tensor_count as usize);
+    for _ in 0..tensor_count {
+        let name = r.string()?;
+        let n_dims = r.u32()?;
+        if n_dims == 0 || n_dims > 4 {
+            return Err(format!("Tensor {} has {} dimensions", name, n_dims));
+        }
+        let dims = (0..n_dims).map(|_| r.u64()).collect::<Result<Vec<_>, _>>()?;
+        let ty = GgmlType::from_id(r.u32()?).map_err(|e| format!("Tensor {}: {}", name, e))?;
+        let offset = r.u64()?;
+        infos.push((name, dims, ty, offset));
+    }
+
+    let alignment = metadata.get("general.alignment").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_ALIGNMENT).max(1);
+    let data_start = r.pos.div_ceil(alignment) * alignment;
+
+    let mut tensors = BTreeMap::new();
+    for (name, dims, ty, offset) in infos {
+        let info = TensorInfo { name: name.clone(), dims, ty, offset: data_start + offset };
+        if info.offset + info.byte_len()? as u64 > source.size() {
+            return Err(format!("Tensor {} extends past the end of the file", name));
+        }
+        tensors.insert(name, info);
+    }
+
+    Ok(GgufFile { version, metadata, tensors })
+}
+
+/// Minimal GGUF writer for building test models
+#[cfg(test)]
+pub(crate) mod writer {
+    use super::*;
+
+    pub struct TensorData {
+        pub name: String,
+        pub dims: Vec<u64>,
+        pub ty: GgmlType,
+        pub data: Vec<u8>,
+    }
+
+    fn put_string(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    fn put_value(out: &mut Vec<u8>, value: &GgufValue) {
+        match value {
+            GgufValue::UInt(v) => out.extend_from_slice(&(*v as u32).to_le_bytes()),
+            GgufValue::Int(v) => out.extend_from_slice(&(*v as i32).to_le_bytes()),
+            GgufValue::Float(v) => out.extend_from_slice(&(*v as f32).to_le_bytes()),
+            GgufValue::Bool(v) => out.push(*v as u8),
+            GgufValue::Str(s) => put_string(out, s),
+            GgufValue::Array(items) => {
+                out.extend_from_slice(&type_id(items.first().unwrap_or(&GgufValue::UInt(0))).to_le_bytes());
+                out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+                for item in items {
+                    put_value(out, item);
+                }
+            }
+        }
+    }
+
+    fn type_id(value: &GgufValue) -> u32 {
+        match value {
+            GgufValue::UInt(_) => 4,
+            GgufValue::Int(_) => 5,
+            GgufValue::Float(_) => 6,
+            GgufValue::Bool(_) => 7,
+            GgufValue::Str(_) => 8,
+            GgufValue::Array(_) => 9,
+        }
+    }
+
+    pub fn write(metadata: &[(&str, GgufValue)], tensors: &[TensorData]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(GGUF_MAGIC);
+        out.extend_from_slice(&3u32.to_le_bytes());
+        out.extend_from_slice(&(tensors.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+        for (key, value) in metadata {
+            put_string(&mut out, key);
+            out.extend_from_slice(&type_id(value).to_le_bytes());
+            put_value(&mut out, value);
+        }
+
+        // Offsets are relative to the aligned data section; each tensor starts aligned
+        let mut offset = 0u64;
+        for t in tensors {
+            put_string(&mut out, &t.name);
+            out.extend_from_slice(&(t.dims.len() as u32).to_le_bytes());
+            for d in &t.dims {
+                out.extend_from_slice(&d.to_le_bytes());
+            }
+            out.extend_from_slice(&t.ty.id().to_le_bytes());
+            out.extend_from_slice(&offset.to_le_bytes());
+            offset = (offset + t.data.len() as u64).div_ceil(DEFAULT_ALIGNMENT) * DEFAULT_ALIGNMENT;
+        }
+
+        for t in tensors {
+            let aligned = (out.len() as u64).div_ceil(DEFAULT_ALIGNMENT) * DEFAULT_ALIGNMENT;
+            out.resize(aligned as usize, 0);
+            out.extend_from_slice(&t.data);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::writer::{write, TensorData};
+    use super::*;
+
+    #[test]
+    fn test_parse_header_and_tensor_directory() {
+        let weights: Vec<u8> = (0..64u32).flat_map(|i| (i as f32).to_le_bytes()).collect();
+        let file = write(
+            &[
+                ("general.architecture", GgufValue::Str("llama".into())),
+                ("llama.block_count", GgufValue::UInt(2)),
+                ("tokenizer.ggml.tokens", GgufValue::Array(vec![GgufValue::Str("a".into()), GgufValue::Str("b".into())])),
+            ],
+            &[
+                TensorData { name: "a".into(), dims: vec![8, 2], ty: GgmlType::F32, data: weights[..64].to_vec() },
+                TensorData { name: "b".into(), dims: vec![16, 3], ty: GgmlType::F32, data: weights[..192].to_vec() },
+            ],
+        );
+
+        let gguf = parse(&file).unwrap();
+        assert_eq!(gguf.version, 3);
+        assert_eq!(gguf.metadata["llama.block_count"].as_u64(), Some(2));
+        assert_eq!(gguf.metadata["tokenizer.ggml.tokens"].as_array().unwrap().len(), 2);
+
+        let b = &gguf.tensors["b"];
+        assert_eq!((b.row_len(), b.rows()), (16, 3));
+        assert_eq!(b.offset % DEFAULT_ALIGNMENT, 0);
+        assert_eq!(file.read(b.offset, 4).unwrap(), 0f32.to_le_bytes());
+        assert_eq!(file.read(b.offset + 4 * 17, 4).unwrap(), 17f32.to_le_bytes());
+
+        assert!(parse(&b"GGML....".to_vec()).is_err());
+        assert!(parse(&file[..file.len() - 8].to_vec()).is_err());
+    }
+}
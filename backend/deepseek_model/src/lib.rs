@@ -12,17 +12,47 @@ use candid::{CandidType, Decode, Encode, Principal};
 use ic_cdk::{api::call::call, init, post_upgrade, pre_upgrade, query, update};
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
-    DefaultMemoryImpl, StableBTreeMap, Storable,
+    DefaultMemoryImpl, StableBTreeMap, StableCell, Storable,
 };
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+pub mod engine;
+pub mod generation;
+pub mod gguf;
+pub mod pipeline;
+pub mod quant;
+pub mod template;
+pub mod tokenizer;
+pub mod upload;
+
+use engine::{InferenceState, StopReason};
+use pipeline::{DecodedCompletion, PreparedPrompt, StageCache, StageConfig, StageInfo, StageRequest, StageResponse};
+use template::{ChatTemplate, Role};
+use tokenizer::{Tokenizer, TokenizerData, TokenizerInfo};
+use generation::{GenerationChunk, GenerationSession, GenerationStatus};
+use upload::{ActiveModel, ChunkKey, ModelManifest, ModelRecord, ModelStatus, ShardState, UploadStatus};
 
 // Memory IDs
 const MODEL_SHARDS_MEM_ID: MemoryId = MemoryId::new(0);
 const SECRETS_MEM_ID: MemoryId = MemoryId::new(1);
+const GENERATION_SESSIONS_MEM_ID: MemoryId = MemoryId::new(2);
+const MODELS_MEM_ID: MemoryId = MemoryId::new(3);
+const SHARD_CHUNKS_MEM_ID: MemoryId = MemoryId::new(4);
+const ACTIVE_MODEL_MEM_ID: MemoryId = MemoryId::new(5);
+const ENGINE_STATES_MEM_ID: MemoryId = MemoryId::new(6);
+const TOKENIZER_UPLOADS_MEM_ID: MemoryId = MemoryId::new(7);
+const TOKENIZERS_MEM_ID: MemoryId = MemoryId::new(8);
+const INFERENCE_CONFIG_MEM_ID: MemoryId = MemoryId::new(9);
+const CHAT_TEMPLATES_MEM_ID: MemoryId = MemoryId::new(10);
+const STAGE_CONFIG_MEM_ID: MemoryId = MemoryId::new(11);
+const STAGE_CACHES_MEM_ID: MemoryId = MemoryId::new(12);
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
+type LoadedModel = Result<Rc<LocalModel>, String>;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
 pub struct SecretConfig {
@@ -59,6 +89,86 @@ thread_local! {
             SecretConfig::default()
         ).unwrap()
     );
+
+    static GENERATION_SESSIONS: RefCell<StableBTreeMap<String, GenerationSession, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(GENERATION_SESSIONS_MEM_ID))
+        ));
+
+    static MODELS: RefCell<StableBTreeMap<String, ModelRecord, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MODELS_MEM_ID))
+        ));
+
+    static SHARD_CHUNKS: RefCell<StableBTreeMap<ChunkKey, Vec<u8>, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SHARD_CHUNKS_MEM_ID))
+        ));
+
+    static ACTIVE_MODEL: RefCell<StableCell<ActiveModel, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ACTIVE_MODEL_MEM_ID)),
+            ActiveModel::default()
+        ).unwrap()
+    );
+
+    // On-canister generation state (token history + KV cache) per session
+    static ENGINE_STATES: RefCell<StableBTreeMap<String, InferenceState, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ENGINE_STATES_MEM_ID))
+        ));
+
+    // tokenizer.json bytes received so far, per model version
+    static TOKENIZER_UPLOADS: RefCell<StableBTreeMap<String, Vec<u8>, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TOKENIZER_UPLOADS_MEM_ID))
+        ));
+
+    static TOKENIZERS: RefCell<StableBTreeMap<String, TokenizerData, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TOKENIZERS_MEM_ID))
+        ));
+
+    static INFERENCE_CONFIG: RefCell<StableCell<InferenceConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(INFERENCE_CONFIG_MEM_ID)),
+            InferenceConfig::default()
+        ).unwrap()
+    );
+
+    static CHAT_TEMPLATES: RefCell<StableBTreeMap<String, ChatTemplate, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(CHAT_TEMPLATES_MEM_ID))
+        ));
+
+    // Transformer layers this canister serves as a pipeline stage
+    static STAGE_CONFIG: RefCell<StableCell<StageConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(STAGE_CONFIG_MEM_ID)),
+            StageConfig::default()
+        ).unwrap()
+    );
+
+    // KV cache of this stage's layers per "{caller}:{session_id}"
+    static STAGE_CACHES: RefCell<StableBTreeMap<String, StageCache, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(STAGE_CACHES_MEM_ID))
+        ));
+
+    // Active model's tokenizer with lookup tables built
+    static LOADED_TOKENIZER: RefCell<Option<(String, Rc<Tokenizer>)>> = const { RefCell::new(None) };
+
+    // Parsed GGUF directory of the active model, rebuilt after activation or upgrade
+    static LOADED_MODEL: RefCell<Option<(String, LoadedModel)>> = const { RefCell::new(None) };
+
+    // Sessions with a round currently awaiting the model (not persisted: an upgrade
+    // drops in-flight calls, so their sessions are simply resumed)
+    static ROUNDS_IN_FLIGHT: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+
+    static SESSION_SEQ: RefCell<u64> = const { RefCell::new(0) };
+
+    // Measured instructions per processed token of each model (not persisted; relearned after upgrade)
+    static TOKEN_COSTS: RefCell<HashMap<String, u64>> = RefCell::new(HashMap::new());
 }
 
 // ============================================================================
@@ -102,6 +212,36 @@ pub struct InferenceResponse {
     pub tokens_generated: u32,
     pub inference_time_ms: u64,
     pub shards_used: Vec<u32>,
+    pub prompt_tokens: u32,     // Prompt after context trimming
+    pub completion_tokens: u32,
+    pub context_messages_dropped: u32,
+    pub reasoning: Option<String>, // The model's <think> section, kept out of `response`
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InferenceConfig {
+    pub context_window_tokens: u32, // Prompt + completion budget; oldest context is trimmed to fit
+    #[serde(default)]
+    pub orchestrator: Option<Principal>, // queen_bee; enforces per-user limits itself
+}
+
+impl Default for InferenceConfig {
+    fn default() -> Self {
+        Self { context_window_tokens: 4096, orchestrator: None }
+    }
+}
+
+impl Storable for InferenceConfig {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
 }
 
 // Storable types
@@ -156,13 +296,21 @@ fn pre_upgrade() {
 #[post_upgrade]
 fn post_upgrade() {
     ic_cdk::println!("DeepSeek Model Canister upgraded");
+
+    // Timers do not survive upgrades; pick unfinished generations back up
+    let active: Vec<String> = GENERATION_SESSIONS.with(|s| {
+        s.borrow().iter().filter(|(_, session)| session.is_active()).map(|(id, _)| id).collect()
+    });
+    for session_id in active {
+        schedule_round(session_id);
+    }
 }
 
 // ============================================================================
 // Model Weight Management
 // ============================================================================
 
-/// Store a model weight shard
+/// Store a model weight shard (legacy, unverified; prefer the manifest upload below)
 /// 
 /// Quantization: Use Q4_K_M for 4-bit quantization (~75% size reduction)
 /// This is essential for fitting within IC instruction limits per round
@@ -170,15 +318,13 @@ fn post_upgrade() {
 fn store_shard(shard: ModelShard) -> Result<(), String> {
     let caller = ic_cdk::caller();
     
-    // Only allow admin or other canisters to store shards
-    // In production, add proper authorization
-    if caller == Principal::anonymous() {
-        return Err("Anonymous callers not allowed".to_string());
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can store shards".to_string());
     }
 
     // Validate quantization format if provided
     if let Some(ref quant) = shard.quantization {
-        let valid_quantizations = ["Q4_K_M", "Q8_0", "Q5_K_M", "Q4_K_S", "F16", "F32"];
+        let valid_quantizations = upload::VALID_QUANTIZATIONS;
         if !valid_quantizations.contains(&quant.as_str()) {
             return Err(format!(
                 "Invalid quantization format: {}. Must be one of: {:?}",
@@ -217,9 +363,12 @@ fn get_shard(shard_id: u32) -> Result<ModelShard, String> {
     })
 }
 
-/// Get all shard IDs stored in this canister
+/// Get all shard IDs stored in this canister (the active model's shards, if one is active)
 #[query]
 fn get_all_shard_ids() -> Vec<u32> {
+    if let Some(record) = active_model_record() {
+        return (0..record.manifest.total_shards).collect();
+    }
     MODEL_SHARDS.with(|s| {
         s.borrow()
             .iter()
@@ -231,6 +380,11 @@ fn get_all_shard_ids() -> Vec<u32> {
 /// Get model information
 #[query]
 fn get_model_info() -> (String, u32, u64) {
+    if let Some(record) = active_model_record() {
+        let manifest = record.manifest;
+        return (manifest.model_id, manifest.total_shards, manifest.shards.iter().map(|s| s.size).sum());
+    }
+
     let shard_ids = get_all_shard_ids();
     let total_shards = shard_ids.len() as u32;
     
@@ -254,72 +408,610 @@ fn get_model_info() -> (String, u32, u64) {
 }
 
 // ============================================================================
-// On-chain Inference
+// Verified Model Upload
 // ============================================================================
 
-/// Perform inference using stored shards
-/// Uses HTTP outcalls to llama.cpp compatible API for actual inference
-/// 
-/// Latency Optimization:
-/// - Uses 4-bit quantization (Q4_K_M) for DeepSeek R1 7B to reduce model size by ~75%
-/// - Limits max_tokens to 256-512 to fit within IC instruction limits per round
-/// - Implements async-safe state management pattern
+fn require_controller() -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can manage model uploads".to_string());
+    }
+    Ok(())
+}
+
+fn active_model_id() -> Option<String> {
+    ACTIVE_MODEL.with(|a| a.borrow().get().model_id.clone())
+}
+
+fn active_model_record() -> Option<ModelRecord> {
+    active_model_id().and_then(|id| MODELS.with(|m| m.borrow().get(&id)))
+}
+
+fn model_record(model_id: &str) -> Result<ModelRecord, String> {
+    MODELS.with(|m| m.borrow().get(&model_id.to_string()))
+        .ok_or_else(|| format!("Model {} not registered", model_id))
+}
+
+fn chunk_range(model_id: &str, shard: u32) -> std::ops::RangeInclusive<ChunkKey> {
+    ChunkKey { model_id: model_id.to_string(), shard, chunk: 0 }
+        ..=ChunkKey { model_id: model_id.to_string(), shard, chunk: u32::MAX }
+}
+
+/// Register a model version; its shards are uploaded and verified separately
 #[update]
-async fn infer(request: InferenceRequest) -> Result<InferenceResponse, String> {
-    let start_time = ic_cdk::api::time();
-    
-    // 1. Extract everything we need from thread_local FIRST (before any .await)
-    let shard_ids = get_all_shard_ids();
-    
-    if shard_ids.is_empty() {
-        return Err("No model shards loaded".to_string());
+fn register_model_manifest(manifest: ModelManifest) -> Result<UploadStatus, String> {
+    require_controller()?;
+    upload::validate_manifest(&manifest)?;
+    if MODELS.with(|m| m.borrow().contains_key(&manifest.model_id)) {
+        return Err(format!("Model {} already registered; delete it first or use a new id", manifest.model_id));
     }
 
-    // Build prompt with context (local computation, no async)
-    let mut full_prompt = String::new();
-    
-    // Add system prompt if provided
-    if let Some(ref sys_prompt) = request.system_prompt {
-        full_prompt.push_str(&format!("System: {}\n\n", sys_prompt));
+    let record = ModelRecord::new(manifest, ic_cdk::caller(), ic_cdk::api::time());
+    let status = record.upload_status(false);
+    MODELS.with(|m| m.borrow_mut().insert(record.manifest.model_id.clone(), record));
+    Ok(status)
+}
+
+/// Upload one chunk of a shard (chunks may arrive in any order and be re-sent)
+#[update]
+fn upload_shard_chunk(model_id: String, shard_index: u32, chunk_index: u32, data: Vec<u8>) -> Result<(), String> {
+    require_controller()?;
+    if active_model_id().as_deref() == Some(model_id.as_str()) {
+        return Err("The active model cannot be modified".to_string());
     }
-    
-    // Add context (limit to prevent instruction limit issues)
-    let context_limit = request.context.len().min(10); // Max 10 context messages
-    for ctx in request.context.iter().take(context_limit) {
-        full_prompt.push_str(&format!("{}: {}\n", ctx.role, ctx.content));
+
+    let mut record = model_record(&model_id)?;
+    record.accept_chunk(shard_index, chunk_index, data.len())?;
+    SHARD_CHUNKS.with(|c| c.borrow_mut().insert(
+        ChunkKey { model_id: model_id.clone(), shard: shard_index, chunk: chunk_index },
+        data,
+    ));
+    MODELS.with(|m| m.borrow_mut().insert(model_id, record));
+    Ok(())
+}
+
+/// Hash an uploaded shard and check it against the manifest. A mismatching shard
+/// is discarded so it can be uploaded again.
+#[update]
+fn finalize_shard(model_id: String, shard_index: u32) -> Result<ShardState, String> {
+    require_controller()?;
+    let mut record = model_record(&model_id)?;
+    if shard_index >= record.manifest.total_shards {
+        return Err(format!("Shard {} not in manifest", shard_index));
     }
-    
-    // Add user query
-    full_prompt.push_str(&format!("User: {}\n\nAssistant:", request.prompt));
-    
+    if record.shards[shard_index as usize].state == ShardState::Verified {
+        return Ok(ShardState::Verified);
+    }
+    let missing = record.missing_chunks(shard_index);
+    if !missing.is_empty() {
+        return Err(format!("Shard {} is missing chunks {:?}", shard_index, missing));
+    }
+
+    // Streams the chunks through the hasher in key (= chunk) order
+    let digest = SHARD_CHUNKS.with(|c| {
+        upload::sha256_hex(c.borrow().range(chunk_range(&model_id, shard_index)).map(|(_, data)| data))
+    });
+
+    let state = record.record_verification(shard_index, &digest);
+    if matches!(state, ShardState::Corrupt(_)) {
+        SHARD_CHUNKS.with(|c| {
+            let keys: Vec<ChunkKey> = c.borrow().range(chunk_range(&model_id, shard_index)).map(|(k, _)| k).collect();
+            let mut chunks = c.borrow_mut();
+            for key in keys {
+                chunks.remove(&key);
+            }
+        });
+    }
+    MODELS.with(|m| m.borrow_mut().insert(model_id, record));
+    Ok(state)
+}
+
+/// Switch inference to a fully verified model in one write; the previous version
+/// stays stored for rollback until deleted
+#[update]
+fn activate_model(model_id: String) -> Result<(), String> {
+    require_controller()?;
+    let record = model_record(&model_id)?;
+    if record.status != ModelStatus::Ready {
+        let status = record.upload_status(false);
+        return Err(format!(
+            "Model {} is not ready: {}/{} shards verified",
+            model_id, status.shards_verified, status.total_shards
+        ));
+    }
+
+    ACTIVE_MODEL.with(|a| {
+        let current = a.borrow().get().clone();
+        if current.model_id.as_deref() == Some(model_id.as_str()) {
+            return Ok(());
+        }
+        a.borrow_mut().set(ActiveModel {
+            model_id: Some(model_id),
+            previous_model_id: current.model_id,
+            activated_at: ic_cdk::api::time(),
+        })
+        .map(|_| ())
+        .map_err(|e| format!("Failed to activate model: {:?}", e))
+    })
+}
+
+/// Delete a model version that is not active, with all its chunks
+#[update]
+fn delete_model(model_id: String) -> Result<(), String> {
+    require_controller()?;
+    if active_model_id().as_deref() == Some(model_id.as_str()) {
+        return Err("The active model cannot be deleted; activate another version first".to_string());
+    }
+    let record = model_record(&model_id)?;
+
+    SHARD_CHUNKS.with(|c| {
+        for shard in 0..record.manifest.total_shards {
+            let keys: Vec<ChunkKey> = c.borrow().range(chunk_range(&model_id, shard)).map(|(k, _)| k).collect();
+            let mut chunks = c.borrow_mut();
+            for key in keys {
+                chunks.remove(&key);
+            }
+        }
+    });
+    MODELS.with(|m| m.borrow_mut().remove(&model_id));
+    TOKENIZERS.with(|t| t.borrow_mut().remove(&model_id));
+    TOKENIZER_UPLOADS.with(|u| u.borrow_mut().remove(&model_id));
+    CHAT_TEMPLATES.with(|t| t.borrow_mut().remove(&model_id));
+    ACTIVE_MODEL.with(|a| {
+        let mut active = a.borrow().get().clone();
+        if active.previous_model_id.as_deref() == Some(model_id.as_str()) {
+            active.previous_model_id = None;
+            let _ = a.borrow_mut().set(active);
+        }
+    });
+    Ok(())
+}
+
+#[query]
+fn get_upload_status(model_id: String) -> Result<UploadStatus, String> {
+    let active = active_model_id().as_deref() == Some(model_id.as_str());
+    model_record(&model_id).map(|r| r.upload_status(active))
+}
+
+#[query]
+fn list_models() -> Vec<UploadStatus> {
+    let active = active_model_id();
+    MODELS.with(|m| {
+        m.borrow()
+            .iter()
+            .map(|(id, record)| record.upload_status(active.as_deref() == Some(id.as_str())))
+            .collect()
+    })
+}
+
+#[query]
+fn get_active_model() -> Option<ModelManifest> {
+    active_model_record().map(|r| r.manifest)
+}
+
+// ============================================================================
+// Tokenizer
+// ============================================================================
+
+/// Append part of a model version's tokenizer.json (it exceeds one ingress message).
+/// `offset` must equal the bytes received so far; offset 0 restarts the upload.
+/// Returns the bytes received.
+#[update]
+fn upload_tokenizer_chunk(model_id: String, offset: u64, data: Vec<u8>) -> Result<u64, String> {
+    require_controller()?;
+    model_record(&model_id)?;
+    if data.len() > upload::MAX_CHUNK_BYTES as usize {
+        return Err(format!("Chunk exceeds {} bytes", upload::MAX_CHUNK_BYTES));
+    }
+    TOKENIZER_UPLOADS.with(|u| {
+        let mut uploads = u.borrow_mut();
+        let mut buffer = if offset == 0 { Vec::new() } else { uploads.get(&model_id).unwrap_or_default() };
+        if buffer.len() as u64 != offset {
+            return Err(format!("Expected offset {}, got {}", buffer.len(), offset));
+        }
+        if offset + data.len() as u64 > tokenizer::MAX_TOKENIZER_BYTES {
+            return Err(format!("tokenizer.json exceeds {} bytes", tokenizer::MAX_TOKENIZER_BYTES));
+        }
+        buffer.extend_from_slice(&data);
+        let received = buffer.len() as u64;
+        uploads.insert(model_id, buffer);
+        Ok(received)
+    })
+}
+
+/// Verify the uploaded tokenizer.json against its SHA-256, parse it and attach it to
+/// the model version (replacing any previous tokenizer)
+#[update]
+fn finalize_tokenizer(model_id: String, sha256: String) -> Result<TokenizerInfo, String> {
+    require_controller()?;
+    let bytes = TOKENIZER_UPLOADS.with(|u| u.borrow().get(&model_id)).ok_or("No tokenizer upload in progress")?;
+    let actual = upload::sha256_hex([&bytes]);
+    if actual != sha256.to_lowercase() {
+        TOKENIZER_UPLOADS.with(|u| u.borrow_mut().remove(&model_id));
+        return Err(format!("tokenizer.json hash mismatch: expected {}, got {}", sha256, actual));
+    }
+    let data = tokenizer::parse_tokenizer_json(&bytes)?;
+    let info = data.info(&model_id);
+
+    TOKENIZERS.with(|t| t.borrow_mut().insert(model_id.clone(), data));
+    TOKENIZER_UPLOADS.with(|u| u.borrow_mut().remove(&model_id));
+    LOADED_TOKENIZER.with(|l| *l.borrow_mut() = None);
+    Ok(info)
+}
+
+#[query]
+fn get_tokenizer_info(model_id: String) -> Option<TokenizerInfo> {
+    TOKENIZERS.with(|t| t.borrow().get(&model_id)).map(|data| data.info(&model_id))
+}
+
+/// The active model's uploaded tokenizer
+fn active_tokenizer() -> Option<Rc<Tokenizer>> {
+    let model_id = active_model_id()?;
+    LOADED_TOKENIZER.with(|loaded| {
+        let mut loaded = loaded.borrow_mut();
+        if loaded.as_ref().map(|(id, _)| id != &model_id).unwrap_or(true) {
+            let data = TOKENIZERS.with(|t| t.borrow().get(&model_id))?;
+            *loaded = Some((model_id, Rc::new(Tokenizer::new(data))));
+        }
+        loaded.as_ref().map(|(_, t)| t.clone())
+    })
+}
+
+/// Text <-> token ids for the active model: its tokenizer.json when uploaded, else
+/// the vocabulary embedded in its GGUF file
+enum Codec {
+    Bpe(Rc<Tokenizer>),
+    Gguf(Rc<LocalModel>),
+}
+
+impl Codec {
+    fn active() -> Option<Self> {
+        active_tokenizer().map(Codec::Bpe).or_else(|| local_model().map(Codec::Gguf))
+    }
+
+    fn encode(&self, text: &str) -> Vec<u32> {
+        match self {
+            Codec::Bpe(t) => t.encode(text),
+            Codec::Gguf(local) => local.model.vocab.encode(text),
+        }
+    }
+
+    fn token_id(&self, token: &str) -> Option<u32> {
+        match self {
+            Codec::Bpe(t) => t.token_id(token),
+            Codec::Gguf(local) => local.model.vocab.token_id(token),
+        }
+    }
+
+    fn decode(&self, ids: &[u32]) -> String {
+        match self {
+            Codec::Bpe(t) => t.decode(ids, true),
+            Codec::Gguf(local) => local.model.vocab.decode(ids),
+        }
+    }
+}
+
+/// Token count of `text`; without any tokenizer, estimated at ~4 bytes per token
+fn count_tokens(codec: Option<&Codec>, text: &str) -> u32 {
+    match codec {
+        Some(codec) => codec.encode(text).len() as u32,
+        None => text.len().div_ceil(4) as u32,
+    }
+}
+
+/// Encode text with the active model's tokenizer
+#[query]
+fn tokenize(text: String) -> Result<Vec<u32>, String> {
+    Codec::active().map(|c| c.encode(&text)).ok_or_else(|| "No tokenizer for the active model".to_string())
+}
+
+/// Decode token ids with the active model's tokenizer (special tokens are skipped)
+#[query]
+fn detokenize(ids: Vec<u32>) -> Result<String, String> {
+    Codec::active().map(|c| c.decode(&ids)).ok_or_else(|| "No tokenizer for the active model".to_string())
+}
+
+#[query]
+fn get_inference_config() -> InferenceConfig {
+    INFERENCE_CONFIG.with(|c| c.borrow().get().clone())
+}
+
+#[update]
+fn admin_set_context_window(tokens: u32) -> Result<(), String> {
+    require_controller()?;
+    if !(256..=131_072).contains(&tokens) {
+        return Err("Context window must be between 256 and 131072 tokens".to_string());
+    }
+    INFERENCE_CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.context_window_tokens = tokens;
+        c.borrow_mut().set(config).map(|_| ()).map_err(|e| format!("Failed to update config: {:?}", e))
+    })
+}
+
+/// Set the orchestrator canister (queen_bee) that serves many users through this one
+#[update]
+fn admin_set_orchestrator(orchestrator: Option<Principal>) -> Result<(), String> {
+    require_controller()?;
+    INFERENCE_CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.orchestrator = orchestrator;
+        c.borrow_mut().set(config).map(|_| ()).map_err(|e| format!("Failed to update config: {:?}", e))
+    })
+}
+
+fn is_orchestrator(caller: &Principal) -> bool {
+    INFERENCE_CONFIG.with(|c| c.borrow().get().orchestrator.as_ref() == Some(caller))
+}
+
+// ============================================================================
+// On-canister Engine
+// ============================================================================
+
+/// Instructions `infer` may spend locally before continuing over the HTTP outcall
+const INFER_INSTRUCTION_BUDGET: u64 = 30_000_000_000;
+/// Instructions one chunked-generation round may spend
+const ROUND_INSTRUCTION_BUDGET: u64 = 30_000_000_000;
+
+/// A verified model's shards read as one contiguous GGUF file
+struct StoredModel {
+    model_id: String,
+    manifest: ModelManifest,
+    last_chunk: RefCell<Option<(ChunkKey, Rc<Vec<u8>>)>>, // Header parsing makes many tiny reads
+}
+
+impl gguf::ByteSource for StoredModel {
+    fn size(&self) -> u64 {
+        self.manifest.total_bytes()
+    }
+
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, String> {
+        let mut out = Vec::with_capacity(len);
+        while out.len() < len {
+            let (shard, chunk, within) = self.manifest.locate(offset + out.len() as u64)
+                .ok_or("Read past the end of the model")?;
+            let key = ChunkKey { model_id: self.model_id.clone(), shard, chunk };
+            let cached = self.last_chunk.borrow().as_ref().filter(|(k, _)| *k == key).map(|(_, d)| d.clone());
+            let data = match cached {
+                Some(data) => data,
+                None => {
+                    let data = Rc::new(SHARD_CHUNKS.with(|c| c.borrow().get(&key))
+                        .ok_or_else(|| format!("Missing chunk {}/{}", shard, chunk))?);
+                    *self.last_chunk.borrow_mut() = Some((key, data.clone()));
+                    data
+                }
+            };
+            let take = data.len().saturating_sub(within).min(len - out.len());
+            if take == 0 {
+                return Err(format!("Chunk {}/{} is shorter than the manifest", shard, chunk));
+            }
+            out.extend_from_slice(&data[within..within + take]);
+        }
+        Ok(out)
+    }
+}
+
+struct LocalModel {
+    source: StoredModel,
+    model: engine::Model,
+}
+
+/// The active model, parsed for local inference with this canister's stage layers
+/// (None if none is active or it is not a supported GGUF; the result is cached per
+/// model id)
+fn local_model() -> Option<Rc<LocalModel>> {
+    local_model_result().and_then(|parsed| parsed.ok())
+}
+
+fn local_model_result() -> Option<LoadedModel> {
+    let record = active_model_record()?;
+    let model_id = record.manifest.model_id.clone();
+    LOADED_MODEL.with(|loaded| {
+        let mut loaded = loaded.borrow_mut();
+        if loaded.as_ref().map(|(id, _)| id != &model_id).unwrap_or(true) {
+            let stage = STAGE_CONFIG.with(|c| c.borrow().get().clone());
+            let source = StoredModel { model_id: model_id.clone(), manifest: record.manifest, last_chunk: RefCell::new(None) };
+            let parsed = engine::Model::load_layers(&source, stage.start_layer as usize, stage.end_layer.map(|e| e as usize))
+                .map(|model| Rc::new(LocalModel { source, model }));
+            if let Err(e) = &parsed {
+                ic_cdk::println!("Model {} is not usable for local inference: {}", model_id, e);
+            }
+            *loaded = Some((model_id, parsed));
+        }
+        loaded.as_ref().map(|(_, parsed)| parsed.clone())
+    })
+}
+
+/// The active model when this canister holds all of its layers (not a pipeline stage)
+fn full_local_model() -> Option<Rc<LocalModel>> {
+    local_model().filter(|local| local.model.holds_all_layers())
+}
+
+fn local_state(local: &LocalModel, codec: &Codec, prompt: &str, max_tokens: u32, temperature: f32, top_p: f32) -> Result<InferenceState, String> {
+    let mut state = InferenceState::new(&local.model.config, codec.encode(prompt), max_tokens, temperature, top_p, ic_cdk::api::time())?;
+    state.stop_tokens = active_template().stop_strings().iter().filter_map(|s| codec.token_id(s)).collect();
+    Ok(state)
+}
+
+/// Advance `state` until the instruction budget is spent; returns the new text,
+/// token count and whether the model ended the answer itself
+fn run_local(local: &LocalModel, codec: &Codec, state: &mut InferenceState, instructions: u64) -> Result<(String, u32, bool), String> {
+    let start = ic_cdk::api::instruction_counter();
+    let limit = start.saturating_add(instructions);
+    let (before, pos) = (codec.decode(state.generated()), state.pos);
+    let new_tokens = engine::run(&local.model, &local.source, state, &mut || ic_cdk::api::instruction_counter() < limit)?;
+    let processed = state.pos.saturating_sub(pos) as u64;
+    if let Some(cost) = (ic_cdk::api::instruction_counter() - start).checked_div(processed) {
+        TOKEN_COSTS.with(|c| c.borrow_mut().insert(local.source.model_id.clone(), cost));
+    }
+
+    // Decode the whole completion so multi-byte characters split across rounds come out whole
+    let after = codec.decode(state.generated());
+    let text = after.strip_prefix(before.as_str()).map(str::to_string)
+        .unwrap_or_else(|| codec.decode(&new_tokens));
+    let stopped = matches!(state.stop, Some(StopReason::EndOfSequence | StopReason::ContextFull));
+    Ok((text, new_tokens.len() as u32, stopped))
+}
+
+// ============================================================================
+// On-chain Inference
+// ============================================================================
+
+/// Chat template of the active model (DeepSeek-R1's unless one was assigned)
+fn active_template() -> ChatTemplate {
+    active_model_id()
+        .and_then(|id| CHAT_TEMPLATES.with(|t| t.borrow().get(&id)))
+        .unwrap_or_default()
+}
+
+/// Assign the chat template a model version's prompts are rendered with
+#[update]
+fn admin_set_chat_template(model_id: String, template: ChatTemplate) -> Result<(), String> {
+    require_controller()?;
+    model_record(&model_id)?;
+    CHAT_TEMPLATES.with(|t| t.borrow_mut().insert(model_id, template));
+    Ok(())
+}
+
+#[query]
+fn get_chat_template(model_id: String) -> ChatTemplate {
+    CHAT_TEMPLATES.with(|t| t.borrow().get(&model_id)).unwrap_or_default()
+}
+
+/// Render system prompt, context (without its `skip` oldest messages) and the query
+/// through the chat template, ending at the assistant's turn
+fn build_prompt(request: &InferenceRequest, template: ChatTemplate, skip: usize) -> String {
+    let messages: Vec<(Role, &str)> = request.context
+        .iter()
+        .skip(skip)
+        .map(|ctx| (Role::parse(&ctx.role), ctx.content.as_str()))
+        .collect();
+    template.render(request.system_prompt.as_deref(), &messages, &request.prompt)
+}
+
+struct FittedPrompt {
+    text: String,
+    tokens: u32,
+    dropped: u32, // Oldest context messages trimmed
+}
+
+/// Render the prompt within the configured context window, leaving room for the
+/// completion (at most half the window) and trimming the oldest context first
+fn fit_prompt(request: &InferenceRequest, codec: Option<&Codec>, max_tokens: u32) -> Result<FittedPrompt, String> {
+    let template = active_template();
+    let window = INFERENCE_CONFIG.with(|c| c.borrow().get().context_window_tokens);
+    let budget = window.saturating_sub(max_tokens.min(window / 2));
+    let (skip, tokens) = tokenizer::fit_window(request.context.len(), budget, |skip| {
+        count_tokens(codec, &build_prompt(request, template, skip))
+    })?;
+    Ok(FittedPrompt { text: build_prompt(request, template, skip), tokens, dropped: skip as u32 })
+}
+
+/// Whether processing `tokens` positions on a model is expected to fit in `budget`
+/// (unknown until the model has run once)
+fn fits_budget(model_id: &str, tokens: u32, budget: u64) -> bool {
+    TOKEN_COSTS.with(|c| c.borrow().get(model_id).copied())
+        .map(|cost| cost.saturating_mul(tokens as u64) <= budget)
+        .unwrap_or(true)
+}
+
+/// Shared by `infer` and `infer_with_shards`: run the active GGUF model in this
+/// message when it is expected to finish within the instruction budget, otherwise
+/// (or when no GGUF model is active) use the HTTP outcall. An answer the budget
+/// cuts short is continued over HTTP rather than thrown away. Long completions on
+/// a large model belong in `start_generation`, which spreads the work over many messages.
+async fn run_inference(request: &InferenceRequest, shards_used: Vec<u32>) -> Result<InferenceResponse, String> {
+    let start_time = ic_cdk::api::time();
+
     // Limit max_tokens to fit within IC instruction limits
     // With 4-bit quantization, we can handle 256-512 tokens per round
-    let max_tokens = request.max_tokens.min(512).max(64);
-    
-    // 2. Drop any borrows on thread_local before .await
-    // (shard_ids is already a Vec<u32>, no borrows held)
-    
-    // 3. Now do async work with only local variables
-    // Call llama.cpp compatible API via HTTP outcall
-    // Using 4-bit quantized model: DeepSeek-R1-Distill-Qwen-7B-Q4_K_M
-    let inference_result = call_deepseek_r1_api(
-        &full_prompt, 
-        max_tokens, 
-        request.temperature, 
-        request.top_p
+    let max_tokens = request.max_tokens.clamp(64, 512);
+
+    let codec = Codec::active();
+    let prompt = fit_prompt(request, codec.as_ref(), max_tokens)?;
+
+    // Text and tokens produced locally before the budget ran out
+    let mut partial = (String::new(), 0u32);
+    let local = full_local_model().filter(|local| {
+        let fits = fits_budget(&local.source.model_id, prompt.tokens + max_tokens, INFER_INSTRUCTION_BUDGET);
+        if !fits {
+            ic_cdk::println!("Local inference would exceed the message budget; using HTTP fallback");
+        }
+        fits
+    });
+    if let (Some(local), Some(codec)) = (local, codec.as_ref()) {
+        let result = local_state(&local, codec, &prompt.text, max_tokens, request.temperature, request.top_p)
+            .and_then(|mut state| run_local(&local, codec, &mut state, INFER_INSTRUCTION_BUDGET).map(|r| (r, state.is_done())));
+        match result {
+            Ok(((text, tokens, _), true)) => {
+                let (reasoning, answer) = template::split_reasoning(&text);
+                return Ok(InferenceResponse {
+                    response: answer,
+                    reasoning,
+                    tokens_generated: tokens,
+                    inference_time_ms: (ic_cdk::api::time() - start_time) / 1_000_000,
+                    shards_used,
+                    prompt_tokens: prompt.tokens,
+                    completion_tokens: tokens,
+                    context_messages_dropped: prompt.dropped,
+                });
+            }
+            Ok(((text, tokens, _), false)) => {
+                ic_cdk::println!("Local inference exceeded the message budget; continuing over HTTP");
+                partial = (text, tokens);
+            }
+            Err(e) => ic_cdk::println!("Local inference failed: {}; using HTTP fallback", e),
+        }
+    }
+
+    // No borrows on thread_local state are held across the outcall
+    let (continuation, reported_tokens, _) = call_deepseek_r1_api(
+        &format!("{}{}", prompt.text, partial.0),
+        max_tokens.saturating_sub(partial.1).max(1),
+        request.temperature,
+        request.top_p,
     ).await?;
-    
-    // 4. Calculate latency and return (no need to put anything back)
-    let latency_ms = (ic_cdk::api::time() - start_time) / 1_000_000;
-    
+
+    // Count with our own tokenizer when there is one rather than trusting the API
+    let response = active_template().truncate_at_stop(&format!("{}{}", partial.0, continuation));
+    let completion_tokens = match &codec {
+        Some(codec) => count_tokens(Some(codec), &response),
+        None => reported_tokens + partial.1,
+    };
+    let (reasoning, answer) = template::split_reasoning(&response);
     Ok(InferenceResponse {
-        response: inference_result.0,
-        tokens_generated: inference_result.1,
-        inference_time_ms: latency_ms,
-        shards_used: shard_ids,
+        response: answer,
+        reasoning,
+        tokens_generated: completion_tokens,
+        inference_time_ms: (ic_cdk::api::time() - start_time) / 1_000_000,
+        shards_used,
+        prompt_tokens: prompt.tokens,
+        completion_tokens,
+        context_messages_dropped: prompt.dropped,
     })
 }
 
+/// Shards of the model inference runs on: the active verified model, else legacy shards
+fn inference_shard_ids() -> Vec<u32> {
+    match active_model_record() {
+        Some(record) => (0..record.manifest.total_shards).collect(),
+        None => get_all_shard_ids(),
+    }
+}
+
+/// Perform inference using the active model
+///
+/// Latency Optimization:
+/// - Uses 4-bit quantization (Q4_K_M) for DeepSeek R1 7B to reduce model size by ~75%
+/// - Limits max_tokens to 64-512 to fit within IC instruction limits per round
+#[update]
+async fn infer(request: InferenceRequest) -> Result<InferenceResponse, String> {
+    let shard_ids = inference_shard_ids();
+    if shard_ids.is_empty() {
+        return Err("No model shards loaded".to_string());
+    }
+    run_inference(&request, shard_ids).await
+}
+
 /// Call DeepSeek R1 API via HTTP outcall
 /// 
 /// Latency Optimizations:
@@ -331,7 +1023,7 @@ async fn call_deepseek_r1_api(
     max_tokens: u32,
     temperature: f32,
     top_p: f32,
-) -> Result<(String, u32), String> {
+) -> Result<(String, u32, bool), String> {
     use ic_cdk::api::management_canister::http_request::{
         http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod,
     };
@@ -347,10 +1039,16 @@ async fn call_deepseek_r1_api(
     // Limit prompt length to prevent instruction limit issues
     // IC has ~20M instructions per message execution
     // With 4-bit quantization, we can handle ~2000 input tokens safely
+    // Keep the end of the prompt: that is where the completion (or a continued
+    // generation session) picks up
     let prompt_len = prompt.len();
     let truncated_prompt = if prompt_len > 8000 {
-        // Truncate to ~2000 tokens (roughly 8000 chars)
-        format!("{}...", &prompt[..8000])
+        // Truncate to ~2000 tokens (roughly 8000 chars), on a char boundary
+        let mut start = prompt_len - 8000;
+        while !prompt.is_char_boundary(start) {
+            start += 1;
+        }
+        format!("...{}", &prompt[start..])
     } else {
         prompt.to_string()
     };
@@ -363,6 +1061,7 @@ async fn call_deepseek_r1_api(
             "top_p": top_p,
             "return_full_text": false,
             "do_sample": true,
+            "details": true, // generated_tokens and finish_reason
             // Optimize for latency
             "top_k": 40,  // Reduce search space
             "repetition_penalty": 1.1,  // Prevent loops
@@ -422,13 +1121,24 @@ async fn call_deepseek_r1_api(
                     .and_then(|item| item.get("generated_text"))
                     .and_then(|v| v.as_str())
                     .ok_or_else(|| "No generated_text in response".to_string())?;
+                let details = json.get(0).and_then(|item| item.get("details"));
+                
+                // Prefer the server's token count; otherwise estimate
+                // (DeepSeek R1 uses BPE tokenizer, ~0.75 tokens per word)
+                let tokens = details
+                    .and_then(|d| d.get("generated_tokens"))
+                    .and_then(|v| v.as_u64())
+                    .map(|t| t as u32)
+                    .unwrap_or_else(|| (generated_text.split_whitespace().count() as f32 * 0.75) as u32);
                 
-                // More accurate token estimation for 4-bit quantized models
-                // DeepSeek R1 uses BPE tokenizer, ~0.75 tokens per word
-                let word_count = generated_text.split_whitespace().count();
-                let tokens = (word_count as f32 * 0.75) as u32;
+                // Stopped on its own unless the server says the length budget ran out
+                // (without details, only an empty completion counts as stopped)
+                let stopped = match details.and_then(|d| d.get("finish_reason")).and_then(|v| v.as_str()) {
+                    Some(reason) => reason != "length",
+                    None => generated_text.trim().is_empty(),
+                };
                 
-                Ok((generated_text.to_string(), tokens))
+                Ok((generated_text.to_string(), tokens, stopped))
             } else {
                 Err(format!("API error {}: {}", response.status, String::from_utf8_lossy(&response.body)))
             }
@@ -437,71 +1147,368 @@ async fn call_deepseek_r1_api(
     }
 }
 
-/// Perform inference with specific shards
-/// Uses async-safe pattern: extract data, drop borrows, do async work
+/// Perform inference naming the shards to use; each must belong to the active model
 #[update]
 async fn infer_with_shards(
     request: InferenceRequest,
     shard_ids: Vec<u32>,
 ) -> Result<InferenceResponse, String> {
-    let start_time = ic_cdk::api::time();
-    
-    // 1. Extract shard data FIRST (before any .await)
-    let mut shard_data: Vec<(u32, Vec<u8>)> = Vec::new();
-    
-    for shard_id in &shard_ids {
-        let shard = get_shard(*shard_id)?;
-        // Clone shard data to avoid holding borrows across .await
-        shard_data.push((*shard_id, shard.shard_data.clone()));
+    let available = inference_shard_ids();
+    if let Some(missing) = shard_ids.iter().find(|id| !available.contains(id)) {
+        return Err(format!("Shard {} not found", missing));
     }
-    
-    // 2. Build prompt (local computation)
-    let mut full_prompt = String::new();
-    
-    if let Some(ref sys_prompt) = request.system_prompt {
-        full_prompt.push_str(&format!("System: {}\n\n", sys_prompt));
+    run_inference(&request, shard_ids).await
+}
+
+// ============================================================================
+// Chunked Generation
+// ============================================================================
+
+/// Run the next round of a session on a timer (skipped if one is already running)
+fn schedule_round(session_id: String) {
+    ic_cdk_timers::set_timer(std::time::Duration::ZERO, move || {
+        ic_cdk::spawn(run_round(session_id));
+    });
+}
+
+/// Generate one round of tokens for a session and schedule the following round
+async fn run_round(session_id: String) {
+    if !ROUNDS_IN_FLIGHT.with(|r| r.borrow_mut().insert(session_id.clone())) {
+        return;
     }
-    
-    let context_limit = request.context.len().min(10);
-    for ctx in request.context.iter().take(context_limit) {
-        full_prompt.push_str(&format!("{}: {}\n", ctx.role, ctx.content));
+
+    let session = GENERATION_SESSIONS.with(|s| s.borrow().get(&session_id));
+    let session = match session {
+        Some(session) if session.is_active() => session,
+        _ => {
+            ROUNDS_IN_FLIGHT.with(|r| r.borrow_mut().remove(&session_id));
+            return;
+        }
+    };
+
+    let result = match &session.local_model {
+        Some(model_id) => run_local_round(&session, model_id),
+        None => call_deepseek_r1_api(
+            &session.continuation_prompt(),
+            session.round_budget(),
+            session.temperature,
+            session.top_p,
+        ).await.map(|(text, tokens, stopped)| {
+            let kept = active_template().truncate_at_stop(&text);
+            let stopped = stopped || kept.is_empty() || kept.len() < text.len();
+            (kept, tokens, stopped)
+        }),
+    };
+
+    ROUNDS_IN_FLIGHT.with(|r| r.borrow_mut().remove(&session_id));
+
+    // Re-read: the session may have been cancelled while the round was running
+    let still_active = GENERATION_SESSIONS.with(|s| {
+        let mut sessions = s.borrow_mut();
+        let mut session = match sessions.get(&session_id) {
+            Some(session) if session.is_active() => session,
+            _ => return false,
+        };
+        let now = ic_cdk::api::time();
+        match result {
+            Ok((text, tokens, stopped)) => session.apply_round(&text, tokens, stopped, now),
+            Err(e) => {
+                ic_cdk::println!("Generation round for {} failed: {}", session_id, e);
+                session.apply_failure(e, now);
+            }
+        }
+        let active = session.is_active();
+        sessions.insert(session_id.clone(), session);
+        active
+    });
+    if !still_active {
+        ENGINE_STATES.with(|e| e.borrow_mut().remove(&session_id));
     }
-    
-    full_prompt.push_str(&format!("User: {}\n\nAssistant:", request.prompt));
-    
-    // Limit tokens for instruction budget
-    let max_tokens = request.max_tokens.min(512).max(64);
-    
-    // 3. Drop all borrows - shard_data is now owned Vec
-    // No thread_local borrows held
-    
-    // 4. Do async work with local variables only
-    // In production, this would:
-    // - Load shard data into llama.cpp context
-    // - Run inference with 4-bit quantization
-    // - Return results
-    // For now, use HTTP outcall as fallback
-    let inference_result = call_deepseek_r1_api(
-        &full_prompt,
-        max_tokens,
+
+    if still_active {
+        schedule_round(session_id);
+    }
+}
+
+/// One round on the on-canister engine, persisting the advanced state
+fn run_local_round(session: &GenerationSession, model_id: &str) -> Result<(String, u32, bool), String> {
+    let local = full_local_model()
+        .filter(|local| local.source.model_id == model_id)
+        .ok_or_else(|| format!("Model {} is no longer active", model_id))?;
+    let mut state = ENGINE_STATES.with(|e| e.borrow().get(&session.id))
+        .ok_or("Generation state missing")?;
+    let codec = Codec::active().ok_or("No tokenizer for the active model")?;
+    let result = run_local(&local, &codec, &mut state, ROUND_INSTRUCTION_BUDGET)?;
+    ENGINE_STATES.with(|e| e.borrow_mut().insert(session.id.clone(), state));
+    Ok(result)
+}
+
+/// Drop sessions untouched for longer than the session TTL
+fn prune_sessions(now: u64) {
+    GENERATION_SESSIONS.with(|s| {
+        let expired: Vec<String> = s.borrow()
+            .iter()
+            .filter(|(_, session)| session.is_expired(now))
+            .map(|(id, _)| id)
+            .collect();
+        let mut sessions = s.borrow_mut();
+        for id in expired {
+            sessions.remove(&id);
+            ENGINE_STATES.with(|e| e.borrow_mut().remove(&id));
+        }
+    });
+}
+
+/// Start a chunked generation and return its session id; poll with `next_chunk`.
+/// Unlike `infer`, `max_tokens` may exceed 512 (up to 4096), produced over several rounds.
+#[update]
+fn start_generation(request: InferenceRequest) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous callers not allowed".to_string());
+    }
+    if inference_shard_ids().is_empty() {
+        return Err("No model shards loaded".to_string());
+    }
+
+    let now = ic_cdk::api::time();
+    prune_sessions(now);
+
+    // The orchestrator starts sessions for many users and limits each of them itself
+    let active = GENERATION_SESSIONS.with(|s| {
+        s.borrow().iter().filter(|(_, session)| session.owner == caller && session.is_active()).count()
+    });
+    if active >= generation::MAX_ACTIVE_SESSIONS_PER_CALLER && !is_orchestrator(&caller) {
+        return Err(format!(
+            "Too many active generations (max {})",
+            generation::MAX_ACTIVE_SESSIONS_PER_CALLER
+        ));
+    }
+
+    let seq = SESSION_SEQ.with(|s| {
+        let mut seq = s.borrow_mut();
+        *seq += 1;
+        *seq
+    });
+    let session_id = format!("gen-{}-{}", now, seq);
+    let mut session = GenerationSession::new(
+        session_id.clone(),
+        caller,
+        String::new(),
+        request.max_tokens,
         request.temperature,
         request.top_p,
-    ).await?;
-    
-    let latency_ms = (ic_cdk::api::time() - start_time) / 1_000_000;
-    
-    Ok(InferenceResponse {
-        response: format!(
-            "[Shards {:?}] {}",
-            shard_ids,
-            inference_result.0
-        ),
-        tokens_generated: inference_result.1,
-        inference_time_ms: latency_ms,
-        shards_used: shard_ids,
+        now,
+    );
+    let codec = Codec::active();
+    session.prompt = fit_prompt(&request, codec.as_ref(), session.max_tokens)?.text;
+    if let (Some(local), Some(codec)) = (full_local_model(), codec.as_ref()) {
+        let state = local_state(&local, codec, &session.prompt, session.max_tokens, session.temperature, session.top_p)?;
+        ENGINE_STATES.with(|e| e.borrow_mut().insert(session_id.clone(), state));
+        session.local_model = Some(local.source.model_id.clone());
+    }
+    GENERATION_SESSIONS.with(|s| s.borrow_mut().insert(session_id.clone(), session));
+    schedule_round(session_id.clone());
+
+    Ok(session_id)
+}
+
+/// Return the text generated since the previous poll (`done` once the session ends)
+#[update]
+fn next_chunk(session_id: String) -> Result<GenerationChunk, String> {
+    let caller = ic_cdk::caller();
+    let chunk = GENERATION_SESSIONS.with(|s| {
+        let mut sessions = s.borrow_mut();
+        let mut session = sessions.get(&session_id).ok_or("Generation session not found")?;
+        if session.owner != caller && !ic_cdk::api::is_controller(&caller) {
+            return Err("Not the owner of this generation session".to_string());
+        }
+        let chunk = session.take_chunk();
+        sessions.insert(session_id.clone(), session);
+        Ok::<_, String>(chunk)
+    })?;
+
+    // Resume a session whose round chain was interrupted (e.g. by an upgrade)
+    if !chunk.done && !ROUNDS_IN_FLIGHT.with(|r| r.borrow().contains(&session_id)) {
+        schedule_round(session_id);
+    }
+    Ok(chunk)
+}
+
+/// Stop a generation; text produced so far stays readable via `next_chunk`
+#[update]
+fn cancel_generation(session_id: String) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    GENERATION_SESSIONS.with(|s| {
+        let mut sessions = s.borrow_mut();
+        let mut session = sessions.get(&session_id).ok_or("Generation session not found")?;
+        if session.owner != caller && !ic_cdk::api::is_controller(&caller) {
+            return Err("Not the owner of this generation session".to_string());
+        }
+        if session.is_active() {
+            session.status = GenerationStatus::Cancelled;
+            session.updated_at = ic_cdk::api::time();
+            sessions.insert(session_id.clone(), session);
+            ENGINE_STATES.with(|e| e.borrow_mut().remove(&session_id));
+        }
+        Ok(())
     })
 }
 
+// ============================================================================
+// Pipeline Stages
+// ============================================================================
+
+/// Assign this canister a contiguous range of the active model's layers (`end_layer`
+/// exclusive, None = through the last layer). Canisters covering 0..n together serve
+/// one model, driven token by token by the orchestrator.
+#[update]
+fn admin_set_pipeline_stage(start_layer: u32, end_layer: Option<u32>) -> Result<StageInfo, String> {
+    require_controller()?;
+    if end_layer.is_some_and(|end| end <= start_layer) {
+        return Err("end_layer must be greater than start_layer".to_string());
+    }
+    STAGE_CONFIG.with(|c| c.borrow_mut().set(StageConfig { start_layer, end_layer }))
+        .map_err(|e| format!("Failed to update stage: {:?}", e))?;
+    LOADED_MODEL.with(|loaded| *loaded.borrow_mut() = None);
+    clear_stage_caches();
+    Ok(get_pipeline_stage())
+}
+
+#[query]
+fn get_pipeline_stage() -> StageInfo {
+    let config = STAGE_CONFIG.with(|c| c.borrow().get().clone());
+    let mut info = StageInfo {
+        model_id: active_model_id(),
+        start_layer: config.start_layer,
+        end_layer: config.end_layer.unwrap_or(config.start_layer),
+        total_layers: 0,
+        hidden_dim: 0,
+        ready: false,
+        error: None,
+    };
+    match local_model_result() {
+        Some(Ok(local)) => {
+            info.end_layer = local.model.layers.end as u32;
+            info.total_layers = local.model.config.n_layers as u32;
+            info.hidden_dim = local.model.config.dim as u32;
+            info.ready = true;
+        }
+        Some(Err(e)) => info.error = Some(e),
+        None => info.error = Some("No active model".to_string()),
+    }
+    info
+}
+
+fn clear_stage_caches() {
+    STAGE_CACHES.with(|c| {
+        let keys: Vec<String> = c.borrow().iter().map(|(k, _)| k).collect();
+        let mut caches = c.borrow_mut();
+        for key in keys {
+            caches.remove(&key);
+        }
+    });
+}
+
+/// Only the orchestrator (queen_bee) and controllers may drive pipeline stages
+fn require_pipeline_driver() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if !is_orchestrator(&caller) && !ic_cdk::api::is_controller(&caller) {
+        return Err("Only the orchestrator can run pipeline stages".to_string());
+    }
+    Ok(())
+}
+
+fn stage_cache_key(session_id: &str) -> String {
+    format!("{}:{}", ic_cdk::caller(), session_id)
+}
+
+/// Run one token position through this canister's layers (orchestrator or
+/// controllers only). Steps must arrive in position order per session; repeating
+/// the latest step (a retry) is safe.
+#[update]
+fn pipeline_forward(request: StageRequest) -> Result<StageResponse, String> {
+    require_pipeline_driver()?;
+    let local = local_model_result().ok_or("No active model")??;
+    let start = ic_cdk::api::instruction_counter();
+    let now = ic_cdk::api::time();
+    let key = stage_cache_key(&request.session_id);
+
+    if request.position == 0 {
+        STAGE_CACHES.with(|c| {
+            let expired: Vec<String> = c.borrow()
+                .iter()
+                .filter(|(_, cache)| now.saturating_sub(cache.updated_at) > pipeline::STAGE_CACHE_TTL_NS)
+                .map(|(k, _)| k)
+                .collect();
+            let mut caches = c.borrow_mut();
+            for k in expired {
+                caches.remove(&k);
+            }
+        });
+    }
+
+    let mut cache = match request.position {
+        0 => StageCache::default(),
+        _ => STAGE_CACHES.with(|c| c.borrow().get(&key)).ok_or("Pipeline session not found on this stage")?,
+    };
+    let output = pipeline::run_stage(&local.model, &local.source, &mut cache, &request, now)?;
+    STAGE_CACHES.with(|c| c.borrow_mut().insert(key, cache));
+
+    Ok(StageResponse {
+        output,
+        start_layer: local.model.layers.start as u32,
+        end_layer: local.model.layers.end as u32,
+        instructions: ic_cdk::api::instruction_counter() - start,
+    })
+}
+
+/// Render and tokenize a request for a pipeline run (done by the first stage):
+/// the prompt trimmed to the context window, the tokens that end the answer and
+/// the completion budget
+#[query]
+fn pipeline_prepare(request: InferenceRequest) -> Result<PreparedPrompt, String> {
+    let codec = Codec::active().ok_or("No tokenizer for the active model")?;
+    let mut max_tokens = request.max_tokens.clamp(1, generation::MAX_SESSION_TOKENS);
+    let prompt = fit_prompt(&request, Some(&codec), max_tokens)?;
+    let tokens = codec.encode(&prompt.text);
+
+    let mut stop_tokens: Vec<u32> = active_template().stop_strings().iter().filter_map(|s| codec.token_id(s)).collect();
+    if let Some(local) = local_model() {
+        stop_tokens.extend(local.model.vocab.eos);
+        let room = local.model.config.context_length.saturating_sub(tokens.len());
+        max_tokens = max_tokens.min(room as u32);
+    }
+    stop_tokens.sort_unstable();
+    stop_tokens.dedup();
+    if max_tokens == 0 {
+        return Err("The prompt fills the model's context".to_string());
+    }
+
+    Ok(PreparedPrompt {
+        tokens,
+        stop_tokens,
+        max_tokens,
+        context_messages_dropped: prompt.dropped,
+    })
+}
+
+/// Turn a pipeline run's generated tokens into the answer and its reasoning
+#[query]
+fn pipeline_decode(tokens: Vec<u32>) -> Result<DecodedCompletion, String> {
+    let codec = Codec::active().ok_or("No tokenizer for the active model")?;
+    let text = active_template().truncate_at_stop(&codec.decode(&tokens));
+    let (reasoning, response) = template::split_reasoning(&text);
+    Ok(DecodedCompletion { response, reasoning })
+}
+
+/// Drop the caller's KV cache for a finished pipeline session
+#[update]
+fn pipeline_release(session_id: String) {
+    let key = stage_cache_key(&session_id);
+    STAGE_CACHES.with(|c| c.borrow_mut().remove(&key));
+}
+
 // ============================================================================
 // Health & Status
 // ============================================================================
@@ -509,7 +1516,7 @@ async fn infer_with_shards(
 /// Get canister status
 #[query]
 fn get_status() -> (bool, u64, u32) {
-    let shard_ids = get_all_shard_ids();
+    let shard_ids = inference_shard_ids();
     let shards_loaded = shard_ids.len() as u32;
     let ready = shards_loaded > 0;
     
@@ -0,0 +1,242 @@
+//! Pipeline-Parallel Stages
+//!
+//! A 7B model does not fit comfortably in one canister, so it is split across
+//! several: each holds a contiguous range of transformer layers
+//! (`admin_set_pipeline_stage`). The orchestrator (queen_bee) drives generation one
+//! token position at a time:
+//! - the first stage embeds the token and runs its layers
+//! - every later stage takes the previous stage's hidden state and runs its layers
+//! - the last stage samples the next token (or only fills its KV cache while the
+//!   prompt is being read)
+//!
+//! Each stage keeps the KV cache of its own layers per session. A step may repeat
+//! the latest position, which replaces that position's cache entries, so a failed
+//! call is simply retried.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::engine::{self, put_f32s, put_u32, Input, LayerCache, Model};
+use crate::gguf::ByteSource;
+
+/// Stage caches are dropped this long after their last step
+pub const STAGE_CACHE_TTL_NS: u64 = 60 * 60 * 1_000_000_000;
+
+/// Layers this canister serves; the default is the whole model
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct StageConfig {
+    pub start_layer: u32,
+    pub end_layer: Option<u32>, // Exclusive; None = through the last layer
+}
+
+impl Storable for StageConfig {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum StageInput {
+    Token(u32),       // First stage
+    Hidden(Vec<f32>), // Later stages: the previous stage's output
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SamplingParams {
+    pub temperature: f32,
+    pub top_p: f32,
+    pub seed: u64, // Mixed with the position, so a retried step samples the same token
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StageRequest {
+    pub session_id: String,
+    pub position: u32,
+    pub input: StageInput,
+    pub sample: Option<SamplingParams>, // None while reading the prompt
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum StageOutput {
+    Hidden(Vec<f32>), // Pass to the next stage
+    Token(u32),       // Last stage: the sampled next token
+    Cached,           // Last stage: prompt token absorbed, nothing to sample
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StageResponse {
+    pub output: StageOutput,
+    pub start_layer: u32,
+    pub end_layer: u32,
+    pub instructions: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StageInfo {
+    pub model_id: Option<String>,
+    pub start_layer: u32,
+    pub end_layer: u32, // Exclusive
+    pub total_layers: u32,
+    pub hidden_dim: u32,
+    pub ready: bool, // The active model is loaded and holds these layers
+    pub error: Option<String>,
+}
+
+/// Prompt rendered and tokenized by the first stage's canister
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PreparedPrompt {
+    pub tokens: Vec<u32>,
+    pub stop_tokens: Vec<u32>, // EOS and the chat template's end-of-turn tokens
+    pub max_tokens: u32,
+    pub context_messages_dropped: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DecodedCompletion {
+    pub response: String,
+    pub reasoning: Option<String>,
+}
+
+/// KV cache of this stage's layers for one session
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StageCache {
+    pub caches: Vec<LayerCache>,
+    pub updated_at: u64,
+}
+
+impl StageCache {
+    /// Positions already in the cache
+    pub fn positions(&self, kv_dim: usize) -> usize {
+        self.caches.first().map(|c| c.keys.len() / kv_dim.max(1)).unwrap_or(0)
+    }
+}
+
+impl Storable for StageCache {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut out = self.updated_at.to_le_bytes().to_vec();
+        put_u32(&mut out, self.caches.len() as u32);
+        for layer in &self.caches {
+            put_f32s(&mut out, &layer.keys);
+            put_f32s(&mut out, &layer.values);
+        }
+        Cow::Owned(out)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let mut i = Input(&bytes, 0);
+        let updated_at = u64::from_le_bytes(i.take(8).try_into().unwrap());
+        let layers = i.u32() as usize;
+        let caches = (0..layers).map(|_| LayerCache { keys: i.f32s(), values: i.f32s() }).collect();
+        Self { caches, updated_at }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// Run one token position through this stage's layers
+pub fn run_stage<S: ByteSource + ?Sized>(model: &Model, source: &S, cache: &mut StageCache, request: &StageRequest, now: u64) -> Result<StageOutput, String> {
+    let pos = request.position as usize;
+    if pos >= model.config.context_length {
+        return Err(format!("Position {} exceeds the context length {}", pos, model.config.context_length));
+    }
+    if cache.caches.len() != model.layers.len() {
+        if pos != 0 {
+            return Err("Stage layers changed during the session".to_string());
+        }
+        cache.caches = vec![LayerCache::default(); model.layers.len()];
+    }
+    // Positions arrive in order; the latest may be repeated by a retry
+    let cached = cache.positions(model.config.kv_dim());
+    if pos > cached {
+        return Err(format!("Stage expected position {} or earlier, got {}", cached, pos));
+    }
+
+    let mut hidden = match &request.input {
+        StageInput::Token(token) => engine::embed(model, source, *token)?,
+        StageInput::Hidden(hidden) => hidden.clone(),
+    };
+    engine::forward_stage(model, source, &mut hidden, pos, &mut cache.caches)?;
+    cache.updated_at = now;
+
+    if !model.is_last_stage() {
+        return Ok(StageOutput::Hidden(hidden));
+    }
+    match &request.sample {
+        None => Ok(StageOutput::Cached),
+        Some(params) => {
+            let mut rng = params.seed ^ (pos as u64).wrapping_mul(0x9e3779b97f4a7c15);
+            engine::next_token(model, source, &hidden, params.temperature, params.top_p, &mut rng).map(StageOutput::Token)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{test_model, InferenceState};
+
+    fn step(session: &str, position: usize, input: StageInput, sample: bool) -> StageRequest {
+        StageRequest {
+            session_id: session.into(),
+            position: position as u32,
+            input,
+            sample: sample.then_some(SamplingParams { temperature: 0.0, top_p: 1.0, seed: 1 }),
+        }
+    }
+
+    #[test]
+    fn test_two_stage_pipeline_matches_single_canister_generation() {
+        let file = test_model::build();
+        let whole = Model::load(&file).unwrap();
+        let first = Model::load_layers(&file, 0, Some(1)).unwrap();
+        let last = Model::load_layers(&file, 1, None).unwrap();
+        assert!(first.is_first_stage() && !first.is_last_stage() && last.is_last_stage());
+        assert!(Model::load_layers(&file, 1, Some(3)).is_err());
+
+        let prompt = whole.vocab.encode("hello world");
+        let mut state = InferenceState::new(&whole.config, prompt.clone(), 4, 0.0, 1.0, 0).unwrap();
+        let expected = engine::run(&whole, &file, &mut state, &mut || true).unwrap();
+
+        let (mut c0, mut c1) = (StageCache::default(), StageCache::default());
+        let mut tokens = prompt.clone();
+        let mut generated = Vec::new();
+        let mut pos = 0;
+        while generated.len() < expected.len() {
+            let reading_prompt = pos + 1 < tokens.len();
+            let hidden = match run_stage(&first, &file, &mut c0, &step("s", pos, StageInput::Token(tokens[pos]), false), 1).unwrap() {
+                StageOutput::Hidden(h) => h,
+                other => panic!("unexpected {:?}", other),
+            };
+            // Retrying the last stage's step leaves its cache and answer unchanged
+            let request = step("s", pos, StageInput::Hidden(hidden), !reading_prompt);
+            let out = run_stage(&last, &file, &mut c1, &request, 1).unwrap();
+            assert_eq!(run_stage(&last, &file, &mut c1, &request, 2).unwrap(), out);
+            match out {
+                StageOutput::Cached => assert!(reading_prompt),
+                StageOutput::Token(t) => {
+                    tokens.push(t);
+                    generated.push(t);
+                }
+                StageOutput::Hidden(_) => panic!("last stage returned a hidden state"),
+            }
+            pos += 1;
+        }
+        assert_eq!(generated, expected);
+        assert_eq!(c1.positions(whole.config.kv_dim()), pos);
+        assert_eq!(StageCache::from_bytes(c1.to_bytes()), c1);
+
+        // Steps cannot skip ahead, and stages reject inputs meant for another stage
+        assert!(run_stage(&first, &file, &mut c0, &step("s", pos + 1, StageInput::Token(2), false), 3).is_err());
+        assert!(run_stage(&last, &file, &mut StageCache::default(), &step("t", 0, StageInput::Token(2), true), 3).is_err());
+    }
+}
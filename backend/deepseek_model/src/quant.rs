@@ -0,0 +1,334 @@
+//! GGML Tensor Formats
+//!
+//! Block layouts and dequantization for the tensor types found in Q4_K_M and Q8_0
+//! GGUF files (Q4_K_M mixes Q4_K with Q6_K for a few sensitive tensors):
+//! - F32 / F16: plain little-endian floats
+//! - Q8_0: blocks of 32 weights, one f16 scale + 32 signed bytes
+//! - Q4_K: super-blocks of 256 weights, 8 sub-blocks with 6-bit scales and mins
+//! - Q6_K: super-blocks of 256 weights, 16 sub-blocks with 8-bit scales
+//!
+//! Rows are dequantized one at a time and dotted with the input vector, so a
+//! matmul never materializes a full f32 weight matrix.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GgmlType {
+    F32,
+    F16,
+    Q8_0,
+    Q4K,
+    Q6K,
+}
+
+impl GgmlType {
+    pub fn from_id(id: u32) -> Result<Self, String> {
+        match id {
+            0 => Ok(GgmlType::F32),
+            1 => Ok(GgmlType::F16),
+            8 => Ok(GgmlType::Q8_0),
+            12 => Ok(GgmlType::Q4K),
+            14 => Ok(GgmlType::Q6K),
+            other => Err(format!("Unsupported GGML tensor type {}", other)),
+        }
+    }
+
+    pub fn id(self) -> u32 {
+        match self {
+            GgmlType::F32 => 0,
+            GgmlType::F16 => 1,
+            GgmlType::Q8_0 => 8,
+            GgmlType::Q4K => 12,
+            GgmlType::Q6K => 14,
+        }
+    }
+
+    /// Weights per block
+    pub fn block_len(self) -> usize {
+        match self {
+            GgmlType::F32 | GgmlType::F16 => 1,
+            GgmlType::Q8_0 => 32,
+            GgmlType::Q4K | GgmlType::Q6K => 256,
+        }
+    }
+
+    /// Bytes per block
+    pub fn block_bytes(self) -> usize {
+        match self {
+            GgmlType::F32 => 4,
+            GgmlType::F16 => 2,
+            GgmlType::Q8_0 => 34,
+            GgmlType::Q4K => 144,
+            GgmlType::Q6K => 210,
+        }
+    }
+
+    /// Bytes of one row of `n` weights (`n` must be a multiple of the block length)
+    pub fn row_bytes(self, n: usize) -> Result<usize, String> {
+        if !n.is_multiple_of(self.block_len()) {
+            return Err(format!("Row length {} is not a multiple of {:?} block size {}", n, self, self.block_len()));
+        }
+        Ok(n / self.block_len() * self.block_bytes())
+    }
+}
+
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exp = ((bits >> 10) & 0x1f) as u32;
+    let mant = (bits & 0x3ff) as u32;
+    let value = match (exp, mant) {
+        (0, 0) => sign,
+        (0, _) => {
+            // Subnormal: renormalize the mantissa
+            let shift = mant.leading_zeros() - 21;
+            sign | ((127 - 15 + 1 - shift) << 23) | (((mant << shift) & 0x3ff) << 13)
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mant << 13),
+        _ => sign | ((exp + 127 - 15) << 23) | (mant << 13),
+    };
+    f32::from_bits(value)
+}
+
+/// Round-to-nearest f32 -> f16 (used to write test fixtures and by quantizers)
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x7f_ffff;
+    if exp == 0xff {
+        return sign | 0x7c00 | if mant != 0 { 0x200 } else { 0 };
+    }
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        if e < -10 {
+            return sign;
+        }
+        let m = (mant | 0x80_0000) >> (1 - e);
+        return sign | ((m + 0x1000) >> 13) as u16;
+    }
+    let rounded = ((e as u32) << 10 | (mant >> 13)) + ((mant >> 12) & 1);
+    sign | rounded as u16
+}
+
+fn read_f16(bytes: &[u8], at: usize) -> f32 {
+    f16_to_f32(u16::from_le_bytes([bytes[at], bytes[at + 1]]))
+}
+
+/// Q4_K packs eight 6-bit (scale, min) pairs into 12 bytes
+fn scale_min_k4(j: usize, q: &[u8]) -> (f32, f32) {
+    if j < 4 {
+        ((q[j] & 63) as f32, (q[j + 4] & 63) as f32)
+    } else {
+        (
+            ((q[j + 4] & 0x0f) | ((q[j - 4] >> 6) << 4)) as f32,
+            ((q[j + 4] >> 4) | ((q[j] >> 6) << 4)) as f32,
+        )
+    }
+}
+
+/// Dequantize one row stored in `bytes` into `out` (whose length is the row length)
+pub fn dequantize_row(ty: GgmlType, bytes: &[u8], out: &mut [f32]) {
+    match ty {
+        GgmlType::F32 => {
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = f32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+            }
+        }
+        GgmlType::F16 => {
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = read_f16(bytes, i * 2);
+            }
+        }
+        GgmlType::Q8_0 => {
+            for (block, out) in bytes.chunks_exact(34).zip(out.chunks_exact_mut(32)) {
+                let d = read_f16(block, 0);
+                for (o, q) in out.iter_mut().zip(&block[2..]) {
+                    *o = d * (*q as i8) as f32;
+                }
+            }
+        }
+        GgmlType::Q4K => {
+            for (block, out) in bytes.chunks_exact(144).zip(out.chunks_exact_mut(256)) {
+                let d = read_f16(block, 0);
+                let dmin = read_f16(block, 2);
+                let scales = &block[4..16];
+                let qs = &block[16..144];
+                for (group, out) in out.chunks_exact_mut(64).enumerate() {
+                    let (sc1, m1) = scale_min_k4(group * 2, scales);
+                    let (sc2, m2) = scale_min_k4(group * 2 + 1, scales);
+                    let q = &qs[group * 32..group * 32 + 32];
+                    for l in 0..32 {
+                        out[l] = d * sc1 * (q[l] & 0x0f) as f32 - dmin * m1;
+                        out[l + 32] = d * sc2 * (q[l] >> 4) as f32 - dmin * m2;
+                    }
+                }
+            }
+        }
+        GgmlType::Q6K => {
+            for (block, out) in bytes.chunks_exact(210).zip(out.chunks_exact_mut(256)) {
+                let d = read_f16(block, 208);
+                for half in 0..2 {
+                    let ql = &block[half * 64..half * 64 + 64];
+                    let qh = &block[128 + half * 32..128 + half * 32 + 32];
+                    let sc = &block[192 + half * 8..192 + half * 8 + 8];
+                    let out = &mut out[half * 128..half * 128 + 128];
+                    for l in 0..32 {
+                        let is = l / 16;
+                        let q1 = ((ql[l] & 0x0f) | ((qh[l] & 3) << 4)) as i32 - 32;
+                        let q2 = ((ql[l + 32] & 0x0f) | (((qh[l] >> 2) & 3) << 4)) as i32 - 32;
+                        let q3 = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i32 - 32;
+                        let q4 = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i32 - 32;
+                        out[l] = d * (sc[is] as i8) as f32 * q1 as f32;
+                        out[l + 32] = d * (sc[is + 2] as i8) as f32 * q2 as f32;
+                        out[l + 64] = d * (sc[is + 4] as i8) as f32 * q3 as f32;
+                        out[l + 96] = d * (sc[is + 6] as i8) as f32 * q4 as f32;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// y = W x for a row-major weight matrix of `rows` x `x.len()` stored as `ty`
+pub fn matvec(ty: GgmlType, bytes: &[u8], rows: usize, x: &[f32]) -> Result<Vec<f32>, String> {
+    let row_bytes = ty.row_bytes(x.len())?;
+    if bytes.len() < rows * row_bytes {
+        return Err(format!("Tensor data too short: {} bytes for {} rows of {}", bytes.len(), rows, row_bytes));
+    }
+    let mut row = vec![0.0f32; x.len()];
+    Ok((0..rows)
+        .map(|r| {
+            dequantize_row(ty, &bytes[r * row_bytes..(r + 1) * row_bytes], &mut row);
+            row.iter().zip(x).map(|(w, v)| w * v).sum()
+        })
+        .collect())
+}
+
+/// Quantize a row to Q8_0
+pub fn quantize_q8_0(row: &[f32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(row.len() / 32 * 34);
+    for block in row.chunks(32) {
+        let amax = block.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+        let d = amax / 127.0;
+        let inv = if d > 0.0 { 1.0 / d } else { 0.0 };
+        out.extend_from_slice(&f32_to_f16(d).to_le_bytes());
+        out.extend(block.iter().map(|v| (v * inv).round() as i8 as u8));
+    }
+    out
+}
+
+/// Quantize a row to Q4_K (per-sub-block affine fit, 6-bit scales and mins)
+pub fn quantize_q4_k(row: &[f32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(row.len() / 256 * 144);
+    for block in row.chunks(256) {
+        // Affine parameters per 32-weight sub-block: w ~ scale * q - min, q in 0..=15
+        let mut scales = [0.0f32; 8];
+        let mut mins = [0.0f32; 8];
+        for (j, sub) in block.chunks(32).enumerate() {
+            let lo = sub.iter().fold(f32::MAX, |m, v| m.min(*v)).min(0.0);
+            let hi = sub.iter().fold(f32::MIN, |m, v| m.max(*v));
+            scales[j] = (hi - lo) / 15.0;
+            mins[j] = -lo;
+        }
+        let max_scale = scales.iter().fold(0.0f32, |m, v| m.max(*v));
+        let max_min = mins.iter().fold(0.0f32, |m, v| m.max(*v));
+        let d = max_scale / 63.0;
+        let dmin = max_min / 63.0;
+        let d16 = f32_to_f16(d);
+        let dmin16 = f32_to_f16(dmin);
+        let (d, dmin) = (f16_to_f32(d16), f16_to_f32(dmin16));
+
+        let mut ls = [0u8; 8];
+        let mut lm = [0u8; 8];
+        for j in 0..8 {
+            ls[j] = if d > 0.0 { (scales[j] / d).round().clamp(0.0, 63.0) as u8 } else { 0 };
+            lm[j] = if dmin > 0.0 { (mins[j] / dmin).round().clamp(0.0, 63.0) as u8 } else { 0 };
+        }
+        let mut packed = [0u8; 12];
+        for j in 0..8 {
+            if j < 4 {
+                packed[j] = ls[j];
+                packed[j + 4] = lm[j];
+            } else {
+                packed[j + 4] = (ls[j] & 0x0f) | ((lm[j] & 0x0f) << 4);
+                packed[j - 4] |= (ls[j] >> 4) << 6;
+                packed[j] |= (lm[j] >> 4) << 6;
+            }
+        }
+
+        let mut q = [0u8; 256];
+        for (j, sub) in block.chunks(32).enumerate() {
+            let scale = d * ls[j] as f32;
+            let min = dmin * lm[j] as f32;
+            for (l, v) in sub.iter().enumerate() {
+                q[j * 32 + l] = if scale > 0.0 { ((v + min) / scale).round().clamp(0.0, 15.0) as u8 } else { 0 };
+            }
+        }
+
+        out.extend_from_slice(&d16.to_le_bytes());
+        out.extend_from_slice(&dmin16.to_le_bytes());
+        out.extend_from_slice(&packed);
+        for group in 0..4 {
+            for l in 0..32 {
+                out.push(q[group * 64 + l] | (q[group * 64 + 32 + l] << 4));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wave(n: usize, seed: f32) -> Vec<f32> {
+        (0..n).map(|i| ((i as f32 * 0.37 + seed).sin() * 0.8) + (i % 7) as f32 * 0.01).collect()
+    }
+
+    fn max_error(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).fold(0.0, |m, (x, y)| m.max((x - y).abs()))
+    }
+
+    #[test]
+    fn test_f16_roundtrip() {
+        for v in [0.0f32, 1.0, -2.5, 2f32.powi(-14), 65504.0, 1365.0 / 4096.0] {
+            assert_eq!(f16_to_f32(f32_to_f16(v)), v);
+        }
+        assert!((f16_to_f32(f32_to_f16(0.1)) - 0.1).abs() < 1e-4);
+        assert!(f16_to_f32(0x0001) > 0.0); // Smallest subnormal
+    }
+
+    #[test]
+    fn test_q8_0_and_q4_k_dequantize_close_to_original() {
+        let row = wave(512, 0.3);
+        let mut out = vec![0.0; 512];
+
+        let q8 = quantize_q8_0(&row);
+        assert_eq!(q8.len(), GgmlType::Q8_0.row_bytes(512).unwrap());
+        dequantize_row(GgmlType::Q8_0, &q8, &mut out);
+        assert!(max_error(&row, &out) < 0.01);
+
+        let q4 = quantize_q4_k(&row);
+        assert_eq!(q4.len(), GgmlType::Q4K.row_bytes(512).unwrap());
+        dequantize_row(GgmlType::Q4K, &q4, &mut out);
+        assert!(max_error(&row, &out) < 0.12, "q4_k error {}", max_error(&row, &out));
+    }
+
+    #[test]
+    fn test_q6_k_layout() {
+        // d = 0.5, all sub-block scales 2, every quant at 32 + 3 => weight 3.0
+        let mut block = vec![0u8; 210];
+        block[..128].iter_mut().for_each(|b| *b = 0x33);
+        block[128..192].iter_mut().for_each(|b| *b = 0b1010_1010);
+        block[192..208].iter_mut().for_each(|b| *b = 2);
+        block[208..210].copy_from_slice(&f32_to_f16(0.5).to_le_bytes());
+        let mut out = vec![0.0; 256];
+        dequantize_row(GgmlType::Q6K, &block, &mut out);
+        assert!(out.iter().all(|v| *v == 3.0), "{:?}", &out[..8]);
+
+        let y = matvec(GgmlType::Q6K, &block, 1, &vec![1.0; 256]).unwrap();
+        assert_eq!(y, vec![768.0]);
+        assert!(GgmlType::Q4K.row_bytes(100).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Chat Templates
+//!
+//! Instruction-tuned models only answer well when the conversation is rendered the
+//! way they were trained, special tokens included. Each model version is assigned
+//! one of the templates below (DeepSeek-R1 by default); `render` turns the system
+//! prompt, context and query into the completion prompt ending at the assistant turn.
+//!
+//! DeepSeek-R1 thinks inside `<think>...</think>` before answering; `split_reasoning`
+//! separates that from the final answer.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChatTemplate {
+    #[default]
+    DeepSeek, // DeepSeek-R1 and its distills
+    Qwen,   // ChatML with Qwen's default system prompt
+    Llama3,
+    ChatMl,
+    Plain, // "System: ...\nUser: ...\n\nAssistant:" for base models
+}
+
+impl Storable for ChatTemplate {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    /// Context roles are free text; anything unrecognised is treated as the user
+    pub fn parse(role: &str) -> Self {
+        match role.trim().to_ascii_lowercase().as_str() {
+            "system" => Role::System,
+            "assistant" | "bot" | "model" | "ai" => Role::Assistant,
+            _ => Role::User,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+const DEEPSEEK_BOS: &str = "<｜begin▁of▁sentence｜>";
+const DEEPSEEK_EOS: &str = "<｜end▁of▁sentence｜>";
+const QWEN_DEFAULT_SYSTEM: &str = "You are Qwen, created by Alibaba Cloud. You are a helpful assistant.";
+
+impl ChatTemplate {
+    /// Strings that end the assistant's turn
+    pub fn stop_strings(self) -> &'static [&'static str] {
+        match self {
+            ChatTemplate::DeepSeek => &[DEEPSEEK_EOS],
+            ChatTemplate::Qwen | ChatTemplate::ChatMl => &["<|im_end|>", "<|endoftext|>"],
+            ChatTemplate::Llama3 => &["<|eot_id|>", "<|end_of_text|>"],
+            ChatTemplate::Plain => &["\nUser:"],
+        }
+    }
+
+    /// Render a conversation up to the start of the assistant's reply
+    pub fn render(self, system: Option<&str>, messages: &[(Role, &str)], prompt: &str) -> String {
+        let system = system.filter(|s| !s.trim().is_empty());
+        let turns = messages.iter().copied().chain(std::iter::once((Role::User, prompt)));
+        let mut out = String::new();
+
+        match self {
+            ChatTemplate::DeepSeek => {
+                // System text goes straight after BOS; earlier answers drop their reasoning
+                out.push_str(DEEPSEEK_BOS);
+                out.push_str(system.unwrap_or_default());
+                for (role, content) in turns {
+                    match role {
+                        Role::System => out.push_str(content),
+                        Role::User => out.push_str(&format!("<｜User｜>{}", content)),
+                        Role::Assistant => {
+                            out.push_str(&format!("<｜Assistant｜>{}{}", split_reasoning(content).1, DEEPSEEK_EOS))
+                        }
+                    }
+                }
+                out.push_str("<｜Assistant｜><think>\n");
+            }
+            ChatTemplate::Qwen | ChatTemplate::ChatMl => {
+                let system = match self {
+                    ChatTemplate::Qwen => Some(system.unwrap_or(QWEN_DEFAULT_SYSTEM)),
+                    _ => system,
+                };
+                for (role, content) in system.map(|s| (Role::System, s)).into_iter().chain(turns) {
+                    out.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", role.name(), content));
+                }
+                out.push_str("<|im_start|>assistant\n");
+            }
+            ChatTemplate::Llama3 => {
+                out.push_str("<|begin_of_text|>");
+                for (role, content) in system.map(|s| (Role::System, s)).into_iter().chain(turns) {
+                    out.push_str(&format!("<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>", role.name(), content.trim()));
+                }
+                out.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+            }
+            ChatTemplate::Plain => {
+                if let Some(system) = system {
+                    out.push_str(&format!("System: {}\n\n", system));
+                }
+                let mut turns: Vec<(Role, &str)> = turns.collect();
+                let (_, query) = turns.pop().unwrap();
+                for (role, content) in turns {
+                    let name = match role {
+                        Role::System => "System",
+                        Role::User => "User",
+                        Role::Assistant => "Assistant",
+                    };
+                    out.push_str(&format!("{}: {}\n", name, content));
+                }
+                out.push_str(&format!("User: {}\n\nAssistant:", query));
+            }
+        }
+        out
+    }
+
+    /// Cut a completion at the first stop string
+    pub fn truncate_at_stop(self, text: &str) -> String {
+        let end = self.stop_strings().iter().filter_map(|s| text.find(s)).min().unwrap_or(text.len());
+        text[..end].to_string()
+    }
+}
+
+/// Split a completion into (reasoning, answer). Handles the opening `<think>` being
+/// part of the prompt (R1's template prefills it) and reasoning cut off by the token
+/// budget before `</think>`.
+pub fn split_reasoning(text: &str) -> (Option<String>, String) {
+    let trimmed = text.trim_start();
+    if let Some(end) = trimmed.find("</think>") {
+        let reasoning = trimmed[..end].trim_start_matches("<think>").trim();
+        let answer = trimmed[end + "</think>".len()..].trim_start();
+        let reasoning = Some(reasoning.to_string()).filter(|r| !r.is_empty());
+        return (reasoning, answer.to_string());
+    }
+    match trimmed.strip_prefix("<think>") {
+        Some(unfinished) => (Some(unfinished.trim().to_string()), String::new()),
+        None => (None, text.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HISTORY: [(Role, &str); 2] = [(Role::User, "Hi"), (Role::Assistant, "<think>greet</think>Hello!")];
+
+    #[test]
+    fn test_deepseek_template() {
+        let prompt = ChatTemplate::DeepSeek.render(Some("Be brief."), &HISTORY, "2+2?");
+        assert_eq!(
+            prompt,
+            "<｜begin▁of▁sentence｜>Be brief.<｜User｜>Hi<｜Assistant｜>Hello!<｜end▁of▁sentence｜><｜User｜>2+2?<｜Assistant｜><think>\n"
+        );
+    }
+
+    #[test]
+    fn test_chatml_qwen_and_llama3_templates() {
+        assert_eq!(
+            ChatTemplate::ChatMl.render(None, &[], "Hi"),
+            "<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+        let qwen = ChatTemplate::Qwen.render(None, &[], "Hi");
+        assert!(qwen.starts_with("<|im_start|>system\nYou are Qwen"));
+
+        assert_eq!(
+            ChatTemplate::Llama3.render(Some("Sys"), &HISTORY[..1], "Q"),
+            "<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\nSys<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>\n\nHi<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>\n\nQ<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+        assert_eq!(
+            ChatTemplate::Plain.render(Some("S"), &HISTORY[..1], "Q"),
+            "System: S\n\nUser: Hi\nUser: Q\n\nAssistant:"
+        );
+        assert_eq!(Role::parse(" Assistant "), Role::Assistant);
+        assert_eq!(Role::parse("moderator"), Role::User);
+    }
+
+    #[test]
+    fn test_split_reasoning_and_stops() {
+        // Opening tag prefilled by the template
+        assert_eq!(split_reasoning("2 plus 2 is 4.\n</think>\n\nIt is 4."), (Some("2 plus 2 is 4.".into()), "It is 4.".into()));
+        assert_eq!(split_reasoning("<think>hmm</think>Done"), (Some("hmm".into()), "Done".into()));
+        assert_eq!(split_reasoning("<think>still going"), (Some("still going".into()), String::new()));
+        assert_eq!(split_reasoning("No reasoning"), (None, "No reasoning".into()));
+
+        assert_eq!(ChatTemplate::ChatMl.truncate_at_stop("Answer<|im_end|>\n<|im_start|>"), "Answer");
+        assert_eq!(ChatTemplate::DeepSeek.truncate_at_stop("Answer"), "Answer");
+    }
+}
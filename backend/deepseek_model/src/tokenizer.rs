@@ -0,0 +1,510 @@
+//! BPE Tokenizer
+//!
+//! Loaded from the Hugging Face `tokenizer.json` uploaded with a model version, so
+//! token counts, context trimming and on-canister generation use exactly the ids the
+//! model was trained on. Supports both BPE flavours used by the models we serve:
+//! - byte-level (GPT-2 / Qwen2 / DeepSeek-R1-Distill / Llama-3): text is split by the
+//!   pre-tokenizer pattern, bytes are mapped to printable characters, then merged
+//! - SentencePiece-style (Metaspace, optional byte fallback): spaces become `▁`
+//!
+//! Added tokens (`<|im_start|>`, `<｜end▁of▁sentence｜>`, `<think>`, ...) are matched
+//! verbatim before BPE runs. Decoding can skip the ones marked `"special": true`;
+//! the others (R1-Distill's `<think>`/`</think>`) are always kept as text.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+/// Largest tokenizer.json accepted (Qwen2's is ~7MB)
+pub const MAX_TOKENIZER_BYTES: u64 = 32 * 1024 * 1024;
+pub const MAX_VOCAB_SIZE: usize = 1_000_000;
+
+/// Compact parsed tokenizer as stored per model version
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenizerData {
+    pub vocab: Vec<String>,      // Indexed by token id ("" for unused ids)
+    pub merges: Vec<(u32, u32)>, // Token-id pairs, highest priority first
+    pub special_tokens: Vec<u32>, // Added tokens, matched verbatim before BPE
+    pub non_special: Option<Vec<u32>>, // Added tokens marked `special: false` (None when stored before the flag was read)
+    pub byte_level: bool,
+    pub byte_fallback: bool,
+    pub unk: Option<u32>,
+    pub digits_per_piece: u32, // Pre-tokenizer digit grouping (0 = unlimited)
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenizerInfo {
+    pub model_id: String,
+    pub vocab_size: u32,
+    pub merges: u32,
+    pub special_tokens: u32,
+    pub byte_level: bool,
+}
+
+impl Storable for TokenizerData {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl TokenizerData {
+    pub fn info(&self, model_id: &str) -> TokenizerInfo {
+        TokenizerInfo {
+            model_id: model_id.to_string(),
+            vocab_size: self.vocab.len() as u32,
+            merges: self.merges.len() as u32,
+            special_tokens: self.special_tokens.len().saturating_sub(self.non_special.as_ref().map_or(0, Vec::len)) as u32,
+            byte_level: self.byte_level,
+        }
+    }
+}
+
+/// GPT-2 byte-level alphabet: printable bytes map to themselves, the rest to U+0100..
+pub fn byte_to_char(b: u8) -> char {
+    let printable = |c: u8| matches!(c, b'!'..=b'~' | 0xa1..=0xac | 0xae..=0xff);
+    if printable(b) {
+        b as char
+    } else {
+        let rank = (0..b).filter(|c| !printable(*c)).count();
+        char::from_u32(256 + rank as u32).unwrap()
+    }
+}
+
+/// Parse a Hugging Face tokenizer.json with a BPE model
+pub fn parse_tokenizer_json(bytes: &[u8]) -> Result<TokenizerData, String> {
+    let json: Value = serde_json::from_slice(bytes).map_err(|e| format!("Invalid tokenizer.json: {}", e))?;
+    let model = &json["model"];
+    if let Some(kind) = model["type"].as_str() {
+        if kind != "BPE" {
+            return Err(format!("Unsupported tokenizer model {} (only BPE)", kind));
+        }
+    }
+
+    let mut entries: Vec<(String, u32)> = model["vocab"]
+        .as_object()
+        .ok_or("tokenizer.json has no model.vocab")?
+        .iter()
+        .map(|(token, id)| id.as_u64().map(|id| (token.clone(), id as u32)).ok_or(format!("Invalid id for token {}", token)))
+        .collect::<Result<_, String>>()?;
+    let mut special_tokens = Vec::new();
+    let mut non_special = Vec::new();
+    for added in json["added_tokens"].as_array().into_iter().flatten() {
+        let (Some(content), Some(id)) = (added["content"].as_str(), added["id"].as_u64()) else {
+            return Err("Invalid entry in added_tokens".to_string());
+        };
+        entries.push((content.to_string(), id as u32));
+        special_tokens.push(id as u32);
+        if !added["special"].as_bool().unwrap_or(true) {
+            non_special.push(id as u32);
+        }
+    }
+
+    let size = entries.iter().map(|(_, id)| *id as usize + 1).max().unwrap_or(0);
+    if size == 0 || size > MAX_VOCAB_SIZE {
+        return Err(format!("Vocabulary size must be between 1 and {}", MAX_VOCAB_SIZE));
+    }
+    let mut vocab = vec![String::new(); size];
+    let mut ids = HashMap::new();
+    for (token, id) in entries {
+        ids.insert(token.clone(), id);
+        vocab[id as usize] = token;
+    }
+
+    let id_of = |token: &str| ids.get(token).copied().ok_or(format!("Merge refers to unknown token {:?}", token));
+    let mut merges = Vec::new();
+    for merge in model["merges"].as_array().into_iter().flatten() {
+        let (a, b) = match merge {
+            Value::String(s) => s.split_once(' ').ok_or(format!("Invalid merge {:?}", s))?,
+            Value::Array(pair) if pair.len() == 2 => match (pair[0].as_str(), pair[1].as_str()) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return Err("Invalid merge pair".to_string()),
+            },
+            _ => return Err("Invalid merge entry".to_string()),
+        };
+        id_of(&format!("{}{}", a, b))?;
+        merges.push((id_of(a)?, id_of(b)?));
+    }
+
+    let pre_tokenizer = json["pre_tokenizer"].to_string();
+    let digits_per_piece = if pre_tokenizer.contains("p{N}{1,3}") {
+        3
+    } else if pre_tokenizer.contains("p{N}") && !pre_tokenizer.contains("p{N}+") {
+        1
+    } else {
+        0
+    };
+
+    Ok(TokenizerData {
+        byte_level: pre_tokenizer.contains("\"ByteLevel\"") || json["decoder"].to_string().contains("\"ByteLevel\""),
+        byte_fallback: model["byte_fallback"].as_bool().unwrap_or(false),
+        unk: model["unk_token"].as_str().and_then(|t| ids.get(t).copied()),
+        digits_per_piece,
+        vocab,
+        merges,
+        special_tokens,
+        non_special: Some(non_special),
+    })
+}
+
+/// Split text the way the byte-level pre-tokenizer regex does:
+/// contractions | [^\r\n\p{L}\p{N}]?\p{L}+ | \p{N}{1,k} | ` ?[^\s\p{L}\p{N}]+[\r\n]*` | \s*[\r\n]+ | \s+(?!\S) | \s+
+pub fn split_words(text: &str, digits_per_piece: u32) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let at = |i: usize| chars.get(i).map(|(_, c)| *c);
+    let is_punct = |c: char| !c.is_whitespace() && !c.is_alphabetic() && !c.is_numeric();
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i].1;
+        let mut j = i + 1;
+        let contraction = ["s", "t", "m", "d", "re", "ve", "ll"].iter().find(|s| {
+            let tail: String = chars[i + 1..].iter().take(s.len()).map(|(_, c)| c.to_ascii_lowercase()).collect();
+            tail == **s
+        });
+
+        if let Some(suffix) = contraction.filter(|_| c == '\'') {
+            j = i + 1 + suffix.len();
+        } else if c.is_alphabetic() || (!c.is_numeric() && c != '\r' && c != '\n' && at(i + 1).is_some_and(char::is_alphabetic)) {
+            while at(j).is_some_and(char::is_alphabetic) {
+                j += 1;
+            }
+        } else if c.is_numeric() {
+            while at(j).is_some_and(char::is_numeric) && (digits_per_piece == 0 || j - i < digits_per_piece as usize) {
+                j += 1;
+            }
+        } else if is_punct(c) || (c == ' ' && at(i + 1).is_some_and(is_punct)) {
+            while at(j).is_some_and(is_punct) {
+                j += 1;
+            }
+            while matches!(at(j), Some('\r' | '\n')) {
+                j += 1;
+            }
+        } else {
+            // Whitespace run: through its last newline, else all but the space that
+            // prefixes the following word
+            let mut end = i;
+            while at(end).is_some_and(char::is_whitespace) {
+                end += 1;
+            }
+            let last_newline = (i..end).rev().find(|k| matches!(chars[*k].1, '\r' | '\n'));
+            j = match last_newline {
+                Some(k) => k + 1,
+                None if end < chars.len() && end - i > 1 => end - 1,
+                None => end,
+            };
+        }
+
+        let end = chars.get(j).map(|(b, _)| *b).unwrap_or(text.len());
+        words.push(&text[chars[i].0..end]);
+        i = j;
+    }
+    words
+}
+
+/// A tokenizer ready to encode and decode, with its lookup tables built
+pub struct Tokenizer {
+    data: TokenizerData,
+    ids: HashMap<String, u32>,
+    ranks: HashMap<(u32, u32), (u32, u32)>, // pair -> (rank, merged id)
+    specials: Vec<(String, u32)>,           // Added tokens, longest first
+    added_set: HashSet<u32>,                // Decoded verbatim
+    special_set: HashSet<u32>,              // Added tokens skipped by `decode(.., true)`
+    byte_ids: Vec<Option<u32>>, // Byte-level: id of each byte's character; else `<0xNN>` fallback ids
+    char_bytes: HashMap<char, u8>,
+}
+
+impl Tokenizer {
+    pub fn new(data: TokenizerData) -> Self {
+        let ids: HashMap<String, u32> = data
+            .vocab
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !t.is_empty())
+            .map(|(i, t)| (t.clone(), i as u32))
+            .collect();
+        let ranks = data
+            .merges
+            .iter()
+            .enumerate()
+            .filter_map(|(rank, (a, b))| {
+                let merged = format!("{}{}", data.vocab[*a as usize], data.vocab[*b as usize]);
+                ids.get(&merged).map(|m| ((*a, *b), (rank as u32, *m)))
+            })
+            .collect();
+        let mut specials: Vec<(String, u32)> = data.special_tokens.iter().map(|id| (data.vocab[*id as usize].clone(), *id)).collect();
+        specials.sort_by_key(|(s, _)| std::cmp::Reverse(s.len()));
+        let byte_ids = (0..=255u8)
+            .map(|b| {
+                let token = if data.byte_level { byte_to_char(b).to_string() } else { format!("<0x{:02X}>", b) };
+                ids.get(&token).copied()
+            })
+            .collect();
+        Self {
+            added_set: data.special_tokens.iter().copied().collect(),
+            special_set: data
+                .special_tokens
+                .iter()
+                .copied()
+                .filter(|id| !data.non_special.as_ref().is_some_and(|plain| plain.contains(id)))
+                .collect(),
+            char_bytes: (0..=255u8).map(|b| (byte_to_char(b), b)).collect(),
+            data,
+            ids,
+            ranks,
+            specials,
+            byte_ids,
+        }
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.data.vocab.len()
+    }
+
+    pub fn token_id(&self, token: &str) -> Option<u32> {
+        self.ids.get(token).copied()
+    }
+
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        let mut out = Vec::new();
+        let mut rest = text;
+        let mut at_start = true;
+        while !rest.is_empty() {
+            let next = self
+                .specials
+                .iter()
+                .filter_map(|(s, id)| rest.find(s.as_str()).map(|pos| (pos, s.len(), *id)))
+                .min_by_key(|(pos, len, _)| (*pos, usize::MAX - len));
+            let (plain_len, special) = match next {
+                Some((pos, len, id)) => (pos, Some((len, id))),
+                None => (rest.len(), None),
+            };
+            self.encode_plain(&rest[..plain_len], at_start, &mut out);
+            rest = &rest[plain_len..];
+            if let Some((len, id)) = special {
+                out.push(id);
+                rest = &rest[len..];
+            }
+            at_start = false;
+        }
+        out
+    }
+
+    pub fn count(&self, text: &str) -> u32 {
+        self.encode(text).len() as u32
+    }
+
+    fn encode_plain(&self, text: &str, at_start: bool, out: &mut Vec<u32>) {
+        if text.is_empty() {
+            return;
+        }
+        if self.data.byte_level {
+            for word in split_words(text, self.data.digits_per_piece) {
+                let symbols = word.bytes().filter_map(|b| self.byte_ids[b as usize].or(self.data.unk)).collect();
+                out.extend(self.bpe(symbols));
+            }
+            return;
+        }
+
+        let normalized = format!("{}{}", if at_start { "\u{2581}" } else { "" }, text.replace(' ', "\u{2581}"));
+        let mut word = Vec::new();
+        let mut prev = None;
+        for c in normalized.chars() {
+            // Each word starts at a `▁` that follows other text
+            if c == '\u{2581}' && prev.is_some_and(|p| p != '\u{2581}') {
+                out.extend(self.bpe(std::mem::take(&mut word)));
+            }
+            prev = Some(c);
+            match self.ids.get(c.encode_utf8(&mut [0; 4]) as &str) {
+                Some(id) => word.push(*id),
+                None if self.data.byte_fallback => {
+                    word.extend(c.to_string().bytes().filter_map(|b| self.byte_ids[b as usize]));
+                }
+                None => word.extend(self.data.unk),
+            }
+        }
+        out.extend(self.bpe(word));
+    }
+
+    /// Apply merges lowest rank first until none applies
+    fn bpe(&self, mut symbols: Vec<u32>) -> Vec<u32> {
+        while let Some((_, i, merged)) = symbols
+            .windows(2)
+            .enumerate()
+            .filter_map(|(i, w)| self.ranks.get(&(w[0], w[1])).map(|(rank, merged)| (*rank, i, *merged)))
+            .min()
+        {
+            symbols[i] = merged;
+            symbols.remove(i + 1);
+        }
+        symbols
+    }
+
+    pub fn decode(&self, ids: &[u32], skip_special: bool) -> String {
+        let mut bytes = Vec::new();
+        for id in ids {
+            let Some(token) = self.data.vocab.get(*id as usize) else { continue };
+            if self.added_set.contains(id) {
+                if !(skip_special && self.special_set.contains(id)) {
+                    bytes.extend_from_slice(token.as_bytes());
+                }
+            } else if self.data.byte_level {
+                for c in token.chars() {
+                    match self.char_bytes.get(&c) {
+                        Some(b) => bytes.push(*b),
+                        None => bytes.extend_from_slice(c.to_string().as_bytes()),
+                    }
+                }
+            } else if let Some(b) = token.strip_prefix("<0x").and_then(|t| t.strip_suffix('>')).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                bytes.push(b);
+            } else {
+                bytes.extend_from_slice(token.replace('\u{2581}', " ").as_bytes());
+            }
+        }
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        match text.strip_prefix(' ') {
+            Some(rest) if !self.data.byte_level => rest.to_string(),
+            _ => text,
+        }
+    }
+}
+
+/// How many of the oldest context messages to drop so the prompt fits `budget` tokens,
+/// and the fitted prompt's token count. `count(skip)` counts the prompt rendered
+/// without its first `skip` messages (the system prompt and query are always kept).
+pub fn fit_window(messages: usize, budget: u32, count: impl Fn(usize) -> u32) -> Result<(usize, u32), String> {
+    let full = count(0);
+    if full <= budget {
+        return Ok((0, full));
+    }
+    let minimal = count(messages);
+    if minimal > budget {
+        return Err(format!("Prompt needs {} tokens but the context window allows {}", minimal, budget));
+    }
+    // Dropping messages only shrinks the prompt, so binary search the fewest drops
+    let (mut lo, mut hi, mut fitted) = (0, messages, minimal);
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        let tokens = count(mid);
+        if tokens <= budget {
+            hi = mid;
+            fitted = tokens;
+        } else {
+            lo = mid;
+        }
+    }
+    Ok((hi, fitted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Byte-level tokenizer: 256 byte tokens, a few merges and one special token
+    fn byte_level() -> Tokenizer {
+        let mut vocab = serde_json::Map::new();
+        for b in 0..=255u8 {
+            vocab.insert(byte_to_char(b).to_string(), json!(b));
+        }
+        let merged = ["he", "ll", "hell", "hello", "Ġw", "or", "Ġwor", "Ġworl", "Ġworld"];
+        for (i, t) in merged.iter().enumerate() {
+            vocab.insert(t.to_string(), json!(256 + i));
+        }
+        let tokenizer = json!({
+            "added_tokens": [{ "id": 300, "content": "<|im_end|>", "special": true }],
+            "pre_tokenizer": { "type": "Sequence", "pretokenizers": [
+                { "type": "Split", "pattern": { "Regex": "\\p{N}| ?\\p{L}+" } },
+                { "type": "ByteLevel" }
+            ]},
+            "model": { "type": "BPE", "vocab": vocab, "merges": [
+                "h e", "l l", "he ll", "hell o", "Ġ w", ["o", "r"], "Ġw or", "Ġwor l", "Ġworl d"
+            ]}
+        });
+        Tokenizer::new(parse_tokenizer_json(tokenizer.to_string().as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_byte_level_encode_decode() {
+        let t = byte_level();
+        assert_eq!(t.vocab_size(), 301);
+        assert_eq!(t.data.digits_per_piece, 1);
+        assert_eq!(t.encode("hello world<|im_end|>"), vec![259, 264, 300]);
+        // Bytes past the longest merge stay single-byte tokens
+        assert_eq!(t.encode(" worldly"), vec![264, b'l' as u32, b'y' as u32]);
+
+        let text = "héllo 123 wörld\n\n  ok<|im_end|>";
+        let ids = t.encode(text);
+        assert_eq!(t.decode(&ids, false), text);
+        assert_eq!(t.decode(&ids, true), "héllo 123 wörld\n\n  ok");
+        assert_eq!(t.count("12"), 2);
+    }
+
+    #[test]
+    fn test_decode_keeps_non_special_added_tokens() {
+        let mut vocab = serde_json::Map::new();
+        for b in 0..=255u8 {
+            vocab.insert(byte_to_char(b).to_string(), json!(b));
+        }
+        let tokenizer = json!({
+            "added_tokens": [
+                { "id": 256, "content": "<｜end▁of▁sentence｜>", "special": true },
+                { "id": 257, "content": "<think>", "special": false },
+                { "id": 258, "content": "</think>", "special": false }
+            ],
+            "pre_tokenizer": { "type": "ByteLevel" },
+            "model": { "type": "BPE", "vocab": vocab, "merges": [] }
+        });
+        let t = Tokenizer::new(parse_tokenizer_json(tokenizer.to_string().as_bytes()).unwrap());
+        assert_eq!(t.data.info("r1").special_tokens, 1);
+
+        let ids = t.encode("<think>hm</think>ok<｜end▁of▁sentence｜>");
+        assert_eq!(ids, vec![257, b'h' as u32, b'm' as u32, 258, b'o' as u32, b'k' as u32, 256]);
+        assert_eq!(t.decode(&ids, true), "<think>hm</think>ok");
+        assert_eq!(t.decode(&ids, false), "<think>hm</think>ok<｜end▁of▁sentence｜>");
+    }
+
+    #[test]
+    fn test_split_words_follows_pre_tokenizer() {
+        assert_eq!(split_words("I'm here: 2024!\n\n  ok", 1), vec!["I", "'m", " here", ":", " ", "2", "0", "2", "4", "!\n\n", " ", " ok"]);
+        assert_eq!(split_words("x 12345", 3), vec!["x", " ", "123", "45"]);
+        assert_eq!(split_words("a  b", 0), vec!["a", " ", " b"]);
+    }
+
+    #[test]
+    fn test_metaspace_with_byte_fallback() {
+        let tokenizer = json!({
+            "decoder": { "type": "Sequence", "decoders": [{ "type": "Replace" }, { "type": "ByteFallback" }] },
+            "model": {
+                "type": "BPE", "byte_fallback": true, "unk_token": "<unk>",
+                "vocab": { "<unk>": 0, "<0xC3>": 1, "<0xA9>": 2, "▁": 3, "a": 4, "b": 5, "▁a": 6, "▁ab": 7 },
+                "merges": ["▁ a", "▁a b"]
+            }
+        });
+        let t = Tokenizer::new(parse_tokenizer_json(tokenizer.to_string().as_bytes()).unwrap());
+        assert!(!t.data.byte_level);
+        assert_eq!(t.encode("ab é"), vec![7, 3, 1, 2]);
+        assert_eq!(t.decode(&[7, 3, 1, 2], true), "ab é");
+        assert!(parse_tokenizer_json(b"{\"model\": {\"type\": \"WordPiece\"}}").is_err());
+    }
+
+    #[test]
+    fn test_fit_window_drops_oldest_messages_first() {
+        let message_tokens = [50, 40, 30, 20];
+        let count = |skip: usize| 10 + message_tokens[skip..].iter().sum::<u32>();
+        assert_eq!(fit_window(4, 200, count), Ok((0, 150)));
+        assert_eq!(fit_window(4, 60, count), Ok((2, 60)));
+        assert_eq!(fit_window(4, 59, count), Ok((3, 30)));
+        assert!(fit_window(4, 5, count).is_err());
+    }
+}
@@ -0,0 +1,372 @@
+//! Verified Model Upload
+//!
+//! Admins register a manifest listing every shard's size and SHA-256, then upload
+//! each shard in fixed-size chunks (at most 2MB, the ingress message limit). Chunks
+//! can be sent in any order and re-sent after a failure; `missing_chunks` tells a
+//! client where to resume. Finalizing a shard hashes its chunks and checks them
+//! against the manifest; a corrupt shard is discarded for re-upload.
+//!
+//! A model is `Ready` once every shard is verified. Activation flips a single
+//! stable cell, so inference switches between versions atomically and the previous
+//! version stays intact until it is deleted.
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+
+/// Largest chunk accepted per upload call
+pub const MAX_CHUNK_BYTES: u32 = 2 * 1024 * 1024;
+/// Largest shard; finalizing hashes the whole shard in one message
+pub const MAX_SHARD_BYTES: u64 = 256 * 1024 * 1024;
+pub const MAX_SHARDS: u32 = 512;
+pub const MAX_MODEL_ID_LEN: usize = 64;
+pub const VALID_QUANTIZATIONS: [&str; 6] = ["Q4_K_M", "Q8_0", "Q5_K_M", "Q4_K_S", "F16", "F32"];
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ShardSpec {
+    pub sha256: String, // Lowercase hex
+    pub size: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ModelManifest {
+    pub model_id: String, // e.g. "deepseek-r1-7b-q4km-v2"
+    pub total_shards: u32,
+    pub shards: Vec<ShardSpec>, // Indexed by shard index
+    pub quantization: String,
+    pub chunk_size: u32, // Every chunk but a shard's last is exactly this long
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ShardState {
+    Uploading,
+    Verified,
+    Corrupt(String),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ShardProgress {
+    pub state: ShardState,
+    pub chunks_received: Vec<bool>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ModelStatus {
+    Uploading,
+    Ready, // Every shard verified; may be activated
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ModelRecord {
+    pub manifest: ModelManifest,
+    pub shards: Vec<ShardProgress>,
+    pub status: ModelStatus,
+    pub registered_by: Principal,
+    pub registered_at: u64,
+}
+
+/// The model inference runs against; replaced in one write on activation
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ActiveModel {
+    pub model_id: Option<String>,
+    pub previous_model_id: Option<String>,
+    pub activated_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct UploadStatus {
+    pub model_id: String,
+    pub status: ModelStatus,
+    pub active: bool,
+    pub total_shards: u32,
+    pub shards_verified: u32,
+    pub missing_chunks: Vec<(u32, Vec<u32>)>, // (shard_index, chunk indices still needed)
+    pub corrupt_shards: Vec<(u32, String)>,
+}
+
+/// Stable-map key of one uploaded chunk
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChunkKey {
+    pub model_id: String,
+    pub shard: u32,
+    pub chunk: u32,
+}
+
+impl Storable for ChunkKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut bytes = Vec::with_capacity(8 + self.model_id.len());
+        bytes.extend_from_slice(&self.shard.to_be_bytes());
+        bytes.extend_from_slice(&self.chunk.to_be_bytes());
+        bytes.extend_from_slice(self.model_id.as_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Self {
+            shard: u32::from_be_bytes(bytes[0..4].try_into().unwrap()),
+            chunk: u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+            model_id: String::from_utf8(bytes[8..].to_vec()).unwrap(),
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Bounded {
+            max_size: 8 + MAX_MODEL_ID_LEN as u32,
+            is_fixed_size: false,
+        };
+}
+
+impl Storable for ModelRecord {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for ActiveModel {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+pub fn chunk_count(size: u64, chunk_size: u32) -> u32 {
+    size.div_ceil(chunk_size as u64) as u32
+}
+
+pub fn validate_manifest(manifest: &ModelManifest) -> Result<(), String> {
+    let id = &manifest.model_id;
+    if id.is_empty() || id.len() > MAX_MODEL_ID_LEN
+        || !id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(format!("Model id must be 1-{} characters of [A-Za-z0-9._-]", MAX_MODEL_ID_LEN));
+    }
+    if manifest.total_shards == 0 || manifest.total_shards > MAX_SHARDS {
+        return Err(format!("total_shards must be between 1 and {}", MAX_SHARDS));
+    }
+    if manifest.shards.len() != manifest.total_shards as usize {
+        return Err(format!("Manifest lists {} shards but total_shards is {}", manifest.shards.len(), manifest.total_shards));
+    }
+    if manifest.chunk_size == 0 || manifest.chunk_size > MAX_CHUNK_BYTES {
+        return Err(format!("chunk_size must be between 1 and {} bytes", MAX_CHUNK_BYTES));
+    }
+    if !VALID_QUANTIZATIONS.contains(&manifest.quantization.as_str()) {
+        return Err(format!(
+            "Invalid quantization format: {}. Must be one of: {:?}",
+            manifest.quantization, VALID_QUANTIZATIONS
+        ));
+    }
+    for (index, shard) in manifest.shards.iter().enumerate() {
+        if shard.size == 0 || shard.size > MAX_SHARD_BYTES {
+            return Err(format!("Shard {} size must be between 1 and {} bytes", index, MAX_SHARD_BYTES));
+        }
+        if shard.sha256.len() != 64 || !shard.sha256.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+            return Err(format!("Shard {} sha256 must be 64 lowercase hex characters", index));
+        }
+    }
+    Ok(())
+}
+
+impl ModelManifest {
+    /// Size of the model file the shards concatenate to
+    pub fn total_bytes(&self) -> u64 {
+        self.shards.iter().map(|s| s.size).sum()
+    }
+
+    /// (shard, chunk, offset within the chunk) holding byte `offset` of the model file
+    pub fn locate(&self, mut offset: u64) -> Option<(u32, u32, usize)> {
+        for (index, shard) in self.shards.iter().enumerate() {
+            if offset < shard.size {
+                let chunk_size = self.chunk_size as u64;
+                return Some((index as u32, (offset / chunk_size) as u32, (offset % chunk_size) as usize));
+            }
+            offset -= shard.size;
+        }
+        None
+    }
+}
+
+/// Hex SHA-256 over a shard's chunks in order
+pub fn sha256_hex<T: AsRef<[u8]>>(chunks: impl IntoIterator<Item = T>) -> String {
+    let mut hasher = Sha256::new();
+    for chunk in chunks {
+        hasher.update(chunk.as_ref());
+    }
+    hex::encode(hasher.finalize())
+}
+
+impl ModelRecord {
+    pub fn new(manifest: ModelManifest, registered_by: Principal, now: u64) -> Self {
+        let shards = manifest
+            .shards
+            .iter()
+            .map(|s| ShardProgress {
+                state: ShardState::Uploading,
+                chunks_received: vec![false; chunk_count(s.size, manifest.chunk_size) as usize],
+            })
+            .collect();
+        Self { manifest, shards, status: ModelStatus::Uploading, registered_by, registered_at: now }
+    }
+
+    /// Check a chunk's position and length against the manifest and mark it received
+    pub fn accept_chunk(&mut self, shard: u32, chunk: u32, len: usize) -> Result<(), String> {
+        let spec = self.manifest.shards.get(shard as usize).ok_or_else(|| format!("Shard {} not in manifest", shard))?;
+        let progress = &mut self.shards[shard as usize];
+        if progress.state == ShardState::Verified {
+            return Err(format!("Shard {} is already verified", shard));
+        }
+        let chunks = progress.chunks_received.len() as u32;
+        if chunk >= chunks {
+            return Err(format!("Chunk {} out of range (shard {} has {} chunks)", chunk, shard, chunks));
+        }
+        let chunk_size = self.manifest.chunk_size as u64;
+        let expected = if chunk + 1 == chunks { spec.size - chunk as u64 * chunk_size } else { chunk_size };
+        if len as u64 != expected {
+            return Err(format!("Chunk {} of shard {} must be {} bytes, got {}", chunk, shard, expected, len));
+        }
+        progress.state = ShardState::Uploading;
+        progress.chunks_received[chunk as usize] = true;
+        Ok(())
+    }
+
+    pub fn missing_chunks(&self, shard: u32) -> Vec<u32> {
+        self.shards[shard as usize]
+            .chunks_received
+            .iter()
+            .enumerate()
+            .filter(|(_, received)| !**received)
+            .map(|(i, _)| i as u32)
+            .collect()
+    }
+
+    /// Record the result of hashing an assembled shard
+    pub fn record_verification(&mut self, shard: u32, sha256: &str) -> ShardState {
+        let expected = &self.manifest.shards[shard as usize].sha256;
+        let progress = &mut self.shards[shard as usize];
+        progress.state = if sha256 == expected {
+            ShardState::Verified
+        } else {
+            progress.chunks_received.iter_mut().for_each(|r| *r = false);
+            ShardState::Corrupt(format!("sha256 mismatch: expected {}, got {}", expected, sha256))
+        };
+        if self.shards.iter().all(|s| s.state == ShardState::Verified) {
+            self.status = ModelStatus::Ready;
+        }
+        self.shards[shard as usize].state.clone()
+    }
+
+    pub fn upload_status(&self, active: bool) -> UploadStatus {
+        UploadStatus {
+            model_id: self.manifest.model_id.clone(),
+            status: self.status.clone(),
+            active,
+            total_shards: self.manifest.total_shards,
+            shards_verified: self.shards.iter().filter(|s| s.state == ShardState::Verified).count() as u32,
+            missing_chunks: (0..self.manifest.total_shards)
+                .map(|i| (i, self.missing_chunks(i)))
+                .filter(|(_, missing)| !missing.is_empty())
+                .collect(),
+            corrupt_shards: self.shards.iter().enumerate()
+                .filter_map(|(i, s)| match &s.state {
+                    ShardState::Corrupt(reason) => Some((i as u32, reason.clone())),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(shards: &[&[u8]], chunk_size: u32) -> ModelManifest {
+        ModelManifest {
+            model_id: "tiny-q8-v1".into(),
+            total_shards: shards.len() as u32,
+            shards: shards.iter().map(|s| ShardSpec { sha256: sha256_hex([*s]), size: s.len() as u64 }).collect(),
+            quantization: "Q8_0".into(),
+            chunk_size,
+        }
+    }
+
+    #[test]
+    fn test_manifest_validation() {
+        let good = manifest(&[b"abcdefgh"], 3);
+        assert!(validate_manifest(&good).is_ok());
+
+        let mut bad = good.clone();
+        bad.total_shards = 2;
+        assert!(validate_manifest(&bad).is_err());
+        let mut bad = good.clone();
+        bad.chunk_size = MAX_CHUNK_BYTES + 1;
+        assert!(validate_manifest(&bad).is_err());
+        let mut bad = good.clone();
+        bad.shards[0].sha256 = "ABC".into();
+        assert!(validate_manifest(&bad).is_err());
+        let mut bad = good;
+        bad.model_id = "bad id".into();
+        assert!(validate_manifest(&bad).is_err());
+    }
+
+    #[test]
+    fn test_resumable_upload_and_verification() {
+        let shard0: &[u8] = b"abcdefgh"; // chunks: abc, def, gh
+        let shard1: &[u8] = b"xyz";
+        let mut record = ModelRecord::new(manifest(&[shard0, shard1], 3), Principal::anonymous(), 0);
+
+        // Out of order, wrong length and out of range chunks
+        record.accept_chunk(0, 2, 2).unwrap();
+        assert!(record.accept_chunk(0, 1, 2).is_err());
+        assert!(record.accept_chunk(0, 3, 3).is_err());
+        assert!(record.accept_chunk(2, 0, 3).is_err());
+        assert_eq!(record.missing_chunks(0), vec![0, 1]);
+
+        record.accept_chunk(0, 0, 3).unwrap();
+        record.accept_chunk(0, 1, 3).unwrap();
+        assert_eq!(record.record_verification(0, &sha256_hex([&b"abc"[..], b"def", b"gh"])), ShardState::Verified);
+        assert_eq!(record.status, ModelStatus::Uploading);
+        assert!(record.accept_chunk(0, 0, 3).is_err());
+
+        // A corrupt shard is reset for re-upload
+        record.accept_chunk(1, 0, 3).unwrap();
+        assert!(matches!(record.record_verification(1, &sha256_hex([&b"xyZ"[..]])), ShardState::Corrupt(_)));
+        let status = record.upload_status(false);
+        assert_eq!(status.missing_chunks, vec![(1, vec![0])]);
+        assert_eq!(status.corrupt_shards.len(), 1);
+
+        record.accept_chunk(1, 0, 3).unwrap();
+        assert_eq!(record.record_verification(1, &sha256_hex([shard1])), ShardState::Verified);
+        assert_eq!(record.status, ModelStatus::Ready);
+    }
+
+    #[test]
+    fn test_chunk_key_roundtrip_and_order() {
+        let key = ChunkKey { model_id: "m-1".into(), shard: 3, chunk: 258 };
+        assert_eq!(ChunkKey::from_bytes(key.to_bytes()), key);
+        assert!(ChunkKey { model_id: "m".into(), shard: 0, chunk: 9 } < ChunkKey { model_id: "m".into(), shard: 1, chunk: 0 });
+        assert_eq!(chunk_count(8, 3), 3);
+        assert_eq!(chunk_count(6, 3), 2);
+
+        // Byte offsets of the concatenated model file map onto shard chunks
+        let m = manifest(&[b"abcdefgh", b"xyz"], 3);
+        assert_eq!(m.total_bytes(), 11);
+        assert_eq!(m.locate(7), Some((0, 2, 1)));
+        assert_eq!(m.locate(8), Some((1, 0, 0)));
+        assert_eq!(m.locate(11), None);
+    }
+}
@@ -0,0 +1,323 @@
+//! Lock-and-Mint Bridge (ICP <-> EVM)
+//!
+//! The admin registers one wrapped-token contract per EVM chain
+//! (`set_bridge_target`); owners can only bridge to those.
+//!
+//! Out: the owner calls `bridge_nft`; the ICRC-7 token moves to the canister's
+//! escrow subaccount (`SourceLocked`) and a wrapped copy is minted on the target
+//! chain through the mint job queue (`TargetMinting`, then `Completed` or `Failed`).
+//! A failed mint can be retried, or cancelled to unlock the token.
+//!
+//! Back: the wrapped token's holder burns it through the contract's
+//! `bridgeBurn(tokenId, icpRecipient)`, which emits `BridgeBurn`. Anyone may then
+//! call `complete_bridge_return` with the burn transaction; once its receipt shows
+//! the event in a finalized block, the escrowed token goes to the principal
+//! named in the event. Redeemed burns are remembered across round trips (the wrapped
+//! token id never changes), so re-submitting one is a no-op.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::evm::{keccak256, parse_address, parse_quantity, EvmAdapter, RpcTransport, TxSigner};
+use crate::multichain::{validate_address, BridgeState, BridgeStatus, Chain, ChainConfig, ChainMintConfig, EVMNFTConfig};
+use crate::orchestrator::config_matches;
+
+/// Subaccount of this canister that holds bridged-out tokens
+pub const ESCROW_SUBACCOUNT: [u8; 32] = escrow_subaccount();
+/// `BridgeBurn(uint256 indexed tokenId, address indexed from, bytes32 icpRecipient)`
+pub const BRIDGE_BURN_EVENT: &str = "BridgeBurn(uint256,address,bytes32)";
+/// Block tag a burn must be at or below before the ICP token is released
+pub const BURN_FINALITY_TAG: &str = "finalized";
+
+const fn escrow_subaccount() -> [u8; 32] {
+    let tag = b"nft-bridge-escrow";
+    let mut subaccount = [0u8; 32];
+    let mut i = 0;
+    while i < tag.len() {
+        subaccount[i] = tag[i];
+        i += 1;
+    }
+    subaccount
+}
+
+/// Bridge state of one NFT; the latest crossing in either direction
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BridgeRecord {
+    pub global_id: String,
+    pub token_id: u64,            // ICRC-7 token locked while bridged out
+    pub owner: Principal,         // Who bridged it out
+    pub target: ChainMintConfig,  // Chain and contract of the wrapped token
+    pub recipient: String,        // First holder of the wrapped token
+    pub status: BridgeStatus,
+    pub redeemed_burns: Vec<String>, // Burn transactions already used to return the token
+    pub returned_to: Option<Principal>,
+    pub last_error: Option<String>,
+    pub updated_at: u64,
+}
+
+/// A verified `BridgeBurn` event
+#[derive(Clone, Debug, PartialEq)]
+pub struct BridgeBurn {
+    pub token_id: u128,
+    pub from: String,
+    pub icp_recipient: Principal,
+    pub block_number: u128,
+}
+
+impl BridgeRecord {
+    pub fn new(global_id: &str, token_id: u64, owner: Principal, target: ChainMintConfig, recipient: &str, now: u64) -> Self {
+        Self {
+            global_id: global_id.to_string(),
+            token_id,
+            owner,
+            status: BridgeStatus {
+                source_chain: Chain::ICP,
+                target_chain: target.chain.clone(),
+                status: BridgeState::Initiated,
+                tx_hash: None,
+                started_at: now,
+                completed_at: None,
+            },
+            target,
+            recipient: recipient.to_string(),
+            redeemed_burns: Vec::new(),
+            returned_to: None,
+            last_error: None,
+            updated_at: now,
+        }
+    }
+
+    pub fn set_state(&mut self, state: BridgeState, now: u64) {
+        if let BridgeState::Failed(error) = &state {
+            self.last_error = Some(error.clone());
+        }
+        if matches!(state, BridgeState::Completed | BridgeState::Failed(_)) {
+            self.status.completed_at = Some(now);
+        }
+        self.status.status = state;
+        self.updated_at = now;
+    }
+
+    /// Bridged out from ICP (as opposed to the return leg)
+    pub fn is_outbound(&self) -> bool {
+        self.status.source_chain == Chain::ICP
+    }
+
+    /// The wrapped token exists and the ICP token is waiting in escrow for its burn
+    pub fn awaiting_return(&self) -> bool {
+        self.is_outbound() && matches!(self.status.status, BridgeState::Completed)
+    }
+
+    pub fn is_redeemed(&self, burn_tx_hash: &str) -> bool {
+        self.redeemed_burns.iter().any(|h| h.eq_ignore_ascii_case(burn_tx_hash))
+    }
+
+    /// Record the return leg: burned on the target chain, released on ICP
+    pub fn complete_return(&mut self, burn_tx_hash: &str, recipient: Principal, now: u64) {
+        self.status = BridgeStatus {
+            source_chain: self.target.chain.clone(),
+            target_chain: Chain::ICP,
+            status: BridgeState::Completed,
+            tx_hash: Some(burn_tx_hash.to_string()),
+            started_at: now,
+            completed_at: Some(now),
+        };
+        self.redeemed_burns.push(burn_tx_hash.to_lowercase());
+        self.returned_to = Some(recipient);
+        self.last_error = None;
+        self.updated_at = now;
+    }
+}
+
+/// EVM configuration of a bridge target
+pub fn validate_target(target: &ChainMintConfig) -> Result<&EVMNFTConfig, String> {
+    if !target.chain.is_evm() || !config_matches(&target.chain, &target.config) {
+        return Err(format!("Bridging to {} is not supported", target.chain));
+    }
+    match &target.config {
+        ChainConfig::EVM(config) => validate_address(&target.chain, &config.contract_address).map(|_| config),
+        _ => Err(format!("{} has no EVM configuration", target.chain)),
+    }
+}
+
+/// Principal from a `bytes32` whose first byte is the principal's length
+pub fn decode_icp_recipient(word: &[u8]) -> Result<Principal, String> {
+    let len = *word.first().ok_or("Missing ICP recipient")? as usize;
+    if len == 0 || len > 29 || word.len() < 1 + len || word[1 + len..].iter().any(|b| *b != 0) {
+        return Err("Malformed ICP recipient".to_string());
+    }
+    Principal::try_from_slice(&word[1..1 + len]).map_err(|e| format!("Malformed ICP recipient: {}", e))
+}
+
+fn hex_bytes(value: &Value) -> Result<Vec<u8>, String> {
+    let text = value.as_str().ok_or("Expected hex data")?;
+    hex::decode(text.strip_prefix("0x").unwrap_or(text)).map_err(|_| format!("Invalid hex data {}", text))
+}
+
+/// The `BridgeBurn` of `token_id` emitted by `contract` in a transaction receipt
+pub fn parse_bridge_burn(receipt: &Value, contract: &[u8; 20], token_id: u128) -> Result<BridgeBurn, String> {
+    if parse_quantity(receipt.get("status").unwrap_or(&Value::Null))? != 1 {
+        return Err("Burn transaction reverted".to_string());
+    }
+    let topic = keccak256(BRIDGE_BURN_EVENT.as_bytes());
+    let mut token_topic = [0u8; 32];
+    token_topic[16..].copy_from_slice(&token_id.to_be_bytes());
+
+    let logs = receipt.get("logs").and_then(Value::as_array).ok_or("Receipt has no logs")?;
+    for log in logs {
+        let from_contract = log.get("address").and_then(Value::as_str).map(parse_address) == Some(Ok(*contract));
+        let topics: Vec<Vec<u8>> = log.get("topics").and_then(Value::as_array).into_iter().flatten().map(hex_bytes).collect::<Result<_, _>>()?;
+        if !from_contract || topics.len() != 3 || topics[0] != topic || topics[1] != token_topic {
+            continue;
+        }
+        let data = hex_bytes(log.get("data").unwrap_or(&Value::Null))?;
+        return Ok(BridgeBurn {
+            token_id,
+            from: format!("0x{}", hex::encode(&topics[2][12..])),
+            icp_recipient: decode_icp_recipient(data.get(..32).ok_or("Malformed BridgeBurn data")?)?,
+            block_number: parse_quantity(receipt.get("blockNumber").unwrap_or(&Value::Null))?,
+        });
+    }
+    Err(format!("No BridgeBurn of token {} by {} in the transaction", token_id, crate::evm::to_hex(contract)))
+}
+
+/// Fetch and check a burn; errors leave nothing changed, so callers may simply retry
+pub async fn verify_burn<R: RpcTransport, S: TxSigner>(
+    adapter: &EvmAdapter<R, S>,
+    config: &EVMNFTConfig,
+    tx_hash: &str,
+    token_id: u128,
+) -> Result<BridgeBurn, String> {
+    let receipt = adapter.call(&config.rpc_url, "eth_getTransactionReceipt", json!([tx_hash])).await?;
+    if receipt.is_null() {
+        return Err(format!("Burn transaction {} is not mined yet", tx_hash));
+    }
+    let burn = parse_bridge_burn(&receipt, &parse_address(&config.contract_address)?, token_id)?;
+    let block = adapter.call(&config.rpc_url, "eth_getBlockByNumber", json!([BURN_FINALITY_TAG, false])).await?;
+    check_finalized(&burn, &block)?;
+    Ok(burn)
+}
+
+/// Whether `burn` is in or below `block`, the chain's `BURN_FINALITY_TAG` block; a
+/// burn above it could still be reorganized away
+pub fn check_finalized(burn: &BridgeBurn, block: &Value) -> Result<(), String> {
+    let finalized = match block.get("number") {
+        Some(number) => parse_quantity(number)?,
+        None => return Err(format!("The RPC node has no {} block", BURN_FINALITY_TAG)),
+    };
+    if burn.block_number > finalized {
+        return Err(format!(
+            "Burn in block {} is not {} yet (at block {}); try again later",
+            burn.block_number, BURN_FINALITY_TAG, finalized
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::to_hex;
+    use crate::multichain::NFTStandard;
+
+    const CONTRACT: &str = "0x52908400098527886e0f7030069857d2e4169ee7";
+    const HOLDER: &str = "0x8617e340b3d01fa5f11f306f4090fd50e238070d";
+
+    fn recipient_word(principal: &Principal) -> String {
+        let bytes = principal.as_slice();
+        let mut word = [0u8; 32];
+        word[0] = bytes.len() as u8;
+        word[1..1 + bytes.len()].copy_from_slice(bytes);
+        to_hex(&word)
+    }
+
+    fn burn_receipt(contract: &str, token_id: u128, recipient: &Principal, status: &str) -> Value {
+        let mut token_topic = [0u8; 32];
+        token_topic[16..].copy_from_slice(&token_id.to_be_bytes());
+        let mut holder_topic = [0u8; 32];
+        holder_topic[12..].copy_from_slice(&parse_address(HOLDER).unwrap());
+        json!({
+            "status": status,
+            "blockNumber": "0x64",
+            "logs": [
+                { "address": contract, "topics": [to_hex(&keccak256(b"Transfer(address,address,uint256)"))], "data": "0x" },
+                {
+                    "address": contract,
+                    "topics": [to_hex(&keccak256(BRIDGE_BURN_EVENT.as_bytes())), to_hex(&token_topic), to_hex(&holder_topic)],
+                    "data": recipient_word(recipient),
+                },
+            ],
+        })
+    }
+
+    fn base_target() -> ChainMintConfig {
+        ChainMintConfig {
+            chain: Chain::Base,
+            standard: NFTStandard::ERC721,
+            config: ChainConfig::EVM(EVMNFTConfig {
+                chain: Chain::Base,
+                contract_address: CONTRACT.into(),
+                standard: NFTStandard::ERC721,
+                rpc_url: "https://base.example".into(),
+                chain_id: 8453,
+                gas_price_gwei: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_parse_bridge_burn() {
+        let user = Principal::from_slice(&[10, 1, 2, 3]);
+        let contract = parse_address(CONTRACT).unwrap();
+
+        let burn = parse_bridge_burn(&burn_receipt(CONTRACT, 42, &user, "0x1"), &contract, 42).unwrap();
+        assert_eq!(burn, BridgeBurn { token_id: 42, from: HOLDER.into(), icp_recipient: user, block_number: 100 });
+
+        // Wrong token, another contract's event, or a reverted burn prove nothing
+        assert!(parse_bridge_burn(&burn_receipt(CONTRACT, 43, &user, "0x1"), &contract, 42).is_err());
+        assert!(parse_bridge_burn(&burn_receipt(HOLDER, 42, &user, "0x1"), &contract, 42).is_err());
+        assert!(parse_bridge_burn(&burn_receipt(CONTRACT, 42, &user, "0x0"), &contract, 42).unwrap_err().contains("reverted"));
+
+        // Released only once the finalized block has reached the burn's
+        assert!(check_finalized(&burn, &json!({ "number": "0x63" })).unwrap_err().contains("not finalized"));
+        assert!(check_finalized(&burn, &json!({ "number": "0x64" })).is_ok());
+        assert!(check_finalized(&burn, &Value::Null).is_err());
+
+        let mut bad = [0u8; 32];
+        bad[0] = 30;
+        assert!(decode_icp_recipient(&bad).is_err());
+        assert!(decode_icp_recipient(&[0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let owner = Principal::from_slice(&[10, 1]);
+        let mut record = BridgeRecord::new("g1", 7, owner, base_target(), HOLDER, 1);
+        assert!(record.is_outbound() && !record.awaiting_return());
+
+        record.set_state(BridgeState::TargetMinting, 2);
+        record.set_state(BridgeState::Failed("out of gas".into()), 3);
+        assert_eq!(record.last_error.as_deref(), Some("out of gas"));
+        record.set_state(BridgeState::Completed, 4);
+        assert!(record.awaiting_return());
+        assert_eq!(record.status.completed_at, Some(4));
+
+        let back_to = Principal::from_slice(&[10, 2]);
+        record.complete_return("0xBURN", back_to, 5);
+        assert!(!record.is_outbound() && !record.awaiting_return());
+        assert!(record.is_redeemed("0xburn"));
+        assert_eq!((record.status.source_chain.clone(), record.status.target_chain.clone()), (Chain::Base, Chain::ICP));
+        assert_eq!(record.returned_to, Some(back_to));
+
+        assert!(validate_target(&base_target()).is_ok());
+        let mut bad_contract = base_target();
+        if let ChainConfig::EVM(config) = &mut bad_contract.config {
+            config.contract_address = "0x12".into();
+        }
+        assert!(validate_target(&bad_contract).is_err());
+        let icp = ChainMintConfig { chain: Chain::ICP, standard: NFTStandard::ICRC7, config: ChainConfig::ICP { canister_id: None } };
+        assert!(validate_target(&icp).is_err());
+        assert_eq!(&ESCROW_SUBACCOUNT[..17], b"nft-bridge-escrow");
+    }
+}
@@ -0,0 +1,809 @@
+//! EVM Chain Adapter
+//!
+//! Mints ERC-721 (`safeMint(address,uint256)`) and ERC-1155
+//! (`mint(address,uint256,uint256,bytes)`) tokens from an address controlled by the
+//! canister's threshold ECDSA key. The contract must grant that address its minter
+//! role; `get_evm_minter_address` reports it.
+//!
+//! A submission reads the nonce, gas estimate and fees over JSON-RPC, builds an
+//! EIP-1559 transaction, signs its hash with threshold ECDSA and sends the raw
+//! transaction. Confirmation polls `eth_getTransactionReceipt`. The EVM token id is
+//! derived from the NFT's global id, so a repeated submission reverts instead of
+//! minting a second token.
+//!
+//! Every replica makes the same HTTPS outcall and the responses must agree after
+//! `canonical_response`, which keeps only the fields read here. State is therefore
+//! read at an agreed block, the head rounded down to `BLOCK_ROUNDING`, instead of at
+//! `latest` or `pending`: the nonce is the minter's transaction count there, raised
+//! past the nonces of the canister's other unfinished mints; fees come from
+//! `eth_feeHistory` up to it. A transaction still without a receipt
+//! `DROPPED_AFTER_NS` after its submission counts as dropped and is submitted again
+//! with the same nonce and fresh fees, replacing it if it is still pending.
+
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+
+use crate::multichain::{ChainConfig, ChainCost, ChainMintResult, EVMNFTConfig, MultiChainMetadata, NFTStandard};
+use crate::orchestrator::{ChainAdapter, Confirmation, MintJob, Submission};
+
+/// Threshold ECDSA key used unless the admin configures another
+pub const DEFAULT_KEY_NAME: &str = "key_1";
+/// Derivation path of the minter address (one address on every EVM chain)
+pub const DERIVATION_PATH: &[u8] = b"nft-evm-minter";
+/// Added to the node's gas estimate, in percent
+const GAS_LIMIT_MARGIN_PCT: u128 = 20;
+/// Priority fee when the fee history has no rewards
+const DEFAULT_PRIORITY_FEE_WEI: u128 = 1_500_000_000;
+/// Block heights are read rounded down to a multiple of this, so replicas whose
+/// nodes are a few blocks apart agree on them
+pub const BLOCK_ROUNDING: u128 = 16;
+/// Blocks whose median priority fee is paid
+const FEE_HISTORY_BLOCKS: u128 = 4;
+/// Without a receipt this long after its submission, a transaction counts as dropped
+pub const DROPPED_AFTER_NS: u64 = 15 * 60 * 1_000_000_000;
+const MAX_RPC_RESPONSE_BYTES: u64 = 64 * 1024;
+const RPC_CYCLES: u128 = 10_000_000_000;
+/// Query method that makes JSON-RPC responses identical across replicas
+pub const TRANSFORM_METHOD: &str = "transform_rpc_response";
+
+/// Carries one JSON-RPC request body to a node and returns the response body
+#[allow(async_fn_in_trait)]
+pub trait RpcTransport {
+    async fn post(&self, url: &str, method: &str, body: String) -> Result<String, String>;
+}
+
+/// Holds the minter key
+#[allow(async_fn_in_trait)]
+pub trait TxSigner {
+    /// SEC1-encoded secp256k1 public key
+    async fn public_key(&self) -> Result<Vec<u8>, String>;
+
+    /// 64-byte `r || s` signature of a 32-byte hash
+    async fn sign_hash(&self, hash: [u8; 32]) -> Result<Vec<u8>, String>;
+}
+
+/// Unsigned EIP-1559 (type 2) transaction with an empty access list
+#[derive(Clone, Debug, PartialEq)]
+pub struct Eip1559Tx {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u128,
+    pub to: [u8; 20],
+    pub value: u128,
+    pub data: Vec<u8>,
+}
+
+// === RLP ===
+
+fn rlp_header(out: &mut Vec<u8>, offset: u8, len: usize) {
+    if len < 56 {
+        out.push(offset + len as u8);
+    } else {
+        let len_bytes = trimmed(&(len as u64).to_be_bytes()).to_vec();
+        out.push(offset + 55 + len_bytes.len() as u8);
+        out.extend(len_bytes);
+    }
+}
+
+fn trimmed(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+pub fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut out = Vec::with_capacity(bytes.len() + 9);
+    rlp_header(&mut out, 0x80, bytes.len());
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// Unsigned integer: big-endian without leading zeros (0 is the empty string)
+pub fn rlp_uint(value: u128) -> Vec<u8> {
+    rlp_bytes(trimmed(&value.to_be_bytes()))
+}
+
+/// List of already encoded items
+pub fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let len = items.iter().map(Vec::len).sum();
+    let mut out = Vec::with_capacity(len + 9);
+    rlp_header(&mut out, 0xc0, len);
+    for item in items {
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    Keccak256::digest(bytes).into()
+}
+
+impl Eip1559Tx {
+    fn fields(&self) -> Vec<Vec<u8>> {
+        vec![
+            rlp_uint(self.chain_id as u128),
+            rlp_uint(self.nonce as u128),
+            rlp_uint(self.max_priority_fee_per_gas),
+            rlp_uint(self.max_fee_per_gas),
+            rlp_uint(self.gas_limit),
+            rlp_bytes(&self.to),
+            rlp_uint(self.value),
+            rlp_bytes(&self.data),
+            rlp_list(&[]),
+        ]
+    }
+
+    /// Hash the sender signs: keccak256(0x02 || rlp(fields))
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut payload = vec![0x02];
+        payload.extend(rlp_list(&self.fields()));
+        keccak256(&payload)
+    }
+
+    /// Raw transaction for `eth_sendRawTransaction`
+    pub fn encode_signed(&self, y_parity: u8, r: &[u8], s: &[u8]) -> Vec<u8> {
+        let mut fields = self.fields();
+        fields.push(rlp_uint(y_parity as u128));
+        fields.push(rlp_bytes(trimmed(r)));
+        fields.push(rlp_bytes(trimmed(s)));
+        let mut raw = vec![0x02];
+        raw.extend(rlp_list(&fields));
+        raw
+    }
+}
+
+// === Keys and signatures ===
+
+/// EVM address of a SEC1 public key (compressed or not)
+pub fn evm_address(public_key: &[u8]) -> Result<[u8; 20], String> {
+    let key = VerifyingKey::from_sec1_bytes(public_key).map_err(|e| format!("Invalid public key: {}", e))?;
+    let point = key.to_encoded_point(false);
+    let hash = keccak256(&point.as_bytes()[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    Ok(address)
+}
+
+/// Low-s `(y_parity, r, s)` of a threshold ECDSA signature, which carries no recovery id
+pub fn signature_parts(hash: &[u8; 32], signature: &[u8], public_key: &[u8]) -> Result<(u8, [u8; 32], [u8; 32]), String> {
+    let signature = Signature::from_slice(signature).map_err(|e| format!("Invalid signature: {}", e))?;
+    let signature = signature.normalize_s().unwrap_or(signature);
+    let key = VerifyingKey::from_sec1_bytes(public_key).map_err(|e| format!("Invalid public key: {}", e))?;
+    for parity in 0..2u8 {
+        let id = RecoveryId::from_byte(parity).expect("parity is 0 or 1");
+        if VerifyingKey::recover_from_prehash(hash, &signature, id).is_ok_and(|k| k == key) {
+            let (r, s) = signature.split_bytes();
+            return Ok((parity, r.into(), s.into()));
+        }
+    }
+    Err("Signature does not match the minter key".to_string())
+}
+
+// === Hex and ABI ===
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+pub fn parse_quantity(value: &Value) -> Result<u128, String> {
+    let text = value.as_str().ok_or_else(|| format!("Expected a hex quantity, got {}", value))?;
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u128::from_str_radix(digits, 16).map_err(|e| format!("Invalid quantity {}: {}", text, e))
+}
+
+pub fn to_quantity(value: u128) -> String {
+    format!("0x{:x}", value)
+}
+
+pub fn parse_address(address: &str) -> Result<[u8; 20], String> {
+    let bytes = hex::decode(address.strip_prefix("0x").unwrap_or(address)).map_err(|_| format!("Invalid EVM address {}", address))?;
+    bytes.try_into().map_err(|_| format!("Invalid EVM address {}", address))
+}
+
+fn selector(signature: &str) -> [u8; 4] {
+    let hash = keccak256(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn word(value: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[16..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn address_word(address: &[u8; 20]) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[12..].copy_from_slice(address);
+    word
+}
+
+/// EVM token id of a cross-chain NFT: its global id read as hex, else a hash of it
+pub fn token_id(global_id: &str) -> u128 {
+    u128::from_str_radix(global_id, 16).unwrap_or_else(|_| {
+        let hash = keccak256(global_id.as_bytes());
+        u128::from_be_bytes(hash[..16].try_into().expect("16 bytes"))
+    })
+}
+
+/// Calldata minting `token_id` to `to`
+pub fn mint_calldata(standard: &NFTStandard, to: &[u8; 20], token_id: u128) -> Result<Vec<u8>, String> {
+    let mut data = Vec::with_capacity(4 + 5 * 32);
+    match standard {
+        NFTStandard::ERC721 | NFTStandard::ERC721Avalanche => {
+            data.extend(selector("safeMint(address,uint256)"));
+            data.extend(address_word(to));
+            data.extend(word(token_id));
+        }
+        NFTStandard::ERC1155 => {
+            data.extend(selector("mint(address,uint256,uint256,bytes)"));
+            data.extend(address_word(to));
+            data.extend(word(token_id));
+            data.extend(word(1)); // amount
+            data.extend(word(0x80)); // offset of the empty `data` argument
+            data.extend(word(0));
+        }
+        other => return Err(format!("{:?} is not an EVM token standard", other)),
+    }
+    Ok(data)
+}
+
+/// Amount in the chain's native token (18 decimals), without trailing zeros
+fn format_wei(wei: u128) -> String {
+    let unit = 1_000_000_000_000_000_000u128;
+    let fraction = format!("{:018}", wei % unit);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        (wei / unit).to_string()
+    } else {
+        format!("{}.{}", wei / unit, fraction)
+    }
+}
+
+// === JSON-RPC ===
+
+pub fn rpc_request(method: &str, params: Value) -> String {
+    json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }).to_string()
+}
+
+pub fn rpc_result(body: &str) -> Result<Value, String> {
+    let response: Value = serde_json::from_str(body).map_err(|e| format!("Invalid JSON-RPC response: {}", e))?;
+    if let Some(error) = response.get("error") {
+        let message = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+        return Err(format!("RPC error {}: {}", error.get("code").unwrap_or(&Value::Null), message));
+    }
+    response.get("result").cloned().ok_or_else(|| "JSON-RPC response has no result".to_string())
+}
+
+/// Only `keys` of a JSON object (null stays null)
+fn pick(value: &Value, keys: &[&str]) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+    Value::Object(keys.iter().filter_map(|k| value.get(*k).map(|v| (k.to_string(), v.clone()))).collect())
+}
+
+/// The fields of a method's result that are read here
+fn canonical_result(method: &str, result: &Value) -> Value {
+    match method {
+        "eth_blockNumber" => match parse_quantity(result) {
+            Ok(head) => json!(to_quantity(head - head % BLOCK_ROUNDING)),
+            Err(_) => result.clone(),
+        },
+        // Only a block tag's number is read; rounded down like the head
+        "eth_getBlockByNumber" => match result.get("number").map(parse_quantity) {
+            Some(Ok(number)) => json!({ "number": to_quantity(number - number % BLOCK_ROUNDING) }),
+            _ => pick(result, &["number"]),
+        },
+        "eth_feeHistory" => pick(result, &["baseFeePerGas", "reward"]),
+        "eth_getTransactionReceipt" => {
+            let mut receipt = pick(result, &["transactionHash", "blockNumber", "status", "gasUsed", "effectiveGasPrice"]);
+            if let (Some(receipt), Some(logs)) = (receipt.as_object_mut(), result.get("logs").and_then(Value::as_array)) {
+                let logs = logs.iter().map(|log| pick(log, &["address", "topics", "data"])).collect();
+                receipt.insert("logs".to_string(), Value::Array(logs));
+            }
+            receipt
+        }
+        _ => result.clone(),
+    }
+}
+
+/// Response body every replica agrees on: the result reduced to the fields read
+/// here, or the error's code and message. Replicas each broadcast a sent
+/// transaction, so all but the first node see it as already known; both answers
+/// mean "submitted".
+pub fn canonical_response(method: &str, body: &[u8]) -> Vec<u8> {
+    let text = String::from_utf8_lossy(body);
+    let Ok(response) = serde_json::from_str::<Value>(&text) else {
+        return body.to_vec();
+    };
+    let canonical = match (response.get("result"), response.get("error")) {
+        (_, Some(error)) => {
+            let message = error.get("message").and_then(Value::as_str).unwrap_or_default().to_lowercase();
+            let known = message.contains("already known") || message.contains("known transaction");
+            if method == "eth_sendRawTransaction" && known {
+                json!({ "jsonrpc": "2.0", "id": 1, "result": null })
+            } else {
+                json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": error.get("code"), "message": message } })
+            }
+        }
+        (Some(_), None) if method == "eth_sendRawTransaction" => json!({ "jsonrpc": "2.0", "id": 1, "result": null }),
+        (Some(result), None) => json!({ "jsonrpc": "2.0", "id": 1, "result": canonical_result(method, result) }),
+        (None, None) => return body.to_vec(),
+    };
+    canonical.to_string().into_bytes()
+}
+
+// === Adapter ===
+
+pub struct EvmAdapter<R, S> {
+    rpc: R,
+    signer: S,
+    min_nonce: u64, // Past the nonces held by the canister's other unfinished mints on the chain
+}
+
+fn evm_config(job: &MintJob) -> Result<&EVMNFTConfig, String> {
+    match &job.target.config {
+        ChainConfig::EVM(config) => Ok(config),
+        _ => Err(format!("{} has no EVM configuration", job.target.chain)),
+    }
+}
+
+impl<R: RpcTransport, S: TxSigner> EvmAdapter<R, S> {
+    pub fn new(rpc: R, signer: S) -> Self {
+        Self { rpc, signer, min_nonce: 0 }
+    }
+
+    /// Start new transactions at `nonce` or later
+    pub fn with_min_nonce(mut self, nonce: u64) -> Self {
+        self.min_nonce = nonce;
+        self
+    }
+
+    pub async fn call(&self, url: &str, method: &str, params: Value) -> Result<Value, String> {
+        let body = self.rpc.post(url, method, rpc_request(method, params)).await?;
+        rpc_result(&body).map_err(|e| format!("{}: {}", method, e))
+    }
+
+    /// Minter address on every EVM chain
+    pub async fn address(&self) -> Result<[u8; 20], String> {
+        evm_address(&self.signer.public_key().await?)
+    }
+
+    /// Recent block every replica agrees on: the head rounded down to `BLOCK_ROUNDING`
+    pub async fn agreed_block(&self, url: &str) -> Result<u128, String> {
+        parse_quantity(&self.call(url, "eth_blockNumber", json!([])).await?)
+    }
+
+    /// Transactions of `from` mined up to `block`: its next nonce there
+    async fn mined_nonce(&self, url: &str, from: &str, block: u128) -> Result<u64, String> {
+        let count = parse_quantity(&self.call(url, "eth_getTransactionCount", json!([from, to_quantity(block)])).await?)?;
+        u64::try_from(count).map_err(|_| "Nonce overflow".to_string())
+    }
+
+    /// `(max_priority_fee_per_gas, max_fee_per_gas)`: twice the base fee after `block`
+    /// plus the median tip of the blocks up to it, capped by the configured gas price
+    async fn fees(&self, config: &EVMNFTConfig, block: u128) -> Result<(u128, u128), String> {
+        let history = self.call(&config.rpc_url, "eth_feeHistory", json!([to_quantity(FEE_HISTORY_BLOCKS), to_quantity(block), [50]])).await?;
+        let base_fee = parse_quantity(
+            history.get("baseFeePerGas").and_then(Value::as_array).and_then(|fees| fees.last()).unwrap_or(&Value::Null),
+        )?;
+        let mut tips: Vec<u128> = history.get("reward")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|reward| reward.get(0).and_then(|tip| parse_quantity(tip).ok()))
+            .collect();
+        tips.sort_unstable();
+        let priority = tips.get(tips.len() / 2).copied().unwrap_or(DEFAULT_PRIORITY_FEE_WEI);
+        let max_fee = base_fee.saturating_mul(2).saturating_add(priority);
+        Ok(match config.gas_price_gwei {
+            Some(gwei) => {
+                let cap = gwei as u128 * 1_000_000_000;
+                (priority.min(cap), max_fee.min(cap))
+            }
+            None => (priority, max_fee),
+        })
+    }
+
+    fn receipt_result(&self, job: &MintJob, config: &EVMNFTConfig, receipt: &Value) -> Result<ChainMintResult, String> {
+        let success = parse_quantity(receipt.get("status").unwrap_or(&Value::Null))? == 1;
+        let gas_used = parse_quantity(receipt.get("gasUsed").unwrap_or(&Value::Null))?;
+        let gas_price = parse_quantity(receipt.get("effectiveGasPrice").unwrap_or(&Value::Null)).unwrap_or(0);
+        Ok(ChainMintResult {
+            chain: job.target.chain.clone(),
+            standard: job.target.standard.clone(),
+            success,
+            token_id: success.then(|| token_id(&job.global_id).to_string()),
+            contract_address: Some(config.contract_address.clone()),
+            tx_hash: receipt.get("transactionHash").and_then(Value::as_str).map(str::to_string),
+            error: (!success).then(|| "Mint transaction reverted".to_string()),
+            cost: Some(ChainCost {
+                chain: job.target.chain.clone(),
+                native_amount: format_wei(gas_used.saturating_mul(gas_price)),
+                native_symbol: job.target.chain.native_token().to_string(),
+                usd_equivalent: 0.0,
+            }),
+        })
+    }
+}
+
+impl<R: RpcTransport, S: TxSigner> ChainAdapter for EvmAdapter<R, S> {
+    async fn submit(&self, job: &MintJob, _metadata: &MultiChainMetadata) -> Result<Submission, String> {
+        let config = evm_config(job)?;
+        let contract = parse_address(&config.contract_address)?;
+        let data = mint_calldata(&job.target.standard, &parse_address(&job.recipient)?, token_id(&job.global_id))?;
+        let public_key = self.signer.public_key().await?;
+        let from = to_hex(&evm_address(&public_key)?);
+        let url = &config.rpc_url;
+
+        let block = self.agreed_block(url).await?;
+        let mined = self.mined_nonce(url, &from, block).await?;
+        let nonce = match job.nonce {
+            // A resubmission whose nonce got used meanwhile: one of the earlier
+            // transactions was mined after all, so confirm those again
+            Some(nonce) if mined > nonce => {
+                let earlier = job.replaced_tx_hashes.iter().flatten().last().ok_or("Nonce was used by another transaction")?;
+                return Ok(Submission { tx_hash: earlier.clone(), nonce: Some(nonce) });
+            }
+            Some(nonce) => nonce,
+            None => mined.max(self.min_nonce),
+        };
+        let call = json!({ "from": from, "to": to_hex(&contract), "data": to_hex(&data) });
+        let estimate = parse_quantity(&self.call(url, "eth_estimateGas", json!([call, to_quantity(block)])).await?)?;
+        let (max_priority_fee_per_gas, max_fee_per_gas) = self.fees(config, block).await?;
+        let tx = Eip1559Tx {
+            chain_id: config.chain_id,
+            nonce,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit: estimate + estimate * GAS_LIMIT_MARGIN_PCT / 100,
+            to: contract,
+            value: 0,
+            data,
+        };
+
+        let hash = tx.signing_hash();
+        let signature = self.signer.sign_hash(hash).await?;
+        let (y_parity, r, s) = signature_parts(&hash, &signature, &public_key)?;
+        let raw = tx.encode_signed(y_parity, &r, &s);
+        self.call(url, "eth_sendRawTransaction", json!([to_hex(&raw)])).await?;
+        Ok(Submission { tx_hash: to_hex(&keccak256(&raw)), nonce: Some(nonce) })
+    }
+
+    async fn confirm(&self, job: &MintJob, now: u64) -> Result<Confirmation, String> {
+        let config = evm_config(job)?;
+        let url = &config.rpc_url;
+        let tx_hash = job.tx_hash.as_deref().ok_or("Job has no transaction")?;
+        let receipt = self.call(url, "eth_getTransactionReceipt", json!([tx_hash])).await?;
+        if !receipt.is_null() {
+            return self.receipt_result(job, config, &receipt).map(|result| Confirmation::Confirmed(Box::new(result)));
+        }
+
+        let replaced = job.replaced_tx_hashes.as_deref().unwrap_or_default();
+        let overdue = now.saturating_sub(job.updated_at) >= DROPPED_AFTER_NS;
+        if replaced.is_empty() && !overdue {
+            return Ok(Confirmation::Pending);
+        }
+        let Some(nonce) = job.nonce else {
+            // Submitted before nonces were recorded: wait for the receipt
+            return Ok(Confirmation::Pending);
+        };
+        let from = to_hex(&self.address().await?);
+        let mined = self.mined_nonce(url, &from, self.agreed_block(url).await?).await?;
+        if mined > nonce {
+            // Mined by the agreed block, so every node has the receipt: the nonce went
+            // to a transaction this one replaced
+            for hash in replaced {
+                let receipt = self.call(url, "eth_getTransactionReceipt", json!([hash])).await?;
+                if !receipt.is_null() {
+                    return self.receipt_result(job, config, &receipt).map(|result| Confirmation::Confirmed(Box::new(result)));
+                }
+            }
+            return Err(format!("Nonce {} was used by another transaction", nonce));
+        }
+        Ok(if overdue { Confirmation::Dropped } else { Confirmation::Pending })
+    }
+}
+
+// === IC implementations ===
+
+/// JSON-RPC over HTTPS outcalls
+pub struct HttpOutcall;
+
+impl RpcTransport for HttpOutcall {
+    async fn post(&self, url: &str, method: &str, body: String) -> Result<String, String> {
+        use ic_cdk::api::management_canister::http_request::{
+            http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext,
+        };
+
+        let request = CanisterHttpRequestArgument {
+            url: url.to_string(),
+            method: HttpMethod::POST,
+            body: Some(body.into_bytes()),
+            headers: vec![HttpHeader { name: "Content-Type".to_string(), value: "application/json".to_string() }],
+            max_response_bytes: Some(MAX_RPC_RESPONSE_BYTES),
+            transform: Some(TransformContext::from_name(TRANSFORM_METHOD.to_string(), method.as_bytes().to_vec())),
+        };
+        let (response,) = http_request(request, RPC_CYCLES)
+            .await
+            .map_err(|(code, msg)| format!("HTTP outcall failed: {:?} - {}", code, msg))?;
+        String::from_utf8(response.body).map_err(|_| "Invalid UTF-8 in RPC response".to_string())
+    }
+}
+
+/// The canister's threshold ECDSA key under `DERIVATION_PATH`
+pub struct ThresholdEcdsa {
+    pub key_name: String,
+}
+
+impl ThresholdEcdsa {
+    fn key_id(&self) -> ic_cdk::api::management_canister::ecdsa::EcdsaKeyId {
+        ic_cdk::api::management_canister::ecdsa::EcdsaKeyId {
+            curve: ic_cdk::api::management_canister::ecdsa::EcdsaCurve::Secp256k1,
+            name: self.key_name.clone(),
+        }
+    }
+}
+
+impl TxSigner for ThresholdEcdsa {
+    async fn public_key(&self) -> Result<Vec<u8>, String> {
+        use ic_cdk::api::management_canister::ecdsa::{ecdsa_public_key, EcdsaPublicKeyArgument};
+
+        let request = EcdsaPublicKeyArgument {
+            canister_id: None,
+            derivation_path: vec![DERIVATION_PATH.to_vec()],
+            key_id: self.key_id(),
+        };
+        let (res,) = ecdsa_public_key(request)
+            .await
+            .map_err(|(code, msg)| format!("Failed to get public key: {:?} - {}", code, msg))?;
+        Ok(res.public_key)
+    }
+
+    async fn sign_hash(&self, hash: [u8; 32]) -> Result<Vec<u8>, String> {
+        use ic_cdk::api::management_canister::ecdsa::{sign_with_ecdsa, SignWithEcdsaArgument};
+
+        let request = SignWithEcdsaArgument {
+            message_hash: hash.to_vec(),
+            derivation_path: vec![DERIVATION_PATH.to_vec()],
+            key_id: self.key_id(),
+        };
+        let (res,) = sign_with_ecdsa(request)
+            .await
+            .map_err(|(code, msg)| format!("Failed to sign transaction: {:?} - {}", code, msg))?;
+        Ok(res.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multichain::{Chain, ChainMintConfig};
+    use crate::orchestrator::advance;
+    use crate::multichain::NFTStatus;
+    use k256::ecdsa::signature::hazmat::PrehashSigner;
+    use k256::ecdsa::SigningKey;
+    use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// The stubs never suspend, so a single poll finishes their futures
+    fn block_on<F: Future>(future: F) -> F::Output {
+        match pin!(future).as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("stub future suspended"),
+        }
+    }
+
+    const CONTRACT: &str = "0x52908400098527886e0f7030069857d2e4169ee7";
+    const RECIPIENT: &str = "0x8617e340b3d01fa5f11f306f4090fd50e238070d";
+
+    struct LocalSigner(SigningKey);
+
+    impl TxSigner for LocalSigner {
+        async fn public_key(&self) -> Result<Vec<u8>, String> {
+            Ok(self.0.verifying_key().to_encoded_point(true).as_bytes().to_vec())
+        }
+
+        async fn sign_hash(&self, hash: [u8; 32]) -> Result<Vec<u8>, String> {
+            let signature: Signature = self.0.sign_prehash(&hash).map_err(|e| e.to_string())?;
+            Ok(signature.to_bytes().to_vec())
+        }
+    }
+
+    /// Local JSON-RPC node: answers from canned values and records sent transactions
+    #[derive(Default)]
+    struct StubNode {
+        mined: Cell<u128>, // Minter transactions mined by the agreed block
+        sent: RefCell<Vec<String>>,
+        receipts: RefCell<HashMap<String, Value>>,
+        requests: RefCell<Vec<Value>>,
+    }
+
+    impl RpcTransport for StubNode {
+        async fn post(&self, _url: &str, method: &str, body: String) -> Result<String, String> {
+            let request: Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(request["method"], method);
+            self.requests.borrow_mut().push(request.clone());
+            let params = &request["params"];
+            let result = match method {
+                "eth_blockNumber" => json!("0x10"),
+                "eth_getTransactionCount" => json!(to_quantity(self.mined.get())),
+                "eth_estimateGas" => json!("0x186a0"), // 100000
+                "eth_feeHistory" => json!({
+                    "baseFeePerGas": ["0x3b9aca00", "0x77359400"], // 1 gwei, then 2 gwei after the block
+                    "reward": [["0x3b9aca00"], ["0x0"], ["0x77359400"]], // Median 1 gwei
+                }),
+                "eth_sendRawTransaction" => {
+                    let raw = params[0].as_str().unwrap().to_string();
+                    let hash = to_hex(&keccak256(&hex::decode(&raw[2..]).unwrap()));
+                    self.sent.borrow_mut().push(raw);
+                    json!(hash)
+                }
+                "eth_getTransactionReceipt" => self.receipts.borrow().get(params[0].as_str().unwrap()).cloned().unwrap_or(Value::Null),
+                _ => return Ok(json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32601, "message": "method not found" } }).to_string()),
+            };
+            Ok(json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string())
+        }
+    }
+
+    fn job(standard: NFTStandard) -> MintJob {
+        let config = EVMNFTConfig {
+            chain: Chain::Base,
+            contract_address: CONTRACT.into(),
+            standard: standard.clone(),
+            rpc_url: "http://127.0.0.1:8545".into(),
+            chain_id: 8453,
+            gas_price_gwei: None,
+        };
+        let target = ChainMintConfig { chain: Chain::Base, standard, config: ChainConfig::EVM(config) };
+        MintJob::new("00000000000000000000000000000abc", target, RECIPIENT, 0)
+    }
+
+    #[test]
+    fn test_rlp_and_calldata_encoding() {
+        assert_eq!(rlp_bytes(b"dog"), vec![0x83, b'd', b'o', b'g']);
+        assert_eq!(rlp_list(&[rlp_bytes(b"cat"), rlp_bytes(b"dog")]), hex::decode("c88363617483646f67").unwrap());
+        assert_eq!(rlp_uint(0), vec![0x80]);
+        assert_eq!(rlp_uint(15), vec![0x0f]);
+        assert_eq!(rlp_uint(1024), vec![0x82, 0x04, 0x00]);
+        let long = vec![b'a'; 56];
+        assert_eq!(&rlp_bytes(&long)[..2], &[0xb8, 56]);
+        assert_eq!(rlp_list(&[]), vec![0xc0]);
+
+        let to = parse_address(RECIPIENT).unwrap();
+        let erc721 = mint_calldata(&NFTStandard::ERC721, &to, 0xabc).unwrap();
+        assert_eq!(hex::encode(&erc721[..4]), "a1448194"); // safeMint(address,uint256)
+        assert_eq!(erc721.len(), 4 + 64);
+        assert_eq!(&erc721[16..36], &to);
+        assert_eq!(&erc721[66..], &[0x0a, 0xbc]);
+        let erc1155 = mint_calldata(&NFTStandard::ERC1155, &to, 0xabc).unwrap();
+        assert_eq!((erc1155.len(), erc1155[4 + 95], erc1155[4 + 127]), (4 + 160, 1, 0x80));
+        assert!(mint_calldata(&NFTStandard::SUIObject, &to, 1).is_err());
+
+        assert_eq!(token_id("00000000000000000000000000000abc"), 0xabc);
+        assert_eq!(format_wei(1_500_000_000_000_000_000), "1.5");
+        assert_eq!(format_wei(21_000 * 2_000_000_000), "0.000042");
+    }
+
+    #[test]
+    fn test_signatures_recover_the_minter_address() {
+        let signer = LocalSigner(SigningKey::from_slice(&[7u8; 32]).unwrap());
+        let public_key = block_on(signer.public_key()).unwrap();
+        let address = evm_address(&public_key).unwrap();
+        let uncompressed = signer.0.verifying_key().to_encoded_point(false);
+        assert_eq!(evm_address(uncompressed.as_bytes()).unwrap(), address);
+
+        for nonce in 0..8 {
+            let tx = Eip1559Tx { chain_id: 1, nonce, max_priority_fee_per_gas: 1, max_fee_per_gas: 2, gas_limit: 21_000, to: address, value: 0, data: Vec::new() };
+            let hash = tx.signing_hash();
+            let signature = block_on(signer.sign_hash(hash)).unwrap();
+            let (parity, r, s) = signature_parts(&hash, &signature, &public_key).unwrap();
+            let sig = Signature::from_scalars(r, s).unwrap();
+            assert!(sig.normalize_s().is_none(), "s must be low");
+            let recovered = VerifyingKey::recover_from_prehash(&hash, &sig, RecoveryId::from_byte(parity).unwrap()).unwrap();
+            assert_eq!(evm_address(recovered.to_encoded_point(true).as_bytes()).unwrap(), address);
+        }
+
+        let other = LocalSigner(SigningKey::from_slice(&[9u8; 32]).unwrap());
+        let hash = [1u8; 32];
+        let signature = block_on(other.sign_hash(hash)).unwrap();
+        assert!(signature_parts(&hash, &signature, &public_key).is_err());
+    }
+
+    #[test]
+    fn test_mint_against_stub_node() {
+        let adapter = EvmAdapter::new(StubNode::default(), LocalSigner(SigningKey::from_slice(&[7u8; 32]).unwrap())).with_min_nonce(7);
+        adapter.rpc.mined.set(5);
+        let minter = to_hex(&block_on(adapter.address()).unwrap());
+        let mut job = job(NFTStandard::ERC721);
+        let metadata = crate::orchestrator::tests::metadata();
+
+        block_on(advance(&adapter, &mut job, &metadata, 1));
+        assert_eq!(job.status, NFTStatus::Minting, "{:?}", job.last_error);
+        let raw = adapter.rpc.sent.borrow()[0].clone();
+        let tx_hash = job.tx_hash.clone().unwrap();
+        assert_eq!(tx_hash, to_hex(&keccak256(&hex::decode(&raw[2..]).unwrap())));
+        assert!(raw.starts_with("0x02"));
+
+        // The nonce past other unfinished mints, the gas and the fees end up in the signed transaction
+        let expected = Eip1559Tx {
+            chain_id: 8453,
+            nonce: 7,
+            max_priority_fee_per_gas: 1_000_000_000,
+            max_fee_per_gas: 5_000_000_000,
+            gas_limit: 120_000,
+            to: parse_address(CONTRACT).unwrap(),
+            value: 0,
+            data: mint_calldata(&NFTStandard::ERC721, &parse_address(RECIPIENT).unwrap(), 0xabc).unwrap(),
+        };
+        let requests = adapter.rpc.requests.borrow().clone();
+        assert_eq!(requests[1]["params"], json!([minter, "0x10"]));
+        assert_eq!(job.nonce, Some(7));
+        let hash = expected.signing_hash();
+        let signature = block_on(adapter.signer.sign_hash(hash)).unwrap();
+        let public_key = block_on(adapter.signer.public_key()).unwrap();
+        let (parity, r, s) = signature_parts(&hash, &signature, &public_key).unwrap();
+        assert_eq!(raw, to_hex(&expected.encode_signed(parity, &r, &s)));
+
+        // Unmined: checked again later
+        block_on(advance(&adapter, &mut job, &metadata, 2));
+        assert_eq!(job.status, NFTStatus::Minting);
+
+        // Still unmined long after: dropped, and sent again with the same nonce
+        block_on(advance(&adapter, &mut job, &metadata, 1 + DROPPED_AFTER_NS));
+        assert_eq!((job.status.clone(), job.attempts), (NFTStatus::Pending, 0));
+        block_on(advance(&adapter, &mut job, &metadata, 1 + DROPPED_AFTER_NS));
+        assert_eq!((job.status.clone(), job.nonce), (NFTStatus::Minting, Some(7)));
+        assert_eq!(adapter.rpc.sent.borrow().len(), 2);
+        assert_eq!(adapter.rpc.sent.borrow()[1], raw);
+        assert_eq!(job.tx_hash.as_deref(), Some(tx_hash.as_str()));
+
+        adapter.rpc.receipts.borrow_mut().insert(tx_hash.clone(), json!({
+            "transactionHash": tx_hash,
+            "status": "0x1",
+            "gasUsed": "0x186a0",
+            "effectiveGasPrice": "0xb2d05e00", // 3 gwei
+        }));
+        block_on(advance(&adapter, &mut job, &metadata, 3));
+        assert_eq!(job.status, NFTStatus::Minted);
+        let result = job.result.unwrap();
+        assert_eq!(result.token_id.as_deref(), Some("2748"));
+        assert_eq!(result.contract_address.as_deref(), Some(CONTRACT));
+        let cost = result.cost.unwrap();
+        assert_eq!((cost.native_amount.as_str(), cost.native_symbol.as_str()), ("0.0003", "ETH"));
+
+        // Replicas that see the transaction as already known agree on "submitted"
+        let known = br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"already known"}}"#;
+        assert_eq!(canonical_response("eth_sendRawTransaction", known), canonical_response("eth_sendRawTransaction", br#"{"jsonrpc":"2.0","id":1,"result":"0xab"}"#));
+        let low = br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"nonce too low"}}"#;
+        assert!(rpc_result(std::str::from_utf8(&canonical_response("eth_sendRawTransaction", low)).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_replicas_agree_on_canonical_responses() {
+        let canonical = |method: &str, body: &str| String::from_utf8(canonical_response(method, body.as_bytes())).unwrap();
+        // Nodes a few blocks apart read the same rounded head
+        assert_eq!(canonical("eth_blockNumber", r#"{"jsonrpc":"2.0","id":1,"result":"0x1231"}"#), canonical("eth_blockNumber", r#"{"id":1,"jsonrpc":"2.0","result":"0x123e"}"#));
+        assert_eq!(rpc_result(&canonical("eth_blockNumber", r#"{"jsonrpc":"2.0","id":1,"result":"0x123e"}"#)).unwrap(), json!("0x1230"));
+
+        // Receipts keep only the fields read, logs included
+        let receipt = canonical("eth_getTransactionReceipt", r#"{"jsonrpc":"2.0","id":1,"result":{"status":"0x1","gasUsed":"0x5","blockHash":"0xaa","logs":[{"address":"0x1","topics":[],"data":"0x","logIndex":"0x0"}]}}"#);
+        assert_eq!(rpc_result(&receipt).unwrap(), json!({ "status": "0x1", "gasUsed": "0x5", "logs": [{ "address": "0x1", "topics": [], "data": "0x" }] }));
+        assert_eq!(rpc_result(&canonical("eth_getTransactionReceipt", r#"{"jsonrpc":"2.0","id":1,"result":null}"#)).unwrap(), Value::Null);
+        let history = canonical("eth_feeHistory", r#"{"jsonrpc":"2.0","id":1,"result":{"oldestBlock":"0x1","baseFeePerGas":["0x1"],"gasUsedRatio":[0.5],"reward":[["0x2"]]}}"#);
+        assert_eq!(rpc_result(&history).unwrap(), json!({ "baseFeePerGas": ["0x1"], "reward": [["0x2"]] }));
+        let finalized = canonical("eth_getBlockByNumber", r#"{"jsonrpc":"2.0","id":1,"result":{"number":"0x123e","hash":"0xbb","baseFeePerGas":"0x7"}}"#);
+        assert_eq!(rpc_result(&finalized).unwrap(), json!({ "number": "0x1230" }));
+    }
+}
@@ -0,0 +1,773 @@
+//! ICRC-7, ICRC-37 and ICRC-3
+//!
+//! Tokens are owned by ICRC accounts: a principal plus an optional 32-byte
+//! subaccount. Owners can approve a spender for one token or for all their tokens,
+//! optionally until an expiry, and approved spenders move tokens with
+//! `icrc37_transfer_from`. Token approvals are dropped whenever the token moves;
+//! collection approvals stay with the owner.
+//!
+//! Every mint, transfer, burn, metadata update, approval and revocation is appended
+//! to an ICRC-3 block log. Each block carries the hash of the block before it and the
+//! hash of the last block is certified, so an indexer can verify the whole log from
+//! the tip certificate.
+//!
+//! Updates that set `created_at_time` are deduplicated: sending the same transaction
+//! again within the transaction window returns `Duplicate` with its first block.
+
+use candid::{CandidType, Int, Nat, Principal};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const MAX_QUERY_BATCH_SIZE: usize = 100;
+pub const MAX_UPDATE_BATCH_SIZE: usize = 20;
+pub const DEFAULT_TAKE_VALUE: usize = 100;
+pub const MAX_TAKE_VALUE: usize = 500;
+pub const MAX_MEMO_SIZE: usize = 32;
+pub const MAX_APPROVALS_PER_TOKEN_OR_COLLECTION: usize = 10;
+pub const MAX_REVOKE_APPROVALS: usize = 20;
+pub const MAX_BLOCKS_PER_RESPONSE: u64 = 500;
+pub const TX_WINDOW: u64 = 24 * 60 * 60 * 1_000_000_000; // 24 hours
+pub const PERMITTED_DRIFT: u64 = 2 * 60 * 1_000_000_000; // 2 minutes
+pub const DEDUP_PRUNE_PER_CALL: usize = 50; // Expired dedup keys forgotten per deduplication check
+pub const SUBACCOUNT_LENGTH: usize = 32;
+
+pub const ICRC3_URL: &str = "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-3";
+pub const ICRC7_URL: &str = "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-7/ICRC-7.md";
+pub const ICRC10_URL: &str = "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-10/ICRC-10.md";
+pub const ICRC37_URL: &str = "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-37/ICRC-37.md";
+
+// `GenericError` codes
+pub const ERR_SALE_IN_FLIGHT: u32 = 1;
+pub const ERR_MEMO_TOO_LONG: u32 = 2;
+pub const ERR_TOO_MANY_APPROVALS: u32 = 3;
+pub const ERR_EXPIRY_IN_PAST: u32 = 4;
+pub const ERR_BATCH_TOO_LARGE: u32 = 5;
+pub const ERR_SOULBOUND: u32 = 6;
+pub const ERR_OTHER: u32 = 100;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+impl Account {
+    /// Account with the all-zero (default) subaccount written as none, so equal
+    /// accounts compare equal
+    pub fn new(owner: Principal, subaccount: Option<Vec<u8>>) -> Self {
+        let subaccount = subaccount.filter(|s| s.iter().any(|&b| b != 0));
+        Self { owner, subaccount }
+    }
+
+    pub fn normalized(self) -> Self {
+        Self::new(self.owner, self.subaccount)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.subaccount.as_ref().is_none_or(|s| s.len() == SUBACCOUNT_LENGTH)
+    }
+
+    /// Storage key: length-prefixed principal followed by the 32-byte subaccount
+    pub fn key(&self) -> Vec<u8> {
+        let owner = self.owner.as_slice();
+        let mut key = Vec::with_capacity(1 + owner.len() + SUBACCOUNT_LENGTH);
+        key.push(owner.len() as u8);
+        key.extend_from_slice(owner);
+        match &self.subaccount {
+            Some(subaccount) => key.extend_from_slice(subaccount),
+            None => key.extend_from_slice(&[0; SUBACCOUNT_LENGTH]),
+        }
+        key
+    }
+
+    fn to_value(&self) -> Value {
+        let mut parts = vec![Value::Blob(self.owner.as_slice().to_vec())];
+        if let Some(subaccount) = &self.subaccount {
+            parts.push(Value::Blob(subaccount.clone()));
+        }
+        Value::Array(parts)
+    }
+}
+
+/// ICRC-3 value, used for metadata and log blocks
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Value {
+    Blob(Vec<u8>),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+impl Value {
+    pub fn nat(n: u64) -> Self {
+        Value::Nat(Nat::from(n))
+    }
+
+    pub fn text(s: &str) -> Self {
+        Value::Text(s.to_string())
+    }
+
+    /// Representation-independent hash defined by ICRC-3
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            Value::Blob(bytes) => sha256(bytes),
+            Value::Text(text) => sha256(text.as_bytes()),
+            Value::Nat(n) => {
+                let mut leb = Vec::new();
+                n.encode(&mut leb).expect("writing to a vec cannot fail");
+                sha256(&leb)
+            }
+            Value::Int(i) => {
+                let mut sleb = Vec::new();
+                i.encode(&mut sleb).expect("writing to a vec cannot fail");
+                sha256(&sleb)
+            }
+            Value::Array(items) => {
+                let mut hasher = Sha256::new();
+                for item in items {
+                    hasher.update(item.hash());
+                }
+                hasher.finalize().into()
+            }
+            Value::Map(entries) => {
+                let mut pairs: Vec<Vec<u8>> = entries.iter()
+                    .map(|(key, value)| [sha256(key.as_bytes()), value.hash()].concat())
+                    .collect();
+                pairs.sort();
+                let mut hasher = Sha256::new();
+                for pair in pairs {
+                    hasher.update(pair);
+                }
+                hasher.finalize().into()
+            }
+        }
+    }
+}
+
+// === Transactions and blocks ===
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    Mint { token_id: u64, to: Account },
+    /// `spender` is set for `icrc37_transfer_from`
+    Transfer { token_id: u64, from: Account, to: Account, spender: Option<Account> },
+    /// Token approval, or collection approval without `token_id`
+    Approve { token_id: Option<u64>, from: Account, spender: Account, expires_at: Option<u64> },
+    /// Revocation of one spender's approvals, or of all of them without `spender`
+    Revoke { token_id: Option<u64>, from: Account, spender: Option<Account> },
+    Burn { token_id: u64, from: Account },
+    /// Metadata change by `from`; `meta` is the token's new metadata
+    UpdateToken { token_id: u64, from: Account, meta: Value },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tx {
+    pub op: Op,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+impl Tx {
+    pub fn new(op: Op) -> Self {
+        Self { op, memo: None, created_at_time: None }
+    }
+
+    pub fn btype(&self) -> &'static str {
+        match &self.op {
+            Op::Mint { .. } => "7mint",
+            Op::Transfer { spender: None, .. } => "7xfer",
+            Op::Transfer { .. } => "37xfer",
+            Op::Approve { token_id: Some(_), .. } => "37approve",
+            Op::Approve { .. } => "37approve_coll",
+            Op::Revoke { token_id: Some(_), .. } => "37revoke",
+            Op::Revoke { .. } => "37revoke_coll",
+            Op::Burn { .. } => "7burn",
+            Op::UpdateToken { .. } => "7update_token",
+        }
+    }
+
+    /// The `tx` field of the block
+    pub fn to_value(&self) -> Value {
+        let mut fields: Vec<(&str, Value)> = Vec::new();
+        match &self.op {
+            Op::Mint { token_id, to } => {
+                fields.push(("tid", Value::nat(*token_id)));
+                fields.push(("to", to.to_value()));
+            }
+            Op::Transfer { token_id, from, to, spender } => {
+                fields.push(("tid", Value::nat(*token_id)));
+                fields.push(("from", from.to_value()));
+                fields.push(("to", to.to_value()));
+                if let Some(spender) = spender {
+                    fields.push(("spender", spender.to_value()));
+                }
+            }
+            Op::Approve { token_id, from, spender, expires_at } => {
+                if let Some(token_id) = token_id {
+                    fields.push(("tid", Value::nat(*token_id)));
+                }
+                fields.push(("from", from.to_value()));
+                fields.push(("spender", spender.to_value()));
+                if let Some(expires_at) = expires_at {
+                    fields.push(("exp", Value::nat(*expires_at)));
+                }
+            }
+            Op::Revoke { token_id, from, spender } => {
+                if let Some(token_id) = token_id {
+                    fields.push(("tid", Value::nat(*token_id)));
+                }
+                fields.push(("from", from.to_value()));
+                if let Some(spender) = spender {
+                    fields.push(("spender", spender.to_value()));
+                }
+            }
+            Op::Burn { token_id, from } => {
+                fields.push(("tid", Value::nat(*token_id)));
+                fields.push(("from", from.to_value()));
+            }
+            Op::UpdateToken { token_id, from, meta } => {
+                fields.push(("tid", Value::nat(*token_id)));
+                fields.push(("from", from.to_value()));
+                fields.push(("meta", meta.clone()));
+            }
+        }
+        if let Some(memo) = &self.memo {
+            fields.push(("memo", Value::Blob(memo.clone())));
+        }
+        if let Some(created_at_time) = self.created_at_time {
+            fields.push(("ts", Value::nat(created_at_time)));
+        }
+        Value::Map(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    /// Key under which the transaction is remembered for deduplication: its
+    /// `created_at_time` first, so expired keys sort first, then its hash
+    pub fn dedup_key(&self) -> Option<Vec<u8>> {
+        let created_at_time = self.created_at_time?;
+        Some([&created_at_time.to_be_bytes()[..], &self.to_value().hash()].concat())
+    }
+}
+
+/// Whether a deduplication key has left the window and can be forgotten
+pub fn dedup_expired(key: &[u8], now: u64) -> bool {
+    let created_at_time = u64::from_be_bytes(key[..8].try_into().unwrap_or([0; 8]));
+    created_at_time.saturating_add(TX_WINDOW + PERMITTED_DRIFT) < now
+}
+
+/// Progress of logging the tokens minted before the log existed as mint blocks,
+/// a batch at a time: tokens `next..end` are still to go, except `logged_early`,
+/// whose mint went in ahead of the batches when they first changed
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LogBackfill {
+    pub next: u64,
+    pub end: u64,
+    pub logged_early: Vec<u64>,
+}
+
+impl LogBackfill {
+    pub fn is_pending(&self, token_id: u64) -> bool {
+        (self.next..self.end).contains(&token_id) && !self.logged_early.contains(&token_id)
+    }
+
+    /// Tokens before `next` are logged
+    pub fn advance(&mut self, next: u64) {
+        self.next = next.min(self.end);
+        let next = self.next;
+        self.logged_early.retain(|id| *id >= next);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.end
+    }
+}
+
+/// Block of `tx` appended at `timestamp` after the block whose hash is `parent`
+pub fn block(tx: &Tx, timestamp: u64, parent: Option<[u8; 32]>) -> Value {
+    let mut fields = vec![
+        ("btype".to_string(), Value::text(tx.btype())),
+        ("ts".to_string(), Value::nat(timestamp)),
+        ("tx".to_string(), tx.to_value()),
+    ];
+    if let Some(parent) = parent {
+        fields.push(("phash".to_string(), Value::Blob(parent.to_vec())));
+    }
+    Value::Map(fields)
+}
+
+// === Tip certificate ===
+
+fn domain_hash(domain: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([domain.len() as u8]);
+    hasher.update(domain.as_bytes());
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn cbor_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    match bytes.len() {
+        len @ 0..=23 => out.push(0x40 | len as u8),
+        len @ 24..=255 => out.extend_from_slice(&[0x58, len as u8]),
+        len => {
+            out.push(0x59);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(bytes);
+}
+
+/// Hash tree `{ last_block_hash, last_block_index }` over the log tip: its root hash
+/// is the canister's certified data and its CBOR encoding goes out with the certificate
+pub fn tip_tree(last_block_index: u64, last_block_hash: &[u8; 32]) -> ([u8; 32], Vec<u8>) {
+    let mut index = Vec::new();
+    Nat::from(last_block_index).encode(&mut index).expect("writing to a vec cannot fail");
+    let leaves: [(&str, &[u8]); 2] = [("last_block_hash", last_block_hash), ("last_block_index", &index)];
+
+    let labeled: Vec<[u8; 32]> = leaves.iter()
+        .map(|(label, value)| {
+            let leaf = domain_hash("ic-hashtree-leaf", &[value]);
+            domain_hash("ic-hashtree-labeled", &[label.as_bytes(), &leaf])
+        })
+        .collect();
+    let root = domain_hash("ic-hashtree-fork", &[&labeled[0], &labeled[1]]);
+
+    // Self-described CBOR: fork = [1, l, r], labeled = [2, label, tree], leaf = [3, value]
+    let mut cbor = vec![0xd9, 0xd9, 0xf7, 0x83, 0x01];
+    for (label, value) in leaves {
+        cbor.extend_from_slice(&[0x83, 0x02]);
+        cbor_bytes(&mut cbor, label.as_bytes());
+        cbor.extend_from_slice(&[0x82, 0x03]);
+        cbor_bytes(&mut cbor, value);
+    }
+    (root, cbor)
+}
+
+// === Validation ===
+
+/// Failure shared by the ICRC-7 and ICRC-37 updates, converted into each method's
+/// own error type
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure {
+    NonExistingTokenId,
+    Unauthorized,
+    InvalidRecipient,
+    InvalidSpender,
+    ApprovalDoesNotExist,
+    TooOld,
+    CreatedInFuture(u64),
+    Duplicate(u64),
+    Generic(u32, String),
+    Batch(String),
+}
+
+pub fn check_batch(len: usize, max: usize) -> Result<(), Failure> {
+    if len > max {
+        return Err(Failure::Batch(format!("At most {} requests are accepted per call", max)));
+    }
+    Ok(())
+}
+
+pub fn check_memo(memo: &Option<Vec<u8>>) -> Result<(), Failure> {
+    if memo.as_ref().is_some_and(|m| m.len() > MAX_MEMO_SIZE) {
+        return Err(Failure::Generic(ERR_MEMO_TOO_LONG, format!("Memo exceeds {} bytes", MAX_MEMO_SIZE)));
+    }
+    Ok(())
+}
+
+pub fn check_created_at_time(created_at_time: Option<u64>, now: u64) -> Result<(), Failure> {
+    match created_at_time {
+        Some(t) if t.saturating_add(TX_WINDOW + PERMITTED_DRIFT) < now => Err(Failure::TooOld),
+        Some(t) if t > now.saturating_add(PERMITTED_DRIFT) => Err(Failure::CreatedInFuture(now)),
+        _ => Ok(()),
+    }
+}
+
+pub fn check_recipient(from: &Account, to: &Account) -> Result<(), Failure> {
+    if !to.is_valid() || to.owner == Principal::anonymous() || to == from {
+        return Err(Failure::InvalidRecipient);
+    }
+    Ok(())
+}
+
+/// Checks an approval granted by `from`
+pub fn check_approval(from: &Account, info: &ApprovalInfo, now: u64) -> Result<(), Failure> {
+    if !info.spender.is_valid() || info.spender.owner == Principal::anonymous() || info.spender == *from {
+        return Err(Failure::InvalidSpender);
+    }
+    check_memo(&info.memo)?;
+    check_created_at_time(Some(info.created_at_time), now)?;
+    if info.expires_at.is_some_and(|at| at <= now) {
+        return Err(Failure::Generic(ERR_EXPIRY_IN_PAST, "Expiry must be in the future".to_string()));
+    }
+    Ok(())
+}
+
+/// Number of entries a paginated query returns
+pub fn take(take: Option<Nat>) -> usize {
+    take.and_then(|t| usize::try_from(t.0).ok())
+        .unwrap_or(DEFAULT_TAKE_VALUE)
+        .min(MAX_TAKE_VALUE)
+}
+
+// === ICRC-7 ===
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Account,
+    pub token_id: Nat,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TransferError {
+    NonExistingTokenId,
+    InvalidRecipient,
+    Unauthorized,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SupportedStandard {
+    pub name: String,
+    pub url: String,
+}
+
+// === ICRC-37 ===
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ApprovalInfo {
+    pub spender: Account,
+    pub from_subaccount: Option<Vec<u8>>,
+    pub expires_at: Option<u64>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: u64,
+}
+
+impl ApprovalInfo {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|at| at > now)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ApproveTokenArg {
+    pub token_id: Nat,
+    pub approval_info: ApprovalInfo,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ApproveTokenError {
+    InvalidSpender,
+    Unauthorized,
+    NonExistingTokenId,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ApproveCollectionArg {
+    pub approval_info: ApprovalInfo,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ApproveCollectionError {
+    InvalidSpender,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevokeTokenApprovalArg {
+    pub spender: Option<Account>,
+    pub from_subaccount: Option<Vec<u8>>,
+    pub token_id: Nat,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RevokeTokenApprovalError {
+    ApprovalDoesNotExist,
+    Unauthorized,
+    NonExistingTokenId,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevokeCollectionApprovalArg {
+    pub spender: Option<Account>,
+    pub from_subaccount: Option<Vec<u8>>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RevokeCollectionApprovalError {
+    ApprovalDoesNotExist,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct IsApprovedArg {
+    pub spender: Account,
+    pub from_subaccount: Option<Vec<u8>>,
+    pub token_id: Nat,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenApproval {
+    pub token_id: Nat,
+    pub approval_info: ApprovalInfo,
+}
+
+pub type CollectionApproval = ApprovalInfo;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferFromArg {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub token_id: Nat,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TransferFromError {
+    InvalidRecipient,
+    Unauthorized,
+    NonExistingTokenId,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+/// Storage key of a token approval; all approvals of a token share its prefix
+pub fn token_approval_key(token_id: u64, spender: Option<&Account>) -> Vec<u8> {
+    let mut key = token_id.to_be_bytes().to_vec();
+    if let Some(spender) = spender {
+        key.extend(spender.key());
+    }
+    key
+}
+
+/// Storage key of a collection approval; all approvals of an owner share its prefix
+pub fn collection_approval_key(owner: &Account, spender: Option<&Account>) -> Vec<u8> {
+    let mut key = owner.key();
+    if let Some(spender) = spender {
+        key.extend(spender.key());
+    }
+    key
+}
+
+macro_rules! from_failure {
+    ($error:ident, [$($unit:ident),*] $(, $duplicate:ident)?) => {
+        impl From<Failure> for $error {
+            fn from(failure: Failure) -> Self {
+                match failure {
+                    $(Failure::$unit => $error::$unit,)*
+                    $(Failure::Duplicate(block) => $error::$duplicate { duplicate_of: Nat::from(block) },)?
+                    Failure::TooOld => $error::TooOld,
+                    Failure::CreatedInFuture(ledger_time) => $error::CreatedInFuture { ledger_time },
+                    Failure::Generic(code, message) => $error::GenericError { error_code: Nat::from(code), message },
+                    Failure::Batch(message) => $error::GenericBatchError { error_code: Nat::from(ERR_BATCH_TOO_LARGE), message },
+                    other => $error::GenericError { error_code: Nat::from(ERR_OTHER), message: format!("{:?}", other) },
+                }
+            }
+        }
+    };
+}
+
+from_failure!(TransferError, [NonExistingTokenId, InvalidRecipient, Unauthorized], Duplicate);
+from_failure!(TransferFromError, [NonExistingTokenId, InvalidRecipient, Unauthorized], Duplicate);
+from_failure!(ApproveTokenError, [NonExistingTokenId, InvalidSpender, Unauthorized]);
+from_failure!(ApproveCollectionError, [InvalidSpender]);
+from_failure!(RevokeTokenApprovalError, [NonExistingTokenId, ApprovalDoesNotExist, Unauthorized], Duplicate);
+from_failure!(RevokeCollectionApprovalError, [ApprovalDoesNotExist], Duplicate);
+
+// === ICRC-3 ===
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetBlocksArgs {
+    pub start: Nat,
+    pub length: Nat,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BlockWithId {
+    pub id: Nat,
+    pub block: Value,
+}
+
+candid::define_function!(pub GetArchivedBlocksFn : (Vec<GetBlocksArgs>) -> (GetBlocksResult) query);
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchivedBlocks {
+    pub args: Vec<GetBlocksArgs>,
+    pub callback: GetArchivedBlocksFn,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct GetBlocksResult {
+    pub log_length: Nat,
+    pub blocks: Vec<BlockWithId>,
+    pub archived_blocks: Vec<ArchivedBlocks>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DataCertificate {
+    pub certificate: Vec<u8>,
+    pub hash_tree: Vec<u8>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BlockType {
+    pub block_type: String,
+    pub url: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetArchivesArgs {
+    pub from: Option<Principal>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ArchiveInfo {
+    pub canister_id: Principal,
+    pub start: Nat,
+    pub end: Nat,
+}
+
+pub fn supported_block_types() -> Vec<BlockType> {
+    let icrc7 = ["7mint", "7burn", "7xfer", "7update_token"].map(|t| (t, ICRC7_URL));
+    let icrc37 = ["37approve", "37approve_coll", "37revoke", "37revoke_coll", "37xfer"].map(|t| (t, ICRC37_URL));
+    icrc7.into_iter()
+        .chain(icrc37)
+        .map(|(block_type, url)| BlockType { block_type: block_type.to_string(), url: url.to_string() })
+        .collect()
+}
+
+pub fn supported_standards() -> Vec<SupportedStandard> {
+    [("ICRC-7", ICRC7_URL), ("ICRC-37", ICRC37_URL), ("ICRC-3", ICRC3_URL), ("ICRC-10", ICRC10_URL)]
+        .into_iter()
+        .map(|(name, url)| SupportedStandard { name: name.to_string(), url: url.to_string() })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: u8, subaccount: Option<u8>) -> Account {
+        Account::new(Principal::from_slice(&[id, 1]), subaccount.map(|s| vec![s; SUBACCOUNT_LENGTH]))
+    }
+
+    #[test]
+    fn test_value_hash_is_representation_independent() {
+        // Example from the ICRC-3 specification
+        assert_eq!(
+            hex::encode(Value::nat(42).hash()),
+            "684888c0ebb17f374298b65ee2807526c066094c701bcc7ebbe1c1095f494fc1"
+        );
+        let a = Value::Map(vec![("x".to_string(), Value::nat(1)), ("y".to_string(), Value::text("z"))]);
+        let b = Value::Map(vec![("y".to_string(), Value::text("z")), ("x".to_string(), Value::nat(1))]);
+        assert_eq!(a.hash(), b.hash());
+        assert_ne!(Value::Array(vec![Value::nat(1), Value::nat(2)]).hash(), Value::Array(vec![Value::nat(2), Value::nat(1)]).hash());
+
+        // Blocks chain through their parent's hash
+        let mint = Tx::new(Op::Mint { token_id: 7, to: account(1, None) });
+        let first = block(&mint, 10, None);
+        let second = block(&mint, 10, Some(first.hash()));
+        let Value::Map(fields) = &second else { panic!("block is a map") };
+        assert!(fields.contains(&("phash".to_string(), Value::Blob(first.hash().to_vec()))));
+        assert_ne!(first.hash(), second.hash());
+    }
+
+    #[test]
+    fn test_accounts_and_dedup_keys() {
+        let owner = Principal::from_slice(&[1, 1]);
+        assert_eq!(Account::new(owner, Some(vec![0; 32])), Account::new(owner, None));
+        assert_eq!(Account::new(owner, Some(vec![0; 32])).key(), Account::new(owner, None).key());
+        assert!(!Account::new(owner, Some(vec![1; 3])).is_valid());
+        assert!(token_approval_key(5, Some(&account(2, Some(1)))).starts_with(&token_approval_key(5, None)));
+
+        let mut tx = Tx::new(Op::Transfer { token_id: 1, from: account(1, None), to: account(2, None), spender: None });
+        assert_eq!(tx.btype(), "7xfer");
+        assert_eq!(tx.dedup_key(), None);
+        tx.created_at_time = Some(1_000);
+        let key = tx.dedup_key().unwrap();
+        tx.memo = Some(vec![1]);
+        assert_ne!(tx.dedup_key().unwrap(), key);
+        assert!(!dedup_expired(&key, 1_000 + TX_WINDOW));
+        assert!(dedup_expired(&key, 1_001 + TX_WINDOW + PERMITTED_DRIFT));
+
+        let now = 10 * TX_WINDOW;
+        assert_eq!(check_created_at_time(Some(now - TX_WINDOW - PERMITTED_DRIFT - 1), now), Err(Failure::TooOld));
+        assert_eq!(check_created_at_time(Some(now + PERMITTED_DRIFT + 1), now), Err(Failure::CreatedInFuture(now)));
+        assert!(check_created_at_time(None, now).is_ok());
+        assert_eq!(check_recipient(&account(1, None), &account(1, None)), Err(Failure::InvalidRecipient));
+        assert_eq!(
+            TransferError::from(Failure::Duplicate(3)),
+            TransferError::Duplicate { duplicate_of: Nat::from(3u32) }
+        );
+    }
+
+    #[test]
+    fn test_log_backfill_progress() {
+        let mut backfill = LogBackfill { next: 0, end: 1_000, logged_early: Vec::new() };
+        assert!(backfill.is_pending(0) && backfill.is_pending(999) && !backfill.is_pending(1_000));
+
+        // A token that changes ahead of its batch is logged once
+        backfill.logged_early.push(700);
+        assert!(!backfill.is_pending(700));
+        backfill.advance(500);
+        assert_eq!(backfill.logged_early, vec![700]);
+        assert!(!backfill.is_pending(499) && backfill.is_pending(500) && !backfill.is_done());
+        backfill.advance(2_000);
+        assert!(backfill.is_done() && backfill.logged_early.is_empty());
+        assert!(LogBackfill::default().is_done());
+    }
+
+    #[test]
+    fn test_tip_tree() {
+        let (root, cbor) = tip_tree(300, &[7; 32]);
+        let mut index = Vec::new();
+        Nat::from(300u32).encode(&mut index).unwrap();
+        assert_eq!(index, vec![0xac, 0x02]);
+        let labeled = |label: &str, value: &[u8]| {
+            domain_hash("ic-hashtree-labeled", &[label.as_bytes(), &domain_hash("ic-hashtree-leaf", &[value])])
+        };
+        let expected = domain_hash("ic-hashtree-fork", &[&labeled("last_block_hash", &[7; 32]), &labeled("last_block_index", &index)]);
+        assert_eq!(root, expected);
+        assert!(cbor.starts_with(&[0xd9, 0xd9, 0xf7, 0x83, 0x01, 0x83, 0x02, 0x4f]));
+        assert!(cbor.ends_with(&[0x82, 0x03, 0x42, 0xac, 0x02]));
+    }
+}
@@ -2,10 +2,18 @@
 //! Handles generative NFT minting, transfers, and metadata
 //! Application canisters are set as controllers for all minted NFTs
 
+pub mod bridge;
 pub mod controller;
+pub mod evm;
+pub mod icrc;
+pub mod lifecycle;
+pub mod marketplace;
 pub mod multichain;
+pub mod orchestrator;
+pub mod rarity;
 
 use candid::{CandidType, Decode, Encode, Nat, Principal};
+use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
@@ -16,6 +24,13 @@ use std::cell::RefCell;
 use std::collections::BTreeMap;
 
 use controller::{ControllerConfig, NFTControllerRecord, build_controller_list, is_authorized_controller};
+use multichain::{Chain, ChainTokenId, MultiChainMintRequest, MultiChainNFT, NFTStatus};
+use orchestrator::{AdapterSettings, JobIndexBackfill, MintJob, MultiChainMintStatus};
+use bridge::BridgeRecord;
+use icrc::{Account, ApprovalInfo, Failure, LogBackfill, Op, Tx, Value};
+use lifecycle::{MetadataVersion, Tombstone};
+use rarity::{RankIndex, TokenRarity, TraitBackfill, TraitStats};
+use marketplace::{Listing, ListingStatus, MarketplaceConfig, Offer, OfferIndexBackfill, OfferStatus, PaymentToken, Payout, Sale};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -24,9 +39,41 @@ const TOKENS_MEM_ID: MemoryId = MemoryId::new(0);
 const OWNERS_MEM_ID: MemoryId = MemoryId::new(1);
 const METADATA_MEM_ID: MemoryId = MemoryId::new(2);
 const CONFIG_MEM_ID: MemoryId = MemoryId::new(3);
-const APPROVALS_MEM_ID: MemoryId = MemoryId::new(4);
+const TOKEN_APPROVALS_MEM_ID: MemoryId = MemoryId::new(4);
 const CONTROLLER_CONFIG_MEM_ID: MemoryId = MemoryId::new(5);
 const CONTROLLER_RECORDS_MEM_ID: MemoryId = MemoryId::new(6);
+const MULTICHAIN_NFTS_MEM_ID: MemoryId = MemoryId::new(7);
+const MINT_JOBS_MEM_ID: MemoryId = MemoryId::new(8);
+const ADAPTER_SETTINGS_MEM_ID: MemoryId = MemoryId::new(9);
+const BRIDGES_MEM_ID: MemoryId = MemoryId::new(10);
+const BRIDGE_TARGETS_MEM_ID: MemoryId = MemoryId::new(11);
+const LISTINGS_MEM_ID: MemoryId = MemoryId::new(12);
+const OFFERS_MEM_ID: MemoryId = MemoryId::new(13);
+const PAYOUTS_MEM_ID: MemoryId = MemoryId::new(14);
+const MARKET_CONFIG_MEM_ID: MemoryId = MemoryId::new(15);
+const COLLECTION_APPROVALS_MEM_ID: MemoryId = MemoryId::new(16);
+const BLOCKS_MEM_ID: MemoryId = MemoryId::new(17);
+const TX_DEDUP_MEM_ID: MemoryId = MemoryId::new(18);
+const METADATA_HISTORY_MEM_ID: MemoryId = MemoryId::new(19);
+const TOMBSTONES_MEM_ID: MemoryId = MemoryId::new(20);
+const SOULBOUND_MEM_ID: MemoryId = MemoryId::new(21);
+const TRAIT_STATS_MEM_ID: MemoryId = MemoryId::new(22);
+const LOG_BACKFILL_MEM_ID: MemoryId = MemoryId::new(23);
+const OPEN_JOBS_MEM_ID: MemoryId = MemoryId::new(24);
+const JOB_INDEX_BACKFILL_MEM_ID: MemoryId = MemoryId::new(25);
+const OFFERS_BY_TOKEN_MEM_ID: MemoryId = MemoryId::new(26);
+const OFFER_INDEX_BACKFILL_MEM_ID: MemoryId = MemoryId::new(27);
+const TRAIT_BACKFILL_MEM_ID: MemoryId = MemoryId::new(28);
+/// Pre-log tokens entered into the ICRC-3 log per timer tick
+const LOG_BACKFILL_BATCH: usize = 500;
+/// Pre-rarity tokens entered into the trait table per timer tick
+const TRAIT_BACKFILL_BATCH: usize = 500;
+/// Tokens scored per timer tick while the rank index is rebuilt
+const RANK_BATCH: usize = 1_000;
+/// Wait after a trait table change before reranking, so a run of mints costs one rebuild
+const RERANK_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+/// Rank rebuild in progress: the next token to score and the scores so far
+type RankBuild = (u64, Vec<(u64, f64)>);
 
 // Admin principals that should always be controllers - Managed dynamically
 
@@ -51,7 +98,7 @@ impl Default for Rarity {
 pub struct Trait {
     pub trait_type: String,
     pub value: String,
-    pub rarity_score: u8,
+    pub rarity_score: u8, // Ignored on mint; filled in by the rarity engine
 }
 
 // NFT Metadata
@@ -62,8 +109,8 @@ pub struct NFTMetadata {
     pub image: String, // IPFS or on-chain reference
     pub external_url: Option<String>,
     pub attributes: Vec<Trait>,
-    pub rarity: Rarity,
-    pub rarity_score: u32,
+    pub rarity: Rarity,     // Derived by the rarity engine
+    pub rarity_score: u32,  // Information content in hundredths of a bit
     pub collection: String,
     pub created_at: u64,
     pub creator: Principal,
@@ -85,7 +132,8 @@ impl Storable for NFTMetadata {
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct TokenOwnership {
     pub owner: Principal,
-    pub approved: Option<Principal>,
+    pub subaccount: Option<Vec<u8>>,
+    pub approved: Option<Principal>, // Unused: approvals are kept by ICRC-37
     pub transferred_at: u64,
 }
 
@@ -186,6 +234,38 @@ impl Storable for StorablePrincipal {
     };
 }
 
+// Storable wrapper for String
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct StorableString(String);
+
+impl Storable for StorableString {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(self.0.as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableString(String::from_utf8(bytes.to_vec()).unwrap_or_default())
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+// Storable wrapper for byte-string keys
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct StorableBytes(Vec<u8>);
+
+impl Storable for StorableBytes {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Borrowed(&self.0)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StorableBytes(bytes.into_owned())
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
 // Storable for Vec<u64>
 #[derive(Clone, Debug, Default)]
 struct StorableTokenList(Vec<u64>);
@@ -228,6 +308,225 @@ impl Storable for NFTControllerRecord {
     const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
 }
 
+// Storable wrappers for cross-chain minting
+impl Storable for MultiChainNFT {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for MintJob {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for BridgeRecord {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for multichain::ChainMintConfig {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Listing {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Offer {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Payout {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for MarketplaceConfig {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_default()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for LogBackfill {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_default()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for JobIndexBackfill {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_default()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for OfferIndexBackfill {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_default()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for TraitBackfill {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_default()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for TraitStats {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_default()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for AdapterSettings {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap_or_default()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+// Storable wrappers for ICRC-37 approvals and ICRC-3 blocks
+impl Storable for ApprovalInfo {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Value {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+// Storable wrappers for metadata history and burned tokens
+impl Storable for MetadataVersion {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for Tombstone {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
 // Thread-local storage
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
@@ -266,39 +565,196 @@ thread_local! {
         RefCell::new(StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(CONTROLLER_RECORDS_MEM_ID))
         ));
-}
 
-// Primary admin principal - Managed dynamically
+    // Cross-chain NFTs by global id, and their per-chain mint jobs by "{global_id}/{chain}"
+    static MULTICHAIN_NFTS: RefCell<StableBTreeMap<StorableString, MultiChainNFT, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MULTICHAIN_NFTS_MEM_ID))
+        ));
 
-fn is_admin(caller: Principal) -> bool {
-    CONFIG.with(|c| c.borrow().get().admin == caller)
-        || ic_cdk::api::is_controller(&caller)
-}
+    static MINT_JOBS: RefCell<StableBTreeMap<StorableString, MintJob, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MINT_JOBS_MEM_ID))
+        ));
 
-fn is_controller(caller: Principal) -> bool {
-    CONTROLLER_CONFIG.with(|c| {
-        let config = c.borrow().get().clone();
-        is_authorized_controller(caller, &config, ic_cdk::id())
-    })
-}
+    static ADAPTER_SETTINGS: RefCell<StableCell<AdapterSettings, Memory>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ADAPTER_SETTINGS_MEM_ID)),
+            AdapterSettings::default()
+        ).unwrap());
 
-// Initialization
-#[init]
-fn init() {
-    let caller = ic_cdk::caller();
-    let this_canister = ic_cdk::id();
-    
-    CONFIG.with(|c| {
-        let mut config = c.borrow().get().clone();
-        config.admin = caller;
-        c.borrow_mut().set(config).unwrap();
-    });
+    // Bridge state by global id
+    static BRIDGES: RefCell<StableBTreeMap<StorableString, BridgeRecord, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BRIDGES_MEM_ID))
+        ));
 
-    // Initialize controller config with caller and this canister
-    CONTROLLER_CONFIG.with(|c| {
-        let mut config = ControllerConfig::default();
-        
-        // Add caller as an admin principal
+    // Wrapped-token contract per EVM chain, by chain name
+    static BRIDGE_TARGETS: RefCell<StableBTreeMap<StorableString, multichain::ChainMintConfig, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BRIDGE_TARGETS_MEM_ID))
+        ));
+
+    // Marketplace: current listing per token, offers and payouts by id
+    static LISTINGS: RefCell<StableBTreeMap<StorableNat, Listing, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LISTINGS_MEM_ID))
+        ));
+
+    static OFFERS: RefCell<StableBTreeMap<StorableNat, Offer, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OFFERS_MEM_ID))
+        ));
+
+    // Offer ids by token: token id ++ offer id
+    static OFFERS_BY_TOKEN: RefCell<StableBTreeMap<StorableBytes, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OFFERS_BY_TOKEN_MEM_ID))
+        ));
+
+    // Offers made before OFFERS_BY_TOKEN existed are indexed in batches; until
+    // then `get_offers` scans OFFERS
+    static OFFER_INDEX_BACKFILL: RefCell<StableCell<OfferIndexBackfill, Memory>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OFFER_INDEX_BACKFILL_MEM_ID)),
+            OfferIndexBackfill::default()
+        ).unwrap());
+
+    static PAYOUTS: RefCell<StableBTreeMap<StorableNat, Payout, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PAYOUTS_MEM_ID))
+        ));
+
+    static MARKET_CONFIG: RefCell<StableCell<MarketplaceConfig, Memory>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MARKET_CONFIG_MEM_ID)),
+            MarketplaceConfig::default()
+        ).unwrap());
+
+    // ICRC-37 approvals, keyed by `icrc::token_approval_key` and `icrc::collection_approval_key`
+    static TOKEN_APPROVALS: RefCell<StableBTreeMap<StorableBytes, ApprovalInfo, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TOKEN_APPROVALS_MEM_ID))
+        ));
+
+    static COLLECTION_APPROVALS: RefCell<StableBTreeMap<StorableBytes, ApprovalInfo, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(COLLECTION_APPROVALS_MEM_ID))
+        ));
+
+    // ICRC-3 log by block index, and the blocks of recent transactions by `Tx::dedup_key`
+    static BLOCKS: RefCell<StableBTreeMap<StorableNat, Value, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(BLOCKS_MEM_ID))
+        ));
+
+    static TX_DEDUP: RefCell<StableBTreeMap<StorableBytes, StorableNat, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TX_DEDUP_MEM_ID))
+        ));
+
+    // Superseded metadata by `lifecycle::history_key`, burned tokens, and soulbound
+    // tokens with the time they were bound
+    static METADATA_HISTORY: RefCell<StableBTreeMap<StorableBytes, MetadataVersion, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(METADATA_HISTORY_MEM_ID))
+        ));
+
+    static TOMBSTONES: RefCell<StableBTreeMap<StorableNat, Tombstone, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TOMBSTONES_MEM_ID))
+        ));
+
+    static SOULBOUND: RefCell<StableBTreeMap<StorableNat, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SOULBOUND_MEM_ID))
+        ));
+
+    // Trait frequency table of the collection, and the rank index derived from it
+    static TRAIT_STATS: RefCell<StableCell<TraitStats, Memory>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TRAIT_STATS_MEM_ID)),
+            TraitStats::default()
+        ).unwrap());
+
+    // Tokens minted before the rarity engine existed that are still to enter TRAIT_STATS
+    static TRAIT_BACKFILL: RefCell<StableCell<TraitBackfill, Memory>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TRAIT_BACKFILL_MEM_ID)),
+            TraitBackfill::default()
+        ).unwrap());
+
+    static RANKS: RefCell<RankIndex> = RefCell::new(RankIndex::default());
+
+    // Rank index being rebuilt. Changes to the trait table after scoring began mark
+    // the result stale, for another rebuild.
+    static RANK_BUILD: RefCell<Option<RankBuild>> = const { RefCell::new(None) };
+    static RANKS_STALE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+
+    // Tokens minted before the ICRC-3 log existed that are still to enter it
+    static LOG_BACKFILL: RefCell<StableCell<LogBackfill, Memory>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(LOG_BACKFILL_MEM_ID)),
+            LogBackfill::default()
+        ).unwrap());
+
+    // Pending and Minting jobs: open key (chain/job key) -> job key
+    static OPEN_JOBS: RefCell<StableBTreeMap<StorableString, StorableString, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(OPEN_JOBS_MEM_ID))
+        ));
+
+    // Jobs queued before OPEN_JOBS existed are indexed in batches; until then
+    // readers scan MINT_JOBS
+    static JOB_INDEX_BACKFILL: RefCell<StableCell<JobIndexBackfill, Memory>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(JOB_INDEX_BACKFILL_MEM_ID)),
+            JobIndexBackfill::default()
+        ).unwrap());
+
+    // Tokens whose sale is collecting payment, and payouts being paid
+    static SALES_IN_FLIGHT: RefCell<std::collections::BTreeSet<u64>> = const { RefCell::new(std::collections::BTreeSet::new()) };
+    static PAYOUTS_IN_FLIGHT: RefCell<std::collections::BTreeSet<u64>> = const { RefCell::new(std::collections::BTreeSet::new()) };
+
+    // Jobs claimed by an `advance_due_jobs` call, from its snapshot until each is saved
+    static JOBS_IN_FLIGHT: RefCell<std::collections::BTreeSet<String>> = const { RefCell::new(std::collections::BTreeSet::new()) };
+
+    // Chains with a submission in flight; one at a time, so each picks its nonce
+    // knowing the others'
+    static CHAINS_SUBMITTING: RefCell<std::collections::BTreeSet<String>> = const { RefCell::new(std::collections::BTreeSet::new()) };
+}
+
+// Primary admin principal - Managed dynamically
+
+fn is_admin(caller: Principal) -> bool {
+    CONFIG.with(|c| c.borrow().get().admin == caller)
+        || ic_cdk::api::is_controller(&caller)
+}
+
+fn is_controller(caller: Principal) -> bool {
+    CONTROLLER_CONFIG.with(|c| {
+        let config = c.borrow().get().clone();
+        is_authorized_controller(caller, &config, ic_cdk::id())
+    })
+}
+
+// Initialization
+#[init]
+fn init() {
+    let caller = ic_cdk::caller();
+    let this_canister = ic_cdk::id();
+    
+    CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.admin = caller;
+        c.borrow_mut().set(config).unwrap();
+    });
+
+    // Initialize controller config with caller and this canister
+    CONTROLLER_CONFIG.with(|c| {
+        let mut config = ControllerConfig::default();
+        
+        // Add caller as an admin principal
         config.admin_principals.push(caller);
         
         // Add this canister as a backend canister controller
@@ -308,29 +764,223 @@ fn init() {
         
         c.borrow_mut().set(config).unwrap();
     });
+
+    JOB_INDEX_BACKFILL.with(|b| b.borrow_mut().set(JobIndexBackfill { cursor: None, done: true }).unwrap());
+    OFFER_INDEX_BACKFILL.with(|b| b.borrow_mut().set(OfferIndexBackfill { next: 0, done: true }).unwrap());
 }
 
 #[pre_upgrade]
 fn pre_upgrade() {}
 
 #[post_upgrade]
-fn post_upgrade() {}
+fn post_upgrade() {
+    // Tokens minted before the ICRC-3 log existed enter it as mints to their current
+    // owners, in batches; an interrupted backfill resumes where it stopped
+    if BLOCKS.with(|b| b.borrow().is_empty()) {
+        let end = TOKENS.with(|t| t.borrow().last_key_value().map(|(id, _)| id.0 + 1).unwrap_or(0));
+        LOG_BACKFILL.with(|b| b.borrow_mut().set(LogBackfill { next: 0, end, logged_early: Vec::new() }).unwrap());
+    }
+    if !LOG_BACKFILL.with(|b| b.borrow().get().is_done()) {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, backfill_log);
+    }
+    certify_tip();
+
+    if !JOB_INDEX_BACKFILL.with(|b| b.borrow().get().done) {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, backfill_job_index);
+    }
+    if !OFFER_INDEX_BACKFILL.with(|b| b.borrow().get().done) {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, backfill_offer_index);
+    }
+
+    // Tokens minted before the rarity engine existed enter its trait table in
+    // batches, the last of which reranks; an interrupted backfill resumes
+    let backfill_done = TRAIT_BACKFILL.with(|b| b.borrow().get().is_done());
+    if backfill_done && TRAIT_STATS.with(|s| s.borrow().get().tokens == 0) {
+        let end = METADATA.with(|m| m.borrow().last_key_value().map(|(id, _)| id.0 + 1).unwrap_or(0));
+        TRAIT_BACKFILL.with(|b| b.borrow_mut().set(TraitBackfill { next: 0, end }).unwrap());
+    }
+    if TRAIT_BACKFILL.with(|b| b.borrow().get().is_done()) {
+        rerank(std::time::Duration::ZERO);
+    } else {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, backfill_trait_stats);
+    }
+}
+
+// === ICRC-3 Transaction Log ===
+
+fn owner_account(own: &TokenOwnership) -> Account {
+    Account::new(own.owner, own.subaccount.clone())
+}
+
+fn last_block() -> Option<(u64, [u8; 32])> {
+    BLOCKS.with(|b| b.borrow().last_key_value().map(|(index, block)| (index.0, block.hash())))
+}
+
+/// Certify the hash of the last block so `icrc3_get_tip_certificate` can prove it
+fn certify_tip() {
+    if let Some((index, hash)) = last_block() {
+        ic_cdk::api::set_certified_data(&icrc::tip_tree(index, &hash).0);
+    }
+}
+
+/// Log the next batch of pre-log tokens as mints
+fn backfill_log() {
+    let now = ic_cdk::api::time();
+    let mut backfill = LOG_BACKFILL.with(|b| b.borrow().get().clone());
+    let batch: Vec<(u64, Account)> = TOKENS.with(|t| {
+        t.borrow()
+            .range(StorableNat(backfill.next)..StorableNat(backfill.end))
+            .take(LOG_BACKFILL_BATCH)
+            .map(|(id, own)| (id.0, owner_account(&own)))
+            .collect()
+    });
+    for (token_id, to) in &batch {
+        if backfill.is_pending(*token_id) {
+            append_block(&Tx::new(Op::Mint { token_id: *token_id, to: to.clone() }), now);
+        }
+    }
+    let next = match batch.last() {
+        Some((token_id, _)) if batch.len() == LOG_BACKFILL_BATCH => token_id + 1,
+        _ => backfill.end,
+    };
+    backfill.advance(next);
+    LOG_BACKFILL.with(|b| b.borrow_mut().set(backfill.clone()).unwrap());
+    if !backfill.is_done() {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, backfill_log);
+    }
+}
+
+/// A pre-log token's first block after the backfill started is preceded by its
+/// mint, to the owner it had before the transaction
+fn backfill_token_of(tx: &Tx, now: u64) {
+    let (token_id, owner_before) = match &tx.op {
+        Op::Transfer { token_id, from, .. } | Op::Burn { token_id, from } => (*token_id, Some(from.clone())),
+        Op::Approve { token_id: Some(token_id), .. } | Op::Revoke { token_id: Some(token_id), .. } | Op::UpdateToken { token_id, .. } => (*token_id, None),
+        _ => return,
+    };
+    let mut backfill = LOG_BACKFILL.with(|b| b.borrow().get().clone());
+    if !backfill.is_pending(token_id) {
+        return;
+    }
+    let owner = owner_before.or_else(|| TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).map(|own| owner_account(&own)));
+    if let Some(to) = owner {
+        append_block(&Tx::new(Op::Mint { token_id, to }), now);
+    }
+    backfill.logged_early.push(token_id);
+    LOG_BACKFILL.with(|b| b.borrow_mut().set(backfill).unwrap());
+}
+
+/// Append a transaction to the log and return its block index
+fn log_tx(tx: &Tx, now: u64) -> u64 {
+    backfill_token_of(tx, now);
+    append_block(tx, now)
+}
+
+fn append_block(tx: &Tx, now: u64) -> u64 {
+    let (index, parent) = match last_block() {
+        Some((index, hash)) => (index + 1, Some(hash)),
+        None => (0, None),
+    };
+    BLOCKS.with(|b| b.borrow_mut().insert(StorableNat(index), icrc::block(tx, now, parent)));
+    if let Some(key) = tx.dedup_key() {
+        TX_DEDUP.with(|d| d.borrow_mut().insert(StorableBytes(key), StorableNat(index)));
+    }
+    certify_tip();
+    index
+}
+
+/// Block of an identical transaction sent earlier within the deduplication window.
+/// Expired keys are forgotten a few at a time; each logged transaction adds at most
+/// one, so the backlog drains. An expired key cannot match, since a transaction
+/// that old is rejected as too old first.
+fn find_duplicate(tx: &Tx, now: u64) -> Result<(), Failure> {
+    TX_DEDUP.with(|d| {
+        let mut dedup = d.borrow_mut();
+        let expired: Vec<StorableBytes> = dedup.iter()
+            .map(|(key, _)| key)
+            .take_while(|key| icrc::dedup_expired(&key.0, now))
+            .take(icrc::DEDUP_PRUNE_PER_CALL)
+            .collect();
+        for key in expired {
+            dedup.remove(&key);
+        }
+        match tx.dedup_key().and_then(|key| dedup.get(&StorableBytes(key))) {
+            Some(block) => Err(Failure::Duplicate(block.0)),
+            None => Ok(()),
+        }
+    })
+}
+
+#[query]
+fn icrc3_get_blocks(args: Vec<icrc::GetBlocksArgs>) -> icrc::GetBlocksResult {
+    let log_length = BLOCKS.with(|b| b.borrow().len());
+    let mut budget = icrc::MAX_BLOCKS_PER_RESPONSE;
+    let mut blocks = Vec::new();
+    for arg in args {
+        let start: u64 = arg.start.0.try_into().unwrap_or(u64::MAX);
+        let length: u64 = arg.length.0.try_into().unwrap_or(u64::MAX);
+        let length = length.min(budget);
+        BLOCKS.with(|b| {
+            for (id, block) in b.borrow().range(StorableNat(start)..).take(length as usize) {
+                blocks.push(icrc::BlockWithId { id: Nat::from(id.0), block });
+            }
+        });
+        budget -= length;
+    }
+    icrc::GetBlocksResult { log_length: Nat::from(log_length), blocks, archived_blocks: Vec::new() }
+}
+
+#[query]
+fn icrc3_get_tip_certificate() -> Option<icrc::DataCertificate> {
+    let certificate = ic_cdk::api::data_certificate()?;
+    let (index, hash) = last_block()?;
+    Some(icrc::DataCertificate { certificate, hash_tree: icrc::tip_tree(index, &hash).1 })
+}
+
+/// The whole log lives in this canister
+#[query]
+fn icrc3_get_archives(_args: icrc::GetArchivesArgs) -> Vec<icrc::ArchiveInfo> {
+    Vec::new()
+}
+
+#[query]
+fn icrc3_supported_block_types() -> Vec<icrc::BlockType> {
+    icrc::supported_block_types()
+}
 
 // === ICRC-7 Standard Methods ===
 
 #[query]
-fn icrc7_collection_metadata() -> Vec<(String, String)> {
-    CONFIG.with(|c| {
-        let binding = c.borrow();
-        let config = binding.get();
-        vec![
-            ("icrc7:name".to_string(), config.name.clone()),
-            ("icrc7:symbol".to_string(), config.symbol.clone()),
-            ("icrc7:description".to_string(), config.description.clone()),
-            ("icrc7:total_supply".to_string(), config.minted.to_string()),
-            ("icrc7:max_supply".to_string(), config.max_supply.to_string()),
-        ]
-    })
+fn icrc10_supported_standards() -> Vec<icrc::SupportedStandard> {
+    icrc::supported_standards()
+}
+
+#[query]
+fn icrc7_supported_standards() -> Vec<icrc::SupportedStandard> {
+    icrc::supported_standards()
+}
+
+#[query]
+fn icrc7_collection_metadata() -> Vec<(String, Value)> {
+    let config = CONFIG.with(|c| c.borrow().get().clone());
+    let limit = |n: usize| Value::nat(n as u64);
+    vec![
+        ("icrc7:name", Value::Text(config.name)),
+        ("icrc7:symbol", Value::Text(config.symbol)),
+        ("icrc7:description", Value::Text(config.description)),
+        ("icrc7:total_supply", Value::nat(total_supply())),
+        ("icrc7:supply_cap", Value::nat(config.max_supply)),
+        ("icrc7:max_query_batch_size", limit(icrc::MAX_QUERY_BATCH_SIZE)),
+        ("icrc7:max_update_batch_size", limit(icrc::MAX_UPDATE_BATCH_SIZE)),
+        ("icrc7:default_take_value", limit(icrc::DEFAULT_TAKE_VALUE)),
+        ("icrc7:max_take_value", limit(icrc::MAX_TAKE_VALUE)),
+        ("icrc7:max_memo_size", limit(icrc::MAX_MEMO_SIZE)),
+        ("icrc7:tx_window", Value::nat(icrc::TX_WINDOW)),
+        ("icrc7:permitted_drift", Value::nat(icrc::PERMITTED_DRIFT)),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect()
 }
 
 #[query]
@@ -343,9 +993,24 @@ fn icrc7_symbol() -> String {
     CONFIG.with(|c| c.borrow().get().symbol.clone())
 }
 
+#[query]
+fn icrc7_description() -> Option<String> {
+    CONFIG.with(|c| Some(c.borrow().get().description.clone()))
+}
+
+#[query]
+fn icrc7_logo() -> Option<String> {
+    None
+}
+
 #[query]
 fn icrc7_total_supply() -> Nat {
-    CONFIG.with(|c| Nat::from(c.borrow().get().minted))
+    Nat::from(total_supply())
+}
+
+/// Tokens in circulation: minted and not burned
+fn total_supply() -> u64 {
+    TOKENS.with(|t| t.borrow().len())
 }
 
 #[query]
@@ -354,199 +1019,533 @@ fn icrc7_supply_cap() -> Option<Nat> {
 }
 
 #[query]
-fn icrc7_owner_of(token_id: Nat) -> Option<Principal> {
-    let id: u64 = token_id.0.try_into().ok()?;
-    TOKENS.with(|t| {
-        t.borrow().get(&StorableNat(id)).map(|o| o.owner)
-    })
+fn icrc7_max_query_batch_size() -> Option<Nat> {
+    Some(Nat::from(icrc::MAX_QUERY_BATCH_SIZE))
 }
 
 #[query]
-fn icrc7_balance_of(owner: Principal) -> Nat {
-    OWNER_TOKENS.with(|ot| {
-        ot.borrow()
-            .get(&StorablePrincipal(owner))
-            .map(|list| Nat::from(list.0.len() as u64))
-            .unwrap_or(Nat::from(0u64))
-    })
+fn icrc7_max_update_batch_size() -> Option<Nat> {
+    Some(Nat::from(icrc::MAX_UPDATE_BATCH_SIZE))
 }
 
 #[query]
-fn icrc7_tokens_of(owner: Principal) -> Vec<Nat> {
-    OWNER_TOKENS.with(|ot| {
-        ot.borrow()
-            .get(&StorablePrincipal(owner))
-            .map(|list| list.0.iter().map(|id| Nat::from(*id)).collect())
-            .unwrap_or_default()
-    })
+fn icrc7_default_take_value() -> Option<Nat> {
+    Some(Nat::from(icrc::DEFAULT_TAKE_VALUE))
 }
 
 #[query]
-fn icrc7_token_metadata(token_id: Nat) -> Option<Vec<(String, String)>> {
-    let id: u64 = token_id.0.try_into().ok()?;
-    METADATA.with(|m| {
-        m.borrow().get(&StorableNat(id)).map(|meta| {
-            let mut result = vec![
-                ("name".to_string(), meta.name.clone()),
-                ("description".to_string(), meta.description.clone()),
-                ("image".to_string(), meta.image.clone()),
-                ("collection".to_string(), meta.collection.clone()),
-                ("rarity".to_string(), format!("{:?}", meta.rarity)),
-                ("rarity_score".to_string(), meta.rarity_score.to_string()),
-            ];
-            
-            if let Some(ref url) = meta.external_url {
-                result.push(("external_url".to_string(), url.clone()));
-            }
-            
-            for (i, attr) in meta.attributes.iter().enumerate() {
-                result.push((
-                    format!("attribute_{}_type", i),
-                    attr.trait_type.clone(),
-                ));
-                result.push((
-                    format!("attribute_{}_value", i),
-                    attr.value.clone(),
-                ));
-            }
-            
-            result
-        })
-    })
+fn icrc7_max_take_value() -> Option<Nat> {
+    Some(Nat::from(icrc::MAX_TAKE_VALUE))
 }
 
-// === ICRC-37 Transfer Methods ===
+#[query]
+fn icrc7_max_memo_size() -> Option<Nat> {
+    Some(Nat::from(icrc::MAX_MEMO_SIZE))
+}
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct TransferArg {
-    pub from_subaccount: Option<Vec<u8>>,
-    pub to: Principal,
-    pub token_id: Nat,
-    pub memo: Option<Vec<u8>>,
-    pub created_at_time: Option<u64>,
+#[query]
+fn icrc7_atomic_batch_transfers() -> Option<bool> {
+    Some(false)
 }
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub enum TransferError {
-    NonExistingTokenId,
-    InvalidRecipient,
-    Unauthorized,
-    TooOld,
-    CreatedInFuture { ledger_time: u64 },
-    Duplicate { duplicate_of: Nat },
-    GenericError { error_code: Nat, message: String },
-    GenericBatchError { error_code: Nat, message: String },
+#[query]
+fn icrc7_tx_window() -> Option<Nat> {
+    Some(Nat::from(icrc::TX_WINDOW))
 }
 
-#[update]
-fn icrc7_transfer(args: Vec<TransferArg>) -> Vec<Option<Result<Nat, TransferError>>> {
-    let caller = ic_cdk::caller();
-    let now = ic_cdk::api::time();
-    
-    args.into_iter().map(|arg| {
-        let token_id: u64 = match arg.token_id.0.try_into() {
-            Ok(id) => id,
-            Err(_) => return Some(Err(TransferError::NonExistingTokenId)),
-        };
-        
-        // Validate recipient
-        if arg.to == Principal::anonymous() {
-            return Some(Err(TransferError::InvalidRecipient));
-        }
-        
-        // Check ownership
-        let ownership = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id)));
-        
-        match ownership {
-            Some(own) => {
-                if own.owner != caller && own.approved != Some(caller) && !is_admin(caller) {
-                    return Some(Err(TransferError::Unauthorized));
-                }
-                
-                // Perform transfer
-                let new_ownership = TokenOwnership {
-                    owner: arg.to,
-                    approved: None,
-                    transferred_at: now,
-                };
-                
-                TOKENS.with(|t| {
-                    t.borrow_mut().insert(StorableNat(token_id), new_ownership);
-                });
-                
-                // Update owner token lists
-                OWNER_TOKENS.with(|ot| {
-                    let mut owner_tokens = ot.borrow_mut();
-                    
-                    // Remove from old owner
-                    if let Some(mut list) = owner_tokens.get(&StorablePrincipal(own.owner)) {
-                        list.0.retain(|&id| id != token_id);
-                        owner_tokens.insert(StorablePrincipal(own.owner), list);
-                    }
-                    
-                    // Add to new owner
-                    let mut new_list = owner_tokens
-                        .get(&StorablePrincipal(arg.to))
-                        .unwrap_or_default();
-                    new_list.0.push(token_id);
-                    owner_tokens.insert(StorablePrincipal(arg.to), new_list);
-                });
-                
-                Some(Ok(Nat::from(token_id)))
-            }
-            None => Some(Err(TransferError::NonExistingTokenId)),
-        }
-    }).collect()
+#[query]
+fn icrc7_permitted_drift() -> Option<Nat> {
+    Some(Nat::from(icrc::PERMITTED_DRIFT))
 }
 
-#[update]
-fn icrc37_approve(token_id: Nat, spender: Principal) -> Result<(), TransferError> {
-    let caller = ic_cdk::caller();
-    let id: u64 = token_id.0.try_into().map_err(|_| TransferError::NonExistingTokenId)?;
-    
+/// Reject query batches over `icrc7_max_query_batch_size`
+fn check_query_batch(len: usize) {
+    if len > icrc::MAX_QUERY_BATCH_SIZE {
+        ic_cdk::trap(&format!("At most {} items are accepted per query", icrc::MAX_QUERY_BATCH_SIZE));
+    }
+}
+
+fn token_id_of(token_id: &Nat) -> Option<u64> {
+    token_id.0.clone().try_into().ok()
+}
+
+#[query]
+fn icrc7_owner_of(token_ids: Vec<Nat>) -> Vec<Option<Account>> {
+    check_query_batch(token_ids.len());
+    token_ids.iter()
+        .map(|token_id| {
+            let id = token_id_of(token_id)?;
+            TOKENS.with(|t| t.borrow().get(&StorableNat(id))).map(|own| owner_account(&own))
+        })
+        .collect()
+}
+
+/// Tokens held by an account, in id order
+fn tokens_of(account: &Account) -> Vec<u64> {
+    let mut ids: Vec<u64> = OWNER_TOKENS.with(|ot| ot.borrow().get(&StorablePrincipal(account.owner)))
+        .map(|list| list.0)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|id| {
+            TOKENS.with(|t| t.borrow().get(&StorableNat(*id)))
+                .is_some_and(|own| owner_account(&own) == *account)
+        })
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[query]
+fn icrc7_balance_of(accounts: Vec<Account>) -> Vec<Nat> {
+    check_query_batch(accounts.len());
+    accounts.into_iter()
+        .map(|account| Nat::from(tokens_of(&account.normalized()).len()))
+        .collect()
+}
+
+#[query]
+fn icrc7_tokens(prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    let start = match prev {
+        Some(prev) => match token_id_of(&prev).and_then(|id| id.checked_add(1)) {
+            Some(start) => start,
+            None => return Vec::new(),
+        },
+        None => 0,
+    };
     TOKENS.with(|t| {
-        let mut tokens = t.borrow_mut();
-        
-        match tokens.get(&StorableNat(id)) {
-            Some(mut own) => {
-                if own.owner != caller && !is_admin(caller) {
-                    return Err(TransferError::Unauthorized);
-                }
-                
-                own.approved = Some(spender);
-                tokens.insert(StorableNat(id), own);
-                Ok(())
-            }
-            None => Err(TransferError::NonExistingTokenId),
-        }
+        t.borrow()
+            .range(StorableNat(start)..)
+            .take(icrc::take(take))
+            .map(|(id, _)| Nat::from(id.0))
+            .collect()
     })
 }
 
-// === Minting ===
+#[query]
+fn icrc7_tokens_of(account: Account, prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    let prev = prev.as_ref().map(token_id_of);
+    tokens_of(&account.normalized())
+        .into_iter()
+        .filter(|id| match prev {
+            Some(Some(prev)) => *id > prev,
+            Some(None) => false,
+            None => true,
+        })
+        .take(icrc::take(take))
+        .map(Nat::from)
+        .collect()
+}
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct MintArgs {
-    pub to: Principal,
-    pub name: String,
-    pub description: String,
-    pub image: String,
-    pub attributes: Vec<Trait>,
+fn token_metadata(token_id: u64) -> Option<Vec<(String, Value)>> {
+    let meta = with_rarity(token_id, METADATA.with(|m| m.borrow().get(&StorableNat(token_id)))?);
+    let mut result = vec![
+        ("name", Value::Text(meta.name)),
+        ("description", Value::Text(meta.description)),
+        ("image", Value::Text(meta.image)),
+        ("collection", Value::Text(meta.collection)),
+        ("rarity", Value::Text(format!("{:?}", meta.rarity))),
+        ("rarity_score", Value::nat(meta.rarity_score as u64)),
+    ];
+    if let Some(url) = meta.external_url {
+        result.push(("external_url", Value::Text(url)));
+    }
+    let attributes = meta.attributes.into_iter()
+        .map(|attr| Value::Map(vec![
+            ("trait_type".to_string(), Value::Text(attr.trait_type)),
+            ("value".to_string(), Value::Text(attr.value)),
+        ]))
+        .collect();
+    result.push(("attributes", Value::Array(attributes)));
+    Some(result.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+#[query]
+fn icrc7_token_metadata(token_ids: Vec<Nat>) -> Vec<Option<Vec<(String, Value)>>> {
+    check_query_batch(token_ids.len());
+    token_ids.iter()
+        .map(|token_id| token_id_of(token_id).and_then(token_metadata))
+        .collect()
+}
+
+/// Token that `from` may move: it exists, `from` holds it and it is not soulbound,
+/// in bridge escrow or being sold
+fn movable_token(token_id: &Nat, from: &Account) -> Result<u64, Failure> {
+    let id = token_id_of(token_id).ok_or(Failure::NonExistingTokenId)?;
+    let own = TOKENS.with(|t| t.borrow().get(&StorableNat(id))).ok_or(Failure::NonExistingTokenId)?;
+    if owner_account(&own) != *from || is_escrowed(&own) {
+        return Err(Failure::Unauthorized);
+    }
+    if is_soulbound(Nat::from(id)) {
+        return Err(Failure::Generic(icrc::ERR_SOULBOUND, "Soulbound tokens cannot be transferred".to_string()));
+    }
+    if sale_in_flight(id) {
+        return Err(Failure::Generic(icrc::ERR_SALE_IN_FLIGHT, "A sale of this token is in progress".to_string()));
+    }
+    Ok(id)
+}
+
+fn transfer(caller: Principal, arg: icrc::TransferArg, now: u64) -> Result<u64, Failure> {
+    let from = Account::new(caller, arg.from_subaccount);
+    let to = arg.to.normalized();
+    icrc::check_recipient(&from, &to)?;
+    icrc::check_memo(&arg.memo)?;
+    icrc::check_created_at_time(arg.created_at_time, now)?;
+    // A retry of a transfer that went through no longer owns the token: dedup first
+    let token_id = token_id_of(&arg.token_id).ok_or(Failure::NonExistingTokenId)?;
+    let tx = Tx {
+        op: Op::Transfer { token_id, from: from.clone(), to: to.clone(), spender: None },
+        memo: arg.memo,
+        created_at_time: arg.created_at_time,
+    };
+    find_duplicate(&tx, now)?;
+    movable_token(&arg.token_id, &from)?;
+    move_token(token_id, &to, now);
+    Ok(log_tx(&tx, now))
 }
 
 #[update]
-fn mint(args: MintArgs) -> Result<Nat, String> {
+fn icrc7_transfer(args: Vec<icrc::TransferArg>) -> Vec<Option<Result<Nat, icrc::TransferError>>> {
     let caller = ic_cdk::caller();
-    
-    // Only admin can mint
-    if !is_admin(caller) {
-        return Err("Only admin can mint NFTs".to_string());
+    let now = ic_cdk::api::time();
+    if let Err(e) = icrc::check_batch(args.len(), icrc::MAX_UPDATE_BATCH_SIZE) {
+        return vec![Some(Err(e.into()))];
     }
-    
-    // Check supply
-    let (token_id, config) = CONFIG.with(|c| {
-        let mut config = c.borrow().get().clone();
-        
-        if config.minted >= config.max_supply {
+    args.into_iter()
+        .map(|arg| Some(transfer(caller, arg, now).map(Nat::from).map_err(Into::into)))
+        .collect()
+}
+
+// === ICRC-37 Approval Methods ===
+
+#[query]
+fn icrc37_metadata() -> Vec<(String, Value)> {
+    vec![
+        ("icrc37:max_approvals_per_token_or_collection".to_string(), Value::nat(icrc::MAX_APPROVALS_PER_TOKEN_OR_COLLECTION as u64)),
+        ("icrc37:max_revoke_approvals".to_string(), Value::nat(icrc::MAX_REVOKE_APPROVALS as u64)),
+    ]
+}
+
+#[query]
+fn icrc37_max_approvals_per_token_or_collection() -> Option<Nat> {
+    Some(Nat::from(icrc::MAX_APPROVALS_PER_TOKEN_OR_COLLECTION))
+}
+
+#[query]
+fn icrc37_max_revoke_approvals() -> Option<Nat> {
+    Some(Nat::from(icrc::MAX_REVOKE_APPROVALS))
+}
+
+/// Approvals stored under `prefix`, dropping any that have expired
+fn active_approvals(
+    map: &'static std::thread::LocalKey<RefCell<StableBTreeMap<StorableBytes, ApprovalInfo, Memory>>>,
+    prefix: &[u8],
+    now: u64,
+) -> Vec<(StorableBytes, ApprovalInfo)> {
+    map.with(|m| {
+        let mut approvals = m.borrow_mut();
+        let (active, expired): (Vec<_>, Vec<_>) = approvals
+            .range(StorableBytes(prefix.to_vec())..)
+            .take_while(|(key, _)| key.0.starts_with(prefix))
+            .partition(|(_, info)| info.is_active(now));
+        for (key, _) in expired {
+            approvals.remove(&key);
+        }
+        active
+    })
+}
+
+/// Store an approval unless its owner already has the maximum number of others
+fn store_approval(
+    map: &'static std::thread::LocalKey<RefCell<StableBTreeMap<StorableBytes, ApprovalInfo, Memory>>>,
+    prefix: &[u8],
+    key: Vec<u8>,
+    info: ApprovalInfo,
+    now: u64,
+) -> Result<(), Failure> {
+    let others = active_approvals(map, prefix, now).into_iter().filter(|(k, _)| k.0 != key).count();
+    if others >= icrc::MAX_APPROVALS_PER_TOKEN_OR_COLLECTION {
+        return Err(Failure::Generic(icrc::ERR_TOO_MANY_APPROVALS, "Too many approvals".to_string()));
+    }
+    map.with(|m| m.borrow_mut().insert(StorableBytes(key), info));
+    Ok(())
+}
+
+fn approval_tx(token_id: Option<u64>, from: Account, info: &ApprovalInfo) -> Tx {
+    Tx {
+        op: Op::Approve { token_id, from, spender: info.spender.clone(), expires_at: info.expires_at },
+        memo: info.memo.clone(),
+        created_at_time: Some(info.created_at_time),
+    }
+}
+
+/// Block of an identical approval sent earlier within the deduplication window.
+/// ICRC-37 approvals have no `Duplicate` error, so a retry gets the original block.
+fn duplicate_approval(tx: &Tx, now: u64) -> Option<u64> {
+    match find_duplicate(tx, now) {
+        Err(Failure::Duplicate(block)) => Some(block),
+        _ => None,
+    }
+}
+
+fn approve_token(caller: Principal, arg: icrc::ApproveTokenArg, now: u64) -> Result<u64, Failure> {
+    let mut info = arg.approval_info;
+    info.spender = info.spender.normalized();
+    let from = Account::new(caller, info.from_subaccount.clone());
+    icrc::check_approval(&from, &info, now)?;
+    let token_id = token_id_of(&arg.token_id).ok_or(Failure::NonExistingTokenId)?;
+    let tx = approval_tx(Some(token_id), from.clone(), &info);
+    if let Some(block) = duplicate_approval(&tx, now) {
+        return Ok(block);
+    }
+    movable_token(&arg.token_id, &from)?;
+    let key = icrc::token_approval_key(token_id, Some(&info.spender));
+    store_approval(&TOKEN_APPROVALS, &icrc::token_approval_key(token_id, None), key, info, now)?;
+    Ok(log_tx(&tx, now))
+}
+
+#[update]
+fn icrc37_approve_tokens(args: Vec<icrc::ApproveTokenArg>) -> Vec<Option<Result<Nat, icrc::ApproveTokenError>>> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    if let Err(e) = icrc::check_batch(args.len(), icrc::MAX_UPDATE_BATCH_SIZE) {
+        return vec![Some(Err(e.into()))];
+    }
+    args.into_iter()
+        .map(|arg| Some(approve_token(caller, arg, now).map(Nat::from).map_err(Into::into)))
+        .collect()
+}
+
+fn approve_collection(caller: Principal, arg: icrc::ApproveCollectionArg, now: u64) -> Result<u64, Failure> {
+    let mut info = arg.approval_info;
+    info.spender = info.spender.normalized();
+    let from = Account::new(caller, info.from_subaccount.clone());
+    icrc::check_approval(&from, &info, now)?;
+    let tx = approval_tx(None, from.clone(), &info);
+    if let Some(block) = duplicate_approval(&tx, now) {
+        return Ok(block);
+    }
+    let key = icrc::collection_approval_key(&from, Some(&info.spender));
+    store_approval(&COLLECTION_APPROVALS, &icrc::collection_approval_key(&from, None), key, info, now)?;
+    Ok(log_tx(&tx, now))
+}
+
+#[update]
+fn icrc37_approve_collection(args: Vec<icrc::ApproveCollectionArg>) -> Vec<Option<Result<Nat, icrc::ApproveCollectionError>>> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    if let Err(e) = icrc::check_batch(args.len(), icrc::MAX_UPDATE_BATCH_SIZE) {
+        return vec![Some(Err(e.into()))];
+    }
+    args.into_iter()
+        .map(|arg| Some(approve_collection(caller, arg, now).map(Nat::from).map_err(Into::into)))
+        .collect()
+}
+
+/// Remove the approvals under `prefix` held by `spender`, or all of them
+fn revoke_approvals(
+    map: &'static std::thread::LocalKey<RefCell<StableBTreeMap<StorableBytes, ApprovalInfo, Memory>>>,
+    prefix: &[u8],
+    spender: Option<&Account>,
+    now: u64,
+) -> Result<(), Failure> {
+    let revoked: Vec<StorableBytes> = active_approvals(map, prefix, now)
+        .into_iter()
+        .filter(|(_, info)| spender.is_none_or(|s| info.spender == *s))
+        .map(|(key, _)| key)
+        .collect();
+    if revoked.is_empty() {
+        return Err(Failure::ApprovalDoesNotExist);
+    }
+    map.with(|m| {
+        let mut approvals = m.borrow_mut();
+        for key in revoked {
+            approvals.remove(&key);
+        }
+    });
+    Ok(())
+}
+
+fn revoke_token_approval(caller: Principal, arg: icrc::RevokeTokenApprovalArg, now: u64) -> Result<u64, Failure> {
+    let from = Account::new(caller, arg.from_subaccount);
+    let spender = arg.spender.map(Account::normalized);
+    icrc::check_memo(&arg.memo)?;
+    icrc::check_created_at_time(arg.created_at_time, now)?;
+    let token_id = token_id_of(&arg.token_id).ok_or(Failure::NonExistingTokenId)?;
+    let own = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).ok_or(Failure::NonExistingTokenId)?;
+    if owner_account(&own) != from {
+        return Err(Failure::Unauthorized);
+    }
+    let tx = Tx {
+        op: Op::Revoke { token_id: Some(token_id), from, spender: spender.clone() },
+        memo: arg.memo,
+        created_at_time: arg.created_at_time,
+    };
+    find_duplicate(&tx, now)?;
+    revoke_approvals(&TOKEN_APPROVALS, &icrc::token_approval_key(token_id, None), spender.as_ref(), now)?;
+    Ok(log_tx(&tx, now))
+}
+
+#[update]
+fn icrc37_revoke_token_approvals(args: Vec<icrc::RevokeTokenApprovalArg>) -> Vec<Option<Result<Nat, icrc::RevokeTokenApprovalError>>> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    if let Err(e) = icrc::check_batch(args.len(), icrc::MAX_REVOKE_APPROVALS) {
+        return vec![Some(Err(e.into()))];
+    }
+    args.into_iter()
+        .map(|arg| Some(revoke_token_approval(caller, arg, now).map(Nat::from).map_err(Into::into)))
+        .collect()
+}
+
+fn revoke_collection_approval(caller: Principal, arg: icrc::RevokeCollectionApprovalArg, now: u64) -> Result<u64, Failure> {
+    let from = Account::new(caller, arg.from_subaccount);
+    let spender = arg.spender.map(Account::normalized);
+    icrc::check_memo(&arg.memo)?;
+    icrc::check_created_at_time(arg.created_at_time, now)?;
+    let tx = Tx {
+        op: Op::Revoke { token_id: None, from: from.clone(), spender: spender.clone() },
+        memo: arg.memo,
+        created_at_time: arg.created_at_time,
+    };
+    find_duplicate(&tx, now)?;
+    revoke_approvals(&COLLECTION_APPROVALS, &icrc::collection_approval_key(&from, None), spender.as_ref(), now)?;
+    Ok(log_tx(&tx, now))
+}
+
+#[update]
+fn icrc37_revoke_collection_approvals(args: Vec<icrc::RevokeCollectionApprovalArg>) -> Vec<Option<Result<Nat, icrc::RevokeCollectionApprovalError>>> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    if let Err(e) = icrc::check_batch(args.len(), icrc::MAX_REVOKE_APPROVALS) {
+        return vec![Some(Err(e.into()))];
+    }
+    args.into_iter()
+        .map(|arg| Some(revoke_collection_approval(caller, arg, now).map(Nat::from).map_err(Into::into)))
+        .collect()
+}
+
+/// Whether `spender` holds an active token or collection approval for a token of `from`
+fn is_approved(spender: &Account, token_id: u64, from: &Account, now: u64) -> bool {
+    let active = |approval: Option<ApprovalInfo>| approval.is_some_and(|info| info.is_active(now));
+    active(TOKEN_APPROVALS.with(|a| a.borrow().get(&StorableBytes(icrc::token_approval_key(token_id, Some(spender))))))
+        || active(COLLECTION_APPROVALS.with(|a| a.borrow().get(&StorableBytes(icrc::collection_approval_key(from, Some(spender))))))
+}
+
+#[query]
+fn icrc37_is_approved(args: Vec<icrc::IsApprovedArg>) -> Vec<bool> {
+    check_query_batch(args.len());
+    let now = ic_cdk::api::time();
+    args.into_iter()
+        .map(|arg| {
+            let Some(token_id) = token_id_of(&arg.token_id) else { return false };
+            let Some(own) = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))) else { return false };
+            let from = owner_account(&own);
+            from == Account::new(own.owner, arg.from_subaccount) && is_approved(&arg.spender.normalized(), token_id, &from, now)
+        })
+        .collect()
+}
+
+#[query]
+fn icrc37_get_token_approvals(token_id: Nat, prev: Option<icrc::TokenApproval>, take: Option<Nat>) -> Vec<icrc::TokenApproval> {
+    let Some(id) = token_id_of(&token_id) else { return Vec::new() };
+    let now = ic_cdk::api::time();
+    let prefix = icrc::token_approval_key(id, None);
+    let after = prev.map(|p| icrc::token_approval_key(id, Some(&p.approval_info.spender.normalized())));
+    TOKEN_APPROVALS.with(|a| {
+        a.borrow()
+            .range(StorableBytes(prefix.clone())..)
+            .take_while(|(key, _)| key.0.starts_with(&prefix))
+            .filter(|(key, info)| info.is_active(now) && after.as_ref().is_none_or(|after| key.0 > *after))
+            .take(icrc::take(take))
+            .map(|(_, approval_info)| icrc::TokenApproval { token_id: token_id.clone(), approval_info })
+            .collect()
+    })
+}
+
+#[query]
+fn icrc37_get_collection_approvals(owner: Account, prev: Option<icrc::CollectionApproval>, take: Option<Nat>) -> Vec<icrc::CollectionApproval> {
+    let now = ic_cdk::api::time();
+    let owner = owner.normalized();
+    let prefix = icrc::collection_approval_key(&owner, None);
+    let after = prev.map(|p| icrc::collection_approval_key(&owner, Some(&p.spender.normalized())));
+    COLLECTION_APPROVALS.with(|a| {
+        a.borrow()
+            .range(StorableBytes(prefix.clone())..)
+            .take_while(|(key, _)| key.0.starts_with(&prefix))
+            .filter(|(key, info)| info.is_active(now) && after.as_ref().is_none_or(|after| key.0 > *after))
+            .take(icrc::take(take))
+            .map(|(_, info)| info)
+            .collect()
+    })
+}
+
+fn transfer_from(caller: Principal, arg: icrc::TransferFromArg, now: u64) -> Result<u64, Failure> {
+    let spender = Account::new(caller, arg.spender_subaccount);
+    let from = arg.from.normalized();
+    let to = arg.to.normalized();
+    icrc::check_recipient(&from, &to)?;
+    icrc::check_memo(&arg.memo)?;
+    icrc::check_created_at_time(arg.created_at_time, now)?;
+    let token_id = token_id_of(&arg.token_id).ok_or(Failure::NonExistingTokenId)?;
+    let tx = Tx {
+        op: Op::Transfer { token_id, from: from.clone(), to: to.clone(), spender: Some(spender.clone()) },
+        memo: arg.memo,
+        created_at_time: arg.created_at_time,
+    };
+    find_duplicate(&tx, now)?;
+    movable_token(&arg.token_id, &from)?;
+    if spender != from && !is_approved(&spender, token_id, &from, now) {
+        return Err(Failure::Unauthorized);
+    }
+    move_token(token_id, &to, now);
+    Ok(log_tx(&tx, now))
+}
+
+#[update]
+fn icrc37_transfer_from(args: Vec<icrc::TransferFromArg>) -> Vec<Option<Result<Nat, icrc::TransferFromError>>> {
+    let caller = ic_cdk::caller();
+    let now = ic_cdk::api::time();
+    if let Err(e) = icrc::check_batch(args.len(), icrc::MAX_UPDATE_BATCH_SIZE) {
+        return vec![Some(Err(e.into()))];
+    }
+    args.into_iter()
+        .map(|arg| Some(transfer_from(caller, arg, now).map(Nat::from).map_err(Into::into)))
+        .collect()
+}
+
+// === Minting ===
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MintArgs {
+    pub to: Principal,
+    pub name: String,
+    pub description: String,
+    pub image: String,
+    pub attributes: Vec<Trait>,
+    pub soulbound: Option<bool>, // Non-transferable, e.g. certifications and KYC badges
+}
+
+#[update]
+fn mint(args: MintArgs) -> Result<Nat, String> {
+    let token_id = mint_token(args)?;
+    rerank(RERANK_DELAY);
+    Ok(token_id)
+}
+
+/// Mint one token, leaving the rank index to the caller
+fn mint_token(args: MintArgs) -> Result<Nat, String> {
+    let caller = ic_cdk::caller();
+    
+    // Only admin can mint
+    if !is_admin(caller) {
+        return Err("Only admin can mint NFTs".to_string());
+    }
+    
+    // Check supply
+    let (token_id, config) = CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        
+        if config.minted >= config.max_supply {
             return Err("Max supply reached".to_string());
         }
         
@@ -561,25 +1560,17 @@ fn mint(args: MintArgs) -> Result<Nat, String> {
         Ok((token_id, config))
     })?;
     
-    // Calculate rarity
-    let rarity_score: u32 = args.attributes.iter().map(|a| a.rarity_score as u32).sum();
-    let rarity = match rarity_score {
-        0..=20 => Rarity::Common,
-        21..=40 => Rarity::Uncommon,
-        41..=60 => Rarity::Rare,
-        61..=80 => Rarity::Epic,
-        _ => Rarity::Legendary,
-    };
+    update_trait_stats(token_id, |stats| stats.add(&args.attributes));
     
-    // Create metadata
+    // Create metadata; rarity is filled in on read by `with_rarity`
     let metadata = NFTMetadata {
         name: args.name,
         description: args.description,
         image: args.image,
         external_url: None,
         attributes: args.attributes,
-        rarity,
-        rarity_score,
+        rarity: Rarity::default(),
+        rarity_score: 0,
         collection: config.name,
         created_at: ic_cdk::api::time(),
         creator: caller,
@@ -593,6 +1584,7 @@ fn mint(args: MintArgs) -> Result<Nat, String> {
     // Create ownership
     let ownership = TokenOwnership {
         owner: args.to,
+        subaccount: None,
         approved: None,
         transferred_at: ic_cdk::api::time(),
     };
@@ -629,268 +1621,1654 @@ fn mint(args: MintArgs) -> Result<Nat, String> {
         r.borrow_mut().insert(StorableNat(token_id), controller_record);
     });
     
+    if args.soulbound == Some(true) {
+        SOULBOUND.with(|s| s.borrow_mut().insert(StorableNat(token_id), ic_cdk::api::time()));
+    }
+    
+    log_tx(&Tx::new(Op::Mint { token_id, to: Account::new(args.to, None) }), ic_cdk::api::time());
+    
     Ok(Nat::from(token_id))
 }
 
-#[update]
-fn batch_mint(args: Vec<MintArgs>) -> Vec<Result<Nat, String>> {
-    args.into_iter().map(|arg| mint(arg)).collect()
+#[update]
+fn batch_mint(args: Vec<MintArgs>) -> Vec<Result<Nat, String>> {
+    let results = args.into_iter().map(mint_token).collect();
+    rerank(RERANK_DELAY);
+    results
+}
+
+// === Admin Functions ===
+
+#[update]
+fn set_paused(paused: bool) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !is_admin(caller) {
+        return Err("Only admin can pause minting".to_string());
+    }
+    
+    CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.paused = paused;
+        c.borrow_mut().set(config).unwrap();
+        Ok(())
+    })
+}
+
+#[update]
+fn update_collection_config(
+    name: String,
+    symbol: String,
+    description: String,
+    royalty_bps: u16,
+) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !is_admin(caller) {
+        return Err("Only admin can update config".to_string());
+    }
+    
+    if royalty_bps > 1000 {
+        return Err("Royalty cannot exceed 10%".to_string());
+    }
+    
+    CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.name = name;
+        config.symbol = symbol;
+        config.description = description;
+        config.royalty_bps = royalty_bps;
+        c.borrow_mut().set(config).unwrap();
+        Ok(())
+    })
+}
+
+// === Query Methods ===
+
+#[query]
+fn get_collection_config() -> CollectionConfig {
+    CONFIG.with(|c| c.borrow().get().clone())
+}
+
+#[query]
+fn get_nft_metadata(token_id: Nat) -> Option<NFTMetadata> {
+    let id: u64 = token_id.0.try_into().ok()?;
+    METADATA.with(|m| m.borrow().get(&StorableNat(id))).map(|meta| with_rarity(id, meta))
+}
+
+#[query]
+fn health() -> String {
+    "OK".to_string()
+}
+
+// === Controller Management Functions ===
+
+/// Get the current controller configuration
+#[query]
+fn get_controller_config() -> ControllerConfig {
+    CONTROLLER_CONFIG.with(|c| c.borrow().get().clone())
+}
+
+/// Get controller record for a specific NFT
+#[query]
+fn get_nft_controllers(token_id: u64) -> Option<NFTControllerRecord> {
+    CONTROLLER_RECORDS.with(|r| r.borrow().get(&StorableNat(token_id)))
+}
+
+/// Set backend canister IDs that should be controllers of all NFTs
+#[update]
+fn set_backend_canisters(canisters: Vec<Principal>) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !is_admin(caller) {
+        return Err("Only admin can set backend canisters".to_string());
+    }
+    
+    CONTROLLER_CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.backend_canisters = canisters;
+        c.borrow_mut().set(config).unwrap();
+    });
+    
+    Ok(())
+}
+
+/// Set frontend canister ID that should be a controller
+#[update]
+fn set_frontend_canister(canister: Principal) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !is_admin(caller) {
+        return Err("Only admin can set frontend canister".to_string());
+    }
+    
+    CONTROLLER_CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.frontend_canister = Some(canister);
+        c.borrow_mut().set(config).unwrap();
+    });
+    
+    Ok(())
+}
+
+/// Add an admin principal
+#[update]
+fn add_admin_principal(principal: Principal) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !is_admin(caller) {
+        return Err("Only admin can add admin principals".to_string());
+    }
+    
+    CONTROLLER_CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        if !config.admin_principals.contains(&principal) {
+            config.admin_principals.push(principal);
+        }
+        c.borrow_mut().set(config).unwrap();
+    });
+    
+    Ok(())
+}
+
+/// Remove an admin principal
+#[update]
+fn remove_admin_principal(principal: Principal) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    
+    if !is_admin(caller) {
+        return Err("Only admin can remove admin principals".to_string());
+    }
+    
+    // Prevent removing the primary admin
+    if CONFIG.with(|c| c.borrow().get().admin) == principal {
+        return Err("Cannot remove primary admin".to_string());
+    }
+    
+    CONTROLLER_CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.admin_principals.retain(|p| *p != principal);
+        c.borrow_mut().set(config).unwrap();
+    });
+    
+    Ok(())
+}
+
+/// Register controller record for a minted NFT
+#[update]
+fn register_nft_controllers(token_id: u64, canister_id: Option<Principal>) -> Result<NFTControllerRecord, String> {
+    let caller = ic_cdk::caller();
+    
+    if !is_admin(caller) && !is_controller(caller) {
+        return Err("Not authorized to register controllers".to_string());
+    }
+    
+    // Get the NFT owner
+    let owner = TOKENS.with(|t| {
+        t.borrow().get(&StorableNat(token_id)).map(|o| o.owner)
+    }).ok_or("NFT not found")?;
+    
+    // Build controller list
+    let config = CONTROLLER_CONFIG.with(|c| c.borrow().get().clone());
+    let controllers = build_controller_list(&config, owner, ic_cdk::id());
+    
+    let record = NFTControllerRecord {
+        token_id,
+        canister_id,
+        controllers: controllers.clone(),
+        owner,
+        created_at: ic_cdk::api::time(),
+        last_updated: ic_cdk::api::time(),
+    };
+    
+    CONTROLLER_RECORDS.with(|r| {
+        r.borrow_mut().insert(StorableNat(token_id), record.clone());
+    });
+    
+    Ok(record)
+}
+
+/// Update controllers for an NFT (e.g., after transfer)
+#[update]
+fn update_nft_controllers(token_id: u64) -> Result<NFTControllerRecord, String> {
+    let caller = ic_cdk::caller();
+    
+    if !is_admin(caller) && !is_controller(caller) {
+        return Err("Not authorized to update controllers".to_string());
+    }
+    
+    // Get existing record
+    let existing = CONTROLLER_RECORDS.with(|r| r.borrow().get(&StorableNat(token_id)));
+    
+    // Get current owner
+    let owner = TOKENS.with(|t| {
+        t.borrow().get(&StorableNat(token_id)).map(|o| o.owner)
+    }).ok_or("NFT not found")?;
+    
+    // Rebuild controller list
+    let config = CONTROLLER_CONFIG.with(|c| c.borrow().get().clone());
+    let controllers = build_controller_list(&config, owner, ic_cdk::id());
+    
+    let record = NFTControllerRecord {
+        token_id,
+        canister_id: existing.as_ref().and_then(|e| e.canister_id),
+        controllers: controllers.clone(),
+        owner,
+        created_at: existing.map(|e| e.created_at).unwrap_or(ic_cdk::api::time()),
+        last_updated: ic_cdk::api::time(),
+    };
+    
+    CONTROLLER_RECORDS.with(|r| {
+        r.borrow_mut().insert(StorableNat(token_id), record.clone());
+    });
+    
+    Ok(record)
+}
+
+/// Get all NFTs with their controller records
+#[query]
+fn get_all_controller_records() -> Vec<NFTControllerRecord> {
+    CONTROLLER_RECORDS.with(|r| {
+        r.borrow().iter().map(|(_, record)| record).collect()
+    })
+}
+
+/// Check if a principal is an authorized controller for any NFT operations
+#[query]
+fn is_authorized_nft_controller(principal: Principal) -> bool {
+    is_controller(principal)
+}
+
+/// Get list of all backend canister controllers
+#[query]
+fn get_backend_controllers() -> Vec<Principal> {
+    CONTROLLER_CONFIG.with(|c| c.borrow().get().backend_canisters.clone())
+}
+
+/// Get list of all admin principals
+#[query]
+fn get_admin_principals() -> Vec<Principal> {
+    CONTROLLER_CONFIG.with(|c| c.borrow().get().admin_principals.clone())
+}
+
+// === Cross-Chain Minting ===
+
+enum Adapter {
+    Mock(orchestrator::MockAdapter),
+    Evm(evm::EvmAdapter<evm::HttpOutcall, evm::ThresholdEcdsa>),
+}
+
+impl orchestrator::ChainAdapter for Adapter {
+    async fn submit(&self, job: &MintJob, metadata: &multichain::MultiChainMetadata) -> Result<orchestrator::Submission, String> {
+        match self {
+            Adapter::Mock(adapter) => adapter.submit(job, metadata).await,
+            Adapter::Evm(adapter) => adapter.submit(job, metadata).await,
+        }
+    }
+
+    async fn confirm(&self, job: &MintJob, now: u64) -> Result<orchestrator::Confirmation, String> {
+        match self {
+            Adapter::Mock(adapter) => adapter.confirm(job, now).await,
+            Adapter::Evm(adapter) => adapter.confirm(job, now).await,
+        }
+    }
+}
+
+fn evm_adapter(settings: &AdapterSettings) -> evm::EvmAdapter<evm::HttpOutcall, evm::ThresholdEcdsa> {
+    let key_name = settings.ecdsa_key_name.clone().unwrap_or_else(|| evm::DEFAULT_KEY_NAME.to_string());
+    evm::EvmAdapter::new(evm::HttpOutcall, evm::ThresholdEcdsa { key_name })
+}
+
+/// Adapter for a job's chain, if one is available
+fn chain_adapter(chain: &Chain) -> Option<Adapter> {
+    let settings = ADAPTER_SETTINGS.with(|s| s.borrow().get().clone());
+    if settings.mock_adapters {
+        Some(Adapter::Mock(orchestrator::MockAdapter::new()))
+    } else if chain.is_evm() {
+        Some(Adapter::Evm(evm_adapter(&settings).with_min_nonce(reserved_nonces_end(chain))))
+    } else {
+        None
+    }
+}
+
+/// One past the highest nonce held by an unfinished job on `chain`; a new
+/// transaction must not reuse those while they may still be mined
+fn reserved_nonces_end(chain: &Chain) -> u64 {
+    open_jobs(Some(chain))
+        .iter()
+        .filter_map(|(_, job)| job.nonce.map(|nonce| nonce + 1))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Store a job and keep the open-job index in step with its status
+fn save_job(key: &str, job: &MintJob) {
+    MINT_JOBS.with(|j| j.borrow_mut().insert(StorableString(key.to_string()), job.clone()));
+    let open_key = StorableString(MintJob::open_key(&job.target.chain, key));
+    OPEN_JOBS.with(|o| {
+        if job.is_open() {
+            o.borrow_mut().insert(open_key, StorableString(key.to_string()));
+        } else {
+            o.borrow_mut().remove(&open_key);
+        }
+    });
+}
+
+/// Pending and Minting jobs, of `chain` only if given
+fn open_jobs(chain: Option<&Chain>) -> Vec<(String, MintJob)> {
+    let on_chain = |job: &MintJob| chain.is_none_or(|c| job.target.chain == *c);
+    if !JOB_INDEX_BACKFILL.with(|b| b.borrow().get().done) {
+        return MINT_JOBS.with(|j| {
+            j.borrow()
+                .iter()
+                .filter(|(_, job)| job.is_open() && on_chain(job))
+                .map(|(k, job)| (k.0, job))
+                .collect()
+        });
+    }
+    let prefix = chain.map(|c| format!("{}/", c)).unwrap_or_default();
+    let keys: Vec<String> = OPEN_JOBS.with(|o| {
+        o.borrow()
+            .range(StorableString(prefix.clone())..)
+            .take_while(|(open_key, _)| open_key.0.starts_with(&prefix))
+            .map(|(_, key)| key.0)
+            .collect()
+    });
+    MINT_JOBS.with(|j| {
+        let jobs = j.borrow();
+        keys.into_iter()
+            .filter_map(|key| jobs.get(&StorableString(key.clone())).map(|job| (key, job)))
+            .filter(|(_, job)| job.is_open() && on_chain(job))
+            .collect()
+    })
+}
+
+/// Index the next batch of jobs queued before the open-job index existed
+fn backfill_job_index() {
+    let mut backfill = JOB_INDEX_BACKFILL.with(|b| b.borrow().get().clone());
+    let batch: Vec<(String, MintJob)> = MINT_JOBS.with(|j| {
+        let jobs = j.borrow();
+        let start = StorableString(backfill.cursor.clone().unwrap_or_default());
+        jobs.range(start..)
+            .filter(|(key, _)| Some(&key.0) != backfill.cursor.as_ref())
+            .take(orchestrator::JOB_INDEX_BATCH)
+            .map(|(key, job)| (key.0, job))
+            .collect()
+    });
+    OPEN_JOBS.with(|o| {
+        let mut open = o.borrow_mut();
+        for (key, job) in batch.iter().filter(|(_, job)| job.is_open()) {
+            open.insert(StorableString(MintJob::open_key(&job.target.chain, key)), StorableString(key.clone()));
+        }
+    });
+    backfill.done = batch.len() < orchestrator::JOB_INDEX_BATCH;
+    backfill.cursor = batch.last().map(|(key, _)| key.clone()).or(backfill.cursor);
+    JOB_INDEX_BACKFILL.with(|b| b.borrow_mut().set(backfill.clone()).unwrap());
+    if !backfill.done {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, backfill_job_index);
+    }
+}
+
+/// Cross-chain id of an ICRC-7 token of this collection
+fn global_id_for(token_id: u64) -> String {
+    hex::encode(&Sha256::digest(format!("{}:{}", ic_cdk::id(), token_id))[..16])
+}
+
+fn icp_token_id(token_id: u64) -> ChainTokenId {
+    ChainTokenId {
+        chain: Chain::ICP,
+        standard: multichain::NFTStandard::ICRC7,
+        contract_address: Some(ic_cdk::id().to_text()),
+        token_id: token_id.to_string(),
+        inscription_id: None,
+    }
+}
+
+fn jobs_of(global_id: &str) -> Vec<MintJob> {
+    let prefix = format!("{}/", global_id);
+    MINT_JOBS.with(|j| {
+        j.borrow()
+            .range(StorableString(prefix.clone())..)
+            .take_while(|(k, _)| k.0.starts_with(&prefix))
+            .map(|(_, job)| job)
+            .collect()
+    })
+}
+
+/// Mint an NFT on ICP and queue its mints on the other target chains (admin only).
+/// Progress is reported by `get_multichain_status`.
+#[update]
+fn multichain_mint(request: MultiChainMintRequest) -> Result<MultiChainMintStatus, String> {
+    let caller = ic_cdk::caller();
+    if !is_admin(caller) {
+        return Err("Only admin can mint NFTs".to_string());
+    }
+    let this_canister = ic_cdk::id();
+    let (icp_owner, targets) = orchestrator::plan(&request, caller, this_canister)?;
+    if let Some(target) = targets.iter().find(|t| chain_adapter(&t.chain).is_none()) {
+        return Err(format!("No adapter is configured for {}", target.chain));
+    }
+
+    let metadata = &request.metadata;
+    let token_id: u64 = mint(MintArgs {
+        to: icp_owner,
+        name: metadata.name.clone(),
+        description: metadata.description.clone(),
+        image: metadata.image.clone(),
+        attributes: metadata.attributes.iter()
+            .map(|a| Trait { trait_type: a.trait_type.clone(), value: a.value.clone(), rarity_score: 0 })
+            .collect(),
+        soulbound: None,
+    })?.0.try_into().map_err(|_| "Token id overflow".to_string())?;
+
+    let now = ic_cdk::api::time();
+    let global_id = global_id_for(token_id);
+    let nft = MultiChainNFT {
+        global_id: global_id.clone(),
+        chain_ids: [(Chain::ICP.to_string(), icp_token_id(token_id))].into_iter().collect(),
+        metadata: request.metadata.clone(),
+        owner: request.recipient.clone(),
+        approved: None,
+        status: if targets.is_empty() { NFTStatus::Minted } else { NFTStatus::Minting },
+        minted_chains: vec![Chain::ICP],
+        pending_chains: targets.iter().map(|t| t.chain.clone()).collect(),
+        bridge_status: None,
+        created_at: now,
+        last_transfer: None,
+    };
+    MULTICHAIN_NFTS.with(|n| n.borrow_mut().insert(StorableString(global_id.clone()), nft));
+
+    for target in targets {
+        let key = MintJob::key(&global_id, &target.chain);
+        save_job(&key, &MintJob::new(&global_id, target, &request.recipient.address, now));
+    }
+
+    ic_cdk::spawn(async {
+        advance_due_jobs(orchestrator::DEFAULT_JOBS_PER_CALL as usize).await;
+    });
+    get_multichain_status(global_id).ok_or_else(|| "Mint record missing".to_string())
+}
+
+/// Record a finished job on its NFT
+fn record_job_outcome(job: &MintJob) {
+    let Some(result) = &job.result else { return };
+    MULTICHAIN_NFTS.with(|n| {
+        let mut nfts = n.borrow_mut();
+        let Some(mut nft) = nfts.get(&StorableString(job.global_id.clone())) else { return };
+        let chain = &job.target.chain;
+        nft.pending_chains.retain(|c| c != chain);
+        if result.success && !nft.minted_chains.contains(chain) {
+            nft.minted_chains.push(chain.clone());
+            nft.chain_ids.insert(chain.to_string(), ChainTokenId {
+                chain: chain.clone(),
+                standard: result.standard.clone(),
+                contract_address: result.contract_address.clone(),
+                token_id: result.token_id.clone().unwrap_or_default(),
+                inscription_id: None,
+            });
+        }
+        if nft.pending_chains.is_empty() {
+            nft.status = NFTStatus::Minted;
+        }
+        nfts.insert(StorableString(job.global_id.clone()), nft);
+    });
+}
+
+/// Advance up to `limit` due jobs by one step each
+async fn advance_due_jobs(limit: usize) -> Vec<MintJob> {
+    let now = ic_cdk::api::time();
+    // Claim the whole snapshot before the first await, so a concurrent call cannot
+    // advance any of these jobs while this one holds a copy
+    let mut due = Vec::new();
+    for (key, job) in open_jobs(None) {
+        if due.len() == limit {
+            break;
+        }
+        if job.is_due(now) && JOBS_IN_FLIGHT.with(|f| f.borrow_mut().insert(key.clone())) {
+            due.push(key);
+        }
+    }
+
+    let mut advanced = Vec::new();
+    for key in due {
+        // Re-read: an earlier job's await may have let another call change this one
+        let job = MINT_JOBS.with(|j| j.borrow().get(&StorableString(key.clone())));
+        let outcome = match job {
+            Some(job) if job.is_due(now) => advance_job(&key, job, now).await,
+            _ => None,
+        };
+        JOBS_IN_FLIGHT.with(|f| f.borrow_mut().remove(&key));
+        advanced.extend(outcome);
+    }
+    advanced
+}
+
+/// Advance one claimed job by one step and save it; None if it was not advanced
+async fn advance_job(key: &str, mut job: MintJob, now: u64) -> Option<MintJob> {
+    let nft = MULTICHAIN_NFTS.with(|n| n.borrow().get(&StorableString(job.global_id.clone())))?;
+    let chain = job.target.chain.to_string();
+    let submitting = job.status == NFTStatus::Pending;
+    if submitting && !CHAINS_SUBMITTING.with(|c| c.borrow_mut().insert(chain.clone())) {
+        return None; // Due again on the next call
+    }
+    match chain_adapter(&job.target.chain) {
+        Some(adapter) => orchestrator::advance(&adapter, &mut job, &nft.metadata, now).await,
+        None => job.fail(format!("No adapter is configured for {}", job.target.chain), now),
+    }
+    save_job(key, &job);
+    if submitting {
+        CHAINS_SUBMITTING.with(|c| c.borrow_mut().remove(&chain));
+    }
+    record_job_outcome(&job);
+    bridge_job_finished(&job, now);
+    Some(job)
+}
+
+/// Advance due cross-chain mint jobs one step each (admin only); returns the jobs
+/// that were advanced
+#[update]
+async fn process_mint_jobs(limit: Option<u32>) -> Result<Vec<MintJob>, String> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err("Only admin can process mint jobs".to_string());
+    }
+    let limit = limit.unwrap_or(orchestrator::DEFAULT_JOBS_PER_CALL).max(1) as usize;
+    Ok(advance_due_jobs(limit).await)
+}
+
+/// Cross-chain NFT, its aggregated mint result and its per-chain jobs
+#[query]
+fn get_multichain_status(global_id: String) -> Option<MultiChainMintStatus> {
+    let nft = MULTICHAIN_NFTS.with(|n| n.borrow().get(&StorableString(global_id.clone())))?;
+    let token_id: u64 = nft.chain_ids.get(&Chain::ICP.to_string())?.token_id.parse().ok()?;
+    let jobs = jobs_of(&global_id);
+    let result = orchestrator::summarize(&global_id, orchestrator::icp_result(token_id, ic_cdk::id()), &jobs);
+    Some(MultiChainMintStatus { nft, result, jobs })
+}
+
+/// Use mock chain adapters, which mint without touching any chain (admin only;
+/// for local and staging deployments)
+#[update]
+fn set_mock_chain_adapters(enabled: bool) -> Result<(), String> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err("Only admin can configure chain adapters".to_string());
+    }
+    ADAPTER_SETTINGS.with(|s| {
+        let mut settings = s.borrow().get().clone();
+        settings.mock_adapters = enabled;
+        s.borrow_mut().set(settings).unwrap();
+    });
+    Ok(())
+}
+
+/// Threshold ECDSA key the EVM minter signs with (admin only)
+#[update]
+fn set_ecdsa_key_name(key_name: String) -> Result<(), String> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err("Only admin can configure chain adapters".to_string());
+    }
+    ADAPTER_SETTINGS.with(|s| {
+        let mut settings = s.borrow().get().clone();
+        settings.ecdsa_key_name = Some(key_name);
+        s.borrow_mut().set(settings).unwrap();
+    });
+    Ok(())
+}
+
+/// Address that mints on EVM chains; it needs the contracts' minter role and gas funds
+#[update]
+async fn get_evm_minter_address() -> Result<String, String> {
+    let settings = ADAPTER_SETTINGS.with(|s| s.borrow().get().clone());
+    evm_adapter(&settings).address().await.map(|a| evm::to_hex(&a))
+}
+
+/// Strip replica-specific headers from JSON-RPC responses and canonicalize their bodies
+#[query]
+fn transform_rpc_response(args: TransformArgs) -> HttpResponse {
+    let method = String::from_utf8_lossy(&args.context).to_string();
+    let mut response = args.response;
+    response.headers.retain(|h| h.name.to_lowercase() == "content-type");
+    response.body = evm::canonical_response(&method, &response.body);
+    response
+}
+
+// === Bridge ===
+
+fn is_escrowed(own: &TokenOwnership) -> bool {
+    own.owner == ic_cdk::id() && own.subaccount.as_deref() == Some(&bridge::ESCROW_SUBACCOUNT[..])
+}
+
+fn clear_token_approvals(token_id: u64) {
+    TOKEN_APPROVALS.with(|a| {
+        let mut approvals = a.borrow_mut();
+        let prefix = icrc::token_approval_key(token_id, None);
+        let keys: Vec<StorableBytes> = approvals.range(StorableBytes(prefix.clone())..)
+            .take_while(|(key, _)| key.0.starts_with(&prefix))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            approvals.remove(&key);
+        }
+    });
+}
+
+fn remove_from_owner(owner: Principal, token_id: u64) {
+    OWNER_TOKENS.with(|ot| {
+        let mut owner_tokens = ot.borrow_mut();
+        if let Some(mut list) = owner_tokens.get(&StorablePrincipal(owner)) {
+            list.0.retain(|&id| id != token_id);
+            owner_tokens.insert(StorablePrincipal(owner), list);
+        }
+    });
+}
+
+/// Hand a token to `to`, dropping its approvals and keeping the owner token lists in sync
+fn move_token(token_id: u64, to: &Account, now: u64) {
+    let Some(previous) = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))) else { return };
+    let ownership = TokenOwnership { owner: to.owner, subaccount: to.subaccount.clone(), approved: None, transferred_at: now };
+    TOKENS.with(|t| t.borrow_mut().insert(StorableNat(token_id), ownership));
+    clear_token_approvals(token_id);
+    token_moved(token_id);
+    remove_from_owner(previous.owner, token_id);
+    OWNER_TOKENS.with(|ot| {
+        let mut owner_tokens = ot.borrow_mut();
+        let mut list = owner_tokens.get(&StorablePrincipal(to.owner)).unwrap_or_default();
+        list.0.push(token_id);
+        owner_tokens.insert(StorablePrincipal(to.owner), list);
+    });
+}
+
+/// Move a token to another account on this canister's own authority and log the transfer
+fn reassign_token(token_id: u64, owner: Principal, subaccount: Option<Vec<u8>>, now: u64) -> Result<(), String> {
+    let previous = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).ok_or("Token not found")?;
+    let to = Account::new(owner, subaccount);
+    move_token(token_id, &to, now);
+    log_tx(&Tx::new(Op::Transfer { token_id, from: owner_account(&previous), to, spender: None }), now);
+    Ok(())
+}
+
+fn icp_address(owner: Principal) -> multichain::MultiChainAddress {
+    multichain::MultiChainAddress { chain: Chain::ICP, address: owner.to_text(), address_type: multichain::AddressType::Principal }
+}
+
+/// Cross-chain record of a token, created from its ICRC-7 metadata on first use
+fn multichain_record(token_id: u64, owner: Principal) -> Result<MultiChainNFT, String> {
+    let global_id = global_id_for(token_id);
+    if let Some(nft) = MULTICHAIN_NFTS.with(|n| n.borrow().get(&StorableString(global_id.clone()))) {
+        return Ok(nft);
+    }
+    let metadata = METADATA.with(|m| m.borrow().get(&StorableNat(token_id))).ok_or("Token metadata not found")?;
+    let metadata = with_rarity(token_id, metadata);
+    let rarity = token_rarity(token_id, &metadata.attributes);
+    let rarity_rank = (!rarity.rank_pending).then_some(rarity.rank);
+    let royalty_bps = CONFIG.with(|c| c.borrow().get().royalty_bps);
+    Ok(MultiChainNFT {
+        global_id,
+        chain_ids: [(Chain::ICP.to_string(), icp_token_id(token_id))].into_iter().collect(),
+        metadata: multichain::MultiChainMetadata {
+            name: metadata.name,
+            description: metadata.description,
+            image: metadata.image,
+            external_url: metadata.external_url,
+            collection_name: metadata.collection.clone(),
+            collection_id: metadata.collection,
+            attributes: metadata.attributes.into_iter()
+                .map(|a| multichain::NFTAttribute { trait_type: a.trait_type, value: a.value, display_type: None, max_value: None })
+                .collect(),
+            rarity_score: Some(metadata.rarity_score as f64),
+            rarity_rank,
+            chain_specific: Default::default(),
+            animation_url: None,
+            background_color: None,
+            creator: metadata.creator.to_text(),
+            royalty_bps,
+            created_at: metadata.created_at,
+            updated_at: metadata.created_at,
+        },
+        owner: icp_address(owner),
+        approved: None,
+        status: NFTStatus::Minted,
+        minted_chains: vec![Chain::ICP],
+        pending_chains: Vec::new(),
+        bridge_status: None,
+        created_at: metadata.created_at,
+        last_transfer: None,
+    })
+}
+
+/// Store a bridge record and mirror its status onto the NFT
+fn save_bridge(record: &BridgeRecord, update: impl FnOnce(&mut MultiChainNFT)) {
+    let key = StorableString(record.global_id.clone());
+    MULTICHAIN_NFTS.with(|n| {
+        let mut nfts = n.borrow_mut();
+        if let Some(mut nft) = nfts.get(&key) {
+            nft.bridge_status = Some(record.status.clone());
+            update(&mut nft);
+            nfts.insert(key.clone(), nft);
+        }
+    });
+    BRIDGES.with(|b| b.borrow_mut().insert(key, record.clone()));
+}
+
+/// Queue the wrapped mint of a bridge
+fn queue_bridge_mint(record: &mut BridgeRecord, now: u64) {
+    let job = MintJob::new(&record.global_id, record.target.clone(), &record.recipient, now);
+    let key = MintJob::key(&record.global_id, &record.target.chain);
+    save_job(&key, &job);
+    record.set_state(multichain::BridgeState::TargetMinting, now);
+    let chain = record.target.chain.clone();
+    save_bridge(record, |nft| {
+        nft.status = NFTStatus::Bridging;
+        if !nft.pending_chains.contains(&chain) {
+            nft.pending_chains.push(chain);
+        }
+    });
+    ic_cdk::spawn(async {
+        advance_due_jobs(orchestrator::DEFAULT_JOBS_PER_CALL as usize).await;
+    });
+}
+
+/// Move a bridge on once its wrapped mint has finished
+fn bridge_job_finished(job: &MintJob, now: u64) {
+    let Some(mut record) = BRIDGES.with(|b| b.borrow().get(&StorableString(job.global_id.clone()))) else { return };
+    if !record.is_outbound()
+        || !matches!(record.status.status, multichain::BridgeState::TargetMinting)
+        || record.target.chain != job.target.chain
+    {
+        return;
+    }
+    match &job.status {
+        NFTStatus::Minted => {
+            record.status.tx_hash = job.tx_hash.clone();
+            record.set_state(multichain::BridgeState::Completed, now);
+        }
+        NFTStatus::Error(e) => record.set_state(multichain::BridgeState::Failed(e.clone()), now),
+        _ => return,
+    }
+    save_bridge(&record, |_| {});
+}
+
+/// Bridge record that `caller` may retry or cancel: a failed outbound bridge whose
+/// token is still in escrow
+fn failed_bridge(global_id: &str, caller: Principal) -> Result<BridgeRecord, String> {
+    let record = BRIDGES.with(|b| b.borrow().get(&StorableString(global_id.to_string()))).ok_or("No bridge for this NFT")?;
+    if record.owner != caller && !is_admin(caller) {
+        return Err("Only the owner can manage this bridge".to_string());
+    }
+    let escrowed = TOKENS.with(|t| t.borrow().get(&StorableNat(record.token_id))).is_some_and(|own| is_escrowed(&own));
+    if !record.is_outbound() || !matches!(record.status.status, multichain::BridgeState::Failed(_)) || !escrowed {
+        return Err("Only a failed bridge can be retried or cancelled".to_string());
+    }
+    Ok(record)
+}
+
+/// Register the wrapped-token contract of an EVM chain (admin only)
+#[update]
+fn set_bridge_target(target: multichain::ChainMintConfig) -> Result<(), String> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err("Only admin can configure bridge targets".to_string());
+    }
+    bridge::validate_target(&target)?;
+    BRIDGE_TARGETS.with(|b| b.borrow_mut().insert(StorableString(target.chain.to_string()), target));
+    Ok(())
+}
+
+#[query]
+fn get_bridge_targets() -> Vec<multichain::ChainMintConfig> {
+    BRIDGE_TARGETS.with(|b| b.borrow().iter().map(|(_, target)| target).collect())
+}
+
+/// Lock an ICRC-7 token in escrow and mint a wrapped copy to `recipient` on an EVM
+/// chain. Progress is reported by `get_bridge_status`.
+#[update]
+fn bridge_nft(token_id: Nat, chain: Chain, recipient: String) -> Result<BridgeRecord, String> {
+    let caller = ic_cdk::caller();
+    let token_id: u64 = token_id.0.try_into().map_err(|_| "Token not found".to_string())?;
+    let own = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).ok_or("Token not found")?;
+    if own.owner != caller || own.subaccount.is_some() {
+        return Err("Only the owner can bridge a token".to_string());
+    }
+    if is_soulbound(Nat::from(token_id)) {
+        return Err("Soulbound tokens cannot be bridged".to_string());
+    }
+    if sale_in_flight(token_id) {
+        return Err("A sale of this token is in progress".to_string());
+    }
+    let target = BRIDGE_TARGETS.with(|b| b.borrow().get(&StorableString(chain.to_string())))
+        .ok_or_else(|| format!("Bridging to {} is not enabled", chain))?;
+    multichain::validate_address(&chain, &recipient)?;
+    if chain_adapter(&target.chain).is_none() {
+        return Err(format!("No adapter is configured for {}", target.chain));
+    }
+
+    let now = ic_cdk::api::time();
+    let mut nft = multichain_record(token_id, caller)?;
+    if nft.minted_chains.contains(&target.chain) || nft.pending_chains.contains(&target.chain) {
+        return Err(format!("This NFT already exists on {}", target.chain));
+    }
+    let global_id = nft.global_id.clone();
+    let mut record = BridgeRecord::new(&global_id, token_id, caller, target.clone(), &recipient, now);
+    if let Some(previous) = BRIDGES.with(|b| b.borrow().get(&StorableString(global_id.clone()))) {
+        record.redeemed_burns = previous.redeemed_burns;
+    }
+
+    reassign_token(token_id, ic_cdk::id(), Some(bridge::ESCROW_SUBACCOUNT.to_vec()), now)?;
+    record.set_state(multichain::BridgeState::SourceLocked, now);
+    nft.owner = multichain::MultiChainAddress { chain: target.chain.clone(), address: recipient, address_type: multichain::AddressType::EVM };
+    nft.last_transfer = Some(now);
+    MULTICHAIN_NFTS.with(|n| n.borrow_mut().insert(StorableString(global_id), nft));
+
+    queue_bridge_mint(&mut record, now);
+    Ok(record)
+}
+
+/// Queue the wrapped mint of a failed bridge again
+#[update]
+fn retry_bridge(global_id: String) -> Result<BridgeRecord, String> {
+    let mut record = failed_bridge(&global_id, ic_cdk::caller())?;
+    queue_bridge_mint(&mut record, ic_cdk::api::time());
+    Ok(record)
+}
+
+/// Give up a failed bridge and unlock the token back to its owner
+#[update]
+fn cancel_bridge(global_id: String) -> Result<BridgeRecord, String> {
+    let mut record = failed_bridge(&global_id, ic_cdk::caller())?;
+    let now = ic_cdk::api::time();
+    reassign_token(record.token_id, record.owner, None, now)?;
+    record.returned_to = Some(record.owner);
+    record.updated_at = now;
+    let owner = record.owner;
+    save_bridge(&record, |nft| {
+        nft.owner = icp_address(owner);
+        nft.last_transfer = Some(now);
+    });
+    Ok(record)
+}
+
+/// Release a bridged-out token on ICP, given the target-chain transaction that burned
+/// its wrapped copy. Anyone may relay the burn; the token goes to the principal
+/// named in its `BridgeBurn` event.
+#[update]
+async fn complete_bridge_return(global_id: String, burn_tx_hash: String) -> Result<BridgeRecord, String> {
+    let record = BRIDGES.with(|b| b.borrow().get(&StorableString(global_id.clone()))).ok_or("No bridge for this NFT")?;
+    if record.is_redeemed(&burn_tx_hash) && !record.awaiting_return() {
+        return Ok(record);
+    }
+    if !record.awaiting_return() {
+        return Err(format!("This NFT is not waiting to return from {}", record.target.chain));
+    }
+    if record.is_redeemed(&burn_tx_hash) {
+        return Err("This burn was already redeemed".to_string());
+    }
+    let multichain::ChainConfig::EVM(config) = &record.target.config else {
+        return Err(format!("{} has no EVM configuration", record.target.chain));
+    };
+    let settings = ADAPTER_SETTINGS.with(|s| s.borrow().get().clone());
+    let verified = bridge::verify_burn(&evm_adapter(&settings), config, &burn_tx_hash, evm::token_id(&global_id)).await;
+
+    // Re-read: another call may have released the token while this one awaited
+    let now = ic_cdk::api::time();
+    let mut record = BRIDGES.with(|b| b.borrow().get(&StorableString(global_id.clone()))).ok_or("No bridge for this NFT")?;
+    if record.is_redeemed(&burn_tx_hash) && !record.awaiting_return() {
+        return Ok(record);
+    }
+    if !record.awaiting_return() || record.is_redeemed(&burn_tx_hash) {
+        return Err("This NFT is not waiting to return".to_string());
+    }
+    let burn = match verified {
+        Ok(burn) => burn,
+        Err(e) => {
+            record.last_error = Some(e.clone());
+            record.updated_at = now;
+            BRIDGES.with(|b| b.borrow_mut().insert(StorableString(global_id), record));
+            return Err(e);
+        }
+    };
+
+    reassign_token(record.token_id, burn.icp_recipient, None, now)?;
+    record.complete_return(&burn_tx_hash, burn.icp_recipient, now);
+    let chain = record.target.chain.clone();
+    save_bridge(&record, |nft| {
+        nft.minted_chains.retain(|c| *c != chain);
+        nft.chain_ids.remove(&chain.to_string());
+        nft.owner = icp_address(burn.icp_recipient);
+        nft.status = NFTStatus::Minted;
+        nft.last_transfer = Some(now);
+    });
+    Ok(record)
+}
+
+/// Latest bridge crossing of an NFT
+#[query]
+fn get_bridge_status(global_id: String) -> Option<BridgeRecord> {
+    BRIDGES.with(|b| b.borrow().get(&StorableString(global_id)))
+}
+
+// === Marketplace ===
+
+fn sale_in_flight(token_id: u64) -> bool {
+    SALES_IN_FLIGHT.with(|s| s.borrow().contains(&token_id))
+}
+
+/// Cancel the active listing of a token that moved other than by its sale
+fn token_moved(token_id: u64) {
+    LISTINGS.with(|l| {
+        let mut listings = l.borrow_mut();
+        if let Some(mut listing) = listings.get(&StorableNat(token_id)) {
+            if listing.status == ListingStatus::Active {
+                listing.status = ListingStatus::Cancelled;
+                listings.insert(StorableNat(token_id), listing);
+            }
+        }
+    });
+}
+
+fn next_market_id() -> u64 {
+    MARKET_CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        let id = config.next_id;
+        config.next_id += 1;
+        c.borrow_mut().set(config).unwrap();
+        id
+    })
+}
+
+fn nat_to_token_id(token_id: Nat) -> Result<u64, String> {
+    token_id.0.try_into().map_err(|_| "Token not found".to_string())
+}
+
+fn require_token_owner(token_id: u64, caller: Principal) -> Result<(), String> {
+    let own = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).ok_or("Token not found")?;
+    if own.owner != caller || own.subaccount.is_some() {
+        return Err("Only the owner can sell this token".to_string());
+    }
+    if is_soulbound(Nat::from(token_id)) {
+        return Err("Soulbound tokens cannot be sold".to_string());
+    }
+    Ok(())
+}
+
+/// Shares of a sale of `token_id` by `seller`; creators selling their own tokens owe no royalty
+fn sale_split(token_id: u64, seller: Principal, token: PaymentToken, price: u64) -> Result<(Principal, marketplace::Split), String> {
+    let creator = METADATA.with(|m| m.borrow().get(&StorableNat(token_id))).ok_or("Token metadata not found")?.creator;
+    let royalty_bps = if creator == seller { 0 } else { CONFIG.with(|c| c.borrow().get().royalty_bps) };
+    let platform_fee_bps = MARKET_CONFIG.with(|c| c.borrow().get().platform_fee_bps);
+    Ok((creator, marketplace::split(price, royalty_bps, platform_fee_bps, token.fee())?))
+}
+
+/// Collect the price from the buyer, then hand over the token and queue the payouts
+async fn settle(token_id: u64, seller: Principal, buyer: Principal, token: PaymentToken, price: u64, offer_id: Option<u64>) -> Result<Sale, String> {
+    let (creator, shares) = sale_split(token_id, seller, token, price)?;
+    if !SALES_IN_FLIGHT.with(|s| s.borrow_mut().insert(token_id)) {
+        return Err("A sale of this token is in progress".to_string());
+    }
+    let sale_id = next_market_id();
+    let collected = marketplace::collect(token, buyer, price, sale_id, ic_cdk::api::time()).await;
+    SALES_IN_FLIGHT.with(|s| s.borrow_mut().remove(&token_id));
+    let collected_block: Option<u64> = collected?.0.try_into().ok();
+
+    // Re-validate: the offer, listing or token may have changed while the payment
+    // was collected. The price then goes back to the buyer.
+    let now = ic_cdk::api::time();
+    if !sale_still_valid(token_id, seller, token, price, offer_id) {
+        let refund = queue_payout(sale_id, buyer, token, price, now, collected_block);
+        ic_cdk::spawn(async move {
+            pay_payouts(refund.into_iter().collect()).await;
+        });
+        return Err("The listing or offer changed while the payment was collected; the price will be refunded".to_string());
+    }
+
+    // The price is in: the sale is final
+    if let Some(offer_id) = offer_id {
+        OFFERS.with(|o| {
+            let mut offers = o.borrow_mut();
+            if let Some(mut offer) = offers.get(&StorableNat(offer_id)) {
+                offer.status = OfferStatus::Accepted;
+                offers.insert(StorableNat(offer_id), offer);
+            }
+        });
+    }
+    LISTINGS.with(|l| {
+        let mut listings = l.borrow_mut();
+        if let Some(mut listing) = listings.get(&StorableNat(token_id)) {
+            if listing.status == ListingStatus::Active {
+                listing.status = ListingStatus::Sold;
+                listing.sale_id = Some(sale_id);
+                listings.insert(StorableNat(token_id), listing);
+            }
+        }
+    });
+    reassign_token(token_id, buyer, None, now)?;
+
+    let treasury = MARKET_CONFIG.with(|c| c.borrow().get().treasury);
+    let payouts: Vec<u64> = [(seller, shares.seller), (creator, shares.royalty), (treasury, shares.platform)]
+        .into_iter()
+        .filter_map(|(to, gross)| queue_payout(sale_id, to, token, gross, now, collected_block))
+        .collect();
+    ic_cdk::spawn(async move {
+        pay_payouts(payouts).await;
+    });
+
+    Ok(Sale {
+        id: sale_id,
+        token_id,
+        seller,
+        buyer,
+        token,
+        price,
+        royalty: shares.royalty,
+        platform_fee: shares.platform,
+        offer_id,
+        settled_at: now,
+    })
+}
+
+/// Is the sale collected for still what the seller offered or accepted?
+fn sale_still_valid(token_id: u64, seller: Principal, token: PaymentToken, price: u64, offer_id: Option<u64>) -> bool {
+    let terms_hold = match offer_id {
+        Some(offer_id) => OFFERS.with(|o| o.borrow().get(&StorableNat(offer_id))).is_some_and(|offer| offer.status == OfferStatus::Open),
+        None => LISTINGS.with(|l| l.borrow().get(&StorableNat(token_id))).is_some_and(|listing| {
+            listing.status == ListingStatus::Active && listing.seller == seller && listing.token == token && listing.price == price
+        }),
+    };
+    terms_hold && TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).is_some_and(|own| own.owner == seller && own.subaccount.is_none())
+}
+
+/// Queue a payout of `gross` less the ledger fee; None if nothing is left to pay
+fn queue_payout(sale_id: u64, to: Principal, token: PaymentToken, gross: u64, now: u64, collected_block: Option<u64>) -> Option<u64> {
+    if gross <= token.fee() {
+        return None;
+    }
+    let payout = Payout {
+        id: next_market_id(),
+        sale_id,
+        to,
+        token,
+        amount: gross - token.fee(),
+        created_at: now,
+        attempts: 0,
+        paid_block: None,
+        last_error: None,
+        issued_at: None,
+        search_from: collected_block, // Payouts land after the payment
+    };
+    let id = payout.id;
+    PAYOUTS.with(|p| p.borrow_mut().insert(StorableNat(id), payout));
+    Some(id)
+}
+
+/// Attempt up to `limit` unpaid payouts
+async fn pay_due_payouts(limit: usize) -> Vec<Payout> {
+    let due: Vec<u64> = PAYOUTS.with(|p| {
+        p.borrow()
+            .iter()
+            .map(|(_, payout)| payout)
+            .filter(|payout| payout.is_due() && !PAYOUTS_IN_FLIGHT.with(|f| f.borrow().contains(&payout.id)))
+            .take(limit)
+            .map(|payout| payout.id)
+            .collect()
+    });
+    pay_payouts(due).await
+}
+
+/// Attempt the given payouts that are still due
+async fn pay_payouts(ids: Vec<u64>) -> Vec<Payout> {
+    // Claim them all before the first await, so no other call pays one of them
+    // from a stale copy
+    let claimed: Vec<u64> = ids.into_iter().filter(|id| PAYOUTS_IN_FLIGHT.with(|f| f.borrow_mut().insert(*id))).collect();
+
+    let mut attempted = Vec::new();
+    for id in claimed {
+        // Re-read: an admin may have reset its attempts while earlier ones were paid
+        let payout = PAYOUTS.with(|p| p.borrow().get(&StorableNat(id))).filter(|payout| payout.is_due());
+        if let Some(payout) = payout {
+            attempted.push(pay_payout(payout).await);
+        }
+        PAYOUTS_IN_FLIGHT.with(|f| f.borrow_mut().remove(&id));
+    }
+    attempted
+}
+
+/// Attempt one claimed payout and save the outcome
+async fn pay_payout(mut payout: Payout) -> Payout {
+    let result = marketplace::pay(&mut payout, ic_cdk::api::time()).await;
+    match result {
+        Ok(Some(block)) => {
+            payout.paid_block = Some(block);
+            payout.last_error = None;
+        }
+        // Still searching the ledger for an earlier transfer; not a failed attempt
+        Ok(None) => {
+            payout.last_error = Some(format!("Searching the ledger for an earlier transfer from block {}", payout.search_from.unwrap_or(0)));
+        }
+        Err(e) => {
+            payout.attempts += 1;
+            payout.last_error = Some(e);
+        }
+    }
+    PAYOUTS.with(|p| p.borrow_mut().insert(StorableNat(payout.id), payout.clone()));
+    payout
+}
+
+/// List a token at a fixed price until `expires_at` (nanoseconds), if given
+#[update]
+fn list_nft(token_id: Nat, token: PaymentToken, price: u64, expires_at: Option<u64>) -> Result<Listing, String> {
+    let caller = ic_cdk::caller();
+    let token_id = nat_to_token_id(token_id)?;
+    require_token_owner(token_id, caller)?;
+    if sale_in_flight(token_id) {
+        return Err("A sale of this token is in progress".to_string());
+    }
+    let now = ic_cdk::api::time();
+    marketplace::validate_expiry(expires_at, now)?;
+    sale_split(token_id, caller, token, price)?;
+
+    let listing = Listing {
+        token_id,
+        seller: caller,
+        token,
+        price,
+        status: ListingStatus::Active,
+        created_at: now,
+        expires_at,
+        sale_id: None,
+    };
+    LISTINGS.with(|l| l.borrow_mut().insert(StorableNat(token_id), listing.clone()));
+    Ok(listing)
+}
+
+#[update]
+fn cancel_listing(token_id: Nat) -> Result<Listing, String> {
+    let caller = ic_cdk::caller();
+    let token_id = nat_to_token_id(token_id)?;
+    let mut listing = LISTINGS.with(|l| l.borrow().get(&StorableNat(token_id))).ok_or("Token is not listed")?;
+    if listing.seller != caller && !is_admin(caller) {
+        return Err("Only the seller can cancel this listing".to_string());
+    }
+    if listing.status != ListingStatus::Active || sale_in_flight(token_id) {
+        return Err("Listing is not active".to_string());
+    }
+    listing.status = ListingStatus::Cancelled;
+    LISTINGS.with(|l| l.borrow_mut().insert(StorableNat(token_id), listing.clone()));
+    Ok(listing)
+}
+
+/// Buy a listed token; the caller must have approved this canister on the listing's
+/// ledger for the price plus the ledger fee
+#[update]
+async fn buy_nft(token_id: Nat) -> Result<Sale, String> {
+    let caller = ic_cdk::caller();
+    let token_id = nat_to_token_id(token_id)?;
+    let listing = LISTINGS.with(|l| l.borrow().get(&StorableNat(token_id))).ok_or("Token is not listed")?;
+    if listing.status_at(ic_cdk::api::time()) != ListingStatus::Active {
+        return Err("Listing is not active".to_string());
+    }
+    if caller == listing.seller || caller == Principal::anonymous() {
+        return Err("Invalid buyer".to_string());
+    }
+    settle(token_id, listing.seller, caller, listing.token, listing.price, None).await
+}
+
+/// Offer `amount` for a token; the caller must approve this canister on the ledger
+/// for the amount plus the ledger fee before the owner accepts
+#[update]
+fn make_offer(token_id: Nat, token: PaymentToken, amount: u64, expires_at: Option<u64>) -> Result<Offer, String> {
+    let caller = ic_cdk::caller();
+    let token_id = nat_to_token_id(token_id)?;
+    let own = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).ok_or("Token not found")?;
+    if caller == own.owner || caller == Principal::anonymous() {
+        return Err("Invalid buyer".to_string());
+    }
+    let now = ic_cdk::api::time();
+    marketplace::validate_expiry(expires_at, now)?;
+    if amount <= token.fee() {
+        return Err("Offer is too low to cover the ledger fee".to_string());
+    }
+
+    let offer = Offer {
+        id: next_market_id(),
+        token_id,
+        buyer: caller,
+        token,
+        amount,
+        status: OfferStatus::Open,
+        created_at: now,
+        expires_at,
+    };
+    OFFERS.with(|o| o.borrow_mut().insert(StorableNat(offer.id), offer.clone()));
+    OFFERS_BY_TOKEN.with(|o| o.borrow_mut().insert(StorableBytes(marketplace::offer_index_key(token_id, offer.id)), ()));
+    Ok(offer)
+}
+
+/// Index the next batch of offers made before the offers-by-token index existed
+fn backfill_offer_index() {
+    let mut backfill = OFFER_INDEX_BACKFILL.with(|b| b.borrow().get().clone());
+    let batch: Vec<(u64, u64)> = OFFERS.with(|o| {
+        o.borrow()
+            .range(StorableNat(backfill.next)..)
+            .take(marketplace::OFFER_INDEX_BATCH)
+            .map(|(id, offer)| (id.0, offer.token_id))
+            .collect()
+    });
+    OFFERS_BY_TOKEN.with(|o| {
+        let mut index = o.borrow_mut();
+        for (offer_id, token_id) in &batch {
+            index.insert(StorableBytes(marketplace::offer_index_key(*token_id, *offer_id)), ());
+        }
+    });
+    backfill.done = batch.len() < marketplace::OFFER_INDEX_BATCH;
+    backfill.next = batch.last().map_or(backfill.next, |(offer_id, _)| offer_id + 1);
+    OFFER_INDEX_BACKFILL.with(|b| b.borrow_mut().set(backfill.clone()).unwrap());
+    if !backfill.done {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, backfill_offer_index);
+    }
 }
 
-// === Admin Functions ===
-
 #[update]
-fn set_paused(paused: bool) -> Result<(), String> {
-    let caller = ic_cdk::caller();
-    
-    if !is_admin(caller) {
-        return Err("Only admin can pause minting".to_string());
+fn withdraw_offer(offer_id: u64) -> Result<Offer, String> {
+    let mut offer = OFFERS.with(|o| o.borrow().get(&StorableNat(offer_id))).ok_or("Offer not found")?;
+    if offer.buyer != ic_cdk::caller() {
+        return Err("Only the buyer can withdraw this offer".to_string());
     }
-    
-    CONFIG.with(|c| {
-        let mut config = c.borrow().get().clone();
-        config.paused = paused;
-        c.borrow_mut().set(config).unwrap();
-        Ok(())
-    })
+    if offer.status != OfferStatus::Open {
+        return Err("Offer is not open".to_string());
+    }
+    if sale_in_flight(offer.token_id) {
+        return Err("A sale of this token is in progress".to_string());
+    }
+    offer.status = OfferStatus::Withdrawn;
+    OFFERS.with(|o| o.borrow_mut().insert(StorableNat(offer_id), offer.clone()));
+    Ok(offer)
 }
 
+/// Sell the token to an open offer
 #[update]
-fn update_collection_config(
-    name: String,
-    symbol: String,
-    description: String,
-    royalty_bps: u16,
-) -> Result<(), String> {
+async fn accept_offer(offer_id: u64) -> Result<Sale, String> {
     let caller = ic_cdk::caller();
-    
-    if !is_admin(caller) {
-        return Err("Only admin can update config".to_string());
-    }
-    
-    if royalty_bps > 1000 {
-        return Err("Royalty cannot exceed 10%".to_string());
+    let offer = OFFERS.with(|o| o.borrow().get(&StorableNat(offer_id))).ok_or("Offer not found")?;
+    if offer.status_at(ic_cdk::api::time()) != OfferStatus::Open {
+        return Err("Offer is not open".to_string());
     }
-    
-    CONFIG.with(|c| {
-        let mut config = c.borrow().get().clone();
-        config.name = name;
-        config.symbol = symbol;
-        config.description = description;
-        config.royalty_bps = royalty_bps;
-        c.borrow_mut().set(config).unwrap();
-        Ok(())
-    })
-}
-
-// === Query Methods ===
-
-#[query]
-fn get_collection_config() -> CollectionConfig {
-    CONFIG.with(|c| c.borrow().get().clone())
+    require_token_owner(offer.token_id, caller)?;
+    settle(offer.token_id, caller, offer.buyer, offer.token, offer.amount, Some(offer_id)).await
 }
 
 #[query]
-fn get_nft_metadata(token_id: Nat) -> Option<NFTMetadata> {
-    let id: u64 = token_id.0.try_into().ok()?;
-    METADATA.with(|m| m.borrow().get(&StorableNat(id)))
+fn get_listing(token_id: Nat) -> Option<Listing> {
+    let token_id = nat_to_token_id(token_id).ok()?;
+    let mut listing = LISTINGS.with(|l| l.borrow().get(&StorableNat(token_id)))?;
+    listing.status = listing.status_at(ic_cdk::api::time());
+    Some(listing)
 }
 
+/// Active listings by token id, after the token `prev` if given
 #[query]
-fn health() -> String {
-    "OK".to_string()
+fn get_active_listings(prev: Option<Nat>, take: Option<Nat>) -> Vec<Listing> {
+    let start = match prev {
+        Some(prev) => match token_id_of(&prev).and_then(|id| id.checked_add(1)) {
+            Some(start) => start,
+            None => return Vec::new(),
+        },
+        None => 0,
+    };
+    let now = ic_cdk::api::time();
+    LISTINGS.with(|l| {
+        l.borrow()
+            .range(StorableNat(start)..)
+            .map(|(_, listing)| listing)
+            .filter(|listing| listing.status_at(now) == ListingStatus::Active)
+            .take(icrc::take(take))
+            .collect()
+    })
 }
 
-// === Controller Management Functions ===
-
-/// Get the current controller configuration
+/// Offers for a token, oldest first, after the offer `prev` if given
 #[query]
-fn get_controller_config() -> ControllerConfig {
-    CONTROLLER_CONFIG.with(|c| c.borrow().get().clone())
+fn get_offers(token_id: Nat, prev: Option<u64>, take: Option<Nat>) -> Vec<Offer> {
+    let Ok(token_id) = nat_to_token_id(token_id) else { return Vec::new() };
+    let Some(start) = prev.map_or(Some(0), |prev| prev.checked_add(1)) else { return Vec::new() };
+    let take = icrc::take(take);
+    let offers: Vec<Offer> = if OFFER_INDEX_BACKFILL.with(|b| b.borrow().get().done) {
+        let ids: Vec<u64> = OFFERS_BY_TOKEN.with(|o| {
+            o.borrow()
+                .range(StorableBytes(marketplace::offer_index_key(token_id, start))..)
+                .take_while(|(key, _)| key.0.starts_with(&token_id.to_be_bytes()))
+                .take(take)
+                .map(|(key, _)| u64::from_be_bytes(key.0[8..].try_into().unwrap()))
+                .collect()
+        });
+        OFFERS.with(|o| ids.into_iter().filter_map(|id| o.borrow().get(&StorableNat(id))).collect())
+    } else {
+        OFFERS.with(|o| {
+            o.borrow()
+                .range(StorableNat(start)..)
+                .map(|(_, offer)| offer)
+                .filter(|offer| offer.token_id == token_id)
+                .take(take)
+                .collect()
+        })
+    };
+    let now = ic_cdk::api::time();
+    offers
+        .into_iter()
+        .map(|mut offer| {
+            offer.status = offer.status_at(now);
+            offer
+        })
+        .collect()
 }
 
-/// Get controller record for a specific NFT
 #[query]
-fn get_nft_controllers(token_id: u64) -> Option<NFTControllerRecord> {
-    CONTROLLER_RECORDS.with(|r| r.borrow().get(&StorableNat(token_id)))
+fn get_marketplace_config() -> MarketplaceConfig {
+    MARKET_CONFIG.with(|c| c.borrow().get().clone())
 }
 
-/// Set backend canister IDs that should be controllers of all NFTs
 #[update]
-fn set_backend_canisters(canisters: Vec<Principal>) -> Result<(), String> {
-    let caller = ic_cdk::caller();
-    
-    if !is_admin(caller) {
-        return Err("Only admin can set backend canisters".to_string());
+fn set_marketplace_config(treasury: Principal, platform_fee_bps: u16) -> Result<(), String> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err("Only admin can update the marketplace".to_string());
     }
-    
-    CONTROLLER_CONFIG.with(|c| {
+    if platform_fee_bps > marketplace::MAX_PLATFORM_FEE_BPS {
+        return Err("Platform fee cannot exceed 10%".to_string());
+    }
+    MARKET_CONFIG.with(|c| {
         let mut config = c.borrow().get().clone();
-        config.backend_canisters = canisters;
+        config.treasury = treasury;
+        config.platform_fee_bps = platform_fee_bps;
         c.borrow_mut().set(config).unwrap();
     });
-    
     Ok(())
 }
 
-/// Set frontend canister ID that should be a controller
+/// Payouts not yet paid, including those that ran out of attempts
+#[query]
+fn get_unpaid_payouts() -> Vec<Payout> {
+    PAYOUTS.with(|p| p.borrow().iter().map(|(_, payout)| payout).filter(|payout| payout.paid_block.is_none()).collect())
+}
+
+/// Retry unpaid payouts (admin only); `reset_attempts` also retries those that ran
+/// out of attempts
 #[update]
-fn set_frontend_canister(canister: Principal) -> Result<(), String> {
+async fn process_payouts(limit: Option<u32>, reset_attempts: bool) -> Result<Vec<Payout>, String> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err("Only admin can process payouts".to_string());
+    }
+    if reset_attempts {
+        PAYOUTS.with(|p| {
+            let mut payouts = p.borrow_mut();
+            let stuck: Vec<Payout> = payouts.iter().map(|(_, payout)| payout).filter(|payout| payout.paid_block.is_none()).collect();
+            for mut payout in stuck {
+                payout.attempts = 0;
+                payouts.insert(StorableNat(payout.id), payout);
+            }
+        });
+    }
+    Ok(pay_due_payouts(limit.unwrap_or(20) as usize).await)
+}
+
+// === Token Lifecycle ===
+
+/// Burn a token (owner or admin). It leaves the collection and the supply; its
+/// tombstone is returned by `get_burned_token`. Returns the ICRC-3 block index.
+#[update]
+fn burn(token_id: Nat) -> Result<Nat, String> {
     let caller = ic_cdk::caller();
-    
-    if !is_admin(caller) {
-        return Err("Only admin can set frontend canister".to_string());
+    let token_id = nat_to_token_id(token_id)?;
+    let own = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).ok_or("Token not found")?;
+    if own.owner != caller && !is_admin(caller) {
+        return Err("Only the owner or an admin can burn a token".to_string());
     }
-    
-    CONTROLLER_CONFIG.with(|c| {
-        let mut config = c.borrow().get().clone();
-        config.frontend_canister = Some(canister);
-        c.borrow_mut().set(config).unwrap();
-    });
-    
-    Ok(())
+    if is_escrowed(&own) {
+        return Err("This token is bridged out and must return before it can be burned".to_string());
+    }
+    if sale_in_flight(token_id) {
+        return Err("A sale of this token is in progress".to_string());
+    }
+    let metadata = METADATA.with(|m| m.borrow().get(&StorableNat(token_id))).ok_or("Token metadata not found")?;
+    let metadata = with_rarity(token_id, metadata);
+
+    let now = ic_cdk::api::time();
+    let owner = owner_account(&own);
+    TOKENS.with(|t| t.borrow_mut().remove(&StorableNat(token_id)));
+    METADATA.with(|m| m.borrow_mut().remove(&StorableNat(token_id)));
+    SOULBOUND.with(|s| s.borrow_mut().remove(&StorableNat(token_id)));
+    remove_from_owner(own.owner, token_id);
+    clear_token_approvals(token_id);
+    token_moved(token_id);
+    update_trait_stats(token_id, |stats| stats.remove(&metadata.attributes));
+    rerank(RERANK_DELAY);
+
+    let block = log_tx(&Tx::new(Op::Burn { token_id, from: owner.clone() }), now);
+    let tombstone = Tombstone { token_id, owner, burned_by: caller, burned_at: now, block, metadata };
+    TOMBSTONES.with(|t| t.borrow_mut().insert(StorableNat(token_id), tombstone));
+    Ok(Nat::from(block))
 }
 
-/// Add an admin principal
+#[query]
+fn get_burned_token(token_id: Nat) -> Option<Tombstone> {
+    let id: u64 = token_id.0.try_into().ok()?;
+    TOMBSTONES.with(|t| t.borrow().get(&StorableNat(id)))
+}
+
+/// Superseded metadata versions of a token, oldest first
+#[query]
+fn get_metadata_history(token_id: Nat) -> Vec<MetadataVersion> {
+    let Ok(id) = nat_to_token_id(token_id) else { return Vec::new() };
+    let prefix = id.to_be_bytes().to_vec();
+    METADATA_HISTORY.with(|h| {
+        h.borrow()
+            .range(StorableBytes(prefix.clone())..)
+            .take_while(|(key, _)| key.0.starts_with(&prefix))
+            .map(|(_, version)| version)
+            .collect()
+    })
+}
+
+/// Replace the editable metadata of a token, keeping the current version in its history (admin only)
 #[update]
-fn add_admin_principal(principal: Principal) -> Result<(), String> {
+fn update_metadata(token_id: Nat, update: lifecycle::MetadataUpdate) -> Result<NFTMetadata, String> {
     let caller = ic_cdk::caller();
-    
     if !is_admin(caller) {
-        return Err("Only admin can add admin principals".to_string());
+        return Err("Only admin can update metadata".to_string());
     }
-    
-    CONTROLLER_CONFIG.with(|c| {
-        let mut config = c.borrow().get().clone();
-        if !config.admin_principals.contains(&principal) {
-            config.admin_principals.push(principal);
-        }
-        c.borrow_mut().set(config).unwrap();
+    let token_id = nat_to_token_id(token_id)?;
+    let current = METADATA.with(|m| m.borrow().get(&StorableNat(token_id))).ok_or("Token not found")?;
+    let current = with_rarity(token_id, current);
+    let updated = lifecycle::apply_update(&current, update)?;
+    let previous = current.attributes.clone();
+
+    let now = ic_cdk::api::time();
+    let version = get_metadata_history(Nat::from(token_id)).len() as u32 + 1;
+    let superseded = MetadataVersion { version, metadata: current, superseded_at: now, superseded_by: caller };
+    METADATA_HISTORY.with(|h| h.borrow_mut().insert(StorableBytes(lifecycle::history_key(token_id, version)), superseded));
+    METADATA.with(|m| m.borrow_mut().insert(StorableNat(token_id), updated.clone()));
+    update_trait_stats(token_id, |stats| {
+        stats.remove(&previous);
+        stats.add(&updated.attributes);
     });
-    
-    Ok(())
+    rerank(RERANK_DELAY);
+
+    let meta = Value::Map(token_metadata(token_id).unwrap_or_default());
+    log_tx(&Tx::new(Op::UpdateToken { token_id, from: Account::new(caller, None), meta }), now);
+    Ok(with_rarity(token_id, updated))
 }
 
-/// Remove an admin principal
+#[query]
+fn is_soulbound(token_id: Nat) -> bool {
+    let Ok(id) = nat_to_token_id(token_id) else { return false };
+    SOULBOUND.with(|s| s.borrow().contains_key(&StorableNat(id)))
+}
+
+/// Make a token non-transferable, or transferable again (admin only). Binding
+/// cancels the token's listing and approvals.
 #[update]
-fn remove_admin_principal(principal: Principal) -> Result<(), String> {
-    let caller = ic_cdk::caller();
-    
-    if !is_admin(caller) {
-        return Err("Only admin can remove admin principals".to_string());
+fn set_soulbound(token_id: Nat, soulbound: bool) -> Result<(), String> {
+    if !is_admin(ic_cdk::caller()) {
+        return Err("Only admin can change soulbound tokens".to_string());
     }
-    
-    // Prevent removing the primary admin
-    if principal.to_text() == ADMIN_PRINCIPAL {
-        return Err("Cannot remove primary admin".to_string());
+    let token_id = nat_to_token_id(token_id)?;
+    let own = TOKENS.with(|t| t.borrow().get(&StorableNat(token_id))).ok_or("Token not found")?;
+    if !soulbound {
+        SOULBOUND.with(|s| s.borrow_mut().remove(&StorableNat(token_id)));
+        return Ok(());
     }
-    
-    CONTROLLER_CONFIG.with(|c| {
-        let mut config = c.borrow().get().clone();
-        config.admin_principals.retain(|p| *p != principal);
-        c.borrow_mut().set(config).unwrap();
-    });
-    
+    if is_escrowed(&own) {
+        return Err("This token is bridged out".to_string());
+    }
+    if sale_in_flight(token_id) {
+        return Err("A sale of this token is in progress".to_string());
+    }
+    SOULBOUND.with(|s| s.borrow_mut().insert(StorableNat(token_id), ic_cdk::api::time()));
+    clear_token_approvals(token_id);
+    token_moved(token_id);
     Ok(())
 }
 
-/// Register controller record for a minted NFT
-#[update]
-fn register_nft_controllers(token_id: u64, canister_id: Option<Principal>) -> Result<NFTControllerRecord, String> {
-    let caller = ic_cdk::caller();
-    
-    if !is_admin(caller) && !is_controller(caller) {
-        return Err("Not authorized to register controllers".to_string());
+// === Rarity ===
+
+/// Apply a token's change to the trait table, unless the backfill is still to
+/// count the token
+fn update_trait_stats(token_id: u64, change: impl FnOnce(&mut TraitStats)) {
+    if TRAIT_BACKFILL.with(|b| b.borrow().get().is_pending(token_id)) {
+        return;
     }
-    
-    // Get the NFT owner
-    let owner = TOKENS.with(|t| {
-        t.borrow().get(&StorableNat(token_id)).map(|o| o.owner)
-    }).ok_or("NFT not found")?;
-    
-    // Build controller list
-    let config = CONTROLLER_CONFIG.with(|c| c.borrow().get().clone());
-    let controllers = build_controller_list(&config, owner, ic_cdk::id());
-    
-    let record = NFTControllerRecord {
-        token_id,
-        canister_id,
-        controllers: controllers.clone(),
-        owner,
-        created_at: ic_cdk::api::time(),
-        last_updated: ic_cdk::api::time(),
-    };
-    
-    CONTROLLER_RECORDS.with(|r| {
-        r.borrow_mut().insert(StorableNat(token_id), record.clone());
+    TRAIT_STATS.with(|s| {
+        let mut stats = s.borrow().get().clone();
+        change(&mut stats);
+        s.borrow_mut().set(stats).unwrap();
     });
-    
-    Ok(record)
 }
 
-/// Update controllers for an NFT (e.g., after transfer)
-#[update]
-fn update_nft_controllers(token_id: u64) -> Result<NFTControllerRecord, String> {
-    let caller = ic_cdk::caller();
-    
-    if !is_admin(caller) && !is_controller(caller) {
-        return Err("Not authorized to update controllers".to_string());
-    }
-    
-    // Get existing record
-    let existing = CONTROLLER_RECORDS.with(|r| r.borrow().get(&StorableNat(token_id)));
-    
-    // Get current owner
-    let owner = TOKENS.with(|t| {
-        t.borrow().get(&StorableNat(token_id)).map(|o| o.owner)
-    }).ok_or("NFT not found")?;
-    
-    // Rebuild controller list
-    let config = CONTROLLER_CONFIG.with(|c| c.borrow().get().clone());
-    let controllers = build_controller_list(&config, owner, ic_cdk::id());
-    
-    let record = NFTControllerRecord {
-        token_id,
-        canister_id: existing.as_ref().and_then(|e| e.canister_id),
-        controllers: controllers.clone(),
-        owner,
-        created_at: existing.map(|e| e.created_at).unwrap_or(ic_cdk::api::time()),
-        last_updated: ic_cdk::api::time(),
+/// Count the next batch of pre-rarity tokens in the trait table
+fn backfill_trait_stats() {
+    let mut backfill = TRAIT_BACKFILL.with(|b| b.borrow().get().clone());
+    let batch: Vec<(u64, Vec<Trait>)> = METADATA.with(|m| {
+        m.borrow()
+            .range(StorableNat(backfill.next)..StorableNat(backfill.end))
+            .take(TRAIT_BACKFILL_BATCH)
+            .map(|(id, meta)| (id.0, meta.attributes))
+            .collect()
+    });
+    TRAIT_STATS.with(|s| {
+        let mut stats = s.borrow().get().clone();
+        batch.iter().for_each(|(_, attributes)| stats.add(attributes));
+        s.borrow_mut().set(stats).unwrap();
+    });
+    backfill.next = match batch.last() {
+        Some((token_id, _)) if batch.len() == TRAIT_BACKFILL_BATCH => token_id + 1,
+        _ => backfill.end,
     };
-    
-    CONTROLLER_RECORDS.with(|r| {
-        r.borrow_mut().insert(StorableNat(token_id), record.clone());
+    TRAIT_BACKFILL.with(|b| b.borrow_mut().set(backfill.clone()).unwrap());
+    if backfill.is_done() {
+        rerank(std::time::Duration::ZERO);
+    } else {
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, backfill_trait_stats);
+    }
+}
+
+/// Rebuild the rank index `delay` from now, after the trait table changed; a
+/// change while a rebuild is scoring tokens queues another one
+fn rerank(delay: std::time::Duration) {
+    let scoring = RANK_BUILD.with(|b| b.borrow().as_ref().map(|(_, scores)| !scores.is_empty()));
+    match scoring {
+        Some(true) => RANKS_STALE.with(|s| s.set(true)),
+        Some(false) => {} // Not started: it will see this change
+        None => {
+            RANK_BUILD.with(|b| *b.borrow_mut() = Some((0, Vec::new())));
+            ic_cdk_timers::set_timer(delay, rebuild_ranks_step);
+        }
+    }
+}
+
+/// Score the next batch of tokens; the last batch replaces the rank index
+fn rebuild_ranks_step() {
+    let Some((next, mut scores)) = RANK_BUILD.with(|b| b.borrow_mut().take()) else { return };
+    let batch: Vec<(u64, f64)> = TRAIT_STATS.with(|s| {
+        let stats = s.borrow();
+        METADATA.with(|m| {
+            m.borrow()
+                .range(StorableNat(next)..)
+                .take(RANK_BATCH)
+                .map(|(id, meta)| (id.0, stats.get().score(&meta.attributes)))
+                .collect()
+        })
     });
-    
-    Ok(record)
+    let done = batch.len() < RANK_BATCH;
+    let next = batch.last().map(|(id, _)| id + 1).unwrap_or(next);
+    scores.extend(batch);
+    if !done {
+        RANK_BUILD.with(|b| *b.borrow_mut() = Some((next, scores)));
+        ic_cdk_timers::set_timer(std::time::Duration::ZERO, rebuild_ranks_step);
+        return;
+    }
+    RANKS.with(|r| *r.borrow_mut() = RankIndex::build(scores));
+    if RANKS_STALE.with(|s| s.replace(false)) {
+        rerank(RERANK_DELAY);
+    }
 }
 
-/// Get all NFTs with their controller records
-#[query]
-fn get_all_controller_records() -> Vec<NFTControllerRecord> {
-    CONTROLLER_RECORDS.with(|r| {
-        r.borrow().iter().map(|(_, record)| record).collect()
-    })
+/// Rarity of a token from its current score, placed in the latest rank index; with
+/// no rank index yet, the score alone with the rank pending
+fn token_rarity(token_id: u64, attributes: &[Trait]) -> TokenRarity {
+    let (score, total, traits) = TRAIT_STATS.with(|s| {
+        let stats = s.borrow();
+        let stats = stats.get();
+        (stats.score(attributes), stats.tokens, stats.trait_rarities(attributes))
+    });
+    let rank = RANKS.with(|r| r.borrow().rank_of(score));
+    TokenRarity {
+        token_id,
+        rarity: rank.map_or(Rarity::Common, |rank| rarity::tier(rank, total)),
+        score,
+        rarity_score: rarity::scaled(score),
+        rank: rank.unwrap_or(0),
+        total,
+        traits,
+        rank_pending: rank.is_none(),
+    }
 }
 
-/// Check if a principal is an authorized controller for any NFT operations
+/// Metadata with the rarity the engine currently derives, in place of what was stored at mint
+fn with_rarity(token_id: u64, mut meta: NFTMetadata) -> NFTMetadata {
+    TRAIT_STATS.with(|s| {
+        let stats = s.borrow();
+        for attr in meta.attributes.iter_mut() {
+            attr.rarity_score = stats.get().trait_score(&attr.trait_type, &attr.value);
+        }
+    });
+    let rarity = token_rarity(token_id, &meta.attributes);
+    meta.rarity_score = rarity.rarity_score;
+    if !rarity.rank_pending {
+        meta.rarity = rarity.rarity; // Otherwise the tier stored at mint stands
+    }
+    meta
+}
+
+/// Rarity tier, score and rank of a token within the collection
 #[query]
-fn is_authorized_nft_controller(principal: Principal) -> bool {
-    is_controller(principal)
+fn get_rarity(token_id: Nat) -> Option<TokenRarity> {
+    let id: u64 = token_id.0.try_into().ok()?;
+    let meta = METADATA.with(|m| m.borrow().get(&StorableNat(id)))?;
+    Some(token_rarity(id, &meta.attributes))
 }
 
-/// Get list of all backend canister controllers
+/// Tokens from rarest to most common, starting at rank position `start` (0-based)
 #[query]
-fn get_backend_controllers() -> Vec<Principal> {
-    CONTROLLER_CONFIG.with(|c| c.borrow().get().backend_canisters.clone())
+fn get_rarity_ranking(start: u64, take: Option<Nat>) -> Vec<TokenRarity> {
+    let token_ids = RANKS.with(|r| r.borrow().page(start as usize, icrc::take(take)));
+    token_ids.into_iter()
+        .filter_map(|id| {
+            let meta = METADATA.with(|m| m.borrow().get(&StorableNat(id)))?;
+            Some(token_rarity(id, &meta.attributes))
+        })
+        .collect()
 }
 
-/// Get list of all admin principals
+/// Trait frequency table the rarity engine scores tokens against
 #[query]
-fn get_admin_principals() -> Vec<Principal> {
-    CONTROLLER_CONFIG.with(|c| c.borrow().get().admin_principals.clone())
+fn get_trait_stats() -> TraitStats {
+    TRAIT_STATS.with(|s| s.borrow().get().clone())
 }
 
 // Generate Candid
 ic_cdk::export_candid!();
-
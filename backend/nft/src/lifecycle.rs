@@ -0,0 +1,113 @@
+//! Token Lifecycle
+//!
+//! After mint, an admin can correct or evolve a token's metadata. Every superseded
+//! version is kept, so what a token looked like at any time stays auditable.
+//!
+//! The owner or an admin can burn a token: it leaves the collection and the supply,
+//! and a tombstone keeps its last owner and metadata. Token ids are never reused.
+//!
+//! Soulbound tokens, such as driver certifications and KYC badges, stay with the
+//! account they were issued to: they cannot be transferred, approved, listed or
+//! bridged. They can still be burned, which is how a badge is revoked.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::icrc::Account;
+use crate::{NFTMetadata, Trait};
+
+/// Editable fields of a token's metadata; collection, creator and creation time are fixed
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MetadataUpdate {
+    pub name: String,
+    pub description: String,
+    pub image: String,
+    pub external_url: Option<String>,
+    pub attributes: Vec<Trait>,
+}
+
+/// A superseded version of a token's metadata. Version 1 is the metadata at mint.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MetadataVersion {
+    pub version: u32,
+    pub metadata: NFTMetadata,
+    pub superseded_at: u64,
+    pub superseded_by: Principal,
+}
+
+/// What remains of a burned token
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Tombstone {
+    pub token_id: u64,
+    pub owner: Account,
+    pub burned_by: Principal,
+    pub burned_at: u64,
+    pub block: u64, // ICRC-3 block of the burn
+    pub metadata: NFTMetadata,
+}
+
+/// Metadata after `update`; the rarity engine rescores the new attributes
+pub fn apply_update(current: &NFTMetadata, update: MetadataUpdate) -> Result<NFTMetadata, String> {
+    if update.name.trim().is_empty() {
+        return Err("Name cannot be empty".to_string());
+    }
+    Ok(NFTMetadata {
+        name: update.name,
+        description: update.description,
+        image: update.image,
+        external_url: update.external_url,
+        attributes: update.attributes,
+        ..current.clone()
+    })
+}
+
+/// Storage key of a superseded version; a token's versions share its prefix, in order
+pub fn history_key(token_id: u64, version: u32) -> Vec<u8> {
+    [&token_id.to_be_bytes()[..], &version.to_be_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rarity;
+
+    fn metadata() -> NFTMetadata {
+        NFTMetadata {
+            name: "Driver Certification".to_string(),
+            description: "Level 1".to_string(),
+            image: "ipfs://cert".to_string(),
+            external_url: None,
+            attributes: Vec::new(),
+            rarity: Rarity::Common,
+            rarity_score: 0,
+            collection: "IC Spicy Collection".to_string(),
+            created_at: 42,
+            creator: Principal::from_slice(&[9, 1]),
+        }
+    }
+
+    #[test]
+    fn test_apply_update_keeps_identity() {
+        let current = metadata();
+        let update = MetadataUpdate {
+            name: "Driver Certification".to_string(),
+            description: "Level 2".to_string(),
+            image: "ipfs://cert-2".to_string(),
+            external_url: Some("https://example.com/cert".to_string()),
+            attributes: vec![Trait { trait_type: "Level".to_string(), value: "2".to_string(), rarity_score: 50 }],
+        };
+        let updated = apply_update(&current, update.clone()).unwrap();
+        assert_eq!(updated.description, "Level 2");
+        assert_eq!(updated.attributes.len(), 1);
+        assert_eq!((updated.created_at, updated.creator, updated.collection), (42, current.creator, current.collection.clone()));
+
+        assert!(apply_update(&current, MetadataUpdate { name: " ".to_string(), ..update }).is_err());
+    }
+
+    #[test]
+    fn test_history_keys_order_versions_per_token() {
+        assert!(history_key(1, 2) < history_key(1, 10));
+        assert!(history_key(1, u32::MAX) < history_key(2, 1));
+        assert!(history_key(7, 3).starts_with(&7u64.to_be_bytes()));
+    }
+}
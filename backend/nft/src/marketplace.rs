@@ -0,0 +1,502 @@
+//! Native Marketplace
+//!
+//! Owners list tokens at a fixed price in ICP, ckBTC or ckUSDC, optionally until an
+//! expiry; buyers can also make offers, which the owner may accept. The buyer
+//! approves this canister on the payment ledger (ICRC-2) for the price plus the
+//! ledger fee.
+//!
+//! Settlement pulls the whole price into this canister with one `icrc2_transfer_from`,
+//! so a sale either happens completely or not at all; the token then moves to the
+//! buyer in the same call. The price is paid out as the collection royalty to the
+//! token's creator, the platform fee to the treasury and the rest to the seller.
+//! Each sale pays its own payouts right away; the rest are ICRC-1 transfers retried
+//! by `process_payouts`. A payout is claimed by one call at a time, and the ledger's
+//! deduplication (same memo and `created_at_time`) keeps retries from paying twice.
+//! Once a transfer is older than the ledger's dedup window, the ledger's blocks
+//! since it was issued are searched for its memo before it is re-issued with a
+//! fresh `created_at_time`.
+//!
+//! A listing is cancelled as soon as its token moves by any other means. If the
+//! listing or offer changed while the price was collected, the price is refunded
+//! to the buyer as a payout instead.
+
+use candid::{CandidType, Nat, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::icrc::{Account, GetBlocksArgs, GetBlocksResult, Value, PERMITTED_DRIFT, TX_WINDOW};
+pub use crate::multichain::ListingStatus;
+
+const ICP_LEDGER_ID: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+const CKBTC_LEDGER_ID: &str = "mxzaz-hqaaa-aaaar-qaada-cai";
+const CKUSDC_LEDGER_ID: &str = "xevnm-gaaaa-aaaar-qafnq-cai";
+pub const DEFAULT_TREASURY: &str = "3rk2d-6yaaa-aaaao-a4xba-cai";
+pub const DEFAULT_PLATFORM_FEE_BPS: u16 = 250; // 2.5%
+pub const MAX_PLATFORM_FEE_BPS: u16 = 1000;
+/// Payout attempts before a payout is left for the admin to look at
+pub const MAX_PAYOUT_ATTEMPTS: u32 = 10;
+/// Ledger blocks read per `icrc3_get_blocks` call when searching for a transfer
+const BLOCKS_PER_PAGE: u64 = 2_000;
+/// Offers indexed per step of the offers-by-token index backfill
+pub const OFFER_INDEX_BATCH: usize = 500;
+/// Ledger blocks searched per payout attempt; the search resumes on the next one
+const MAX_SEARCH_BLOCKS: u64 = 20_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum PaymentToken {
+    ICP,
+    CkBTC,
+    CkUSDC,
+}
+
+impl PaymentToken {
+    pub fn ledger(&self) -> Principal {
+        let id = match self {
+            PaymentToken::ICP => ICP_LEDGER_ID,
+            PaymentToken::CkBTC => CKBTC_LEDGER_ID,
+            PaymentToken::CkUSDC => CKUSDC_LEDGER_ID,
+        };
+        Principal::from_text(id).expect("valid ledger id")
+    }
+
+    /// Ledger transfer fee in the token's smallest unit
+    pub fn fee(&self) -> u64 {
+        match self {
+            PaymentToken::ICP => 10_000,
+            PaymentToken::CkBTC => 10,
+            PaymentToken::CkUSDC => 10_000,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Listing {
+    pub token_id: u64,
+    pub seller: Principal,
+    pub token: PaymentToken,
+    pub price: u64, // Smallest unit of `token` (e8s, satoshis, micro-USDC)
+    pub status: ListingStatus,
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+    pub sale_id: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum OfferStatus {
+    Open,
+    Accepted,
+    Withdrawn,
+    Expired,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Offer {
+    pub id: u64,
+    pub token_id: u64,
+    pub buyer: Principal,
+    pub token: PaymentToken,
+    pub amount: u64,
+    pub status: OfferStatus,
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+}
+
+/// A settled sale
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Sale {
+    pub id: u64,
+    pub token_id: u64,
+    pub seller: Principal,
+    pub buyer: Principal,
+    pub token: PaymentToken,
+    pub price: u64,
+    pub royalty: u64,
+    pub platform_fee: u64,
+    pub offer_id: Option<u64>, // None for a listing purchase
+    pub settled_at: u64,
+}
+
+/// One share of a sale price owed by this canister
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Payout {
+    pub id: u64,
+    pub sale_id: u64,
+    pub to: Principal,
+    pub token: PaymentToken,
+    pub amount: u64, // Net of the ledger fee
+    pub created_at: u64,
+    pub attempts: u32,
+    pub paid_block: Option<Nat>,
+    pub last_error: Option<String>,
+    pub issued_at: Option<u64>,   // `created_at_time` of the current transfer; None = created_at
+    pub search_from: Option<u64>, // First ledger block that could hold it
+}
+
+/// Progress of indexing the offers made before the offers-by-token index existed
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct OfferIndexBackfill {
+    pub next: u64, // First offer id not yet indexed
+    pub done: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MarketplaceConfig {
+    pub treasury: Principal,
+    pub platform_fee_bps: u16,
+    pub next_id: u64, // Shared by offers, sales and payouts
+}
+
+impl Default for MarketplaceConfig {
+    fn default() -> Self {
+        Self {
+            treasury: Principal::from_text(DEFAULT_TREASURY).expect("valid treasury id"),
+            platform_fee_bps: DEFAULT_PLATFORM_FEE_BPS,
+            next_id: 1,
+        }
+    }
+}
+
+/// Gross shares of a sale price
+#[derive(Clone, Debug, PartialEq)]
+pub struct Split {
+    pub seller: u64,
+    pub royalty: u64,
+    pub platform: u64,
+}
+
+/// Split a price into seller, royalty and platform shares. Each share is paid by
+/// one ledger transfer whose fee it bears; royalty or platform shares too small to
+/// cover that fee are left to the seller.
+pub fn split(price: u64, royalty_bps: u16, platform_fee_bps: u16, ledger_fee: u64) -> Result<Split, String> {
+    let share = |bps: u16| {
+        let share = (price as u128 * bps as u128 / 10_000) as u64;
+        if share > ledger_fee { share } else { 0 }
+    };
+    let royalty = share(royalty_bps);
+    let platform = share(platform_fee_bps);
+    let seller = price - royalty - platform;
+    if seller <= ledger_fee {
+        return Err("Price is too low to cover the ledger fees".to_string());
+    }
+    Ok(Split { seller, royalty, platform })
+}
+
+fn is_expired(expires_at: Option<u64>, now: u64) -> bool {
+    expires_at.is_some_and(|at| at <= now)
+}
+
+impl Listing {
+    /// Status as of `now`: an active listing past its expiry is expired
+    pub fn status_at(&self, now: u64) -> ListingStatus {
+        match self.status {
+            ListingStatus::Active if is_expired(self.expires_at, now) => ListingStatus::Expired,
+            ref status => status.clone(),
+        }
+    }
+}
+
+/// Offers-by-token index key: offers of one token are adjacent, oldest first
+pub fn offer_index_key(token_id: u64, offer_id: u64) -> Vec<u8> {
+    [&token_id.to_be_bytes()[..], &offer_id.to_be_bytes()].concat()
+}
+
+impl Payout {
+    pub fn is_due(&self) -> bool {
+        self.paid_block.is_none() && self.attempts < MAX_PAYOUT_ATTEMPTS
+    }
+}
+
+impl Offer {
+    pub fn status_at(&self, now: u64) -> OfferStatus {
+        match self.status {
+            OfferStatus::Open if is_expired(self.expires_at, now) => OfferStatus::Expired,
+            ref status => status.clone(),
+        }
+    }
+}
+
+pub fn validate_expiry(expires_at: Option<u64>, now: u64) -> Result<(), String> {
+    if is_expired(expires_at, now) {
+        return Err("Expiry must be in the future".to_string());
+    }
+    Ok(())
+}
+
+// === Ledger calls ===
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct TransferFromArgs {
+    spender_subaccount: Option<Vec<u8>>,
+    from: Account,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct LedgerTransferArg {
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+/// ICRC-1 and ICRC-2 transfer errors
+#[derive(CandidType, Deserialize, Clone, Debug)]
+enum LedgerError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+/// Pull `amount` from `from` into this canister's main account
+pub async fn collect(token: PaymentToken, from: Principal, amount: u64, memo: u64, now: u64) -> Result<Nat, String> {
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account { owner: from, subaccount: None },
+        to: Account { owner: ic_cdk::id(), subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(memo.to_be_bytes().to_vec()),
+        created_at_time: Some(now),
+    };
+    let (result,): (Result<Nat, LedgerError>,) = ic_cdk::call(token.ledger(), "icrc2_transfer_from", (args,))
+        .await
+        .map_err(|(code, msg)| format!("Failed to call the {:?} ledger: {:?} - {}", token, code, msg))?;
+    result.map_err(|e| match e {
+        LedgerError::InsufficientAllowance { allowance } => format!("Approve this canister for the price plus fee (allowance is {})", allowance),
+        LedgerError::InsufficientFunds { balance } => format!("Insufficient funds (balance is {})", balance),
+        other => format!("Payment failed: {:?}", other),
+    })
+}
+
+/// Pay a payout. Within the ledger's dedup window a retry reuses the transfer's
+/// `created_at_time`, so a ledger duplicate means an earlier attempt already paid
+/// it. Past the window the transfer is looked for in the ledger's blocks and only
+/// re-issued, with a fresh `created_at_time`, if it is not there. `Ok(None)` means
+/// the search is not finished; `search_from` keeps its progress for the next attempt.
+pub async fn pay(payout: &mut Payout, now: u64) -> Result<Option<Nat>, String> {
+    let issued_at = payout.issued_at.unwrap_or(payout.created_at);
+    let expires_at = issued_at.saturating_add(TX_WINDOW + PERMITTED_DRIFT);
+    if now < expires_at {
+        return transfer(payout, issued_at).await.map(Some);
+    }
+
+    let ledger = payout.token.ledger();
+    let mut from = match payout.search_from {
+        Some(from) => from,
+        None => first_block_since(ledger, issued_at.saturating_sub(PERMITTED_DRIFT)).await?,
+    };
+    let mut searched = 0;
+    loop {
+        let (log_length, blocks) = get_blocks(ledger, from, BLOCKS_PER_PAGE).await?;
+        if let Some((id, _)) = blocks.iter().find(|(_, block)| is_payout_transfer(block, payout, ic_cdk::id())) {
+            return Ok(Some(Nat::from(*id)));
+        }
+        let past_window = blocks
+            .last()
+            .and_then(|(_, block)| block_time(block))
+            .is_some_and(|time| time >= expires_at);
+        from = match blocks.last() {
+            Some((id, _)) => id + 1,
+            None if from < log_length => return Err(format!("The {:?} ledger returned no block {}", payout.token, from)),
+            None => from,
+        };
+        payout.search_from = Some(from);
+        if past_window || from >= log_length {
+            break;
+        }
+        searched += BLOCKS_PER_PAGE;
+        if searched >= MAX_SEARCH_BLOCKS {
+            return Ok(None);
+        }
+    }
+
+    // Never paid: the new transfer can only land after the blocks searched
+    payout.issued_at = Some(now);
+    transfer(payout, now).await.map(Some)
+}
+
+async fn transfer(payout: &Payout, created_at_time: u64) -> Result<Nat, String> {
+    let args = LedgerTransferArg {
+        from_subaccount: None,
+        to: Account { owner: payout.to, subaccount: None },
+        amount: Nat::from(payout.amount),
+        fee: None,
+        memo: Some(payout.id.to_be_bytes().to_vec()),
+        created_at_time: Some(created_at_time),
+    };
+    let (result,): (Result<Nat, LedgerError>,) = ic_cdk::call(payout.token.ledger(), "icrc1_transfer", (args,))
+        .await
+        .map_err(|(code, msg)| format!("Failed to call the {:?} ledger: {:?} - {}", payout.token, code, msg))?;
+    match result {
+        Ok(block) | Err(LedgerError::Duplicate { duplicate_of: block }) => Ok(block),
+        Err(e) => Err(format!("Payout failed: {:?}", e)),
+    }
+}
+
+fn nat_to_u64(n: Nat) -> u64 {
+    n.0.try_into().unwrap_or(u64::MAX)
+}
+
+/// A ledger's blocks from `start` on, archived ones included, and its log length
+async fn get_blocks(ledger: Principal, start: u64, length: u64) -> Result<(u64, Vec<(u64, Value)>), String> {
+    let args = vec![GetBlocksArgs { start: Nat::from(start), length: Nat::from(length) }];
+    let (result,): (GetBlocksResult,) = ic_cdk::call(ledger, "icrc3_get_blocks", (args,))
+        .await
+        .map_err(|(code, msg)| format!("Failed to read the ledger's blocks: {:?} - {}", code, msg))?;
+    let mut blocks = Vec::new();
+    for archived in result.archived_blocks {
+        let (archive,): (GetBlocksResult,) = ic_cdk::call(archived.callback.0.principal, &archived.callback.0.method, (archived.args,))
+            .await
+            .map_err(|(code, msg)| format!("Failed to read the ledger's archive: {:?} - {}", code, msg))?;
+        blocks.extend(archive.blocks);
+    }
+    blocks.extend(result.blocks);
+    let mut blocks: Vec<(u64, Value)> = blocks.into_iter().map(|b| (nat_to_u64(b.id), b.block)).collect();
+    blocks.sort_by_key(|(id, _)| *id);
+    Ok((nat_to_u64(result.log_length), blocks))
+}
+
+/// First ledger block at or after `time`, by binary search over block timestamps
+async fn first_block_since(ledger: Principal, time: u64) -> Result<u64, String> {
+    let (mut low, mut high) = (0, get_blocks(ledger, 0, 0).await?.0);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let (_, blocks) = get_blocks(ledger, mid, 1).await?;
+        let block_at = blocks.first().and_then(|(_, block)| block_time(block)).ok_or(format!("Ledger block {} has no timestamp", mid))?;
+        if block_at < time {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(low)
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+        _ => None,
+    }
+}
+
+fn block_time(block: &Value) -> Option<u64> {
+    match field(block, "ts")? {
+        Value::Nat(ts) => Some(nat_to_u64(ts.clone())),
+        _ => None,
+    }
+}
+
+fn account_owner(account: Option<&Value>) -> Option<&[u8]> {
+    match account? {
+        Value::Array(parts) => match parts.first()? {
+            Value::Blob(owner) => Some(owner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a ledger block is `payer`'s transfer of `payout`
+fn is_payout_transfer(block: &Value, payout: &Payout, payer: Principal) -> bool {
+    let Some(tx) = field(block, "tx") else {
+        return false;
+    };
+    field(tx, "memo") == Some(&Value::Blob(payout.id.to_be_bytes().to_vec()))
+        && field(tx, "amt") == Some(&Value::nat(payout.amount))
+        && account_owner(field(tx, "from")) == Some(payer.as_slice())
+        && account_owner(field(tx, "to")) == Some(payout.to.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_split() {
+        // 1 ICP, 5% royalty, 2.5% platform fee
+        let s = split(100_000_000, 500, 250, 10_000).unwrap();
+        assert_eq!(s, Split { seller: 92_500_000, royalty: 5_000_000, platform: 2_500_000 });
+        assert_eq!(s.seller + s.royalty + s.platform, 100_000_000);
+
+        // Shares that would not cover their transfer fee stay with the seller
+        let s = split(300_000, 500, 250, 10_000).unwrap();
+        assert_eq!(s, Split { seller: 285_000, royalty: 15_000, platform: 0 });
+        assert!(split(10_000, 500, 250, 10_000).is_err());
+        assert_eq!(split(u64::MAX, 1000, 1000, 10).unwrap().royalty, u64::MAX / 10);
+    }
+
+    #[test]
+    fn test_listing_and_offer_expiry() {
+        let seller = Principal::from_slice(&[10, 1]);
+        let mut listing = Listing {
+            token_id: 1,
+            seller,
+            token: PaymentToken::CkUSDC,
+            price: 5_000_000,
+            status: ListingStatus::Active,
+            created_at: 0,
+            expires_at: Some(100),
+            sale_id: None,
+        };
+        assert_eq!(listing.status_at(99), ListingStatus::Active);
+        assert_eq!(listing.status_at(100), ListingStatus::Expired);
+        listing.status = ListingStatus::Sold;
+        assert_eq!(listing.status_at(200), ListingStatus::Sold);
+
+        let offer = Offer { id: 1, token_id: 1, buyer: seller, token: PaymentToken::ICP, amount: 1, status: OfferStatus::Open, created_at: 0, expires_at: None };
+        assert_eq!(offer.status_at(u64::MAX), OfferStatus::Open);
+        assert!(validate_expiry(Some(5), 5).is_err() && validate_expiry(None, 5).is_ok());
+        assert_eq!(PaymentToken::CkBTC.ledger().to_text(), CKBTC_LEDGER_ID);
+        assert!(offer_index_key(1, u64::MAX) < offer_index_key(2, 0) && offer_index_key(2, 3) < offer_index_key(2, 4));
+    }
+
+    #[test]
+    fn test_payout_found_in_ledger_block() {
+        let (canister, seller) = (Principal::from_slice(&[10, 9]), Principal::from_slice(&[10, 1]));
+        let payout = Payout {
+            id: 7,
+            sale_id: 6,
+            to: seller,
+            token: PaymentToken::ICP,
+            amount: 90_000,
+            created_at: 0,
+            attempts: 3,
+            paid_block: None,
+            last_error: None,
+            issued_at: None,
+            search_from: Some(40),
+        };
+        let block = |memo: u64, from: Principal| {
+            let account = |owner: Principal| Value::Array(vec![Value::Blob(owner.as_slice().to_vec())]);
+            let tx = Value::Map(vec![
+                ("op".into(), Value::text("xfer")),
+                ("from".into(), account(from)),
+                ("to".into(), account(seller)),
+                ("amt".into(), Value::nat(90_000)),
+                ("memo".into(), Value::Blob(memo.to_be_bytes().to_vec())),
+            ]);
+            Value::Map(vec![("ts".into(), Value::nat(5)), ("tx".into(), tx)])
+        };
+
+        assert!(is_payout_transfer(&block(7, canister), &payout, canister));
+        assert_eq!(block_time(&block(7, canister)), Some(5));
+        // Another payout's memo, or the same memo from someone else, is not it
+        assert!(!is_payout_transfer(&block(8, canister), &payout, canister));
+        assert!(!is_payout_transfer(&block(7, seller), &payout, canister));
+        assert!(!is_payout_transfer(&Value::Map(vec![]), &payout, canister));
+        assert!(payout.is_due());
+        assert!(!Payout { paid_block: Some(Nat::from(9u64)), ..payout.clone() }.is_due());
+        assert!(!Payout { attempts: MAX_PAYOUT_ATTEMPTS, ..payout }.is_due());
+    }
+}
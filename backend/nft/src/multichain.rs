@@ -460,7 +460,7 @@ pub struct ListingPrice {
     pub usd_equivalent: Option<f64>,
 }
 
-#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum ListingStatus {
     Active,
     Sold,
@@ -0,0 +1,516 @@
+//! Cross-Chain Mint Orchestrator
+//!
+//! `multichain_mint` mints the ICP token right away through `mint` and queues one
+//! job per other target chain. Jobs are advanced by `process_mint_jobs`:
+//! - `Pending`: the chain adapter submits the mint transaction
+//! - `Minting`: the adapter is asked for confirmation until it returns a result;
+//!   a transaction the chain dropped sends the job back to `Pending`, to be
+//!   submitted again with the same nonce
+//! - `Minted` / `Error`: final, with the `ChainMintResult` recorded
+//!
+//! Failed submissions and confirmation checks are retried with backoff up to
+//! `MAX_JOB_ATTEMPTS` times; resubmitting a dropped transaction is not a failure.
+//! Unfinished jobs are also indexed by chain, so finding due jobs and reserved
+//! nonces does not scan finished ones.
+//!
+//! Chain-specific work sits behind `ChainAdapter`; `evm::EvmAdapter` mints on EVM
+//! chains; `MockAdapter` stands in for real chains in tests and local deployments.
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+
+use crate::multichain::{
+    validate_address, Chain, ChainConfig, ChainMintConfig, ChainMintResult, MintCost, MintResultStatus,
+    MultiChainMetadata, MultiChainMintRequest, MultiChainMintResult, MultiChainNFT, NFTStandard, NFTStatus,
+};
+
+/// Submissions or confirmation checks per job before it fails
+pub const MAX_JOB_ATTEMPTS: u32 = 5;
+/// Delay before the first retry; doubled on every further attempt
+pub const RETRY_BACKOFF_NS: u64 = 30 * 1_000_000_000;
+/// Jobs advanced per `process_mint_jobs` call unless a limit is given
+pub const DEFAULT_JOBS_PER_CALL: u32 = 10;
+/// Jobs indexed per step of the open-job index backfill
+pub const JOB_INDEX_BATCH: usize = 500;
+
+/// Mint of one NFT on one non-ICP chain
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MintJob {
+    pub global_id: String,
+    pub target: ChainMintConfig,
+    pub recipient: String, // Address on the target chain
+    pub status: NFTStatus,
+    pub attempts: u32, // Failed submissions or confirmation checks so far
+    pub tx_hash: Option<String>,
+    pub nonce: Option<u64>,                      // Sender nonce of the transaction, kept for resubmissions
+    pub replaced_tx_hashes: Option<Vec<String>>, // Earlier submissions with the same nonce
+    pub result: Option<ChainMintResult>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Which chain adapters are available
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AdapterSettings {
+    pub mock_adapters: bool,            // Serve every non-ICP chain with `MockAdapter`
+    pub ecdsa_key_name: Option<String>, // Threshold ECDSA key of the EVM minter; None = `evm::DEFAULT_KEY_NAME`
+}
+
+/// Progress of indexing the jobs queued before the open-job index existed
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct JobIndexBackfill {
+    pub cursor: Option<String>, // Last job key indexed
+    pub done: bool,
+}
+
+/// `get_multichain_status` answer
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MultiChainMintStatus {
+    pub nft: MultiChainNFT,
+    pub result: MultiChainMintResult,
+    pub jobs: Vec<MintJob>,
+}
+
+/// A sent mint transaction
+#[derive(Clone, Debug, PartialEq)]
+pub struct Submission {
+    pub tx_hash: String,    // Or the chain's equivalent
+    pub nonce: Option<u64>, // For chains whose transactions are ordered by a sender nonce
+}
+
+/// State of a submitted mint
+#[derive(Clone, Debug)]
+pub enum Confirmation {
+    Pending,
+    Confirmed(Box<ChainMintResult>),
+    Dropped, // The chain will not include the transaction; submit it again
+}
+
+/// Chain-specific minting. Adapters run on the canister's single-threaded executor,
+/// so their futures need not be `Send`.
+#[allow(async_fn_in_trait)]
+pub trait ChainAdapter {
+    /// Submit the mint transaction, reusing `job.nonce` if it was submitted before
+    async fn submit(&self, job: &MintJob, metadata: &MultiChainMetadata) -> Result<Submission, String>;
+
+    /// Look up a submitted mint
+    async fn confirm(&self, job: &MintJob, now: u64) -> Result<Confirmation, String>;
+}
+
+impl MintJob {
+    pub fn new(global_id: &str, target: ChainMintConfig, recipient: &str, now: u64) -> Self {
+        Self {
+            global_id: global_id.to_string(),
+            target,
+            recipient: recipient.to_string(),
+            status: NFTStatus::Pending,
+            attempts: 0,
+            tx_hash: None,
+            nonce: None,
+            replaced_tx_hashes: None,
+            result: None,
+            last_error: None,
+            next_attempt_at: now,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Storage key: jobs of one NFT are adjacent
+    pub fn key(global_id: &str, chain: &Chain) -> String {
+        format!("{}/{}", global_id, chain)
+    }
+
+    /// Open-job index key: jobs of one chain are adjacent
+    pub fn open_key(chain: &Chain, key: &str) -> String {
+        format!("{}/{}", chain, key)
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self.status, NFTStatus::Pending | NFTStatus::Minting)
+    }
+
+    pub fn is_due(&self, now: u64) -> bool {
+        self.is_open() && self.next_attempt_at <= now
+    }
+
+    /// A failed step: retry later, or give up after `MAX_JOB_ATTEMPTS`
+    pub fn fail(&mut self, error: String, now: u64) {
+        self.attempts += 1;
+        self.updated_at = now;
+        if self.attempts >= MAX_JOB_ATTEMPTS {
+            self.result = Some(failed_result(&self.target, &error));
+            self.status = NFTStatus::Error(error.clone());
+        } else {
+            self.next_attempt_at = now + RETRY_BACKOFF_NS.saturating_mul(1 << (self.attempts - 1).min(16));
+        }
+        self.last_error = Some(error);
+    }
+}
+
+pub fn failed_result(target: &ChainMintConfig, error: &str) -> ChainMintResult {
+    ChainMintResult {
+        chain: target.chain.clone(),
+        standard: target.standard.clone(),
+        success: false,
+        token_id: None,
+        contract_address: None,
+        tx_hash: None,
+        error: Some(error.to_string()),
+        cost: None,
+    }
+}
+
+/// Run the next step of a due job
+pub async fn advance<A: ChainAdapter>(adapter: &A, job: &mut MintJob, metadata: &MultiChainMetadata, now: u64) {
+    match job.status {
+        NFTStatus::Pending => match adapter.submit(job, metadata).await {
+            Ok(submission) => {
+                if let Some(replaced) = &mut job.replaced_tx_hashes {
+                    replaced.retain(|hash| *hash != submission.tx_hash);
+                }
+                job.tx_hash = Some(submission.tx_hash);
+                job.nonce = submission.nonce;
+                job.status = NFTStatus::Minting;
+                job.updated_at = now;
+            }
+            Err(e) => job.fail(e, now),
+        },
+        NFTStatus::Minting => match adapter.confirm(job, now).await {
+            Ok(Confirmation::Confirmed(mut result)) => {
+                result.tx_hash = result.tx_hash.or_else(|| job.tx_hash.clone());
+                job.status = if result.success {
+                    NFTStatus::Minted
+                } else {
+                    NFTStatus::Error(result.error.clone().unwrap_or_else(|| "Mint reverted".to_string()))
+                };
+                job.result = Some(*result);
+                job.updated_at = now;
+            }
+            Ok(Confirmation::Pending) => job.next_attempt_at = now + RETRY_BACKOFF_NS,
+            Ok(Confirmation::Dropped) => {
+                job.replaced_tx_hashes.get_or_insert_with(Vec::new).extend(job.tx_hash.take());
+                job.status = NFTStatus::Pending;
+                job.next_attempt_at = now;
+                job.updated_at = now;
+            }
+            Err(e) => job.fail(e, now),
+        },
+        _ => {}
+    }
+}
+
+/// Does `config` configure `chain`?
+pub fn config_matches(chain: &Chain, config: &ChainConfig) -> bool {
+    match config {
+        ChainConfig::ICP { .. } => *chain == Chain::ICP,
+        ChainConfig::EVM(evm) => chain.is_evm() && evm.chain == *chain,
+        ChainConfig::Bitcoin(_) => *chain == Chain::Bitcoin,
+        ChainConfig::Solana(_) => *chain == Chain::Solana,
+        ChainConfig::SUI(_) => *chain == Chain::SUI,
+        ChainConfig::TRON(_) => *chain == Chain::TRON,
+    }
+}
+
+/// Owner of the ICP token and the per-chain jobs of a valid request. The recipient
+/// must be an address on every target chain other than ICP (one EVM address serves
+/// all EVM chains); without an ICP recipient the ICP token stays with `caller`.
+pub fn plan(request: &MultiChainMintRequest, caller: Principal, this_canister: Principal) -> Result<(Principal, Vec<ChainMintConfig>), String> {
+    if request.metadata.name.trim().is_empty() {
+        return Err("NFT name is required".to_string());
+    }
+    let recipient = &request.recipient;
+    validate_address(&recipient.chain, &recipient.address)?;
+
+    let icp_owner = if recipient.chain == Chain::ICP {
+        Principal::from_text(&recipient.address).map_err(|e| format!("Invalid ICP recipient: {}", e))?
+    } else {
+        caller
+    };
+
+    let mut jobs: Vec<ChainMintConfig> = Vec::new();
+    for (i, target) in request.target_chains.iter().enumerate() {
+        if request.target_chains[..i].iter().any(|t| t.chain == target.chain) {
+            return Err(format!("{} is targeted twice", target.chain));
+        }
+        if !config_matches(&target.chain, &target.config) {
+            return Err(format!("The configuration given for {} is for another chain", target.chain));
+        }
+        if target.chain == Chain::ICP {
+            if let ChainConfig::ICP { canister_id: Some(id) } = &target.config {
+                if *id != this_canister.to_text() {
+                    return Err("ICP tokens are minted by this canister".to_string());
+                }
+            }
+            continue;
+        }
+        let same_family = recipient.chain == target.chain || (recipient.chain.is_evm() && target.chain.is_evm());
+        if !same_family {
+            return Err(format!("The recipient is a {} address, {} needs its own", recipient.chain, target.chain));
+        }
+        validate_address(&target.chain, &recipient.address)?;
+        jobs.push(target.clone());
+    }
+    Ok((icp_owner, jobs))
+}
+
+/// Result of the ICP mint, the first of every NFT's chain results
+pub fn icp_result(token_id: u64, this_canister: Principal) -> ChainMintResult {
+    ChainMintResult {
+        chain: Chain::ICP,
+        standard: NFTStandard::ICRC7,
+        success: true,
+        token_id: Some(token_id.to_string()),
+        contract_address: Some(this_canister.to_text()),
+        tx_hash: None,
+        error: None,
+        cost: None,
+    }
+}
+
+/// Aggregate the ICP mint and the jobs of one NFT
+pub fn summarize(global_id: &str, icp: ChainMintResult, jobs: &[MintJob]) -> MultiChainMintResult {
+    let mut chain_results = vec![icp];
+    chain_results.extend(jobs.iter().filter_map(|j| j.result.clone()));
+
+    let pending = jobs.iter().any(|j| j.result.is_none());
+    let succeeded = chain_results.iter().filter(|r| r.success).count();
+    let status = if pending {
+        MintResultStatus::Pending
+    } else if succeeded == chain_results.len() {
+        MintResultStatus::AllSucceeded
+    } else if succeeded == 0 {
+        MintResultStatus::AllFailed
+    } else {
+        MintResultStatus::PartialSuccess
+    };
+
+    let chain_costs: Vec<_> = chain_results.iter().filter_map(|r| r.cost.clone()).collect();
+    MultiChainMintResult {
+        global_id: global_id.to_string(),
+        status,
+        total_cost: MintCost {
+            total_usd: chain_costs.iter().map(|c| c.usd_equivalent).sum(),
+            chain_costs,
+        },
+        chain_results,
+    }
+}
+
+/// Adapter that mints instantly (or fails on demand) without touching any chain
+#[derive(Debug, Default)]
+pub struct MockAdapter {
+    pub fail_submissions: Cell<u32>, // Submissions to reject before accepting
+    pub pending_checks: u32,         // Confirmation checks answered "unconfirmed" first
+    pub drops: Cell<u32>,            // Confirmation checks answered "dropped" after those
+    pub revert: bool,                // Confirm the mint as failed
+    checks: Cell<u32>,
+}
+
+impl MockAdapter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ChainAdapter for MockAdapter {
+    async fn submit(&self, job: &MintJob, _metadata: &MultiChainMetadata) -> Result<Submission, String> {
+        if self.fail_submissions.get() > 0 {
+            self.fail_submissions.set(self.fail_submissions.get() - 1);
+            return Err(format!("mock {} node unavailable", job.target.chain));
+        }
+        let resubmissions = job.replaced_tx_hashes.as_ref().map(Vec::len).unwrap_or(0);
+        let key = MintJob::key(&job.global_id, &job.target.chain);
+        Ok(Submission {
+            tx_hash: if resubmissions == 0 { format!("mock-tx-{}", key) } else { format!("mock-tx-{}-{}", key, resubmissions) },
+            nonce: Some(job.nonce.unwrap_or(0)),
+        })
+    }
+
+    async fn confirm(&self, job: &MintJob, _now: u64) -> Result<Confirmation, String> {
+        if self.checks.get() < self.pending_checks {
+            self.checks.set(self.checks.get() + 1);
+            return Ok(Confirmation::Pending);
+        }
+        if self.drops.get() > 0 {
+            self.drops.set(self.drops.get() - 1);
+            return Ok(Confirmation::Dropped);
+        }
+        if self.revert {
+            return Ok(Confirmation::Confirmed(Box::new(failed_result(&job.target, "mock revert"))));
+        }
+        Ok(Confirmation::Confirmed(Box::new(ChainMintResult {
+            chain: job.target.chain.clone(),
+            standard: job.target.standard.clone(),
+            success: true,
+            token_id: Some(job.global_id.clone()),
+            contract_address: Some(format!("mock-{}", job.target.chain)),
+            tx_hash: job.tx_hash.clone(),
+            error: None,
+            cost: None,
+        })))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::multichain::{EVMNFTConfig, MetadataStorage, MintOptions, MintPriority, MultiChainAddress, AddressType, SUIConfig};
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// Mock adapters never suspend, so a single poll finishes their futures
+    fn block_on<F: Future>(future: F) -> F::Output {
+        match pin!(future).as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("mock adapter future suspended"),
+        }
+    }
+
+    const EVM_ADDRESS: &str = "0x52908400098527886E0F7030069857D2E4169EE7";
+
+    pub(crate) fn metadata() -> MultiChainMetadata {
+        MultiChainMetadata {
+            name: "Raven #1".into(),
+            description: String::new(),
+            image: "ipfs://raven".into(),
+            external_url: None,
+            collection_name: "Ravens".into(),
+            collection_id: "ravens".into(),
+            attributes: Vec::new(),
+            rarity_score: None,
+            rarity_rank: None,
+            chain_specific: HashMap::new(),
+            animation_url: None,
+            background_color: None,
+            creator: "raven".into(),
+            royalty_bps: 500,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn evm(chain: Chain) -> ChainMintConfig {
+        ChainMintConfig {
+            standard: NFTStandard::ERC721,
+            config: ChainConfig::EVM(EVMNFTConfig {
+                chain: chain.clone(),
+                contract_address: EVM_ADDRESS.into(),
+                standard: NFTStandard::ERC721,
+                rpc_url: "https://rpc.example".into(),
+                chain_id: 1,
+                gas_price_gwei: None,
+            }),
+            chain,
+        }
+    }
+
+    fn request(recipient: MultiChainAddress, target_chains: Vec<ChainMintConfig>) -> MultiChainMintRequest {
+        MultiChainMintRequest {
+            metadata: metadata(),
+            target_chains,
+            recipient,
+            options: MintOptions { batch_id: None, priority: MintPriority::Normal, callback_url: None, metadata_storage: MetadataStorage::IPFS },
+        }
+    }
+
+    fn evm_recipient() -> MultiChainAddress {
+        MultiChainAddress { chain: Chain::Ethereum, address: EVM_ADDRESS.into(), address_type: AddressType::EVM }
+    }
+
+    #[test]
+    fn test_plan_validates_targets_and_recipient() {
+        let caller = Principal::from_slice(&[1]);
+        let this = Principal::from_slice(&[2]);
+        let icp = ChainMintConfig { chain: Chain::ICP, standard: NFTStandard::ICRC7, config: ChainConfig::ICP { canister_id: None } };
+
+        let (owner, jobs) = plan(&request(evm_recipient(), vec![icp.clone(), evm(Chain::Ethereum), evm(Chain::Base)]), caller, this).unwrap();
+        assert_eq!(owner, caller);
+        assert_eq!(jobs.iter().map(|j| j.chain.clone()).collect::<Vec<_>>(), vec![Chain::Ethereum, Chain::Base]);
+
+        assert!(plan(&request(evm_recipient(), vec![evm(Chain::Base), evm(Chain::Base)]), caller, this).is_err());
+        let mut mismatched = evm(Chain::Polygon);
+        mismatched.chain = Chain::Ethereum;
+        assert!(plan(&request(evm_recipient(), vec![mismatched]), caller, this).is_err());
+        let sui = ChainMintConfig {
+            chain: Chain::SUI,
+            standard: NFTStandard::SUIObject,
+            config: ChainConfig::SUI(SUIConfig { package_id: "0x2".into(), module_name: "nft".into(), object_type: "Raven".into(), display_fields: HashMap::new() }),
+        };
+        assert!(plan(&request(evm_recipient(), vec![sui]), caller, this).unwrap_err().contains("needs its own"));
+        let bad = MultiChainAddress { address: "0x1234".into(), ..evm_recipient() };
+        assert!(plan(&request(bad, vec![evm(Chain::Ethereum)]), caller, this).is_err());
+    }
+
+    #[test]
+    fn test_job_submits_confirms_and_summarizes() {
+        let adapter = MockAdapter { pending_checks: 1, ..MockAdapter::new() };
+        let mut job = MintJob::new("g1", evm(Chain::Ethereum), EVM_ADDRESS, 0);
+        assert!(job.is_open() && job.is_due(0));
+
+        block_on(advance(&adapter, &mut job, &metadata(), 1));
+        assert_eq!(job.status, NFTStatus::Minting);
+        assert_eq!(job.tx_hash.as_deref(), Some("mock-tx-g1/ETH"));
+
+        // Unconfirmed: checked again after the backoff
+        block_on(advance(&adapter, &mut job, &metadata(), 2));
+        assert_eq!(job.status, NFTStatus::Minting);
+        assert!(!job.is_due(3) && job.is_due(2 + RETRY_BACKOFF_NS));
+        block_on(advance(&adapter, &mut job, &metadata(), 2 + RETRY_BACKOFF_NS));
+        assert_eq!(job.status, NFTStatus::Minted);
+        assert_eq!(job.result.as_ref().unwrap().tx_hash.as_deref(), Some("mock-tx-g1/ETH"));
+        assert!(!job.is_open());
+        assert!(MintJob::open_key(&Chain::Ethereum, "g1/ETH").starts_with("ETH/"));
+
+        let pending = MintJob::new("g1", evm(Chain::Base), EVM_ADDRESS, 0);
+        let icp = icp_result(7, Principal::from_slice(&[2]));
+        let summary = summarize("g1", icp.clone(), &[job.clone(), pending]);
+        assert!(matches!(summary.status, MintResultStatus::Pending));
+        assert_eq!(summary.chain_results.len(), 2);
+        assert!(matches!(summarize("g1", icp, &[job]).status, MintResultStatus::AllSucceeded));
+    }
+
+    #[test]
+    fn test_failed_jobs_retry_then_give_up() {
+        let adapter = MockAdapter { fail_submissions: Cell::new(1), ..MockAdapter::new() };
+        let mut job = MintJob::new("g1", evm(Chain::Polygon), EVM_ADDRESS, 0);
+        block_on(advance(&adapter, &mut job, &metadata(), 0));
+        assert_eq!((job.status.clone(), job.attempts, job.next_attempt_at), (NFTStatus::Pending, 1, RETRY_BACKOFF_NS));
+        block_on(advance(&adapter, &mut job, &metadata(), RETRY_BACKOFF_NS));
+        assert_eq!(job.status, NFTStatus::Minting);
+
+        let reverting = MockAdapter { revert: true, ..MockAdapter::new() };
+        block_on(advance(&reverting, &mut job, &metadata(), RETRY_BACKOFF_NS));
+        assert!(matches!(job.status, NFTStatus::Error(_)));
+        let icp = icp_result(7, Principal::from_slice(&[2]));
+        assert!(matches!(summarize("g1", icp, &[job]).status, MintResultStatus::PartialSuccess));
+
+        let down = MockAdapter { fail_submissions: Cell::new(u32::MAX), ..MockAdapter::new() };
+        let mut job = MintJob::new("g2", evm(Chain::Base), EVM_ADDRESS, 0);
+        for _ in 0..MAX_JOB_ATTEMPTS {
+            let now = job.next_attempt_at;
+            block_on(advance(&down, &mut job, &metadata(), now));
+        }
+        assert!(matches!(job.status, NFTStatus::Error(_)) && !job.is_due(u64::MAX));
+        assert!(!job.result.unwrap().success);
+
+        // A dropped transaction is resubmitted with its nonce without counting as a failure
+        let dropping = MockAdapter { drops: Cell::new(1), ..MockAdapter::new() };
+        let mut job = MintJob::new("g3", evm(Chain::Base), EVM_ADDRESS, 0);
+        job.nonce = Some(9);
+        block_on(advance(&dropping, &mut job, &metadata(), 0));
+        block_on(advance(&dropping, &mut job, &metadata(), 1));
+        assert_eq!((job.status.clone(), job.attempts, job.tx_hash.clone()), (NFTStatus::Pending, 0, None));
+        assert!(job.is_due(1));
+        block_on(advance(&dropping, &mut job, &metadata(), 1));
+        assert_eq!(job.tx_hash.as_deref(), Some("mock-tx-g3/BASE-1"));
+        assert_eq!((job.nonce, job.replaced_tx_hashes.clone()), (Some(9), Some(vec!["mock-tx-g3/BASE".to_string()])));
+        block_on(advance(&dropping, &mut job, &metadata(), 2));
+        assert_eq!((job.status, job.attempts), (NFTStatus::Minted, 0));
+    }
+}
@@ -0,0 +1,318 @@
+//! Rarity Engine
+//!
+//! Rarity is derived from the collection itself rather than taken from the minter.
+//! A trait table counts, for every trait type and value, how many tokens carry it;
+//! it is updated as tokens are minted, burned and have their metadata updated.
+//!
+//! A token's score is its information content: the sum over the collection's trait
+//! types of `-log2(share of tokens with the same value)`, where lacking a trait type
+//! counts as a value of its own. Tokens are ranked by score (rank 1 is the rarest,
+//! equal scores share a rank) and the `Rarity` tier follows from the rank percentile.
+//!
+//! Every change to the trait table shifts every score, so the rank index is rebuilt
+//! in batches some time after a change; meanwhile a token's current score is placed
+//! in the previous ranking. The rank index lives on the heap, so after an upgrade a
+//! token has a score but no rank until the first rebuild finishes.
+//!
+//! Tokens minted before the engine existed enter the trait table in batches after
+//! the upgrade that adds it; until a token's batch, its own changes leave the
+//! table alone, since the batch counts its metadata as it is by then.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Rarity, Trait};
+
+/// Upper rank percentiles of each tier; the rest is Common
+const TIER_PERCENTILES: [(f64, Rarity); 4] = [
+    (0.01, Rarity::Legendary),
+    (0.05, Rarity::Epic),
+    (0.15, Rarity::Rare),
+    (0.40, Rarity::Uncommon),
+];
+
+/// Tokens carrying each value of one trait type
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TraitTypeStats {
+    pub tokens: u64, // Tokens with at least one value of this type
+    pub values: BTreeMap<String, u64>,
+}
+
+/// Trait frequency table of the collection
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TraitStats {
+    pub tokens: u64,
+    pub trait_types: BTreeMap<String, TraitTypeStats>,
+}
+
+/// Rarity of one of a token's traits
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TraitRarity {
+    pub trait_type: String,
+    pub value: String,
+    pub count: u64,  // Tokens sharing this value
+    pub score: f64,  // Information content in bits
+}
+
+/// Rarity of a token within the collection
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenRarity {
+    pub token_id: u64,
+    pub rarity: Rarity,
+    pub score: f64,        // Information content in bits
+    pub rarity_score: u32, // `score` in hundredths of a bit, as in `NFTMetadata`
+    pub rank: u32,         // 1 is the rarest; 0 while `rank_pending`
+    pub total: u64,        // Ranked tokens
+    pub traits: Vec<TraitRarity>,
+    pub rank_pending: bool, // No rank index yet; `rarity` is Common until there is one
+}
+
+/// Progress of counting the tokens minted before the trait table existed
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TraitBackfill {
+    pub next: u64,
+    pub end: u64,
+}
+
+impl TraitBackfill {
+    /// Is the token still to be counted by the backfill?
+    pub fn is_pending(&self, token_id: u64) -> bool {
+        (self.next..self.end).contains(&token_id)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.end
+    }
+}
+
+/// Distinct (type, value) pairs of a token and the trait types it carries
+fn distinct(attributes: &[Trait]) -> (BTreeSet<(&str, &str)>, BTreeSet<&str>) {
+    let pairs: BTreeSet<(&str, &str)> = attributes.iter()
+        .map(|a| (a.trait_type.as_str(), a.value.as_str()))
+        .collect();
+    let types = pairs.iter().map(|(trait_type, _)| *trait_type).collect();
+    (pairs, types)
+}
+
+fn information(count: u64, total: u64) -> f64 {
+    if count == 0 || total == 0 {
+        return 0.0;
+    }
+    -(count as f64 / total as f64).log2()
+}
+
+impl TraitStats {
+    /// Count a token that entered the collection
+    pub fn add(&mut self, attributes: &[Trait]) {
+        let (pairs, types) = distinct(attributes);
+        self.tokens += 1;
+        for trait_type in types {
+            self.trait_types.entry(trait_type.to_string()).or_default().tokens += 1;
+        }
+        for (trait_type, value) in pairs {
+            let stats = self.trait_types.entry(trait_type.to_string()).or_default();
+            *stats.values.entry(value.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Uncount a token that left the collection or is about to change its traits
+    pub fn remove(&mut self, attributes: &[Trait]) {
+        let (pairs, types) = distinct(attributes);
+        self.tokens = self.tokens.saturating_sub(1);
+        for (trait_type, value) in pairs {
+            if let Some(stats) = self.trait_types.get_mut(trait_type) {
+                if let Some(count) = stats.values.get_mut(value) {
+                    *count = count.saturating_sub(1);
+                    if *count == 0 {
+                        stats.values.remove(value);
+                    }
+                }
+            }
+        }
+        for trait_type in types {
+            if let Some(stats) = self.trait_types.get_mut(trait_type) {
+                stats.tokens = stats.tokens.saturating_sub(1);
+                if stats.tokens == 0 {
+                    self.trait_types.remove(trait_type);
+                }
+            }
+        }
+    }
+
+    /// Tokens sharing a trait value
+    pub fn count(&self, trait_type: &str, value: &str) -> u64 {
+        self.trait_types.get(trait_type)
+            .and_then(|stats| stats.values.get(value))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Information content of a set of traits, in bits
+    pub fn score(&self, attributes: &[Trait]) -> f64 {
+        let (pairs, types) = distinct(attributes);
+        let carried: f64 = pairs.iter()
+            .map(|(trait_type, value)| information(self.count(trait_type, value), self.tokens))
+            .sum();
+        let missing: f64 = self.trait_types.iter()
+            .filter(|(trait_type, _)| !types.contains(trait_type.as_str()))
+            .map(|(_, stats)| information(self.tokens - stats.tokens.min(self.tokens), self.tokens))
+            .sum();
+        carried + missing
+    }
+
+    /// Per-trait breakdown of a token's score
+    pub fn trait_rarities(&self, attributes: &[Trait]) -> Vec<TraitRarity> {
+        attributes.iter()
+            .map(|a| {
+                let count = self.count(&a.trait_type, &a.value);
+                TraitRarity { trait_type: a.trait_type.clone(), value: a.value.clone(), count, score: information(count, self.tokens) }
+            })
+            .collect()
+    }
+
+    /// 0-100 share of the collection that does not carry a trait value, for `Trait.rarity_score`
+    pub fn trait_score(&self, trait_type: &str, value: &str) -> u8 {
+        if self.tokens == 0 {
+            return 0;
+        }
+        let shared = self.count(trait_type, value).min(self.tokens) as f64 / self.tokens as f64;
+        ((1.0 - shared) * 100.0).round() as u8
+    }
+}
+
+/// Score in hundredths of a bit
+pub fn scaled(score: f64) -> u32 {
+    (score * 100.0).round() as u32
+}
+
+/// Tier of a rank among `total` tokens
+pub fn tier(rank: u32, total: u64) -> Rarity {
+    if total == 0 {
+        return Rarity::Common;
+    }
+    let percentile = rank as f64 / total as f64;
+    TIER_PERCENTILES.iter()
+        .find(|(upper, _)| percentile <= *upper)
+        .map(|(_, rarity)| rarity.clone())
+        .unwrap_or(Rarity::Common)
+}
+
+/// Tokens ordered from rarest to most common, with their ranks
+#[derive(Clone, Debug, Default)]
+pub struct RankIndex {
+    order: Vec<(u64, f64, u32)>,
+    positions: BTreeMap<u64, usize>,
+}
+
+impl RankIndex {
+    pub fn build(mut scores: Vec<(u64, f64)>) -> Self {
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        let mut order = Vec::with_capacity(scores.len());
+        for (i, (token_id, score)) in scores.into_iter().enumerate() {
+            let rank = match order.last() {
+                Some(&(_, previous, rank)) if previous == score => rank,
+                _ => i as u32 + 1,
+            };
+            order.push((token_id, score, rank));
+        }
+        let positions = order.iter().enumerate().map(|(i, (token_id, _, _))| (*token_id, i)).collect();
+        RankIndex { order, positions }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.order.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Score and rank of a token
+    pub fn get(&self, token_id: u64) -> Option<(f64, u32)> {
+        self.positions.get(&token_id).map(|&i| (self.order[i].1, self.order[i].2))
+    }
+
+    /// Rank a token with `score` has, or would have, in this ranking
+    pub fn rank_of(&self, score: f64) -> Option<u32> {
+        if self.order.is_empty() {
+            return None;
+        }
+        Some(self.order.partition_point(|(_, ranked, _)| *ranked > score) as u32 + 1)
+    }
+
+    /// Token ids by rank, starting at position `start`
+    pub fn page(&self, start: usize, take: usize) -> Vec<u64> {
+        self.order.iter().skip(start).take(take).map(|(token_id, _, _)| *token_id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traits(pairs: &[(&str, &str)]) -> Vec<Trait> {
+        pairs.iter()
+            .map(|(trait_type, value)| Trait { trait_type: trait_type.to_string(), value: value.to_string(), rarity_score: 0 })
+            .collect()
+    }
+
+    #[test]
+    fn test_trait_table_follows_mints_and_burns() {
+        let mut stats = TraitStats::default();
+        stats.add(&traits(&[("Heat", "Mild"), ("Color", "Red")]));
+        stats.add(&traits(&[("Heat", "Mild")]));
+        stats.add(&traits(&[("Heat", "Ghost"), ("Heat", "Ghost")]));
+        assert_eq!(stats.tokens, 3);
+        assert_eq!(stats.count("Heat", "Mild"), 2);
+        assert_eq!(stats.count("Heat", "Ghost"), 1);
+        assert_eq!(stats.trait_types["Color"].tokens, 1);
+        assert_eq!(stats.trait_score("Heat", "Ghost"), 67);
+
+        stats.remove(&traits(&[("Heat", "Mild"), ("Color", "Red")]));
+        assert_eq!(stats.tokens, 2);
+        assert!(!stats.trait_types.contains_key("Color"));
+        stats.remove(&traits(&[("Heat", "Mild")]));
+        stats.remove(&traits(&[("Heat", "Ghost"), ("Heat", "Ghost")]));
+        assert_eq!(stats, TraitStats::default());
+    }
+
+    #[test]
+    fn test_score_counts_missing_traits() {
+        let mut stats = TraitStats::default();
+        let common = traits(&[("Heat", "Mild")]);
+        let rare = traits(&[("Heat", "Mild"), ("Color", "Red")]);
+        for _ in 0..3 {
+            stats.add(&common);
+        }
+        stats.add(&rare);
+        // Heat Mild is shared by all 4 tokens; Color Red by 1, missing Color by 3
+        assert_eq!(stats.score(&rare), 2.0);
+        assert!((stats.score(&common) - (4.0f64 / 3.0).log2()).abs() < 1e-9);
+        assert_eq!(scaled(stats.score(&rare)), 200);
+        assert_eq!(stats.trait_rarities(&rare)[1].count, 1);
+    }
+
+    #[test]
+    fn test_rank_index_and_tiers() {
+        let index = RankIndex::build(vec![(0, 1.0), (1, 5.0), (2, 1.0), (3, 3.0)]);
+        assert_eq!(index.page(0, 10), vec![1, 3, 0, 2]);
+        assert_eq!(index.get(1), Some((5.0, 1)));
+        assert_eq!(index.get(0), Some((1.0, 3)));
+        assert_eq!(index.get(2), Some((1.0, 3)));
+        assert_eq!(index.get(9), None);
+        assert_eq!((index.rank_of(1.0), index.rank_of(4.0), index.rank_of(9.0)), (Some(3), Some(2), Some(1)));
+        assert_eq!(RankIndex::default().rank_of(1.0), None);
+
+        assert_eq!(tier(1, 100), Rarity::Legendary);
+        assert_eq!(tier(5, 100), Rarity::Epic);
+        assert_eq!(tier(15, 100), Rarity::Rare);
+        assert_eq!(tier(40, 100), Rarity::Uncommon);
+        assert_eq!(tier(41, 100), Rarity::Common);
+        assert_eq!(tier(1, 4), Rarity::Uncommon);
+
+        let backfill = TraitBackfill { next: 10, end: 20 };
+        assert!(!backfill.is_pending(9) && backfill.is_pending(10) && !backfill.is_pending(20));
+        assert!(!backfill.is_done() && TraitBackfill { next: 20, end: 20 }.is_done() && TraitBackfill::default().is_done());
+    }
+}
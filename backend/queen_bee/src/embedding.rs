@@ -0,0 +1,154 @@
+//! Text Embedding
+//!
+//! Deterministic hashed n-gram embedder used for both stored memories and queries.
+//! Word unigrams, word bigrams and character trigrams are hashed into 384 signed
+//! buckets (the feature-hashing trick), weighted by sublinear term frequency and a
+//! fixed inverse-frequency prior, then L2-normalized. Texts sharing vocabulary (or
+//! word fragments) land close together under cosine similarity.
+//!
+//! The embedder id/version is stored with every vector so that a future model
+//! (e.g. a quantized MiniLM encoder) can coexist with vectors from this one.
+
+use std::collections::BTreeMap;
+
+pub const EMBEDDING_DIM: usize = 384;
+pub const EMBEDDER_ID: &str = "hashed-ngram";
+pub const EMBEDDER_VERSION: &str = "1";
+/// `embedder` tag of vectors supplied by clients; they are only compared with client-supplied queries
+pub const EXTERNAL_EMBEDDER_ID: &str = "external";
+
+// Relative weights of the feature families
+const UNIGRAM_WEIGHT: f32 = 1.0;
+const BIGRAM_WEIGHT: f32 = 0.7;
+const TRIGRAM_WEIGHT: f32 = 0.35;
+
+/// Very common words carry little meaning; they get a small fixed weight instead of an IDF
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "do", "for", "from", "has", "have", "how",
+    "i", "in", "is", "it", "its", "me", "my", "of", "on", "or", "so", "that", "the", "this", "to",
+    "was", "we", "what", "when", "where", "which", "who", "why", "will", "with", "you", "your",
+];
+const STOPWORD_WEIGHT: f32 = 0.1;
+
+fn hash64(data: &str, seed: u64) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325 ^ seed;
+    for byte in data.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Prior for a word: stopwords are damped, longer words are slightly favoured
+fn word_weight(word: &str) -> f32 {
+    if STOPWORDS.contains(&word) {
+        STOPWORD_WEIGHT
+    } else {
+        1.0 + (word.chars().count().min(12) as f32 / 12.0) * 0.5
+    }
+}
+
+/// Add a feature to its signed bucket (sign from a second hash keeps collisions unbiased)
+fn add_feature(vector: &mut [f32], feature: &str, weight: f32) {
+    let h = hash64(feature, 0);
+    let bucket = (h % EMBEDDING_DIM as u64) as usize;
+    let sign = if hash64(feature, 0x9e3779b97f4a7c15) & 1 == 0 { 1.0 } else { -1.0 };
+    vector[bucket] += sign * weight;
+}
+
+/// Embed text into a unit-length 384-dim vector (all zeros for text without words)
+pub fn embed(text: &str) -> Vec<f32> {
+    let words = tokenize(text);
+    let mut features: BTreeMap<String, f32> = BTreeMap::new(); // Ordered so float sums are reproducible
+
+    for word in &words {
+        let w = word_weight(word);
+        *features.entry(format!("w:{}", word)).or_insert(0.0) += UNIGRAM_WEIGHT * w;
+
+        // Character trigrams over the padded word capture morphology ("pepper"/"peppers")
+        if w > STOPWORD_WEIGHT {
+            let padded: Vec<char> = format!("<{}>", word).chars().collect();
+            for tri in padded.windows(3) {
+                let tri: String = tri.iter().collect();
+                *features.entry(format!("c:{}", tri)).or_insert(0.0) += TRIGRAM_WEIGHT * w;
+            }
+        }
+    }
+    for pair in words.windows(2) {
+        let w = (word_weight(&pair[0]) * word_weight(&pair[1])).sqrt();
+        *features.entry(format!("b:{} {}", pair[0], pair[1])).or_insert(0.0) += BIGRAM_WEIGHT * w;
+    }
+
+    let mut vector = vec![0.0f32; EMBEDDING_DIM];
+    for (feature, tf) in &features {
+        // Sublinear term frequency so repeated words do not dominate
+        let weight = if *tf > 1.0 { 1.0 + tf.ln() } else { *tf };
+        add_feature(&mut vector, feature, weight);
+    }
+
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for x in vector.iter_mut() {
+            *x /= norm;
+        }
+    }
+    vector
+}
+
+/// Metadata entries identifying the embedder that produced a vector
+pub fn embedder_metadata() -> Vec<(String, String)> {
+    vec![
+        ("embedder".to_string(), EMBEDDER_ID.to_string()),
+        ("embedder_version".to_string(), EMBEDDER_VERSION.to_string()),
+    ]
+}
+
+/// Metadata entries of a client-supplied vector
+pub fn external_metadata() -> Vec<(String, String)> {
+    vec![("embedder".to_string(), EXTERNAL_EMBEDDER_ID.to_string())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn test_dimension_normalization_and_determinism() {
+        let v = embed("Ghost peppers ripen in late summer");
+        assert_eq!(v.len(), EMBEDDING_DIM);
+        assert!((cosine(&v, &v) - 1.0).abs() < 1e-4);
+        assert_eq!(v, embed("Ghost peppers ripen in late summer"));
+        assert!(embed("  ,.; ").iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn test_related_texts_score_higher_than_unrelated() {
+        let memory = embed("The farm harvested ghost peppers and carolina reapers this week");
+        let related = embed("When are the ghost pepper harvests at the farm?");
+        let unrelated = embed("Bitcoin ordinals inscription fees spiked on the mempool");
+        assert!(cosine(&memory, &related) > cosine(&memory, &unrelated) + 0.2);
+    }
+
+    #[test]
+    fn test_same_length_texts_differ() {
+        // The previous placeholder embedded any two texts of equal length identically
+        let a = embed("swap tokens");
+        let b = embed("plant seeds");
+        assert_eq!("swap tokens".len(), "plant seeds".len());
+        assert!(cosine(&a, &b) < 0.5);
+    }
+}
@@ -14,6 +14,13 @@
 //! - Vector DB Shards: vector_db canisters storing embeddings
 //! - NFT Canisters: Individual "bees" that call the queen
 
+pub mod embedding;
+pub mod pipeline;
+pub mod routing;
+pub mod scatter;
+pub mod shard_router;
+pub mod synthesis;
+
 use candid::{CandidType, Decode, Encode, Principal};
 use ic_cdk::{api::call::call, init, post_upgrade, pre_upgrade, query, update};
 use ic_stable_structures::{
@@ -22,11 +29,36 @@ use ic_stable_structures::{
 };
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+
+use pipeline::{PipelineResponse, PipelineStage, PipelineStatus, PipelineTopology, StageInput, StageOutput};
+use shard_router::{HashRing, MemoryQueryResult, MemoryResult, RebalanceState, RebalanceStatus, ShardError, ShardFailure, ShardHealth};
 
 // Memory IDs
 const MODEL_CANISTERS_MEM_ID: MemoryId = MemoryId::new(0);
 const VECTOR_DB_CANISTERS_MEM_ID: MemoryId = MemoryId::new(1);
+const VECTOR_DB_DRAINING_MEM_ID: MemoryId = MemoryId::new(2);
+const REBALANCE_MEM_ID: MemoryId = MemoryId::new(3);
+const SPECIALISTS_MEM_ID: MemoryId = MemoryId::new(4);
+const ROUTING_LOG_MEM_ID: MemoryId = MemoryId::new(5);
+const STREAMS_MEM_ID: MemoryId = MemoryId::new(6);
+const PIPELINE_MEM_ID: MemoryId = MemoryId::new(7);
+
+// Shard query deadline; later answers are kept for `get_late_memory_results` and the shard is reported as timed out
+const SHARD_QUERY_TIMEOUT_MS: u64 = 10_000;
+// On-chain inference deadline when fanning out to specialist model canisters
+const ONCHAIN_INFER_TIMEOUT_MS: u64 = 60_000;
+// Queries whose late shard answers are kept
+const MAX_LATE_QUERIES: usize = 100;
+// Self-call used to wait out scatter-gather deadlines and stage retry backoffs
+const DEADLINE_TICK_METHOD: &str = "deadline_tick";
+// Base cooldown before retrying a failed shard (doubles per consecutive failure)
+const SHARD_COOLDOWN_NS: u64 = 30 * 1_000_000_000;
+// Vectors migrated per `rebalance_shards` call
+const DEFAULT_MIGRATION_BATCH: u32 = 100;
+// Vector ids listed per `list_vector_ids` call while looking for misplaced vectors
+const MIGRATION_ID_PAGE: u32 = 2_000;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -45,6 +77,59 @@ thread_local! {
         RefCell::new(StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(VECTOR_DB_CANISTERS_MEM_ID))
         ));
+
+    // Vector DB shards being emptied before removal (excluded from the hash ring)
+    static VECTOR_DB_DRAINING: RefCell<StableBTreeMap<StorableU32, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(VECTOR_DB_DRAINING_MEM_ID))
+        ));
+
+    // Progress of vector migration after shards are added or removed
+    static REBALANCE: RefCell<ic_stable_structures::StableCell<RebalanceState, Memory>> =
+        RefCell::new(ic_stable_structures::StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(REBALANCE_MEM_ID)),
+            RebalanceState::default()
+        ).unwrap());
+
+    // Registered Axiom specialists with their embedding centroids
+    static SPECIALISTS: RefCell<StableBTreeMap<StorableU32, routing::Specialist, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SPECIALISTS_MEM_ID))
+        ));
+
+    // Routing decisions (sequence -> decision), capped at MAX_LOG_ENTRIES
+    static ROUTING_LOG: RefCell<StableBTreeMap<u64, routing::RoutingDecision, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(ROUTING_LOG_MEM_ID))
+        ));
+
+    // Streamed requests (stream id -> backing model generation session)
+    static STREAMS: RefCell<StableBTreeMap<String, StreamSession, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(STREAMS_MEM_ID))
+        ));
+
+    // Recent shard failures/latency (in-memory; resets on upgrade)
+    static SHARD_HEALTH: RefCell<HashMap<u32, ShardHealth>> = RefCell::new(HashMap::new());
+
+    // Layer-range stage canisters of the pipelined on-chain model
+    static PIPELINE: RefCell<ic_stable_structures::StableCell<PipelineTopology, Memory>> =
+        RefCell::new(ic_stable_structures::StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(PIPELINE_MEM_ID)),
+            PipelineTopology::default()
+        ).unwrap());
+
+    // Call statistics per pipeline stage, in stage order (in-memory; resets on upgrade)
+    static PIPELINE_STATS: RefCell<Vec<pipeline::StageStats>> = const { RefCell::new(Vec::new()) };
+
+    // Disambiguates memory ids created in the same round
+    static MEMORY_SEQ: Cell<u64> = const { Cell::new(0) };
+
+    // Streams being started per user, counted against MAX_STREAMS_PER_USER while the model call is in flight
+    static STREAM_STARTS: RefCell<HashMap<Principal, usize>> = RefCell::new(HashMap::new());
+
+    // Shard answers that arrived after a query's deadline (query_id -> caller, results; in-memory)
+    static LATE_RESULTS: RefCell<BTreeMap<String, (Principal, Vec<MemoryResult>)>> = const { RefCell::new(BTreeMap::new()) };
 }
 
 // ============================================================================
@@ -67,6 +152,8 @@ pub struct AIRequest {
     pub token_id: Option<u64>,
     pub use_onchain: bool,
     pub use_http_parallel: bool,
+    pub chairman_synthesis: Option<bool>, // Let the primary specialist's model rewrite the merge
+    pub max_tokens: Option<u32>,          // Streaming and pipeline only: completion length (default 2048 / 512, max 4096)
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -77,6 +164,8 @@ pub struct AIResponse {
     pub tokens_used: u32,
     pub latency_ms: u64,
     pub model_responses: Vec<(String, String, f32)>, // (model_name, response, confidence)
+    pub routing: Option<routing::RoutingResult>,
+    pub synthesis: Option<synthesis::SynthesisReport>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -94,6 +183,54 @@ pub struct VoiceResponse {
     pub characters_used: u32,
 }
 
+// Vector DB canister interface (mirrors vector_db's types)
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct VectorEmbedding {
+    id: String,
+    vector: Vec<f32>,
+    metadata: Vec<(String, String)>,
+    timestamp: u64,
+    importance: f32,
+    shard_id: u32,
+    namespace: Option<String>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct VectorQueryRequest {
+    query_vector: Vec<f32>,
+    top_k: u32,
+    min_similarity: f32,
+    filter_metadata: Option<Vec<(String, String)>>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct VectorQueryResult {
+    id: String,
+    vector: Vec<f32>,
+    similarity: f32,
+    metadata: Vec<(String, String)>,
+    timestamp: u64,
+}
+
+// Namespace definition copied to the shard a vector migrates to (a subset of vector_db's `Namespace`)
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ShardNamespace {
+    name: String,
+    owner: Principal,
+    dimension: u32,
+    acl: shard_router::NamespaceAcl,
+    quota: shard_router::NamespaceQuota,
+}
+
+// One page of a namespace's vector ids on a shard
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct VectorIdPage {
+    ids: Vec<String>,
+    next_cursor: Option<String>,
+}
+
+type VectorCallResult<T> = Result<(Result<T, String>,), (ic_cdk::api::call::RejectionCode, String)>;
+
 // Storable types
 #[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
 struct StorableU32(u32);
@@ -142,6 +279,7 @@ impl Storable for StorablePrincipal {
 
 #[init]
 fn init() {
+    seed_default_specialists();
     ic_cdk::println!("Queen Bee AI Pipeline Orchestrator initialized");
 }
 
@@ -152,6 +290,7 @@ fn pre_upgrade() {
 
 #[post_upgrade]
 fn post_upgrade() {
+    seed_default_specialists();
     ic_cdk::println!("Queen Bee AI Pipeline Orchestrator upgraded");
 }
 
@@ -176,57 +315,572 @@ fn register_model_canister(shard_id: u32, canister_id: Principal) -> Result<(),
     Ok(())
 }
 
-/// Register a vector DB shard canister
+/// Register a vector DB shard canister. The canister must report the same shard id,
+/// and existing vectors are migrated onto it by `rebalance_shards`.
 #[update]
-fn register_vector_db_canister(shard_id: u32, canister_id: Principal) -> Result<(), String> {
+async fn register_vector_db_canister(shard_id: u32, canister_id: Principal) -> Result<(), String> {
     let caller = ic_cdk::caller();
     if !ic_cdk::api::is_controller(&caller) {
         return Err("Only controllers can register canisters".to_string());
     }
-    
+
+    let info: Result<((u32, u64),), _> = call(canister_id, "get_shard_info", ()).await;
+    match info {
+        Ok(((reported, _),)) if reported == shard_id => {}
+        Ok(((reported, _),)) => {
+            return Err(format!("Canister reports shard {}, expected {}", reported, shard_id));
+        }
+        Err(e) => return Err(format!("Could not reach vector DB canister: {:?}", e)),
+    }
+
+    let had_shards = !active_shard_ids().is_empty();
     VECTOR_DB_CANISTERS.with(|v| {
         v.borrow_mut()
             .insert(StorableU32(shard_id), StorablePrincipal(canister_id));
     });
-    
+    VECTOR_DB_DRAINING.with(|d| d.borrow_mut().remove(&StorableU32(shard_id)));
+    if had_shards {
+        start_rebalance();
+    }
+
     ic_cdk::println!("Registered vector DB canister {} for shard {}", canister_id, shard_id);
     Ok(())
 }
 
+/// Start draining a vector DB shard; it is removed once `rebalance_shards` has emptied it
+#[update]
+fn unregister_vector_db_canister(shard_id: u32) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can unregister canisters".to_string());
+    }
+    if vector_db_canister(shard_id).is_none() {
+        return Err("Shard not registered".to_string());
+    }
+    if active_shard_ids() == vec![shard_id] {
+        return Err("Cannot drain the last active shard".to_string());
+    }
+
+    VECTOR_DB_DRAINING.with(|d| d.borrow_mut().insert(StorableU32(shard_id), ()));
+    start_rebalance();
+    Ok(())
+}
+
 // ============================================================================
-// Main AI Pipeline
+// Shard Routing
 // ============================================================================
 
-/// Analyze query and route to the best specialist in the swarm
-fn analyze_specialist_routing(query: &str) -> u8 {
-    let q = query.to_lowercase();
-    
-    // 1. Blockchain & Bitcoin Expert
-    if q.contains("bitcoin") || q.contains("btc") || q.contains("ordinal") || q.contains("blockchain") || q.contains("consensus") || q.contains("ledger") {
-        1 
-    } 
-    // 2. Creative Mind / Art & Culinary / RWA Specialist
-    else if q.contains("pepper") || q.contains("spicy") || q.contains("farm") || q.contains("nursery") || q.contains("recipe") || q.contains("art") || q.contains("nft") || q.contains("creative") || q.contains("design") {
-        2
-    } 
-    // 3. DeFi Sage / Finance & Trading
-    else if q.contains("finance") || q.contains("defi") || q.contains("trading") || q.contains("token") || q.contains("yield") || q.contains("liquidity") || q.contains("dex") || q.contains("price") {
-        3
-    } 
-    // 4. Tech Architect / Smart Contracts
-    else if q.contains("code") || q.contains("contract") || q.contains("rust") || q.contains("develop") || q.contains("backend") || q.contains("api") || q.contains("candid") || q.contains("canister") {
-        4
-    } 
-    // 5. Community Builder / Marketing / Engagement
-    else if q.contains("community") || q.contains("twitter") || q.contains("social") || q.contains("marketing") || q.contains("discord") || q.contains("telegram") || q.contains("growth") {
-        5
-    } 
-    // Default to the first specialist (Lead Oracle)
-    else {
-        1
+fn vector_db_canister(shard_id: u32) -> Option<Principal> {
+    VECTOR_DB_CANISTERS.with(|v| v.borrow().get(&StorableU32(shard_id)).map(|p| p.0))
+}
+
+fn all_shards() -> Vec<(u32, Principal)> {
+    VECTOR_DB_CANISTERS.with(|v| v.borrow().iter().map(|(k, p)| (k.0, p.0)).collect())
+}
+
+fn is_draining(shard_id: u32) -> bool {
+    VECTOR_DB_DRAINING.with(|d| d.borrow().contains_key(&StorableU32(shard_id)))
+}
+
+/// Shards that own a segment of the hash ring (registered and not draining)
+fn active_shard_ids() -> Vec<u32> {
+    all_shards()
+        .into_iter()
+        .map(|(id, _)| id)
+        .filter(|id| !is_draining(*id))
+        .collect()
+}
+
+fn current_ring() -> HashRing {
+    HashRing::new(&active_shard_ids())
+}
+
+fn start_rebalance() {
+    REBALANCE.with(|r| {
+        let mut state = r.borrow().get().clone();
+        state.active = true;
+        state.shard_cursor = None;
+        state.namespace_cursor = None;
+        state.id_cursor = None;
+        state.started_at = ic_cdk::api::time();
+        r.borrow_mut().set(state).expect("Failed to save rebalance state");
+    });
+}
+
+fn record_shard_outcome(shard_id: u32, latency_ms: u64, failed: bool) {
+    SHARD_HEALTH.with(|h| {
+        let mut health = h.borrow_mut();
+        let entry = health.entry(shard_id).or_default();
+        entry.last_latency_ms = latency_ms;
+        if failed {
+            entry.consecutive_failures += 1;
+            entry.last_failure_at = ic_cdk::api::time();
+        } else {
+            entry.consecutive_failures = 0;
+        }
+    });
+}
+
+/// Query one shard, classifying rejections and errors; also returns the latency in ms
+async fn query_shard(canister_id: Principal, request: VectorQueryRequest) -> (Result<Vec<MemoryResult>, ShardFailure>, u64) {
+    let start = ic_cdk::api::time();
+    let call_result: VectorCallResult<Vec<VectorQueryResult>> = call(canister_id, "query_vectors", (request,)).await;
+    let latency_ms = (ic_cdk::api::time() - start) / 1_000_000;
+
+    let outcome = match call_result {
+        Ok((Ok(results),)) => Ok(results
+            .into_iter()
+            .map(|r| MemoryResult { id: r.id, vector: r.vector, similarity: r.similarity, metadata: r.metadata })
+            .collect()),
+        Ok((Err(e),)) => Err(ShardFailure::Rejected(e)),
+        Err(e) => Err(ShardFailure::Rejected(format!("{:?}", e))),
+    };
+    (outcome, latency_ms)
+}
+
+/// Keep shard answers that arrived after their query had been answered
+fn keep_late_results(query_id: &str, caller: Principal, results: Vec<MemoryResult>) {
+    LATE_RESULTS.with(|l| {
+        let mut late = l.borrow_mut();
+        late.entry(query_id.to_string()).or_insert_with(|| (caller, Vec::new())).1.extend(results);
+        while late.len() > MAX_LATE_QUERIES {
+            late.pop_first();
+        }
+    });
+}
+
+/// No-op the canister calls on itself to wait out deadlines and backoffs
+#[update]
+fn deadline_tick() {}
+
+/// Create a namespace on a shard that does not have it yet, copying its definition
+async fn ensure_namespace(target: Principal, ns: &ShardNamespace) -> Result<(), String> {
+    let existing: VectorCallResult<ShardNamespace> = call(target, "get_namespace", (ns.name.clone(),)).await;
+    if let Ok((Ok(_),)) = existing {
+        return Ok(());
+    }
+
+    let created: VectorCallResult<ShardNamespace> =
+        call(target, "create_namespace", (ns.name.clone(), ns.owner, ns.dimension, ns.quota.clone())).await;
+    match created {
+        Ok((Ok(_),)) => {}
+        Ok((Err(e),)) => return Err(format!("namespace {}: {}", ns.name, e)),
+        Err(e) => return Err(format!("namespace {}: {:?}", ns.name, e)),
+    }
+    let acl: VectorCallResult<()> = call(target, "set_namespace_acl", (ns.name.clone(), ns.acl.clone())).await;
+    match acl {
+        Ok((Ok(()),)) => Ok(()),
+        Ok((Err(e),)) => Err(format!("namespace {}: {}", ns.name, e)),
+        Err(e) => Err(format!("namespace {}: {:?}", ns.name, e)),
+    }
+}
+
+/// Move one vector of a namespace between shards (store on the new owner, then delete from the old)
+async fn migrate_vector(id: &str, namespace: &str, from: Principal, to_shard: u32, to: Principal) -> Result<(), MigrationError> {
+    let namespace = Some(namespace.to_string());
+    let fetched: VectorCallResult<VectorEmbedding> = call(from, "get_vector", (id.to_string(), namespace.clone())).await;
+    let mut embedding = match fetched {
+        Ok((Ok(e),)) => e,
+        Ok((Err(e),)) => return Err(MigrationError::Refused(format!("{}: {}", id, e))),
+        Err(e) => return Err(MigrationError::Call(format!("{}: {:?}", id, e))),
+    };
+    embedding.shard_id = to_shard;
+    embedding.namespace = namespace.clone();
+
+    let stored: VectorCallResult<()> = call(to, "store_vector", (embedding,)).await;
+    match stored {
+        Ok((Ok(()),)) => {}
+        Ok((Err(e),)) => return Err(MigrationError::Refused(format!("{}: {}", id, e))),
+        Err(e) => return Err(MigrationError::Call(format!("{}: {:?}", id, e))),
+    }
+
+    let deleted: VectorCallResult<()> = call(from, "delete_vector", (id.to_string(), namespace)).await;
+    match deleted {
+        Ok((Ok(()),)) => Ok(()),
+        Ok((Err(e),)) => Err(MigrationError::Refused(format!("{}: {}", id, e))),
+        Err(e) => Err(MigrationError::Call(format!("{}: {:?}", id, e))),
+    }
+}
+
+/// Why a vector could not be moved. Failed calls are retried on the next step; a shard
+/// refusing the operation (e.g. queen_bee lacking access to the namespace) would refuse
+/// again, so the step stops with an error instead.
+enum MigrationError {
+    Call(String),
+    Refused(String),
+}
+
+fn access_error(shard_id: u32, namespace: &str, error: &str) -> String {
+    format!(
+        "Shard {} refused migrating namespace {}: {}. If queen_bee lacks access, grant it read and write access (set_namespace_acl) before calling rebalance_shards again",
+        shard_id, namespace, error
+    )
+}
+
+/// Migrate up to `batch_size` misplaced vectors; call repeatedly until `active` is false
+#[update]
+async fn rebalance_shards(batch_size: Option<u32>) -> Result<RebalanceStatus, String> {
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can rebalance shards".to_string());
     }
+
+    let mut state = REBALANCE.with(|r| r.borrow().get().clone());
+    let mut status = RebalanceStatus {
+        active: state.active,
+        moved_this_step: 0,
+        moved_total: state.moved,
+        current_shard: state.shard_cursor,
+        removed_shards: Vec::new(),
+        errors: Vec::new(),
+    };
+    if !state.active {
+        return Ok(status);
+    }
+
+    let ring = current_ring();
+    let batch = batch_size.unwrap_or(DEFAULT_MIGRATION_BATCH).max(1) as usize;
+    let next_shard = all_shards()
+        .into_iter()
+        .find(|(id, _)| state.shard_cursor.map(|c| *id >= c).unwrap_or(true));
+
+    match next_shard {
+        Some((shard_id, canister_id)) => {
+            // Every namespace of the shard is scanned in name order, a page of ids at a time
+            let listed: Result<(Vec<ShardNamespace>,), _> = call(canister_id, "list_namespaces", ()).await;
+            let namespaces = listed.map_err(|e| format!("Could not list namespaces of shard {}: {:?}", shard_id, e))?.0;
+            if state.shard_cursor != Some(shard_id) {
+                state.namespace_cursor = None;
+            }
+            let position = namespaces
+                .iter()
+                .position(|ns| state.namespace_cursor.as_ref().map(|c| ns.name >= *c).unwrap_or(true));
+
+            let mut shard_done = true;
+            if let Some(position) = position {
+                let ns = &namespaces[position];
+                if state.namespace_cursor.as_ref() != Some(&ns.name) {
+                    state.id_cursor = None;
+                }
+                let listed: VectorCallResult<VectorIdPage> = call(
+                    canister_id,
+                    "list_vector_ids",
+                    (ns.name.clone(), state.id_cursor.clone(), Some(MIGRATION_ID_PAGE)),
+                )
+                .await;
+                let page = match listed {
+                    Ok((Ok(page),)) => page,
+                    Ok((Err(e),)) => return Err(access_error(shard_id, &ns.name, &e)),
+                    Err(e) => return Err(format!("Could not list shard {}: {:?}", shard_id, e)),
+                };
+                let pending = shard_router::misplaced(&ring, shard_id, &page.ids);
+
+                let mut ready: Vec<u32> = Vec::new();
+                let mut refused = None;
+                for (id, owner) in pending.iter().take(batch) {
+                    let target = match vector_db_canister(*owner) {
+                        Some(t) => t,
+                        None => continue,
+                    };
+                    if !ready.contains(owner) {
+                        if let Err(e) = ensure_namespace(target, ns).await {
+                            status.errors.push(format!("shard {}: {}", owner, e));
+                            continue;
+                        }
+                        ready.push(*owner);
+                    }
+                    match migrate_vector(id, &ns.name, canister_id, *owner, target).await {
+                        Ok(()) => status.moved_this_step += 1,
+                        Err(MigrationError::Call(e)) => status.errors.push(e),
+                        Err(MigrationError::Refused(e)) => {
+                            refused = Some(e);
+                            break;
+                        }
+                    }
+                }
+                if let Some(e) = refused {
+                    // Keep what moved before the refusal; the cursors stay on this page
+                    state.moved += status.moved_this_step as u64;
+                    REBALANCE.with(|r| r.borrow_mut().set(state).expect("Failed to save rebalance state"));
+                    return Err(access_error(shard_id, &ns.name, &e));
+                }
+
+                // Move on once this page has nothing misplaced left (errors retry next call)
+                if pending.len() <= batch && status.errors.is_empty() {
+                    state.id_cursor = page.next_cursor;
+                    state.namespace_cursor = if state.id_cursor.is_some() {
+                        Some(ns.name.clone())
+                    } else {
+                        namespaces.get(position + 1).map(|next| next.name.clone())
+                    };
+                } else {
+                    state.namespace_cursor = Some(ns.name.clone());
+                }
+                shard_done = state.namespace_cursor.is_none();
+            }
+
+            if shard_done {
+                state.namespace_cursor = None;
+                state.id_cursor = None;
+                state.shard_cursor = Some(shard_id.saturating_add(1));
+                if shard_id == u32::MAX {
+                    state.shard_cursor = None;
+                    state.active = false;
+                }
+            } else {
+                state.shard_cursor = Some(shard_id);
+            }
+        }
+        None => {
+            // Full pass done: drop draining shards that are now empty
+            for (shard_id, canister_id) in all_shards().into_iter().filter(|(id, _)| is_draining(*id)) {
+                let info: Result<((u32, u64),), _> = call(canister_id, "get_shard_info", ()).await;
+                if let Ok(((_, 0),)) = info {
+                    VECTOR_DB_CANISTERS.with(|v| v.borrow_mut().remove(&StorableU32(shard_id)));
+                    VECTOR_DB_DRAINING.with(|d| d.borrow_mut().remove(&StorableU32(shard_id)));
+                    status.removed_shards.push(shard_id);
+                } else {
+                    status.errors.push(format!("Shard {} still holds vectors", shard_id));
+                }
+            }
+            state.shard_cursor = None;
+            state.active = !status.errors.is_empty();
+        }
+    }
+
+    state.moved += status.moved_this_step as u64;
+    status.active = state.active;
+    status.moved_total = state.moved;
+    status.current_shard = state.shard_cursor;
+    REBALANCE.with(|r| r.borrow_mut().set(state).expect("Failed to save rebalance state"));
+    Ok(status)
 }
 
+/// Registered vector DB shards: (shard_id, canister, draining, consecutive failures, last latency ms)
+#[query]
+fn get_vector_shards() -> Vec<(u32, Principal, bool, u32, u64)> {
+    all_shards()
+        .into_iter()
+        .map(|(id, canister)| {
+            let health = SHARD_HEALTH.with(|h| h.borrow().get(&id).cloned().unwrap_or_default());
+            (id, canister, is_draining(id), health.consecutive_failures, health.last_latency_ms)
+        })
+        .collect()
+}
+
+// ============================================================================
+// Model Inference
+// ============================================================================
+
+// On-chain inference interface (mirrors deepseek_model's types)
+#[derive(CandidType, Clone)]
+struct InferenceRequest {
+    prompt: String,
+    max_tokens: u32,
+    temperature: f32,
+    top_p: f32,
+    context: Vec<ChatMessage>,
+    system_prompt: Option<String>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct InferenceResponse {
+    response: String,
+    tokens_generated: u32,
+    inference_time_ms: u64,
+    shards_used: Vec<u32>,
+}
+
+type InferenceCallResult = Result<(Result<InferenceResponse, String>,), (ic_cdk::api::call::RejectionCode, String)>;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum GenerationStatus {
+    Generating,
+    Completed,
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GenerationChunk {
+    pub session_id: String,
+    pub index: u32,
+    pub text: String,
+    pub tokens_generated: u32,
+    pub done: bool,
+    pub status: GenerationStatus,
+}
+
+type StartGenerationCallResult = Result<(Result<String, String>,), (ic_cdk::api::call::RejectionCode, String)>;
+type NextChunkCallResult = Result<(Result<GenerationChunk, String>,), (ic_cdk::api::call::RejectionCode, String)>;
+
+/// A streamed request: which model canister generation session backs it
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StreamSession {
+    pub id: String,
+    pub owner: Principal,
+    pub model_canister: Principal,
+    pub model_session_id: String,
+    pub specialist_id: u32,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StreamStart {
+    pub session_id: String,
+    pub model: String,
+    pub routing: routing::RoutingResult,
+}
+
+impl Storable for StreamSession {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+// Default and maximum completion length for streamed requests
+const DEFAULT_STREAM_TOKENS: u32 = 2048;
+const MAX_STREAM_TOKENS: u32 = 4096;
+// Streams are forgotten this long after they start, finished or not
+const STREAM_TTL_NS: u64 = 60 * 60 * 1_000_000_000;
+// Open streams one user may hold (model canisters exempt queen_bee from their per-caller cap)
+const MAX_STREAMS_PER_USER: usize = 4;
+
+/// Model canister serving a specialist, falling back to the first registered shard
+fn specialist_model_canister(specialist_id: u32) -> Option<Principal> {
+    MODEL_CANISTERS.with(|m| {
+        m.borrow().get(&StorableU32(specialist_id)).map(|p| p.0)
+            .or_else(|| m.borrow().iter().next().map(|(_, p)| p.0))
+    })
+}
+
+fn specialist_name(specialist_id: u32) -> String {
+    SPECIALISTS.with(|s| s.borrow().get(&StorableU32(specialist_id)).map(|sp| sp.name))
+        .unwrap_or_else(|| format!("AXIOM #{}", specialist_id))
+}
+
+// ============================================================================
+// Specialist Routing
+// ============================================================================
+
+/// Register the built-in specialists on a fresh (or pre-routing) canister
+fn seed_default_specialists() {
+    if SPECIALISTS.with(|s| !s.borrow().is_empty()) {
+        return;
+    }
+    let now = ic_cdk::api::time();
+    for input in routing::default_specialists() {
+        if let Ok(specialist) = routing::build_specialist(input, now) {
+            SPECIALISTS.with(|s| s.borrow_mut().insert(StorableU32(specialist.id), specialist));
+        }
+    }
+}
+
+fn all_specialists() -> Vec<routing::Specialist> {
+    SPECIALISTS.with(|s| s.borrow().iter().map(|(_, sp)| sp).collect())
+}
+
+/// Route a query and append the decision to the routing log
+fn route_and_log(query: &str, caller: Principal) -> routing::RoutingResult {
+    let result = routing::route(query, &all_specialists());
+
+    ROUTING_LOG.with(|l| {
+        let mut log = l.borrow_mut();
+        let id = log.last_key_value().map(|(k, _)| k + 1).unwrap_or(0);
+        log.insert(id, routing::RoutingDecision {
+            id,
+            query: query.chars().take(500).collect(),
+            caller,
+            result: result.clone(),
+            timestamp: ic_cdk::api::time(),
+            label: None,
+        });
+        while log.len() > routing::MAX_LOG_ENTRIES {
+            match log.first_key_value() {
+                Some((oldest, _)) => log.remove(&oldest),
+                None => break,
+            };
+        }
+    });
+
+    result
+}
+
+/// Add or replace a specialist (controllers only); its centroid is recomputed
+#[update]
+fn upsert_specialist(input: routing::SpecialistInput) -> Result<routing::Specialist, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can manage specialists".to_string());
+    }
+    let specialist = routing::build_specialist(input, ic_cdk::api::time())?;
+    SPECIALISTS.with(|s| s.borrow_mut().insert(StorableU32(specialist.id), specialist.clone()));
+    Ok(specialist)
+}
+
+/// Remove a specialist (controllers only)
+#[update]
+fn remove_specialist(specialist_id: u32) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can manage specialists".to_string());
+    }
+    SPECIALISTS.with(|s| s.borrow_mut().remove(&StorableU32(specialist_id)))
+        .map(|_| ())
+        .ok_or_else(|| "Specialist not found".to_string())
+}
+
+#[query]
+fn list_specialists() -> Vec<routing::Specialist> {
+    all_specialists()
+}
+
+/// Preview how a query would be routed (not logged)
+#[query]
+fn route_query(query: String) -> routing::RoutingResult {
+    routing::route(&query, &all_specialists())
+}
+
+/// Page through logged routing decisions, oldest first. Controllers see every
+/// decision; other callers only their own.
+#[query]
+fn get_routing_log(from_id: u64, limit: u32) -> Vec<routing::RoutingDecision> {
+    let caller = ic_cdk::caller();
+    let see_all = ic_cdk::api::is_controller(&caller);
+    ROUTING_LOG.with(|l| {
+        l.borrow()
+            .range(from_id..)
+            .map(|(_, d)| d)
+            .filter(|d| see_all || d.caller == caller)
+            .take(limit.min(500) as usize)
+            .collect()
+    })
+}
+
+/// Record the correct specialist for a logged decision (controllers only)
+#[update]
+fn label_routing_decision(decision_id: u64, specialist_id: u32) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can label routing decisions".to_string());
+    }
+    ROUTING_LOG.with(|l| {
+        let mut log = l.borrow_mut();
+        let mut decision = log.get(&decision_id).ok_or("Routing decision not found")?;
+        decision.label = Some(specialist_id);
+        log.insert(decision_id, decision);
+        Ok(())
+    })
+}
+
+// ============================================================================
+// Main AI Pipeline
+// ============================================================================
+
 /// Process AI request - coordinates on-chain inference and HTTP outcalls
 #[update]
 async fn process_ai_request(request: AIRequest) -> Result<AIResponse, String> {
@@ -237,68 +891,88 @@ async fn process_ai_request(request: AIRequest) -> Result<AIResponse, String> {
         return Err("Authentication required".to_string());
     }
 
-    let mut model_responses: Vec<(String, String, f32)> = Vec::new();
+    let mut outputs: Vec<synthesis::ModelOutput> = Vec::new();
     let mut inference_method = "none".to_string();
 
     // 1. Task Orchestration & Specialist Routing
-    // Analyze query to find the best specialist in the swarm
-    let specialist_id = analyze_specialist_routing(&request.query);
-    ic_cdk::println!("Routing request to specialist AXIOM #{}", specialist_id);
+    // Nearest-centroid routing; close runners-up are consulted as well
+    let routing = route_and_log(&request.query, caller);
+    ic_cdk::println!("Routing request to specialists {:?} (confidence {:.2})", routing.routed, routing.confidence);
 
-    // 2. On-chain DeepSeek R1 inference (if enabled)
+    // 2. On-chain DeepSeek R1 inference (if enabled), one call per routed specialist's model canister
     if request.use_onchain {
         inference_method = "onchain".to_string();
-        
-        // Route to the specific model shard or specialist canister
-        let model_canister = MODEL_CANISTERS.with(|m| {
-            m.borrow().get(&StorableU32(specialist_id as u32)).map(|p| p.0)
-                .or_else(|| m.borrow().iter().next().map(|(_, p)| p.0))
-        });
-        
-        if let Some(model_canister_id) = model_canister {
-            // Define InferenceRequest inline (can't import from other canister)
-            #[derive(CandidType)]
-            struct InferenceRequest {
-                prompt: String,
-                max_tokens: u32,
-                temperature: f32,
-                top_p: f32,
-                context: Vec<ChatMessage>,
-                system_prompt: Option<String>,
+    }
+    let pipeline = pipeline_topology().filter(|_| request.use_onchain);
+    if let Some(topology) = &pipeline {
+        // One model split across stage canisters serves every specialist
+        let inference_request = InferenceRequest {
+            prompt: request.query.clone(),
+            max_tokens: 512,
+            temperature: 0.7,
+            top_p: 0.95,
+            context: request.context.clone(),
+            system_prompt: request.system_prompt.clone(),
+        };
+        match run_pipeline(topology, inference_request).await {
+            Ok(response) => outputs.push(synthesis::ModelOutput {
+                model: "DeepSeek-R1-7B-Pipeline".to_string(),
+                response: response.response,
+                confidence: None,
+                tokens_used: response.tokens_generated,
+            }),
+            Err(e) => ic_cdk::println!("Pipeline inference failed: {}", e),
+        }
+    } else if request.use_onchain {
+        let mut targets: Vec<(u32, Principal)> = Vec::new();
+        for (specialist_id, _) in &routing.routed {
+            if let Some(canister) = specialist_model_canister(*specialist_id) {
+                if !targets.iter().any(|(_, c)| *c == canister) {
+                    targets.push((*specialist_id, canister));
+                }
             }
-            
-            #[derive(CandidType, Deserialize)]
-            struct InferenceResponse {
-                response: String,
-                tokens_generated: u32,
-                inference_time_ms: u64,
-                shards_used: Vec<u32>,
+        }
+
+        let inference_request = InferenceRequest {
+            prompt: request.query.clone(),
+            max_tokens: 512,
+            temperature: 0.7,
+            top_p: 0.95,
+            context: request.context.clone(),
+            system_prompt: request.system_prompt.clone(),
+        };
+
+        let calls: Vec<_> = targets.iter().map(|(_, canister)| {
+            let (canister, req) = (*canister, inference_request.clone());
+            async move {
+                let result: InferenceCallResult = call(canister, "infer", (req,)).await;
+                result
             }
-            
-            let inference_request = InferenceRequest {
-                prompt: request.query.clone(),
-                max_tokens: 512,
-                temperature: 0.7,
-                top_p: 0.95,
-                context: request.context.clone(),
-                system_prompt: request.system_prompt.clone(),
-            };
-            
-            type CallResult = Result<(Result<InferenceResponse, String>,), (ic_cdk::api::call::RejectionCode, String)>;
-            let call_result: CallResult = call(model_canister_id, "infer", (inference_request,)).await;
-            
+        }).collect();
+        let deadline = ic_cdk::api::time() + ONCHAIN_INFER_TIMEOUT_MS * 1_000_000;
+        let late_targets = targets.clone();
+        let outcomes = scatter::gather(calls, deadline, DEADLINE_TICK_METHOD, move |index, _| {
+            ic_cdk::println!("On-chain inference from {} answered after the deadline", late_targets[index].1);
+        }).await;
+
+        for ((specialist_id, canister), call_result) in targets.iter().zip(outcomes) {
             match call_result {
-                Ok((Ok(response),)) => {
-                    model_responses.push((
-                        "DeepSeek-R1-7B-OnChain".to_string(),
-                        response.response,
-                        0.85,
-                    ));
+                None => {
+                    ic_cdk::println!("On-chain inference from {} timed out", canister);
                 }
-                Ok((Err(e),)) => {
+                Some(Ok((Ok(response),))) => {
+                    // No self-reported confidence; synthesis scores it by agreement
+                    outputs.push(synthesis::ModelOutput {
+                        model: format!("DeepSeek-R1-7B-OnChain ({})", specialist_name(*specialist_id)),
+                        response: response.response,
+                        confidence: None,
+                        tokens_used: response.tokens_generated,
+                    });
+                }
+                Some(Ok((Err(e),))) => {
                     ic_cdk::println!("On-chain inference error: {}", e);
                 }
-                Err(e) => {
+                Some(Err(e)) => {
                     ic_cdk::println!("On-chain inference call failed: {:?}", e);
                 }
             }
@@ -372,11 +1046,12 @@ async fn process_ai_request(request: AIRequest) -> Result<AIResponse, String> {
                     // Extract responses from AI Council session
                     for council_response in session.responses {
                         if council_response.error.is_none() {
-                            model_responses.push((
-                                council_response.llm_name,
-                                council_response.response,
-                                council_response.confidence,
-                            ));
+                            outputs.push(synthesis::ModelOutput {
+                                model: council_response.llm_name,
+                                response: council_response.response,
+                                confidence: Some(council_response.confidence),
+                                tokens_used: council_response.tokens_used.min(u32::MAX as u64) as u32,
+                            });
                         }
                     }
                 }
@@ -390,39 +1065,399 @@ async fn process_ai_request(request: AIRequest) -> Result<AIResponse, String> {
         }
     }
 
-    // 3. Synthesize consensus from all responses
-    let consensus = if model_responses.is_empty() {
+    // 3. Synthesize: claims, agreements, contradictions, extractive merge
+    let mut report = synthesis::synthesize(&outputs);
+
+    // Optional chairman pass over the extractive merge (kept if the call fails)
+    if request.chairman_synthesis.unwrap_or(false) && outputs.len() > 1 {
+        if let Some(canister) = specialist_model_canister(routing.primary) {
+            let chairman_request = InferenceRequest {
+                prompt: synthesis::chairman_prompt(&request.query, &outputs, &report),
+                max_tokens: 512,
+                temperature: 0.3,
+                top_p: 0.9,
+                context: Vec::new(),
+                system_prompt: Some("You are the chairman of an AI council.".to_string()),
+            };
+            let result: InferenceCallResult = call(canister, "infer", (chairman_request,)).await;
+            match result {
+                Ok((Ok(response),)) if !response.response.trim().is_empty() => {
+                    report.merged_response = response.response;
+                    report.method = "chairman".to_string();
+                    report.tokens_used = report.tokens_used.saturating_add(response.tokens_generated);
+                }
+                Ok((Ok(_),)) => ic_cdk::println!("Chairman returned an empty synthesis"),
+                Ok((Err(e),)) => ic_cdk::println!("Chairman synthesis error: {}", e),
+                Err(e) => ic_cdk::println!("Chairman synthesis call failed: {:?}", e),
+            }
+        }
+    }
+
+    let response = if report.merged_response.is_empty() {
         "The Hive Mind could not reach a consensus. Please check your connectivity or try a different query.".to_string()
-    } else if model_responses.len() == 1 {
-        model_responses[0].1.clone()
     } else {
-        // Multi-agent consensus synthesis
-        // We use the highest confidence response as the base
-        model_responses
-            .iter()
-            .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
-            .map(|(_, response, _)| response.clone())
-            .unwrap_or_default()
+        report.merged_response.clone()
     };
 
-    let confidence_score = if model_responses.is_empty() {
-        0.0
-    } else {
-        model_responses.iter().map(|(_, _, conf)| conf).sum::<f32>() / model_responses.len() as f32
-    };
+    let model_responses = outputs
+        .into_iter()
+        .zip(report.contributions.iter())
+        .map(|(output, contribution)| (output.model, output.response, contribution.confidence))
+        .collect();
 
     let latency_ms = (ic_cdk::api::time() - start_time) / 1_000_000;
 
     Ok(AIResponse {
-        response: consensus,
-        confidence_score,
+        response,
+        confidence_score: report.confidence,
         inference_method,
-        tokens_used: 0, 
+        tokens_used: report.tokens_used,
         latency_ms,
         model_responses,
+        routing: Some(routing),
+        synthesis: Some(report),
     })
 }
 
+// ============================================================================
+// Pipeline Inference
+// ============================================================================
+
+type StageInfoCallResult = Result<(pipeline::StageInfo,), (ic_cdk::api::call::RejectionCode, String)>;
+type StageCallResult = Result<(Result<pipeline::StageResponse, String>,), (ic_cdk::api::call::RejectionCode, String)>;
+type PrepareCallResult = Result<(Result<pipeline::PreparedPrompt, String>,), (ic_cdk::api::call::RejectionCode, String)>;
+type DecodeCallResult = Result<(Result<pipeline::DecodedCompletion, String>,), (ic_cdk::api::call::RejectionCode, String)>;
+
+fn pipeline_topology() -> Option<PipelineTopology> {
+    Some(PIPELINE.with(|p| p.borrow().get().clone())).filter(|t| !t.stages.is_empty())
+}
+
+/// Configure the pipelined model from its stage canisters in layer order
+/// (controllers only). Each canister reports the layers it holds; together they
+/// must chain from layer 0 to the model's last. An empty list removes the pipeline.
+#[update]
+async fn set_pipeline_topology(canister_ids: Vec<Principal>) -> Result<PipelineStatus, String> {
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can configure the pipeline".to_string());
+    }
+
+    let mut topology = PipelineTopology { configured_at: ic_cdk::api::time(), ..Default::default() };
+    for canister_id in canister_ids {
+        let result: StageInfoCallResult = call(canister_id, "get_pipeline_stage", ()).await;
+        let info = match result {
+            Ok((info,)) => info,
+            Err(e) => return Err(format!("Could not reach stage canister {}: {:?}", canister_id, e)),
+        };
+        if !info.ready {
+            return Err(format!("Stage canister {} is not ready: {}", canister_id, info.error.unwrap_or_default()));
+        }
+        if topology.stages.is_empty() {
+            topology.model_id = info.model_id.clone();
+            topology.total_layers = info.total_layers;
+            topology.hidden_dim = info.hidden_dim;
+        } else if info.model_id != topology.model_id || info.total_layers != topology.total_layers || info.hidden_dim != topology.hidden_dim {
+            return Err(format!("Stage canister {} serves a different model", canister_id));
+        }
+        topology.stages.push(PipelineStage { canister_id, start_layer: info.start_layer, end_layer: info.end_layer });
+    }
+    if !topology.stages.is_empty() {
+        pipeline::validate_topology(&topology.stages, topology.total_layers)?;
+    }
+
+    let stages = topology.stages.len();
+    PIPELINE.with(|p| p.borrow_mut().set(topology))
+        .map_err(|e| format!("Failed to save pipeline: {:?}", e))?;
+    PIPELINE_STATS.with(|s| *s.borrow_mut() = vec![pipeline::StageStats::default(); stages]);
+    Ok(get_pipeline_topology())
+}
+
+/// Pipeline stages with their layer ranges and call latency
+#[query]
+fn get_pipeline_topology() -> PipelineStatus {
+    let topology = PIPELINE.with(|p| p.borrow().get().clone());
+    let stats = PIPELINE_STATS.with(|s| s.borrow().clone());
+    PipelineStatus {
+        model_id: topology.model_id,
+        total_layers: topology.total_layers,
+        hidden_dim: topology.hidden_dim,
+        stages: topology.stages
+            .into_iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                let stats = stats.get(i).cloned().unwrap_or_default();
+                pipeline::StageStatus { stage, mean_latency_ms: stats.mean_latency_ms(), stats }
+            })
+            .collect(),
+    }
+}
+
+fn record_stage_call(index: usize, latency_ms: u64, error: Option<String>, retry: bool) {
+    PIPELINE_STATS.with(|s| {
+        if let Some(stats) = s.borrow_mut().get_mut(index) {
+            stats.retries += retry as u64;
+            stats.record(latency_ms, error);
+        }
+    });
+}
+
+/// One stage step, retried with backoff when the call fails (stages accept a repeat
+/// of the latest position). Returns the output and the time spent over all attempts.
+async fn call_stage(index: usize, canister: Principal, request: &pipeline::StageRequest) -> Result<(StageOutput, u64), String> {
+    let mut spent_ms = 0;
+    let mut last_error = String::new();
+    for attempt in 0..pipeline::MAX_STAGE_ATTEMPTS {
+        let backoff = pipeline::retry_backoff_ns(attempt);
+        if backoff > 0 {
+            let waited_from = ic_cdk::api::time();
+            scatter::sleep_until(waited_from + backoff, DEADLINE_TICK_METHOD).await;
+            spent_ms += (ic_cdk::api::time() - waited_from) / 1_000_000;
+        }
+        let start = ic_cdk::api::time();
+        let result: StageCallResult = call(canister, "pipeline_forward", (request.clone(),)).await;
+        let latency_ms = (ic_cdk::api::time() - start) / 1_000_000;
+        spent_ms += latency_ms;
+        let error = match result {
+            Ok((Ok(response),)) => {
+                record_stage_call(index, latency_ms, None, attempt > 0);
+                return Ok((response.output, spent_ms));
+            }
+            Ok((Err(e),)) => e,
+            Err((code, msg)) => format!("{:?} - {}", code, msg),
+        };
+        ic_cdk::println!("Pipeline stage {} attempt {} failed: {}", index, attempt + 1, error);
+        record_stage_call(index, latency_ms, Some(error.clone()), attempt > 0);
+        last_error = error;
+    }
+    Err(format!("Stage {} failed after {} attempts: {}", index, pipeline::MAX_STAGE_ATTEMPTS, last_error))
+}
+
+/// Push every position through the stages until the run is done
+async fn drive_pipeline(
+    topology: &PipelineTopology,
+    session_id: &str,
+    run: &mut pipeline::PipelineRun,
+    sampling: &pipeline::SamplingParams,
+    stage_latency_ms: &mut [u64],
+) -> Result<(), String> {
+    while !run.done {
+        let mut input = StageInput::Token(run.token());
+        for (index, stage) in topology.stages.iter().enumerate() {
+            let last = index + 1 == topology.stages.len();
+            let request = pipeline::StageRequest {
+                session_id: session_id.to_string(),
+                position: run.position as u32,
+                input,
+                sample: (last && run.samples()).then(|| sampling.clone()),
+            };
+            let (output, latency_ms) = call_stage(index, stage.canister_id, &request).await?;
+            stage_latency_ms[index] += latency_ms;
+            if last {
+                run.accept(output)?;
+                break;
+            }
+            input = match output {
+                StageOutput::Hidden(hidden) if hidden.len() == topology.hidden_dim as usize => StageInput::Hidden(hidden),
+                other => return Err(format!("Stage {} returned {:?} instead of a hidden state", index, other)),
+            };
+        }
+    }
+    Ok(())
+}
+
+/// Generate a completion on the pipeline: the first stage renders and tokenizes the
+/// prompt, positions flow through every stage, and the first stage decodes the answer
+async fn run_pipeline(topology: &PipelineTopology, request: InferenceRequest) -> Result<PipelineResponse, String> {
+    let start_time = ic_cdk::api::time();
+    let first = topology.stages[0].canister_id;
+
+    let prepared: PrepareCallResult = call(first, "pipeline_prepare", (request.clone(),)).await;
+    let prepared = match prepared {
+        Ok((Ok(prepared),)) => prepared,
+        Ok((Err(e),)) => return Err(format!("Prompt preparation failed: {}", e)),
+        Err((code, msg)) => return Err(format!("Prompt preparation call failed: {:?} - {}", code, msg)),
+    };
+    let mut run = pipeline::PipelineRun::new(prepared)?;
+
+    let seq = MEMORY_SEQ.with(|s| {
+        s.set(s.get() + 1);
+        s.get()
+    });
+    let session_id = format!("pipe-{}-{}", start_time, seq);
+    let sampling = pipeline::SamplingParams { temperature: request.temperature, top_p: request.top_p, seed: start_time };
+    let mut stage_latency_ms = vec![0; topology.stages.len()];
+    let outcome = drive_pipeline(topology, &session_id, &mut run, &sampling, &mut stage_latency_ms).await;
+
+    // Free the stages' KV caches whether or not the run finished
+    for stage in &topology.stages {
+        let _ = ic_cdk::api::call::notify(stage.canister_id, "pipeline_release", (session_id.clone(),));
+    }
+    outcome?;
+
+    let decoded: DecodeCallResult = call(first, "pipeline_decode", (run.generated().to_vec(),)).await;
+    let decoded = match decoded {
+        Ok((Ok(decoded),)) => decoded,
+        Ok((Err(e),)) => return Err(format!("Decoding failed: {}", e)),
+        Err((code, msg)) => return Err(format!("Decoding call failed: {:?} - {}", code, msg)),
+    };
+
+    Ok(PipelineResponse {
+        response: decoded.response,
+        reasoning: decoded.reasoning,
+        prompt_tokens: run.prompt_len() as u32,
+        tokens_generated: run.generated().len() as u32,
+        latency_ms: (ic_cdk::api::time() - start_time) / 1_000_000,
+        stage_latency_ms,
+    })
+}
+
+/// Answer a request on the configured pipeline alone (no routing or synthesis)
+#[update]
+async fn pipeline_infer(request: AIRequest) -> Result<PipelineResponse, String> {
+    if ic_cdk::caller() == Principal::anonymous() {
+        return Err("Authentication required".to_string());
+    }
+    let topology = pipeline_topology().ok_or("No inference pipeline configured")?;
+    let inference_request = InferenceRequest {
+        prompt: request.query,
+        max_tokens: request.max_tokens.unwrap_or(512).clamp(1, MAX_STREAM_TOKENS),
+        temperature: 0.7,
+        top_p: 0.95,
+        context: request.context,
+        system_prompt: request.system_prompt,
+    };
+    run_pipeline(&topology, inference_request).await
+}
+
+// ============================================================================
+// Streaming AI Pipeline
+// ============================================================================
+
+/// Start a streamed answer from the primary routed specialist's model canister.
+/// The model generates in rounds; poll `next_chunk` for progressive output.
+/// Each user may hold `MAX_STREAMS_PER_USER` open streams.
+#[update]
+async fn start_ai_stream(request: AIRequest) -> Result<StreamStart, String> {
+    let caller = ic_cdk::caller();
+    if caller == Principal::anonymous() {
+        return Err("Authentication required".to_string());
+    }
+
+    let now = ic_cdk::api::time();
+    STREAMS.with(|s| {
+        let expired: Vec<String> = s.borrow()
+            .iter()
+            .filter(|(_, stream)| now.saturating_sub(stream.created_at) > STREAM_TTL_NS)
+            .map(|(id, _)| id)
+            .collect();
+        let mut streams = s.borrow_mut();
+        for id in expired {
+            streams.remove(&id);
+        }
+    });
+
+    let open = STREAMS.with(|s| s.borrow().iter().filter(|(_, stream)| stream.owner == caller).count());
+    let starting = STREAM_STARTS.with(|s| s.borrow().get(&caller).copied().unwrap_or(0));
+    if open + starting >= MAX_STREAMS_PER_USER {
+        return Err(format!("Too many open streams (max {})", MAX_STREAMS_PER_USER));
+    }
+
+    let routing = route_and_log(&request.query, caller);
+    let (specialist_id, model_canister) = routing.routed.iter()
+        .find_map(|(id, _)| specialist_model_canister(*id).map(|c| (*id, c)))
+        .ok_or("No model canisters registered")?;
+
+    let inference_request = InferenceRequest {
+        prompt: request.query.clone(),
+        max_tokens: request.max_tokens.unwrap_or(DEFAULT_STREAM_TOKENS).clamp(1, MAX_STREAM_TOKENS),
+        temperature: 0.7,
+        top_p: 0.95,
+        context: request.context.clone(),
+        system_prompt: request.system_prompt.clone(),
+    };
+    STREAM_STARTS.with(|s| *s.borrow_mut().entry(caller).or_default() += 1);
+    let result: StartGenerationCallResult = call(model_canister, "start_generation", (inference_request,)).await;
+    STREAM_STARTS.with(|s| {
+        let mut starts = s.borrow_mut();
+        if let Some(count) = starts.get_mut(&caller) {
+            *count -= 1;
+            if *count == 0 {
+                starts.remove(&caller);
+            }
+        }
+    });
+    let model_session_id = match result {
+        Ok((Ok(id),)) => id,
+        Ok((Err(e),)) => return Err(format!("Model canister refused generation: {}", e)),
+        Err((code, msg)) => return Err(format!("Model canister call failed: {:?} - {}", code, msg)),
+    };
+
+    let seq = MEMORY_SEQ.with(|s| {
+        s.set(s.get() + 1);
+        s.get()
+    });
+    let session_id = format!("stream-{}-{}", now, seq);
+    STREAMS.with(|s| s.borrow_mut().insert(session_id.clone(), StreamSession {
+        id: session_id.clone(),
+        owner: caller,
+        model_canister,
+        model_session_id,
+        specialist_id,
+        created_at: now,
+    }));
+
+    Ok(StreamStart {
+        session_id,
+        model: format!("DeepSeek-R1-7B-OnChain ({})", specialist_name(specialist_id)),
+        routing,
+    })
+}
+
+fn owned_stream(session_id: &str, caller: Principal) -> Result<StreamSession, String> {
+    let stream = STREAMS.with(|s| s.borrow().get(&session_id.to_string()))
+        .ok_or("Stream not found")?;
+    if stream.owner != caller {
+        return Err("Not the owner of this stream".to_string());
+    }
+    Ok(stream)
+}
+
+/// Text generated since the previous poll; the stream is closed once `done`
+#[update]
+async fn next_chunk(session_id: String) -> Result<GenerationChunk, String> {
+    let stream = owned_stream(&session_id, ic_cdk::caller())?;
+
+    let result: NextChunkCallResult = call(stream.model_canister, "next_chunk", (stream.model_session_id.clone(),)).await;
+    let mut chunk = match result {
+        Ok((Ok(chunk),)) => chunk,
+        Ok((Err(e),)) => return Err(e),
+        Err((code, msg)) => return Err(format!("Model canister call failed: {:?} - {}", code, msg)),
+    };
+    chunk.session_id = session_id.clone();
+
+    if chunk.done {
+        STREAMS.with(|s| s.borrow_mut().remove(&session_id));
+    }
+    Ok(chunk)
+}
+
+/// Stop a streamed answer
+#[update]
+async fn cancel_ai_stream(session_id: String) -> Result<(), String> {
+    let stream = owned_stream(&session_id, ic_cdk::caller())?;
+
+    let result: Result<(Result<(), String>,), (ic_cdk::api::call::RejectionCode, String)> =
+        call(stream.model_canister, "cancel_generation", (stream.model_session_id.clone(),)).await;
+    match result {
+        Ok((Ok(()),)) => {
+            STREAMS.with(|s| s.borrow_mut().remove(&session_id));
+            Ok(())
+        }
+        Ok((Err(e),)) => Err(e),
+        Err((code, msg)) => Err(format!("Model canister call failed: {:?} - {}", code, msg)),
+    }
+}
+
 // ============================================================================
 // Voice Processing
 // ============================================================================
@@ -478,7 +1513,8 @@ async fn synthesize_voice(request: VoiceRequest) -> Result<VoiceResponse, String
 // Memory Management
 // ============================================================================
 
-/// Store memory in vector database shards
+/// Store memory on the shard that owns its id on the hash ring.
+/// An empty `embedding` is computed from `content` with the on-canister embedder.
 #[update]
 async fn store_memory(
     content: String,
@@ -486,106 +1522,165 @@ async fn store_memory(
     metadata: Vec<(String, String)>,
     importance: f32,
 ) -> Result<String, String> {
-    // Get first vector DB canister (in production, would hash to determine shard)
-    let vector_db_canister = VECTOR_DB_CANISTERS.with(|v| {
-        v.borrow().iter().next().map(|(shard_id, p)| (shard_id.0, p.0))
-    });
-    
-    if let Some((shard_id, vector_db_id)) = vector_db_canister {
-        #[derive(CandidType)]
-        struct VectorEmbedding {
-            id: String,
-            vector: Vec<f32>,
-            metadata: Vec<(String, String)>,
-            timestamp: u64,
-            importance: f32,
-            shard_id: u32,
-        }
-        
-        let memory_id = format!("memory-{}-{}", ic_cdk::api::time(), shard_id);
-        let now = ic_cdk::api::time();
-        
-        let vector_embedding = VectorEmbedding {
-            id: memory_id.clone(),
-            vector: embedding,
-            metadata,
-            timestamp: now,
-            importance,
-            shard_id,
-        };
-        
-        type VectorCallResult = Result<(Result<(), String>,), (ic_cdk::api::call::RejectionCode, String)>;
-        let call_result: VectorCallResult = call(vector_db_id, "store_vector", (vector_embedding,)).await;
-        
-        match call_result {
-            Ok((Ok(_),)) => Ok(memory_id),
-            Ok((Err(e),)) => Err(format!("Vector storage error: {}", e)),
-            Err(e) => Err(format!("Vector storage call failed: {:?}", e)),
+    let mut metadata: Vec<(String, String)> = metadata
+        .into_iter()
+        .filter(|(k, _)| k != "embedder" && k != "embedder_version")
+        .collect();
+    let embedding = if embedding.is_empty() {
+        if content.trim().is_empty() {
+            return Err("Content or embedding required".to_string());
         }
+        metadata.extend(embedding::embedder_metadata());
+        embedding::embed(&content)
+    } else if embedding.len() != embedding::EMBEDDING_DIM {
+        return Err(format!("Embedding must have {} dimensions", embedding::EMBEDDING_DIM));
     } else {
-        Err("No vector DB canisters registered".to_string())
+        metadata.extend(embedding::external_metadata());
+        embedding
+    };
+
+    let now = ic_cdk::api::time();
+    let seq = MEMORY_SEQ.with(|s| {
+        s.set(s.get() + 1);
+        s.get()
+    });
+    let memory_id = format!("memory-{}-{}", now, seq);
+
+    let shard_id = current_ring()
+        .shard_for(&memory_id)
+        .ok_or("No vector DB canisters registered")?;
+    let vector_db_id = vector_db_canister(shard_id).ok_or("No vector DB canisters registered")?;
+
+    if !content.is_empty() && !metadata.iter().any(|(k, _)| k == "content") {
+        metadata.push(("content".to_string(), content));
+    }
+
+    let vector_embedding = VectorEmbedding {
+        id: memory_id.clone(),
+        vector: embedding,
+        metadata,
+        timestamp: now,
+        importance,
+        shard_id,
+        namespace: None,
+    };
+
+    let call_result: VectorCallResult<()> = call(vector_db_id, "store_vector", (vector_embedding,)).await;
+
+    match call_result {
+        Ok((Ok(_),)) => Ok(memory_id),
+        Ok((Err(e),)) => Err(format!("Vector storage error: {}", e)),
+        Err(e) => Err(format!("Vector storage call failed: {:?}", e)),
     }
 }
 
-/// Query memories from vector database shards
-#[query]
-async fn query_memory(
-    query_text: String,
-    top_k: u32,
-) -> Result<Vec<(String, Vec<f32>, f32, Vec<(String, String)>)>, String> {
-    // For now, query first vector DB canister
-    // In production, would query all shards and aggregate results
-    let vector_db_canister = VECTOR_DB_CANISTERS.with(|v| {
-        v.borrow().iter().next().map(|(_, p)| p.0)
-    });
-    
-    if let Some(vector_db_id) = vector_db_canister {
-        // Generate embedding for query text (simplified - in production would use actual embedding model)
-        // For now, create a simple embedding based on text hash
-        let query_embedding: Vec<f32> = (0..384)
-            .map(|i| {
-                let hash = (query_text.len() as u64).wrapping_mul(i as u64);
-                ((hash % 1000) as f32 / 1000.0) - 0.5
-            })
-            .collect();
-        
-        #[derive(CandidType)]
-        struct QueryRequest {
-            query_vector: Vec<f32>,
-            top_k: u32,
-            min_similarity: f32,
-            filter_metadata: Option<Vec<(String, String)>>,
+/// Query memories from all vector database shards in parallel and merge a global top-k.
+/// Shards that fail, miss the deadline or are cooling down are listed in `shards_failed`;
+/// answers arriving after the deadline can be fetched with `get_late_memory_results`.
+/// With `query_embedding` the client-supplied memories are searched instead of the
+/// on-canister embedder's.
+#[update]
+async fn query_memory(query_text: String, top_k: u32, query_embedding: Option<Vec<f32>>) -> Result<MemoryQueryResult, String> {
+    let caller = ic_cdk::caller();
+    // Only compare against vectors from the same embedder/version
+    let (query_vector, filter) = match query_embedding {
+        Some(vector) if vector.len() != embedding::EMBEDDING_DIM => {
+            return Err(format!("Embedding must have {} dimensions", embedding::EMBEDDING_DIM));
         }
-        
-        #[derive(CandidType, Deserialize)]
-        struct QueryResult {
-            id: String,
-            vector: Vec<f32>,
-            similarity: f32,
-            metadata: Vec<(String, String)>,
-            timestamp: u64,
+        Some(vector) => (vector, embedding::external_metadata()),
+        None => (embedding::embed(&query_text), embedding::embedder_metadata()),
+    };
+    let request = VectorQueryRequest {
+        query_vector,
+        top_k,
+        min_similarity: 0.0,
+        filter_metadata: Some(filter),
+    };
+
+    let now = ic_cdk::api::time();
+    let mut shards_failed = Vec::new();
+    let mut targets = Vec::new();
+    for (shard_id, canister_id) in all_shards() {
+        let cooling = SHARD_HEALTH.with(|h| {
+            h.borrow().get(&shard_id).map(|s| s.cooling_down(now, SHARD_COOLDOWN_NS)).unwrap_or(false)
+        });
+        if cooling {
+            shards_failed.push(ShardError { shard_id, failure: ShardFailure::Skipped });
+        } else {
+            targets.push((shard_id, canister_id));
         }
-        
-        let query_request = QueryRequest {
-            query_vector: query_embedding,
-            top_k,
-            min_similarity: 0.0,
-            filter_metadata: None,
-        };
-        
-        type QueryCallResult = Result<(Result<Vec<QueryResult>, String>,), (ic_cdk::api::call::RejectionCode, String)>;
-        let call_result: QueryCallResult = call(vector_db_id, "query_vectors", (query_request,)).await;
-        
-        match call_result {
-            Ok((Ok(results),)) => {
-                Ok(results.into_iter().map(|r| (r.id, r.vector, r.similarity, r.metadata)).collect())
+    }
+
+    let seq = MEMORY_SEQ.with(|s| {
+        s.set(s.get() + 1);
+        s.get()
+    });
+    let query_id = format!("query-{}-{}", now, seq);
+
+    let calls: Vec<_> = targets
+        .iter()
+        .map(|(_, canister_id)| query_shard(*canister_id, request.clone()))
+        .collect();
+    let late_query_id = query_id.clone();
+    let late_shards: Vec<u32> = targets.iter().map(|(shard_id, _)| *shard_id).collect();
+    let outcomes = scatter::gather(calls, now + SHARD_QUERY_TIMEOUT_MS * 1_000_000, DEADLINE_TICK_METHOD, move |index, (outcome, latency_ms)| {
+        SHARD_HEALTH.with(|h| h.borrow_mut().entry(late_shards[index]).or_default().last_latency_ms = latency_ms);
+        if let Ok(results) = outcome {
+            keep_late_results(&late_query_id, caller, results);
+        }
+    }).await;
+
+    let mut per_shard = Vec::new();
+    for ((shard_id, _), outcome) in targets.iter().zip(outcomes) {
+        match outcome {
+            Some((outcome, latency_ms)) => {
+                record_shard_outcome(*shard_id, latency_ms, outcome.is_err());
+                match outcome {
+                    Ok(results) => per_shard.push(results),
+                    Err(failure) => shards_failed.push(ShardError { shard_id: *shard_id, failure }),
+                }
+            }
+            None => {
+                record_shard_outcome(*shard_id, SHARD_QUERY_TIMEOUT_MS, true);
+                shards_failed.push(ShardError { shard_id: *shard_id, failure: ShardFailure::TimedOut(SHARD_QUERY_TIMEOUT_MS) });
             }
-            Ok((Err(e),)) => Err(format!("Vector query error: {}", e)),
-            Err(e) => Err(format!("Vector query call failed: {:?}", e)),
         }
-    } else {
-        Ok(Vec::new()) // Return empty if no vector DB canisters
     }
+
+    Ok(MemoryQueryResult {
+        query_id,
+        results: shard_router::merge_top_k(per_shard, top_k as usize),
+        shards_queried: targets.len() as u32,
+        partial: !shards_failed.is_empty(),
+        shards_failed,
+    })
+}
+
+/// Merged shard answers to one of the caller's queries that arrived after its deadline
+#[query]
+fn get_late_memory_results(query_id: String, top_k: u32) -> Result<Vec<MemoryResult>, String> {
+    let caller = ic_cdk::caller();
+    LATE_RESULTS.with(|l| match l.borrow().get(&query_id) {
+        Some((owner, results)) if *owner == caller => Ok(shard_router::merge_top_k(vec![results.clone()], top_k as usize)),
+        Some(_) => Err("Not your query".to_string()),
+        None => Ok(Vec::new()),
+    })
+}
+
+/// Embed text with the on-canister embedder (for clients storing their own vectors)
+#[query]
+fn embed_text(text: String) -> Vec<f32> {
+    embedding::embed(&text)
+}
+
+/// (embedder id, version, dimensions)
+#[query]
+fn get_embedder_info() -> (String, String, u32) {
+    (
+        embedding::EMBEDDER_ID.to_string(),
+        embedding::EMBEDDER_VERSION.to_string(),
+        embedding::EMBEDDING_DIM as u32,
+    )
 }
 
 // ============================================================================
@@ -0,0 +1,326 @@
+//! Pipeline-Parallel Inference
+//!
+//! A model too large for one canister is split by layer range across several
+//! deepseek_model canisters (stages). For every token position the orchestrator
+//! passes the token to the first stage and each stage's hidden state to the next;
+//! the last stage returns the sampled token. Stages keep their own KV caches, so
+//! only one position's activations cross canister boundaries per step.
+//!
+//! A stage call that fails is retried after an exponential backoff (stages accept
+//! a repeat of the latest position), and per-stage call latency is tracked for
+//! `get_pipeline_topology`.
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Attempts per stage step before the run fails
+pub const MAX_STAGE_ATTEMPTS: u32 = 3;
+/// Wait before the first retry of a stage step; doubles per further retry
+pub const STAGE_RETRY_BACKOFF_NS: u64 = 2_000_000_000;
+
+/// Wait before attempt `attempt` (0-based) of a stage step
+pub fn retry_backoff_ns(attempt: u32) -> u64 {
+    match attempt {
+        0 => 0,
+        n => STAGE_RETRY_BACKOFF_NS.saturating_mul(1 << (n - 1).min(10)),
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PipelineStage {
+    pub canister_id: Principal,
+    pub start_layer: u32,
+    pub end_layer: u32, // Exclusive
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PipelineTopology {
+    pub model_id: Option<String>,
+    pub stages: Vec<PipelineStage>, // In layer order; empty = no pipeline configured
+    pub total_layers: u32,
+    pub hidden_dim: u32,
+    pub configured_at: u64,
+}
+
+impl Storable for PipelineTopology {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// Stages must cover layers 0..total_layers in order, without gaps or overlaps
+pub fn validate_topology(stages: &[PipelineStage], total_layers: u32) -> Result<(), String> {
+    if stages.is_empty() {
+        return Err("A pipeline needs at least one stage".to_string());
+    }
+    let mut next = 0;
+    for (i, stage) in stages.iter().enumerate() {
+        if stage.start_layer != next {
+            return Err(format!("Stage {} starts at layer {}, expected {}", i, stage.start_layer, next));
+        }
+        if stage.end_layer <= stage.start_layer {
+            return Err(format!("Stage {} holds no layers", i));
+        }
+        if stages[..i].iter().any(|s| s.canister_id == stage.canister_id) {
+            return Err(format!("Canister {} appears twice", stage.canister_id));
+        }
+        next = stage.end_layer;
+    }
+    if next != total_layers {
+        return Err(format!("Stages end at layer {}, the model has {}", next, total_layers));
+    }
+    Ok(())
+}
+
+/// Call statistics of one stage (in-memory; reset on upgrade or reconfiguration)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct StageStats {
+    pub calls: u64,
+    pub failures: u64,
+    pub retries: u64,
+    pub total_latency_ms: u64,
+    pub last_latency_ms: u64,
+    pub max_latency_ms: u64,
+    pub last_error: Option<String>,
+}
+
+impl StageStats {
+    pub fn record(&mut self, latency_ms: u64, error: Option<String>) {
+        self.calls += 1;
+        self.total_latency_ms += latency_ms;
+        self.last_latency_ms = latency_ms;
+        self.max_latency_ms = self.max_latency_ms.max(latency_ms);
+        if error.is_some() {
+            self.failures += 1;
+            self.last_error = error;
+        }
+    }
+
+    pub fn mean_latency_ms(&self) -> u64 {
+        self.total_latency_ms.checked_div(self.calls).unwrap_or(0)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StageStatus {
+    pub stage: PipelineStage,
+    pub stats: StageStats,
+    pub mean_latency_ms: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PipelineStatus {
+    pub model_id: Option<String>,
+    pub total_layers: u32,
+    pub hidden_dim: u32,
+    pub stages: Vec<StageStatus>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PipelineResponse {
+    pub response: String,
+    pub reasoning: Option<String>,
+    pub prompt_tokens: u32,
+    pub tokens_generated: u32,
+    pub latency_ms: u64,
+    pub stage_latency_ms: Vec<u64>, // Time spent in each stage during this run
+}
+
+// Stage canister interface (mirrors deepseek_model's pipeline types)
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct StageInfo {
+    pub model_id: Option<String>,
+    pub start_layer: u32,
+    pub end_layer: u32,
+    pub total_layers: u32,
+    pub hidden_dim: u32,
+    pub ready: bool,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum StageInput {
+    Token(u32),
+    Hidden(Vec<f32>),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SamplingParams {
+    pub temperature: f32,
+    pub top_p: f32,
+    pub seed: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct StageRequest {
+    pub session_id: String,
+    pub position: u32,
+    pub input: StageInput,
+    pub sample: Option<SamplingParams>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum StageOutput {
+    Hidden(Vec<f32>),
+    Token(u32),
+    Cached,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct StageResponse {
+    pub output: StageOutput,
+    pub start_layer: u32,
+    pub end_layer: u32,
+    pub instructions: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct PreparedPrompt {
+    pub tokens: Vec<u32>,
+    pub stop_tokens: Vec<u32>,
+    pub max_tokens: u32,
+    pub context_messages_dropped: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct DecodedCompletion {
+    pub response: String,
+    pub reasoning: Option<String>,
+}
+
+/// Token bookkeeping of one pipeline run: which token enters the pipeline at each
+/// position, whether the last stage samples there, and when generation ends
+#[derive(Clone, Debug)]
+pub struct PipelineRun {
+    tokens: Vec<u32>,
+    prompt_len: usize,
+    max_tokens: u32,
+    stop_tokens: Vec<u32>,
+    pub position: usize,
+    pub done: bool,
+}
+
+impl PipelineRun {
+    pub fn new(prompt: PreparedPrompt) -> Result<Self, String> {
+        if prompt.tokens.is_empty() {
+            return Err("Empty prompt".to_string());
+        }
+        Ok(Self {
+            prompt_len: prompt.tokens.len(),
+            tokens: prompt.tokens,
+            max_tokens: prompt.max_tokens,
+            stop_tokens: prompt.stop_tokens,
+            position: 0,
+            done: prompt.max_tokens == 0,
+        })
+    }
+
+    /// Token entering the first stage at the current position
+    pub fn token(&self) -> u32 {
+        self.tokens[self.position]
+    }
+
+    /// The last stage samples once the whole prompt has been read
+    pub fn samples(&self) -> bool {
+        self.position + 1 >= self.prompt_len
+    }
+
+    pub fn prompt_len(&self) -> usize {
+        self.prompt_len
+    }
+
+    pub fn generated(&self) -> &[u32] {
+        &self.tokens[self.prompt_len..]
+    }
+
+    /// Apply the last stage's output for the current position and advance
+    pub fn accept(&mut self, output: StageOutput) -> Result<(), String> {
+        match (output, self.samples()) {
+            (StageOutput::Cached, false) => {}
+            (StageOutput::Token(token), true) => {
+                if self.stop_tokens.contains(&token) {
+                    self.done = true;
+                } else {
+                    self.tokens.push(token);
+                    self.done = self.generated().len() >= self.max_tokens as usize;
+                }
+            }
+            (output, _) => return Err(format!("Unexpected output from the last stage: {:?}", output)),
+        }
+        self.position += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(id: u8, start: u32, end: u32) -> PipelineStage {
+        PipelineStage { canister_id: Principal::from_slice(&[id]), start_layer: start, end_layer: end }
+    }
+
+    #[test]
+    fn test_topology_must_cover_all_layers_in_order() {
+        assert!(validate_topology(&[stage(1, 0, 14), stage(2, 14, 28)], 28).is_ok());
+        assert!(validate_topology(&[], 28).is_err());
+        assert!(validate_topology(&[stage(1, 0, 14), stage(2, 15, 28)], 28).is_err()); // Gap
+        assert!(validate_topology(&[stage(1, 0, 14), stage(2, 10, 28)], 28).is_err()); // Overlap
+        assert!(validate_topology(&[stage(1, 0, 14)], 28).is_err()); // Short
+        assert!(validate_topology(&[stage(1, 0, 14), stage(1, 14, 28)], 28).is_err()); // Same canister
+    }
+
+    #[test]
+    fn test_retries_back_off_exponentially() {
+        assert_eq!(retry_backoff_ns(0), 0);
+        assert_eq!(retry_backoff_ns(1), STAGE_RETRY_BACKOFF_NS);
+        assert_eq!(retry_backoff_ns(2), 2 * STAGE_RETRY_BACKOFF_NS);
+        assert_eq!(retry_backoff_ns(40), 1024 * STAGE_RETRY_BACKOFF_NS);
+    }
+
+    #[test]
+    fn test_run_reads_prompt_then_samples_until_stop() {
+        let prompt = PreparedPrompt { tokens: vec![5, 6, 7], stop_tokens: vec![1], max_tokens: 10, context_messages_dropped: 0 };
+        let mut run = PipelineRun::new(prompt).unwrap();
+
+        // Prompt positions only fill caches; the last one yields the first token
+        assert!(!run.samples());
+        assert!(run.accept(StageOutput::Token(9)).is_err());
+        run.accept(StageOutput::Cached).unwrap();
+        run.accept(StageOutput::Cached).unwrap();
+        assert!(run.samples());
+        run.accept(StageOutput::Token(9)).unwrap();
+        assert_eq!((run.token(), run.position), (9, 3));
+        run.accept(StageOutput::Token(8)).unwrap();
+        run.accept(StageOutput::Token(1)).unwrap();
+        assert!(run.done);
+        assert_eq!(run.generated(), &[9, 8]);
+
+        let prompt = PreparedPrompt { tokens: vec![5], stop_tokens: vec![], max_tokens: 2, context_messages_dropped: 0 };
+        let mut run = PipelineRun::new(prompt).unwrap();
+        run.accept(StageOutput::Token(3)).unwrap();
+        run.accept(StageOutput::Token(4)).unwrap();
+        assert!(run.done);
+        assert_eq!(run.generated(), &[3, 4]);
+    }
+
+    #[test]
+    fn test_stage_stats() {
+        let mut stats = StageStats::default();
+        assert_eq!(stats.mean_latency_ms(), 0);
+        stats.record(100, None);
+        stats.record(300, Some("rejected".into()));
+        assert_eq!((stats.calls, stats.failures, stats.max_latency_ms, stats.last_latency_ms), (2, 1, 300, 300));
+        assert_eq!(stats.mean_latency_ms(), 200);
+        assert_eq!(stats.last_error.as_deref(), Some("rejected"));
+    }
+}
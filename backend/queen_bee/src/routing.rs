@@ -0,0 +1,225 @@
+//! Specialist Routing
+//!
+//! Each Axiom specialist is registered with a description and example queries. Their
+//! embeddings are averaged into a centroid; a query is routed to the nearest centroid
+//! by cosine similarity. When other specialists score within `ROUTING_MARGIN` of the
+//! best, they are routed to as well. Every decision is logged for later evaluation.
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::embedding;
+
+/// Specialists scoring within this of the best are included in the route
+pub const ROUTING_MARGIN: f32 = 0.05;
+/// Upper bound on specialists consulted for one query
+pub const MAX_ROUTED: usize = 3;
+/// Softmax temperature turning similarity gaps into a confidence
+const CONFIDENCE_TEMPERATURE: f32 = 0.05;
+/// Routing decisions kept in the log (oldest dropped first)
+pub const MAX_LOG_ENTRIES: u64 = 10_000;
+/// Specialist used when none are registered
+pub const FALLBACK_SPECIALIST: u32 = 1;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SpecialistInput {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub examples: Vec<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Specialist {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    pub examples: Vec<String>,
+    pub centroid: Vec<f32>,
+    pub embedder_version: String,
+    pub updated_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoutingResult {
+    pub primary: u32,
+    pub routed: Vec<(u32, f32)>, // (specialist_id, similarity), best first
+    pub confidence: f32,         // Softmax probability of the primary specialist
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RoutingDecision {
+    pub id: u64,
+    pub query: String,
+    pub caller: Principal,
+    pub result: RoutingResult,
+    pub timestamp: u64,
+    pub label: Option<u32>, // Correct specialist, set afterwards by an evaluator
+}
+
+impl Storable for Specialist {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for RoutingDecision {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// Mean of the description and example embeddings, re-normalized
+pub fn build_specialist(input: SpecialistInput, now: u64) -> Result<Specialist, String> {
+    if input.name.trim().is_empty() {
+        return Err("Specialist name required".to_string());
+    }
+    let texts: Vec<&String> = std::iter::once(&input.description)
+        .chain(input.examples.iter())
+        .filter(|t| !t.trim().is_empty())
+        .collect();
+    if texts.is_empty() {
+        return Err("Description or examples required".to_string());
+    }
+
+    let mut centroid = vec![0.0f32; embedding::EMBEDDING_DIM];
+    for text in &texts {
+        for (c, x) in centroid.iter_mut().zip(embedding::embed(text)) {
+            *c += x;
+        }
+    }
+    let norm = centroid.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        centroid.iter_mut().for_each(|x| *x /= norm);
+    }
+
+    Ok(Specialist {
+        id: input.id,
+        name: input.name,
+        description: input.description,
+        examples: input.examples,
+        centroid,
+        embedder_version: format!("{}/{}", embedding::EMBEDDER_ID, embedding::EMBEDDER_VERSION),
+        updated_at: now,
+    })
+}
+
+/// Route a query to the nearest specialist centroid(s)
+pub fn route(query: &str, specialists: &[Specialist]) -> RoutingResult {
+    let q = embedding::embed(query);
+    let mut scores: Vec<(u32, f32)> = specialists
+        .iter()
+        .map(|s| (s.id, q.iter().zip(s.centroid.iter()).map(|(a, b)| a * b).sum::<f32>()))
+        .collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let best = match scores.first() {
+        Some(best) => *best,
+        None => {
+            return RoutingResult {
+                primary: FALLBACK_SPECIALIST,
+                routed: vec![(FALLBACK_SPECIALIST, 0.0)],
+                confidence: 0.0,
+            }
+        }
+    };
+
+    let exp_sum: f32 = scores.iter().map(|(_, s)| ((s - best.1) / CONFIDENCE_TEMPERATURE).exp()).sum();
+    let routed: Vec<(u32, f32)> = scores
+        .iter()
+        .take_while(|(_, s)| best.1 - s <= ROUTING_MARGIN)
+        .take(MAX_ROUTED)
+        .copied()
+        .collect();
+
+    RoutingResult {
+        primary: best.0,
+        routed,
+        confidence: 1.0 / exp_sum,
+    }
+}
+
+/// The five Axiom specialists the swarm starts with
+pub fn default_specialists() -> Vec<SpecialistInput> {
+    let s = |id: u32, name: &str, description: &str, examples: &[&str]| SpecialistInput {
+        id,
+        name: name.to_string(),
+        description: description.to_string(),
+        examples: examples.iter().map(|e| e.to_string()).collect(),
+    };
+    vec![
+        s(1, "Lead Oracle / Blockchain & Bitcoin Expert",
+            "Bitcoin, ordinals, blockchain consensus, ledgers, and general questions about the Internet Computer",
+            &["How do bitcoin ordinals inscriptions work?", "Explain blockchain consensus", "What is the ICP ledger?", "How are BTC transactions confirmed?"]),
+        s(2, "Creative Mind / Art, Culinary & RWA",
+            "Peppers, spicy food, farming and nursery plants, recipes, art, NFT design and creative work",
+            &["Give me a hot sauce recipe with ghost peppers", "How do I grow pepper plants in a nursery?", "Design artwork for my NFT collection", "When should the farm harvest chilies?"]),
+        s(3, "DeFi Sage / Finance & Trading",
+            "Trading, buying and selling, prices, tokens, DeFi yield, liquidity pools and DEX swaps",
+            &["I want to trade tokens on a dex", "Where can I buy or sell this asset at a good price?", "How does liquidity provision earn yield?", "Should I swap ICP for ckBTC?", "How do I sell my seeds to other traders?"]),
+        s(4, "Tech Architect / Smart Contracts",
+            "Code, smart contracts, Rust canisters, Candid interfaces, backend APIs and software development",
+            &["How do I write a Rust canister?", "Fix this Candid interface error", "Design a backend API for my dapp", "Review my smart contract code"]),
+        s(5, "Community Builder / Marketing & Engagement",
+            "Community growth, social media, Twitter, Discord and Telegram engagement, and marketing campaigns",
+            &["How can I grow our Discord community?", "Write a Twitter thread announcing our launch", "Plan a marketing campaign", "Ideas to engage Telegram members"]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Vec<Specialist> {
+        default_specialists().into_iter().map(|s| build_specialist(s, 0).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_routes_to_nearest_specialist() {
+        let specialists = defaults();
+        assert_eq!(route("How do ordinals inscriptions work on bitcoin?", &specialists).primary, 1);
+        assert_eq!(route("Best recipe for a spicy pepper sauce", &specialists).primary, 2);
+        assert_eq!(route("Help me debug my rust canister code", &specialists).primary, 4);
+        assert_eq!(route("How do we grow our discord community?", &specialists).primary, 5);
+
+        // Keyword routing sent this to Creative because "pepper" was checked first
+        assert_eq!(route("I want to trade pepper seeds", &specialists).primary, 3);
+    }
+
+    #[test]
+    fn test_close_scores_route_to_several_specialists() {
+        let specialists = defaults();
+        let mixed = route("sell ghost pepper nft art for tokens on a dex", &specialists);
+        let ids: Vec<u32> = mixed.routed.iter().map(|(id, _)| *id).collect();
+        assert!(ids.contains(&2) && ids.contains(&3), "{:?}", mixed);
+        assert!(mixed.confidence < 0.8);
+
+        let clear = route("How do ordinals inscriptions work on bitcoin?", &specialists);
+        assert_eq!(clear.routed.len(), 1);
+        assert!(clear.confidence > mixed.confidence);
+    }
+
+    #[test]
+    fn test_empty_registry_falls_back() {
+        let result = route("anything", &[]);
+        assert_eq!(result.primary, FALLBACK_SPECIALIST);
+        assert_eq!(result.confidence, 0.0);
+        assert!(build_specialist(SpecialistInput { id: 9, name: "x".into(), description: " ".into(), examples: vec![] }, 0).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Scatter-gather with a deadline
+//!
+//! Calls are spawned as their own tasks so the caller can stop waiting at the
+//! deadline while they keep running: each answer lands in its slot, or, once
+//! the gather has closed, is handed to the late handler instead of dropped.
+//!
+//! The deadline cannot be a timer: a timer callback runs outside the caller's
+//! call context, so resuming the caller from it could not reply. The wait is a
+//! chain of no-op self-calls instead, each answered in the next round.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+
+/// Answers of one scatter-gather, filled as they arrive
+pub struct Gather<T> {
+    slots: Vec<Option<T>>,
+    pending: usize,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<T> Gather<T> {
+    pub fn new(len: usize) -> Self {
+        Self { slots: (0..len).map(|_| None).collect(), pending: len, closed: false, waker: None }
+    }
+
+    /// Record the answer of call `index`; returns it back if it came after `close`
+    pub fn fill(&mut self, index: usize, value: T) -> Option<T> {
+        if self.closed {
+            return Some(value);
+        }
+        if self.slots[index].is_none() {
+            self.pending -= 1;
+        }
+        self.slots[index] = Some(value);
+        if self.pending == 0 {
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+        None
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// Stop collecting; calls that have not answered yet are `None`
+    pub fn close(&mut self) -> Vec<Option<T>> {
+        self.closed = true;
+        self.waker = None;
+        std::mem::take(&mut self.slots)
+    }
+}
+
+/// Wait until `deadline` (IC time in ns) by calling `tick_method` on this canister
+pub async fn sleep_until(deadline: u64, tick_method: &str) {
+    while ic_cdk::api::time() < deadline {
+        let ticked: Result<(), _> = ic_cdk::api::call::call(ic_cdk::id(), tick_method, ()).await;
+        if ticked.is_err() {
+            // Without self-calls there is no way to wait; answer with what has arrived
+            return;
+        }
+    }
+}
+
+/// Run `calls` concurrently and return their answers once all have arrived or
+/// `deadline` has passed (`None` for calls still running). Later answers are
+/// passed to `on_late` with their call index.
+pub async fn gather<T, F>(calls: Vec<F>, deadline: u64, tick_method: &str, on_late: impl Fn(usize, T) + 'static) -> Vec<Option<T>>
+where
+    T: 'static,
+    F: Future<Output = T> + 'static,
+{
+    let state = Rc::new(RefCell::new(Gather::new(calls.len())));
+    let on_late = Rc::new(on_late);
+    for (index, call) in calls.into_iter().enumerate() {
+        let state = Rc::clone(&state);
+        let on_late = Rc::clone(&on_late);
+        ic_cdk::spawn(async move {
+            let value = call.await;
+            let late = state.borrow_mut().fill(index, value);
+            if let Some(value) = late {
+                on_late(index, value);
+            }
+        });
+    }
+
+    let all_answered = {
+        let state = Rc::clone(&state);
+        std::future::poll_fn(move |cx| {
+            let mut gather = state.borrow_mut();
+            if gather.is_complete() {
+                Poll::Ready(())
+            } else {
+                gather.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+    };
+    futures::future::select(Box::pin(all_answered), Box::pin(sleep_until(deadline, tick_method))).await;
+    let answers = state.borrow_mut().close();
+    answers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gather_collects_until_closed() {
+        let mut gather = Gather::new(3);
+        assert_eq!(gather.fill(1, "b"), None);
+        assert_eq!(gather.fill(0, "a"), None);
+        assert!(!gather.is_complete());
+
+        assert_eq!(gather.close(), vec![Some("a"), Some("b"), None]);
+        // The straggler is handed back as late instead of being stored
+        assert_eq!(gather.fill(2, "c"), Some("c"));
+    }
+
+    #[test]
+    fn test_gather_completes_when_every_call_answered() {
+        let mut gather = Gather::new(2);
+        gather.fill(0, 1);
+        gather.fill(0, 2);
+        assert!(!gather.is_complete());
+        gather.fill(1, 3);
+        assert!(gather.is_complete());
+        assert_eq!(gather.close(), vec![Some(2), Some(3)]);
+    }
+}
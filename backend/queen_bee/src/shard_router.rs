@@ -0,0 +1,264 @@
+//! Vector DB Shard Router
+//!
+//! Consistent-hash ring over the registered vector_db shards. Memory ids are hashed
+//! onto the ring so each id has exactly one owning shard, and adding or removing a
+//! shard only moves the ids in the ring segments it gains or loses.
+//!
+//! - Writes go to the owning shard
+//! - Queries fan out to every registered shard (including draining ones) and the
+//!   per-shard top-k lists are merged into a global top-k
+//! - Draining shards are excluded from the ring; `rebalance_step` migrates misplaced
+//!   vectors of every namespace in batches and drops a draining shard once it is empty
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// Virtual nodes per shard; more points spread ids more evenly
+pub const VIRTUAL_NODES: u32 = 64;
+
+/// 64-bit FNV-1a with a splitmix64 finalizer (stable across upgrades and canisters)
+pub fn hash64(data: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HashRing {
+    points: Vec<(u64, u32)>, // (ring position, shard_id), sorted by position
+}
+
+impl HashRing {
+    pub fn new(shard_ids: &[u32]) -> Self {
+        let mut points: Vec<(u64, u32)> = shard_ids
+            .iter()
+            .flat_map(|shard| (0..VIRTUAL_NODES).map(move |v| (hash64(&format!("shard-{}-{}", shard, v)), *shard)))
+            .collect();
+        points.sort();
+        points.dedup_by_key(|p| p.0);
+        Self { points }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Shard owning a key: first ring point clockwise from the key's hash
+    pub fn shard_for(&self, key: &str) -> Option<u32> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let h = hash64(key);
+        let idx = self.points.partition_point(|(pos, _)| *pos < h);
+        Some(self.points[idx % self.points.len()].1)
+    }
+}
+
+// ============================================================================
+// Query Results
+// ============================================================================
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MemoryResult {
+    pub id: String,
+    pub vector: Vec<f32>,
+    pub similarity: f32,
+    pub metadata: Vec<(String, String)>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ShardFailure {
+    Rejected(String),   // Call rejected or shard returned an error
+    TimedOut(u64),      // No answer by the deadline (ms waited); a late answer is kept under the query id
+    Skipped,            // Cooling down after a recent failure
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ShardError {
+    pub shard_id: u32,
+    pub failure: ShardFailure,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MemoryQueryResult {
+    pub query_id: String, // Key for `get_late_memory_results` when a shard timed out
+    pub results: Vec<MemoryResult>,
+    pub shards_queried: u32,
+    pub shards_failed: Vec<ShardError>,
+    pub partial: bool,
+}
+
+/// Merge per-shard top-k lists into a global top-k (ids seen twice during a
+/// migration are kept once, with the better score)
+pub fn merge_top_k(per_shard: Vec<Vec<MemoryResult>>, top_k: usize) -> Vec<MemoryResult> {
+    let mut all: Vec<MemoryResult> = per_shard.into_iter().flatten().collect();
+    all.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then_with(|| a.id.cmp(&b.id)));
+
+    let mut seen = HashSet::new();
+    all.retain(|r| seen.insert(r.id.clone()));
+    all.truncate(top_k);
+    all
+}
+
+// ============================================================================
+// Shard Health & Rebalancing State
+// ============================================================================
+
+/// Per-shard health used to skip shards that recently failed or timed out
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ShardHealth {
+    pub consecutive_failures: u32,
+    pub last_failure_at: u64,
+    pub last_latency_ms: u64,
+}
+
+impl ShardHealth {
+    /// Back off exponentially (capped) after consecutive failures
+    pub fn cooling_down(&self, now: u64, base_cooldown_ns: u64) -> bool {
+        if self.consecutive_failures == 0 {
+            return false;
+        }
+        let factor = 1u64 << (self.consecutive_failures - 1).min(5);
+        now < self.last_failure_at.saturating_add(base_cooldown_ns.saturating_mul(factor))
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RebalanceState {
+    pub active: bool,
+    pub shard_cursor: Option<u32>, // Next shard to scan for misplaced vectors
+    pub namespace_cursor: Option<String>, // Next namespace of that shard, in name order
+    pub id_cursor: Option<String>, // Last vector key listed in that namespace
+    pub moved: u64,
+    pub started_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RebalanceStatus {
+    pub active: bool,
+    pub moved_this_step: u32,
+    pub moved_total: u64,
+    pub current_shard: Option<u32>,
+    pub removed_shards: Vec<u32>,
+    pub errors: Vec<String>,
+}
+
+impl Storable for RebalanceState {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+// Namespace access and limits, mirroring vector_db's types
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NamespaceQuota {
+    pub max_vectors: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NamespaceAcl {
+    pub writers: Vec<Principal>,
+    pub readers: Vec<Principal>,
+    pub public_read: bool,
+    pub public_write: bool,
+}
+
+/// Ids stored on `shard_id` that the ring assigns elsewhere, as (id, new owner)
+pub fn misplaced(ring: &HashRing, shard_id: u32, ids: &[String]) -> Vec<(String, u32)> {
+    ids.iter()
+        .filter_map(|id| match ring.shard_for(id) {
+            Some(owner) if owner != shard_id => Some((id.clone(), owner)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("memory-{}", i)).collect()
+    }
+
+    #[test]
+    fn test_ring_spreads_keys_and_is_stable() {
+        let ring = HashRing::new(&[0, 1, 2, 3]);
+        let mut counts = [0usize; 4];
+        for id in ids(4000) {
+            counts[ring.shard_for(&id).unwrap() as usize] += 1;
+        }
+        for c in counts {
+            assert!(c > 600 && c < 1400, "unbalanced shard counts {:?}", counts);
+        }
+        assert_eq!(ring.shard_for("memory-7"), HashRing::new(&[3, 2, 1, 0]).shard_for("memory-7"));
+        assert_eq!(HashRing::new(&[]).shard_for("memory-7"), None);
+    }
+
+    #[test]
+    fn test_adding_shard_moves_only_its_share() {
+        let keys = ids(4000);
+        let before = HashRing::new(&[0, 1, 2]);
+        let after = HashRing::new(&[0, 1, 2, 3]);
+
+        let mut moved = 0;
+        for id in &keys {
+            let (old, new) = (before.shard_for(id).unwrap(), after.shard_for(id).unwrap());
+            if old != new {
+                // Keys only ever move to the new shard
+                assert_eq!(new, 3);
+                moved += 1;
+            }
+        }
+        assert!(moved > 600 && moved < 1400, "moved {}", moved);
+
+        // Removing it again sends every key back where it was
+        for (id, owner) in misplaced(&before, 3, &keys) {
+            assert_eq!(Some(owner), before.shard_for(&id));
+        }
+    }
+
+    #[test]
+    fn test_merge_global_top_k() {
+        let r = |id: &str, s: f32| MemoryResult { id: id.to_string(), vector: vec![], similarity: s, metadata: vec![] };
+        let merged = merge_top_k(
+            vec![
+                vec![r("a", 0.9), r("b", 0.5)],
+                vec![r("c", 0.8), r("a", 0.7)],
+                vec![],
+                vec![r("d", 0.95)],
+            ],
+            3,
+        );
+        let got: Vec<(&str, f32)> = merged.iter().map(|m| (m.id.as_str(), m.similarity)).collect();
+        assert_eq!(got, vec![("d", 0.95), ("a", 0.9), ("c", 0.8)]);
+    }
+
+    #[test]
+    fn test_failed_shard_backs_off() {
+        let sec = 1_000_000_000u64;
+        let mut health = ShardHealth::default();
+        assert!(!health.cooling_down(0, 30 * sec));
+
+        health.consecutive_failures = 3;
+        health.last_failure_at = 100 * sec;
+        assert!(health.cooling_down(200 * sec, 30 * sec));
+        assert!(!health.cooling_down(221 * sec, 30 * sec));
+    }
+}
@@ -0,0 +1,396 @@
+//! Multi-Response Synthesis
+//!
+//! Merges the answers collected from on-chain and HTTP models:
+//! 1. Each response is split into sentence-level claims and embedded
+//! 2. Claims from different models that say the same thing are grouped (agreements)
+//! 3. Groups on the same topic with opposite polarity or different numbers are
+//!    flagged as contradictions; the better-supported side wins
+//! 4. The merged answer is extracted from the best-supported claims, in the order
+//!    the strongest model stated them (a chairman model may rewrite it afterwards)
+//!
+//! Per-model contribution is the share of merged claims each model supports.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::embedding;
+
+/// Claims at least this similar are the same claim
+const AGREEMENT_THRESHOLD: f32 = 0.6;
+/// Claims at least this similar are about the same topic (candidates for contradiction)
+const TOPIC_THRESHOLD: f32 = 0.45;
+/// Sentences in the merged answer
+const MAX_MERGED_CLAIMS: usize = 8;
+/// Confidence given to a lone response that reported none
+const DEFAULT_CONFIDENCE: f32 = 0.5;
+
+const NEGATIONS: &[&str] = &[
+    "not", "no", "never", "none", "cannot", "can't", "don't", "doesn't", "isn't", "aren't",
+    "won't", "shouldn't", "wasn't", "weren't", "without",
+];
+
+#[derive(Clone, Debug)]
+pub struct ModelOutput {
+    pub model: String,
+    pub response: String,
+    pub confidence: Option<f32>, // Self-reported, if the model provides one
+    pub tokens_used: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClaimGroup {
+    pub text: String,        // Representative sentence
+    pub models: Vec<String>, // Models stating it
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Contradiction {
+    pub kept: ClaimGroup,
+    pub rejected: ClaimGroup,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModelContribution {
+    pub model: String,
+    pub claims: u32,          // Claims extracted from its response
+    pub claims_used: u32,     // Of those, claims that made it into the merged answer
+    pub agreement: f32,       // Share of its claims another model also made
+    pub confidence: f32,      // Self-reported, else its agreement (single response: neutral)
+    pub contribution: f32,    // Share of merged claims it supports
+    pub tokens_used: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SynthesisReport {
+    pub merged_response: String,
+    pub method: String, // "none", "single", "extractive" or "chairman"
+    pub confidence: f32,
+    pub agreements: Vec<ClaimGroup>,
+    pub contradictions: Vec<Contradiction>,
+    pub contributions: Vec<ModelContribution>,
+    pub tokens_used: u32,
+}
+
+struct Claim {
+    model: usize,
+    position: usize,
+    text: String,
+    vector: Vec<f32>,
+    negated: bool,
+    numbers: Vec<String>,
+}
+
+struct Group {
+    claims: Vec<usize>,
+    models: BTreeSet<usize>,
+    rejected: bool,
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+/// Split a response into sentence claims (markdown bullets and numbering stripped)
+pub fn extract_claims(response: &str) -> Vec<String> {
+    let mut claims = Vec::new();
+    for line in response.lines() {
+        let line = line.trim().trim_start_matches(['-', '*', '#', '>', ' ']);
+        let line = line.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ')').trim();
+        let mut current = String::new();
+        let chars: Vec<char> = line.chars().collect();
+        for (i, c) in chars.iter().enumerate() {
+            current.push(*c);
+            let boundary = matches!(c, '.' | '!' | '?')
+                && chars.get(i + 1).map(|n| n.is_whitespace()).unwrap_or(true)
+                // Keep decimals like "3.5" together
+                && !(i > 0 && chars[i - 1].is_ascii_digit() && chars.get(i + 1).map(|n| n.is_ascii_digit()).unwrap_or(false));
+            if boundary {
+                push_claim(&mut claims, &current);
+                current.clear();
+            }
+        }
+        push_claim(&mut claims, &current);
+    }
+    claims
+}
+
+fn push_claim(claims: &mut Vec<String>, text: &str) {
+    let text = text.trim();
+    if text.split_whitespace().count() >= 3 {
+        claims.push(text.to_string());
+    }
+}
+
+fn analyze(text: &str) -> (bool, Vec<String>) {
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '.'))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let negated = words.iter().filter(|w| NEGATIONS.contains(&w.trim_end_matches('.'))).count() % 2 == 1;
+    let numbers = words
+        .iter()
+        .map(|w| w.trim_end_matches('.'))
+        .filter(|w| w.chars().next().map(|c| c.is_ascii_digit()).unwrap_or(false))
+        .map(String::from)
+        .collect();
+    (negated, numbers)
+}
+
+fn effective_confidence(output: &ModelOutput, agreement: f32, models: usize) -> f32 {
+    match output.confidence {
+        Some(c) => c.clamp(0.0, 1.0),
+        None if models > 1 => agreement,
+        None => DEFAULT_CONFIDENCE,
+    }
+}
+
+/// Deterministic extractive synthesis of several model responses
+pub fn synthesize(outputs: &[ModelOutput]) -> SynthesisReport {
+    let tokens_used = outputs.iter().map(|o| o.tokens_used).sum();
+    let responding = outputs.iter().filter(|o| !o.response.trim().is_empty()).count();
+
+    if responding == 0 {
+        return SynthesisReport {
+            merged_response: String::new(),
+            method: "none".to_string(),
+            confidence: 0.0,
+            agreements: Vec::new(),
+            contradictions: Vec::new(),
+            contributions: outputs.iter().map(|o| ModelContribution {
+                model: o.model.clone(),
+                claims: 0,
+                claims_used: 0,
+                agreement: 0.0,
+                confidence: 0.0,
+                contribution: 0.0,
+                tokens_used: o.tokens_used,
+            }).collect(),
+            tokens_used,
+        };
+    }
+
+    // 1. Claims
+    let mut claims: Vec<Claim> = Vec::new();
+    for (model, output) in outputs.iter().enumerate() {
+        for (position, text) in extract_claims(&output.response).into_iter().enumerate() {
+            let (negated, numbers) = analyze(&text);
+            claims.push(Claim { model, position, vector: embedding::embed(&text), text, negated, numbers });
+        }
+    }
+
+    // 2. Group equivalent claims (same polarity and numbers, similar wording)
+    let mut groups: Vec<Group> = Vec::new();
+    for (i, claim) in claims.iter().enumerate() {
+        let home = groups.iter_mut().find(|g| {
+            let rep = &claims[g.claims[0]];
+            rep.negated == claim.negated
+                && rep.numbers == claim.numbers
+                && cosine(&rep.vector, &claim.vector) >= AGREEMENT_THRESHOLD
+        });
+        match home {
+            Some(g) => {
+                g.claims.push(i);
+                g.models.insert(claim.model);
+            }
+            None => groups.push(Group { claims: vec![i], models: [claim.model].into_iter().collect(), rejected: false }),
+        }
+    }
+
+    // Per-model agreement: share of its claims that another model also made
+    let agreement: Vec<f32> = (0..outputs.len())
+        .map(|m| {
+            let own: Vec<&Group> = groups.iter().filter(|g| g.models.contains(&m)).collect();
+            if own.is_empty() {
+                return 0.0;
+            }
+            own.iter().filter(|g| g.models.len() > 1).count() as f32 / own.len() as f32
+        })
+        .collect();
+    let confidence: Vec<f32> = outputs
+        .iter()
+        .enumerate()
+        .map(|(m, o)| effective_confidence(o, agreement[m], responding))
+        .collect();
+    let group_weight = |g: &Group| g.models.iter().map(|m| confidence[*m]).sum::<f32>();
+    let to_claim_group = |g: &Group| ClaimGroup {
+        text: claims[g.claims[0]].text.clone(),
+        models: g.models.iter().map(|m| outputs[*m].model.clone()).collect(),
+    };
+
+    // 3. Contradictions: same topic, different polarity or numbers
+    let mut contradictions = Vec::new();
+    for a in 0..groups.len() {
+        for b in (a + 1)..groups.len() {
+            if groups[a].rejected || groups[b].rejected {
+                continue;
+            }
+            let (ra, rb) = (&claims[groups[a].claims[0]], &claims[groups[b].claims[0]]);
+            let conflicting = ra.negated != rb.negated
+                || (!ra.numbers.is_empty() && !rb.numbers.is_empty() && ra.numbers != rb.numbers);
+            if conflicting && cosine(&ra.vector, &rb.vector) >= TOPIC_THRESHOLD {
+                let (kept, rejected) = if group_weight(&groups[b]) > group_weight(&groups[a]) { (b, a) } else { (a, b) };
+                groups[rejected].rejected = true;
+                contradictions.push(Contradiction {
+                    kept: to_claim_group(&groups[kept]),
+                    rejected: to_claim_group(&groups[rejected]),
+                });
+            }
+        }
+    }
+
+    // 4. Merge: best-supported claims first, then ordered as the strongest model stated them
+    let lead = (0..outputs.len())
+        .filter(|m| !outputs[*m].response.trim().is_empty())
+        .max_by(|a, b| confidence[*a].total_cmp(&confidence[*b]).then_with(|| b.cmp(a)))
+        .unwrap_or(0);
+    let mut selected: Vec<usize> = (0..groups.len()).filter(|g| !groups[*g].rejected).collect();
+    selected.sort_by(|a, b| {
+        groups[*b].models.len().cmp(&groups[*a].models.len())
+            .then_with(|| group_weight(&groups[*b]).total_cmp(&group_weight(&groups[*a])))
+            .then_with(|| a.cmp(b))
+    });
+    selected.truncate(MAX_MERGED_CLAIMS);
+
+    // Representative: the lead model's wording when it made the claim
+    let representative = |g: &Group| -> usize {
+        g.claims.iter().copied().find(|c| claims[*c].model == lead).unwrap_or(g.claims[0])
+    };
+    let order_key = |g: &Group| -> (usize, usize, usize) {
+        let c = &claims[representative(g)];
+        (if c.model == lead { 0 } else { 1 }, c.model, c.position)
+    };
+    selected.sort_by_key(|g| order_key(&groups[*g]));
+
+    let merged_response = selected
+        .iter()
+        .map(|g| claims[representative(&groups[*g])].text.clone())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let contributions = outputs
+        .iter()
+        .enumerate()
+        .map(|(m, o)| {
+            let claims_of_model = claims.iter().filter(|c| c.model == m).count() as u32;
+            let used = selected.iter().filter(|g| groups[**g].models.contains(&m)).count() as u32;
+            ModelContribution {
+                model: o.model.clone(),
+                claims: claims_of_model,
+                claims_used: used,
+                agreement: agreement[m],
+                confidence: confidence[m],
+                contribution: if selected.is_empty() { 0.0 } else { used as f32 / selected.len() as f32 },
+                tokens_used: o.tokens_used,
+            }
+        })
+        .collect();
+
+    // Confidence: support-weighted share of the merged claims
+    let overall = if responding == 1 {
+        confidence[lead]
+    } else if selected.is_empty() {
+        0.0
+    } else {
+        selected.iter().map(|g| groups[*g].models.len() as f32 / responding as f32).sum::<f32>()
+            / selected.len() as f32
+    };
+
+    SynthesisReport {
+        merged_response: if merged_response.is_empty() { outputs[lead].response.clone() } else { merged_response },
+        method: if responding == 1 { "single" } else { "extractive" }.to_string(),
+        confidence: overall,
+        agreements: groups.iter().filter(|g| g.models.len() > 1 && !g.rejected).map(to_claim_group).collect(),
+        contradictions,
+        contributions,
+        tokens_used,
+    }
+}
+
+/// Prompt asking a chairman model to rewrite the extractive merge
+pub fn chairman_prompt(query: &str, outputs: &[ModelOutput], report: &SynthesisReport) -> String {
+    let mut prompt = format!("Question: {}\n\nAnswers from the council:\n", query);
+    for o in outputs {
+        prompt.push_str(&format!("- {}: {}\n", o.model, o.response));
+    }
+    if !report.agreements.is_empty() {
+        prompt.push_str("\nPoints the models agree on:\n");
+        for a in &report.agreements {
+            prompt.push_str(&format!("- {}\n", a.text));
+        }
+    }
+    if !report.contradictions.is_empty() {
+        prompt.push_str("\nContradictions (first is better supported):\n");
+        for c in &report.contradictions {
+            prompt.push_str(&format!("- \"{}\" vs \"{}\"\n", c.kept.text, c.rejected.text));
+        }
+    }
+    prompt.push_str(&format!(
+        "\nDraft synthesis: {}\n\nWrite one clear, accurate answer that keeps the agreed points and resolves the contradictions.",
+        report.merged_response
+    ));
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(model: &str, response: &str, confidence: Option<f32>, tokens: u32) -> ModelOutput {
+        ModelOutput { model: model.to_string(), response: response.to_string(), confidence, tokens_used: tokens }
+    }
+
+    #[test]
+    fn test_extract_claims() {
+        let claims = extract_claims("1. Ghost peppers are very hot.\n- They rate about 1.0 million Scoville units! Ok.");
+        assert_eq!(claims, vec![
+            "Ghost peppers are very hot.".to_string(),
+            "They rate about 1.0 million Scoville units!".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_agreement_and_contradiction() {
+        let outputs = vec![
+            output("onchain", "Ghost peppers are extremely hot chilies. The ghost pepper is native to India. Ghost peppers can be grown indoors.", None, 40),
+            output("gpt", "Ghost peppers are extremely hot chili peppers. The ghost pepper is native to northeast India. Ghost peppers cannot be grown indoors.", Some(0.7), 55),
+            output("claude", "The ghost pepper is native to India. Ghost peppers can be grown indoors with strong light.", Some(0.9), 30),
+        ];
+        let report = synthesize(&outputs);
+
+        assert_eq!(report.method, "extractive");
+        assert_eq!(report.tokens_used, 125);
+        assert!(report.agreements.iter().any(|a| a.text.contains("native to") && a.models.len() >= 2), "{:?}", report.agreements);
+        assert_eq!(report.contradictions.len(), 1, "{:?}", report.contradictions);
+        assert!(report.contradictions[0].rejected.text.contains("cannot"));
+        assert!(!report.merged_response.contains("cannot"));
+        assert!(report.merged_response.contains("native to"));
+
+        let gpt = report.contributions.iter().find(|c| c.model == "gpt").unwrap();
+        assert!(gpt.claims_used < gpt.claims);
+        assert!(report.confidence > 0.0 && report.confidence <= 1.0);
+    }
+
+    #[test]
+    fn test_numeric_disagreement_and_single_response() {
+        let outputs = vec![
+            output("a", "The staking lockup period is 30 days for new neurons.", Some(0.6), 10),
+            output("b", "The staking lockup period is 90 days for new neurons.", Some(0.8), 10),
+        ];
+        let report = synthesize(&outputs);
+        assert_eq!(report.contradictions.len(), 1);
+        assert!(report.merged_response.contains("90 days"));
+
+        let single = synthesize(&outputs[..1]);
+        assert_eq!(single.method, "single");
+        assert_eq!(single.confidence, 0.6);
+        assert!(synthesize(&[]).merged_response.is_empty());
+
+        // Failed/empty answers still count their tokens and keep their contribution slot
+        let with_empty = synthesize(&[output("x", " ", None, 7), outputs[1].clone()]);
+        assert_eq!(with_empty.contributions.len(), 2);
+        assert_eq!(with_empty.tokens_used, 17);
+        assert!(with_empty.merged_response.contains("90 days"));
+    }
+}
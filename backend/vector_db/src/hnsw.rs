@@ -0,0 +1,753 @@
+//! HNSW Approximate Nearest-Neighbour Index
+//!
+//! Hierarchical navigable small-world graph kept in stable memory next to the
+//! vectors, so `query_vectors` visits a few hundred nodes instead of the whole shard.
+//!
+//! - One graph per namespace, so a query only walks vectors of its namespace and
+//!   every graph has a single dimension; a namespace's entry point is its highest
+//!   node in the level index
+//! - One `HnswNode` per vector: normalized vector + neighbour lists per layer;
+//!   vectors of quantized namespaces are kept as int8 in their nodes (`compact`)
+//! - Node levels are derived from a hash of the vector id (deterministic, no randomness needed)
+//! - Updated incrementally on store/delete; deleted nodes' neighbours are re-linked
+//! - Vectors stored before the index existed are added in batches via `build_step`
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::{namespace, StorableString, FULL_VECTORS, HNSW_LEVELS, HNSW_NODES, HNSW_STATE, VECTORS};
+
+const MAX_LEVEL: u8 = 16;
+// Set in the level byte of a stored node whose vector is int8
+const COMPACT_FLAG: u8 = 0x80;
+// Unit vector components are stored as round(x * 127) in compact nodes
+const COMPACT_SCALE: f32 = 127.0;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Tunable index parameters. `m` and `ef_construction` apply to vectors inserted
+/// after the change; `ef_search` applies immediately.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HnswParams {
+    pub m: u32,               // Max neighbours per node on upper layers (2*m on layer 0)
+    pub ef_construction: u32, // Candidate list size while inserting
+    pub ef_search: u32,       // Candidate list size while querying (raised to top_k if smaller)
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 100,
+            ef_search: 64,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct IndexState {
+    pub params: HnswParams,
+    pub node_count: u64,
+    pub initialized: bool,
+    pub building: bool,               // Backfill of pre-existing vectors in progress
+    pub build_cursor: Option<String>, // Last vector id visited by the backfill
+    pub per_namespace: Option<bool>,  // None: one graph over all namespaces, rebuilt on upgrade
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct HnswNode {
+    pub vector: Vec<f32>, // Unit-normalized copy, so similarity is a dot product
+    pub level: u8,
+    pub neighbors: Vec<Vec<String>>, // neighbors[layer]
+    pub compact: bool,               // Vector stored as int8 (quantized namespaces)
+}
+
+/// Level index entry; the last key of a namespace is its highest node, the graph's entry point
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LevelKey {
+    pub namespace: String,
+    pub level: u8,
+    pub id: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct IndexStatus {
+    pub params: HnswParams,
+    pub node_count: u64,
+    pub max_level: u8,
+    pub ready: bool,
+}
+
+impl Storable for IndexState {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// Nodes are read on every hop of a search, so they use a compact fixed layout
+/// instead of Candid: level (with `COMPACT_FLAG`), dim, f32 LE values (or one i8
+/// per dimension), then per layer a count and length-prefixed ids.
+impl Storable for HnswNode {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut bytes = Vec::with_capacity(5 + self.vector.len() * 4);
+        bytes.push(if self.compact { self.level | COMPACT_FLAG } else { self.level });
+        bytes.extend_from_slice(&(self.vector.len() as u32).to_le_bytes());
+        for x in &self.vector {
+            if self.compact {
+                bytes.push((x * COMPACT_SCALE).round().clamp(-127.0, 127.0) as i8 as u8);
+            } else {
+                bytes.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        for links in &self.neighbors {
+            bytes.extend_from_slice(&(links.len() as u16).to_le_bytes());
+            for id in links {
+                bytes.extend_from_slice(&(id.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(id.as_bytes());
+            }
+        }
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let b = bytes.as_ref();
+        let compact = b[0] & COMPACT_FLAG != 0;
+        let level = b[0] & !COMPACT_FLAG;
+        let dim = u32::from_le_bytes([b[1], b[2], b[3], b[4]]) as usize;
+        let mut pos = 5;
+        let vector = if compact {
+            b[pos..pos + dim].iter().map(|x| *x as i8 as f32 / COMPACT_SCALE).collect()
+        } else {
+            (0..dim)
+                .map(|i| {
+                    let at = pos + i * 4;
+                    f32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+                })
+                .collect()
+        };
+        pos += if compact { dim } else { dim * 4 };
+
+        let mut neighbors = Vec::with_capacity(level as usize + 1);
+        for _ in 0..=level {
+            let count = u16::from_le_bytes([b[pos], b[pos + 1]]) as usize;
+            pos += 2;
+            let mut links = Vec::with_capacity(count);
+            for _ in 0..count {
+                let len = u16::from_le_bytes([b[pos], b[pos + 1]]) as usize;
+                pos += 2;
+                links.push(String::from_utf8_lossy(&b[pos..pos + len]).into_owned());
+                pos += len;
+            }
+            neighbors.push(links);
+        }
+
+        Self { vector, level, neighbors, compact }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for LevelKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// (distance, id) ordered by distance, ties broken by id
+#[derive(Clone, Debug)]
+struct Scored {
+    dist: f32,
+    id: String,
+}
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist.total_cmp(&other.dist).then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn state() -> IndexState {
+    HNSW_STATE.with(|s| s.borrow().get().clone())
+}
+
+fn set_state(state: IndexState) {
+    HNSW_STATE.with(|s| s.borrow_mut().set(state).expect("Failed to save index state"));
+}
+
+/// Entry point of a namespace's graph: its highest node
+fn entry_point(namespace: &str) -> Option<LevelKey> {
+    let first = LevelKey { namespace: namespace.to_string(), level: 0, id: String::new() };
+    let end = LevelKey { namespace: namespace.to_string(), level: u8::MAX, id: String::new() };
+    HNSW_LEVELS.with(|l| l.borrow().range(first..end).next_back().map(|(key, _)| key))
+}
+
+/// Entry points of every namespace's graph
+fn entry_points() -> Vec<LevelKey> {
+    let mut tops = Vec::new();
+    let mut next = HNSW_LEVELS.with(|l| l.borrow().first_key_value().map(|(key, _)| key.namespace));
+    while let Some(namespace) = next {
+        tops.extend(entry_point(&namespace));
+        let end = LevelKey { namespace, level: u8::MAX, id: String::new() };
+        next = HNSW_LEVELS.with(|l| l.borrow().range(end..).next().map(|(key, _)| key.namespace));
+    }
+    tops
+}
+
+pub fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|x| x / norm).collect()
+}
+
+/// Cosine distance between unit vectors (dimension mismatch counts as orthogonal)
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 1.0;
+    }
+    1.0 - a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>()
+}
+
+/// Level from a 64-bit FNV-1a hash of the id: floor(-ln(u) / ln(m))
+fn level_for(id: &str, m: u32) -> u8 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in id.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    // Finalize (splitmix64) so similar ids do not get correlated levels
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^= hash >> 31;
+
+    let u = ((hash >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+    let ml = 1.0 / (m.max(2) as f64).ln();
+    ((-u.ln() * ml).floor() as u8).min(MAX_LEVEL)
+}
+
+fn max_neighbors(params: &HnswParams, layer: usize) -> usize {
+    let m = params.m.max(2) as usize;
+    if layer == 0 {
+        m * 2
+    } else {
+        m
+    }
+}
+
+/// Per-operation node cache so a node is decoded from stable memory at most once
+struct Graph {
+    cache: HashMap<String, Option<HnswNode>>,
+    visited: usize,
+}
+
+impl Graph {
+    fn new() -> Self {
+        Self {
+            cache: HashMap::new(),
+            visited: 0,
+        }
+    }
+
+    fn node(&mut self, id: &str) -> Option<&HnswNode> {
+        if !self.cache.contains_key(id) {
+            let node = HNSW_NODES.with(|n| n.borrow().get(&StorableString(id.to_string())));
+            self.cache.insert(id.to_string(), node);
+        }
+        self.cache.get(id).and_then(|n| n.as_ref())
+    }
+
+    fn dist(&mut self, query: &[f32], id: &str) -> Option<f32> {
+        self.visited += 1;
+        self.node(id).map(|n| distance(query, &n.vector))
+    }
+
+    fn put(&mut self, id: &str, node: HnswNode) {
+        HNSW_NODES.with(|n| n.borrow_mut().insert(StorableString(id.to_string()), node.clone()));
+        self.cache.insert(id.to_string(), Some(node));
+    }
+
+    /// Greedy beam search on one layer; returns up to `ef` closest, nearest first
+    fn search_layer(&mut self, query: &[f32], entry: Vec<Scored>, ef: usize, layer: usize) -> Vec<Scored> {
+        let mut visited: HashSet<String> = entry.iter().map(|s| s.id.clone()).collect();
+        let mut candidates: BinaryHeap<Reverse<Scored>> = entry.iter().cloned().map(Reverse).collect();
+        let mut results: BinaryHeap<Scored> = entry.into_iter().collect();
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            if let Some(furthest) = results.peek() {
+                if current.dist > furthest.dist && results.len() >= ef {
+                    break;
+                }
+            }
+
+            let neighbors = match self.node(&current.id) {
+                Some(node) => node.neighbors.get(layer).cloned().unwrap_or_default(),
+                None => continue,
+            };
+
+            for neighbor in neighbors {
+                if !visited.insert(neighbor.clone()) {
+                    continue;
+                }
+                let dist = match self.dist(query, &neighbor) {
+                    Some(d) => d,
+                    None => continue, // Dangling link to a deleted node
+                };
+                let worse_than_all = results.len() >= ef
+                    && results.peek().map(|f| dist > f.dist).unwrap_or(false);
+                if !worse_than_all {
+                    let scored = Scored { dist, id: neighbor };
+                    candidates.push(Reverse(scored.clone()));
+                    results.push(scored);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Descend from the entry point to `layer`, then beam search there
+    fn descend(&mut self, query: &[f32], entry_point: Option<LevelKey>, to_layer: usize) -> Option<Vec<Scored>> {
+        let top = entry_point?;
+        let dist = self.dist(query, &top.id)?;
+        let mut entry = vec![Scored { dist, id: top.id }];
+        for layer in (to_layer + 1..=top.level as usize).rev() {
+            entry = self.search_layer(query, entry, 1, layer);
+        }
+        Some(entry)
+    }
+
+    /// Keep the `max` closest of `candidates` to `vector`
+    fn select(&mut self, vector: &[f32], candidates: impl IntoIterator<Item = String>, exclude: &str, max: usize) -> Vec<String> {
+        let mut scored: Vec<Scored> = Vec::new();
+        let mut seen = HashSet::new();
+        for id in candidates {
+            if id == exclude || !seen.insert(id.clone()) {
+                continue;
+            }
+            if let Some(dist) = self.node(&id).map(|n| distance(vector, &n.vector)) {
+                scored.push(Scored { dist, id });
+            }
+        }
+        scored.sort();
+        scored.truncate(max);
+        scored.into_iter().map(|s| s.id).collect()
+    }
+}
+
+// ============================================================================
+// Index Operations
+// ============================================================================
+
+/// Mark the index initialized; vectors stored before it existed are backfilled by `build_step`.
+/// A single graph over all namespaces, from before graphs were per namespace, is
+/// dropped and rebuilt the same way.
+pub fn ensure_initialized(existing_vectors: u64) {
+    let mut s = state();
+    if s.initialized && s.per_namespace == Some(true) {
+        return;
+    }
+    if s.initialized {
+        HNSW_NODES.with(|n| n.borrow_mut().clear_new());
+        HNSW_LEVELS.with(|l| l.borrow_mut().clear_new());
+        s.node_count = 0;
+    }
+    s.initialized = true;
+    s.per_namespace = Some(true);
+    s.building = existing_vectors > 0;
+    s.build_cursor = None;
+    set_state(s);
+}
+
+pub fn is_ready() -> bool {
+    let s = state();
+    s.initialized && !s.building
+}
+
+pub fn contains(id: &str) -> bool {
+    HNSW_NODES.with(|n| n.borrow().contains_key(&StorableString(id.to_string())))
+}
+
+/// The vector is in the graph with an int8 node vector
+pub fn is_compact(id: &str) -> bool {
+    HNSW_NODES.with(|n| n.borrow().get(&StorableString(id.to_string()))).is_some_and(|node| node.compact)
+}
+
+/// Insert (or replace) a vector in its namespace's graph; `compact` keeps the node's
+/// copy as int8, for namespaces whose full-precision vectors are stored elsewhere
+pub fn insert(id: &str, vector: &[f32], compact: bool) {
+    if contains(id) {
+        remove(id);
+    }
+
+    let mut s = state();
+    let namespace = namespace::of_key(id);
+    let vector = normalize(vector);
+    let level = level_for(id, s.params.m);
+    let top = entry_point(namespace);
+    let top_level = top.as_ref().map(|t| t.level).unwrap_or(0);
+    let mut graph = Graph::new();
+    let mut node = HnswNode {
+        vector: vector.clone(),
+        level,
+        neighbors: vec![Vec::new(); level as usize + 1],
+        compact,
+    };
+
+    if let Some(mut entry) = graph.descend(&vector, top, level as usize) {
+        let ef = s.params.ef_construction.max(1) as usize;
+        for layer in (0..=(level.min(top_level)) as usize).rev() {
+            let found = graph.search_layer(&vector, entry.clone(), ef, layer);
+            let max = max_neighbors(&s.params, layer);
+            node.neighbors[layer] = found.iter().take(max).map(|c| c.id.clone()).collect();
+
+            // Link back, pruning neighbours that overflow
+            for neighbor_id in node.neighbors[layer].clone() {
+                let mut neighbor = match graph.node(&neighbor_id) {
+                    Some(n) => n.clone(),
+                    None => continue,
+                };
+                if neighbor.neighbors.len() <= layer {
+                    continue;
+                }
+                neighbor.neighbors[layer].push(id.to_string());
+                if neighbor.neighbors[layer].len() > max {
+                    let links = std::mem::take(&mut neighbor.neighbors[layer]);
+                    // The new node is not in the store yet, so score it directly
+                    let mut kept = graph.select(&neighbor.vector, links.into_iter().filter(|l| l != id), &neighbor_id, max);
+                    let new_dist = distance(&neighbor.vector, &vector);
+                    let worst = kept.last().and_then(|w| graph.node(w)).map(|w| distance(&neighbor.vector, &w.vector));
+                    if kept.len() < max {
+                        kept.push(id.to_string());
+                    } else if worst.map(|w| new_dist < w).unwrap_or(true) {
+                        kept.pop();
+                        kept.push(id.to_string());
+                    }
+                    neighbor.neighbors[layer] = kept;
+                }
+                graph.put(&neighbor_id, neighbor);
+            }
+            entry = found;
+        }
+    }
+
+    graph.put(id, node);
+    HNSW_LEVELS.with(|l| l.borrow_mut().insert(LevelKey { namespace: namespace.to_string(), level, id: id.to_string() }, ()));
+    s.node_count += 1;
+    set_state(s);
+}
+
+/// Remove a vector from the graph and re-link its neighbours
+pub fn remove(id: &str) -> bool {
+    let node = match HNSW_NODES.with(|n| n.borrow_mut().remove(&StorableString(id.to_string()))) {
+        Some(node) => node,
+        None => return false,
+    };
+    let namespace = namespace::of_key(id).to_string();
+    HNSW_LEVELS.with(|l| l.borrow_mut().remove(&LevelKey { namespace, level: node.level, id: id.to_string() }));
+
+    let mut s = state();
+    let mut graph = Graph::new();
+    graph.cache.insert(id.to_string(), None);
+
+    for (layer, links) in node.neighbors.iter().enumerate() {
+        let max = max_neighbors(&s.params, layer);
+        for neighbor_id in links {
+            let mut neighbor = match graph.node(neighbor_id) {
+                Some(n) => n.clone(),
+                None => continue,
+            };
+            if neighbor.neighbors.len() <= layer {
+                continue;
+            }
+            // Candidates: its own links plus the deleted node's links on this layer
+            let candidates: Vec<String> = neighbor.neighbors[layer].iter()
+                .chain(links.iter())
+                .filter(|l| l.as_str() != id)
+                .cloned()
+                .collect();
+            neighbor.neighbors[layer] = graph.select(&neighbor.vector, candidates, neighbor_id, max);
+            graph.put(neighbor_id, neighbor);
+        }
+    }
+
+    s.node_count = s.node_count.saturating_sub(1);
+    set_state(s);
+    true
+}
+
+/// Top-k approximate neighbours in a namespace as (storage key, cosine similarity), best first
+pub fn search(namespace: &str, query: &[f32], top_k: usize, ef: Option<usize>) -> Vec<(String, f32)> {
+    search_counted(namespace, query, top_k, ef).0
+}
+
+/// Search and also report how many nodes had their distance computed
+pub fn search_counted(namespace: &str, query: &[f32], top_k: usize, ef: Option<usize>) -> (Vec<(String, f32)>, usize) {
+    let s = state();
+    let query = normalize(query);
+    let ef = ef.unwrap_or(s.params.ef_search as usize).max(top_k).max(1);
+
+    let mut graph = Graph::new();
+    let results = match graph.descend(&query, entry_point(namespace), 0) {
+        Some(entry) => graph.search_layer(&query, entry, ef, 0),
+        None => Vec::new(),
+    };
+
+    let top = results.into_iter()
+        .take(top_k)
+        .map(|c| (c.id, 1.0 - c.dist))
+        .collect();
+    (top, graph.visited)
+}
+
+/// Index up to `batch` vectors that were stored before the index existed
+pub fn build_step(batch: usize) -> IndexStatus {
+    let s = state();
+    if s.building {
+        let start = s.build_cursor.clone().unwrap_or_default();
+        let pending: Vec<(String, Vec<f32>)> = VECTORS.with(|v| {
+            v.borrow()
+                .range(StorableString(start.clone())..)
+                .filter(|(k, _)| s.build_cursor.is_none() || k.0 != start)
+                .take(batch)
+                .map(|(k, e)| (k.0, e.vector))
+                .collect()
+        });
+
+        for (id, vector) in &pending {
+            if contains(id) {
+                continue;
+            }
+            // Quantized entries keep their vector in the full-precision store
+            if vector.is_empty() {
+                if let Some(full) = FULL_VECTORS.with(|f| f.borrow().get(&StorableString(id.clone()))) {
+                    insert(id, &full.0, true);
+                }
+            } else {
+                insert(id, vector, false);
+            }
+        }
+
+        let mut s = state();
+        if pending.len() < batch {
+            s.building = false;
+            s.build_cursor = None;
+        } else {
+            s.build_cursor = pending.last().map(|(id, _)| id.clone());
+        }
+        set_state(s);
+    }
+    status()
+}
+
+pub fn set_params(params: HnswParams) -> Result<(), String> {
+    if params.m < 2 || params.m > 128 {
+        return Err("m must be between 2 and 128".to_string());
+    }
+    if params.ef_construction == 0 || params.ef_search == 0 {
+        return Err("ef values must be greater than zero".to_string());
+    }
+    let mut s = state();
+    s.params = params;
+    set_state(s);
+    Ok(())
+}
+
+pub fn status() -> IndexStatus {
+    let s = state();
+    IndexStatus {
+        ready: s.initialized && !s.building,
+        params: s.params,
+        node_count: s.node_count,
+        max_level: entry_points().iter().map(|top| top.level).max().unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random vectors (xorshift)
+    fn vectors(count: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut x = seed;
+        (0..count)
+            .map(|_| {
+                (0..dim)
+                    .map(|_| {
+                        x ^= x << 13;
+                        x ^= x >> 7;
+                        x ^= x << 17;
+                        (x % 2000) as f32 / 1000.0 - 1.0
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn brute_force(data: &[(String, Vec<f32>)], query: &[f32], k: usize) -> Vec<String> {
+        let q = normalize(query);
+        let mut scored: Vec<Scored> = data.iter()
+            .map(|(id, v)| Scored { dist: distance(&q, &normalize(v)), id: id.clone() })
+            .collect();
+        scored.sort();
+        scored.into_iter().take(k).map(|s| s.id).collect()
+    }
+
+    fn recall(data: &[(String, Vec<f32>)], queries: &[Vec<f32>], k: usize) -> f32 {
+        let mut hits = 0;
+        for q in queries {
+            let expected: HashSet<String> = brute_force(data, q, k).into_iter().collect();
+            hits += search(namespace::DEFAULT_NAMESPACE, q, k, None).iter().filter(|(id, _)| expected.contains(id)).count();
+        }
+        hits as f32 / (queries.len() * k) as f32
+    }
+
+    fn build(count: usize, dim: usize) -> Vec<(String, Vec<f32>)> {
+        ensure_initialized(0);
+        set_params(HnswParams { m: 12, ef_construction: 64, ef_search: 24 }).unwrap();
+        let data: Vec<(String, Vec<f32>)> = vectors(count, dim, 42)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (format!("vec-{}", i), v))
+            .collect();
+        for (id, v) in &data {
+            insert(id, v, false);
+        }
+        data
+    }
+
+    #[test]
+    fn test_recall_against_brute_force() {
+        let data = build(1500, 8);
+        let queries = vectors(30, 8, 7);
+
+        let r = recall(&data, &queries, 10);
+        assert!(r >= 0.9, "recall@10 was {}", r);
+
+        // Sub-linear: a query computes distances for only a fraction of the shard
+        let visited: usize = queries.iter().map(|q| search_counted(namespace::DEFAULT_NAMESPACE, q, 10, None).1).sum::<usize>() / queries.len();
+        assert!(visited < data.len() / 4, "visited {} of {}", visited, data.len());
+        assert_eq!(status().node_count, 1500);
+    }
+
+    #[test]
+    fn test_delete_relinks_and_keeps_recall() {
+        let mut data = build(450, 12);
+        let removed: Vec<(String, Vec<f32>)> = data.iter().step_by(3).cloned().collect();
+        for (id, _) in &removed {
+            assert!(remove(id));
+        }
+        data.retain(|(id, _)| !removed.iter().any(|(r, _)| r == id));
+
+        let queries = vectors(20, 12, 99);
+        for q in &queries {
+            assert!(search(namespace::DEFAULT_NAMESPACE, q, 10, None).iter().all(|(id, _)| !removed.iter().any(|(r, _)| r == id)));
+        }
+        let r = recall(&data, &queries, 10);
+        assert!(r >= 0.85, "recall@10 after deletes was {}", r);
+        assert_eq!(status().node_count, 300);
+    }
+
+    #[test]
+    fn test_exact_match_and_update() {
+        build(200, 8);
+        let target = vec![0.9, -0.1, 0.3, 0.0, 0.5, -0.7, 0.2, 0.1];
+        insert("target", &target, false);
+        let top = search(namespace::DEFAULT_NAMESPACE, &target, 1, None);
+        assert_eq!(top[0].0, "target");
+        assert!(top[0].1 > 0.999);
+
+        // Re-inserting replaces rather than duplicates
+        let moved: Vec<f32> = target.iter().map(|x| -x).collect();
+        insert("target", &moved, false);
+        assert_eq!(search(namespace::DEFAULT_NAMESPACE, &moved, 1, None)[0].0, "target");
+        assert_eq!(status().node_count, 201);
+    }
+
+    #[test]
+    fn test_namespaces_have_separate_graphs() {
+        build(300, 8);
+        // Another namespace with its own dimension
+        let wide = vectors(40, 16, 5);
+        for (i, v) in wide.iter().enumerate() {
+            insert(&namespace::storage_key("wide", &format!("w-{}", i)), v, false);
+        }
+
+        let hits = search("wide", &wide[3], 10, None);
+        assert_eq!(hits.len(), 10);
+        assert_eq!(hits[0].0, namespace::storage_key("wide", "w-3"));
+        assert!(hits.iter().all(|(key, _)| namespace::of_key(key) == "wide"));
+        assert!(search(namespace::DEFAULT_NAMESPACE, &vectors(1, 8, 3)[0], 10, None).iter().all(|(key, _)| key.starts_with("vec-")));
+        assert!(search("empty", &wide[0], 10, None).is_empty());
+
+        // Removing a graph's entry point hands it to the namespace's next highest node
+        let top = entry_point("wide").unwrap();
+        assert!(remove(&top.id));
+        assert!(entry_point("wide").is_some_and(|next| namespace::of_key(&next.id) == "wide"));
+        assert_eq!(search("wide", &wide[3], 39, None).len(), 39);
+    }
+
+    #[test]
+    fn test_compact_nodes_keep_recall() {
+        let node = HnswNode { vector: normalize(&[0.3, -0.8, 0.5]), level: 2, neighbors: vec![vec!["a".into()], vec![], vec!["b".into()]], compact: true };
+        let stored = HnswNode::from_bytes(node.to_bytes());
+        assert!(stored.compact && stored.level == 2 && stored.neighbors == node.neighbors);
+        assert!(node.vector.iter().zip(&stored.vector).all(|(x, y)| (x - y).abs() <= 0.5 / COMPACT_SCALE));
+        assert_eq!(node.to_bytes().len(), 5 + 3 + (2 + 2 + 1) * 2 + 2);
+
+        ensure_initialized(0);
+        let data: Vec<(String, Vec<f32>)> = vectors(800, 16, 11)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (format!("vec-{}", i), v))
+            .collect();
+        for (id, v) in &data {
+            insert(id, v, true);
+        }
+        assert!(is_compact("vec-0"));
+        let r = recall(&data, &vectors(20, 16, 13), 10);
+        assert!(r >= 0.85, "recall@10 with int8 nodes was {}", r);
+    }
+}
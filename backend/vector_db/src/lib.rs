@@ -7,6 +7,17 @@
 //! - Each canister stores a subset of vectors based on shard_id
 //! - Cosine similarity search for memory retrieval
 //! - Metadata filtering for advanced queries
+//! - HNSW approximate nearest-neighbour index (see `hnsw`) for sub-linear queries
+//! - Metadata indexes, boolean filters and BM25 keyword search fused with vector
+//!   similarity (see `search`)
+//! - Namespaces with owners, read/write ACLs, quotas and per-namespace dimensions,
+//!   so one shard can serve several tenants (see `namespace`)
+//! - Optional int8 / product quantization per namespace (see `quantize`)
+
+pub mod hnsw;
+pub mod namespace;
+pub mod quantize;
+pub mod search;
 
 use candid::{CandidType, Decode, Encode, Principal};
 use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
@@ -17,10 +28,36 @@ use ic_stable_structures::{
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
 
 // Memory IDs
 const VECTORS_MEM_ID: MemoryId = MemoryId::new(0);
 const SHARD_ID_MEM_ID: MemoryId = MemoryId::new(1);
+const HNSW_NODES_MEM_ID: MemoryId = MemoryId::new(2);
+const HNSW_LEVELS_MEM_ID: MemoryId = MemoryId::new(3);
+const HNSW_STATE_MEM_ID: MemoryId = MemoryId::new(4);
+const META_INDEX_MEM_ID: MemoryId = MemoryId::new(5);
+const NUMERIC_INDEX_MEM_ID: MemoryId = MemoryId::new(6);
+const TEXT_POSTINGS_MEM_ID: MemoryId = MemoryId::new(7);
+const TEXT_TERMS_MEM_ID: MemoryId = MemoryId::new(8);
+const TEXT_DOCS_MEM_ID: MemoryId = MemoryId::new(9);
+const SEARCH_STATE_MEM_ID: MemoryId = MemoryId::new(10);
+const NAMESPACES_MEM_ID: MemoryId = MemoryId::new(11);
+const QUANTIZERS_MEM_ID: MemoryId = MemoryId::new(12);
+const VECTOR_CODES_MEM_ID: MemoryId = MemoryId::new(13);
+const FULL_VECTORS_MEM_ID: MemoryId = MemoryId::new(14);
+const TEXT_STATS_MEM_ID: MemoryId = MemoryId::new(15);
+
+// Dimension of queen_bee's embedder, used by the default namespace
+const EMBEDDING_DIM: usize = 384;
+// Vectors indexed per `build_index` call when backfilling pre-existing vectors
+const DEFAULT_BUILD_BATCH: u32 = 500;
+// Filtered ANN queries over-fetch by this factor before falling back to a full scan
+const FILTER_OVERFETCH: usize = 8;
+// Filters matching at most this many vectors are answered by scoring just those
+const EXACT_CANDIDATE_LIMIT: usize = 5_000;
+// Each ranking contributes this many times top_k results to reciprocal-rank fusion
+const FUSION_DEPTH: usize = 4;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -38,6 +75,85 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(SHARD_ID_MEM_ID)),
             0
         ).unwrap());
+
+    // HNSW graph: vector id -> node, level index and index state
+    static HNSW_NODES: RefCell<StableBTreeMap<StorableString, hnsw::HnswNode, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(HNSW_NODES_MEM_ID))
+        ));
+
+    static HNSW_LEVELS: RefCell<StableBTreeMap<hnsw::LevelKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(HNSW_LEVELS_MEM_ID))
+        ));
+
+    static HNSW_STATE: RefCell<ic_stable_structures::StableCell<hnsw::IndexState, Memory>> =
+        RefCell::new(ic_stable_structures::StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(HNSW_STATE_MEM_ID)),
+            hnsw::IndexState::default()
+        ).unwrap());
+
+    // Metadata indexes: (namespace, key, value, id) and (namespace, numeric field, value, id)
+    static META_INDEX: RefCell<StableBTreeMap<search::MetaKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(META_INDEX_MEM_ID))
+        ));
+
+    static NUMERIC_INDEX: RefCell<StableBTreeMap<search::NumKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(NUMERIC_INDEX_MEM_ID))
+        ));
+
+    // BM25 over the content field: (namespace, term, id) -> term frequency,
+    // (namespace, term) -> document frequency, id -> content length in terms, and
+    // namespace -> document count and total length
+    static TEXT_POSTINGS: RefCell<StableBTreeMap<search::TermKey, u32, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TEXT_POSTINGS_MEM_ID))
+        ));
+
+    static TEXT_TERMS: RefCell<StableBTreeMap<search::DfKey, u32, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TEXT_TERMS_MEM_ID))
+        ));
+
+    static TEXT_DOCS: RefCell<StableBTreeMap<StorableString, u32, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TEXT_DOCS_MEM_ID))
+        ));
+
+    static TEXT_STATS: RefCell<StableBTreeMap<StorableString, search::TextStats, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(TEXT_STATS_MEM_ID))
+        ));
+
+    static SEARCH_STATE: RefCell<ic_stable_structures::StableCell<search::SearchState, Memory>> =
+        RefCell::new(ic_stable_structures::StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(SEARCH_STATE_MEM_ID)),
+            search::SearchState::default()
+        ).unwrap());
+
+    static NAMESPACES: RefCell<StableBTreeMap<StorableString, namespace::Namespace, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(NAMESPACES_MEM_ID))
+        ));
+
+    // Quantization: namespace -> codebook, storage key -> codes, and the
+    // full-precision vectors of quantized entries (their VECTORS entries hold none)
+    static QUANTIZERS: RefCell<StableBTreeMap<StorableString, quantize::QuantState, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(QUANTIZERS_MEM_ID))
+        ));
+
+    static VECTOR_CODES: RefCell<StableBTreeMap<StorableString, quantize::Codes, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(VECTOR_CODES_MEM_ID))
+        ));
+
+    static FULL_VECTORS: RefCell<StableBTreeMap<StorableString, quantize::FullVector, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(FULL_VECTORS_MEM_ID))
+        ));
 }
 
 // ============================================================================
@@ -52,6 +168,7 @@ pub struct VectorEmbedding {
     pub timestamp: u64,
     pub importance: f32,
     pub shard_id: u32,
+    pub namespace: Option<String>, // None = the default namespace
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -60,6 +177,12 @@ pub struct QueryRequest {
     pub top_k: u32,
     pub min_similarity: f32,
     pub filter_metadata: Option<Vec<(String, String)>>,
+    pub exact: Option<bool>, // Force a brute-force scan instead of the HNSW index
+    pub filter: Option<search::Filter>, // Combined with filter_metadata
+    pub text_query: Option<String>,     // BM25 over `content`, fused with similarity; query_vector may then be empty
+    pub rrf_k: Option<u32>,             // Fusion constant (default 60)
+    pub namespace: Option<String>,      // None = the default namespace
+    pub include_vectors: Option<bool>,  // false = leave QueryResult.vector empty (default true)
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -69,6 +192,8 @@ pub struct QueryResult {
     pub similarity: f32,
     pub metadata: Vec<(String, String)>,
     pub timestamp: u64,
+    pub lexical_score: Option<f32>, // BM25, for hybrid queries
+    pub fused_score: Option<f32>,   // Reciprocal-rank fusion score, for hybrid queries
 }
 
 // Storable types
@@ -107,6 +232,9 @@ impl Storable for VectorEmbedding {
 
 #[init]
 fn init() {
+    hnsw::ensure_initialized(0);
+    search::ensure_initialized(0);
+    ensure_default_namespace();
     ic_cdk::println!("Vector DB Canister initialized");
 }
 
@@ -117,14 +245,114 @@ fn pre_upgrade() {
 
 #[post_upgrade]
 fn post_upgrade() {
+    // Shards upgraded from before the indexes existed, or from an older index layout,
+    // backfill them via `build_index`
+    let vectors = VECTORS.with(|v| v.borrow().len());
+    hnsw::ensure_initialized(vectors);
+    search::ensure_initialized(vectors);
+    ensure_default_namespace();
     ic_cdk::println!("Vector DB Canister upgraded");
 }
 
+/// Create the default namespace, adopting every vector stored before namespaces
+/// existed. Any signed-in caller may read it; writers have to be added by the
+/// controllers with `set_namespace_acl`. The registration scripts add queen_bee; a
+/// shard upgraded from before namespaces needs the same call.
+fn ensure_default_namespace() {
+    let key = StorableString(namespace::DEFAULT_NAMESPACE.to_string());
+    if NAMESPACES.with(|n| n.borrow().contains_key(&key)) {
+        return;
+    }
+    let mut ns = namespace::Namespace::new(
+        key.0.clone(),
+        ic_cdk::id(),
+        EMBEDDING_DIM as u32,
+        namespace::NamespaceQuota::default(),
+        ic_cdk::api::time(),
+    );
+    ns.acl.public_read = true;
+    VECTORS.with(|v| {
+        for (_, embedding) in v.borrow().iter() {
+            ns.account(Some(&embedding), None);
+        }
+    });
+    NAMESPACES.with(|n| n.borrow_mut().insert(key, ns));
+}
+
 // ============================================================================
 // Vector Storage
 // ============================================================================
 
-/// Store a vector embedding
+fn get_namespace_record(name: &str) -> Result<namespace::Namespace, String> {
+    NAMESPACES.with(|n| n.borrow().get(&StorableString(name.to_string())))
+        .ok_or_else(|| format!("Namespace {} not found", name))
+}
+
+fn save_namespace(ns: namespace::Namespace) {
+    NAMESPACES.with(|n| n.borrow_mut().insert(StorableString(ns.name.clone()), ns));
+}
+
+/// The caller's namespace if it may read it
+fn readable_namespace(name: Option<&str>) -> Result<namespace::Namespace, String> {
+    let ns = get_namespace_record(name.unwrap_or(namespace::DEFAULT_NAMESPACE))?;
+    let caller = ic_cdk::caller();
+    if !ns.can_read(&caller, ic_cdk::api::is_controller(&caller)) {
+        return Err(format!("Not allowed to read namespace {}", ns.name));
+    }
+    Ok(ns)
+}
+
+/// The caller's namespace if it may write to it
+fn writable_namespace(name: Option<&str>) -> Result<namespace::Namespace, String> {
+    let ns = get_namespace_record(name.unwrap_or(namespace::DEFAULT_NAMESPACE))?;
+    let caller = ic_cdk::caller();
+    if !ns.can_write(&caller, ic_cdk::api::is_controller(&caller)) {
+        return Err(format!("Not allowed to write to namespace {}", ns.name));
+    }
+    Ok(ns)
+}
+
+fn quantizer(namespace: &str) -> Option<quantize::QuantState> {
+    QUANTIZERS.with(|q| q.borrow().get(&StorableString(namespace.to_string())))
+}
+
+/// Put back the full-precision vector of a quantized entry
+fn with_full_vector(key: &str, mut embedding: VectorEmbedding) -> VectorEmbedding {
+    if embedding.vector.is_empty() {
+        if let Some(full) = FULL_VECTORS.with(|f| f.borrow().get(&StorableString(key.to_string()))) {
+            embedding.vector = full.0;
+        }
+    }
+    embedding
+}
+
+/// A stored vector as it was given to `store_vector`
+fn load_stored(key: &str) -> Option<VectorEmbedding> {
+    VECTORS.with(|v| v.borrow().get(&StorableString(key.to_string())))
+        .map(|embedding| with_full_vector(key, embedding))
+}
+
+/// Keep a vector as codes plus a full-precision copy instead of in its entry
+fn quantize_entry(key: &str, state: &quantize::QuantState, embedding: &mut VectorEmbedding) {
+    let key = StorableString(key.to_string());
+    let codes = quantize::Codes { version: state.version, codes: state.codebook.encode(&embedding.vector) };
+    VECTOR_CODES.with(|c| c.borrow_mut().insert(key.clone(), codes));
+    let vector = std::mem::take(&mut embedding.vector);
+    FULL_VECTORS.with(|f| f.borrow_mut().insert(key, quantize::FullVector(vector)));
+}
+
+/// Remove a stored vector from the shard and its indexes
+fn remove_stored(key: &str) -> Option<VectorEmbedding> {
+    let removed = VECTORS.with(|v| v.borrow_mut().remove(&StorableString(key.to_string())))?;
+    let removed = with_full_vector(key, removed);
+    VECTOR_CODES.with(|c| c.borrow_mut().remove(&StorableString(key.to_string())));
+    FULL_VECTORS.with(|f| f.borrow_mut().remove(&StorableString(key.to_string())));
+    search::unindex(key, &removed);
+    hnsw::remove(key);
+    Some(removed)
+}
+
+/// Store a vector embedding in its namespace (`embedding.namespace`, default if None)
 #[update]
 fn store_vector(embedding: VectorEmbedding) -> Result<(), String> {
     let caller = ic_cdk::caller();
@@ -133,6 +361,9 @@ fn store_vector(embedding: VectorEmbedding) -> Result<(), String> {
         return Err("Anonymous callers not allowed".to_string());
     }
 
+    let mut ns = writable_namespace(embedding.namespace.as_deref())?;
+    namespace::validate_id(&embedding.id)?;
+
     // Ensure shard_id matches this canister's shard
     let this_shard_id = SHARD_ID.with(|s| s.borrow().get().clone());
     if embedding.shard_id != this_shard_id {
@@ -142,34 +373,50 @@ fn store_vector(embedding: VectorEmbedding) -> Result<(), String> {
         ));
     }
 
-    VECTORS.with(|v| {
-        v.borrow_mut()
-            .insert(StorableString(embedding.id.clone()), embedding.clone());
-    });
+    let key = namespace::storage_key(&ns.name, &embedding.id);
+    let previous = load_stored(&key);
+    ns.check_store(&embedding, previous.as_ref())?;
+    ns.account(Some(&embedding), previous.as_ref());
+    let quantizer = quantizer(&ns.name);
+    save_namespace(ns);
+
+    let mut entry = embedding.clone();
+    match &quantizer {
+        Some(state) => quantize_entry(&key, state, &mut entry),
+        None => {
+            VECTOR_CODES.with(|c| c.borrow_mut().remove(&StorableString(key.clone())));
+            FULL_VECTORS.with(|f| f.borrow_mut().remove(&StorableString(key.clone())));
+        }
+    }
+    VECTORS.with(|v| v.borrow_mut().insert(StorableString(key.clone()), entry));
+
+    if let Some(previous) = previous {
+        search::unindex(&key, &previous);
+    }
+    search::index(&key, &embedding);
+    hnsw::insert(&key, &embedding.vector, quantizer.is_some());
 
-    ic_cdk::println!("Stored vector: {}", embedding.id);
+    ic_cdk::println!("Stored vector: {}", key);
     Ok(())
 }
 
 /// Get a specific vector
 #[query]
-fn get_vector(id: String) -> Result<VectorEmbedding, String> {
-    VECTORS.with(|v| {
-        v.borrow()
-            .get(&StorableString(id))
-            .ok_or_else(|| "Vector not found".to_string())
-    })
+fn get_vector(id: String, namespace: Option<String>) -> Result<VectorEmbedding, String> {
+    let ns = readable_namespace(namespace.as_deref())?;
+    load_stored(&namespace::storage_key(&ns.name, &id))
+        .ok_or_else(|| "Vector not found".to_string())
 }
 
 /// Delete a vector
 #[update]
-fn delete_vector(id: String) -> Result<(), String> {
-    VECTORS.with(|v| {
-        v.borrow_mut()
-            .remove(&StorableString(id))
-            .ok_or_else(|| "Vector not found".to_string())
-            .map(|_| ())
-    })
+fn delete_vector(id: String, namespace: Option<String>) -> Result<(), String> {
+    let mut ns = writable_namespace(namespace.as_deref())?;
+    let removed = remove_stored(&namespace::storage_key(&ns.name, &id))
+        .ok_or_else(|| "Vector not found".to_string())?;
+    ns.account(None, Some(&removed));
+    save_namespace(ns);
+    Ok(())
 }
 
 // ============================================================================
@@ -193,50 +440,275 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     dot_product / (norm_a * norm_b)
 }
 
-/// Query vectors by similarity
-#[query]
-fn query_vectors(request: QueryRequest) -> Result<Vec<QueryResult>, String> {
-    let mut results: Vec<(QueryResult, f32)> = Vec::new();
+/// The request's filter: `filter` and every `filter_metadata` pair must hold
+fn request_filter(request: &QueryRequest) -> Option<search::Filter> {
+    let mut filters: Vec<search::Filter> = request.filter_metadata.iter()
+        .flatten()
+        .map(|(key, value)| search::Filter::Eq { key: key.clone(), value: value.clone() })
+        .collect();
+    filters.extend(request.filter.clone());
+    match filters.len() {
+        0 => None,
+        1 => filters.pop(),
+        _ => Some(search::Filter::And(filters)),
+    }
+}
+
+/// The vector belongs to the queried namespace and passes the filter
+fn matches_filter(embedding: &VectorEmbedding, namespace: &str, filter: Option<&search::Filter>) -> bool {
+    namespace::of(embedding) == namespace && filter.map(|f| search::matches(f, embedding)).unwrap_or(true)
+}
 
+/// Storage keys of a namespace's vectors, if it is small enough to score them all
+/// (the default namespace's bare keys cannot be enumerated by range)
+fn namespace_keys(name: &str) -> Option<BTreeSet<String>> {
+    if name == namespace::DEFAULT_NAMESPACE {
+        return None;
+    }
+    let prefix = namespace::key_prefix(name);
+    let keys: BTreeSet<String> = VECTORS.with(|v| {
+        v.borrow()
+            .range(StorableString(prefix.clone())..)
+            .map(|(k, _)| k.0)
+            .take_while(|k| k.starts_with(&prefix))
+            .take(EXACT_CANDIDATE_LIMIT + 1)
+            .collect()
+    });
+    (keys.len() <= EXACT_CANDIDATE_LIMIT).then_some(keys)
+}
+
+fn to_result(embedding: &VectorEmbedding, similarity: f32) -> QueryResult {
+    QueryResult {
+        id: embedding.id.clone(),
+        vector: embedding.vector.clone(),
+        similarity,
+        metadata: embedding.metadata.clone(),
+        timestamp: embedding.timestamp,
+        lexical_score: None,
+        fused_score: None,
+    }
+}
+
+/// Sort by weighted similarity and take top_k
+fn rank(mut results: Vec<(QueryResult, f32)>, top_k: usize) -> Vec<QueryResult> {
+    results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    results.truncate(top_k);
+    results.into_iter().map(|(result, _)| result).collect()
+}
+
+/// Visit every stored entry of the namespace, or only those in `ids`
+fn scan(namespace: &str, ids: Option<&BTreeSet<String>>, mut visit: impl FnMut(String, VectorEmbedding)) {
     VECTORS.with(|v| {
-        for (_, embedding) in v.borrow().iter() {
-            // Check metadata filter if provided
-            if let Some(ref filter) = request.filter_metadata {
-                let mut matches = true;
-                for (key, value) in filter {
-                    let found = embedding.metadata.iter().any(|(k, v)| k == key && v == value);
-                    if !found {
-                        matches = false;
-                        break;
-                    }
-                }
-                if !matches {
-                    continue;
-                }
+        let vectors = v.borrow();
+        match ids {
+            Some(ids) => ids.iter()
+                .filter_map(|id| vectors.get(&StorableString(id.clone())).map(|e| (id.clone(), e)))
+                .for_each(|(key, embedding)| visit(key, embedding)),
+            None if namespace == namespace::DEFAULT_NAMESPACE => {
+                vectors.iter().for_each(|(key, embedding)| visit(key.0, embedding))
             }
+            None => {
+                let prefix = namespace::key_prefix(namespace);
+                vectors.range(StorableString(prefix.clone())..)
+                    .take_while(|(k, _)| k.0.starts_with(&prefix))
+                    .for_each(|(key, embedding)| visit(key.0, embedding))
+            }
+        }
+    });
+}
+
+/// Brute-force scan over every vector in the namespace, or only over `ids` when the
+/// filter's candidates are known
+fn query_exact(request: &QueryRequest, namespace: &str, filter: Option<&search::Filter>, ids: Option<&BTreeSet<String>>, top_k: usize) -> Vec<QueryResult> {
+    let mut results: Vec<(QueryResult, f32)> = Vec::new();
+    scan(namespace, ids, |key, embedding| {
+        if !matches_filter(&embedding, namespace, filter) {
+            return;
+        }
+        let embedding = with_full_vector(&key, embedding);
+        let similarity = cosine_similarity(&request.query_vector, &embedding.vector);
+        if similarity >= request.min_similarity {
+            // Weight by importance
+            results.push((to_result(&embedding, similarity), similarity * embedding.importance));
+        }
+    });
+
+    rank(results, top_k)
+}
+
+/// Score a quantized namespace's codes against the query (asymmetric distance), then
+/// re-rank the best candidates with their full-precision vectors
+fn query_quantized(request: &QueryRequest, namespace: &str, state: &quantize::QuantState, filter: Option<&search::Filter>, candidates: Option<&BTreeSet<String>>, top_k: usize) -> Vec<QueryResult> {
+    let scorer = state.codebook.scorer(&request.query_vector);
+    let mut shortlist: Vec<(String, f32)> = Vec::new();
+    scan(namespace, candidates, |key, embedding| {
+        if !matches_filter(&embedding, namespace, filter) {
+            return;
+        }
+        let codes = VECTOR_CODES.with(|c| c.borrow().get(&StorableString(key.clone())));
+        let similarity = match state.current(codes.as_ref()) {
+            Some(codes) => scorer.similarity(codes),
+            // Not yet encoded with the current codebook
+            None => cosine_similarity(&request.query_vector, &with_full_vector(&key, embedding.clone()).vector),
+        };
+        shortlist.push((key, similarity * embedding.importance));
+    });
 
+    let depth = top_k * quantize::RERANK_FACTOR;
+    if shortlist.len() > depth {
+        shortlist.select_nth_unstable_by(depth, |a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        shortlist.truncate(depth);
+    }
+
+    let results = shortlist.into_iter()
+        .filter_map(|(key, _)| load_stored(&key))
+        .filter_map(|embedding| {
             let similarity = cosine_similarity(&request.query_vector, &embedding.vector);
-            
+            (similarity >= request.min_similarity)
+                .then(|| (to_result(&embedding, similarity), similarity * embedding.importance))
+        })
+        .collect();
+    rank(results, top_k)
+}
+
+/// Similarity ranking, best first
+fn vector_ranking(request: &QueryRequest, namespace: &str, filter: Option<&search::Filter>, candidates: Option<&BTreeSet<String>>, top_k: usize) -> Vec<QueryResult> {
+    let quantizer = if request.exact == Some(true) { None } else { quantizer(namespace) };
+    let scan_all = |ids: Option<&BTreeSet<String>>| match &quantizer {
+        Some(state) => query_quantized(request, namespace, state, filter, ids, top_k),
+        None => query_exact(request, namespace, filter, ids, top_k),
+    };
+    // A selective filter leaves few enough vectors to score them all
+    if let Some(ids) = candidates.filter(|ids| ids.len() <= EXACT_CANDIDATE_LIMIT) {
+        return scan_all(Some(ids));
+    }
+    if request.exact == Some(true) || !hnsw::is_ready() {
+        return scan_all(candidates);
+    }
+
+    // Over-fetch so importance weighting and metadata filters can reorder/drop candidates;
+    // quantized namespaces' int8 graph vectors are re-ranked with full precision
+    let depth = if quantizer.is_some() { top_k * quantize::RERANK_FACTOR } else { top_k };
+    let fetch = if filter.is_some() { depth * FILTER_OVERFETCH } else { depth * 2 };
+    let hits = hnsw::search(namespace, &request.query_vector, fetch, None);
+    // Hits are best first, so once one is under min_similarity the rest are too
+    let exhausted = hits.last().is_some_and(|(_, similarity)| *similarity < request.min_similarity);
+
+    let mut results: Vec<(QueryResult, f32)> = Vec::new();
+    VECTORS.with(|v| {
+        let vectors = v.borrow();
+        for (id, similarity) in hits {
+            if candidates.is_some_and(|ids| !ids.contains(&id)) {
+                continue;
+            }
+            let Some(embedding) = vectors.get(&StorableString(id.clone())) else {
+                continue;
+            };
+            if !matches_filter(&embedding, namespace, filter) {
+                continue;
+            }
+            let (embedding, similarity) = if quantizer.is_some() {
+                let embedding = with_full_vector(&id, embedding);
+                let similarity = cosine_similarity(&request.query_vector, &embedding.vector);
+                (embedding, similarity)
+            } else {
+                (embedding, similarity)
+            };
             if similarity >= request.min_similarity {
-                results.push((
-                    QueryResult {
-                        id: embedding.id.clone(),
-                        vector: embedding.vector.clone(),
-                        similarity,
-                        metadata: embedding.metadata.clone(),
-                        timestamp: embedding.timestamp,
-                    },
-                    similarity * embedding.importance, // Weight by importance
-                ));
+                results.push((to_result(&embedding, similarity), similarity * embedding.importance));
+            }
+        }
+    });
+
+    // A selective filter can starve the ANN candidates; answer exactly instead
+    if !exhausted && results.len() < top_k {
+        return scan_all(candidates);
+    }
+
+    rank(results, top_k)
+}
+
+/// Fuse the similarity ranking with BM25 keyword relevance over `content`
+fn query_hybrid(request: &QueryRequest, text: &str, namespace: &str, filter: Option<&search::Filter>, candidates: Option<&BTreeSet<String>>, top_k: usize) -> Vec<QueryResult> {
+    let depth = top_k * FUSION_DEPTH;
+    let vector_hits = if request.query_vector.is_empty() {
+        Vec::new()
+    } else {
+        vector_ranking(request, namespace, filter, candidates, depth)
+    };
+
+    let mut lexical_hits: Vec<(VectorEmbedding, f32)> = Vec::new();
+    VECTORS.with(|v| {
+        let vectors = v.borrow();
+        for (id, score) in search::lexical(namespace, text) {
+            if lexical_hits.len() >= depth {
+                break;
+            }
+            if candidates.is_some_and(|ids| !ids.contains(&id)) {
+                continue;
+            }
+            if let Some(embedding) = vectors.get(&StorableString(id.clone())) {
+                if matches_filter(&embedding, namespace, filter) {
+                    lexical_hits.push((with_full_vector(&id, embedding), score));
+                }
             }
         }
     });
 
-    // Sort by weighted similarity and take top_k
-    results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-    results.truncate(request.top_k as usize);
+    let rankings = [
+        vector_hits.iter().map(|r| r.id.clone()).collect(),
+        lexical_hits.iter().map(|(e, _)| e.id.clone()).collect(),
+    ];
+    let fused = search::rrf(&rankings, request.rrf_k.unwrap_or(search::DEFAULT_RRF_K));
+
+    let mut by_id: HashMap<String, QueryResult> = vector_hits.into_iter().map(|r| (r.id.clone(), r)).collect();
+    for (embedding, score) in lexical_hits {
+        let result = by_id.entry(embedding.id.clone()).or_insert_with(|| {
+            let similarity = if request.query_vector.is_empty() {
+                0.0
+            } else {
+                cosine_similarity(&request.query_vector, &embedding.vector)
+            };
+            to_result(&embedding, similarity)
+        });
+        result.lexical_score = Some(score);
+    }
+
+    fused.into_iter()
+        .take(top_k)
+        .filter_map(|(id, score)| {
+            let mut result = by_id.remove(&id)?;
+            result.fused_score = Some(score);
+            Some(result)
+        })
+        .collect()
+}
+
+/// Query vectors of one namespace by similarity, optionally fused with keyword
+/// relevance (`text_query`). Filters are resolved through the metadata indexes
+/// first, so only matching vectors are scored.
+#[query]
+fn query_vectors(request: QueryRequest) -> Result<Vec<QueryResult>, String> {
+    let ns = readable_namespace(request.namespace.as_deref())?;
+    let lexical_only = request.query_vector.is_empty() && request.text_query.is_some();
+    if !lexical_only && request.query_vector.len() != ns.dimension as usize {
+        return Err(format!("Query vector must have {} dimensions", ns.dimension));
+    }
 
-    Ok(results.into_iter().map(|(result, _)| result).collect())
+    let filter = request_filter(&request);
+    // Index candidates are already limited to the namespace
+    let candidates = filter.as_ref()
+        .and_then(|f| search::candidates(&ns.name, f))
+        .or_else(|| namespace_keys(&ns.name));
+    let top_k = request.top_k as usize;
+    let mut results = match &request.text_query {
+        Some(text) => query_hybrid(&request, text, &ns.name, filter.as_ref(), candidates.as_ref(), top_k),
+        None => vector_ranking(&request, &ns.name, filter.as_ref(), candidates.as_ref(), top_k),
+    };
+    if request.include_vectors == Some(false) {
+        results.iter_mut().for_each(|r| r.vector = Vec::new());
+    }
+    Ok(results)
 }
 
 /// Query similar vectors (simplified interface)
@@ -247,9 +719,54 @@ fn query_similar(query_vector: Vec<f32>, top_k: u32) -> Result<Vec<QueryResult>,
         top_k,
         min_similarity: 0.0,
         filter_metadata: None,
+        exact: None,
+        filter: None,
+        text_query: None,
+        rrf_k: None,
+        namespace: None,
+        include_vectors: None,
     })
 }
 
+// ============================================================================
+// Index Management
+// ============================================================================
+
+/// HNSW parameters, size and whether queries use it yet
+#[query]
+fn get_index_status() -> hnsw::IndexStatus {
+    hnsw::status()
+}
+
+/// Tune the HNSW index (controllers only)
+#[update]
+fn set_index_params(params: hnsw::HnswParams) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can tune the index".to_string());
+    }
+    hnsw::set_params(params)
+}
+
+/// Index the next batch of vectors stored before the HNSW and metadata indexes
+/// existed, and encode the next batch of a newly (re)trained quantized namespace
+/// (controllers only)
+#[update]
+fn build_index(batch_size: Option<u32>) -> Result<hnsw::IndexStatus, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can build the index".to_string());
+    }
+    let batch = batch_size.unwrap_or(DEFAULT_BUILD_BATCH).max(1) as usize;
+    search::build_step(batch);
+    quantize_step(batch);
+    Ok(hnsw::build_step(batch))
+}
+
+/// Metadata and keyword index size and whether filters use it yet
+#[query]
+fn get_search_index_status() -> search::SearchIndexStatus {
+    search::status()
+}
+
 // ============================================================================
 // Shard Management
 // ============================================================================
@@ -262,17 +779,288 @@ fn get_shard_info() -> (u32, u64) {
     (shard_id, vector_count)
 }
 
-/// Get all vector IDs in this shard
+/// Set this canister's shard id (controllers only; must match its queen_bee registration)
+#[update]
+fn set_shard_id(shard_id: u32) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can set the shard id".to_string());
+    }
+    SHARD_ID.with(|s| s.borrow_mut().set(shard_id))
+        .map(|_| ())
+        .map_err(|e| format!("Failed to set shard id: {:?}", e))
+}
+
+/// Get all vector IDs of a namespace in this shard (the default namespace if None;
+/// empty if the caller may not read it)
 #[query]
-fn get_all_vector_ids() -> Vec<String> {
+fn get_all_vector_ids(namespace: Option<String>) -> Vec<String> {
+    let Ok(ns) = readable_namespace(namespace.as_deref()) else {
+        return Vec::new();
+    };
     VECTORS.with(|v| {
-        v.borrow()
+        let vectors = v.borrow();
+        if ns.name == namespace::DEFAULT_NAMESPACE {
+            vectors.iter()
+                .filter(|(_, e)| namespace::of(e) == ns.name)
+                .map(|(k, _)| k.0.clone())
+                .collect()
+        } else {
+            let prefix = namespace::key_prefix(&ns.name);
+            vectors.range(StorableString(prefix.clone())..)
+                .take_while(|(k, _)| k.0.starts_with(&prefix))
+                .map(|(_, e)| e.id)
+                .collect()
+        }
+    })
+}
+
+// ============================================================================
+// Namespaces
+// ============================================================================
+
+/// Create a namespace (controllers only)
+#[update]
+fn create_namespace(name: String, owner: Principal, dimension: u32, quota: namespace::NamespaceQuota) -> Result<namespace::Namespace, String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can create namespaces".to_string());
+    }
+    namespace::validate_name(&name)?;
+    if owner == Principal::anonymous() {
+        return Err("The anonymous principal cannot own a namespace".to_string());
+    }
+    if dimension == 0 {
+        return Err("Dimension must be positive".to_string());
+    }
+    if get_namespace_record(&name).is_ok() {
+        return Err(format!("Namespace {} already exists", name));
+    }
+    let ns = namespace::Namespace::new(name, owner, dimension, quota, ic_cdk::api::time());
+    save_namespace(ns.clone());
+    Ok(ns)
+}
+
+/// Change a namespace's quotas (controllers only); existing vectors are kept even
+/// if they exceed the new limits
+#[update]
+fn set_namespace_quota(name: String, quota: namespace::NamespaceQuota) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers can set quotas".to_string());
+    }
+    let mut ns = get_namespace_record(&name)?;
+    ns.quota = quota;
+    save_namespace(ns);
+    Ok(())
+}
+
+/// Replace a namespace's writer and reader lists (owner or controllers)
+#[update]
+fn set_namespace_acl(name: String, acl: namespace::NamespaceAcl) -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    let mut ns = get_namespace_record(&name)?;
+    if !ns.can_manage(&caller, ic_cdk::api::is_controller(&caller)) {
+        return Err("Only the owner or controllers can change access".to_string());
+    }
+    namespace::validate_acl(&acl)?;
+    ns.acl = acl;
+    save_namespace(ns);
+    Ok(())
+}
+
+#[query]
+fn get_namespace(name: String) -> Result<namespace::Namespace, String> {
+    readable_namespace(Some(&name))
+}
+
+/// Namespaces the caller may read
+#[query]
+fn list_namespaces() -> Vec<namespace::Namespace> {
+    let caller = ic_cdk::caller();
+    let is_controller = ic_cdk::api::is_controller(&caller);
+    NAMESPACES.with(|n| {
+        n.borrow()
             .iter()
-            .map(|(k, _)| k.0.clone())
+            .map(|(_, ns)| ns)
+            .filter(|ns| ns.can_read(&caller, is_controller))
+            .collect()
+    })
+}
+
+/// Up to `limit` stored entries of a namespace in key order, after the key `after`
+fn namespace_page(name: &str, after: Option<&str>, limit: usize) -> Vec<(String, VectorEmbedding)> {
+    let prefix = if name == namespace::DEFAULT_NAMESPACE { String::new() } else { namespace::key_prefix(name) };
+    let start = after.map(str::to_string).unwrap_or_else(|| prefix.clone());
+    VECTORS.with(|v| {
+        v.borrow()
+            .range(StorableString(start.clone())..)
+            .filter(|(k, _)| after.is_none() || k.0 != start)
+            .take_while(|(k, _)| k.0.starts_with(&prefix))
+            .filter(|(_, e)| namespace::of(e) == name)
+            .take(limit)
+            .map(|(k, e)| (k.0, e))
             .collect()
     })
 }
 
+/// Page through a namespace's vectors in key order (readers of the namespace)
+#[query]
+fn export_namespace(name: String, cursor: Option<String>, limit: Option<u32>) -> Result<namespace::NamespaceExport, String> {
+    let ns = readable_namespace(Some(&name))?;
+    let limit = limit.unwrap_or(namespace::MAX_EXPORT_PAGE).clamp(1, namespace::MAX_EXPORT_PAGE) as usize;
+    let mut page = namespace_page(&ns.name, cursor.as_deref(), limit + 1);
+    let more = page.len() > limit;
+    page.truncate(limit);
+    Ok(namespace::NamespaceExport {
+        next_cursor: if more { page.last().map(|(k, _)| k.clone()) } else { None },
+        vectors: page.into_iter().map(|(k, e)| with_full_vector(&k, e)).collect(),
+    })
+}
+
+/// Page through a namespace's vector ids in key order (readers of the namespace)
+#[query]
+fn list_vector_ids(name: String, cursor: Option<String>, limit: Option<u32>) -> Result<namespace::VectorIdPage, String> {
+    let ns = readable_namespace(Some(&name))?;
+    let limit = limit.unwrap_or(namespace::MAX_ID_PAGE).clamp(1, namespace::MAX_ID_PAGE) as usize;
+    let mut page = namespace_page(&ns.name, cursor.as_deref(), limit + 1);
+    let more = page.len() > limit;
+    page.truncate(limit);
+    Ok(namespace::VectorIdPage {
+        next_cursor: if more { page.last().map(|(k, _)| k.clone()) } else { None },
+        ids: page.into_iter().map(|(_, e)| e.id).collect(),
+    })
+}
+
+/// Delete a namespace's vectors, a batch per call (owner or controllers). The
+/// namespace refuses writes from the first call on; once empty its record is removed
+/// (the default namespace is only emptied).
+#[update]
+fn delete_namespace(name: String, batch_size: Option<u32>) -> Result<namespace::NamespaceDeletion, String> {
+    let caller = ic_cdk::caller();
+    let mut ns = get_namespace_record(&name)?;
+    if !ns.can_manage(&caller, ic_cdk::api::is_controller(&caller)) {
+        return Err("Only the owner or controllers can delete a namespace".to_string());
+    }
+    let batch = batch_size.unwrap_or(namespace::DEFAULT_DELETE_BATCH).max(1) as usize;
+    ns.deleting = true;
+
+    let keys: Vec<String> = VECTORS.with(|v| {
+        let vectors = v.borrow();
+        if ns.name == namespace::DEFAULT_NAMESPACE {
+            vectors.iter()
+                .filter(|(_, e)| namespace::of(e) == ns.name)
+                .map(|(k, _)| k.0)
+                .take(batch)
+                .collect()
+        } else {
+            let prefix = namespace::key_prefix(&ns.name);
+            vectors.range(StorableString(prefix.clone())..)
+                .map(|(k, _)| k.0)
+                .take_while(|k| k.starts_with(&prefix))
+                .take(batch)
+                .collect()
+        }
+    });
+    for key in &keys {
+        if let Some(removed) = remove_stored(key) {
+            ns.account(None, Some(&removed));
+        }
+    }
+
+    let done = keys.len() < batch;
+    let deletion = namespace::NamespaceDeletion {
+        deleted: keys.len() as u32,
+        remaining: if done { 0 } else { ns.vector_count },
+        done,
+    };
+    if !done {
+        save_namespace(ns);
+    } else if ns.name == namespace::DEFAULT_NAMESPACE {
+        ns.deleting = false;
+        ns.vector_count = 0;
+        ns.bytes_used = 0;
+        save_namespace(ns);
+    } else {
+        NAMESPACES.with(|n| n.borrow_mut().remove(&StorableString(ns.name.clone())));
+        QUANTIZERS.with(|q| q.borrow_mut().remove(&StorableString(ns.name.clone())));
+    }
+    Ok(deletion)
+}
+
+// ============================================================================
+// Quantization
+// ============================================================================
+
+/// Train a codebook from the first `sample_size` vectors of a namespace and quantize
+/// it (owner or controllers). Vectors stored from now on are kept as codes; existing
+/// ones are encoded by `build_index`. Retraining replaces the codebook (the mode may
+/// change); a quantized namespace cannot go back to plain vectors.
+#[update]
+fn train_quantizer(name: String, mode: quantize::QuantizationMode, sample_size: Option<u32>) -> Result<quantize::QuantizationStatus, String> {
+    let caller = ic_cdk::caller();
+    let ns = get_namespace_record(&name)?;
+    if !ns.can_manage(&caller, ic_cdk::api::is_controller(&caller)) {
+        return Err("Only the owner or controllers can quantize a namespace".to_string());
+    }
+    let sample_size = sample_size
+        .unwrap_or(quantize::DEFAULT_TRAINING_SAMPLE)
+        .clamp(1, quantize::MAX_TRAINING_SAMPLE) as usize;
+    let sample: Vec<Vec<f32>> = namespace_page(&ns.name, None, sample_size)
+        .into_iter()
+        .map(|(key, embedding)| with_full_vector(&key, embedding).vector)
+        .collect();
+    let codebook = quantize::Codebook::train(&mode, &sample, ns.dimension as usize)?;
+
+    let state = quantize::QuantState {
+        mode,
+        codebook,
+        version: quantizer(&ns.name).map(|s| s.version + 1).unwrap_or(1),
+        sample_size: sample.len() as u32,
+        trained_at: ic_cdk::api::time(),
+        encoding: true,
+        cursor: None,
+    };
+    let status = state.status(&ns.name);
+    QUANTIZERS.with(|q| q.borrow_mut().insert(StorableString(ns.name), state));
+    Ok(status)
+}
+
+/// (Re-)encode the next batch of the first namespace whose codebook is newer than
+/// some of its codes
+fn quantize_step(batch: usize) {
+    let Some((name, mut state)) = QUANTIZERS.with(|q| q.borrow().iter().find(|(_, s)| s.encoding)) else {
+        return;
+    };
+    let page = namespace_page(&name.0, state.cursor.as_deref(), batch);
+    for (key, embedding) in &page {
+        let codes = VECTOR_CODES.with(|c| c.borrow().get(&StorableString(key.clone())));
+        if state.current(codes.as_ref()).is_some() {
+            continue;
+        }
+        let mut entry = with_full_vector(key, embedding.clone());
+        if !hnsw::is_compact(key) {
+            hnsw::insert(key, &entry.vector, true);
+        }
+        quantize_entry(key, &state, &mut entry);
+        VECTORS.with(|v| v.borrow_mut().insert(StorableString(key.clone()), entry));
+    }
+
+    if page.len() < batch {
+        state.encoding = false;
+        state.cursor = None;
+    } else {
+        state.cursor = page.last().map(|(key, _)| key.clone());
+    }
+    QUANTIZERS.with(|q| q.borrow_mut().insert(name, state));
+}
+
+/// Codebook and encoding progress of a quantized namespace (readers of the namespace)
+#[query]
+fn get_quantization_status(name: String) -> Result<quantize::QuantizationStatus, String> {
+    let ns = readable_namespace(Some(&name))?;
+    quantizer(&ns.name)
+        .map(|state| state.status(&ns.name))
+        .ok_or_else(|| format!("Namespace {} is not quantized", ns.name))
+}
+
 // ============================================================================
 // Health & Status
 // ============================================================================
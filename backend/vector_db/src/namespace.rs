@@ -0,0 +1,286 @@
+//! Namespaces
+//!
+//! Tenants (Axiom agents, raven_ai, queen_bee, ...) get separate keyspaces on one
+//! shard. A namespace has an owner who manages its writer and reader lists, a fixed
+//! vector dimension, and vector-count and byte quotas set by the controllers.
+//!
+//! Vectors of a namespace are stored under `"{namespace}\x1f{id}"`, so ids only have
+//! to be unique within their namespace and a namespace's vectors form one key range.
+//! The `default` namespace keeps bare ids (vectors stored before namespaces existed)
+//! and is readable by every signed-in caller; only the controllers and the writers
+//! they add with `set_namespace_acl` (e.g. queen_bee, see scripts/register_canisters.sh)
+//! may write to it.
+
+use candid::{CandidType, Decode, Encode, Principal};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::VectorEmbedding;
+
+pub const DEFAULT_NAMESPACE: &str = "default";
+const KEY_SEPARATOR: char = '\u{1f}';
+const MAX_NAME_LEN: usize = 64;
+const MAX_ACL_ENTRIES: usize = 100;
+/// Vectors removed per `delete_namespace` call unless a batch size is given
+pub const DEFAULT_DELETE_BATCH: u32 = 500;
+/// Vectors returned per `export_namespace` page at most
+pub const MAX_EXPORT_PAGE: u32 = 500;
+/// Ids returned per `list_vector_ids` page at most
+pub const MAX_ID_PAGE: u32 = 5_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct NamespaceQuota {
+    pub max_vectors: Option<u64>, // None = unlimited
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct NamespaceAcl {
+    pub writers: Vec<Principal>,
+    pub readers: Vec<Principal>,
+    pub public_read: bool,  // Any signed-in caller may read
+    pub public_write: bool, // Any signed-in caller may write
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Namespace {
+    pub name: String,
+    pub owner: Principal,
+    pub dimension: u32,
+    pub acl: NamespaceAcl,
+    pub quota: NamespaceQuota,
+    pub vector_count: u64,
+    pub bytes_used: u64,
+    pub deleting: bool, // Being emptied by `delete_namespace`; writes are refused
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct NamespaceExport {
+    pub vectors: Vec<VectorEmbedding>,
+    pub next_cursor: Option<String>, // Pass back to continue; None once the namespace is exhausted
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct VectorIdPage {
+    pub ids: Vec<String>,
+    pub next_cursor: Option<String>, // Pass back to continue; None once the namespace is exhausted
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct NamespaceDeletion {
+    pub deleted: u32,
+    pub remaining: u64,
+    pub done: bool,
+}
+
+impl Storable for Namespace {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(format!("Namespace names must be 1-{} characters", MAX_NAME_LEN));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') {
+        return Err("Namespace names may only contain letters, digits, '-', '_' and '.'".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_acl(acl: &NamespaceAcl) -> Result<(), String> {
+    if acl.writers.len() > MAX_ACL_ENTRIES || acl.readers.len() > MAX_ACL_ENTRIES {
+        return Err(format!("At most {} writers and {} readers", MAX_ACL_ENTRIES, MAX_ACL_ENTRIES));
+    }
+    if acl.writers.iter().chain(&acl.readers).any(|p| *p == Principal::anonymous()) {
+        return Err("The anonymous principal cannot be granted access".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || id.contains(KEY_SEPARATOR) {
+        return Err("Invalid vector id".to_string());
+    }
+    Ok(())
+}
+
+/// Key a vector is stored under
+pub fn storage_key(namespace: &str, id: &str) -> String {
+    if namespace == DEFAULT_NAMESPACE {
+        id.to_string()
+    } else {
+        format!("{}{}{}", namespace, KEY_SEPARATOR, id)
+    }
+}
+
+/// First key of a (non-default) namespace's key range
+pub fn key_prefix(namespace: &str) -> String {
+    format!("{}{}", namespace, KEY_SEPARATOR)
+}
+
+/// Namespace a storage key belongs to
+pub fn of_key(key: &str) -> &str {
+    key.split_once(KEY_SEPARATOR).map(|(namespace, _)| namespace).unwrap_or(DEFAULT_NAMESPACE)
+}
+
+/// Namespace of a stored vector (None in the embedding means the default namespace)
+pub fn of(embedding: &VectorEmbedding) -> &str {
+    embedding.namespace.as_deref().unwrap_or(DEFAULT_NAMESPACE)
+}
+
+/// Approximate stored size counted against the byte quota
+pub fn stored_bytes(embedding: &VectorEmbedding) -> u64 {
+    let metadata: usize = embedding.metadata.iter().map(|(k, v)| k.len() + v.len()).sum();
+    (embedding.id.len() + embedding.vector.len() * 4 + metadata) as u64
+}
+
+impl Namespace {
+    pub fn new(name: String, owner: Principal, dimension: u32, quota: NamespaceQuota, now: u64) -> Self {
+        Self {
+            name,
+            owner,
+            dimension,
+            acl: NamespaceAcl::default(),
+            quota,
+            vector_count: 0,
+            bytes_used: 0,
+            deleting: false,
+            created_at: now,
+        }
+    }
+
+    /// Owner or controller: may change the ACL, export and delete
+    pub fn can_manage(&self, caller: &Principal, is_controller: bool) -> bool {
+        is_controller || *caller == self.owner
+    }
+
+    pub fn can_write(&self, caller: &Principal, is_controller: bool) -> bool {
+        if *caller == Principal::anonymous() {
+            return false;
+        }
+        self.can_manage(caller, is_controller) || self.acl.public_write || self.acl.writers.contains(caller)
+    }
+
+    pub fn can_read(&self, caller: &Principal, is_controller: bool) -> bool {
+        if *caller == Principal::anonymous() {
+            return false;
+        }
+        self.can_write(caller, is_controller) || self.acl.public_read || self.acl.readers.contains(caller)
+    }
+
+    /// Check the dimension and quotas for storing `embedding`, replacing `previous`
+    /// (same id) if there was one
+    pub fn check_store(&self, embedding: &VectorEmbedding, previous: Option<&VectorEmbedding>) -> Result<(), String> {
+        if self.deleting {
+            return Err(format!("Namespace {} is being deleted", self.name));
+        }
+        if embedding.vector.len() != self.dimension as usize {
+            return Err(format!(
+                "Vector must have {} dimensions, got {}",
+                self.dimension,
+                embedding.vector.len()
+            ));
+        }
+        let count = self.vector_count + previous.is_none() as u64;
+        if self.quota.max_vectors.is_some_and(|max| count > max) {
+            return Err(format!("Namespace {} is full ({} vectors)", self.name, self.vector_count));
+        }
+        let bytes = (self.bytes_used + stored_bytes(embedding)).saturating_sub(previous.map(stored_bytes).unwrap_or(0));
+        if self.quota.max_bytes.is_some_and(|max| bytes > max) {
+            return Err(format!("Namespace {} would exceed its byte quota", self.name));
+        }
+        Ok(())
+    }
+
+    /// Update usage after a store (replacing `previous`) or a delete (`embedding` None)
+    pub fn account(&mut self, embedding: Option<&VectorEmbedding>, previous: Option<&VectorEmbedding>) {
+        self.vector_count = (self.vector_count + embedding.is_some() as u64).saturating_sub(previous.is_some() as u64);
+        self.bytes_used = (self.bytes_used + embedding.map(stored_bytes).unwrap_or(0))
+            .saturating_sub(previous.map(stored_bytes).unwrap_or(0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[10, id]) // [4] alone is the anonymous principal
+    }
+
+    fn embedding(id: &str, dim: usize) -> VectorEmbedding {
+        VectorEmbedding {
+            id: id.to_string(),
+            vector: vec![0.5; dim],
+            metadata: vec![("k".to_string(), "v".to_string())],
+            timestamp: 0,
+            importance: 1.0,
+            shard_id: 0,
+            namespace: Some("agents".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_access_control() {
+        let mut ns = Namespace::new("agents".into(), principal(1), 4, NamespaceQuota::default(), 0);
+        ns.acl = NamespaceAcl { writers: vec![principal(2)], readers: vec![principal(3)], public_read: false, public_write: false };
+
+        assert!(ns.can_manage(&principal(1), false) && ns.can_write(&principal(1), false));
+        assert!(ns.can_write(&principal(2), false) && !ns.can_manage(&principal(2), false));
+        assert!(ns.can_read(&principal(3), false) && !ns.can_write(&principal(3), false));
+        assert!(!ns.can_read(&principal(4), false));
+        assert!(ns.can_manage(&principal(4), true));
+
+        ns.acl.public_read = true;
+        assert!(ns.can_read(&principal(4), false));
+        assert!(!ns.can_read(&Principal::anonymous(), false));
+        assert!(validate_acl(&NamespaceAcl { writers: vec![Principal::anonymous()], ..Default::default() }).is_err());
+
+        assert!(validate_name("agent-7.memories").is_ok());
+        assert!(validate_name("a/b").is_err() && validate_name("").is_err());
+        assert_eq!(storage_key(DEFAULT_NAMESPACE, "m1"), "m1");
+        assert!(storage_key("agents", "m1").starts_with(&key_prefix("agents")));
+        assert_eq!(of_key(&storage_key("agents", "m1")), "agents");
+        assert_eq!(of_key("m1"), DEFAULT_NAMESPACE);
+        assert!(validate_id("a\u{1f}b").is_err());
+    }
+
+    #[test]
+    fn test_dimension_and_quotas() {
+        let quota = NamespaceQuota { max_vectors: Some(2), max_bytes: Some(60) };
+        let mut ns = Namespace::new("agents".into(), principal(1), 4, quota, 0);
+
+        let a = embedding("a", 4); // 1 + 16 + 2 = 19 bytes
+        assert!(ns.check_store(&embedding("x", 3), None).unwrap_err().contains("dimensions"));
+        ns.check_store(&a, None).unwrap();
+        ns.account(Some(&a), None);
+        let b = embedding("b", 4);
+        ns.account(Some(&b), None);
+        assert_eq!((ns.vector_count, ns.bytes_used), (2, 38));
+
+        // Full on count, but replacing an existing vector is allowed
+        assert!(ns.check_store(&embedding("c", 4), None).unwrap_err().contains("full"));
+        ns.check_store(&a, Some(&a)).unwrap();
+
+        // Byte quota counts the size difference of a replacement
+        let mut big = embedding("a", 4);
+        big.metadata.push(("note".to_string(), "x".repeat(30)));
+        assert!(ns.check_store(&big, Some(&a)).unwrap_err().contains("byte quota"));
+
+        ns.account(None, Some(&b));
+        assert_eq!((ns.vector_count, ns.bytes_used), (1, 19));
+        ns.deleting = true;
+        assert!(ns.check_store(&b, None).is_err());
+    }
+}
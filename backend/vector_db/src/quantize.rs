@@ -0,0 +1,433 @@
+//! Vector Quantization
+//!
+//! A namespace can trade some precision for memory by keeping compressed codes
+//! in place of its f32 vectors:
+//! - `Int8`: every dimension scaled to a byte between the sample's min and max
+//!   (4x smaller)
+//! - `Product`: the vector split into subspaces, each replaced by the index of its
+//!   nearest k-means centroid (384 dims in 48 subspaces: 48 bytes)
+//!
+//! Codebooks are trained from a sample of the namespace's vectors. Full-precision
+//! vectors move out of the vector entries into a separate store that is only read
+//! for re-ranking and `get_vector`. Quantized vectors stay in their namespace's HNSW
+//! graph with int8 node vectors; queries walk the graph and re-rank its hits with
+//! full precision. When the graph cannot answer (not built yet, or a filter starves
+//! it), the codes are scored against the uncompressed query vector (asymmetric
+//! distance) and the best of them re-ranked the same way.
+//!
+//! Retraining bumps the codebook version; codes of an older version are scored
+//! with full precision until `build_index` has re-encoded them.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Vectors sampled for training unless a sample size is given
+pub const DEFAULT_TRAINING_SAMPLE: u32 = 1_000;
+/// Training is k-means in one call, so the sample is capped
+pub const MAX_TRAINING_SAMPLE: u32 = 2_000;
+const KMEANS_ITERATIONS: usize = 8;
+/// Candidates per requested result that are re-ranked with full precision
+pub const RERANK_FACTOR: usize = 4;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum QuantizationMode {
+    Int8,
+    Product { subspaces: u32, centroids: u32 }, // subspaces must divide the dimension; 2-256 centroids
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Codebook {
+    Int8 { min: Vec<f32>, scale: Vec<f32> },
+    Product { sub_dim: u32, centroids: u32, codewords: Vec<f32> }, // [subspace][centroid][sub_dim]
+}
+
+/// Quantization of one namespace
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct QuantState {
+    pub mode: QuantizationMode,
+    pub codebook: Codebook,
+    pub version: u32, // Bumped on retraining
+    pub sample_size: u32,
+    pub trained_at: u64,
+    pub encoding: bool,         // Existing vectors are still being (re-)encoded by `build_index`
+    pub cursor: Option<String>, // Last storage key encoded
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct QuantizationStatus {
+    pub namespace: String,
+    pub mode: QuantizationMode,
+    pub version: u32,
+    pub sample_size: u32,
+    pub trained_at: u64,
+    pub code_bytes: u32, // Per vector, against 4 bytes per dimension unquantized
+    pub encoding: bool,
+}
+
+/// Codes of one vector, tagged with the codebook version that produced them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Codes {
+    pub version: u32,
+    pub codes: Vec<u8>,
+}
+
+/// Full-precision vector of a quantized entry, read for re-ranking
+#[derive(Clone, Debug, PartialEq)]
+pub struct FullVector(pub Vec<f32>);
+
+impl Storable for QuantState {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// Codes are scanned by every query of the namespace: version then raw bytes
+impl Storable for Codes {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut bytes = self.version.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.codes);
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Self {
+            version: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            codes: bytes[4..].to_vec(),
+        }
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for FullVector {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(self.0.iter().flat_map(|x| x.to_le_bytes()).collect())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        FullVector(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl QuantState {
+    pub fn status(&self, namespace: &str) -> QuantizationStatus {
+        QuantizationStatus {
+            namespace: namespace.to_string(),
+            mode: self.mode.clone(),
+            version: self.version,
+            sample_size: self.sample_size,
+            trained_at: self.trained_at,
+            code_bytes: self.codebook.code_bytes() as u32,
+            encoding: self.encoding,
+        }
+    }
+
+    /// Codes produced by the current codebook, if `codes` are
+    pub fn current<'a>(&self, codes: Option<&'a Codes>) -> Option<&'a [u8]> {
+        codes.filter(|c| c.version == self.version).map(|c| c.codes.as_slice())
+    }
+}
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// Index of the centroid (rows of `d` values) closest to `point`
+fn nearest(centroids: &[f32], d: usize, point: &[f32]) -> usize {
+    centroids.chunks(d)
+        .map(|c| squared_distance(c, point))
+        .enumerate()
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Lloyd's k-means seeded with evenly spaced sample points, so training is
+/// deterministic; an empty cluster keeps its previous centroid
+fn kmeans(points: &[&[f32]], k: usize) -> Vec<f32> {
+    let d = points[0].len();
+    let mut centroids: Vec<f32> = (0..k)
+        .flat_map(|c| points[c * points.len() / k].iter().copied())
+        .collect();
+    let mut assignment = vec![usize::MAX; points.len()];
+
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut changed = false;
+        for (point, assigned) in points.iter().zip(assignment.iter_mut()) {
+            let closest = nearest(&centroids, d, point);
+            changed |= closest != *assigned;
+            *assigned = closest;
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![0f32; k * d];
+        let mut counts = vec![0u32; k];
+        for (point, &c) in points.iter().zip(&assignment) {
+            counts[c] += 1;
+            for (sum, x) in sums[c * d..(c + 1) * d].iter_mut().zip(point.iter()) {
+                *sum += x;
+            }
+        }
+        for (c, &count) in counts.iter().enumerate().filter(|(_, &n)| n > 0) {
+            for j in 0..d {
+                centroids[c * d + j] = sums[c * d + j] / count as f32;
+            }
+        }
+    }
+    centroids
+}
+
+impl Codebook {
+    pub fn train(mode: &QuantizationMode, sample: &[Vec<f32>], dim: usize) -> Result<Self, String> {
+        if sample.is_empty() {
+            return Err("No vectors to train on".to_string());
+        }
+        if sample.iter().any(|v| v.len() != dim) {
+            return Err(format!("Training vectors must have {} dimensions", dim));
+        }
+
+        match mode {
+            QuantizationMode::Int8 => {
+                let mut min = vec![f32::INFINITY; dim];
+                let mut max = vec![f32::NEG_INFINITY; dim];
+                for v in sample {
+                    for (i, &x) in v.iter().enumerate() {
+                        min[i] = min[i].min(x);
+                        max[i] = max[i].max(x);
+                    }
+                }
+                let scale = min.iter().zip(&max).map(|(lo, hi)| ((hi - lo) / 255.0).max(f32::EPSILON)).collect();
+                Ok(Codebook::Int8 { min, scale })
+            }
+            QuantizationMode::Product { subspaces, centroids } => {
+                let (m, k) = (*subspaces as usize, *centroids as usize);
+                if m == 0 || !dim.is_multiple_of(m) {
+                    return Err(format!("{} subspaces do not divide the dimension {}", m, dim));
+                }
+                if !(2..=256).contains(&k) {
+                    return Err("Product quantization needs 2-256 centroids".to_string());
+                }
+                if sample.len() < k {
+                    return Err(format!("Training {} centroids needs at least {} vectors, got {}", k, k, sample.len()));
+                }
+                let sub_dim = dim / m;
+                let mut codewords = Vec::with_capacity(m * k * sub_dim);
+                for s in 0..m {
+                    let points: Vec<&[f32]> = sample.iter().map(|v| &v[s * sub_dim..(s + 1) * sub_dim]).collect();
+                    codewords.extend(kmeans(&points, k));
+                }
+                Ok(Codebook::Product { sub_dim: sub_dim as u32, centroids: k as u32, codewords })
+            }
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        match self {
+            Codebook::Int8 { min, .. } => min.len(),
+            Codebook::Product { centroids, codewords, .. } => codewords.len() / *centroids as usize,
+        }
+    }
+
+    pub fn code_bytes(&self) -> usize {
+        match self {
+            Codebook::Int8 { min, .. } => min.len(),
+            Codebook::Product { sub_dim, .. } => self.dimension() / *sub_dim as usize,
+        }
+    }
+
+    pub fn encode(&self, vector: &[f32]) -> Vec<u8> {
+        match self {
+            Codebook::Int8 { min, scale } => vector.iter()
+                .zip(min.iter().zip(scale))
+                .map(|(x, (lo, s))| ((x - lo) / s).round().clamp(0.0, 255.0) as u8)
+                .collect(),
+            Codebook::Product { sub_dim, centroids, codewords } => {
+                let (d, k) = (*sub_dim as usize, *centroids as usize);
+                vector.chunks(d)
+                    .enumerate()
+                    .map(|(s, sub)| nearest(&codewords[s * k * d..(s + 1) * k * d], d, sub) as u8)
+                    .collect()
+            }
+        }
+    }
+
+    pub fn decode(&self, codes: &[u8]) -> Vec<f32> {
+        match self {
+            Codebook::Int8 { min, scale } => codes.iter()
+                .zip(min.iter().zip(scale))
+                .map(|(&c, (lo, s))| lo + c as f32 * s)
+                .collect(),
+            Codebook::Product { sub_dim, centroids, codewords } => {
+                let (d, k) = (*sub_dim as usize, *centroids as usize);
+                codes.iter()
+                    .enumerate()
+                    .flat_map(|(s, &c)| codewords[(s * k + c as usize) * d..(s * k + c as usize + 1) * d].iter().copied())
+                    .collect()
+            }
+        }
+    }
+
+    /// Prepare asymmetric scoring of codes against `query`
+    pub fn scorer<'a>(&'a self, query: &'a [f32]) -> Scorer<'a> {
+        // Product codes are scored from per-subspace tables of (query . centroid, |centroid|^2)
+        let tables = match self {
+            Codebook::Int8 { .. } => Vec::new(),
+            Codebook::Product { sub_dim, centroids, codewords } => {
+                let (d, k) = (*sub_dim as usize, *centroids as usize);
+                codewords.chunks(d)
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let q = &query[(i / k) * d..(i / k + 1) * d];
+                        (q.iter().zip(c).map(|(x, y)| x * y).sum(), c.iter().map(|y| y * y).sum())
+                    })
+                    .collect()
+            }
+        };
+        Scorer {
+            codebook: self,
+            query,
+            query_norm: query.iter().map(|x| x * x).sum::<f32>().sqrt(),
+            tables,
+        }
+    }
+}
+
+/// Cosine similarity between a full-precision query and quantized vectors
+pub struct Scorer<'a> {
+    codebook: &'a Codebook,
+    query: &'a [f32],
+    query_norm: f32,
+    tables: Vec<(f32, f32)>,
+}
+
+impl Scorer<'_> {
+    pub fn similarity(&self, codes: &[u8]) -> f32 {
+        if codes.len() != self.codebook.code_bytes() || self.query.len() != self.codebook.dimension() {
+            return 0.0;
+        }
+        let (dot, norm_sq) = match self.codebook {
+            Codebook::Int8 { min, scale } => codes.iter()
+                .zip(min.iter().zip(scale))
+                .zip(self.query)
+                .fold((0.0, 0.0), |(dot, norm), ((&c, (lo, s)), q)| {
+                    let x = lo + c as f32 * s;
+                    (dot + q * x, norm + x * x)
+                }),
+            Codebook::Product { centroids, .. } => codes.iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(dot, norm), (s, &c)| {
+                    let (d, n) = self.tables[s * *centroids as usize + c as usize];
+                    (dot + d, norm + n)
+                }),
+        };
+        if self.query_norm == 0.0 || norm_sq == 0.0 {
+            return 0.0;
+        }
+        dot / (self.query_norm * norm_sq.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random vectors (xorshift)
+    fn vectors(count: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut x = seed;
+        (0..count)
+            .map(|_| {
+                (0..dim)
+                    .map(|_| {
+                        x ^= x << 13;
+                        x ^= x >> 7;
+                        x ^= x << 17;
+                        (x % 2000) as f32 / 1000.0 - 1.0
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        dot / (a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt())
+    }
+
+    #[test]
+    fn test_int8_codes_approximate_cosine() {
+        let data = vectors(200, 32, 7);
+        let codebook = Codebook::train(&QuantizationMode::Int8, &data, 32).unwrap();
+        assert_eq!(codebook.code_bytes(), 32);
+
+        let query = &vectors(1, 32, 99)[0];
+        let scorer = codebook.scorer(query);
+        for v in &data {
+            let codes = codebook.encode(v);
+            let decoded = codebook.decode(&codes);
+            assert!(squared_distance(v, &decoded).sqrt() < 0.05);
+            assert!((scorer.similarity(&codes) - cosine(query, v)).abs() < 0.01);
+        }
+        assert!(Codebook::train(&QuantizationMode::Int8, &data, 16).is_err());
+        assert_eq!(scorer.similarity(&[0; 8]), 0.0);
+    }
+
+    #[test]
+    fn test_product_quantization_shortlist_keeps_nearest_neighbours() {
+        let data = vectors(400, 32, 3);
+        let mode = QuantizationMode::Product { subspaces: 8, centroids: 32 };
+        let codebook = Codebook::train(&mode, &data, 32).unwrap();
+        assert_eq!((codebook.code_bytes(), codebook.dimension()), (8, 32));
+        let codes: Vec<Vec<u8>> = data.iter().map(|v| codebook.encode(v)).collect();
+
+        // The exact nearest neighbour is in the re-rank shortlist of the code scores
+        for query in vectors(10, 32, 11) {
+            let scorer = codebook.scorer(&query);
+            let mut approx: Vec<(usize, f32)> = codes.iter().map(|c| scorer.similarity(c)).enumerate().collect();
+            approx.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            let best = (0..data.len())
+                .max_by(|&a, &b| cosine(&query, &data[a]).partial_cmp(&cosine(&query, &data[b])).unwrap())
+                .unwrap();
+            assert!(approx.iter().take(10 * RERANK_FACTOR).any(|(i, _)| *i == best));
+        }
+
+        assert!(Codebook::train(&QuantizationMode::Product { subspaces: 5, centroids: 32 }, &data, 32).is_err());
+        assert!(Codebook::train(&QuantizationMode::Product { subspaces: 8, centroids: 300 }, &data, 32).is_err());
+        assert!(Codebook::train(&mode, &data[..10], 32).is_err());
+    }
+
+    #[test]
+    fn test_code_storage_roundtrip() {
+        let codes = Codes { version: 3, codes: vec![1, 2, 255] };
+        assert_eq!(Codes::from_bytes(codes.to_bytes()), codes);
+        let full = FullVector(vec![0.5, -1.25, 3.0]);
+        assert_eq!(FullVector::from_bytes(full.to_bytes()), full);
+
+        let state = QuantState {
+            mode: QuantizationMode::Int8,
+            codebook: Codebook::Int8 { min: vec![0.0], scale: vec![1.0] },
+            version: 2,
+            sample_size: 1,
+            trained_at: 0,
+            encoding: false,
+            cursor: None,
+        };
+        let stale = Codes { version: 1, codes: vec![7] };
+        assert_eq!(state.current(Some(&stale)), None);
+        assert_eq!(state.current(Some(&Codes { version: 2, codes: vec![7] })), Some(&[7u8][..]));
+    }
+}
@@ -0,0 +1,697 @@
+//! Metadata Indexes and Lexical Search
+//!
+//! Inverted indexes kept in stable memory next to the vectors, so filtered and
+//! keyword queries do not scan the whole shard. Every index and the BM25 statistics
+//! are keyed by namespace first, so one tenant's documents neither skew another's
+//! scores nor lengthen its lookups:
+//!
+//! - Equality index: (namespace, key, value) -> vector ids
+//! - Numeric index: (namespace, field, value) -> vector ids, for `timestamp`,
+//!   `importance` and metadata values that parse as numbers (range filters)
+//! - BM25 postings over the `content` metadata field: (namespace, term) ->
+//!   (vector id, term frequency), with document frequencies and lengths per namespace
+//!
+//! A `Filter` (and/or/not over equality, set and range tests) is first turned into a
+//! candidate id set from the indexes, then checked against each candidate's metadata.
+//! Vector and BM25 rankings are merged with reciprocal-rank fusion.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::Storable;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::namespace;
+use crate::{StorableString, VectorEmbedding, META_INDEX, NUMERIC_INDEX, SEARCH_STATE, TEXT_DOCS, TEXT_POSTINGS, TEXT_STATS, TEXT_TERMS, VECTORS};
+
+/// Metadata field holding a vector's text for BM25
+pub const CONTENT_FIELD: &str = "content";
+/// Longer metadata values are not put in the equality index (filters on them scan)
+const MAX_INDEXED_VALUE_LEN: usize = 256;
+const MAX_TERM_LEN: usize = 64;
+/// Index lookups yielding more ids than this are dropped in favour of checking the filter per vector
+const MAX_INDEX_CANDIDATES: usize = 50_000;
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+/// Reciprocal-rank fusion constant (score = sum of 1 / (k + rank))
+pub const DEFAULT_RRF_K: u32 = 60;
+/// Layout of the index keys; indexes of an older layout are dropped and rebuilt
+const INDEX_VERSION: u32 = 2; // 2: keyed by namespace
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Boolean metadata filter. `Range` bounds are inclusive and apply to `timestamp`,
+/// `importance` or any metadata key whose value is numeric.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Filter {
+    Eq { key: String, value: String },
+    In { key: String, values: Vec<String> },
+    Range { field: String, min: Option<f64>, max: Option<f64> },
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SearchState {
+    pub initialized: bool,
+    pub building: bool,               // Backfill of pre-existing vectors in progress
+    pub build_cursor: Option<String>, // Last vector id visited by the backfill
+    pub version: Option<u32>,         // INDEX_VERSION the indexes were built with (None = 1)
+}
+
+/// BM25 statistics of one namespace
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TextStats {
+    pub docs: u64,   // Vectors with a content field
+    pub length: u64, // Total content terms, for the average document length
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SearchIndexStatus {
+    pub ready: bool,
+    pub text_docs: u64,
+    pub distinct_terms: u64,
+    pub avg_doc_length: f32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MetaKey {
+    pub namespace: String,
+    pub key: String,
+    pub value: String,
+    pub id: String,
+}
+
+/// `value` is an order-preserving encoding of the f64 (see `sortable`)
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NumKey {
+    pub namespace: String,
+    pub field: String,
+    pub value: u64,
+    pub id: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TermKey {
+    pub namespace: String,
+    pub term: String,
+    pub id: String,
+}
+
+/// Document-frequency key of a term
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DfKey {
+    pub namespace: String,
+    pub term: String,
+}
+
+impl Storable for SearchState {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for MetaKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for NumKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for TermKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for DfKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Storable for TextStats {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound =
+        ic_stable_structures::storable::Bound::Unbounded;
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn state() -> SearchState {
+    SEARCH_STATE.with(|s| s.borrow().get().clone())
+}
+
+fn set_state(state: SearchState) {
+    SEARCH_STATE.with(|s| s.borrow_mut().set(state).expect("Failed to save search state"));
+}
+
+fn text_stats(namespace: &str) -> TextStats {
+    TEXT_STATS.with(|t| t.borrow().get(&StorableString(namespace.to_string()))).unwrap_or_default()
+}
+
+fn set_text_stats(namespace: &str, stats: TextStats) {
+    TEXT_STATS.with(|t| {
+        let key = StorableString(namespace.to_string());
+        if stats.docs == 0 {
+            t.borrow_mut().remove(&key);
+        } else {
+            t.borrow_mut().insert(key, stats);
+        }
+    });
+}
+
+/// Map an f64 onto a u64 with the same ordering (negative values flip all bits)
+fn sortable(value: f64) -> u64 {
+    let bits = value.to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | (1 << 63)
+    }
+}
+
+/// Lowercased alphanumeric words
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty() && w.len() <= MAX_TERM_LEN)
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+fn content(embedding: &VectorEmbedding) -> Option<&str> {
+    embedding.metadata.iter().find(|(k, _)| k == CONTENT_FIELD).map(|(_, v)| v.as_str())
+}
+
+fn indexable(key: &str, value: &str) -> bool {
+    key != CONTENT_FIELD && value.len() <= MAX_INDEXED_VALUE_LEN
+}
+
+/// Numeric values of a field: the built-in `timestamp`/`importance`, else the
+/// metadata values under that key that parse as numbers
+fn numeric_values(embedding: &VectorEmbedding, field: &str) -> Vec<f64> {
+    match field {
+        "timestamp" => vec![embedding.timestamp as f64],
+        "importance" => vec![embedding.importance as f64],
+        _ => embedding.metadata.iter()
+            .filter(|(k, _)| k == field)
+            .filter_map(|(_, v)| v.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+            .collect(),
+    }
+}
+
+fn numeric_entries(namespace: &str, id: &str, embedding: &VectorEmbedding) -> Vec<NumKey> {
+    let mut fields: Vec<&str> = vec!["timestamp", "importance"];
+    fields.extend(embedding.metadata.iter().map(|(k, _)| k.as_str()).filter(|k| *k != CONTENT_FIELD && *k != "timestamp" && *k != "importance"));
+    fields.sort_unstable();
+    fields.dedup();
+    fields.into_iter()
+        .flat_map(|field| numeric_values(embedding, field).into_iter().map(move |v| NumKey {
+            namespace: namespace.to_string(),
+            field: field.to_string(),
+            value: sortable(v),
+            id: id.to_string(),
+        }))
+        .collect()
+}
+
+fn term_frequencies(text: &str) -> HashMap<String, u32> {
+    let mut tf = HashMap::new();
+    for term in tokenize(text) {
+        *tf.entry(term).or_insert(0) += 1;
+    }
+    tf
+}
+
+/// BM25 contribution of one term to one document
+pub fn bm25(tf: u32, df: u64, doc_len: u32, avg_len: f32, docs: u64) -> f32 {
+    let idf = (1.0 + (docs as f32 - df as f32 + 0.5) / (df as f32 + 0.5)).ln();
+    let tf = tf as f32;
+    let norm = 1.0 - BM25_B + BM25_B * doc_len as f32 / avg_len.max(1.0);
+    idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm)
+}
+
+/// Reciprocal-rank fusion of ranked id lists, best first
+pub fn rrf(rankings: &[Vec<String>], k: u32) -> Vec<(String, f32)> {
+    let mut scores: HashMap<&str, f32> = HashMap::new();
+    for ranking in rankings {
+        for (rank, id) in ranking.iter().enumerate() {
+            *scores.entry(id.as_str()).or_insert(0.0) += 1.0 / (k as f32 + rank as f32 + 1.0);
+        }
+    }
+    let mut fused: Vec<(String, f32)> = scores.into_iter().map(|(id, s)| (id.to_string(), s)).collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    fused
+}
+
+// ============================================================================
+// Filters
+// ============================================================================
+
+/// Evaluate a filter against one vector
+pub fn matches(filter: &Filter, embedding: &VectorEmbedding) -> bool {
+    match filter {
+        Filter::Eq { key, value } => embedding.metadata.iter().any(|(k, v)| k == key && v == value),
+        Filter::In { key, values } => embedding.metadata.iter().any(|(k, v)| k == key && values.contains(v)),
+        Filter::Range { field, min, max } => numeric_values(embedding, field).iter()
+            .any(|v| min.map(|m| *v >= m).unwrap_or(true) && max.map(|m| *v <= m).unwrap_or(true)),
+        Filter::And(filters) => filters.iter().all(|f| matches(f, embedding)),
+        Filter::Or(filters) => filters.iter().any(|f| matches(f, embedding)),
+        Filter::Not(filter) => !matches(filter, embedding),
+    }
+}
+
+fn equal_ids(namespace: &str, key: &str, value: &str) -> Option<BTreeSet<String>> {
+    if !indexable(key, value) {
+        return None;
+    }
+    let start = MetaKey { namespace: namespace.to_string(), key: key.to_string(), value: value.to_string(), id: String::new() };
+    META_INDEX.with(|m| {
+        let mut ids = BTreeSet::new();
+        for (k, _) in m.borrow().range(start..) {
+            if k.namespace != namespace || k.key != key || k.value != value {
+                break;
+            }
+            if ids.len() >= MAX_INDEX_CANDIDATES {
+                return None;
+            }
+            ids.insert(k.id);
+        }
+        Some(ids)
+    })
+}
+
+fn range_ids(namespace: &str, field: &str, min: Option<f64>, max: Option<f64>) -> Option<BTreeSet<String>> {
+    let start = NumKey { namespace: namespace.to_string(), field: field.to_string(), value: min.map(sortable).unwrap_or(0), id: String::new() };
+    let end = max.map(sortable).unwrap_or(u64::MAX);
+    NUMERIC_INDEX.with(|n| {
+        let mut ids = BTreeSet::new();
+        for (k, _) in n.borrow().range(start..) {
+            if k.namespace != namespace || k.field != field || k.value > end {
+                break;
+            }
+            if ids.len() >= MAX_INDEX_CANDIDATES {
+                return None;
+            }
+            ids.insert(k.id);
+        }
+        Some(ids)
+    })
+}
+
+/// Storage keys in `namespace` that can match the filter, from the indexes; None when
+/// the indexes cannot narrow it down (e.g. a bare `Not`) and every vector has to be
+/// checked. The set may include non-matching ids, so candidates are still checked
+/// with `matches`.
+pub fn candidates(namespace: &str, filter: &Filter) -> Option<BTreeSet<String>> {
+    if !is_ready() {
+        return None;
+    }
+    candidate_ids(namespace, filter)
+}
+
+fn candidate_ids(namespace: &str, filter: &Filter) -> Option<BTreeSet<String>> {
+    match filter {
+        Filter::Eq { key, value } => equal_ids(namespace, key, value),
+        Filter::In { key, values } => {
+            let mut ids = BTreeSet::new();
+            for value in values {
+                ids.extend(equal_ids(namespace, key, value)?);
+            }
+            Some(ids)
+        }
+        Filter::Range { field, min, max } => range_ids(namespace, field, *min, *max),
+        Filter::And(filters) => filters.iter()
+            .filter_map(|f| candidate_ids(namespace, f))
+            .reduce(|a, b| a.intersection(&b).cloned().collect()),
+        Filter::Or(filters) => {
+            let mut ids = BTreeSet::new();
+            for f in filters {
+                ids.extend(candidate_ids(namespace, f)?);
+            }
+            Some(ids)
+        }
+        Filter::Not(_) => None,
+    }
+}
+
+// ============================================================================
+// Index Maintenance
+// ============================================================================
+
+/// Mark the indexes initialized; vectors stored before they existed, or indexed with
+/// an older key layout, are backfilled by `build_step`
+pub fn ensure_initialized(existing_vectors: u64) {
+    let mut s = state();
+    if s.initialized && s.version == Some(INDEX_VERSION) {
+        return;
+    }
+    if s.initialized {
+        META_INDEX.with(|m| m.borrow_mut().clear_new());
+        NUMERIC_INDEX.with(|n| n.borrow_mut().clear_new());
+        TEXT_POSTINGS.with(|p| p.borrow_mut().clear_new());
+        TEXT_TERMS.with(|t| t.borrow_mut().clear_new());
+        TEXT_DOCS.with(|d| d.borrow_mut().clear_new());
+        TEXT_STATS.with(|t| t.borrow_mut().clear_new());
+    }
+    s.initialized = true;
+    s.version = Some(INDEX_VERSION);
+    s.building = existing_vectors > 0;
+    s.build_cursor = None;
+    set_state(s);
+}
+
+pub fn is_ready() -> bool {
+    let s = state();
+    s.initialized && !s.building
+}
+
+fn is_indexed(id: &str) -> bool {
+    TEXT_DOCS.with(|d| d.borrow().contains_key(&StorableString(id.to_string())))
+}
+
+/// Add a vector's metadata and content to its namespace's indexes under its storage key
+pub fn index(key: &str, embedding: &VectorEmbedding) {
+    let id = &key.to_string();
+    let ns = namespace::of_key(key);
+    META_INDEX.with(|m| {
+        let mut index = m.borrow_mut();
+        for (key, value) in embedding.metadata.iter().filter(|(k, v)| indexable(k, v)) {
+            index.insert(MetaKey { namespace: ns.to_string(), key: key.clone(), value: value.clone(), id: id.clone() }, ());
+        }
+    });
+    NUMERIC_INDEX.with(|n| {
+        let mut index = n.borrow_mut();
+        for key in numeric_entries(ns, id, embedding) {
+            index.insert(key, ());
+        }
+    });
+
+    let tf = content(embedding).map(term_frequencies).unwrap_or_default();
+    let length: u32 = tf.values().sum();
+    for (term, count) in &tf {
+        TEXT_POSTINGS.with(|p| p.borrow_mut().insert(TermKey { namespace: ns.to_string(), term: term.clone(), id: id.clone() }, *count));
+        TEXT_TERMS.with(|t| {
+            let key = DfKey { namespace: ns.to_string(), term: term.clone() };
+            let df = t.borrow().get(&key).unwrap_or(0);
+            t.borrow_mut().insert(key, df + 1);
+        });
+    }
+    // Every indexed vector gets a length entry, which also marks it as indexed
+    TEXT_DOCS.with(|d| d.borrow_mut().insert(StorableString(id.clone()), length));
+    if !tf.is_empty() {
+        let mut stats = text_stats(ns);
+        stats.docs += 1;
+        stats.length += length as u64;
+        set_text_stats(ns, stats);
+    }
+}
+
+/// Remove a vector from the indexes (no-op if it was never indexed)
+pub fn unindex(key: &str, embedding: &VectorEmbedding) {
+    let id = &key.to_string();
+    let length = match TEXT_DOCS.with(|d| d.borrow_mut().remove(&StorableString(id.clone()))) {
+        Some(length) => length,
+        None => return,
+    };
+    let ns = namespace::of_key(key);
+    META_INDEX.with(|m| {
+        let mut index = m.borrow_mut();
+        for (key, value) in &embedding.metadata {
+            index.remove(&MetaKey { namespace: ns.to_string(), key: key.clone(), value: value.clone(), id: id.clone() });
+        }
+    });
+    NUMERIC_INDEX.with(|n| {
+        let mut index = n.borrow_mut();
+        for key in numeric_entries(ns, id, embedding) {
+            index.remove(&key);
+        }
+    });
+
+    let tf = content(embedding).map(term_frequencies).unwrap_or_default();
+    for term in tf.keys() {
+        TEXT_POSTINGS.with(|p| p.borrow_mut().remove(&TermKey { namespace: ns.to_string(), term: term.clone(), id: id.clone() }));
+        TEXT_TERMS.with(|t| {
+            let key = DfKey { namespace: ns.to_string(), term: term.clone() };
+            let df = t.borrow().get(&key).unwrap_or(0);
+            if df <= 1 {
+                t.borrow_mut().remove(&key);
+            } else {
+                t.borrow_mut().insert(key, df - 1);
+            }
+        });
+    }
+    if !tf.is_empty() {
+        let mut stats = text_stats(ns);
+        stats.docs = stats.docs.saturating_sub(1);
+        stats.length = stats.length.saturating_sub(length as u64);
+        set_text_stats(ns, stats);
+    }
+}
+
+/// Index up to `batch` vectors that were stored before the indexes existed
+pub fn build_step(batch: usize) -> SearchIndexStatus {
+    let s = state();
+    if s.building {
+        let start = s.build_cursor.clone().unwrap_or_default();
+        let pending: Vec<(String, VectorEmbedding)> = VECTORS.with(|v| {
+            v.borrow()
+                .range(StorableString(start.clone())..)
+                .filter(|(k, _)| s.build_cursor.is_none() || k.0 != start)
+                .take(batch)
+                .map(|(k, e)| (k.0, e))
+                .collect()
+        });
+
+        for (key, embedding) in &pending {
+            if !is_indexed(key) {
+                index(key, embedding);
+            }
+        }
+
+        let mut s = state();
+        if pending.len() < batch {
+            s.building = false;
+            s.build_cursor = None;
+        } else {
+            s.build_cursor = pending.last().map(|(key, _)| key.clone());
+        }
+        set_state(s);
+    }
+    status()
+}
+
+/// Shard-wide totals; `distinct_terms` counts each namespace's terms separately
+pub fn status() -> SearchIndexStatus {
+    let s = state();
+    let (docs, length) = TEXT_STATS.with(|t| {
+        t.borrow().iter().fold((0, 0), |(docs, length), (_, stats)| (docs + stats.docs, length + stats.length))
+    });
+    SearchIndexStatus {
+        ready: s.initialized && !s.building,
+        text_docs: docs,
+        distinct_terms: TEXT_TERMS.with(|t| t.borrow().len()),
+        avg_doc_length: length as f32 / docs.max(1) as f32,
+    }
+}
+
+// ============================================================================
+// Lexical Search
+// ============================================================================
+
+/// BM25 scores of every vector of `namespace` whose content contains a query term,
+/// by storage key, best first
+pub fn lexical(namespace: &str, query: &str) -> Vec<(String, f32)> {
+    let stats = text_stats(namespace);
+    let avg_len = stats.length as f32 / stats.docs.max(1) as f32;
+    let mut terms = tokenize(query);
+    terms.sort_unstable();
+    terms.dedup();
+
+    let mut scores: HashMap<String, f32> = HashMap::new();
+    for term in terms {
+        let df_key = DfKey { namespace: namespace.to_string(), term: term.clone() };
+        let df = TEXT_TERMS.with(|t| t.borrow().get(&df_key)).unwrap_or(0) as u64;
+        if df == 0 {
+            continue;
+        }
+        let postings: Vec<(String, u32)> = TEXT_POSTINGS.with(|p| {
+            p.borrow()
+                .range(TermKey { namespace: namespace.to_string(), term: term.clone(), id: String::new() }..)
+                .take_while(|(k, _)| k.namespace == namespace && k.term == term)
+                .map(|(k, tf)| (k.id, tf))
+                .collect()
+        });
+        for (id, tf) in postings {
+            let doc_len = TEXT_DOCS.with(|d| d.borrow().get(&StorableString(id.clone()))).unwrap_or(0);
+            *scores.entry(id).or_insert(0.0) += bm25(tf, df, doc_len, avg_len, stats.docs);
+        }
+    }
+
+    let mut ranked: Vec<(String, f32)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embedding(id: &str, content: &str, tag: &str, timestamp: u64, importance: f32) -> VectorEmbedding {
+        VectorEmbedding {
+            id: id.to_string(),
+            vector: vec![0.0; 4],
+            metadata: vec![
+                ("content".to_string(), content.to_string()),
+                ("tag".to_string(), tag.to_string()),
+                ("score".to_string(), format!("{}", importance * 10.0)),
+            ],
+            timestamp,
+            importance,
+            shard_id: 0,
+            namespace: None,
+        }
+    }
+
+    fn store(embeddings: &[VectorEmbedding]) {
+        ensure_initialized(0);
+        for e in embeddings {
+            VECTORS.with(|v| v.borrow_mut().insert(StorableString(e.id.clone()), e.clone()));
+            index(&e.id, e);
+        }
+    }
+
+    fn ids(set: Option<BTreeSet<String>>) -> Vec<String> {
+        set.unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn test_bm25_ranks_by_term_rarity_and_frequency() {
+        store(&[
+            embedding("a", "The queen bee rules the hive", "bee", 1, 0.5),
+            embedding("b", "Honey honey honey from the hive", "bee", 2, 0.5),
+            embedding("c", "Market prices for ICP tokens", "finance", 3, 0.5),
+        ]);
+        let ranked = lexical(namespace::DEFAULT_NAMESPACE, "honey hive");
+        assert_eq!(ranked.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["b", "a"]);
+        assert!(lexical(namespace::DEFAULT_NAMESPACE, "the").iter().all(|(id, _)| id != "c"));
+        assert_eq!(tokenize("Hello, World! ICP-2"), vec!["hello", "world", "icp", "2"]);
+
+        // Removing a document updates postings and document frequencies
+        let b = VECTORS.with(|v| v.borrow().get(&StorableString("b".into()))).unwrap();
+        unindex("b", &b);
+        assert_eq!(lexical(namespace::DEFAULT_NAMESPACE, "honey"), vec![]);
+        assert_eq!(status().text_docs, 2);
+    }
+
+    #[test]
+    fn test_filters_narrow_candidates_through_indexes() {
+        let data = [
+            embedding("a", "x", "bee", 100, 0.2),
+            embedding("b", "x", "bee", 200, 0.9),
+            embedding("c", "x", "finance", 300, 0.9),
+            embedding("d", "x", "news", 400, -0.5),
+        ];
+        store(&data);
+
+        let bee = Filter::Eq { key: "tag".into(), value: "bee".into() };
+        let recent = Filter::Range { field: "timestamp".into(), min: Some(150.0), max: None };
+        assert_eq!(ids(candidates(namespace::DEFAULT_NAMESPACE, &Filter::And(vec![bee.clone(), recent.clone()]))), vec!["b"]);
+        assert_eq!(ids(candidates(namespace::DEFAULT_NAMESPACE, &Filter::In { key: "tag".into(), values: vec!["news".into(), "finance".into()] })), vec!["c", "d"]);
+        // Numeric metadata values, including negatives, sort correctly
+        assert_eq!(ids(candidates(namespace::DEFAULT_NAMESPACE, &Filter::Range { field: "score".into(), min: Some(-10.0), max: Some(2.0) })), vec!["a", "d"]);
+        assert_eq!(ids(candidates(namespace::DEFAULT_NAMESPACE, &Filter::Range { field: "importance".into(), min: None, max: Some(0.0) })), vec!["d"]);
+
+        // Negation cannot be answered from the index alone but is checked per vector
+        let not_bee = Filter::And(vec![recent, Filter::Not(Box::new(bee.clone()))]);
+        assert_eq!(ids(candidates(namespace::DEFAULT_NAMESPACE, &not_bee)), vec!["b", "c", "d"]);
+        let matching: Vec<&str> = data.iter().filter(|e| matches(&not_bee, e)).map(|e| e.id.as_str()).collect();
+        assert_eq!(matching, vec!["c", "d"]);
+        assert!(candidates(namespace::DEFAULT_NAMESPACE, &Filter::Not(Box::new(bee))).is_none());
+    }
+
+    #[test]
+    fn test_namespaces_have_separate_indexes_and_statistics() {
+        ensure_initialized(0);
+        let mut other = embedding("x", "honey honey honey honey", "bee", 1, 0.5);
+        other.namespace = Some("tenant".to_string());
+        let key = namespace::storage_key("tenant", "x");
+        index(&key, &other);
+        store(&[embedding("mine", "Honey from the hive", "bee", 1, 0.5)]);
+
+        // The tenant's document is neither returned nor counted in the default namespace
+        let ranked = lexical(namespace::DEFAULT_NAMESPACE, "honey");
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].0, "mine");
+        assert_eq!(text_stats(namespace::DEFAULT_NAMESPACE).docs, 1);
+        assert_eq!(lexical("tenant", "honey")[0].0, key);
+
+        let bee = Filter::Eq { key: "tag".into(), value: "bee".into() };
+        assert_eq!(ids(candidates("tenant", &bee)), vec![key.clone()]);
+        assert_eq!(ids(candidates(namespace::DEFAULT_NAMESPACE, &bee)), vec!["mine"]);
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let vector = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let lexical = vec!["c".to_string(), "a".to_string()];
+        let fused = rrf(&[vector, lexical], DEFAULT_RRF_K);
+        // "a" is near the top of both lists; "b" appears in only one
+        assert_eq!(fused.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["a", "c", "b"]);
+        assert!((fused[0].1 - (1.0 / 61.0 + 1.0 / 62.0)).abs() < 1e-6);
+        assert!(sortable(-1.0) < sortable(0.0) && sortable(0.0) < sortable(0.5));
+    }
+}
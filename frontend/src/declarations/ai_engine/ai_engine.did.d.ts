@@ -3,7 +3,10 @@ import type { ActorMethod } from '@dfinity/agent';
 import type { IDL } from '@dfinity/candid';
 
 export interface AIConfig {
+  'openai_api_key' : string,
   'admin' : Principal,
+  'huggingface_api_key' : string,
+  'perplexity_api_key' : string,
   'cache_duration_ns' : bigint,
   'max_requests_per_minute' : number,
 }
@@ -12,12 +15,43 @@ export interface AgentMemory {
   'context_window' : Array<string>,
   'context_size' : bigint,
 }
+export type Aggregate = { 'CountByType' : null } |
+  { 'CountByProperty' : string } |
+  { 'Count' : null };
+export interface AggregateRow {
+  'key' : string,
+  'count' : bigint,
+  'total_weight' : number,
+  'min_hops' : number,
+}
 export interface AlternativeRoute {
   'name' : string,
   'duration_hours' : number,
   'notes' : string,
   'distance_miles' : number,
 }
+export type CompareOp = { 'Eq' : null } |
+  { 'Gt' : null } |
+  { 'Lt' : null } |
+  { 'Gte' : null } |
+  { 'Lte' : null } |
+  { 'Contains' : null } |
+  { 'NotEq' : null } |
+  { 'Exists' : null };
+export interface ConsolidationConfig {
+  'max_summary_sentences' : number,
+  'summarizer' : Summarizer,
+  'similarity_threshold' : number,
+  'max_cluster_size' : number,
+  'min_age_ns' : bigint,
+  'min_cluster_size' : number,
+}
+export interface ConsolidationReport {
+  'summary_ids' : Array<string>,
+  'council_sessions' : Array<string>,
+  'archived' : number,
+  'clusters' : number,
+}
 export interface CouncilConfig {
   'review_enabled' : boolean,
   'members' : Array<LLMProvider>,
@@ -73,6 +107,17 @@ export interface ETAPrediction {
   'factors' : Array<string>,
   'confidence' : number,
 }
+export type EdgeDirection = { 'Both' : null } |
+  { 'Outgoing' : null } |
+  { 'Incoming' : null };
+export interface EdgePattern {
+  'max_weight' : [] | [number],
+  'max_hops' : number,
+  'direction' : EdgeDirection,
+  'min_hops' : number,
+  'min_weight' : [] | [number],
+  'relationships' : Array<string>,
+}
 export interface FuelOptimization {
   'potential_savings' : number,
   'recommended_stops' : Array<FuelStop>,
@@ -85,8 +130,29 @@ export interface FuelStop {
   'location' : string,
   'distance_from_start' : number,
 }
+export type GrantStatus = { 'Active' : null } |
+  { 'Declined' : null } |
+  { 'Revoked' : null } |
+  { 'Expired' : null } |
+  { 'Pending' : null };
+export interface GraphQuery {
+  'limit' : [] | [number],
+  'start' : NodePattern,
+  'steps' : Array<PathStep>,
+  'aggregate' : [] | [Aggregate],
+}
+export interface KnowledgeEdge {
+  'id' : string,
+  'weight' : number,
+  'relationship' : string,
+  'properties' : Array<[string, string]>,
+  'source_id' : string,
+  'target_id' : string,
+  'created_at' : bigint,
+}
 export interface KnowledgeNode {
   'id' : string,
+  'provenance' : [] | [Provenance],
   'updated_at' : bigint,
   'node_type' : NodeType,
   'properties' : Array<[string, string]>,
@@ -113,6 +179,7 @@ export interface LLMResponse {
 }
 export interface Memory {
   'id' : string,
+  'provenance' : [] | [Provenance],
   'content' : string,
   'memory_type' : MemoryType,
   'metadata' : Array<[string, string]>,
@@ -125,26 +192,54 @@ export interface Memory {
   'embedding' : [] | [Array<number>],
   'access_count' : number,
 }
-/**
- * Memory Types
- */
 export type MemoryType = { 'Episodic' : null } |
   { 'Procedural' : null } |
   { 'ShortTerm' : null } |
   { 'LongTerm' : null } |
   { 'Semantic' : null };
+export interface NodePattern {
+  'id' : [] | [string],
+  'node_type' : [] | [NodeType],
+  'properties' : Array<PropertyFilter>,
+  'label' : [] | [string],
+}
 export type NodeType = { 'Event' : null } |
   { 'Entity' : null } |
   { 'Action' : null } |
   { 'Attribute' : null } |
   { 'Concept' : null };
-/**
- * LLM Council Types
- */
+export interface PathStep { 'edge' : EdgePattern, 'node' : NodePattern }
+export interface PropertyFilter {
+  'op' : CompareOp,
+  'key' : string,
+  'value' : string,
+}
+export interface Provenance {
+  'revoked' : boolean,
+  'source_id' : string,
+  'source_version' : bigint,
+  'source_agent' : string,
+  'grant_id' : string,
+  'source_owner' : string,
+  'copied_at' : bigint,
+}
 export type QueryPriority = { 'Low' : null } |
   { 'High' : null } |
   { 'Normal' : null } |
   { 'Critical' : null };
+export interface QueryResult {
+  'total_matches' : bigint,
+  'aggregates' : Array<AggregateRow>,
+  'truncated' : boolean,
+  'rows' : Array<QueryRow>,
+}
+export interface QueryRow {
+  'hops' : number,
+  'path' : Array<string>,
+  'total_weight' : number,
+  'edges' : Array<string>,
+  'nodes' : Array<KnowledgeNode>,
+}
 export interface ResponseReview {
   'insight_score' : number,
   'overall_rank' : number,
@@ -154,6 +249,8 @@ export interface ResponseReview {
   'completeness_score' : number,
   'accuracy_score' : number,
 }
+export type RevokeAction = { 'Flag' : null } |
+  { 'Remove' : null };
 export interface RouteOptimization {
   'fuel_cost_estimate' : number,
   'destination' : string,
@@ -167,7 +264,61 @@ export interface RouteOptimization {
   'distance_miles' : number,
   'recommended_stops' : Array<string>,
 }
+export interface ShareGrant {
+  'status' : GrantStatus,
+  'copied_memory_ids' : Array<string>,
+  'to_agent' : string,
+  'copied_edge_ids' : Array<string>,
+  'to_owner' : string,
+  'from_agent' : string,
+  'owner' : string,
+  'on_expiry' : RevokeAction,
+  'mode' : ShareMode,
+  'include_edges' : boolean,
+  'created_at' : bigint,
+  'copied_node_ids' : Array<string>,
+  'ended_at' : [] | [bigint],
+  'expires_at' : [] | [bigint],
+  'grant_id' : string,
+  'memory_ids' : Array<string>,
+  'node_ids' : Array<string>,
+}
+export interface ShareListing {
+  'shared' : Array<ShareGrant>,
+  'received' : Array<ShareGrant>,
+}
+export type ShareMode = { 'Copy' : null } |
+  { 'Read' : null };
+export interface ShareRequest {
+  'to_agent' : string,
+  'to_owner' : string,
+  'from_agent' : string,
+  'on_expiry' : RevokeAction,
+  'mode' : ShareMode,
+  'include_edges' : boolean,
+  'expires_at' : [] | [bigint],
+  'memory_ids' : Array<string>,
+  'node_ids' : Array<string>,
+}
+export interface SharedKnowledge {
+  'edges' : Array<KnowledgeEdge>,
+  'memories' : Array<Memory>,
+  'nodes' : Array<KnowledgeNode>,
+}
+export type Summarizer = { 'LlmCouncil' : null } |
+  { 'RuleBased' : null };
+export interface WeightedPath {
+  'hops' : number,
+  'total_weight' : number,
+  'edge_ids' : Array<string>,
+  'node_ids' : Array<string>,
+}
 export interface _SERVICE {
+  'accept_share' : ActorMethod<
+    [string],
+    { 'Ok' : ShareGrant } |
+      { 'Err' : string }
+  >,
   'add_context' : ActorMethod<
     [string, string],
     { 'Ok' : string } |
@@ -193,28 +344,40 @@ export interface _SERVICE {
     { 'Ok' : string } |
       { 'Err' : string }
   >,
-  /**
-   * Cache Management
-   */
+  'admin_set_api_keys' : ActorMethod<
+    [string, string, string],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'cancel_memory_maintenance' : ActorMethod<
+    [string],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
   'clear_cache' : ActorMethod<[], { 'Ok' : bigint } | { 'Err' : string }>,
-  /**
-   * LLM Council API
-   */
   'create_council_query' : ActorMethod<
     [string, string],
     { 'Ok' : string } |
       { 'Err' : string }
   >,
+  'decline_share' : ActorMethod<
+    [string],
+    { 'Ok' : ShareGrant } |
+      { 'Err' : string }
+  >,
   'finalize_council_response' : ActorMethod<
     [string, string, string],
     { 'Ok' : CouncilResult } |
       { 'Err' : string }
   >,
   'find_knowledge_node' : ActorMethod<[string, string], [] | [KnowledgeNode]>,
-  /**
-   * Agent Memory API
-   */
+  'find_knowledge_path' : ActorMethod<
+    [string, string, string, Array<string>, EdgeDirection, [] | [number]],
+    { 'Ok' : [] | [WeightedPath] } |
+      { 'Err' : string }
+  >,
   'get_agent_memory' : ActorMethod<[string], AgentMemory>,
+  'get_archived_memories' : ActorMethod<[string, string], Array<Memory>>,
   'get_cache_stats' : ActorMethod<[], [bigint, bigint]>,
   'get_cached_route' : ActorMethod<[string, string], [] | [RouteOptimization]>,
   'get_chairman_prompt' : ActorMethod<
@@ -222,36 +385,76 @@ export interface _SERVICE {
     { 'Ok' : string } |
       { 'Err' : string }
   >,
-  /**
-   * Config
-   */
   'get_config' : ActorMethod<[], AIConfig>,
   'get_context' : ActorMethod<[string], string>,
   'get_council_config' : ActorMethod<[], CouncilConfig>,
   'get_council_session' : ActorMethod<[string], [] | [CouncilSession]>,
   'get_memory_stats' : ActorMethod<[string], [bigint, bigint, bigint]>,
   'health' : ActorMethod<[], string>,
+  'list_shares' : ActorMethod<[string], ShareListing>,
   'maintain_memory' : ActorMethod<
     [string],
-    { 'Ok' : string } |
+    { 'Ok' : ConsolidationReport } |
+      { 'Err' : string }
+  >,
+  'optimize_fuel' : ActorMethod<
+    [string, string, number],
+    { 'Ok' : FuelOptimization } |
       { 'Err' : string }
   >,
-  'optimize_fuel' : ActorMethod<[string, string, number], FuelOptimization>,
-  /**
-   * Route Optimization
-   */
   'optimize_route' : ActorMethod<
     [string, string],
     { 'Ok' : RouteOptimization } |
       { 'Err' : string }
   >,
-  'predict_eta' : ActorMethod<[string, string, [] | [string]], ETAPrediction>,
+  'predict_eta' : ActorMethod<
+    [string, string, [] | [string]],
+    { 'Ok' : ETAPrediction } |
+      { 'Err' : string }
+  >,
+  'query_knowledge_graph' : ActorMethod<
+    [string, GraphQuery],
+    { 'Ok' : QueryResult } |
+      { 'Err' : string }
+  >,
+  'query_knowledge_graph_text' : ActorMethod<
+    [string, string],
+    { 'Ok' : QueryResult } |
+      { 'Err' : string }
+  >,
+  'read_shared_knowledge' : ActorMethod<
+    [string],
+    { 'Ok' : SharedKnowledge } |
+      { 'Err' : string }
+  >,
   'recall' : ActorMethod<[string, string, number], Array<Memory>>,
+  'recall_by_tag' : ActorMethod<[string, string], Array<Memory>>,
+  'recall_by_type' : ActorMethod<[string, string], Array<Memory>>,
   'remember' : ActorMethod<
-    [string, string, string, number, Array<string>],
+    [string, string, string, number, Array<string>, [] | [Array<number>]],
     { 'Ok' : string } |
       { 'Err' : string }
   >,
+  'revoke_share' : ActorMethod<
+    [string, RevokeAction],
+    { 'Ok' : ShareGrant } |
+      { 'Err' : string }
+  >,
+  'schedule_memory_maintenance' : ActorMethod<
+    [string, bigint, [] | [ConsolidationConfig]],
+    { 'Ok' : bigint } |
+      { 'Err' : string }
+  >,
+  'share_knowledge' : ActorMethod<
+    [ShareRequest],
+    { 'Ok' : ShareGrant } |
+      { 'Err' : string }
+  >,
+  'sweep_expired_shares' : ActorMethod<
+    [],
+    { 'Ok' : bigint } |
+      { 'Err' : string }
+  >,
 }
 export declare const idlFactory: IDL.InterfaceFactory;
 export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
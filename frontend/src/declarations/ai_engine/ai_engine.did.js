@@ -1,4 +1,32 @@
 export const idlFactory = ({ IDL }) => {
+  const GrantStatus = IDL.Variant({
+    'Active' : IDL.Null,
+    'Declined' : IDL.Null,
+    'Revoked' : IDL.Null,
+    'Expired' : IDL.Null,
+    'Pending' : IDL.Null,
+  });
+  const RevokeAction = IDL.Variant({ 'Flag' : IDL.Null, 'Remove' : IDL.Null });
+  const ShareMode = IDL.Variant({ 'Copy' : IDL.Null, 'Read' : IDL.Null });
+  const ShareGrant = IDL.Record({
+    'status' : GrantStatus,
+    'copied_memory_ids' : IDL.Vec(IDL.Text),
+    'to_agent' : IDL.Text,
+    'copied_edge_ids' : IDL.Vec(IDL.Text),
+    'to_owner' : IDL.Text,
+    'from_agent' : IDL.Text,
+    'owner' : IDL.Text,
+    'on_expiry' : RevokeAction,
+    'mode' : ShareMode,
+    'include_edges' : IDL.Bool,
+    'created_at' : IDL.Nat64,
+    'copied_node_ids' : IDL.Vec(IDL.Text),
+    'ended_at' : IDL.Opt(IDL.Nat64),
+    'expires_at' : IDL.Opt(IDL.Nat64),
+    'grant_id' : IDL.Text,
+    'memory_ids' : IDL.Vec(IDL.Text),
+    'node_ids' : IDL.Vec(IDL.Text),
+  });
   const LLMResponse = IDL.Record({
     'provider_name' : IDL.Text,
     'provider_id' : IDL.Text,
@@ -17,6 +45,15 @@ export const idlFactory = ({ IDL }) => {
     'dissent_notes' : IDL.Opt(IDL.Text),
     'confidence_score' : IDL.Float32,
   });
+  const Provenance = IDL.Record({
+    'revoked' : IDL.Bool,
+    'source_id' : IDL.Text,
+    'source_version' : IDL.Nat64,
+    'source_agent' : IDL.Text,
+    'grant_id' : IDL.Text,
+    'source_owner' : IDL.Text,
+    'copied_at' : IDL.Nat64,
+  });
   const NodeType = IDL.Variant({
     'Event' : IDL.Null,
     'Entity' : IDL.Null,
@@ -26,6 +63,7 @@ export const idlFactory = ({ IDL }) => {
   });
   const KnowledgeNode = IDL.Record({
     'id' : IDL.Text,
+    'provenance' : IDL.Opt(Provenance),
     'updated_at' : IDL.Nat64,
     'node_type' : NodeType,
     'properties' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
@@ -33,11 +71,44 @@ export const idlFactory = ({ IDL }) => {
     'label' : IDL.Text,
     'embedding' : IDL.Opt(IDL.Vec(IDL.Float32)),
   });
+  const EdgeDirection = IDL.Variant({
+    'Both' : IDL.Null,
+    'Outgoing' : IDL.Null,
+    'Incoming' : IDL.Null,
+  });
+  const WeightedPath = IDL.Record({
+    'hops' : IDL.Nat32,
+    'total_weight' : IDL.Float32,
+    'edge_ids' : IDL.Vec(IDL.Text),
+    'node_ids' : IDL.Vec(IDL.Text),
+  });
   const AgentMemory = IDL.Record({
     'agent_id' : IDL.Text,
     'context_window' : IDL.Vec(IDL.Text),
     'context_size' : IDL.Nat64,
   });
+  const MemoryType = IDL.Variant({
+    'Episodic' : IDL.Null,
+    'Procedural' : IDL.Null,
+    'ShortTerm' : IDL.Null,
+    'LongTerm' : IDL.Null,
+    'Semantic' : IDL.Null,
+  });
+  const Memory = IDL.Record({
+    'id' : IDL.Text,
+    'provenance' : IDL.Opt(Provenance),
+    'content' : IDL.Text,
+    'memory_type' : MemoryType,
+    'metadata' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+    'tags' : IDL.Vec(IDL.Text),
+    'importance' : IDL.Float32,
+    'created_at' : IDL.Nat64,
+    'last_accessed' : IDL.Nat64,
+    'summary' : IDL.Opt(IDL.Text),
+    'related_memories' : IDL.Vec(IDL.Text),
+    'embedding' : IDL.Opt(IDL.Vec(IDL.Float32)),
+    'access_count' : IDL.Nat32,
+  });
   const AlternativeRoute = IDL.Record({
     'name' : IDL.Text,
     'duration_hours' : IDL.Float64,
@@ -58,7 +129,10 @@ export const idlFactory = ({ IDL }) => {
     'recommended_stops' : IDL.Vec(IDL.Text),
   });
   const AIConfig = IDL.Record({
+    'openai_api_key' : IDL.Text,
     'admin' : IDL.Principal,
+    'huggingface_api_key' : IDL.Text,
+    'perplexity_api_key' : IDL.Text,
     'cache_duration_ns' : IDL.Nat64,
     'max_requests_per_minute' : IDL.Nat32,
   });
@@ -125,6 +199,16 @@ export const idlFactory = ({ IDL }) => {
     'total_tokens' : IDL.Nat32,
     'council_query' : CouncilQuery,
   });
+  const ShareListing = IDL.Record({
+    'shared' : IDL.Vec(ShareGrant),
+    'received' : IDL.Vec(ShareGrant),
+  });
+  const ConsolidationReport = IDL.Record({
+    'summary_ids' : IDL.Vec(IDL.Text),
+    'council_sessions' : IDL.Vec(IDL.Text),
+    'archived' : IDL.Nat32,
+    'clusters' : IDL.Nat32,
+  });
   const FuelStop = IDL.Record({
     'price_per_gallon' : IDL.Float64,
     'location' : IDL.Text,
@@ -145,28 +229,109 @@ export const idlFactory = ({ IDL }) => {
     'factors' : IDL.Vec(IDL.Text),
     'confidence' : IDL.Float64,
   });
-  const MemoryType = IDL.Variant({
-    'Episodic' : IDL.Null,
-    'Procedural' : IDL.Null,
-    'ShortTerm' : IDL.Null,
-    'LongTerm' : IDL.Null,
-    'Semantic' : IDL.Null,
+  const CompareOp = IDL.Variant({
+    'Eq' : IDL.Null,
+    'Gt' : IDL.Null,
+    'Lt' : IDL.Null,
+    'Gte' : IDL.Null,
+    'Lte' : IDL.Null,
+    'Contains' : IDL.Null,
+    'NotEq' : IDL.Null,
+    'Exists' : IDL.Null,
   });
-  const Memory = IDL.Record({
+  const PropertyFilter = IDL.Record({
+    'op' : CompareOp,
+    'key' : IDL.Text,
+    'value' : IDL.Text,
+  });
+  const NodePattern = IDL.Record({
+    'id' : IDL.Opt(IDL.Text),
+    'node_type' : IDL.Opt(NodeType),
+    'properties' : IDL.Vec(PropertyFilter),
+    'label' : IDL.Opt(IDL.Text),
+  });
+  const EdgePattern = IDL.Record({
+    'max_weight' : IDL.Opt(IDL.Float32),
+    'max_hops' : IDL.Nat32,
+    'direction' : EdgeDirection,
+    'min_hops' : IDL.Nat32,
+    'min_weight' : IDL.Opt(IDL.Float32),
+    'relationships' : IDL.Vec(IDL.Text),
+  });
+  const PathStep = IDL.Record({ 'edge' : EdgePattern, 'node' : NodePattern });
+  const Aggregate = IDL.Variant({
+    'CountByType' : IDL.Null,
+    'CountByProperty' : IDL.Text,
+    'Count' : IDL.Null,
+  });
+  const GraphQuery = IDL.Record({
+    'limit' : IDL.Opt(IDL.Nat32),
+    'start' : NodePattern,
+    'steps' : IDL.Vec(PathStep),
+    'aggregate' : IDL.Opt(Aggregate),
+  });
+  const AggregateRow = IDL.Record({
+    'key' : IDL.Text,
+    'count' : IDL.Nat64,
+    'total_weight' : IDL.Float32,
+    'min_hops' : IDL.Nat32,
+  });
+  const QueryRow = IDL.Record({
+    'hops' : IDL.Nat32,
+    'path' : IDL.Vec(IDL.Text),
+    'total_weight' : IDL.Float32,
+    'edges' : IDL.Vec(IDL.Text),
+    'nodes' : IDL.Vec(KnowledgeNode),
+  });
+  const QueryResult = IDL.Record({
+    'total_matches' : IDL.Nat64,
+    'aggregates' : IDL.Vec(AggregateRow),
+    'truncated' : IDL.Bool,
+    'rows' : IDL.Vec(QueryRow),
+  });
+  const KnowledgeEdge = IDL.Record({
     'id' : IDL.Text,
-    'content' : IDL.Text,
-    'memory_type' : MemoryType,
-    'metadata' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
-    'tags' : IDL.Vec(IDL.Text),
-    'importance' : IDL.Float32,
+    'weight' : IDL.Float32,
+    'relationship' : IDL.Text,
+    'properties' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+    'source_id' : IDL.Text,
+    'target_id' : IDL.Text,
     'created_at' : IDL.Nat64,
-    'last_accessed' : IDL.Nat64,
-    'summary' : IDL.Opt(IDL.Text),
-    'related_memories' : IDL.Vec(IDL.Text),
-    'embedding' : IDL.Opt(IDL.Vec(IDL.Float32)),
-    'access_count' : IDL.Nat32,
+  });
+  const SharedKnowledge = IDL.Record({
+    'edges' : IDL.Vec(KnowledgeEdge),
+    'memories' : IDL.Vec(Memory),
+    'nodes' : IDL.Vec(KnowledgeNode),
+  });
+  const Summarizer = IDL.Variant({
+    'LlmCouncil' : IDL.Null,
+    'RuleBased' : IDL.Null,
+  });
+  const ConsolidationConfig = IDL.Record({
+    'max_summary_sentences' : IDL.Nat32,
+    'summarizer' : Summarizer,
+    'similarity_threshold' : IDL.Float32,
+    'max_cluster_size' : IDL.Nat32,
+    'min_age_ns' : IDL.Nat64,
+    'min_cluster_size' : IDL.Nat32,
+  });
+  const ShareRequest = IDL.Record({
+    'to_agent' : IDL.Text,
+    'to_owner' : IDL.Text,
+    'from_agent' : IDL.Text,
+    'on_expiry' : RevokeAction,
+    'mode' : ShareMode,
+    'include_edges' : IDL.Bool,
+    'expires_at' : IDL.Opt(IDL.Nat64),
+    'memory_ids' : IDL.Vec(IDL.Text),
+    'node_ids' : IDL.Vec(IDL.Text),
   });
   return IDL.Service({
+    'accept_share' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : ShareGrant, 'Err' : IDL.Text })],
+        [],
+      ),
     'add_context' : IDL.Func(
         [IDL.Text, IDL.Text],
         [IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text })],
@@ -201,6 +366,16 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text })],
         [],
       ),
+    'admin_set_api_keys' : IDL.Func(
+        [IDL.Text, IDL.Text, IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'cancel_memory_maintenance' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
     'clear_cache' : IDL.Func(
         [],
         [IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : IDL.Text })],
@@ -211,6 +386,11 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text })],
         [],
       ),
+    'decline_share' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : ShareGrant, 'Err' : IDL.Text })],
+        [],
+      ),
     'finalize_council_response' : IDL.Func(
         [IDL.Text, IDL.Text, IDL.Text],
         [IDL.Variant({ 'Ok' : CouncilResult, 'Err' : IDL.Text })],
@@ -221,7 +401,24 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Opt(KnowledgeNode)],
         ['query'],
       ),
+    'find_knowledge_path' : IDL.Func(
+        [
+          IDL.Text,
+          IDL.Text,
+          IDL.Text,
+          IDL.Vec(IDL.Text),
+          EdgeDirection,
+          IDL.Opt(IDL.Nat32),
+        ],
+        [IDL.Variant({ 'Ok' : IDL.Opt(WeightedPath), 'Err' : IDL.Text })],
+        ['query'],
+      ),
     'get_agent_memory' : IDL.Func([IDL.Text], [AgentMemory], []),
+    'get_archived_memories' : IDL.Func(
+        [IDL.Text, IDL.Text],
+        [IDL.Vec(Memory)],
+        ['query'],
+      ),
     'get_cache_stats' : IDL.Func([], [IDL.Nat64, IDL.Nat64], ['query']),
     'get_cached_route' : IDL.Func(
         [IDL.Text, IDL.Text],
@@ -247,15 +444,16 @@ export const idlFactory = ({ IDL }) => {
         ['query'],
       ),
     'health' : IDL.Func([], [IDL.Text], ['query']),
+    'list_shares' : IDL.Func([IDL.Text], [ShareListing], ['query']),
     'maintain_memory' : IDL.Func(
         [IDL.Text],
-        [IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text })],
+        [IDL.Variant({ 'Ok' : ConsolidationReport, 'Err' : IDL.Text })],
         [],
       ),
     'optimize_fuel' : IDL.Func(
         [IDL.Text, IDL.Text, IDL.Float64],
-        [FuelOptimization],
-        ['query'],
+        [IDL.Variant({ 'Ok' : FuelOptimization, 'Err' : IDL.Text })],
+        [],
       ),
     'optimize_route' : IDL.Func(
         [IDL.Text, IDL.Text],
@@ -264,7 +462,22 @@ export const idlFactory = ({ IDL }) => {
       ),
     'predict_eta' : IDL.Func(
         [IDL.Text, IDL.Text, IDL.Opt(IDL.Text)],
-        [ETAPrediction],
+        [IDL.Variant({ 'Ok' : ETAPrediction, 'Err' : IDL.Text })],
+        [],
+      ),
+    'query_knowledge_graph' : IDL.Func(
+        [IDL.Text, GraphQuery],
+        [IDL.Variant({ 'Ok' : QueryResult, 'Err' : IDL.Text })],
+        ['query'],
+      ),
+    'query_knowledge_graph_text' : IDL.Func(
+        [IDL.Text, IDL.Text],
+        [IDL.Variant({ 'Ok' : QueryResult, 'Err' : IDL.Text })],
+        ['query'],
+      ),
+    'read_shared_knowledge' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : SharedKnowledge, 'Err' : IDL.Text })],
         ['query'],
       ),
     'recall' : IDL.Func(
@@ -272,11 +485,48 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Vec(Memory)],
         ['query'],
       ),
+    'recall_by_tag' : IDL.Func(
+        [IDL.Text, IDL.Text],
+        [IDL.Vec(Memory)],
+        ['query'],
+      ),
+    'recall_by_type' : IDL.Func(
+        [IDL.Text, IDL.Text],
+        [IDL.Vec(Memory)],
+        ['query'],
+      ),
     'remember' : IDL.Func(
-        [IDL.Text, IDL.Text, IDL.Text, IDL.Float32, IDL.Vec(IDL.Text)],
+        [
+          IDL.Text,
+          IDL.Text,
+          IDL.Text,
+          IDL.Float32,
+          IDL.Vec(IDL.Text),
+          IDL.Opt(IDL.Vec(IDL.Float32)),
+        ],
         [IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text })],
         [],
       ),
+    'revoke_share' : IDL.Func(
+        [IDL.Text, RevokeAction],
+        [IDL.Variant({ 'Ok' : ShareGrant, 'Err' : IDL.Text })],
+        [],
+      ),
+    'schedule_memory_maintenance' : IDL.Func(
+        [IDL.Text, IDL.Nat64, IDL.Opt(ConsolidationConfig)],
+        [IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : IDL.Text })],
+        [],
+      ),
+    'share_knowledge' : IDL.Func(
+        [ShareRequest],
+        [IDL.Variant({ 'Ok' : ShareGrant, 'Err' : IDL.Text })],
+        [],
+      ),
+    'sweep_expired_shares' : IDL.Func(
+        [],
+        [IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : IDL.Text })],
+        [],
+      ),
   });
 };
-export const init = ({ IDL }) => { return []; };
+export const init = ({ IDL }) => { return []; };
\ No newline at end of file
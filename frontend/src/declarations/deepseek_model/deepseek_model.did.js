@@ -1,4 +1,44 @@
 export const idlFactory = ({ IDL }) => {
+  const ChatTemplate = IDL.Variant({
+    'Qwen' : IDL.Null,
+    'Plain' : IDL.Null,
+    'DeepSeek' : IDL.Null,
+    'Llama3' : IDL.Null,
+    'ChatMl' : IDL.Null,
+  });
+  const StageInfo = IDL.Record({
+    'hidden_dim' : IDL.Nat32,
+    'error' : IDL.Opt(IDL.Text),
+    'end_layer' : IDL.Nat32,
+    'total_layers' : IDL.Nat32,
+    'start_layer' : IDL.Nat32,
+    'model_id' : IDL.Opt(IDL.Text),
+    'ready' : IDL.Bool,
+  });
+  const ShardState = IDL.Variant({
+    'Uploading' : IDL.Null,
+    'Corrupt' : IDL.Text,
+    'Verified' : IDL.Null,
+  });
+  const TokenizerInfo = IDL.Record({
+    'merges' : IDL.Nat32,
+    'byte_level' : IDL.Bool,
+    'vocab_size' : IDL.Nat32,
+    'special_tokens' : IDL.Nat32,
+    'model_id' : IDL.Text,
+  });
+  const ShardSpec = IDL.Record({ 'sha256' : IDL.Text, 'size' : IDL.Nat64 });
+  const ModelManifest = IDL.Record({
+    'shards' : IDL.Vec(ShardSpec),
+    'quantization' : IDL.Text,
+    'total_shards' : IDL.Nat32,
+    'model_id' : IDL.Text,
+    'chunk_size' : IDL.Nat32,
+  });
+  const InferenceConfig = IDL.Record({
+    'orchestrator' : IDL.Opt(IDL.Principal),
+    'context_window_tokens' : IDL.Nat32,
+  });
   const ModelShard = IDL.Record({
     'shard_data' : IDL.Vec(IDL.Nat8),
     'model_hash' : IDL.Text,
@@ -9,6 +49,19 @@ export const idlFactory = ({ IDL }) => {
     'total_shards' : IDL.Nat32,
     'compression_ratio' : IDL.Opt(IDL.Float32),
   });
+  const ModelStatus = IDL.Variant({
+    'Uploading' : IDL.Null,
+    'Ready' : IDL.Null,
+  });
+  const UploadStatus = IDL.Record({
+    'status' : ModelStatus,
+    'active' : IDL.Bool,
+    'total_shards' : IDL.Nat32,
+    'missing_chunks' : IDL.Vec(IDL.Tuple(IDL.Nat32, IDL.Vec(IDL.Nat32))),
+    'shards_verified' : IDL.Nat32,
+    'model_id' : IDL.Text,
+    'corrupt_shards' : IDL.Vec(IDL.Tuple(IDL.Nat32, IDL.Text)),
+  });
   const ChatMessage = IDL.Record({
     'content' : IDL.Text,
     'role' : IDL.Text,
@@ -23,24 +76,147 @@ export const idlFactory = ({ IDL }) => {
     'prompt' : IDL.Text,
   });
   const InferenceResponse = IDL.Record({
+    'completion_tokens' : IDL.Nat32,
+    'reasoning' : IDL.Opt(IDL.Text),
     'inference_time_ms' : IDL.Nat64,
     'response' : IDL.Text,
     'shards_used' : IDL.Vec(IDL.Nat32),
+    'prompt_tokens' : IDL.Nat32,
+    'context_messages_dropped' : IDL.Nat32,
     'tokens_generated' : IDL.Nat32,
   });
+  const GenerationStatus = IDL.Variant({
+    'Failed' : IDL.Text,
+    'Generating' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const GenerationChunk = IDL.Record({
+    'status' : GenerationStatus,
+    'session_id' : IDL.Text,
+    'done' : IDL.Bool,
+    'text' : IDL.Text,
+    'index' : IDL.Nat32,
+    'tokens_generated' : IDL.Nat32,
+  });
+  const DecodedCompletion = IDL.Record({
+    'reasoning' : IDL.Opt(IDL.Text),
+    'response' : IDL.Text,
+  });
+  const SamplingParams = IDL.Record({
+    'top_p' : IDL.Float32,
+    'temperature' : IDL.Float32,
+    'seed' : IDL.Nat64,
+  });
+  const StageInput = IDL.Variant({
+    'Hidden' : IDL.Vec(IDL.Float32),
+    'Token' : IDL.Nat32,
+  });
+  const StageRequest = IDL.Record({
+    'sample' : IDL.Opt(SamplingParams),
+    'session_id' : IDL.Text,
+    'input' : StageInput,
+    'position' : IDL.Nat32,
+  });
+  const StageOutput = IDL.Variant({
+    'Hidden' : IDL.Vec(IDL.Float32),
+    'Token' : IDL.Nat32,
+    'Cached' : IDL.Null,
+  });
+  const StageResponse = IDL.Record({
+    'output' : StageOutput,
+    'instructions' : IDL.Nat64,
+    'end_layer' : IDL.Nat32,
+    'start_layer' : IDL.Nat32,
+  });
+  const PreparedPrompt = IDL.Record({
+    'stop_tokens' : IDL.Vec(IDL.Nat32),
+    'tokens' : IDL.Vec(IDL.Nat32),
+    'max_tokens' : IDL.Nat32,
+    'context_messages_dropped' : IDL.Nat32,
+  });
   return IDL.Service({
+    'activate_model' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'admin_set_chat_template' : IDL.Func(
+        [IDL.Text, ChatTemplate],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'admin_set_context_window' : IDL.Func(
+        [IDL.Nat32],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'admin_set_hf_config' : IDL.Func(
+        [IDL.Opt(IDL.Text), IDL.Opt(IDL.Text)],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'admin_set_orchestrator' : IDL.Func(
+        [IDL.Opt(IDL.Principal)],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'admin_set_pipeline_stage' : IDL.Func(
+        [IDL.Nat32, IDL.Opt(IDL.Nat32)],
+        [IDL.Variant({ 'Ok' : StageInfo, 'Err' : IDL.Text })],
+        [],
+      ),
+    'cancel_generation' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'delete_model' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'detokenize' : IDL.Func(
+        [IDL.Vec(IDL.Nat32)],
+        [IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text })],
+        ['query'],
+      ),
+    'finalize_shard' : IDL.Func(
+        [IDL.Text, IDL.Nat32],
+        [IDL.Variant({ 'Ok' : ShardState, 'Err' : IDL.Text })],
+        [],
+      ),
+    'finalize_tokenizer' : IDL.Func(
+        [IDL.Text, IDL.Text],
+        [IDL.Variant({ 'Ok' : TokenizerInfo, 'Err' : IDL.Text })],
+        [],
+      ),
+    'get_active_model' : IDL.Func([], [IDL.Opt(ModelManifest)], ['query']),
     'get_all_shard_ids' : IDL.Func([], [IDL.Vec(IDL.Nat32)], ['query']),
+    'get_chat_template' : IDL.Func([IDL.Text], [ChatTemplate], ['query']),
+    'get_inference_config' : IDL.Func([], [InferenceConfig], ['query']),
     'get_model_info' : IDL.Func(
         [],
         [IDL.Text, IDL.Nat32, IDL.Nat64],
         ['query'],
       ),
+    'get_pipeline_stage' : IDL.Func([], [StageInfo], ['query']),
     'get_shard' : IDL.Func(
         [IDL.Nat32],
         [IDL.Variant({ 'Ok' : ModelShard, 'Err' : IDL.Text })],
         ['query'],
       ),
     'get_status' : IDL.Func([], [IDL.Bool, IDL.Nat64, IDL.Nat32], ['query']),
+    'get_tokenizer_info' : IDL.Func(
+        [IDL.Text],
+        [IDL.Opt(TokenizerInfo)],
+        ['query'],
+      ),
+    'get_upload_status' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : UploadStatus, 'Err' : IDL.Text })],
+        ['query'],
+      ),
     'infer' : IDL.Func(
         [InferenceRequest],
         [IDL.Variant({ 'Ok' : InferenceResponse, 'Err' : IDL.Text })],
@@ -51,11 +227,58 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Variant({ 'Ok' : InferenceResponse, 'Err' : IDL.Text })],
         [],
       ),
+    'list_models' : IDL.Func([], [IDL.Vec(UploadStatus)], ['query']),
+    'next_chunk' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : GenerationChunk, 'Err' : IDL.Text })],
+        [],
+      ),
+    'pipeline_decode' : IDL.Func(
+        [IDL.Vec(IDL.Nat32)],
+        [IDL.Variant({ 'Ok' : DecodedCompletion, 'Err' : IDL.Text })],
+        ['query'],
+      ),
+    'pipeline_forward' : IDL.Func(
+        [StageRequest],
+        [IDL.Variant({ 'Ok' : StageResponse, 'Err' : IDL.Text })],
+        [],
+      ),
+    'pipeline_prepare' : IDL.Func(
+        [InferenceRequest],
+        [IDL.Variant({ 'Ok' : PreparedPrompt, 'Err' : IDL.Text })],
+        ['query'],
+      ),
+    'pipeline_release' : IDL.Func([IDL.Text], [], []),
+    'register_model_manifest' : IDL.Func(
+        [ModelManifest],
+        [IDL.Variant({ 'Ok' : UploadStatus, 'Err' : IDL.Text })],
+        [],
+      ),
+    'start_generation' : IDL.Func(
+        [InferenceRequest],
+        [IDL.Variant({ 'Ok' : IDL.Text, 'Err' : IDL.Text })],
+        [],
+      ),
     'store_shard' : IDL.Func(
         [ModelShard],
         [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
         [],
       ),
+    'tokenize' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Vec(IDL.Nat32), 'Err' : IDL.Text })],
+        ['query'],
+      ),
+    'upload_shard_chunk' : IDL.Func(
+        [IDL.Text, IDL.Nat32, IDL.Nat32, IDL.Vec(IDL.Nat8)],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'upload_tokenizer_chunk' : IDL.Func(
+        [IDL.Text, IDL.Nat64, IDL.Vec(IDL.Nat8)],
+        [IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : IDL.Text })],
+        [],
+      ),
   });
 };
-export const init = ({ IDL }) => { return []; };
+export const init = ({ IDL }) => { return []; };
\ No newline at end of file
@@ -2,6 +2,10 @@ import type { Principal } from '@dfinity/principal';
 import type { ActorMethod } from '@dfinity/agent';
 import type { IDL } from '@dfinity/candid';
 
+export interface Account {
+  'owner' : Principal,
+  'subaccount' : [] | [Uint8Array | number[]],
+}
 export interface CollectionConfig {
   'admin' : Principal,
   'name' : string,
@@ -22,10 +26,27 @@ export interface ControllerConfig {
   'backend_canisters' : Array<Principal>,
   'admin_principals' : Array<Principal>,
 }
+/**
+ * Token Lifecycle Types
+ */
+export interface MetadataUpdate {
+  'external_url' : [] | [string],
+  'name' : string,
+  'description' : string,
+  'attributes' : Array<Trait>,
+  'image' : string,
+}
+export interface MetadataVersion {
+  'superseded_at' : bigint,
+  'superseded_by' : Principal,
+  'metadata' : NFTMetadata,
+  'version' : number,
+}
 export interface MintArgs {
   'to' : Principal,
   'name' : string,
   'description' : string,
+  'soulbound' : [] | [boolean],
   'attributes' : Array<Trait>,
   'image' : string,
 }
@@ -54,13 +75,47 @@ export type Rarity = { 'Epic' : null } |
   { 'Uncommon' : null } |
   { 'Legendary' : null } |
   { 'Common' : null };
+export interface TokenRarity {
+  'total' : bigint,
+  'token_id' : bigint,
+  'traits' : Array<TraitRarity>,
+  'rarity_score' : number,
+  'rank' : number,
+  'score' : number,
+  'rarity' : Rarity,
+}
+export interface Tombstone {
+  'token_id' : bigint,
+  'owner' : Account,
+  'metadata' : NFTMetadata,
+  'block' : bigint,
+  'burned_at' : bigint,
+  'burned_by' : Principal,
+}
 export interface Trait {
   'trait_type' : string,
   'value' : string,
   'rarity_score' : number,
 }
+export interface TraitRarity {
+  'trait_type' : string,
+  'value' : string,
+  'count' : bigint,
+  'score' : number,
+}
+export interface TraitStats {
+  'trait_types' : Array<[string, TraitTypeStats]>,
+  'tokens' : bigint,
+}
+/**
+ * Rarity Engine Types
+ */
+export interface TraitTypeStats {
+  'values' : Array<[string, bigint]>,
+  'tokens' : bigint,
+}
 export interface TransferArg {
-  'to' : Principal,
+  'to' : Account,
   'token_id' : bigint,
   'memo' : [] | [Uint8Array | number[]],
   'from_subaccount' : [] | [Uint8Array | number[]],
@@ -76,6 +131,12 @@ export type TransferError = {
   { 'InvalidRecipient' : null } |
   { 'GenericBatchError' : { 'message' : string, 'error_code' : bigint } } |
   { 'TooOld' : null };
+export type Value = { 'Int' : bigint } |
+  { 'Map' : Array<[string, Value]> } |
+  { 'Nat' : bigint } |
+  { 'Blob' : Uint8Array | number[] } |
+  { 'Text' : string } |
+  { 'Array' : Array<Value> };
 export interface _SERVICE {
   'add_admin_principal' : ActorMethod<
     [Principal],
@@ -86,9 +147,14 @@ export interface _SERVICE {
     [Array<MintArgs>],
     Array<{ 'Ok' : bigint } | { 'Err' : string }>
   >,
+  /**
+   * Token Lifecycle
+   */
+  'burn' : ActorMethod<[bigint], { 'Ok' : bigint } | { 'Err' : string }>,
   'get_admin_principals' : ActorMethod<[], Array<Principal>>,
   'get_all_controller_records' : ActorMethod<[], Array<NFTControllerRecord>>,
   'get_backend_controllers' : ActorMethod<[], Array<Principal>>,
+  'get_burned_token' : ActorMethod<[bigint], [] | [Tombstone]>,
   /**
    * Queries
    */
@@ -97,28 +163,36 @@ export interface _SERVICE {
    * Controller Management - Application canisters as controllers
    */
   'get_controller_config' : ActorMethod<[], ControllerConfig>,
+  'get_metadata_history' : ActorMethod<[bigint], Array<MetadataVersion>>,
   'get_nft_controllers' : ActorMethod<[bigint], [] | [NFTControllerRecord]>,
   'get_nft_metadata' : ActorMethod<[bigint], [] | [NFTMetadata]>,
-  'health' : ActorMethod<[], string>,
-  'icrc37_approve' : ActorMethod<
-    [bigint, Principal],
-    { 'Ok' : null } |
-      { 'Err' : TransferError }
+  /**
+   * Rarity
+   */
+  'get_rarity' : ActorMethod<[bigint], [] | [TokenRarity]>,
+  'get_rarity_ranking' : ActorMethod<
+    [bigint, [] | [bigint]],
+    Array<TokenRarity>
   >,
-  'icrc7_balance_of' : ActorMethod<[Principal], bigint>,
+  'get_trait_stats' : ActorMethod<[], TraitStats>,
+  'health' : ActorMethod<[], string>,
+  'icrc7_balance_of' : ActorMethod<[Array<Account>], Array<bigint>>,
   /**
    * ICRC-7 Standard
    */
-  'icrc7_collection_metadata' : ActorMethod<[], Array<[string, string]>>,
+  'icrc7_collection_metadata' : ActorMethod<[], Array<[string, Value]>>,
   'icrc7_name' : ActorMethod<[], string>,
-  'icrc7_owner_of' : ActorMethod<[bigint], [] | [Principal]>,
+  'icrc7_owner_of' : ActorMethod<[Array<bigint>], Array<[] | [Account]>>,
   'icrc7_supply_cap' : ActorMethod<[], [] | [bigint]>,
   'icrc7_symbol' : ActorMethod<[], string>,
   'icrc7_token_metadata' : ActorMethod<
-    [bigint],
-    [] | [Array<[string, string]>]
+    [Array<bigint>],
+    Array<[] | [Array<[string, Value]>]>
+  >,
+  'icrc7_tokens_of' : ActorMethod<
+    [Account, [] | [bigint], [] | [bigint]],
+    Array<bigint>
   >,
-  'icrc7_tokens_of' : ActorMethod<[Principal], Array<bigint>>,
   'icrc7_total_supply' : ActorMethod<[], bigint>,
   /**
    * ICRC-37 Transfer
@@ -128,6 +202,7 @@ export interface _SERVICE {
     Array<[] | [{ 'Ok' : bigint } | { 'Err' : TransferError }]>
   >,
   'is_authorized_nft_controller' : ActorMethod<[Principal], boolean>,
+  'is_soulbound' : ActorMethod<[bigint], boolean>,
   /**
    * Minting
    */
@@ -156,11 +231,21 @@ export interface _SERVICE {
    * Admin
    */
   'set_paused' : ActorMethod<[boolean], { 'Ok' : null } | { 'Err' : string }>,
+  'set_soulbound' : ActorMethod<
+    [bigint, boolean],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
   'update_collection_config' : ActorMethod<
     [string, string, string, number],
     { 'Ok' : null } |
       { 'Err' : string }
   >,
+  'update_metadata' : ActorMethod<
+    [bigint, MetadataUpdate],
+    { 'Ok' : NFTMetadata } |
+      { 'Err' : string }
+  >,
   'update_nft_controllers' : ActorMethod<
     [bigint],
     { 'Ok' : NFTControllerRecord } |
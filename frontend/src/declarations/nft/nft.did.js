@@ -1,4 +1,5 @@
 export const idlFactory = ({ IDL }) => {
+  const Value = IDL.Rec();
   const Trait = IDL.Record({
     'trait_type' : IDL.Text,
     'value' : IDL.Text,
@@ -8,6 +9,7 @@ export const idlFactory = ({ IDL }) => {
     'to' : IDL.Principal,
     'name' : IDL.Text,
     'description' : IDL.Text,
+    'soulbound' : IDL.Opt(IDL.Bool),
     'attributes' : IDL.Vec(Trait),
     'image' : IDL.Text,
   });
@@ -71,13 +73,71 @@ export const idlFactory = ({ IDL }) => {
     }),
     'TooOld' : IDL.Null,
   });
+  const Account = IDL.Record({
+    'owner' : IDL.Principal,
+    'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const Tombstone = IDL.Record({
+    'token_id' : IDL.Nat64,
+    'owner' : Account,
+    'metadata' : NFTMetadata,
+    'block' : IDL.Nat64,
+    'burned_at' : IDL.Nat64,
+    'burned_by' : IDL.Principal,
+  });
+  const MetadataVersion = IDL.Record({
+    'superseded_at' : IDL.Nat64,
+    'superseded_by' : IDL.Principal,
+    'metadata' : NFTMetadata,
+    'version' : IDL.Nat32,
+  });
+  const TraitRarity = IDL.Record({
+    'trait_type' : IDL.Text,
+    'value' : IDL.Text,
+    'count' : IDL.Nat64,
+    'score' : IDL.Float64,
+  });
+  const TokenRarity = IDL.Record({
+    'total' : IDL.Nat64,
+    'token_id' : IDL.Nat64,
+    'traits' : IDL.Vec(TraitRarity),
+    'rarity_score' : IDL.Nat32,
+    'rank' : IDL.Nat32,
+    'score' : IDL.Float64,
+    'rarity' : Rarity,
+  });
+  const TraitTypeStats = IDL.Record({
+    'values' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Nat64)),
+    'tokens' : IDL.Nat64,
+  });
+  const TraitStats = IDL.Record({
+    'trait_types' : IDL.Vec(IDL.Tuple(IDL.Text, TraitTypeStats)),
+    'tokens' : IDL.Nat64,
+  });
+  Value.fill(
+    IDL.Variant({
+      'Int' : IDL.Int,
+      'Map' : IDL.Vec(IDL.Tuple(IDL.Text, Value)),
+      'Nat' : IDL.Nat,
+      'Blob' : IDL.Vec(IDL.Nat8),
+      'Text' : IDL.Text,
+      'Array' : IDL.Vec(Value),
+    })
+  );
   const TransferArg = IDL.Record({
-    'to' : IDL.Principal,
+    'to' : Account,
     'token_id' : IDL.Nat,
     'memo' : IDL.Opt(IDL.Vec(IDL.Nat8)),
     'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
     'created_at_time' : IDL.Opt(IDL.Nat64),
   });
+  const MetadataUpdate = IDL.Record({
+    'external_url' : IDL.Opt(IDL.Text),
+    'name' : IDL.Text,
+    'description' : IDL.Text,
+    'attributes' : IDL.Vec(Trait),
+    'image' : IDL.Text,
+  });
   return IDL.Service({
     'add_admin_principal' : IDL.Func(
         [IDL.Principal],
@@ -89,6 +149,11 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Vec(IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : IDL.Text }))],
         [],
       ),
+    'burn' : IDL.Func(
+        [IDL.Nat],
+        [IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : IDL.Text })],
+        [],
+      ),
     'get_admin_principals' : IDL.Func([], [IDL.Vec(IDL.Principal)], ['query']),
     'get_all_controller_records' : IDL.Func(
         [],
@@ -100,37 +165,53 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Vec(IDL.Principal)],
         ['query'],
       ),
+    'get_burned_token' : IDL.Func([IDL.Nat], [IDL.Opt(Tombstone)], ['query']),
     'get_collection_config' : IDL.Func([], [CollectionConfig], ['query']),
     'get_controller_config' : IDL.Func([], [ControllerConfig], ['query']),
+    'get_metadata_history' : IDL.Func(
+        [IDL.Nat],
+        [IDL.Vec(MetadataVersion)],
+        ['query'],
+      ),
     'get_nft_controllers' : IDL.Func(
         [IDL.Nat64],
         [IDL.Opt(NFTControllerRecord)],
         ['query'],
       ),
     'get_nft_metadata' : IDL.Func([IDL.Nat], [IDL.Opt(NFTMetadata)], ['query']),
+    'get_rarity' : IDL.Func([IDL.Nat], [IDL.Opt(TokenRarity)], ['query']),
+    'get_rarity_ranking' : IDL.Func(
+        [IDL.Nat64, IDL.Opt(IDL.Nat)],
+        [IDL.Vec(TokenRarity)],
+        ['query'],
+      ),
+    'get_trait_stats' : IDL.Func([], [TraitStats], ['query']),
     'health' : IDL.Func([], [IDL.Text], ['query']),
-    'icrc37_approve' : IDL.Func(
-        [IDL.Nat, IDL.Principal],
-        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : TransferError })],
-        [],
+    'icrc7_balance_of' : IDL.Func(
+        [IDL.Vec(Account)],
+        [IDL.Vec(IDL.Nat)],
+        ['query'],
       ),
-    'icrc7_balance_of' : IDL.Func([IDL.Principal], [IDL.Nat], ['query']),
     'icrc7_collection_metadata' : IDL.Func(
         [],
-        [IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text))],
+        [IDL.Vec(IDL.Tuple(IDL.Text, Value))],
         ['query'],
       ),
     'icrc7_name' : IDL.Func([], [IDL.Text], ['query']),
-    'icrc7_owner_of' : IDL.Func([IDL.Nat], [IDL.Opt(IDL.Principal)], ['query']),
+    'icrc7_owner_of' : IDL.Func(
+        [IDL.Vec(IDL.Nat)],
+        [IDL.Vec(IDL.Opt(Account))],
+        ['query'],
+      ),
     'icrc7_supply_cap' : IDL.Func([], [IDL.Opt(IDL.Nat)], ['query']),
     'icrc7_symbol' : IDL.Func([], [IDL.Text], ['query']),
     'icrc7_token_metadata' : IDL.Func(
-        [IDL.Nat],
-        [IDL.Opt(IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)))],
+        [IDL.Vec(IDL.Nat)],
+        [IDL.Vec(IDL.Opt(IDL.Vec(IDL.Tuple(IDL.Text, Value))))],
         ['query'],
       ),
     'icrc7_tokens_of' : IDL.Func(
-        [IDL.Principal],
+        [Account, IDL.Opt(IDL.Nat), IDL.Opt(IDL.Nat)],
         [IDL.Vec(IDL.Nat)],
         ['query'],
       ),
@@ -149,6 +230,7 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Bool],
         ['query'],
       ),
+    'is_soulbound' : IDL.Func([IDL.Nat], [IDL.Bool], ['query']),
     'mint' : IDL.Func(
         [MintArgs],
         [IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : IDL.Text })],
@@ -179,11 +261,21 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
         [],
       ),
+    'set_soulbound' : IDL.Func(
+        [IDL.Nat, IDL.Bool],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
     'update_collection_config' : IDL.Func(
         [IDL.Text, IDL.Text, IDL.Text, IDL.Nat16],
         [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
         [],
       ),
+    'update_metadata' : IDL.Func(
+        [IDL.Nat, MetadataUpdate],
+        [IDL.Variant({ 'Ok' : NFTMetadata, 'Err' : IDL.Text })],
+        [],
+      ),
     'update_nft_controllers' : IDL.Func(
         [IDL.Nat64],
         [IDL.Variant({ 'Ok' : NFTControllerRecord, 'Err' : IDL.Text })],
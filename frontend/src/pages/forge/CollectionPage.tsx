@@ -84,7 +84,7 @@ export default function CollectionPage() {
         for (let i = 0; i < Math.min(mintedCount, 100); i++) {
           try {
             const metadata = await nftActor.get_nft_metadata(BigInt(i));
-            const owner = await nftActor.icrc7_owner_of(BigInt(i));
+            const [owner] = await nftActor.icrc7_owner_of([BigInt(i)]);
             
             if (metadata && metadata.length > 0 && metadata[0]) {
               const meta = metadata[0];
@@ -98,7 +98,7 @@ export default function CollectionPage() {
                   name: attr.trait_type,
                   value: attr.value,
                 })),
-                owner: owner && owner.length > 0 && owner[0] ? owner[0].toText() : undefined,
+                owner: owner && owner.length > 0 && owner[0] ? owner[0].owner.toText() : undefined,
               });
             }
           } catch (e) {
@@ -72,6 +72,7 @@ export class ICSpicyMintService {
             rarity_score: 1,
           }
         ],
+        soulbound: [],
       };
       
       const result = await actor.mint(mintArgs);
@@ -116,6 +117,7 @@ export class ICSpicyMintService {
             rarity_score: 1,
           },
         ],
+        soulbound: [],
       }));
       
       const results = await actor.batch_mint(mintArgs);
@@ -145,7 +147,7 @@ export class ICSpicyMintService {
     const actor = await createNFTActor(getActiveIdentity());
     
     try {
-      const tokens = await actor.icrc7_tokens_of(principal);
+      const tokens = await actor.icrc7_tokens_of({ owner: principal, subaccount: [] }, [], []);
       return tokens.map((id: any) => BigInt(id));
     } catch (error) {
       console.error('Forge NFT get user tokens error:', error);
@@ -178,8 +178,8 @@ export class NFTService {
   async getOwnerOf(tokenId: bigint): Promise<string | null> {
     const actor = this.getActor();
     try {
-      const result = await actor.icrc7_owner_of(tokenId);
-      return result[0] ? result[0].toText() : null;
+      const [owner] = await actor.icrc7_owner_of([tokenId]);
+      return owner?.[0] ? owner[0].owner.toText() : null;
     } catch (error) {
       console.error('Failed to fetch owner:', error);
       throw error;
@@ -189,8 +189,8 @@ export class NFTService {
   async getBalanceOf(owner: string): Promise<number> {
     const actor = this.getActor();
     try {
-      const result = await actor.icrc7_balance_of(Principal.fromText(owner));
-      return Number(result);
+      const [balance] = await actor.icrc7_balance_of([{ owner: Principal.fromText(owner), subaccount: [] }]);
+      return Number(balance);
     } catch (error) {
       console.error('Failed to fetch balance:', error);
       throw error;
@@ -200,7 +200,7 @@ export class NFTService {
   async getTokensOf(owner: string): Promise<bigint[]> {
     const actor = this.getActor();
     try {
-      const result = await actor.icrc7_tokens_of(Principal.fromText(owner));
+      const result = await actor.icrc7_tokens_of({ owner: Principal.fromText(owner), subaccount: [] }, [], []);
       return result.map((id: any) => BigInt(id));
     } catch (error) {
       console.error('Failed to fetch tokens:', error);
@@ -25,6 +25,11 @@ dfx canister call queen_bee register_model_canister "(1, principal \"$DEEPSEEK_I
 echo "Registering vector_db..."
 dfx canister call queen_bee register_vector_db_canister "(1, principal \"$VECTOR_DB_ID\")" --network $NETWORK
 
+# queen_bee writes its memories to the shard's default namespace, which only
+# controllers and the writers they add may write to
+echo "Granting queen_bee write access to the default vector namespace..."
+dfx canister call vector_db set_namespace_acl "(\"default\", record { writers = vec { principal \"$QUEEN_BEE_ID\" }; readers = vec {}; public_read = true; public_write = false })" --network $NETWORK
+
 echo ""
 echo "✅ Canisters registered successfully!"
 echo ""
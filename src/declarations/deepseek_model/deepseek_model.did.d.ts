@@ -7,6 +7,31 @@ export interface ChatMessage {
   'role' : string,
   'timestamp' : bigint,
 }
+export type ChatTemplate = { 'Qwen' : null } |
+  { 'Plain' : null } |
+  { 'DeepSeek' : null } |
+  { 'Llama3' : null } |
+  { 'ChatMl' : null };
+export interface DecodedCompletion {
+  'reasoning' : [] | [string],
+  'response' : string,
+}
+export interface GenerationChunk {
+  'status' : GenerationStatus,
+  'session_id' : string,
+  'done' : boolean,
+  'text' : string,
+  'index' : number,
+  'tokens_generated' : number,
+}
+export type GenerationStatus = { 'Failed' : string } |
+  { 'Generating' : null } |
+  { 'Cancelled' : null } |
+  { 'Completed' : null };
+export interface InferenceConfig {
+  'orchestrator' : [] | [Principal],
+  'context_window_tokens' : number,
+}
 export interface InferenceRequest {
   'top_p' : number,
   'context' : Array<ChatMessage>,
@@ -16,15 +41,22 @@ export interface InferenceRequest {
   'prompt' : string,
 }
 export interface InferenceResponse {
+  'completion_tokens' : number,
+  'reasoning' : [] | [string],
   'inference_time_ms' : bigint,
   'response' : string,
   'shards_used' : Uint32Array | number[],
+  'prompt_tokens' : number,
+  'context_messages_dropped' : number,
   'tokens_generated' : number,
 }
-/**
- * DeepSeek R1 7B Model Weight Sharding Canister
- * Stores and serves sharded model weights for on-chain inference
- */
+export interface ModelManifest {
+  'shards' : Array<ShardSpec>,
+  'quantization' : string,
+  'total_shards' : number,
+  'model_id' : string,
+  'chunk_size' : number,
+}
 export interface ModelShard {
   'shard_data' : Uint8Array | number[],
   'model_hash' : string,
@@ -33,27 +65,137 @@ export interface ModelShard {
   'quantization' : [] | [string],
   'shard_index' : bigint,
   'total_shards' : number,
-  /**
-   * "Q4_K_M", "Q8_0", "F16", etc. (optional for backward compatibility)
-   */
   'compression_ratio' : [] | [number],
 }
+export type ModelStatus = { 'Uploading' : null } |
+  { 'Ready' : null };
+export interface PreparedPrompt {
+  'stop_tokens' : Uint32Array | number[],
+  'tokens' : Uint32Array | number[],
+  'max_tokens' : number,
+  'context_messages_dropped' : number,
+}
+export interface SamplingParams {
+  'top_p' : number,
+  'temperature' : number,
+  'seed' : bigint,
+}
+export interface ShardSpec { 'sha256' : string, 'size' : bigint }
+export type ShardState = { 'Uploading' : null } |
+  { 'Corrupt' : string } |
+  { 'Verified' : null };
+export interface StageInfo {
+  'hidden_dim' : number,
+  'error' : [] | [string],
+  'end_layer' : number,
+  'total_layers' : number,
+  'start_layer' : number,
+  'model_id' : [] | [string],
+  'ready' : boolean,
+}
+export type StageInput = { 'Hidden' : Array<number> } |
+  { 'Token' : number };
+export type StageOutput = { 'Hidden' : Array<number> } |
+  { 'Token' : number } |
+  { 'Cached' : null };
+export interface StageRequest {
+  'sample' : [] | [SamplingParams],
+  'session_id' : string,
+  'input' : StageInput,
+  'position' : number,
+}
+export interface StageResponse {
+  'output' : StageOutput,
+  'instructions' : bigint,
+  'end_layer' : number,
+  'start_layer' : number,
+}
+export interface TokenizerInfo {
+  'merges' : number,
+  'byte_level' : boolean,
+  'vocab_size' : number,
+  'special_tokens' : number,
+  'model_id' : string,
+}
+export interface UploadStatus {
+  'status' : ModelStatus,
+  'active' : boolean,
+  'total_shards' : number,
+  'missing_chunks' : Array<[number, Uint32Array | number[]]>,
+  'shards_verified' : number,
+  'model_id' : string,
+  'corrupt_shards' : Array<[number, string]>,
+}
 export interface _SERVICE {
+  'activate_model' : ActorMethod<
+    [string],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'admin_set_chat_template' : ActorMethod<
+    [string, ChatTemplate],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'admin_set_context_window' : ActorMethod<
+    [number],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'admin_set_hf_config' : ActorMethod<
+    [[] | [string], [] | [string]],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'admin_set_orchestrator' : ActorMethod<
+    [[] | [Principal]],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'admin_set_pipeline_stage' : ActorMethod<
+    [number, [] | [number]],
+    { 'Ok' : StageInfo } |
+      { 'Err' : string }
+  >,
+  'cancel_generation' : ActorMethod<
+    [string],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'delete_model' : ActorMethod<[string], { 'Ok' : null } | { 'Err' : string }>,
+  'detokenize' : ActorMethod<
+    [Uint32Array | number[]],
+    { 'Ok' : string } |
+      { 'Err' : string }
+  >,
+  'finalize_shard' : ActorMethod<
+    [string, number],
+    { 'Ok' : ShardState } |
+      { 'Err' : string }
+  >,
+  'finalize_tokenizer' : ActorMethod<
+    [string, string],
+    { 'Ok' : TokenizerInfo } |
+      { 'Err' : string }
+  >,
+  'get_active_model' : ActorMethod<[], [] | [ModelManifest]>,
   'get_all_shard_ids' : ActorMethod<[], Uint32Array | number[]>,
+  'get_chat_template' : ActorMethod<[string], ChatTemplate>,
+  'get_inference_config' : ActorMethod<[], InferenceConfig>,
   'get_model_info' : ActorMethod<[], [string, number, bigint]>,
+  'get_pipeline_stage' : ActorMethod<[], StageInfo>,
   'get_shard' : ActorMethod<
     [number],
     { 'Ok' : ModelShard } |
       { 'Err' : string }
   >,
-  /**
-   * Health & Status
-   */
   'get_status' : ActorMethod<[], [boolean, bigint, number]>,
-  /**
-   * (model_name, total_shards, total_size)
-   * On-chain Inference
-   */
+  'get_tokenizer_info' : ActorMethod<[string], [] | [TokenizerInfo]>,
+  'get_upload_status' : ActorMethod<
+    [string],
+    { 'Ok' : UploadStatus } |
+      { 'Err' : string }
+  >,
   'infer' : ActorMethod<
     [InferenceRequest],
     { 'Ok' : InferenceResponse } |
@@ -64,14 +206,58 @@ export interface _SERVICE {
     { 'Ok' : InferenceResponse } |
       { 'Err' : string }
   >,
-  /**
-   * Model Weight Management
-   */
+  'list_models' : ActorMethod<[], Array<UploadStatus>>,
+  'next_chunk' : ActorMethod<
+    [string],
+    { 'Ok' : GenerationChunk } |
+      { 'Err' : string }
+  >,
+  'pipeline_decode' : ActorMethod<
+    [Uint32Array | number[]],
+    { 'Ok' : DecodedCompletion } |
+      { 'Err' : string }
+  >,
+  'pipeline_forward' : ActorMethod<
+    [StageRequest],
+    { 'Ok' : StageResponse } |
+      { 'Err' : string }
+  >,
+  'pipeline_prepare' : ActorMethod<
+    [InferenceRequest],
+    { 'Ok' : PreparedPrompt } |
+      { 'Err' : string }
+  >,
+  'pipeline_release' : ActorMethod<[string], undefined>,
+  'register_model_manifest' : ActorMethod<
+    [ModelManifest],
+    { 'Ok' : UploadStatus } |
+      { 'Err' : string }
+  >,
+  'start_generation' : ActorMethod<
+    [InferenceRequest],
+    { 'Ok' : string } |
+      { 'Err' : string }
+  >,
   'store_shard' : ActorMethod<
     [ModelShard],
     { 'Ok' : null } |
       { 'Err' : string }
   >,
+  'tokenize' : ActorMethod<
+    [string],
+    { 'Ok' : Uint32Array | number[] } |
+      { 'Err' : string }
+  >,
+  'upload_shard_chunk' : ActorMethod<
+    [string, number, number, Uint8Array | number[]],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'upload_tokenizer_chunk' : ActorMethod<
+    [string, bigint, Uint8Array | number[]],
+    { 'Ok' : bigint } |
+      { 'Err' : string }
+  >,
 }
 export declare const idlFactory: IDL.InterfaceFactory;
 export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
@@ -5,34 +5,151 @@ import type { IDL } from '@dfinity/candid';
 export interface AIRequest {
   'use_onchain' : boolean,
   'context' : Array<ChatMessage>,
+  'chairman_synthesis' : [] | [boolean],
   'system_prompt' : [] | [string],
   'token_id' : [] | [bigint],
+  'max_tokens' : [] | [number],
   'query_text' : string,
   'use_http_parallel' : boolean,
 }
 export interface AIResponse {
   'inference_method' : string,
+  'synthesis' : [] | [SynthesisReport],
   'tokens_used' : number,
+  'routing' : [] | [RoutingResult],
   'response' : string,
   'latency_ms' : bigint,
   'confidence_score' : number,
   'model_responses' : Array<[string, string, number]>,
 }
-/**
- * Queen Bee AI Pipeline Orchestrator
- * Coordinates on-chain DeepSeek R1 inference, HTTP outcalls, voice processing, and memory storage
- */
 export interface ChatMessage {
   'content' : string,
   'role' : string,
   'timestamp' : bigint,
 }
+export interface ClaimGroup { 'text' : string, 'models' : Array<string> }
+export interface Contradiction { 'kept' : ClaimGroup, 'rejected' : ClaimGroup }
+export interface GenerationChunk {
+  'status' : GenerationStatus,
+  'session_id' : string,
+  'done' : boolean,
+  'text' : string,
+  'index' : number,
+  'tokens_generated' : number,
+}
+export type GenerationStatus = { 'Failed' : string } |
+  { 'Generating' : null } |
+  { 'Cancelled' : null } |
+  { 'Completed' : null };
+export interface MemoryQueryResult {
+  'results' : Array<MemoryResult>,
+  'query_id' : string,
+  'shards_queried' : number,
+  'partial' : boolean,
+  'shards_failed' : Array<ShardError>,
+}
 export interface MemoryResult {
   'id' : string,
   'metadata' : Array<[string, string]>,
   'vector' : Array<number>,
   'similarity' : number,
 }
+export interface ModelContribution {
+  'claims' : number,
+  'claims_used' : number,
+  'model' : string,
+  'agreement' : number,
+  'tokens_used' : number,
+  'confidence' : number,
+  'contribution' : number,
+}
+export interface PipelineResponse {
+  'stage_latency_ms' : BigUint64Array | bigint[],
+  'reasoning' : [] | [string],
+  'response' : string,
+  'latency_ms' : bigint,
+  'prompt_tokens' : number,
+  'tokens_generated' : number,
+}
+export interface PipelineStage {
+  'canister_id' : Principal,
+  'end_layer' : number,
+  'start_layer' : number,
+}
+export interface PipelineStatus {
+  'stages' : Array<StageStatus>,
+  'hidden_dim' : number,
+  'total_layers' : number,
+  'model_id' : [] | [string],
+}
+export interface RebalanceStatus {
+  'active' : boolean,
+  'current_shard' : [] | [number],
+  'errors' : Array<string>,
+  'moved_total' : bigint,
+  'moved_this_step' : number,
+  'removed_shards' : Uint32Array | number[],
+}
+export interface RoutingDecision {
+  'id' : bigint,
+  'result' : RoutingResult,
+  'query' : string,
+  'label' : [] | [number],
+  'timestamp' : bigint,
+  'caller' : Principal,
+}
+export interface RoutingResult {
+  'primary' : number,
+  'confidence' : number,
+  'routed' : Array<[number, number]>,
+}
+export interface ShardError { 'failure' : ShardFailure, 'shard_id' : number }
+export type ShardFailure = { 'Skipped' : null } |
+  { 'Rejected' : string } |
+  { 'TimedOut' : bigint };
+export interface Specialist {
+  'id' : number,
+  'updated_at' : bigint,
+  'name' : string,
+  'description' : string,
+  'centroid' : Array<number>,
+  'embedder_version' : string,
+  'examples' : Array<string>,
+}
+export interface SpecialistInput {
+  'id' : number,
+  'name' : string,
+  'description' : string,
+  'examples' : Array<string>,
+}
+export interface StageStats {
+  'failures' : bigint,
+  'last_error' : [] | [string],
+  'last_latency_ms' : bigint,
+  'calls' : bigint,
+  'max_latency_ms' : bigint,
+  'total_latency_ms' : bigint,
+  'retries' : bigint,
+}
+export interface StageStatus {
+  'mean_latency_ms' : bigint,
+  'stage' : PipelineStage,
+  'stats' : StageStats,
+}
+export interface StreamStart {
+  'model' : string,
+  'session_id' : string,
+  'routing' : RoutingResult,
+}
+export interface SynthesisReport {
+  'agreements' : Array<ClaimGroup>,
+  'method' : string,
+  'contributions' : Array<ModelContribution>,
+  'tokens_used' : number,
+  'merged_response' : string,
+  'contradictions' : Array<Contradiction>,
+  'confidence' : number,
+}
 export interface VoiceRequest {
   'similarity_boost' : [] | [number],
   'text' : string,
@@ -45,15 +162,55 @@ export interface VoiceResponse {
   'duration_ms' : bigint,
 }
 export interface _SERVICE {
+  'cancel_ai_stream' : ActorMethod<
+    [string],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'deadline_tick' : ActorMethod<[], undefined>,
+  'embed_text' : ActorMethod<[string], Array<number>>,
+  'get_embedder_info' : ActorMethod<[], [string, string, number]>,
+  'get_late_memory_results' : ActorMethod<
+    [string, number],
+    { 'Ok' : Array<MemoryResult> } |
+      { 'Err' : string }
+  >,
+  'get_pipeline_topology' : ActorMethod<[], PipelineStatus>,
+  'get_routing_log' : ActorMethod<[bigint, number], Array<RoutingDecision>>,
   'get_status' : ActorMethod<[], [boolean, bigint, number, number]>,
+  'get_vector_shards' : ActorMethod<
+    [],
+    Array<[number, Principal, boolean, number, bigint]>
+  >,
+  'label_routing_decision' : ActorMethod<
+    [bigint, number],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'list_specialists' : ActorMethod<[], Array<Specialist>>,
+  'next_chunk' : ActorMethod<
+    [string],
+    { 'Ok' : GenerationChunk } |
+      { 'Err' : string }
+  >,
+  'pipeline_infer' : ActorMethod<
+    [AIRequest],
+    { 'Ok' : PipelineResponse } |
+      { 'Err' : string }
+  >,
   'process_ai_request' : ActorMethod<
     [AIRequest],
     { 'Ok' : AIResponse } |
       { 'Err' : string }
   >,
   'query_memory' : ActorMethod<
-    [string, number],
-    { 'Ok' : Array<MemoryResult> } |
+    [string, number, [] | [Array<number>]],
+    { 'Ok' : MemoryQueryResult } |
+      { 'Err' : string }
+  >,
+  'rebalance_shards' : ActorMethod<
+    [[] | [number]],
+    { 'Ok' : RebalanceStatus } |
       { 'Err' : string }
   >,
   'register_model_canister' : ActorMethod<
@@ -66,6 +223,22 @@ export interface _SERVICE {
     { 'Ok' : null } |
       { 'Err' : string }
   >,
+  'remove_specialist' : ActorMethod<
+    [number],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'route_query' : ActorMethod<[string], RoutingResult>,
+  'set_pipeline_topology' : ActorMethod<
+    [Array<Principal>],
+    { 'Ok' : PipelineStatus } |
+      { 'Err' : string }
+  >,
+  'start_ai_stream' : ActorMethod<
+    [AIRequest],
+    { 'Ok' : StreamStart } |
+      { 'Err' : string }
+  >,
   'store_memory' : ActorMethod<
     [string, Array<number>, Array<[string, string]>, number],
     { 'Ok' : string } |
@@ -76,6 +249,16 @@ export interface _SERVICE {
     { 'Ok' : VoiceResponse } |
       { 'Err' : string }
   >,
+  'unregister_vector_db_canister' : ActorMethod<
+    [number],
+    { 'Ok' : null } |
+      { 'Err' : string }
+  >,
+  'upsert_specialist' : ActorMethod<
+    [SpecialistInput],
+    { 'Ok' : Specialist } |
+      { 'Err' : string }
+  >,
 }
 export declare const idlFactory: IDL.InterfaceFactory;
 export declare const init: (args: { IDL: typeof IDL }) => IDL.Type[];
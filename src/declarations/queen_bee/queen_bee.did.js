@@ -1,4 +1,71 @@
 export const idlFactory = ({ IDL }) => {
+  const MemoryResult = IDL.Record({
+    'id' : IDL.Text,
+    'metadata' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+    'vector' : IDL.Vec(IDL.Float32),
+    'similarity' : IDL.Float32,
+  });
+  const PipelineStage = IDL.Record({
+    'canister_id' : IDL.Principal,
+    'end_layer' : IDL.Nat32,
+    'start_layer' : IDL.Nat32,
+  });
+  const StageStats = IDL.Record({
+    'failures' : IDL.Nat64,
+    'last_error' : IDL.Opt(IDL.Text),
+    'last_latency_ms' : IDL.Nat64,
+    'calls' : IDL.Nat64,
+    'max_latency_ms' : IDL.Nat64,
+    'total_latency_ms' : IDL.Nat64,
+    'retries' : IDL.Nat64,
+  });
+  const StageStatus = IDL.Record({
+    'mean_latency_ms' : IDL.Nat64,
+    'stage' : PipelineStage,
+    'stats' : StageStats,
+  });
+  const PipelineStatus = IDL.Record({
+    'stages' : IDL.Vec(StageStatus),
+    'hidden_dim' : IDL.Nat32,
+    'total_layers' : IDL.Nat32,
+    'model_id' : IDL.Opt(IDL.Text),
+  });
+  const RoutingResult = IDL.Record({
+    'primary' : IDL.Nat32,
+    'confidence' : IDL.Float32,
+    'routed' : IDL.Vec(IDL.Tuple(IDL.Nat32, IDL.Float32)),
+  });
+  const RoutingDecision = IDL.Record({
+    'id' : IDL.Nat64,
+    'result' : RoutingResult,
+    'query' : IDL.Text,
+    'label' : IDL.Opt(IDL.Nat32),
+    'timestamp' : IDL.Nat64,
+    'caller' : IDL.Principal,
+  });
+  const Specialist = IDL.Record({
+    'id' : IDL.Nat32,
+    'updated_at' : IDL.Nat64,
+    'name' : IDL.Text,
+    'description' : IDL.Text,
+    'centroid' : IDL.Vec(IDL.Float32),
+    'embedder_version' : IDL.Text,
+    'examples' : IDL.Vec(IDL.Text),
+  });
+  const GenerationStatus = IDL.Variant({
+    'Failed' : IDL.Text,
+    'Generating' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const GenerationChunk = IDL.Record({
+    'status' : GenerationStatus,
+    'session_id' : IDL.Text,
+    'done' : IDL.Bool,
+    'text' : IDL.Text,
+    'index' : IDL.Nat32,
+    'tokens_generated' : IDL.Nat32,
+  });
   const ChatMessage = IDL.Record({
     'content' : IDL.Text,
     'role' : IDL.Text,
@@ -7,24 +74,85 @@ export const idlFactory = ({ IDL }) => {
   const AIRequest = IDL.Record({
     'use_onchain' : IDL.Bool,
     'context' : IDL.Vec(ChatMessage),
+    'chairman_synthesis' : IDL.Opt(IDL.Bool),
     'system_prompt' : IDL.Opt(IDL.Text),
     'token_id' : IDL.Opt(IDL.Nat64),
+    'max_tokens' : IDL.Opt(IDL.Nat32),
     'query_text' : IDL.Text,
     'use_http_parallel' : IDL.Bool,
   });
+  const PipelineResponse = IDL.Record({
+    'stage_latency_ms' : IDL.Vec(IDL.Nat64),
+    'reasoning' : IDL.Opt(IDL.Text),
+    'response' : IDL.Text,
+    'latency_ms' : IDL.Nat64,
+    'prompt_tokens' : IDL.Nat32,
+    'tokens_generated' : IDL.Nat32,
+  });
+  const ClaimGroup = IDL.Record({
+    'text' : IDL.Text,
+    'models' : IDL.Vec(IDL.Text),
+  });
+  const ModelContribution = IDL.Record({
+    'claims' : IDL.Nat32,
+    'claims_used' : IDL.Nat32,
+    'model' : IDL.Text,
+    'agreement' : IDL.Float32,
+    'tokens_used' : IDL.Nat32,
+    'confidence' : IDL.Float32,
+    'contribution' : IDL.Float32,
+  });
+  const Contradiction = IDL.Record({
+    'kept' : ClaimGroup,
+    'rejected' : ClaimGroup,
+  });
+  const SynthesisReport = IDL.Record({
+    'agreements' : IDL.Vec(ClaimGroup),
+    'method' : IDL.Text,
+    'contributions' : IDL.Vec(ModelContribution),
+    'tokens_used' : IDL.Nat32,
+    'merged_response' : IDL.Text,
+    'contradictions' : IDL.Vec(Contradiction),
+    'confidence' : IDL.Float32,
+  });
   const AIResponse = IDL.Record({
     'inference_method' : IDL.Text,
+    'synthesis' : IDL.Opt(SynthesisReport),
     'tokens_used' : IDL.Nat32,
+    'routing' : IDL.Opt(RoutingResult),
     'response' : IDL.Text,
     'latency_ms' : IDL.Nat64,
     'confidence_score' : IDL.Float32,
     'model_responses' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text, IDL.Float32)),
   });
-  const MemoryResult = IDL.Record({
-    'id' : IDL.Text,
-    'metadata' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
-    'vector' : IDL.Vec(IDL.Float32),
-    'similarity' : IDL.Float32,
+  const ShardFailure = IDL.Variant({
+    'Skipped' : IDL.Null,
+    'Rejected' : IDL.Text,
+    'TimedOut' : IDL.Nat64,
+  });
+  const ShardError = IDL.Record({
+    'failure' : ShardFailure,
+    'shard_id' : IDL.Nat32,
+  });
+  const MemoryQueryResult = IDL.Record({
+    'results' : IDL.Vec(MemoryResult),
+    'query_id' : IDL.Text,
+    'shards_queried' : IDL.Nat32,
+    'partial' : IDL.Bool,
+    'shards_failed' : IDL.Vec(ShardError),
+  });
+  const RebalanceStatus = IDL.Record({
+    'active' : IDL.Bool,
+    'current_shard' : IDL.Opt(IDL.Nat32),
+    'errors' : IDL.Vec(IDL.Text),
+    'moved_total' : IDL.Nat64,
+    'moved_this_step' : IDL.Nat32,
+    'removed_shards' : IDL.Vec(IDL.Nat32),
+  });
+  const StreamStart = IDL.Record({
+    'model' : IDL.Text,
+    'session_id' : IDL.Text,
+    'routing' : RoutingResult,
   });
   const VoiceRequest = IDL.Record({
     'similarity_boost' : IDL.Opt(IDL.Float32),
@@ -37,21 +165,80 @@ export const idlFactory = ({ IDL }) => {
     'characters_used' : IDL.Nat32,
     'duration_ms' : IDL.Nat64,
   });
+  const SpecialistInput = IDL.Record({
+    'id' : IDL.Nat32,
+    'name' : IDL.Text,
+    'description' : IDL.Text,
+    'examples' : IDL.Vec(IDL.Text),
+  });
   return IDL.Service({
+    'cancel_ai_stream' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'deadline_tick' : IDL.Func([], [], []),
+    'embed_text' : IDL.Func([IDL.Text], [IDL.Vec(IDL.Float32)], ['query']),
+    'get_embedder_info' : IDL.Func(
+        [],
+        [IDL.Text, IDL.Text, IDL.Nat32],
+        ['query'],
+      ),
+    'get_late_memory_results' : IDL.Func(
+        [IDL.Text, IDL.Nat32],
+        [IDL.Variant({ 'Ok' : IDL.Vec(MemoryResult), 'Err' : IDL.Text })],
+        ['query'],
+      ),
+    'get_pipeline_topology' : IDL.Func([], [PipelineStatus], ['query']),
+    'get_routing_log' : IDL.Func(
+        [IDL.Nat64, IDL.Nat32],
+        [IDL.Vec(RoutingDecision)],
+        ['query'],
+      ),
     'get_status' : IDL.Func(
         [],
         [IDL.Bool, IDL.Nat64, IDL.Nat32, IDL.Nat32],
         ['query'],
       ),
+    'get_vector_shards' : IDL.Func(
+        [],
+        [
+          IDL.Vec(
+            IDL.Tuple(IDL.Nat32, IDL.Principal, IDL.Bool, IDL.Nat32, IDL.Nat64)
+          ),
+        ],
+        ['query'],
+      ),
+    'label_routing_decision' : IDL.Func(
+        [IDL.Nat64, IDL.Nat32],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'list_specialists' : IDL.Func([], [IDL.Vec(Specialist)], ['query']),
+    'next_chunk' : IDL.Func(
+        [IDL.Text],
+        [IDL.Variant({ 'Ok' : GenerationChunk, 'Err' : IDL.Text })],
+        [],
+      ),
+    'pipeline_infer' : IDL.Func(
+        [AIRequest],
+        [IDL.Variant({ 'Ok' : PipelineResponse, 'Err' : IDL.Text })],
+        [],
+      ),
     'process_ai_request' : IDL.Func(
         [AIRequest],
         [IDL.Variant({ 'Ok' : AIResponse, 'Err' : IDL.Text })],
         [],
       ),
     'query_memory' : IDL.Func(
-        [IDL.Text, IDL.Nat32],
-        [IDL.Variant({ 'Ok' : IDL.Vec(MemoryResult), 'Err' : IDL.Text })],
-        ['query'],
+        [IDL.Text, IDL.Nat32, IDL.Opt(IDL.Vec(IDL.Float32))],
+        [IDL.Variant({ 'Ok' : MemoryQueryResult, 'Err' : IDL.Text })],
+        [],
+      ),
+    'rebalance_shards' : IDL.Func(
+        [IDL.Opt(IDL.Nat32)],
+        [IDL.Variant({ 'Ok' : RebalanceStatus, 'Err' : IDL.Text })],
+        [],
       ),
     'register_model_canister' : IDL.Func(
         [IDL.Nat32, IDL.Principal],
@@ -63,6 +250,22 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
         [],
       ),
+    'remove_specialist' : IDL.Func(
+        [IDL.Nat32],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'route_query' : IDL.Func([IDL.Text], [RoutingResult], ['query']),
+    'set_pipeline_topology' : IDL.Func(
+        [IDL.Vec(IDL.Principal)],
+        [IDL.Variant({ 'Ok' : PipelineStatus, 'Err' : IDL.Text })],
+        [],
+      ),
+    'start_ai_stream' : IDL.Func(
+        [AIRequest],
+        [IDL.Variant({ 'Ok' : StreamStart, 'Err' : IDL.Text })],
+        [],
+      ),
     'store_memory' : IDL.Func(
         [
           IDL.Text,
@@ -78,6 +281,16 @@ export const idlFactory = ({ IDL }) => {
         [IDL.Variant({ 'Ok' : VoiceResponse, 'Err' : IDL.Text })],
         [],
       ),
+    'unregister_vector_db_canister' : IDL.Func(
+        [IDL.Nat32],
+        [IDL.Variant({ 'Ok' : IDL.Null, 'Err' : IDL.Text })],
+        [],
+      ),
+    'upsert_specialist' : IDL.Func(
+        [SpecialistInput],
+        [IDL.Variant({ 'Ok' : Specialist, 'Err' : IDL.Text })],
+        [],
+      ),
   });
 };
-export const init = ({ IDL }) => { return []; };
+export const init = ({ IDL }) => { return []; };
\ No newline at end of file